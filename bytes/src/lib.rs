@@ -135,6 +135,17 @@ impl<'a, T: AsRef<[u8]> + ?Sized> From<&'a T> for TabIter<'a, T> {
 
 pub const TAB_SEQUENCE: &[u8] = b"-->8";
 
+/// The length of the tab-separator at the start of the bytes, the [`TAB_SEQUENCE`] and the
+/// line-ending following it, `\n` or `\r\n`, if any
+pub fn tab_separator_len(bytes: &[u8]) -> usize {
+    let line_ending = bytes.get(TAB_SEQUENCE.len()..).unwrap_or_default();
+    let line_ending_len = [b"\r\n".as_slice(), b"\n"]
+        .into_iter()
+        .find(|line_ending_bytes| line_ending.starts_with(line_ending_bytes))
+        .map_or(0, <[u8]>::len);
+    TAB_SEQUENCE.len() + line_ending_len
+}
+
 impl<'a, T: AsRef<[u8]> + ?Sized> Iterator for TabIter<'a, T> {
     type Item = &'a [u8];
     fn next(&mut self) -> Option<Self::Item> {
@@ -157,8 +168,8 @@ impl<'a, T: AsRef<[u8]> + ?Sized> Iterator for TabIter<'a, T> {
 
         let (tab_data, remainder) = src.split_at(index_of_tab_sequence);
 
-        // Split off the tab-sequence itself
-        let (_tab_sequence, remainder) = remainder.split_at(TAB_SEQUENCE.len() + 1);
+        // Split off the tab-sequence itself, with its line-ending
+        let (_tab_sequence, remainder) = remainder.split_at(tab_separator_len(remainder));
 
         // Load the remainder
         self.0 = Some(ByteCursor::Tail(remainder));
@@ -0,0 +1,160 @@
+use pico_8_cart_model::tokens::TOKEN_LIMIT;
+use pico_build_rs::analysis::{CartAnalysis, TabStats};
+use pico_build_rs::lint::Finding;
+use ratatui::{
+    prelude::*,
+    widgets::{Block, Cell, List, ListState, Row, StatefulWidget, Table},
+};
+
+/// Moves the selection of the findings, bound to Up/Down
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FindingSelection {
+    Previous,
+    Next,
+}
+
+/// The last analyzed cart, with the findings of the lints listed below its statistics
+#[derive(Debug)]
+pub struct AnalyzedCart {
+    pub analysis: CartAnalysis,
+    pub findings: Vec<Finding>,
+    /// The index of the finding Enter opens in `$EDITOR`
+    selected: usize,
+}
+
+impl AnalyzedCart {
+    pub fn new(analysis: CartAnalysis, findings: Vec<Finding>) -> AnalyzedCart {
+        AnalyzedCart {
+            analysis,
+            findings,
+            selected: 0,
+        }
+    }
+    pub fn select(&mut self, selection: FindingSelection) {
+        self.selected = match selection {
+            FindingSelection::Previous => self.selected.saturating_sub(1),
+            FindingSelection::Next => {
+                (self.selected + 1).min(self.findings.len().saturating_sub(1))
+            }
+        };
+    }
+    pub fn selected(&self) -> Option<&Finding> {
+        self.findings.get(self.selected)
+    }
+}
+
+/// The statistics of the last analyzed cart, and its findings
+pub struct AnalysisWidget<'a> {
+    analyzed_cart: &'a AnalyzedCart,
+}
+
+impl<'a> From<&'a AnalyzedCart> for AnalysisWidget<'a> {
+    fn from(analyzed_cart: &'a AnalyzedCart) -> Self {
+        AnalysisWidget { analyzed_cart }
+    }
+}
+
+/// The percentage of the count, for the usage-lines
+fn percentage(used: usize, count: usize) -> usize {
+    (used * 100).checked_div(count).unwrap_or_default()
+}
+
+impl Widget for AnalysisWidget<'_> {
+    fn render(self, area: Rect, buf: &mut Buffer)
+    where
+        Self: Sized,
+    {
+        let AnalyzedCart {
+            analysis,
+            findings,
+            selected,
+        } = self.analyzed_cart;
+        let CartAnalysis {
+            tabs,
+            section_sizes,
+            sprites_used,
+            sprite_count,
+            tiles_used,
+            tile_count,
+        } = analysis;
+        let block = Block::bordered().title("analysis");
+        let inner = block.inner(area);
+        block.render(area, buf);
+
+        let [table_area, summary_area, findings_area] = Layout::vertical([
+            Constraint::Fill(1),
+            // tokens, sections, sprites and map
+            Constraint::Length(4),
+            Constraint::Fill(u16::from(!findings.is_empty())),
+        ])
+        .areas(inner);
+
+        let rows = tabs.iter().map(
+            |TabStats {
+                 tab_index,
+                 title,
+                 lines,
+                 tokens,
+                 chars,
+             }| {
+                let stats = [tab_index, lines, tokens, chars].map(|stat| stat.to_string());
+                let [tab_index, stats @ ..] = stats;
+                Row::new(
+                    [tab_index, title.clone().unwrap_or_default()]
+                        .into_iter()
+                        .chain(stats)
+                        .map(Cell::new),
+                )
+            },
+        );
+        let widths = [
+            Constraint::Length(8),
+            Constraint::Fill(1),
+            Constraint::Length(8),
+            Constraint::Length(8),
+            Constraint::Length(8),
+        ];
+        let table = Table::new(rows, widths)
+            .header(Row::new(["tab", "title", "lines", "tokens", "chars"]).bold());
+        Widget::render(table, table_area, buf);
+
+        let sections: Vec<String> = section_sizes
+            .iter()
+            .map(|(section, size)| {
+                let name = <&'static str>::from(section).trim_matches('_');
+                format!("{name} {size}B")
+            })
+            .collect();
+        let summary = [
+            format!("tokens: {}/{TOKEN_LIMIT}", analysis.token_count()),
+            format!("sections: {}", sections.join(", ")),
+            format!(
+                "sprites: {sprites_used}/{sprite_count} ({}%)",
+                percentage(*sprites_used, *sprite_count)
+            ),
+            format!(
+                "map: {tiles_used}/{tile_count} tiles ({}%)",
+                percentage(*tiles_used, *tile_count)
+            ),
+        ];
+        Text::from_iter(summary).render(summary_area, buf);
+
+        let findings = List::new(findings.iter().map(
+            |Finding {
+                 rule,
+                 tab_index,
+                 line_number,
+                 ..
+             }| {
+                format!(
+                    "{}: tab {tab_index}, line {line_number}: {}",
+                    rule.code, rule.description
+                )
+            },
+        ))
+        .block(Block::new().title("findings (enter opens $EDITOR)"))
+        .highlight_style(Style::new().reversed());
+        let mut list_state = ListState::default().with_selected(Some(*selected));
+        StatefulWidget::render(findings, findings_area, buf, &mut list_state);
+    }
+}
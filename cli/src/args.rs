@@ -1,7 +1,11 @@
 use std::borrow::Cow;
 use std::path;
 
-use clap::Parser;
+use clap::{Parser, Subcommand};
+use pico_build_rs::transform::BuildProfile;
+
+use crate::diff_view::DiffView;
+use crate::theme::Theme;
 
 #[expect(dead_code)] // here as a detail on the long_about
 const CRAB_EMOJI: char = '\u{1f980}';
@@ -42,7 +46,202 @@ pub struct AppArgs {
     /// The executable-path to be used if `open_pico` is specified
     #[arg(short, long, value_name = "EXECUTABLE", default_value = "None")]
     executable: Option<path::PathBuf>,
+    /// The member of the `pico-workspace.toml` in the root-directory to use
+    #[arg(short, long, value_name = "PROJECT")]
+    project: Option<String>,
+    /// The `[[cart]]` of the config-file to build, the first one if not set
+    #[arg(short, long, value_name = "TARGET")]
+    target: Option<String>,
+    /// The configuration as JSON, with the keys of a `pico.json`, instead of the
+    /// config-file of the root-directory, e.g. for editor-plugins driving the
+    /// command-line without writing a file
+    #[arg(long, value_name = "JSON", conflicts_with_all = ["stdin_config", "project"])]
+    config_json: Option<String>,
+    /// Reads the configuration as JSON from stdin, like `--config-json`
+    #[arg(
+        long,
+        value_name = "STDIN_CONFIG",
+        default_value_t = false,
+        conflicts_with = "project"
+    )]
+    pub stdin_config: bool,
+    /// The color-theme of the terminal user-interface
+    #[arg(long, value_name = "THEME", value_enum)]
+    theme: Option<Theme>,
+    /// Compiles with the release-profile, stripping debug-calls such as `printh`
+    #[arg(long, value_name = "RELEASE", default_value_t = false)]
+    pub release: bool,
+    /// Opens a project which is not trusted, e.g. one downloaded during a jam:
+    /// no external programs or scripts are run, and no files outside the
+    /// project-directory are read
+    #[arg(long, value_name = "SAFE", default_value_t = false)]
+    pub safe: bool,
+    /// Records the actions of the session to the file, for reproducing a bug
+    #[arg(long, value_name = "REPLAY_FILE", conflicts_with = "replay")]
+    pub record: Option<path::PathBuf>,
+    /// Feeds the actions recorded with `--record` back without the terminal
+    /// user-interface, reporting where the rebuilt carts differ from the recorded ones
+    #[arg(long, value_name = "REPLAY_FILE")]
+    pub replay: Option<path::PathBuf>,
+
+    /// Runs a single command instead of the terminal user-interface
+    #[command(subcommand)]
+    pub command: Option<Command>,
 }
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Creates a project with a `pico.toml`, a `src/main.lua` and an empty cart
+    #[command(visible_alias = "new")]
+    Init {
+        /// The name of the project-directory, and of the cart
+        name: String,
+    },
+    /// Lists `-- TODO`/`-- FIXME` style comments in the source-files
+    Todos,
+    /// Compiles the cartridge
+    Build {
+        /// Compiles each project of the workspace, or each `[[cart]]` of the config-file,
+        /// reporting per project
+        #[arg(long)]
+        all: bool,
+    },
+    /// Compiles the cartridge on each change to the lua source-files, until interrupted
+    Watch,
+    /// Prints the token-count of each tab, and the usage against the limits of the cartridge
+    Analyze {
+        /// Also writes the statistics as a report, as markdown or as html by the extension,
+        /// the html embedding the label and sprite-sheet
+        #[arg(long, value_name = "REPORT_FILE")]
+        out: Option<path::PathBuf>,
+    },
+    /// Checks the cartridge for usage which breaks when published to the BBS,
+    /// and that it is within the token limit
+    #[command(visible_alias = "check")]
+    Preflight {
+        /// Checks each project of the workspace, or each `[[cart]]` of the config-file,
+        /// reporting per project
+        #[arg(long)]
+        all: bool,
+        /// Fails if the code has more tokens, a budget below the limit of the cartridge
+        #[arg(long, value_name = "TOKENS")]
+        max_tokens: Option<usize>,
+        /// Fails if the compressed code is larger, in bytes
+        #[arg(long, value_name = "BYTES")]
+        max_compressed: Option<usize>,
+    },
+    /// Prints the changed sections and tabs between two carts, with the changed lines of code
+    Diff {
+        /// The cart compared against, e.g. a backup
+        old: path::PathBuf,
+        new: path::PathBuf,
+        /// How the changes are shown, the image-view marking the changed sprites
+        #[arg(long, value_enum, default_value_t)]
+        view: DiffView,
+    },
+    /// Prints the changes between the cart published to the BBS and the built cartridge,
+    /// e.g. to check the latest build was uploaded
+    #[cfg(feature = "network")]
+    BbsDiff {
+        /// The cart-id, e.g. `celeste-0`, or the BBS-url of the cart
+        cart: String,
+        /// Downloads the cart again, instead of using the one downloaded before
+        #[arg(long)]
+        refresh: bool,
+        /// How the changes are shown, the image-view marking the changed sprites
+        #[arg(long, value_enum, default_value_t)]
+        view: DiffView,
+    },
+    /// Prints the longer explanation of a diagnostic-code, e.g. `L004`
+    Explain {
+        /// The code, as printed next to the diagnostic
+        code: String,
+    },
+    /// Prints where the tabs and sections of the last compiled cartridge came from
+    Provenance {
+        /// Only prints the tab with this index
+        #[arg(long)]
+        tab: Option<usize>,
+        /// Only prints the section with this name, e.g. `gfx`
+        #[arg(long, conflicts_with = "tab")]
+        section: Option<String>,
+    },
+    /// Lists the source-files which no longer compile into a tab, and the tabs of the
+    /// cartridge which no source-file compiles into anymore
+    Orphans {
+        /// Asks to remove each orphaned file
+        #[arg(long)]
+        prune: bool,
+    },
+    /// Prints the dependency-graph of the cartridge: its tabs, the source-files compiled
+    /// into them, and the files they include
+    Graph {
+        /// How the graph is printed, `dot` for Graphviz
+        #[arg(long, value_enum, default_value_t)]
+        format: GraphFormat,
+    },
+    /// Extracts the string-literals of the source-files into a string-catalog, to be
+    /// translated and built with `language` in the `[strings]` section
+    Strings {
+        /// The catalog written, keeping the translations already in it, instead of
+        /// printing it, e.g. `lang/de.txt`
+        #[arg(long)]
+        output: Option<path::PathBuf>,
+    },
+    /// Removes the top-level functions of the built cartridge which are never called,
+    /// listing each with the tokens it counts for
+    DeadCode {
+        /// Only lists the functions, keeping the cartridge as it is
+        #[arg(long)]
+        report_only: bool,
+    },
+    /// Prints the source-file and line of a line of the built cartridge, e.g. of a
+    /// runtime-error reported by pico-8 as `line 12 (tab 2)`, from its source-map
+    Trace {
+        /// The tab of the cartridge, from 0
+        tab: usize,
+        /// The line of the tab, from 1
+        line: usize,
+    },
+    /// Builds the cartridge repeatedly, printing the time and allocations of each stage,
+    /// the first build on its own and the mean and percentiles of the others
+    ///
+    /// Allocations are only counted when built with the `bench` feature
+    Bench {
+        /// The builds, including the first one
+        #[arg(short = 'n', long, default_value_t = 10)]
+        iterations: usize,
+    },
+    /// Builds and launches the cartridge to record GIFs for a trailer, with F8 and F9 in
+    /// pico-8, and once pico-8 is closed collects them into `dist/media`
+    Capture {
+        /// Also converts each GIF to an mp4, if `ffmpeg` is found on the path
+        #[arg(long)]
+        mp4: bool,
+    },
+    /// Parses and writes back each `.p8` file below the directory, checking that the carts
+    /// are reproduced byte for byte
+    Verify {
+        /// The directory of the carts, e.g. the `carts` folder of PICO-8
+        dir: path::PathBuf,
+    },
+    /// Runs a rhai-script calling the build-actions, e.g. a release-checklist
+    #[cfg(feature = "scripting")]
+    RunScript {
+        /// The path of the script, e.g. `release.rhai`
+        script: path::PathBuf,
+    },
+}
+/// The output of the `graph` command
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum GraphFormat {
+    /// The DOT-language of Graphviz, e.g. for `dot -Tsvg`
+    #[default]
+    Dot,
+    /// A line per dependency
+    Text,
+}
+
 impl AppArgs {
     pub fn get_root_directory(&self) -> std::io::Result<Cow<'_, path::Path>> {
         if let Some(dir) = self.root_directory.as_deref() {
@@ -71,6 +270,34 @@ impl AppArgs {
     pub fn get_executable(&self) -> Option<&path::Path> {
         self.executable.as_deref()
     }
+    pub fn get_project(&self) -> Option<&str> {
+        self.project.as_deref()
+    }
+    pub fn get_target(&self) -> Option<&str> {
+        self.target.as_deref()
+    }
+    /// Reads the configuration from stdin if `--stdin-config` is set, once for the
+    /// whole session
+    pub fn read_stdin_config(&mut self) -> std::io::Result<()> {
+        if self.stdin_config && self.config_json.is_none() {
+            self.config_json = Some(std::io::read_to_string(std::io::stdin())?);
+        }
+        Ok(())
+    }
+    /// The JSON-configuration of `--config-json` or `--stdin-config`
+    pub fn get_config_json(&self) -> Option<&str> {
+        self.config_json.as_deref()
+    }
+    pub fn get_theme(&self) -> Option<Theme> {
+        self.theme
+    }
+    pub fn get_build_profile(&self) -> BuildProfile {
+        if self.release {
+            BuildProfile::Release
+        } else {
+            BuildProfile::Debug
+        }
+    }
     pub fn configuration_values(
         &self,
     ) -> Option<(&path::Path, &str, bool, bool, Option<&path::Path>)> {
@@ -0,0 +1,240 @@
+//! Fetching the carts published to the BBS, for `bbs-diff`
+//!
+//! A cart is fetched as the `.p8.png` the BBS serves for its cart-id, and kept in the
+//! [`CACHE_DIR`] of the project-directory. The cached download is used until it is
+//! refreshed, and when the BBS can not be reached, so comparing works offline once a cart
+//! has been fetched
+
+use core::fmt;
+use core::time::Duration;
+
+use std::fs;
+use std::io;
+use std::path;
+
+use pico_8_cart_model::CartData;
+use pico_8_cart_model::png::{self, PngCartError};
+
+use pico_build_rs::paths;
+
+/// The directory of the downloaded carts, relative to the project-directory
+pub const CACHE_DIR: &str = ".pico-build/bbs";
+
+/// Where the BBS serves the `.p8.png` of the carts, by the first two characters of the
+/// cart-id and the cart-id
+const CART_URL: &str = "https://www.lexaloffle.com/bbs/cposts";
+
+/// How long a download may take, before the cached cart is used instead
+const TIMEOUT: Duration = Duration::from_secs(15);
+
+#[derive(Debug)]
+pub enum BbsError {
+    /// Neither a cart-id, e.g. `celeste-0`, nor a BBS-url with one
+    InvalidCartId(String),
+    /// The BBS could not be reached, and the cart was never downloaded before
+    Unreachable {
+        cart_id: String,
+        reason: String,
+    },
+    /// The BBS has no cart of the id
+    NotFound(String),
+    Io(io::Error),
+    Png(PngCartError),
+}
+
+impl From<io::Error> for BbsError {
+    fn from(v: io::Error) -> Self {
+        Self::Io(v)
+    }
+}
+
+impl From<PngCartError> for BbsError {
+    fn from(v: PngCartError) -> Self {
+        Self::Png(v)
+    }
+}
+
+impl fmt::Display for BbsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let description = "BBS error";
+        let reason = match self {
+            BbsError::InvalidCartId(id) => {
+                format!("{id:?} is neither a cart-id nor a BBS-url with one")
+            }
+            BbsError::Unreachable { cart_id, reason } => format!(
+                "could not fetch {cart_id} ({reason}), and it was never downloaded before; retry once online, or compare a downloaded .p8.png with `diff`"
+            ),
+            BbsError::NotFound(cart_id) => format!("the BBS has no cart {cart_id}"),
+            BbsError::Io(e) => e.to_string(),
+            BbsError::Png(e) => e.to_string(),
+        };
+        f.write_fmt(format_args!("{description}: {reason}"))
+    }
+}
+
+impl core::error::Error for BbsError {}
+
+/// Whether the cart was downloaded, or read from the cache
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Fetched {
+    Downloaded,
+    Cached,
+    /// The download failed, and the cached cart was used instead
+    CachedOffline,
+}
+
+/// The cart-id of a cart-id, or of a BBS-url
+///
+/// Urls are read for their `pid`/`lid` query-parameter, or a path ending in `.p8.png`
+pub fn cart_id(id_or_url: &str) -> Result<String, BbsError> {
+    let invalid = || BbsError::InvalidCartId(id_or_url.to_string());
+    let id = match id_or_url.split_once("://") {
+        Some((_, rest)) => {
+            let (path, query) = rest.split_once('?').unwrap_or((rest, ""));
+            query
+                .split(['&', '#'])
+                .find_map(|parameter| {
+                    parameter
+                        .strip_prefix("pid=")
+                        .or_else(|| parameter.strip_prefix("lid="))
+                })
+                .or_else(|| path.rsplit('/').next()?.strip_suffix(".p8.png"))
+                .ok_or_else(invalid)?
+        }
+        None => id_or_url.strip_suffix(".p8.png").unwrap_or(id_or_url),
+    };
+    let is_valid = id.len() >= 2
+        && id
+            .bytes()
+            .all(|byte| byte.is_ascii_alphanumeric() || matches!(byte, b'-' | b'_'));
+    match is_valid {
+        true => Ok(id.to_ascii_lowercase()),
+        false => Err(invalid()),
+    }
+}
+
+/// The url of the `.p8.png` of the cart-id
+pub fn cart_url(cart_id: &str) -> String {
+    let prefix: String = cart_id.chars().take(2).collect();
+    format!("{CART_URL}/{prefix}/{cart_id}.p8.png")
+}
+
+fn download(cart_id: &str) -> Result<Vec<u8>, BbsError> {
+    let url = cart_url(cart_id);
+    tracing::info!("Downloading {url}");
+    let unreachable = |reason: String| BbsError::Unreachable {
+        cart_id: cart_id.to_string(),
+        reason,
+    };
+    let response = match ureq::get(&url).timeout(TIMEOUT).call() {
+        Ok(response) => response,
+        Err(ureq::Error::Status(404, _)) => return Err(BbsError::NotFound(cart_id.to_string())),
+        Err(ureq::Error::Status(status, _)) => return Err(unreachable(format!("HTTP {status}"))),
+        Err(ureq::Error::Transport(e)) => return Err(unreachable(e.to_string())),
+    };
+    let mut bytes = Vec::new();
+    io::Read::read_to_end(&mut response.into_reader(), &mut bytes)
+        .map_err(|e| unreachable(e.to_string()))?;
+    Ok(bytes)
+}
+
+/// Fetches the cart of the id, from the cache of the project-directory unless `refresh`
+///
+/// A failed download falls back to the cached cart, if there is one
+#[tracing::instrument(level = "debug")]
+pub fn fetch_cart(
+    root_dir: &path::Path,
+    cart_id: &str,
+    refresh: bool,
+) -> Result<(CartData<'static>, Fetched), BbsError> {
+    let cache_dir = root_dir.join(CACHE_DIR);
+    let cached_path = cache_dir.join(format!("{cart_id}.p8.png"));
+    let cached = cached_path.is_file();
+    let (bytes, fetched) = match (cached, refresh) {
+        (true, false) => (fs::read(&cached_path)?, Fetched::Cached),
+        _ => match download(cart_id) {
+            Ok(bytes) => {
+                fs::create_dir_all(&cache_dir)?;
+                fs::write(&cached_path, &bytes)?;
+                (bytes, Fetched::Downloaded)
+            }
+            Err(BbsError::Unreachable { reason, .. }) if cached => {
+                tracing::warn!(
+                    "Could not fetch {cart_id} ({reason}), using the download in {}",
+                    paths::relative_to(&cached_path, root_dir)
+                );
+                (fs::read(&cached_path)?, Fetched::CachedOffline)
+            }
+            Err(e) => return Err(e),
+        },
+    };
+    Ok((png::read_cart_png(bytes.as_slice())?, fetched))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_cart_ids_of_urls() {
+        for (id_or_url, expected) in [
+            ("celeste-0", "celeste-0"),
+            ("15133", "15133"),
+            (
+                "https://www.lexaloffle.com/bbs/?pid=Celeste-0#p",
+                "celeste-0",
+            ),
+            (
+                "https://www.lexaloffle.com/bbs/?tid=2145&lid=15133",
+                "15133",
+            ),
+            (
+                "https://www.lexaloffle.com/bbs/cposts/ce/celeste-0.p8.png",
+                "celeste-0",
+            ),
+        ] {
+            assert_eq!(cart_id(id_or_url).unwrap(), expected);
+        }
+        for invalid in ["", "../etc", "https://www.lexaloffle.com/bbs/?tid=2145"] {
+            assert!(cart_id(invalid).is_err());
+        }
+        assert_eq!(
+            cart_url("celeste-0"),
+            "https://www.lexaloffle.com/bbs/cposts/ce/celeste-0.p8.png"
+        );
+    }
+
+    /// Compares cached downloads with the `.p8` they were exported from: the reference
+    /// cart written as a `.p8.png`, and each `.p8.png` saved by pico-8 in the fixtures of
+    /// the cart-model
+    #[test]
+    fn compares_cached_downloads() {
+        let carts_dir =
+            path::Path::new(env!("CARGO_MANIFEST_DIR")).join("../pico-8/cart-model/tests/carts");
+        let root_dir = std::env::temp_dir().join(format!("pico-build-bbs-{}", std::process::id()));
+        let cache_dir = root_dir.join(CACHE_DIR);
+        fs::create_dir_all(&cache_dir).unwrap();
+
+        let reference = CartData::from_path(&carts_dir.join("reference.p8")).unwrap();
+        let mut png_data = Vec::new();
+        png::write_cart_png(&reference, &png::CartTemplate::default(), &mut png_data).unwrap();
+        fs::write(cache_dir.join("reference.p8.png"), png_data).unwrap();
+        for entry in fs::read_dir(&carts_dir).unwrap() {
+            let png_path = entry.unwrap().path();
+            if png_path.to_string_lossy().ends_with(".p8.png") {
+                fs::copy(&png_path, cache_dir.join(png_path.file_name().unwrap())).unwrap();
+            }
+        }
+
+        for entry in fs::read_dir(&cache_dir).unwrap() {
+            let file_name = entry.unwrap().file_name();
+            let cart_id = cart_id(&file_name.to_string_lossy()).unwrap();
+            let (published_cart, fetched) = fetch_cart(&root_dir, &cart_id, false).unwrap();
+            assert_eq!(fetched, Fetched::Cached);
+            let exported = CartData::from_path(&carts_dir.join(format!("{cart_id}.p8"))).unwrap();
+            let diff = published_cart.diff(&exported);
+            assert!(diff.is_empty(), "{cart_id} differs from its .p8: {diff:?}");
+        }
+        fs::remove_dir_all(&root_dir).unwrap();
+    }
+}
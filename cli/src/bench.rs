@@ -0,0 +1,201 @@
+//! Timing repeated builds of the project, for `pico-build-rs bench`
+//!
+//! Each build is timed per action, `compile` being the transforms and the merge into
+//! the cart and `save-compiled-cartridge` the writing of the cart and its manifest.
+//! The first build is reported on its own as the cold one, the rest summarized
+//! as the warm ones
+//!
+//! The frames of the ui are measured the same way, see [`FrameTimes`]
+//!
+//! Allocations are only counted with the `bench` feature, which installs a counting
+//! allocator as the global one of the binary
+
+use core::fmt;
+use core::time::Duration;
+
+use std::collections::VecDeque;
+use std::time::Instant;
+
+#[cfg(feature = "bench")]
+mod counting {
+    use core::alloc::{GlobalAlloc, Layout};
+    use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+    use std::alloc::System;
+
+    /// Counts the allocations while [`count_allocations`] runs, passing them on to the system
+    struct CountingAllocator;
+
+    static COUNTING: AtomicBool = AtomicBool::new(false);
+    static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+    unsafe impl GlobalAlloc for CountingAllocator {
+        unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+            if COUNTING.load(Ordering::Relaxed) {
+                ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+            }
+            unsafe { System.alloc(layout) }
+        }
+        unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+            unsafe { System.dealloc(ptr, layout) }
+        }
+        unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+            if COUNTING.load(Ordering::Relaxed) {
+                ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+            }
+            unsafe { System.realloc(ptr, layout, new_size) }
+        }
+    }
+
+    #[global_allocator]
+    static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+    /// Returns the result of `f`, and the allocations made while running it
+    ///
+    /// Allocations of other threads are counted as well, the builds run on a single one
+    pub fn count_allocations<T>(f: impl FnOnce() -> T) -> (T, usize) {
+        let before = ALLOCATIONS.load(Ordering::Relaxed);
+        COUNTING.store(true, Ordering::Relaxed);
+        let result = f();
+        COUNTING.store(false, Ordering::Relaxed);
+        (result, ALLOCATIONS.load(Ordering::Relaxed) - before)
+    }
+}
+
+/// Returns the result of `f`, and the allocations made while running it
+///
+/// The allocations are `None` without the `bench` feature, see the module-documentation
+pub fn count_allocations<T>(f: impl FnOnce() -> T) -> (T, Option<usize>) {
+    #[cfg(feature = "bench")]
+    {
+        let (result, allocations) = counting::count_allocations(f);
+        (result, Some(allocations))
+    }
+    #[cfg(not(feature = "bench"))]
+    {
+        (f(), None)
+    }
+}
+
+/// The measurements of one stage of a build
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct StageSample {
+    pub time: Duration,
+    /// `None` when not counted, see [`count_allocations`]
+    pub allocations: Option<usize>,
+}
+
+/// The measurements of one stage over the warm builds
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct StageSummary {
+    pub mean: Duration,
+    pub p50: Duration,
+    pub p95: Duration,
+    pub max: Duration,
+    pub mean_allocations: Option<usize>,
+}
+
+impl StageSummary {
+    /// `None` without samples
+    pub fn of(samples: &[StageSample]) -> Option<StageSummary> {
+        let mut times: Vec<Duration> = samples.iter().map(|sample| sample.time).collect();
+        times.sort();
+        let count = u32::try_from(times.len()).ok().filter(|count| *count > 0)?;
+        // Nearest-rank, so each percentile is one of the samples
+        let percentile = |percent: usize| times[(times.len() * percent).div_ceil(100).max(1) - 1];
+        Some(StageSummary {
+            mean: times.iter().sum::<Duration>() / count,
+            p50: percentile(50),
+            p95: percentile(95),
+            max: times[times.len() - 1],
+            mean_allocations: samples
+                .iter()
+                .map(|sample| sample.allocations)
+                .sum::<Option<usize>>()
+                .map(|allocations| allocations / samples.len()),
+        })
+    }
+}
+
+/// The frames kept for [`FrameTimes::summary`]
+const FRAME_SAMPLE_COUNT: usize = 120;
+
+/// The time and allocations of drawing the recent frames of the ui
+#[derive(Debug, Default)]
+pub struct FrameTimes {
+    samples: VecDeque<StageSample>,
+    summary: Option<StageSummary>,
+}
+
+impl FrameTimes {
+    /// Returns the result of drawing a frame with `draw`, and its measurements
+    pub fn measure<T>(draw: impl FnOnce() -> T) -> (T, StageSample) {
+        let started = Instant::now();
+        let (drawn, allocations) = count_allocations(draw);
+        let sample = StageSample {
+            time: started.elapsed(),
+            allocations,
+        };
+        (drawn, sample)
+    }
+    /// Keeps the frame, dropping the oldest beyond [`FRAME_SAMPLE_COUNT`]
+    pub fn record(&mut self, sample: StageSample) {
+        if self.samples.len() == FRAME_SAMPLE_COUNT {
+            self.samples.pop_front();
+        }
+        self.samples.push_back(sample);
+        self.summary = StageSummary::of(self.samples.make_contiguous());
+    }
+    /// The recent frames, `None` before the first one is drawn
+    pub const fn summary(&self) -> Option<&StageSummary> {
+        self.summary.as_ref()
+    }
+}
+
+/// Milliseconds with a fraction, as the stages of small projects take well below one
+pub struct Millis(pub Duration);
+
+impl fmt::Display for Millis {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_fmt(format_args!("{:.2}ms", self.0.as_secs_f64() * 1000.0))
+    }
+}
+
+/// The count of allocations, or `-` when they were not counted
+pub struct Allocations(pub Option<usize>);
+
+impl fmt::Display for Allocations {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            Some(allocations) => allocations.fmt(f),
+            None => "-".fmt(f),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn summarizes_samples() {
+        let samples: Vec<StageSample> = (1..=20)
+            .map(|millis| StageSample {
+                time: Duration::from_millis(millis),
+                allocations: Some(10),
+            })
+            .collect();
+        let summary = StageSummary::of(&samples).unwrap();
+        assert_eq!(summary.mean, Duration::from_micros(10_500));
+        assert_eq!(summary.p50, Duration::from_millis(10));
+        assert_eq!(summary.p95, Duration::from_millis(19));
+        assert_eq!(summary.max, Duration::from_millis(20));
+        assert_eq!(summary.mean_allocations, Some(10));
+        assert_eq!(StageSummary::of(&[]), None);
+        let (allocated, allocations) = count_allocations(|| vec![0u8; 16]);
+        assert_eq!(allocated.len(), 16);
+        assert_eq!(allocations.is_some(), cfg!(feature = "bench"));
+        assert!(allocations.is_none_or(|allocations| allocations >= 1));
+        assert_eq!(format!("{:>3}", Allocations(None)), "  -");
+    }
+}
@@ -0,0 +1,148 @@
+//! Collecting the GIFs recorded in pico-8, for `capture`
+//!
+//! pico-8 records a GIF from F8 until F9, saving it to its desktop-folder. Once pico-8 is
+//! closed, the GIFs saved since the launch are moved into the [`MEDIA_DIR`], named after
+//! the cart and its build-version, so the recordings of each build are kept apart
+
+use core::time::Duration;
+
+use std::fs;
+use std::io;
+use std::path;
+use std::process;
+use std::time::SystemTime;
+
+use pico_build_rs::provenance;
+
+/// The directory of the collected recordings, relative to the project-directory
+pub const MEDIA_DIR: &str = "dist/media";
+
+/// File-systems keep coarser modification-times than the clock, so a GIF saved right
+/// after the launch may seem saved before it
+const MODIFIED_TOLERANCE: Duration = Duration::from_secs(1);
+
+/// The build-version of the cart, the start of the [`provenance::content_hash`] of the
+/// written cart, so a rebuild without changes keeps the version
+pub fn build_version(cart_data: &[u8]) -> String {
+    let content_hash = format!("{:016x}", provenance::content_hash(cart_data));
+    content_hash[..8].to_string()
+}
+
+/// Returns the GIFs of the folder modified since the time, the oldest first
+pub fn recorded_since(
+    desktop_dir: &path::Path,
+    since: SystemTime,
+) -> io::Result<Vec<path::PathBuf>> {
+    let since = since - MODIFIED_TOLERANCE;
+    let mut recordings = Vec::new();
+    for entry in fs::read_dir(desktop_dir)? {
+        let entry = entry?;
+        let path = entry.path();
+        if !path
+            .extension()
+            .is_some_and(|extension| extension.eq_ignore_ascii_case("gif"))
+        {
+            continue;
+        }
+        let modified = entry.metadata()?.modified()?;
+        if modified >= since {
+            recordings.push((modified, path));
+        }
+    }
+    recordings.sort();
+    Ok(recordings.into_iter().map(|(_, path)| path).collect())
+}
+
+/// Moves the GIFs into the media-directory as `<cart>-<build-version>-<n>.gif`, counting
+/// on from the recordings of the version already collected, and returns their new paths
+pub fn collect(
+    recordings: &[path::PathBuf],
+    media_dir: &path::Path,
+    cart_name: &str,
+    build_version: &str,
+) -> io::Result<Vec<path::PathBuf>> {
+    fs::create_dir_all(media_dir)?;
+    let mut collected = Vec::with_capacity(recordings.len());
+    let mut index = 1;
+    for recording in recordings {
+        let media_path = loop {
+            let media_path = media_dir.join(format!("{cart_name}-{build_version}-{index}.gif"));
+            index += 1;
+            if !media_path.exists() {
+                break media_path;
+            }
+        };
+        // Renaming fails across file-systems, e.g. to a desktop on another drive
+        if fs::rename(recording, &media_path).is_err() {
+            fs::copy(recording, &media_path)?;
+            fs::remove_file(recording)?;
+        }
+        collected.push(media_path);
+    }
+    Ok(collected)
+}
+
+/// Converts the GIF into an mp4 next to it through `ffmpeg`, returning its path, or
+/// `None` if `ffmpeg` is not found on the path
+pub fn convert_to_mp4(gif_path: &path::Path) -> io::Result<Option<path::PathBuf>> {
+    let mp4_path = gif_path.with_extension("mp4");
+    let status = match process::Command::new("ffmpeg")
+        .args(["-y", "-loglevel", "error", "-i"])
+        .arg(gif_path)
+        // Most players only play yuv420p, which needs an even width and height
+        .args([
+            "-movflags",
+            "faststart",
+            "-pix_fmt",
+            "yuv420p",
+            "-vf",
+            "scale=trunc(iw/2)*2:trunc(ih/2)*2:flags=neighbor",
+        ])
+        .arg(&mp4_path)
+        .stdin(process::Stdio::null())
+        .status()
+    {
+        Ok(status) => status,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e),
+    };
+    if !status.success() {
+        return Err(io::Error::other(format!("ffmpeg exited with {status}")));
+    }
+    Ok(Some(mp4_path))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn collects_recordings_by_build_version() {
+        let temp_dir = std::env::temp_dir().join(format!("pico-capture-{}", std::process::id()));
+        let desktop_dir = temp_dir.join("desktop");
+        let media_dir = temp_dir.join(MEDIA_DIR);
+        fs::create_dir_all(&desktop_dir).unwrap();
+        // Recorded before the launch
+        let old_gif = fs::File::create(desktop_dir.join("old_0.gif")).unwrap();
+        old_gif.set_modified(SystemTime::UNIX_EPOCH).unwrap();
+        let launched_at = SystemTime::now();
+        fs::write(desktop_dir.join("game_0.gif"), "a").unwrap();
+        fs::write(desktop_dir.join("game_0.png"), "b").unwrap();
+        let recordings = recorded_since(&desktop_dir, launched_at).unwrap();
+        assert_eq!(recordings, [desktop_dir.join("game_0.gif")]);
+
+        let build_version = build_version(b"cart");
+        assert_eq!(build_version.len(), 8);
+        // The recordings of an earlier capture of the version are kept
+        fs::create_dir_all(&media_dir).unwrap();
+        fs::write(media_dir.join(format!("game-{build_version}-1.gif")), "c").unwrap();
+        let collected = collect(&recordings, &media_dir, "game", &build_version).unwrap();
+        assert_eq!(
+            collected,
+            [media_dir.join(format!("game-{build_version}-2.gif"))]
+        );
+        assert_eq!(fs::read_to_string(&collected[0]).unwrap(), "a");
+        assert!(!desktop_dir.join("game_0.gif").exists());
+        fs::remove_dir_all(&temp_dir).unwrap();
+    }
+}
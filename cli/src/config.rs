@@ -2,7 +2,25 @@ use anyhow::anyhow;
 
 use std::path;
 
+use pico_8_cart_builder::{
+    ConstantValue, Constants, IncludeResolver, SourceExclude, SourceOrder, TabOrder, TabOverflow,
+};
+use pico_8_cart_model::GfxRows;
+use pico_8_cart_model::embed::{self, SpriteEmbedding};
+use pico_build_rs::assets::{self, AssetImport};
+use pico_build_rs::label::LabelFallback;
+use pico_build_rs::merge::MergeStrategy;
+use pico_build_rs::minify::Minification;
+use pico_build_rs::paths;
+use pico_build_rs::pico_folders::PicoFolders;
+use pico_build_rs::strings::{self, Localization};
+use pico_build_rs::transform::BuildProfile;
+
 use crate::args::AppArgs;
+use crate::keys::KeyBindings;
+use crate::log_file::LogFileSettings;
+use crate::theme::Theme;
+use crate::workspace::{WORKSPACE_FILE_NAME, Workspace, WorkspaceMember};
 
 pub fn try_from_path<P: AsRef<path::Path> + ?Sized>(
     config_file_path: &P,
@@ -13,6 +31,11 @@ pub fn try_from_path<P: AsRef<path::Path> + ?Sized>(
     config::Config::builder().add_source(config_file).build()
 }
 
+/// Whether the directory has a `pico.toml` or `pico.json`
+pub fn has_config_file(root_dir: &path::Path) -> bool {
+    root_dir.join("pico.toml").exists() || root_dir.join("pico.json").exists()
+}
+
 pub struct AppConfigFile {
     values: config::Config,
 }
@@ -32,7 +55,9 @@ impl From<config::Config> for AppConfigFile {
 
 impl AppConfigFile {
     pub fn open(args: &AppArgs) -> anyhow::Result<AppConfigFile> {
-        let root_dir = args.get_root_directory()?;
+        AppConfigFile::open_in(&args.get_root_directory()?)
+    }
+    pub fn open_in(root_dir: &path::Path) -> anyhow::Result<AppConfigFile> {
         let toml_path = root_dir.join("pico.toml");
         let json_path = root_dir.join("pico.json");
         let mut config_file = None;
@@ -45,12 +70,20 @@ impl AppConfigFile {
 
         config_file.ok_or_else(|| anyhow!("No config file found."))
     }
+    /// Reads the configuration from JSON, with the keys of a `pico.json`
+    pub fn from_json(json: &str) -> anyhow::Result<AppConfigFile> {
+        config::Config::builder()
+            .add_source(config::File::from_str(json, config::FileFormat::Json))
+            .build()
+            .map(AppConfigFile::from)
+            .map_err(|e| anyhow!("Invalid JSON-configuration: {e}"))
+    }
 }
 
 /// The set of values defining
 /// runtime-behavior for the `pico-build-rs`
 /// command-line interface
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct AppConfiguration {
     /// Required.
     ///
@@ -65,8 +98,6 @@ pub struct AppConfiguration {
     /// Whether to automatically rebuild
     /// once an update has been seen on one of
     /// the source-files.
-    ///
-    /// unimplemented atm.
     pub watch: bool,
     /// Required. (but if not found, false will be used)
     ///
@@ -78,9 +109,506 @@ pub struct AppConfiguration {
     /// if `open_pico` has been set to true,
     /// while no executable path has been provided.
     pub executable: Option<path::PathBuf>,
+    /// Not required (`dark` will be used if not found)
+    ///
+    /// The color-theme of the terminal user-interface,
+    /// one of `dark`, `light`, `high-contrast` or `none`
+    pub theme: Theme,
+    /// Not required (`TODO` and `FIXME` will be used if not found)
+    ///
+    /// The comment-markers listed by the todo-panel
+    pub todo_markers: Vec<String>,
+    /// Not required (`debug`, or `release` if `--release` is passed, will be used if not found)
+    ///
+    /// The build-profile used when compiling the cartridge
+    pub profile: BuildProfile,
+    /// Not required (`printh` will be used if not found)
+    ///
+    /// The functions whose calls are stripped in the release-profile
+    pub debug_calls: Vec<String>,
+    /// Not required (files sorted by name will be used if not found)
+    ///
+    /// The source-files pinned to the first tabs, from `order` in the `[tabs]` table,
+    /// or in a `tabs.toml` in the source-directory, and the order of the others from
+    /// `source_order` in the `[tabs]` table, one of `alphabetical`, `number-prefix`,
+    /// `manifest` or `modified-time`
+    pub tab_order: TabOrder,
+    /// Not required (`preserve` will be used if not found)
+    ///
+    /// The rows written to the `__gfx__` section of the compiled cart,
+    /// one of `preserve`, `minimal` or `full`
+    pub gfx_rows: GfxRows,
+    /// Not required (only the directory of the including file will be searched if not found)
+    ///
+    /// The directories searched for files included with `#include`, from `include_paths`
+    pub include_resolver: IncludeResolver,
+    /// Not required (no source-files will be excluded if not found)
+    ///
+    /// The glob-patterns of the source-files skipped when compiling, from `exclude`,
+    /// and from a `.picoignore` in the source-directory
+    pub exclude: SourceExclude,
+    /// Not required (`replace-code` will be used if not found)
+    ///
+    /// How code already in the cart is treated when compiling into it,
+    /// one of `replace-code`, `preserve-main-tab` or `merge-by-marker`
+    pub merge: MergeStrategy,
+    /// Not required (`error` will be used if not found)
+    ///
+    /// What becomes of the source-files past the last tab, `error` or `merge-into-last`
+    pub tab_overflow: TabOverflow,
+    /// Not required (the default keys will be used if not found)
+    ///
+    /// The keys rebound by the `[keys]` section, see [`KeyBindings`]
+    pub keys: KeyBindings,
+    /// Not required (nothing is written if not found)
+    ///
+    /// The log-file written next to the log-panel, from the `[log_file]` section,
+    /// see [`LogFileSettings`]
+    pub log_file: Option<LogFileSettings>,
+    /// Not required (the sprites stay in `__gfx__` if not found)
+    ///
+    /// The sprites moved into a generated tab, from the `[embed_sprites]` section,
+    /// see [`pico_8_cart_model::embed`]
+    pub embed_sprites: Option<SpriteEmbedding>,
+    /// Not required (no images are imported if not found)
+    ///
+    /// The png-images drawn into `__gfx__` on each build, from the `[assets]` section,
+    /// see [`AssetImport`]
+    pub assets: Option<AssetImport>,
+    /// Not required (the top-left tiles of the map will be used if not found)
+    ///
+    /// What the label of a cart without one is rendered from, from the `[label]`
+    /// section, see [`LabelFallback`]
+    pub label_fallback: LabelFallback,
+    /// Not required (the strings are built as written if not found)
+    ///
+    /// The language whose string-catalog is swapped in on each build, from `language`
+    /// in the `[strings]` section or of the `[[cart]]`, see [`Localization`]
+    pub localization: Option<Localization>,
+    /// Not required (the tabs are written as compiled if not found)
+    ///
+    /// The minifying of the compiled tabs, from the `[minify]` section,
+    /// see [`Minification`]
+    pub minify: Option<Minification>,
+    /// Not required (only the constants defined in the source-files are substituted if
+    /// not found)
+    ///
+    /// The constants of the `[constants]` section, see [`Constants`]
+    pub constants: Constants,
+    /// Not required (the version in the header of the cart will be used if not found)
+    ///
+    /// The cart-version the code is checked against, from `cart_version`, e.g. `16`
+    /// for a web player of pico-8 0.1
+    pub cart_version: Option<usize>,
+    /// Not required (the functions are kept if not found)
+    ///
+    /// Whether the top-level functions never called are removed on each build, from
+    /// `remove_dead_code`, see [`pico_build_rs::dead_code`]
+    pub remove_dead_code: bool,
+    /// Not required (no backups are kept if not found)
+    ///
+    /// The backups kept of the cart each build replaces, `game.p8.bak` for the latest
+    pub cart_backups: usize,
+    /// Not required (read from the `config.txt` of PICO-8 if not found)
+    ///
+    /// The folders of PICO-8 overridden by the `[pico8]` section, see [`PicoFolders`]
+    pub pico_folder_overrides: PicoFolders,
+    /// Not required (the `config.txt` in the configuration-directory of PICO-8 is used
+    /// if not found)
+    ///
+    /// The `config_txt` of the `[pico8]` section
+    pub pico_config_txt: Option<path::PathBuf>,
+    /// Not required (the top-level `src_dir`, `cart` and `include_paths` will be used
+    /// if not found)
+    ///
+    /// The name of the `[[cart]]` built, selected by `--target` or else the first one,
+    /// see [`CartTarget`]
+    pub target: Option<String>,
+    /// Set by `--safe`, see [`AppConfiguration::restrict_to_root`]
+    pub safe: bool,
+    /// The directory of the project, which shown paths are relative to
+    pub root_dir: path::PathBuf,
+}
+
+/// Whether the path is inside the directory, following `..` and symbolic links
+///
+/// A path which does not exist yet, such as a cart not built yet, is checked by its parent
+fn is_within(path: &path::Path, root_dir: &path::Path) -> bool {
+    let Ok(root_dir) = root_dir.canonicalize() else {
+        return false;
+    };
+    let canonical_path = path.canonicalize().or_else(|_| {
+        path.parent()
+            .filter(|parent| !parent.as_os_str().is_empty())
+            .unwrap_or(path::Path::new("."))
+            .canonicalize()
+            .map(|parent| parent.join(path.file_name().unwrap_or_default()))
+    });
+    canonical_path.is_ok_and(|canonical_path| canonical_path.starts_with(root_dir))
+}
+
+fn default_todo_markers() -> Vec<String> {
+    pico_build_rs::todos::DEFAULT_MARKERS
+        .iter()
+        .map(ToString::to_string)
+        .collect()
+}
+
+/// Reads `order` from the `tabs.toml` in the source-directory, if there is one
+fn tab_order_from_src_dir(src_dir: &path::Path) -> anyhow::Result<TabOrder> {
+    let tabs_path = src_dir.join("tabs.toml");
+    if !tabs_path.exists() {
+        return Ok(TabOrder::default());
+    }
+    let order = try_from_path(tabs_path.as_path())?.get_array("order")?;
+    order
+        .into_iter()
+        .map(config::Value::into_string)
+        .collect::<Result<Vec<_>, _>>()
+        .map(TabOrder::new)
+        .map_err(Into::into)
+}
+
+/// Reads the `[embed_sprites]` section, e.g. `sprites = ["16-31", "64"]`
+fn sprite_embedding_from_table(
+    mut table: config::Map<String, config::Value>,
+) -> anyhow::Result<SpriteEmbedding> {
+    let mut sprite_embedding = SpriteEmbedding::default();
+    if let Some(value) = table.remove("sprites") {
+        sprite_embedding.ranges = value
+            .into_array()?
+            .into_iter()
+            .map(|value| {
+                let range = value.into_string()?;
+                embed::parse_sprite_range(&range).ok_or_else(|| {
+                    anyhow!(
+                        "Invalid sprite-range {range:?} in [embed_sprites], expected e.g. \"16-31\""
+                    )
+                })
+            })
+            .collect::<anyhow::Result<_>>()?;
+    }
+    if let Some(value) = table.remove("loader") {
+        sprite_embedding.loader = value.into_string()?;
+    }
+    if let Some(value) = table.remove("load_at_init") {
+        sprite_embedding.load_at_init = value.into_bool()?;
+    }
+    Ok(sprite_embedding)
+}
+
+/// Reads the `[assets]` section, e.g. `sprites = { player = 16 }`,
+/// `maps = { level = [0, 32] }`, `label = "title.ase"`, `locked_sprites = [[0, 15]]`
+/// and `locked_maps = [[0, 0, 16, 16]]`, with a relative assets-directory joined onto
+/// `src_base`
+fn asset_import_from_table(
+    mut table: config::Map<String, config::Value>,
+    src_base: &path::Path,
+) -> anyhow::Result<AssetImport> {
+    let assets_dir = match table.remove("dir") {
+        Some(value) => paths::from_config(&value.into_string()?),
+        None => path::PathBuf::from(assets::DEFAULT_ASSETS_DIR),
+    };
+    let mut asset_import = AssetImport::new(src_base.join(assets_dir));
+    if let Some(value) = table.remove("sprites") {
+        for (file_stem, sprite) in value.into_table()? {
+            let sprite = sprite.into_int()?;
+            let sprite = usize::try_from(sprite)
+                .map_err(|_| anyhow!("Invalid sprite {sprite} for {file_stem:?} in [assets]"))?;
+            asset_import = asset_import.with_sprite(file_stem, sprite);
+        }
+    }
+    if let Some(value) = table.remove("maps") {
+        for (file_stem, origin) in value.into_table()? {
+            let invalid_origin =
+                || anyhow!("Invalid tile for {file_stem:?} in [assets], expected e.g. [0, 32]");
+            let origin = origin
+                .into_array()?
+                .into_iter()
+                .map(|value| usize::try_from(value.into_int()?).map_err(|_| invalid_origin()))
+                .collect::<anyhow::Result<Vec<usize>>>()?;
+            let [x, y] = origin[..] else {
+                return Err(invalid_origin());
+            };
+            asset_import = asset_import.with_map(file_stem, x, y);
+        }
+    }
+    if let Some(value) = table.remove("label") {
+        asset_import = asset_import.with_label(paths::from_config(&value.into_string()?));
+    }
+    if let Some(value) = table.remove("locked_sprites") {
+        for range in value.into_array()? {
+            let invalid_range =
+                || anyhow!("Invalid locked sprites in [assets], expected e.g. [0, 15]");
+            let range = range
+                .into_array()?
+                .into_iter()
+                .map(|value| usize::try_from(value.into_int()?).map_err(|_| invalid_range()))
+                .collect::<anyhow::Result<Vec<usize>>>()?;
+            let [first, last] = range[..] else {
+                return Err(invalid_range());
+            };
+            asset_import = asset_import.with_locked_sprites(first..=last);
+        }
+    }
+    if let Some(value) = table.remove("locked_maps") {
+        for region in value.into_array()? {
+            let invalid_region = || {
+                anyhow!(
+                    "Invalid locked map-region in [assets], expected e.g. [0, 0, 16, 16] for x, y, width and height"
+                )
+            };
+            let region = region
+                .into_array()?
+                .into_iter()
+                .map(|value| usize::try_from(value.into_int()?).map_err(|_| invalid_region()))
+                .collect::<anyhow::Result<Vec<usize>>>()?;
+            let [x, y, width, height] = region[..] else {
+                return Err(invalid_region());
+            };
+            asset_import = asset_import.with_locked_map(assets::MapRegion {
+                x,
+                y,
+                width,
+                height,
+            });
+        }
+    }
+    Ok(asset_import)
+}
+
+/// Reads the `[constants]` section, e.g. `DEBUG = false` and `TITLE = "cat"`
+fn constants_from_table(table: config::Map<String, config::Value>) -> anyhow::Result<Constants> {
+    table
+        .into_iter()
+        .try_fold(Constants::new(), |constants, (name, value)| {
+            let value = match value.kind {
+                config::ValueKind::Boolean(boolean) => ConstantValue::boolean(boolean),
+                config::ValueKind::I64(number) => ConstantValue::number(number as f64),
+                config::ValueKind::Float(number) => ConstantValue::number(number),
+                config::ValueKind::String(string) => ConstantValue::string(&string),
+                _ => return Err(anyhow!("Invalid value of {name} in [constants]")),
+            };
+            constants
+                .with_constant(name, value)
+                .map_err(|e| anyhow!("{}: {e}", e.code()))
+        })
+}
+
+/// Reads the `[minify]` section, e.g. `rename_locals = true` and `skip = ["debug.lua"]`
+fn minification_from_table(
+    mut table: config::Map<String, config::Value>,
+) -> anyhow::Result<Minification> {
+    let mut minification = Minification::default();
+    if let Some(value) = table.remove("rename_locals") {
+        minification.rename_locals = value.into_bool()?;
+    }
+    if let Some(value) = table.remove("skip") {
+        minification.skip = value
+            .into_array()?
+            .into_iter()
+            .map(config::Value::into_string)
+            .collect::<Result<_, _>>()?;
+    }
+    Ok(minification)
+}
+
+/// Reads the `[label]` section, e.g. `fallback = "map"` with `map = [0, 16]`, or
+/// `fallback = "sprites"` with `sprites = [[1, 2], [17, 18]]`
+///
+/// The fallback defaults to `sprites` if they are configured, and else to `map`
+fn label_fallback_from_table(
+    mut table: config::Map<String, config::Value>,
+) -> anyhow::Result<LabelFallback> {
+    let take_numbers = |value: config::Value, what: &str| {
+        value
+            .into_array()?
+            .into_iter()
+            .map(|value| {
+                let number = value.into_int()?;
+                usize::try_from(number).map_err(|_| anyhow!("Invalid {what} {number} in [label]"))
+            })
+            .collect::<anyhow::Result<Vec<usize>>>()
+    };
+    let map = table
+        .remove("map")
+        .map(|value| take_numbers(value, "tile"))
+        .transpose()?;
+    let sprites = table
+        .remove("sprites")
+        .map(|value| {
+            value
+                .into_array()?
+                .into_iter()
+                .map(|row| take_numbers(row, "sprite"))
+                .collect::<anyhow::Result<Vec<_>>>()
+        })
+        .transpose()?;
+    let name = match table.remove("fallback") {
+        Some(value) => value.into_string()?,
+        None if sprites.is_some() => "sprites".to_string(),
+        None => "map".to_string(),
+    };
+    match name.as_str() {
+        "none" => Ok(LabelFallback::None),
+        "map" => match map.as_deref() {
+            None => Ok(LabelFallback::default()),
+            Some(&[x, y]) => Ok(LabelFallback::Map { x, y }),
+            Some(_) => Err(anyhow!("Invalid map in [label], expected e.g. [0, 16]")),
+        },
+        "sprites" => Ok(LabelFallback::Sprites(sprites.ok_or_else(|| {
+            anyhow!("fallback = \"sprites\" needs sprites in [label], e.g. [[1, 2], [17, 18]]")
+        })?)),
+        _ => Err(anyhow!("Unknown label-fallback {name:?} in config-file")),
+    }
+}
+
+/// Reads the `[strings]` section, e.g. `language = "de"` and `dir = "lang"`, with a
+/// relative strings-directory joined onto `src_base`
+///
+/// The language of the `[[cart]]` built overrides the one of the section, so each
+/// language can be built as a cart of its own
+fn localization_from_table(
+    mut table: config::Map<String, config::Value>,
+    src_base: &path::Path,
+    target_language: Option<String>,
+) -> anyhow::Result<Option<Localization>> {
+    let strings_dir = match table.remove("dir") {
+        Some(value) => paths::from_config(&value.into_string()?),
+        None => path::PathBuf::from(strings::DEFAULT_STRINGS_DIR),
+    };
+    let language = match target_language {
+        Some(language) => Some(language),
+        None => table
+            .remove("language")
+            .map(config::Value::into_string)
+            .transpose()?,
+    };
+    Ok(language.map(|language| Localization {
+        strings_dir: src_base.join(strings_dir),
+        language,
+    }))
+}
+
+/// The `[pico8]` section of the config-file, returning the overridden folders
+/// and the path of the `config.txt`
+fn pico_folders_from_table(
+    mut table: config::Map<String, config::Value>,
+) -> anyhow::Result<(PicoFolders, Option<path::PathBuf>)> {
+    let mut take_path = |name: &str| -> anyhow::Result<Option<path::PathBuf>> {
+        table
+            .remove(name)
+            .map(|value| Ok(paths::from_config(&value.into_string()?)))
+            .transpose()
+    };
+    let pico_folders = PicoFolders {
+        root_path: take_path("root_path")?,
+        desktop_path: take_path("desktop_path")?,
+        cdata_path: take_path("cdata_path")?,
+    };
+    Ok((pico_folders, take_path("config_txt")?))
+}
+
+/// A `[[cart]]` of the config-file, for building several carts of one project,
+/// e.g. a game and its level-editor
+///
+/// ```toml
+/// [[cart]]
+/// name = "editor"
+/// src_dir = "editor"
+/// cart = "editor.p8"
+/// include_paths = ["shared"]
+/// ```
+///
+/// The keys left out fall back to the top-level ones, except for `cart`: a top-level
+/// `cart` clashes with the `[[cart]]` array, so each cart names its own. A `language`
+/// selects the string-catalog of the cart, e.g. for a `game_de.p8` built with
+/// `--target de`, see [`localization_from_table`]
+#[derive(Clone, Debug)]
+pub struct CartTarget {
+    pub name: String,
+    src_dir: Option<String>,
+    cart: Option<String>,
+    include_paths: Option<Vec<config::Value>>,
+    language: Option<String>,
+}
+
+impl CartTarget {
+    fn from_table(mut table: config::Map<String, config::Value>) -> anyhow::Result<CartTarget> {
+        let name = table
+            .remove("name")
+            .ok_or_else(|| anyhow!("A [[cart]] in the config-file has no name"))?
+            .into_string()?;
+        let mut take_string = |key: &str| table.remove(key).map(config::Value::into_string);
+        let src_dir = take_string("src_dir").transpose()?;
+        let cart = take_string("cart").transpose()?;
+        let language = take_string("language").transpose()?;
+        let include_paths = table
+            .remove("include_paths")
+            .map(config::Value::into_array)
+            .transpose()?;
+        Ok(CartTarget {
+            name,
+            src_dir,
+            cart,
+            include_paths,
+            language,
+        })
+    }
+}
+
+/// The `[[cart]]`s of the config-file, in the order listed
+fn cart_targets(config_file: &AppConfigFile) -> anyhow::Result<Vec<CartTarget>> {
+    match config_file.values.get_array("cart") {
+        Ok(values) => values
+            .into_iter()
+            .map(|value| CartTarget::from_table(value.into_table()?))
+            .collect(),
+        Err(_) => Ok(Vec::new()),
+    }
+}
+
+/// The names of the `[[cart]]`s in the config-file of the root-directory,
+/// empty without a config-file
+pub fn target_names(root_dir: &path::Path) -> anyhow::Result<Vec<String>> {
+    if !has_config_file(root_dir) {
+        return Ok(Vec::new());
+    }
+    let config_file = AppConfigFile::open_in(root_dir)?;
+    Ok(cart_targets(&config_file)?
+        .into_iter()
+        .map(|target| target.name)
+        .collect())
+}
+
+fn default_debug_calls() -> Vec<String> {
+    pico_build_rs::transform::DEFAULT_DEBUG_CALLS
+        .iter()
+        .map(ToString::to_string)
+        .collect()
 }
 impl AppConfiguration {
     pub fn new(args: &AppArgs) -> anyhow::Result<AppConfiguration> {
+        // Bypasses the discovery of config-files and workspaces
+        if let Some(json) = args.get_config_json() {
+            return AppConfiguration::from_config_file(
+                args,
+                AppConfigFile::from_json(json)?,
+                &args.get_root_directory()?,
+                path::Path::new(""),
+                args.get_target(),
+            );
+        }
+        if let Some(project) = args.get_project() {
+            let root_dir = args.get_root_directory()?;
+            let workspace = Workspace::open(&root_dir)?.ok_or_else(|| {
+                anyhow!(
+                    "--project needs a {WORKSPACE_FILE_NAME} in {}",
+                    root_dir.display()
+                )
+            })?;
+            return AppConfiguration::for_member(args, workspace.find(project)?);
+        }
         if let Some((src_dir, cart, watch, open_pico, executable)) = args.configuration_values() {
             // In this case we assume the user explicitly intended this,
             // due to how cumbersome it would be to type all the args out fully
@@ -90,71 +618,449 @@ impl AppConfiguration {
                 watch,
                 open_pico,
                 executable: executable.map(path::Path::to_path_buf),
+                theme: args.get_theme().unwrap_or_default(),
+                todo_markers: default_todo_markers(),
+                profile: args.get_build_profile(),
+                debug_calls: default_debug_calls(),
+                tab_order: tab_order_from_src_dir(src_dir)?,
+                gfx_rows: GfxRows::default(),
+                include_resolver: IncludeResolver::default(),
+                exclude: SourceExclude::default().with_ignore_file(src_dir)?,
+                merge: MergeStrategy::default(),
+                tab_overflow: TabOverflow::default(),
+                keys: KeyBindings::default(),
+                log_file: None,
+                embed_sprites: None,
+                assets: None,
+                label_fallback: LabelFallback::default(),
+                localization: None,
+                minify: None,
+                constants: Constants::new(),
+                cart_version: None,
+                remove_dead_code: false,
+                cart_backups: 0,
+                pico_folder_overrides: PicoFolders::default(),
+                pico_config_txt: None,
+                target: None,
+                safe: false,
+                root_dir: args.get_root_directory()?.into_owned(),
+            })
+            .and_then(|cfg| {
+                if args.safe {
+                    cfg.restrict_to_root(&args.get_root_directory()?)
+                } else {
+                    Ok(cfg)
+                }
             })
         } else {
             let root_dir = args.get_root_directory()?;
-            let toml_path = root_dir.join("pico.toml");
-            let json_path = root_dir.join("pico.json");
-            let mut config_file = None;
-
-            if toml_path.exists() {
-                config_file = AppConfigFile::try_from(toml_path.as_path()).map(Some)?
-            } else if json_path.exists() {
-                config_file = AppConfigFile::try_from(json_path.as_path()).map(Some)?
-            };
-
-            let Some(config_file) = config_file else {
-                return Err(anyhow!("No config file found."));
-            };
+            match Workspace::open(&root_dir)? {
+                // Without a config-file of its own, the workspace opens its first member
+                Some(workspace) if !has_config_file(&root_dir) => {
+                    AppConfiguration::for_member(args, &workspace.members()[0])
+                }
+                _ => AppConfiguration::from_root_directory(
+                    args,
+                    &root_dir,
+                    path::Path::new(""),
+                    args.get_target(),
+                ),
+            }
+        }
+    }
+    /// Reads the config-file of the workspace-member, with its source-directory
+    /// relative to the root-directory of the member
+    ///
+    /// The `[[cart]]` of the member is built, or else the one selected by `--target`
+    pub fn for_member(
+        args: &AppArgs,
+        member: &WorkspaceMember,
+    ) -> anyhow::Result<AppConfiguration> {
+        if args.safe && !is_within(&member.root_dir, &args.get_root_directory()?) {
+            return Err(anyhow!(
+                "{}: {} is outside the workspace, refused by --safe",
+                member.name,
+                paths::display(&member.root_dir)
+            ));
+        }
+        let target = member.target.as_deref().or(args.get_target());
+        AppConfiguration::from_root_directory(args, &member.root_dir, &member.root_dir, target)
+            .map_err(|e| anyhow!("{}: {e}", member.name))
+    }
+    /// Reads the config-file in the root-directory,
+    /// with a relative source-directory joined onto `src_base`
+    ///
+    /// The `src_dir`, `cart` and `include_paths` are read from the named `[[cart]]`,
+    /// or the first one without a name, falling back to the top-level ones
+    fn from_root_directory(
+        args: &AppArgs,
+        root_dir: &path::Path,
+        src_base: &path::Path,
+        target_name: Option<&str>,
+    ) -> anyhow::Result<AppConfiguration> {
+        let config_file = AppConfigFile::open_in(root_dir)?;
+        AppConfiguration::from_config_file(args, config_file, root_dir, src_base, target_name)
+    }
+    /// Reads the configuration of the project in the root-directory,
+    /// with a relative source-directory joined onto `src_base`
+    fn from_config_file(
+        args: &AppArgs,
+        config_file: AppConfigFile,
+        root_dir: &path::Path,
+        src_base: &path::Path,
+        target_name: Option<&str>,
+    ) -> anyhow::Result<AppConfiguration> {
+        let mut targets = cart_targets(&config_file)?.into_iter();
+        let target = match target_name {
+            Some(name) => Some(
+                targets
+                    .find(|target| target.name == name)
+                    .ok_or_else(|| anyhow!("Unknown cart-target {name:?} in config-file"))?,
+            ),
+            None => targets.next(),
+        };
 
-            let src_dir = match config_file.values.get_string("src_dir") {
-                Ok(val) => path::PathBuf::from(val),
-                Err(e) => {
-                    if let Some(src_dir_arg) = args.get_src_dir() {
-                        src_dir_arg.to_path_buf()
-                    } else {
-                        return Err(anyhow!(
-                            "Failed to find source-dir configuration value in args, or in config-file due to {e}"
-                        ));
-                    }
+        let src_dir = match target
+            .as_ref()
+            .and_then(|target| target.src_dir.clone())
+            .map_or_else(|| config_file.values.get_string("src_dir"), Ok)
+        {
+            Ok(val) => src_base.join(paths::from_config(&val)),
+            Err(e) => {
+                if let Some(src_dir_arg) = args.get_src_dir() {
+                    src_dir_arg.to_path_buf()
+                } else {
+                    return Err(anyhow!(
+                        "Failed to find source-dir configuration value in args, or in config-file due to {e}"
+                    ));
                 }
-            };
+            }
+        };
 
-            let cart = match config_file.values.get_string("cart") {
-                Ok(val) => val,
-                Err(e) => {
-                    if let Some(cart_arg) = args.get_cart() {
-                        cart_arg.into()
-                    } else {
-                        return Err(anyhow!(
-                            "Failed to find source-dir configuration value in args, or in config-file due to {e}"
-                        ));
-                    }
+        let cart = match target
+            .as_ref()
+            .and_then(|target| target.cart.clone())
+            .map_or_else(|| config_file.values.get_string("cart"), Ok)
+        {
+            Ok(val) => paths::from_config(&val).to_string_lossy().into_owned(),
+            Err(e) => {
+                if let Some(cart_arg) = args.get_cart() {
+                    cart_arg.into()
+                } else {
+                    return Err(anyhow!(
+                        "Failed to find source-dir configuration value in args, or in config-file due to {e}"
+                    ));
                 }
-            };
+            }
+        };
 
-            let watch = config_file.values.get_bool("watch").unwrap_or(args.watch);
-            let open_pico = config_file
-                .values
-                .get_bool("open_pico")
-                .unwrap_or(args.open_pico);
+        let watch = config_file.values.get_bool("watch").unwrap_or(args.watch);
+        let open_pico = config_file
+            .values
+            .get_bool("open_pico")
+            .unwrap_or(args.open_pico);
 
-            let executable = config_file
-                .values
-                .get_string("executable")
-                .map(path::PathBuf::from)
-                .ok()
-                .or_else(|| args.get_executable().map(path::Path::to_path_buf));
+        let executable = config_file
+            .values
+            .get_string("executable")
+            .map(|val| paths::from_config(&val))
+            .ok()
+            .or_else(|| args.get_executable().map(path::Path::to_path_buf));
+        if open_pico && executable.is_none() {
+            return Err(anyhow!(
+                "open_pico is set, but no pico-8 executable is configured"
+            ));
+        }
 
-            Ok(AppConfiguration {
-                src_dir,
-                cart,
-                watch,
-                open_pico,
-                executable,
+        let theme = match config_file.values.get_string("theme") {
+            Ok(name) => Theme::from_name(name.as_str())
+                .ok_or_else(|| anyhow!("Unknown theme {name:?} in config-file"))?,
+            Err(_) => args.get_theme().unwrap_or_default(),
+        };
+
+        let todo_markers = match config_file.values.get_array("todo_markers") {
+            Ok(values) => values
+                .into_iter()
+                .map(config::Value::into_string)
+                .collect::<Result<_, _>>()?,
+            Err(_) => default_todo_markers(),
+        };
+
+        let profile = match config_file.values.get_string("profile") {
+            Ok(name) => BuildProfile::from_name(name.as_str())
+                .ok_or_else(|| anyhow!("Unknown profile {name:?} in config-file"))?,
+            Err(_) => args.get_build_profile(),
+        };
+
+        let debug_calls = match config_file.values.get_array("debug_calls") {
+            Ok(values) => values
+                .into_iter()
+                .map(config::Value::into_string)
+                .collect::<Result<_, _>>()?,
+            Err(_) => default_debug_calls(),
+        };
+
+        let tab_order = match config_file.values.get_array("tabs.order") {
+            Ok(values) => values
+                .into_iter()
+                .map(config::Value::into_string)
+                .collect::<Result<Vec<_>, _>>()
+                .map(TabOrder::new)?,
+            Err(_) => tab_order_from_src_dir(&src_dir)?,
+        };
+        let tab_order = match config_file.values.get_string("tabs.source_order") {
+            Ok(name) => tab_order.with_source_order(
+                SourceOrder::from_name(name.as_str())
+                    .ok_or_else(|| anyhow!("Unknown source-order {name:?} in config-file"))?,
+            ),
+            Err(_) => tab_order,
+        };
+
+        let gfx_rows = match config_file.values.get_string("gfx_rows") {
+            Ok(name) => GfxRows::from_name(name.as_str())
+                .ok_or_else(|| anyhow!("Unknown gfx_rows {name:?} in config-file"))?,
+            Err(_) => GfxRows::default(),
+        };
+
+        let include_paths = match target
+            .as_ref()
+            .and_then(|target| target.include_paths.clone())
+        {
+            Some(values) => Some(values),
+            None => config_file.values.get_array("include_paths").ok(),
+        };
+        let include_resolver = match include_paths {
+            Some(values) => values
+                .into_iter()
+                .map(|value| value.into_string().map(|val| paths::from_config(&val)))
+                .collect::<Result<Vec<_>, _>>()
+                .map(IncludeResolver::new)?,
+            None => IncludeResolver::default(),
+        };
+
+        let exclude = match config_file.values.get_array("exclude") {
+            Ok(values) => values
+                .into_iter()
+                .map(config::Value::into_string)
+                .collect::<Result<Vec<_>, _>>()
+                .map(SourceExclude::new)?,
+            Err(_) => SourceExclude::default(),
+        }
+        .with_ignore_file(&src_dir)?;
+
+        let merge = match config_file.values.get_string("merge") {
+            Ok(name) => MergeStrategy::from_name(name.as_str())
+                .ok_or_else(|| anyhow!("Unknown merge-strategy {name:?} in config-file"))?,
+            Err(_) => MergeStrategy::default(),
+        };
+
+        let tab_overflow = match config_file.values.get_string("tab_overflow") {
+            Ok(name) => TabOverflow::from_name(name.as_str())
+                .ok_or_else(|| anyhow!("Unknown tab-overflow {name:?} in config-file"))?,
+            Err(_) => TabOverflow::default(),
+        };
+
+        let keys = match config_file.values.get_table("keys") {
+            Ok(table) => KeyBindings::from_table(table),
+            Err(_) => KeyBindings::default(),
+        };
+
+        let log_file = match config_file.values.get_table("log_file") {
+            Ok(table) => Some(LogFileSettings::from_table(table)?),
+            Err(_) => None,
+        };
+
+        let embed_sprites = match config_file.values.get_table("embed_sprites") {
+            Ok(table) => Some(sprite_embedding_from_table(table)?),
+            Err(_) => None,
+        };
+
+        let assets = match config_file.values.get_table("assets") {
+            Ok(table) => Some(asset_import_from_table(table, src_base)?),
+            Err(_) => None,
+        };
+
+        let label_fallback = match config_file.values.get_table("label") {
+            Ok(table) => label_fallback_from_table(table)?,
+            Err(_) => LabelFallback::default(),
+        };
+
+        let target_language = target.as_ref().and_then(|target| target.language.clone());
+        let localization = match config_file.values.get_table("strings") {
+            Ok(table) => localization_from_table(table, src_base, target_language)?,
+            Err(_) => target_language.map(|language| Localization {
+                strings_dir: src_base.join(strings::DEFAULT_STRINGS_DIR),
+                language,
+            }),
+        };
+
+        let minify = match config_file.values.get_table("minify") {
+            Ok(table) => Some(minification_from_table(table)?),
+            Err(_) => None,
+        };
+
+        let constants = match config_file.values.get_table("constants") {
+            Ok(table) => constants_from_table(table)?,
+            Err(_) => Constants::new(),
+        };
+
+        let cart_version = match config_file.values.get_int("cart_version") {
+            Ok(version) => Some(
+                usize::try_from(version)
+                    .map_err(|_| anyhow!("cart_version must not be negative, got {version}"))?,
+            ),
+            Err(_) => None,
+        };
+
+        let remove_dead_code = config_file
+            .values
+            .get_bool("remove_dead_code")
+            .unwrap_or(false);
+
+        let cart_backups = match config_file.values.get_int("cart_backups") {
+            Ok(count) => usize::try_from(count)
+                .map_err(|_| anyhow!("cart_backups must not be negative, got {count}"))?,
+            Err(_) => 0,
+        };
+
+        let (pico_folder_overrides, pico_config_txt) = match config_file.values.get_table("pico8") {
+            Ok(table) => pico_folders_from_table(table)?,
+            Err(_) => (PicoFolders::default(), None),
+        };
+
+        Ok(AppConfiguration {
+            src_dir,
+            cart,
+            watch,
+            open_pico,
+            executable,
+            theme,
+            todo_markers,
+            profile,
+            debug_calls,
+            tab_order,
+            gfx_rows,
+            include_resolver,
+            exclude,
+            merge,
+            tab_overflow,
+            keys,
+            log_file,
+            embed_sprites,
+            assets,
+            label_fallback,
+            localization,
+            minify,
+            constants,
+            cart_version,
+            remove_dead_code,
+            cart_backups,
+            pico_folder_overrides,
+            pico_config_txt,
+            target: target.map(|target| target.name),
+            safe: false,
+            root_dir: root_dir.to_path_buf(),
+        })
+        .and_then(|cfg| {
+            if args.safe {
+                cfg.restrict_to_root(root_dir)
+            } else {
+                Ok(cfg)
+            }
+        })
+    }
+    /// Restricts the configuration of a project which is not trusted, for `--safe`
+    ///
+    /// Paths outside the root-directory are refused, and the features running external
+    /// programs are dropped, with a warning naming each one
+    fn restrict_to_root(self, root_dir: &path::Path) -> anyhow::Result<AppConfiguration> {
+        if !is_within(&self.src_dir, root_dir) {
+            return Err(anyhow!(
+                "src_dir {} is outside the project-directory, refused by --safe",
+                self.display_path(&self.src_dir)
+            ));
+        }
+        let cart_path = self.cart_path();
+        if !is_within(&cart_path, root_dir) {
+            return Err(anyhow!(
+                "cart {} is outside the project-directory, refused by --safe",
+                self.display_path(&cart_path)
+            ));
+        }
+        if let Some(executable) = &self.executable {
+            tracing::warn!(
+                "Ignoring the executable {}, --safe does not run external programs",
+                paths::display(executable)
+            );
+        }
+        if self.open_pico {
+            tracing::warn!("Ignoring open_pico, --safe does not run external programs");
+        }
+        let assets = self.assets.clone().filter(|assets| {
+            let is_within_root = is_within(&assets.assets_dir, root_dir);
+            if !is_within_root {
+                tracing::warn!(
+                    "Ignoring the assets-directory {}, it is outside the project-directory",
+                    self.display_path(&assets.assets_dir)
+                );
+            }
+            is_within_root
+        });
+        let localization = self.localization.clone().filter(|localization| {
+            let is_within_root = is_within(&localization.strings_dir, root_dir);
+            if !is_within_root {
+                tracing::warn!(
+                    "Ignoring the strings-directory {}, it is outside the project-directory",
+                    self.display_path(&localization.strings_dir)
+                );
+            }
+            is_within_root
+        });
+        let include_paths: Vec<path::PathBuf> = self
+            .include_resolver
+            .include_paths()
+            .iter()
+            .filter(|include_path| {
+                let is_within_root = is_within(include_path, root_dir);
+                if !is_within_root {
+                    tracing::warn!(
+                        "Ignoring the include-path {}, it is outside the project-directory",
+                        self.display_path(include_path)
+                    );
+                }
+                is_within_root
             })
+            .cloned()
+            .collect();
+        Ok(AppConfiguration {
+            executable: None,
+            open_pico: false,
+            include_resolver: IncludeResolver::new(include_paths).with_root(root_dir),
+            assets,
+            localization,
+            safe: true,
+            ..self
+        })
+    }
+    /// Discovers the folders of PICO-8, see [`PicoFolders::discover`]
+    ///
+    /// The `config.txt` is not read with `--safe`, as it is outside the project-directory
+    pub fn pico_folders(&self) -> PicoFolders {
+        if self.safe {
+            self.pico_folder_overrides
+                .clone()
+                .or(PicoFolders::defaults())
+        } else {
+            PicoFolders::discover(
+                self.pico_folder_overrides.clone(),
+                self.pico_config_txt.as_deref(),
+            )
         }
     }
+    /// Shows the path relative to the project, with `/` separators
+    pub fn display_path<'a>(&'a self, path: &'a path::Path) -> paths::DisplayPath<'a> {
+        paths::relative_to(path, &self.root_dir)
+    }
     /// The output path (I think)
     pub fn cart_path(&self) -> path::PathBuf {
         let mut cart_path = self.src_dir.clone();
@@ -0,0 +1,244 @@
+use core::time::Duration;
+
+use std::time::Instant;
+
+use pico_8_cart_model::compress::CODE_REGION_SIZE;
+use pico_8_cart_model::embed::EmbedReport;
+use pico_8_cart_model::tokens::TOKEN_LIMIT;
+use ratatui::{
+    prelude::*,
+    widgets::{Block, Gauge, Paragraph},
+};
+
+use crate::log_panel::LogPanelStore;
+use crate::pico_runner::PicoState;
+use crate::text;
+use crate::todo_panel::TodoPanelStore;
+
+/// The outcome of a successful build
+#[derive(Clone, Copy, Debug)]
+pub struct BuildReport {
+    pub finished: Instant,
+    pub tab_count: usize,
+    pub token_count: usize,
+    /// The size of the compressed code, if it could be compressed
+    pub compressed_size: Option<usize>,
+    /// The sprites moved into the code, if configured
+    pub embedded_sprites: Option<EmbedReport>,
+    /// The size of the written cart, `0` until it is saved
+    pub bytes_written: usize,
+}
+
+/// How long the last successful build took
+#[derive(Clone, Copy, Debug)]
+pub struct BuildTiming {
+    /// From the start of the build, to the cart being written
+    pub build_time: Duration,
+    /// From the source-change triggering the build, to the cart being written
+    pub latency: Option<Duration>,
+}
+
+/// Holds the last build-attempt, and the last successful build
+#[derive(Debug, Default)]
+pub struct BuildStatusStore {
+    last_attempt: Option<Instant>,
+    last_build: Option<BuildReport>,
+    /// The source-change the next build is triggered by, if any
+    source_changed: Option<Instant>,
+    last_timing: Option<BuildTiming>,
+    /// Why the last attempt failed, if known
+    failure_reason: Option<&'static str>,
+}
+
+impl BuildStatusStore {
+    /// Keeps the earliest change, if the build has not started since the last one
+    pub fn source_changed(&mut self, changed: Instant) {
+        self.source_changed = Some(self.source_changed.map_or(changed, |c| c.min(changed)));
+    }
+    pub fn start(&mut self) {
+        self.last_attempt = Some(Instant::now());
+        self.failure_reason = None;
+    }
+    /// Records why the attempt failed, shown next to the build-status
+    pub fn fail(&mut self, failure_reason: &'static str) {
+        self.failure_reason = Some(failure_reason);
+    }
+    /// Records the build, once the cart is written
+    pub fn finish(&mut self, build_report: BuildReport) {
+        let written = Instant::now();
+        self.last_timing = self.last_attempt.map(|last_attempt| BuildTiming {
+            build_time: written - last_attempt,
+            latency: self.source_changed.take().map(|changed| written - changed),
+        });
+        if let Some(BuildTiming {
+            latency: Some(latency),
+            ..
+        }) = self.last_timing
+        {
+            tracing::info!("Save-to-cart-written in {}ms", latency.as_millis());
+        }
+        self.last_build = Some(build_report);
+    }
+    pub fn last_timing(&self) -> Option<&BuildTiming> {
+        self.last_timing.as_ref()
+    }
+    pub fn last_build(&self) -> Option<&BuildReport> {
+        self.last_build.as_ref()
+    }
+    /// Whether the last attempt did not finish, i.e. it started after the last build
+    pub fn failed(&self) -> bool {
+        match (self.last_attempt, &self.last_build) {
+            (Some(last_attempt), Some(last_build)) => last_attempt > last_build.finished,
+            (Some(_), None) => true,
+            (None, _) => false,
+        }
+    }
+}
+
+/// The main-screen summary, querying the other stores
+pub struct DashboardWidget<'a> {
+    build_status: &'a BuildStatusStore,
+    log_panel_store: &'a LogPanelStore,
+    todo_panel_store: &'a TodoPanelStore,
+    watch: bool,
+    /// `None` if no pico-8 executable is configured
+    pico_state: Option<PicoState>,
+    /// The name of the open workspace-member, if any
+    project: Option<&'a str>,
+}
+
+impl<'a> DashboardWidget<'a> {
+    pub fn new(
+        build_status: &'a BuildStatusStore,
+        log_panel_store: &'a LogPanelStore,
+        todo_panel_store: &'a TodoPanelStore,
+        watch: bool,
+    ) -> DashboardWidget<'a> {
+        DashboardWidget {
+            build_status,
+            log_panel_store,
+            todo_panel_store,
+            watch,
+            pico_state: None,
+            project: None,
+        }
+    }
+    pub fn with_pico_state(self, pico_state: Option<PicoState>) -> DashboardWidget<'a> {
+        DashboardWidget { pico_state, ..self }
+    }
+    pub fn with_project(self, project: Option<&'a str>) -> DashboardWidget<'a> {
+        DashboardWidget { project, ..self }
+    }
+    fn build_line(&self) -> Line<'static> {
+        let palette = self.log_panel_store.palette();
+        let mut build = match self.build_status.last_build() {
+            Some(BuildReport {
+                finished,
+                tab_count,
+                ..
+            }) => format!(
+                "last built {}s ago, {tab_count} tabs",
+                finished.elapsed().as_secs()
+            ),
+            None => "not built yet".to_string(),
+        };
+        if let Some(BuildTiming {
+            build_time,
+            latency,
+        }) = self.build_status.last_timing()
+        {
+            build.push_str(&format!(", in {}ms", build_time.as_millis()));
+            if let Some(latency) = latency {
+                build.push_str(&format!(" ({}ms since save)", latency.as_millis()));
+            }
+        }
+        if self.build_status.failed() {
+            let failed = match self.build_status.failure_reason {
+                Some(failure_reason) => format!("failed: {failure_reason}"),
+                None => "failed".to_string(),
+            };
+            Line::from_iter([
+                Span::styled(failed, palette.error),
+                Span::raw(format!(", {build}")),
+            ])
+        } else {
+            Line::raw(build)
+        }
+    }
+}
+
+/// A gauge of the usage against the limit, full when over it
+fn limit_gauge(label: &str, usage: Option<usize>, limit: usize) -> Gauge<'static> {
+    let usage = usage.unwrap_or_default();
+    Gauge::default()
+        .ratio((usage as f64 / limit as f64).min(1.0))
+        .label(format!("{label} {usage}/{limit}"))
+}
+
+impl Widget for DashboardWidget<'_> {
+    fn render(self, area: Rect, buf: &mut Buffer)
+    where
+        Self: Sized,
+    {
+        let block = Block::bordered().title(match self.project {
+            Some(project) => format!("dashboard: {project}"),
+            None => "dashboard".to_string(),
+        });
+        let inner = block.inner(area);
+        block.render(area, buf);
+
+        let [
+            build_area,
+            token_area,
+            size_area,
+            status_area,
+            warnings_area,
+        ] = Layout::vertical([
+            Constraint::Length(1),
+            Constraint::Length(1),
+            Constraint::Length(1),
+            Constraint::Length(1),
+            Constraint::Fill(1),
+        ])
+        .areas(inner);
+
+        self.build_line().render(build_area, buf);
+        let last_build = self.build_status.last_build();
+        limit_gauge(
+            "tokens",
+            last_build.map(|build| build.token_count),
+            TOKEN_LIMIT,
+        )
+        .render(token_area, buf);
+        limit_gauge(
+            "compressed",
+            last_build.and_then(|build| build.compressed_size),
+            CODE_REGION_SIZE,
+        )
+        .render(size_area, buf);
+        let pico_state = match self.pico_state {
+            None => "not configured".to_string(),
+            Some(PicoState::NotLaunched) => "not launched".to_string(),
+            Some(PicoState::Running { pid }) => format!("running (pid {pid})"),
+            Some(PicoState::Exited(exit_status)) => format!("exited ({exit_status})"),
+        };
+        Line::raw(format!(
+            "todos: {}  watch: {}  pico-8: {pico_state}",
+            self.todo_panel_store.entries().len(),
+            if self.watch { "on" } else { "off" }
+        ))
+        .render(status_area, buf);
+
+        let palette = self.log_panel_store.palette();
+        let warnings = core::iter::once(Line::raw(format!(
+            "warnings: {}",
+            self.log_panel_store.warning_count()
+        )))
+        .chain(self.log_panel_store.recent_warnings().map(|warning| {
+            let warning = warning.to_string();
+            let warning = text::truncate_to_width(&warning, warnings_area.width.into());
+            Line::styled(warning.into_owned(), palette.warn)
+        }));
+        Paragraph::new(Text::from_iter(warnings)).render(warnings_area, buf);
+    }
+}
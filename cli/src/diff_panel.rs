@@ -0,0 +1,68 @@
+use pico_8_cart_model::diff::CartDiff;
+use ratatui::{
+    prelude::*,
+    widgets::{Block, List},
+};
+
+use crate::diff_view::DiffView;
+use crate::theme::Palette;
+
+/// Holds what the last rebuild changed in the cart, rendered into owned lines
+/// for each view as the diff borrows from the carts
+#[derive(Debug, Default)]
+pub struct DiffPanelStore {
+    views: Vec<(DiffView, Vec<Line<'static>>)>,
+    view: DiffView,
+    title: String,
+}
+
+impl DiffPanelStore {
+    const VIEWS: [DiffView; 3] = [DiffView::Unified, DiffView::SideBySide, DiffView::Image];
+
+    pub fn record(&mut self, diff: &CartDiff<'_>, palette: Palette) {
+        self.title = format!(
+            "last rebuild: {} tabs, {} sections changed",
+            diff.tabs.len(),
+            diff.sections.len()
+        );
+        self.views = Self::VIEWS
+            .into_iter()
+            .map(|view| (view, view.renderer().render(diff, &palette)))
+            .collect();
+    }
+    /// Whether a rebuild has been recorded
+    pub fn is_recorded(&self) -> bool {
+        !self.title.is_empty()
+    }
+    pub fn cycle_view(&mut self) {
+        self.view = self.view.next();
+    }
+    fn lines(&self) -> &[Line<'static>] {
+        self.views
+            .iter()
+            .find(|(view, _)| *view == self.view)
+            .map_or(&[], |(_, lines)| lines.as_slice())
+    }
+}
+
+pub struct DiffPanelWidget<'a> {
+    store: &'a DiffPanelStore,
+}
+
+impl<'a> From<&'a DiffPanelStore> for DiffPanelWidget<'a> {
+    fn from(store: &'a DiffPanelStore) -> Self {
+        DiffPanelWidget { store }
+    }
+}
+
+impl Widget for DiffPanelWidget<'_> {
+    fn render(self, area: Rect, buf: &mut Buffer)
+    where
+        Self: Sized,
+    {
+        let title = format!("{} ({})", self.store.title, self.store.view.name());
+        let list =
+            List::new(self.store.lines().iter().cloned()).block(Block::bordered().title(title));
+        Widget::render(list, area, buf);
+    }
+}
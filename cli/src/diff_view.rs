@@ -0,0 +1,211 @@
+//! The views of a cart-diff, shared by the `diff` command and the diff-panel
+
+use pico_8_cart_model::diff::{CartDiff, DiffLine, ImageDiff, TabDiff, TabStatus};
+use ratatui::prelude::*;
+use unicode_width::UnicodeWidthStr;
+
+use crate::text;
+use crate::theme::Palette;
+
+/// The pixels summarized by a single cell of the image-view, a sprite each
+const IMAGE_BLOCK_SIZE: usize = 8;
+
+/// Renders a diff into owned lines, in the style of one of the [`DiffView`]s
+pub trait DiffRenderer {
+    fn render(&self, diff: &CartDiff<'_>, palette: &Palette) -> Vec<Line<'static>>;
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum DiffView {
+    /// The removed and added lines of each tab, one after the other
+    #[default]
+    Unified,
+    /// The old and new lines of each tab, next to each other
+    SideBySide,
+    /// The changed sprites of the gfx- and label-sections
+    Image,
+}
+
+impl DiffView {
+    /// Cycles through the views, for the diff-panel keybinding
+    pub const fn next(self) -> DiffView {
+        match self {
+            DiffView::Unified => DiffView::SideBySide,
+            DiffView::SideBySide => DiffView::Image,
+            DiffView::Image => DiffView::Unified,
+        }
+    }
+    pub const fn name(self) -> &'static str {
+        match self {
+            DiffView::Unified => "unified",
+            DiffView::SideBySide => "side-by-side",
+            DiffView::Image => "image",
+        }
+    }
+    pub fn renderer(self) -> &'static dyn DiffRenderer {
+        match self {
+            DiffView::Unified => &UnifiedRenderer,
+            DiffView::SideBySide => &SideBySideRenderer,
+            DiffView::Image => &ImageRenderer,
+        }
+    }
+}
+
+fn tab_header(tab_index: usize, status: TabStatus) -> Line<'static> {
+    let status = match status {
+        TabStatus::Added => "added",
+        TabStatus::Removed => "removed",
+        TabStatus::Changed => "changed",
+    };
+    Line::raw(format!("tab {tab_index} {status}")).bold()
+}
+
+fn section_lines(diff: &CartDiff<'_>) -> impl Iterator<Item = Line<'static>> {
+    diff.sections
+        .iter()
+        .map(|section| Line::raw(format!("{} changed", <&'static str>::from(section))))
+}
+
+pub struct UnifiedRenderer;
+
+impl DiffRenderer for UnifiedRenderer {
+    fn render(&self, diff: &CartDiff<'_>, palette: &Palette) -> Vec<Line<'static>> {
+        let mut lines: Vec<Line<'static>> = section_lines(diff).collect();
+        for TabDiff {
+            tab_index,
+            status,
+            lines: tab_lines,
+        } in &diff.tabs
+        {
+            lines.push(tab_header(*tab_index, *status));
+            lines.extend(tab_lines.iter().filter_map(|line| match line {
+                DiffLine::Unchanged(_) => None,
+                DiffLine::Added(line) => Some(Line::styled(
+                    format!("+{}", String::from_utf8_lossy(line)),
+                    palette.info,
+                )),
+                DiffLine::Removed(line) => Some(Line::styled(
+                    format!("-{}", String::from_utf8_lossy(line)),
+                    palette.error,
+                )),
+            }));
+        }
+        lines
+    }
+}
+
+pub struct SideBySideRenderer;
+
+impl SideBySideRenderer {
+    const SEPARATOR: &'static str = " │ ";
+    /// The old column is as wide as its widest line, up to this width
+    const MAX_COLUMN_WIDTH: usize = 60;
+
+    /// Pairs the removed lines with the added lines following them
+    fn rows<'a>(tab_lines: &[DiffLine<'a>]) -> Vec<(Option<DiffLine<'a>>, Option<DiffLine<'a>>)> {
+        let mut rows = Vec::new();
+        let (mut removed, mut added) = (Vec::new(), Vec::new());
+        let flush = |rows: &mut Vec<_>, removed: &mut Vec<_>, added: &mut Vec<_>| {
+            let count = removed.len().max(added.len());
+            let mut removed = core::mem::take(removed).into_iter();
+            let mut added = core::mem::take(added).into_iter();
+            rows.extend((0..count).map(|_| (removed.next(), added.next())));
+        };
+        for line in tab_lines {
+            match line {
+                DiffLine::Removed(_) => removed.push(*line),
+                DiffLine::Added(_) => added.push(*line),
+                DiffLine::Unchanged(_) => {
+                    flush(&mut rows, &mut removed, &mut added);
+                    rows.push((Some(*line), Some(*line)));
+                }
+            }
+        }
+        flush(&mut rows, &mut removed, &mut added);
+        rows
+    }
+}
+
+impl DiffRenderer for SideBySideRenderer {
+    fn render(&self, diff: &CartDiff<'_>, palette: &Palette) -> Vec<Line<'static>> {
+        let column_width = diff
+            .tabs
+            .iter()
+            .flat_map(|tab| &tab.lines)
+            .filter_map(|line| match line {
+                DiffLine::Unchanged(line) | DiffLine::Removed(line) => {
+                    Some(String::from_utf8_lossy(line).width())
+                }
+                DiffLine::Added(_) => None,
+            })
+            .max()
+            .unwrap_or_default()
+            .min(Self::MAX_COLUMN_WIDTH);
+        // Marked like the unified view, for when the colors are lost, e.g. in a pipe
+        let cell = |line: Option<DiffLine<'_>>| {
+            let (marker, line, style) = match line {
+                Some(DiffLine::Unchanged(line)) => (' ', line, palette.muted),
+                Some(DiffLine::Removed(line)) => ('-', line, palette.error),
+                Some(DiffLine::Added(line)) => ('+', line, palette.info),
+                None => (' ', &[][..], Style::default()),
+            };
+            let text = format!("{marker}{}", String::from_utf8_lossy(line));
+            Span::styled(text::fit_to_width(&text, column_width + 1), style)
+        };
+        let mut lines: Vec<Line<'static>> = section_lines(diff).collect();
+        for TabDiff {
+            tab_index,
+            status,
+            lines: tab_lines,
+        } in &diff.tabs
+        {
+            lines.push(tab_header(*tab_index, *status));
+            lines.extend(
+                SideBySideRenderer::rows(tab_lines)
+                    .into_iter()
+                    .map(|(old, new)| {
+                        Line::from_iter([cell(old), Span::raw(Self::SEPARATOR), cell(new)])
+                    }),
+            );
+        }
+        lines
+    }
+}
+
+pub struct ImageRenderer;
+
+impl DiffRenderer for ImageRenderer {
+    fn render(&self, diff: &CartDiff<'_>, palette: &Palette) -> Vec<Line<'static>> {
+        if diff.images.is_empty() {
+            return vec![Line::raw("no image-sections changed")];
+        }
+        let mut lines = Vec::new();
+        for image in &diff.images {
+            let ImageDiff {
+                section,
+                width,
+                height,
+                ..
+            } = image;
+            lines.push(
+                Line::raw(format!(
+                    "{}: {} pixels changed",
+                    <&'static str>::from(section),
+                    image.changed_count()
+                ))
+                .bold(),
+            );
+            // Two columns per block, as terminal-cells are about twice as tall as wide
+            lines.extend((0..height / IMAGE_BLOCK_SIZE).map(|block_y| {
+                Line::from_iter((0..width / IMAGE_BLOCK_SIZE).map(|block_x| {
+                    if image.is_block_changed(block_x, block_y, IMAGE_BLOCK_SIZE) {
+                        Span::styled("██", palette.error)
+                    } else {
+                        Span::styled("··", palette.muted)
+                    }
+                }))
+            }));
+        }
+        lines
+    }
+}
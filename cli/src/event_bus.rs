@@ -0,0 +1,214 @@
+//! Polling the event-listeners of the ui, each on its own [`ListenerSchedule`]
+//!
+//! A listener waiting for an event, like the keyboard, is only allowed to wait
+//! until the next listener is due, so no listener stalls the others
+
+use core::time::Duration;
+
+use std::sync::mpsc;
+use std::thread;
+use std::time::Instant;
+
+use crate::Action;
+
+/// How a listener checks for the arrival of an event
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Polling {
+    /// Returns at once, whether an event arrived or not
+    NonBlocking,
+    /// Waits up to the timeout for an event, cut short once another listener is due
+    Blocking { timeout: Duration },
+}
+
+/// When, and in which order, the [`EventBus`] polls a listener
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ListenerSchedule {
+    /// Listeners due at the same time are polled highest priority first
+    pub priority: u8,
+    /// The time between two polls of the listener
+    pub poll_interval: Duration,
+    pub polling: Polling,
+}
+
+impl Default for ListenerSchedule {
+    fn default() -> Self {
+        ListenerSchedule {
+            priority: 0,
+            poll_interval: Duration::from_millis(10),
+            polling: Polling::NonBlocking,
+        }
+    }
+}
+
+/// Listens for some type of event and maps into an [`Action`]
+pub trait EventListener {
+    /// Checks for the arrival of an event and tries to turn it
+    /// into an action, waiting at most the timeout for it
+    ///
+    /// The timeout is zero unless the listener is [`Polling::Blocking`]
+    fn next_action(&self, timeout: Duration) -> Option<Action>;
+    /// The schedule the listener is registered with, unless registered with another
+    fn schedule(&self) -> ListenerSchedule {
+        ListenerSchedule::default()
+    }
+}
+
+struct ScheduledListener {
+    listener: Box<dyn EventListener + Send>,
+    schedule: ListenerSchedule,
+    next_poll: Instant,
+}
+
+pub struct EventBus {
+    /// Ordered by descending priority
+    listeners: Vec<ScheduledListener>,
+    action_tx: mpsc::Sender<Action>,
+}
+
+impl EventBus {
+    pub fn new(action_tx: mpsc::Sender<Action>) -> EventBus {
+        EventBus {
+            action_tx,
+            listeners: vec![],
+        }
+    }
+    pub fn register_listener<T>(&mut self, listener: T)
+    where
+        T: EventListener + Send + 'static,
+    {
+        let schedule = listener.schedule();
+        self.register_listener_with(listener, schedule);
+    }
+    /// Registers the listener with another schedule than its own
+    pub fn register_listener_with<T>(&mut self, listener: T, schedule: ListenerSchedule)
+    where
+        T: EventListener + Send + 'static,
+    {
+        // After the listeners of the same priority, so those are polled in order of registration
+        let index = self
+            .listeners
+            .partition_point(|scheduled| scheduled.schedule.priority >= schedule.priority);
+        self.listeners.insert(
+            index,
+            ScheduledListener {
+                listener: Box::new(listener),
+                schedule,
+                next_poll: Instant::now(),
+            },
+        );
+    }
+    /// The earliest time a listener other than the one at the index is due
+    fn next_due_except(&self, index: usize) -> Option<Instant> {
+        self.listeners
+            .iter()
+            .enumerate()
+            .filter(|(other, _)| *other != index)
+            .map(|(_, scheduled)| scheduled.next_poll)
+            .min()
+    }
+    /// Polls the listeners which are due and sends their actions, then waits until
+    /// the next one is due if none of them waited for an event
+    pub fn update(&mut self) -> Result<(), mpsc::SendError<Action>> {
+        let mut waited = false;
+        for index in 0..self.listeners.len() {
+            let now = Instant::now();
+            if self.listeners[index].next_poll > now {
+                continue;
+            }
+            let timeout = match self.listeners[index].schedule.polling {
+                Polling::NonBlocking => Duration::ZERO,
+                Polling::Blocking { timeout } => {
+                    waited = true;
+                    self.next_due_except(index).map_or(timeout, |next_due| {
+                        timeout.min(next_due.saturating_duration_since(now))
+                    })
+                }
+            };
+            let scheduled = &mut self.listeners[index];
+            let action = scheduled.listener.next_action(timeout);
+            scheduled.next_poll = now + scheduled.schedule.poll_interval;
+            if let Some(action) = action {
+                self.action_tx.send(action)?;
+            }
+        }
+        if !waited
+            && let Some(next_due) = self
+                .listeners
+                .iter()
+                .map(|scheduled| scheduled.next_poll)
+                .min()
+        {
+            thread::sleep(next_due.saturating_duration_since(Instant::now()));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::{Arc, Mutex};
+
+    /// Emits the action on each poll, recording the timeout it was polled with
+    struct FakeListener {
+        action: fn() -> Action,
+        schedule: ListenerSchedule,
+        timeouts: Arc<Mutex<Vec<Duration>>>,
+    }
+
+    impl EventListener for FakeListener {
+        fn next_action(&self, timeout: Duration) -> Option<Action> {
+            self.timeouts.lock().unwrap().push(timeout);
+            Some((self.action)())
+        }
+        fn schedule(&self) -> ListenerSchedule {
+            self.schedule
+        }
+    }
+
+    #[test]
+    fn polls_by_priority_without_stalling() {
+        let (action_tx, action_rx) = mpsc::channel();
+        let mut event_bus = EventBus::new(action_tx);
+        let blocking_timeouts = Arc::new(Mutex::new(Vec::new()));
+        event_bus.register_listener(FakeListener {
+            action: || Action::Quit,
+            schedule: ListenerSchedule {
+                priority: 0,
+                poll_interval: Duration::ZERO,
+                polling: Polling::Blocking {
+                    timeout: Duration::from_secs(1),
+                },
+            },
+            timeouts: blocking_timeouts.clone(),
+        });
+        let slow_timeouts = Arc::new(Mutex::new(Vec::new()));
+        event_bus.register_listener(FakeListener {
+            action: || Action::ClearLogPanel,
+            schedule: ListenerSchedule {
+                priority: 1,
+                poll_interval: Duration::from_millis(20),
+                polling: Polling::NonBlocking,
+            },
+            timeouts: slow_timeouts.clone(),
+        });
+
+        event_bus.update().unwrap();
+        event_bus.update().unwrap();
+        let actions: Vec<Action> = action_rx.try_iter().collect();
+        assert!(matches!(
+            actions.as_slice(),
+            [Action::ClearLogPanel, Action::Quit, Action::Quit]
+        ));
+        // Not yet due on the second update
+        assert_eq!(*slow_timeouts.lock().unwrap(), [Duration::ZERO]);
+        let blocking_timeouts = blocking_timeouts.lock().unwrap();
+        assert_eq!(blocking_timeouts.len(), 2);
+        assert!(
+            blocking_timeouts
+                .iter()
+                .all(|timeout| *timeout <= Duration::from_millis(20))
+        );
+    }
+}
@@ -0,0 +1,95 @@
+//! The `[keys]` section of the config-file, rebinding the keys of the [`UserCommand`]s
+//!
+//! ```toml
+//! [keys]
+//! b = "compile"
+//! enter = "none"
+//! f5 = "restart-pico"
+//! ```
+//!
+//! The bindings are applied over the default ones, `none` unbinding the key
+
+use std::collections::HashMap;
+
+use crossterm::event::KeyCode;
+
+use crate::UserCommand;
+
+/// Parses a single character, e.g. `b` or `?`, or the name of a special key, e.g. `enter` or `f5`
+pub fn parse_key(name: &str) -> Option<KeyCode> {
+    let mut chars = name.chars();
+    if let (Some(c), None) = (chars.next(), chars.next()) {
+        return Some(KeyCode::Char(c));
+    }
+    let key_code = match name.to_ascii_lowercase().as_str() {
+        "enter" | "return" => KeyCode::Enter,
+        "esc" | "escape" => KeyCode::Esc,
+        "space" => KeyCode::Char(' '),
+        "tab" => KeyCode::Tab,
+        "backspace" => KeyCode::Backspace,
+        "delete" => KeyCode::Delete,
+        "insert" => KeyCode::Insert,
+        "up" => KeyCode::Up,
+        "down" => KeyCode::Down,
+        "left" => KeyCode::Left,
+        "right" => KeyCode::Right,
+        "home" => KeyCode::Home,
+        "end" => KeyCode::End,
+        "pageup" => KeyCode::PageUp,
+        "pagedown" => KeyCode::PageDown,
+        name => {
+            let number = name.strip_prefix('f')?.parse().ok()?;
+            (1..=12).contains(&number).then_some(KeyCode::F(number))?
+        }
+    };
+    Some(key_code)
+}
+
+/// The keys rebound by the config-file, `None` for the unbound ones
+#[derive(Clone, Debug, Default)]
+pub struct KeyBindings {
+    bindings: Vec<(KeyCode, Option<UserCommand>)>,
+}
+
+impl KeyBindings {
+    /// Parses the section, skipping invalid bindings with a warning
+    ///
+    /// A typo in a binding should not keep the project from opening, so it is
+    /// reported in the log-panel instead
+    pub fn from_table(table: config::Map<String, config::Value>) -> KeyBindings {
+        let mut bindings = Vec::new();
+        for (key_name, value) in table {
+            let Some(key_code) = parse_key(&key_name) else {
+                tracing::warn!("Unknown key {key_name:?} in [keys], skipping it");
+                continue;
+            };
+            let command_name = match value.into_string() {
+                Ok(command_name) => command_name,
+                Err(e) => {
+                    tracing::warn!("Invalid binding of {key_name:?} in [keys], skipping it: {e}");
+                    continue;
+                }
+            };
+            if command_name == "none" {
+                bindings.push((key_code, None));
+                continue;
+            }
+            match UserCommand::from_name(&command_name) {
+                Some(user_command) => bindings.push((key_code, Some(user_command))),
+                None => tracing::warn!(
+                    "Unknown command {command_name:?} bound to {key_name:?} in [keys], skipping it"
+                ),
+            }
+        }
+        KeyBindings { bindings }
+    }
+    /// Rebinds the keys of the key-map
+    pub fn apply(&self, key_map: &mut HashMap<KeyCode, UserCommand>) {
+        for (key_code, user_command) in &self.bindings {
+            match user_command {
+                Some(user_command) => key_map.insert(*key_code, *user_command),
+                None => key_map.remove(key_code),
+            };
+        }
+    }
+}
@@ -0,0 +1,314 @@
+//! Persisting the log to a file in the project-directory, alongside the log-panel,
+//! for looking into a session after the TUI exits
+//!
+//! ```toml
+//! [log_file]
+//! format = "json"
+//! max_size = 1048576
+//! max_files = 3
+//! ```
+//!
+//! Without the `[log_file]` section nothing is written. Once the file grows past
+//! `max_size` bytes it is rotated to `pico-build.log.1`, and the previous ones shifted
+//! along, keeping `max_files` of them
+
+use core::fmt;
+
+use std::fs;
+use std::io::{self, Write};
+use std::path;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::anyhow;
+use pico_build_rs::paths;
+use tracing::Subscriber;
+use tracing::field::{Field, Visit};
+use tracing_subscriber::layer::{Context, Layer};
+use tracing_subscriber::{Registry, reload};
+
+/// The directory of the log-files, relative to the project-directory
+pub const LOG_DIR: &str = ".pico-build/logs";
+
+const LOG_FILE_NAME: &str = "pico-build.log";
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum LogFileFormat {
+    /// A line of `timestamp level target: message field=value`
+    #[default]
+    Plain,
+    /// A json-object per line, with the fields of the event as members
+    JsonLines,
+}
+
+impl LogFileFormat {
+    pub fn from_name(name: &str) -> Option<LogFileFormat> {
+        match name {
+            "plain" => Some(LogFileFormat::Plain),
+            "json" | "json-lines" => Some(LogFileFormat::JsonLines),
+            _ => None,
+        }
+    }
+}
+
+/// The `[log_file]` section of the config-file, see the [module-documentation](self)
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LogFileSettings {
+    pub format: LogFileFormat,
+    /// The size in bytes a log-file is rotated at
+    pub max_size: u64,
+    /// The rotated log-files kept, besides the one written to
+    pub max_files: usize,
+}
+
+impl Default for LogFileSettings {
+    fn default() -> Self {
+        LogFileSettings {
+            format: LogFileFormat::default(),
+            max_size: 1024 * 1024,
+            max_files: 3,
+        }
+    }
+}
+
+impl LogFileSettings {
+    pub fn from_table(
+        mut table: config::Map<String, config::Value>,
+    ) -> anyhow::Result<LogFileSettings> {
+        let mut settings = LogFileSettings::default();
+        if let Some(value) = table.remove("format") {
+            let name = value.into_string()?;
+            settings.format = LogFileFormat::from_name(&name)
+                .ok_or_else(|| anyhow!("Unknown log_file.format {name:?} in config-file"))?;
+        }
+        if let Some(value) = table.remove("max_size") {
+            settings.max_size = value.into_uint()?;
+        }
+        if let Some(value) = table.remove("max_files") {
+            settings.max_files = value.into_uint()?.try_into()?;
+        }
+        Ok(settings)
+    }
+}
+
+/// The log-file, rotated once it grows past the size of the settings
+#[derive(Debug)]
+struct RotatingFile {
+    dir: path::PathBuf,
+    file: fs::File,
+    size: u64,
+    settings: LogFileSettings,
+}
+
+impl RotatingFile {
+    fn open(dir: path::PathBuf, settings: LogFileSettings) -> io::Result<RotatingFile> {
+        fs::create_dir_all(&dir)?;
+        let file = fs::File::options()
+            .create(true)
+            .append(true)
+            .open(dir.join(LOG_FILE_NAME))?;
+        let size = file.metadata()?.len();
+        Ok(RotatingFile {
+            dir,
+            file,
+            size,
+            settings,
+        })
+    }
+    fn rotated_path(&self, index: usize) -> path::PathBuf {
+        self.dir.join(format!("{LOG_FILE_NAME}.{index}"))
+    }
+    /// Shifts each log-file along, dropping the oldest, and starts an empty one
+    fn rotate(&mut self) -> io::Result<()> {
+        let max_files = self.settings.max_files;
+        if max_files == 0 {
+            self.file.set_len(0)?;
+        } else {
+            // Not existing when fewer files were rotated yet
+            let _ = fs::remove_file(self.rotated_path(max_files));
+            for index in (1..max_files).rev() {
+                let _ = fs::rename(self.rotated_path(index), self.rotated_path(index + 1));
+            }
+            fs::rename(self.dir.join(LOG_FILE_NAME), self.rotated_path(1))?;
+            self.file = fs::File::options()
+                .create(true)
+                .append(true)
+                .open(self.dir.join(LOG_FILE_NAME))?;
+        }
+        self.size = 0;
+        Ok(())
+    }
+    fn write_line(&mut self, line: &str) -> io::Result<()> {
+        let len = line.len() as u64 + 1;
+        if self.size > 0 && self.size + len > self.settings.max_size {
+            self.rotate()?;
+        }
+        writeln!(self.file, "{line}")?;
+        self.size += len;
+        Ok(())
+    }
+}
+
+/// The message and the other fields of an event
+#[derive(Default)]
+struct FieldVisitor {
+    message: String,
+    fields: Vec<(&'static str, String)>,
+}
+
+impl Visit for FieldVisitor {
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        self.record_str(field, &format!("{value:?}"));
+    }
+    fn record_str(&mut self, field: &Field, value: &str) {
+        match field.name() {
+            "message" => self.message = value.to_string(),
+            name => self.fields.push((name, value.to_string())),
+        }
+    }
+}
+
+/// Appends the string as a json-string, with quotes
+fn push_json_string(line: &mut String, value: &str) {
+    line.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => line.push_str("\\\""),
+            '\\' => line.push_str("\\\\"),
+            '\n' => line.push_str("\\n"),
+            '\r' => line.push_str("\\r"),
+            '\t' => line.push_str("\\t"),
+            c if c.is_control() => line.push_str(&format!("\\u{:04x}", c as u32)),
+            c => line.push(c),
+        }
+    }
+    line.push('"');
+}
+
+/// Writes each event to the log-file, next to the layer sending them to the log-panel
+#[derive(Debug)]
+pub struct FileLayer {
+    file: Mutex<RotatingFile>,
+}
+
+impl FileLayer {
+    /// Opens the log-file in the [`LOG_DIR`] of the project-directory
+    pub fn open(root_dir: &path::Path, settings: LogFileSettings) -> io::Result<FileLayer> {
+        RotatingFile::open(root_dir.join(LOG_DIR), settings).map(|file| FileLayer {
+            file: Mutex::new(file),
+        })
+    }
+    fn format_line(
+        format: LogFileFormat,
+        metadata: &tracing::Metadata<'_>,
+        FieldVisitor { message, fields }: FieldVisitor,
+    ) -> String {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs_f64();
+        match format {
+            LogFileFormat::Plain => {
+                let mut line = format!(
+                    "{timestamp:.3} {} {}: {message}",
+                    metadata.level(),
+                    metadata.target()
+                );
+                for (name, value) in fields {
+                    line.push_str(&format!(" {name}={value}"));
+                }
+                line
+            }
+            LogFileFormat::JsonLines => {
+                let mut line = format!(
+                    "{{\"timestamp\":{timestamp:.3},\"level\":\"{}\",\"target\":",
+                    metadata.level()
+                );
+                push_json_string(&mut line, metadata.target());
+                line.push_str(",\"message\":");
+                push_json_string(&mut line, &message);
+                for (name, value) in fields {
+                    line.push(',');
+                    push_json_string(&mut line, name);
+                    line.push(':');
+                    push_json_string(&mut line, &value);
+                }
+                line.push('}');
+                line
+            }
+        }
+    }
+}
+
+impl<S> Layer<S> for FileLayer
+where
+    S: Subscriber,
+{
+    fn on_event(&self, event: &tracing::Event<'_>, _ctx: Context<'_, S>) {
+        let mut visitor = FieldVisitor::default();
+        event.record(&mut visitor);
+        let Ok(mut file) = self.file.lock() else {
+            return;
+        };
+        let line = FileLayer::format_line(file.settings.format, event.metadata(), visitor);
+        // Reporting the failure would log again, and the log-panel still has the event
+        let _ = file.write_line(&line);
+    }
+}
+
+/// Replaces the log-file of the installed subscriber, as the project is only known
+/// once the subscriber is already logging the configuration
+#[derive(Clone, Debug)]
+pub struct LogFileHandle {
+    handle: reload::Handle<Option<FileLayer>, Registry>,
+}
+
+impl LogFileHandle {
+    pub fn new(handle: reload::Handle<Option<FileLayer>, Registry>) -> LogFileHandle {
+        LogFileHandle { handle }
+    }
+    /// Writes to the log-file of the project from now on, or stops writing
+    /// if the project has no `[log_file]` section
+    pub fn open(&self, root_dir: &path::Path, settings: Option<&LogFileSettings>) {
+        let file_layer = match settings.map(|settings| FileLayer::open(root_dir, settings.clone()))
+        {
+            Some(Ok(file_layer)) => Some(file_layer),
+            Some(Err(e)) => {
+                tracing::warn!(
+                    "Failed to open the log-file in {}: {e}",
+                    paths::relative_to(&root_dir.join(LOG_DIR), root_dir)
+                );
+                None
+            }
+            None => None,
+        };
+        if let Err(e) = self.handle.reload(file_layer) {
+            tracing::warn!("Failed to replace the log-file: {e}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rotates_past_max_size() {
+        let dir = std::env::temp_dir().join(format!("pico-build-log-file-{}", std::process::id()));
+        let settings = LogFileSettings {
+            max_size: 16,
+            max_files: 2,
+            ..LogFileSettings::default()
+        };
+        let mut file = RotatingFile::open(dir.clone(), settings).unwrap();
+        for line in ["first line", "second line", "third line", "fourth line"] {
+            file.write_line(line).unwrap();
+        }
+        let read = |name: &str| fs::read_to_string(dir.join(name)).unwrap();
+        assert_eq!(read(LOG_FILE_NAME), "fourth line\n");
+        assert_eq!(read("pico-build.log.1"), "third line\n");
+        assert_eq!(read("pico-build.log.2"), "second line\n");
+        assert!(!dir.join("pico-build.log.3").exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,63 +1,180 @@
 use core::fmt;
 use core::ops::{Deref, DerefMut};
 
-use std::sync::mpsc;
+use std::collections::VecDeque;
 
-use pico_build_rs::Fifo;
+use pico_build_rs::SyncFifo;
 use ratatui::{
     prelude::*,
-    widgets::{Block, Padding, Paragraph},
+    widgets::{Block, Padding},
 };
 use tracing::Subscriber;
 use tracing::field::{Field, Visit};
 use tracing_subscriber::layer::{Layer, SubscriberExt};
+use tracing_subscriber::reload;
 use tracing_subscriber::util::SubscriberInitExt;
 
-#[derive(Debug)]
-pub struct LogPanelStore {
-    buf: Fifo<Line<'static>>,
-}
+use crate::bench::{Millis, StageSummary};
+use crate::log_file::{FileLayer, LogFileHandle};
+use crate::theme::{Palette, Theme};
 
-impl Deref for LogPanelStore {
-    type Target = Fifo<Line<'static>>;
+/// The warnings and errors kept for [`LogPanelStore::recent_warnings`]
+const RECENT_WARNING_COUNT: usize = 3;
 
-    fn deref(&self) -> &Self::Target {
-        &self.buf
-    }
-}
+/// The log-lines kept for scrolling back, the oldest overwritten first
+pub const SCROLLBACK_LINE_COUNT: usize = 1000;
 
-// impl DerefMut for LogPanelStore {
-//     fn deref_mut(&mut self) -> &mut Self::Target {
-//         &mut self.buf
-//     }
-// }
+/// The log-lines scrolled by a page, those visible in the log-panel
+const PAGE_LINE_COUNT: usize = LINE_COUNT - 2;
+
+/// Scrolls the log-panel, bound to PageUp/PageDown/Home/End
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LogPanelScroll {
+    PageUp,
+    PageDown,
+    /// To the oldest line kept
+    Top,
+    /// To the newest line, following the lines logged from then on
+    Bottom,
+}
 
-impl FromIterator<Line<'static>> for LogPanelStore {
-    fn from_iter<T: IntoIterator<Item = Line<'static>>>(iter: T) -> Self {
-        let buf = Box::from_iter(iter);
-        let buf = Fifo::from(buf);
-        LogPanelStore { buf }
+#[derive(Debug)]
+pub struct LogPanelStore {
+    /// Written by the tracing-layer from any thread, see [`setup_tracing_subscriber`]
+    events: SyncFifo<LogEvent>,
+    /// The events written as of the last [`LogPanelStore::sync`]
+    synced: usize,
+    /// The events kept, each rendered once when synced instead of on every frame
+    lines: VecDeque<Line<'static>>,
+    /// The lines scrolled back from the newest, following the newest lines at 0
+    scroll_offset: usize,
+    palette: Palette,
+    warning_count: usize,
+    recent_warnings: VecDeque<String>,
+}
+
+impl FromIterator<LogEvent> for LogPanelStore {
+    fn from_iter<T: IntoIterator<Item = LogEvent>>(iter: T) -> Self {
+        let store = LogPanelStore::new(Theme::default());
+        for log_event in iter {
+            store.events.overwrite(log_event);
+        }
+        store
     }
 }
 
 impl Default for LogPanelStore {
     fn default() -> Self {
-        let arr: [Line<'static>; LINE_COUNT] = core::array::from_fn(|_| Line::default());
-        LogPanelStore {
-            buf: Fifo::from_iter(arr),
-        }
+        LogPanelStore::new(Theme::default())
     }
 }
 
 impl LogPanelStore {
+    pub fn new(theme: Theme) -> LogPanelStore {
+        LogPanelStore::with_events(theme, SyncFifo::with_capacity(SCROLLBACK_LINE_COUNT))
+    }
+    /// Shows the events written into the buffer, e.g. by the tracing-layer
+    pub fn with_events(theme: Theme, events: SyncFifo<LogEvent>) -> LogPanelStore {
+        LogPanelStore {
+            events,
+            synced: 0,
+            lines: VecDeque::new(),
+            scroll_offset: 0,
+            palette: theme.palette(),
+            warning_count: 0,
+            recent_warnings: VecDeque::new(),
+        }
+    }
     pub fn clear(&mut self) {
-        for log_line in self.buf.iter_mut() {
-            *log_line = Line::default();
+        let mut events = self.events.lock();
+        events.clear();
+        self.synced = self.events.written();
+        drop(events);
+        self.lines.clear();
+        self.scroll_offset = 0;
+        self.warning_count = 0;
+        self.recent_warnings.clear();
+    }
+    /// Catches up with the events written since last synced, rendering them and counting
+    /// the warnings among them, and keeping the scrolled-back lines in view
+    pub fn sync(&mut self) {
+        let events = self.events.lock();
+        // Counted under the lock by the writers, so the newest events are exactly these
+        let written = self.events.written();
+        let new_count = written - self.synced;
+        self.synced = written;
+        if new_count == 0 {
+            return;
+        }
+        let old_count = events.len().saturating_sub(new_count);
+        for log_event in events.iter().skip(old_count) {
+            self.lines.push_back(log_event.to_line(&self.palette));
+            if log_event
+                .level()
+                .is_some_and(|level| level <= tracing::Level::WARN)
+            {
+                self.warning_count += 1;
+                if self.recent_warnings.len() == RECENT_WARNING_COUNT {
+                    self.recent_warnings.pop_front();
+                }
+                self.recent_warnings.push_back(log_event.to_string());
+            }
         }
-        self.buf.reset_cursor();
+        // Those overwritten in the buffer since
+        let stale_count = self.lines.len().saturating_sub(events.len());
+        self.lines.drain(..stale_count);
+        let max_scroll_offset = events.len().saturating_sub(PAGE_LINE_COUNT);
+        drop(events);
+        if self.scroll_offset > 0 {
+            self.scroll_offset = (self.scroll_offset + new_count).min(max_scroll_offset);
+        }
+    }
+    /// The events kept for scrolling back
+    pub fn event_count(&self) -> usize {
+        self.events.lock().len()
     }
-    pub fn update(&mut self, log_event: LogEvent) {
-        self.buf.overwrite(Line::from(log_event));
+    fn max_scroll_offset(&self) -> usize {
+        self.event_count().saturating_sub(PAGE_LINE_COUNT)
+    }
+    pub fn scroll(&mut self, scroll: LogPanelScroll) {
+        self.scroll_offset = match scroll {
+            LogPanelScroll::PageUp => self.scroll_offset + PAGE_LINE_COUNT,
+            LogPanelScroll::PageDown => self.scroll_offset.saturating_sub(PAGE_LINE_COUNT),
+            LogPanelScroll::Top => usize::MAX,
+            LogPanelScroll::Bottom => 0,
+        }
+        .min(self.max_scroll_offset());
+    }
+    /// Whether the panel is pinned to the newest lines
+    pub const fn is_following(&self) -> bool {
+        self.scroll_offset == 0
+    }
+    /// The lines in view as of the last [`LogPanelStore::sync`], up to the count, oldest first
+    pub fn visible_lines(&self, count: usize) -> impl Iterator<Item = &Line<'static>> {
+        let end = self.lines.len().saturating_sub(self.scroll_offset);
+        self.lines.range(end.saturating_sub(count)..end)
+    }
+    /// The title of the panel, telling whether it follows the newest lines
+    pub fn title(&self) -> String {
+        if self.is_following() {
+            "log-panel (following)".to_string()
+        } else {
+            format!(
+                "log-panel ({} lines back, End to follow)",
+                self.scroll_offset
+            )
+        }
+    }
+    pub const fn palette(&self) -> &Palette {
+        &self.palette
+    }
+    /// The warnings and errors logged since the panel was last cleared
+    pub const fn warning_count(&self) -> usize {
+        self.warning_count
+    }
+    /// The last few warnings and errors, newest last
+    pub fn recent_warnings(&self) -> impl Iterator<Item = &str> {
+        self.recent_warnings.iter().map(String::as_str)
     }
 }
 
@@ -88,22 +205,39 @@ pub enum LogPanelAction {
 //     }
 // }
 
-pub struct LogPanelWidget {
-    log_lines: Vec<Line<'static>>,
+/// Renders the lines of the store as borrowed, without cloning them on each frame
+pub struct LogPanelWidget<'a> {
+    log_lines: Vec<&'a Line<'static>>,
+    title: String,
 }
 
-impl From<Vec<Line<'static>>> for LogPanelWidget {
-    fn from(value: Vec<Line<'static>>) -> Self {
-        LogPanelWidget { log_lines: value }
+impl<'a> LogPanelWidget<'a> {
+    /// Renders the lines in view of the store, titled by whether it follows the newest lines
+    pub fn from_store(store: &'a LogPanelStore, area: Rect) -> LogPanelWidget<'a> {
+        let line_count = get_block().inner(area).height.into();
+        LogPanelWidget {
+            log_lines: store.visible_lines(line_count).collect(),
+            title: store.title(),
+        }
     }
-}
-
-impl FromIterator<Line<'static>> for LogPanelWidget {
-    fn from_iter<T: IntoIterator<Item = Line<'static>>>(iter: T) -> Self {
-        let log_lines: Vec<Line<'static>> = iter.into_iter().collect();
-        LogPanelWidget::from(log_lines)
+    /// Adds the time taken to draw the recent frames to the title
+    pub fn with_frame_times(mut self, frame_times: Option<&StageSummary>) -> LogPanelWidget<'a> {
+        if let Some(frame_times) = frame_times {
+            let allocations = frame_times
+                .mean_allocations
+                .map(|allocations| format!(", {allocations} allocations"))
+                .unwrap_or_default();
+            self.title = format!(
+                "{} frame {} ({} p95{allocations})",
+                self.title,
+                Millis(frame_times.mean),
+                Millis(frame_times.p95)
+            );
+        }
+        self
     }
 }
+
 pub fn get_block() -> Block<'static> {
     Block::bordered().title("log-panel")
 }
@@ -151,19 +285,6 @@ impl<'a> Widget for LogPanelBlock<'a> {
     }
 }
 
-trait ImplementationSpecificBlock<'a>: Deref<Target = Block<'a>> {
-    fn get_rect(&self, frame: &mut Frame<'_>) -> Rect;
-    fn get_enclosed_area_in(&self, frame: &mut Frame<'_>) -> Rect {
-        self.deref().inner(self.get_rect(frame))
-    }
-}
-
-impl<'a> ImplementationSpecificBlock<'a> for LogPanelBlock<'a> {
-    fn get_rect(&self, frame: &mut Frame<'_>) -> Rect {
-        crate::get_ui_rects(frame, self.log_panel_lines)[RECT_INDEX]
-    }
-}
-
 pub const RECT_INDEX: usize = 1;
 /// Returns the rectangle enclosed in the block
 fn get_rect(frame: &mut Frame, log_panel_lines: usize) -> Rect {
@@ -173,88 +294,29 @@ fn get_rect_from_area(log_panel_area: Rect) -> Rect {
     get_block().inner(log_panel_area)
 }
 
-trait ImplementationSpecificParagraph<'text, 'block>: Deref<Target = Paragraph<'text>> {
-    fn get_block(&self) -> Option<&dyn ImplementationSpecificBlock<'block>> {
-        None
-    }
-    fn render(&self, area: Rect, buf: &mut Buffer) {
-        let paragraph = self.deref().clone();
-        let area = if let Some(block) = self.get_block() {
-            let inner_block = block.deref();
-            inner_block.render(area, buf);
-            inner_block.inner(area).intersection(area)
-        } else {
-            area
-        };
-        // let (paragraph, inner_area) = if let Some(block) = self.get_block() {
-        //     let inner_block = block.deref();
-        //     let inner_area = inner_block.inner(area);
-        //     (paragraph.block(inner_block.clone()), inner_area)
-        // } else {
-        //     (paragraph, area)
-        // };
-        // let paragraph = paragraph.scroll((2, 0));
-        paragraph.render(area, buf);
-    }
-}
-
-struct LogPanelParagraph<'text, 'block> {
-    inner: Paragraph<'text>,
-    block: LogPanelBlock<'block>,
-}
-
-impl<'text> Deref for LogPanelParagraph<'text, '_> {
-    type Target = Paragraph<'text>;
-
-    fn deref(&self) -> &Self::Target {
-        &self.inner
-    }
-}
-
-impl<'a> LogPanelParagraph<'a, 'static> {
-    pub fn new<T: Into<Text<'a>>>(text: T) -> LogPanelParagraph<'a, 'static> {
-        LogPanelParagraph {
-            inner: Paragraph::new(text).scroll((2, 0)),
-            block: LogPanelBlock::default(),
-        }
-    }
-}
-
-impl<'text, 'block> ImplementationSpecificParagraph<'text, 'block>
-    for LogPanelParagraph<'text, 'block>
-{
-    fn get_block(&self) -> Option<&dyn ImplementationSpecificBlock<'block>> {
-        Some(&self.block)
-    }
-}
-
-// fn get_log_panel_rect(frame: &mut Frame, log_panel_lines: usize) -> Rect {
-//     log_panel::get_block().inner(crate::get_ui_rects(frame, log_panel_lines)[RECT_INDEX])
-// }
-impl Widget for LogPanelWidget {
+impl Widget for LogPanelWidget<'_> {
     fn render(self, area: Rect, buf: &mut Buffer)
     where
         Self: Sized,
     {
-        // use ratatui::widgets::Paragraph;
-        let paragraph = LogPanelParagraph::new(self.log_lines);
-        paragraph.render(area, buf);
-        // let border_block = LogPanelBlock::default();
-        // let text_area = border_block.get_enclosed_area_in(area);
-        // border_block.render(area, buf);
-
-        // Paragraph::new(self.log_lines)
-        //     .block(get_block())
-        //     .render(area, buf)
+        let block = LogPanelBlock {
+            inner: Block::bordered().title(self.title),
+            ..LogPanelBlock::default()
+        };
+        let lines_area = block.inner(area).intersection(area);
+        block.render(area, buf);
+        for (log_line, row) in self.log_lines.into_iter().zip(lines_area.rows()) {
+            buf.set_line(row.x, row.y, log_line, row.width);
+        }
     }
 }
-/// Just intercepts the messages and forwards them to the frontend bits
+/// Just intercepts the messages and writes them into the buffer of the log-panel
 #[derive(Debug)]
-struct SenderLayer<T> {
-    message_tx: mpsc::Sender<T>,
+struct PanelLayer<T> {
+    events: SyncFifo<T>,
 }
 
-impl<S> Layer<S> for SenderLayer<LogEvent>
+impl<S> Layer<S> for PanelLayer<LogEvent>
 where
     S: Subscriber,
 {
@@ -263,8 +325,8 @@ where
         event: &tracing::Event<'_>,
         _ctx: tracing_subscriber::layer::Context<'_, S>,
     ) {
-        event.record(&mut SendingVisitor {
-            message_tx: self.message_tx.clone(),
+        event.record(&mut WritingVisitor {
+            events: &self.events,
             metadata: _ctx.current_span().metadata(),
         });
     }
@@ -313,20 +375,11 @@ pub struct VisitMetadata {
 }
 
 /// Stylizes and constructs a [`Span`] with the [`tracing::Level`]
-struct LevelSpan(tracing::Level);
+struct LevelSpan<'a>(tracing::Level, &'a Palette);
 
-impl From<LevelSpan> for Span<'static> {
-    fn from(LevelSpan(level): LevelSpan) -> Self {
-        Span::styled(
-            level.to_string(),
-            Style::new().fg(match level {
-                tracing::Level::ERROR => Color::Red,
-                tracing::Level::WARN => Color::Yellow,
-                tracing::Level::INFO => Color::Green,
-                tracing::Level::DEBUG => Color::Blue,
-                tracing::Level::TRACE => Color::DarkGray,
-            }),
-        )
+impl From<LevelSpan<'_>> for Span<'static> {
+    fn from(LevelSpan(level, palette): LevelSpan<'_>) -> Self {
+        Span::styled(level.to_string(), palette.level(level))
     }
 }
 
@@ -367,10 +420,10 @@ impl From<&tracing::Metadata<'static>> for VisitMetadata {
 }
 
 impl VisitMetadata {
-    pub fn spans(&self) -> impl Iterator<Item = Span<'static>> {
+    pub fn spans(&self, palette: &Palette) -> impl Iterator<Item = Span<'static>> {
         [
             // Prints level and colorizes correctly
-            LevelSpan(self.level).into(),
+            LevelSpan(self.level, palette).into(),
             // Add the target in
             Span::raw(format!(" | {} | ", self.target)),
             // Describes the message primarily
@@ -386,7 +439,7 @@ impl VisitMetadata {
 
 impl From<&VisitMetadata> for Line<'static> {
     fn from(visit_metadata: &VisitMetadata) -> Self {
-        Line::default().spans(visit_metadata.spans())
+        Line::default().spans(visit_metadata.spans(&Palette::default()))
     }
 }
 
@@ -431,15 +484,22 @@ impl core::fmt::Display for LogEvent {
 struct VisitDataSpan<'a> {
     field: &'a Field,
     data: &'a VisitData,
+    palette: &'a Palette,
 }
 
 impl From<VisitDataSpan<'_>> for Span<'static> {
-    fn from(VisitDataSpan { field, data }: VisitDataSpan<'_>) -> Self {
+    fn from(
+        VisitDataSpan {
+            field,
+            data,
+            palette,
+        }: VisitDataSpan<'_>,
+    ) -> Self {
         let field_name = field.name();
         let data_str: std::borrow::Cow<'_, str> = data.into();
         let data_str = std::borrow::Cow::Owned(data_str.into_owned());
         match field_name {
-            "return" => Span::styled(data_str, Style::new().fg(Color::DarkGray).italic()),
+            "return" => Span::styled(data_str, palette.muted),
             "message" => Span::raw(data_str),
             _ => Span::raw(format!("{field_name}={data_str}")),
         }
@@ -453,30 +513,40 @@ impl From<LogEvent> for Line<'static> {
 }
 
 impl From<&LogEvent> for Line<'static> {
-    fn from(
-        LogEvent {
+    fn from(log_event: &LogEvent) -> Self {
+        log_event.to_line(&Palette::default())
+    }
+}
+
+impl LogEvent {
+    pub fn level(&self) -> Option<tracing::Level> {
+        self.metadata.as_ref().map(|metadata| metadata.level)
+    }
+    /// Renders the event as a line, styled according to the palette
+    pub fn to_line(&self, palette: &Palette) -> Line<'static> {
+        let LogEvent {
             field,
             data,
             metadata,
-        }: &LogEvent,
-    ) -> Self {
+        } = self;
         let mut line_builder = if let Some(metadata) = metadata {
             Line::default().spans(
                 core::iter::once(Span::from("[ "))
-                    .chain(metadata.spans())
+                    .chain(metadata.spans(palette))
                     .chain(core::iter::once(Span::from(" ] "))),
             )
         } else {
             Line::default()
         };
 
-        line_builder.push_span(VisitDataSpan { field, data });
+        line_builder.push_span(VisitDataSpan {
+            field,
+            data,
+            palette,
+        });
 
         line_builder
     }
-}
-
-impl LogEvent {
     pub fn new<'p, P: ?Sized>(
         field: &Field,
         metadata: Option<&tracing::Metadata<'static>>,
@@ -494,27 +564,34 @@ impl LogEvent {
 }
 
 #[derive(Debug)]
-struct SendingVisitor<'ctx, T> {
-    message_tx: mpsc::Sender<T>,
+struct WritingVisitor<'ctx, T> {
+    events: &'ctx SyncFifo<T>,
     metadata: Option<&'ctx tracing::Metadata<'static>>,
 }
 
-impl SendingVisitor<'_, LogEvent> {
+/// Attempts at writing an event while the buffer is locked by another thread,
+/// before dropping it
+const WRITE_ATTEMPTS: usize = 3;
+
+impl WritingVisitor<'_, LogEvent> {
     fn send_payload<'p, P: ?Sized>(&self, field: &Field, payload_data: &'p P)
     where
         VisitData: From<&'p P>,
     {
-        match self
-            .message_tx
-            .send(LogEvent::new(field, self.metadata, payload_data))
-        {
-            Ok(_) => {}
-            Err(e) => eprintln!("Failed to send event from sending-visitor: {e}"),
+        // The thread rendering the panel may log while holding the lock,
+        // where waiting for it would never return
+        let mut log_event = LogEvent::new(field, self.metadata, payload_data);
+        for _ in 0..WRITE_ATTEMPTS {
+            match self.events.try_overwrite(log_event) {
+                Ok(_) => return,
+                Err(unwritten) => log_event = unwritten,
+            }
+            std::thread::yield_now();
         }
     }
 }
 
-impl Visit for SendingVisitor<'_, LogEvent> {
+impl Visit for WritingVisitor<'_, LogEvent> {
     fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
         self.send_payload(field, value);
         // match self.send_payload(field, value) {
@@ -547,13 +624,18 @@ impl Visit for SendingVisitor<'_, LogEvent> {
     }
 }
 
-/// Returns a channel for the messages (u probably want em)
-pub fn setup_tracing_subscriber() -> mpsc::Receiver<LogEvent> {
-    let (message_tx, message_rx) = mpsc::channel();
+/// Returns the buffer the messages are written into (u probably want em), and the handle
+/// opening the log-file once the project is known
+pub fn setup_tracing_subscriber() -> (SyncFifo<LogEvent>, LogFileHandle) {
+    let events = SyncFifo::with_capacity(SCROLLBACK_LINE_COUNT);
+    let (file_layer, file_layer_handle) = reload::Layer::new(None::<FileLayer>);
 
     tracing_subscriber::registry()
-        .with(SenderLayer { message_tx })
+        .with(file_layer)
+        .with(PanelLayer {
+            events: events.clone(),
+        })
         .init();
 
-    message_rx
+    (events, LogFileHandle::new(file_layer_handle))
 }
@@ -1,1036 +1,3157 @@
-use core::ops::Deref;
-use core::time::Duration;
-
-use std::collections::HashMap;
-use std::fs;
-use std::io;
-use std::path;
-use std::sync::mpsc;
-
-use anyhow::anyhow;
-use clap::Parser;
-use pico_8_cart_model::CartData;
-use pico_build_rs::Fifo;
-use ratatui::prelude::*;
-
-mod args;
-mod config;
-mod log_panel;
-
-use log_panel::{LogPanelAction, LogPanelStore, LogPanelWidget};
-
-pub trait StoreUpdate {
-    type Action;
-
-    fn update(&mut self, action: Self::Action) -> Option<Message>;
-}
-
-use pico_build_rs::FileData;
-
-/// The size of the log-panel in log-lines
-const LOG_LINE_COUNT: usize = 20;
-
-#[derive(Debug)]
-pub enum Action {
-    UpdateLogPanel(LogEvent),
-    ClearLogPanel,
-    CompileCartridge,
-    SaveCompiledCartridge {
-        cartridge_data: Box<CartData<'static>>,
-    },
-    AnalyzeCartridge,
-    DisplayAnalyzedCartridge {
-        cartridge_data: Box<CartData<'static>>,
-    },
-    Quit,
-}
-
-pub struct ActionContext<'a> {
-    log_panel_store: &'a mut LogPanelStore,
-    running_state: &'a mut RunningState,
-    project_source_file_path: &'a path::Path,
-    project_source_directory_path: &'a path::Path,
-}
-
-impl Action {
-    /// Invokes the action, and if a follow-up is needed,
-    /// returns the next step
-    #[tracing::instrument(level = "trace", ret)]
-    pub fn invoke(
-        self,
-        ActionContext {
-            log_panel_store,
-            running_state,
-            project_source_file_path,
-            project_source_directory_path,
-        }: ActionContext<'_>,
-    ) -> Option<Action> {
-        match self {
-            Action::ClearLogPanel => {
-                log_panel_store.clear();
-                // TODO: Here we maybe wanna return a clear or redraw terminal action?
-                None
-            }
-            Action::UpdateLogPanel(log_event) => {
-                log_panel_store.update(log_event);
-                // TODO: Here we maybe wanna return a clear or redraw terminal action?
-                None
-            }
-            Action::CompileCartridge => {
-                tracing::info!("Writing to cart-path {project_source_file_path:?}");
-                let source_files =
-                    match pico_build_rs::get_lua_files(project_source_directory_path) {
-                        Ok(files) => files,
-                        // files.inspect(|entry| {
-                        //     let path = entry.path();
-                        //     let name = path
-                        //         .file_name()
-                        //         .map(|file_name| file_name.to_string_lossy().into_owned())
-                        //         .unwrap_or_default();
-                        //     file_loading_tracker
-                        //         .paths
-                        //         .insert(name, FileLoadingState::Opened(path));
-                        // }),
-                        Err(e) => {
-                            tracing::error!("Failed to get lua files {e}");
-                            return None;
-                        }
-                    }
-                    .filter_map(|source_entry| {
-                        FileData::try_from(source_entry)
-                            .map_err(pico_build_rs::FileDataError::Io)
-                            .inspect_err(|e| {
-                                tracing::error!("Failed to convert source-entry: {e:?}")
-                            })
-                            .and_then(FileData::into_loaded_or_default)
-                            .ok()
-                    });
-                match FileData::new(project_source_file_path)
-                    .into_loaded_or_default()
-                    .and_then(|cart_file| {
-                        pico_build_rs::compile_cartridge(cart_file, source_files)
-                            .map_err(Into::into)
-                    }) {
-                    Ok(cart) => {
-                        tracing::info!("Got cart-data");
-                        Some(Action::SaveCompiledCartridge {
-                            cartridge_data: Box::new(cart),
-                        })
-                    }
-                    Err(e) => {
-                        tracing::error!("Failed to compile {e:?}");
-                        None
-                    }
-                }
-            }
-            Action::SaveCompiledCartridge { cartridge_data } => {
-                let buf: Box<[u8]> = cartridge_data.into_cart_source();
-                tracing::info!("Saving compiled cartridge (size: {})", buf.len());
-                let Ok(mut file) = fs::OpenOptions::new()
-                    .truncate(true)
-                    .write(true)
-                    .open(project_source_file_path)
-                else {
-                    tracing::info!("Failed to open output cart");
-                    return None;
-                };
-                // tracing::info!("Writing {} bytes to cart", buf.len());
-                match io::Write::write_all(&mut file, buf.as_ref()) {
-                    Ok(_) => {
-                        tracing::info!("Successfully wrote to cart");
-                        // panic!();
-                        None
-                    }
-                    Err(e) => {
-                        tracing::error!("Failed to write to cart: {e}");
-                        None
-                    }
-                }
-            }
-            Action::AnalyzeCartridge => {
-                tracing::debug!("Pretend im analyzing a cartridge");
-                None
-            }
-            Action::DisplayAnalyzedCartridge { cartridge_data } => {
-                todo!("implement displaying analyzed cartridge")
-            }
-            Action::Quit => {
-                *running_state = RunningState::Done;
-                None
-            }
-        }
-    }
-}
-
-#[derive(Debug)]
-pub struct Store {
-    log_panel_store: log_panel::LogPanelStore,
-    workspace_store: WorkspaceStore,
-    running_state: RunningState,
-}
-
-pub enum WorkspaceStoreAction {
-    Compile,
-    Analyze,
-}
-
-#[derive(Debug)]
-struct WorkspaceStore {
-    project_file: FileData<Box<pico_8_cart_model::CartData<'static>>>,
-    source_directory: path::PathBuf,
-    source_files: Box<[FileData<Box<[u8]>>]>,
-    // running_state: RunningState,
-}
-
-impl WorkspaceStore {
-    fn new(cfg: config::AppConfiguration) -> io::Result<WorkspaceStore> {
-        let project_file = FileData::new(&cfg.cart_path());
-
-        let mut app = WorkspaceStore {
-            project_file,
-            source_directory: cfg.src_dir,
-            source_files: Box::default(),
-            // running_state: RunningState::Running,
-        };
-
-        Ok(app)
-    }
-
-    /// Discovers all source files in the configured directory
-    fn discover_source_files(&self) -> io::Result<impl Iterator<Item = FileData<Box<[u8]>>>> {
-        pico_build_rs::get_lua_files(self.source_directory.as_path())
-            .map(pico_build_rs::dir_entries_to_source_files)
-    }
-
-    /// Reads the stateful files into memory
-    fn read_source_files(&mut self) -> Result<(), pico_build_rs::FileDataError<Box<[u8]>>> {
-        for source_file in self.source_files.iter_mut() {
-            source_file.load()?;
-        }
-
-        Ok(())
-    }
-
-    /// Rediscovers, but does not load source files in the configured directory
-    fn reset_source_files(&mut self) -> Result<(), pico_build_rs::FileDataError<Box<[u8]>>> {
-        let source_files = self.discover_source_files().map(Box::from_iter)?;
-        self.source_files = source_files;
-        Ok(())
-    }
-
-    /// Loads all source files in the configured directory
-    fn load_source_files(&mut self) -> Result<(), pico_build_rs::FileDataError<Box<[u8]>>> {
-        self.reset_source_files()?;
-        self.read_source_files()
-    }
-
-    fn reset_project_file(&mut self) {
-        tracing::debug!("Resetting project file");
-        self.project_file.unload();
-    }
-
-    /// Creates or loads the project-file
-    fn load_project_file(
-        &mut self,
-    ) -> Result<(), pico_build_rs::FileDataError<Box<pico_8_cart_model::CartData<'static>>>> {
-        tracing::debug!("Loading project file");
-        self.project_file.load()
-    }
-
-    /// Compile a new cartridge based on internal state
-    ///
-    /// Expects that [`ModelV2::load_source_files`] and [`ModelV2::load_project_file`] has been
-    /// called previously. Otherwise `Err` will be returned.
-    fn compile_cartridge(&self) -> io::Result<pico_8_cart_model::CartData<'static>> {
-        if !self.project_file.is_loaded() {
-            return Err(io::Error::new(
-                io::ErrorKind::InvalidData,
-                "Project file not loaded",
-            ));
-        }
-
-        if !self.source_files.iter().all(FileData::is_loaded) {
-            return Err(io::Error::new(
-                io::ErrorKind::InvalidData,
-                "Not all source-files are loaded",
-            ));
-        }
-
-        pico_build_rs::compile_cartridge(
-            self.project_file.clone(),
-            self.source_files.iter().cloned(),
-        )
-    }
-}
-
-impl StoreUpdate for WorkspaceStore {
-    type Action = WorkspaceStoreAction;
-    fn update(&mut self, action: Self::Action) -> Option<Message> {
-        match action {
-            WorkspaceStoreAction::Compile => todo!(),
-            WorkspaceStoreAction::Analyze => todo!(),
-        }
-    }
-}
-
-impl Store {
-    pub fn update(&mut self, action: Action) {
-        todo!()
-    }
-}
-// impl StoreUpdate for Store {
-//     type Action = Action;
-//     fn update(&mut self, action: Self::Action) -> Option<Message> {
-//         match action {
-//             Action::LogPanel(log_panel_action) => self.log_panel_store.update(log_panel_action),
-//             Action::Quit => ,
-//         }
-//     }
-// }
-
-#[derive(Debug)]
-struct ModelV2 {
-    workspace: WorkspaceStore,
-    running_state: RunningState,
-    log_messages: Fifo<Line<'static>>,
-
-    action_rx: mpsc::Receiver<Action>,
-}
-
-impl ModelV2 {
-    // /// Checks for incoming actions and handles them accordingly
-    // pub fn update(&self) -> Result<(), mpsc::TryRecvError> {
-    //     'drain: loop {
-    //         let Ok(action) = self.action_rx.try_recv() {
-    //             match action {
-    //                 Action::LogPanel(LogPanelAction::HandleIncoming(log_event)) =>
-    //             }
-    //         }
-    //     }
-    //     todo!()
-    // }
-}
-
-#[tracing::instrument(level = "info", ret)]
-fn main() -> anyhow::Result<()> {
-    use crate::args::AppArgs;
-    use crate::config::AppConfiguration;
-
-    let log_event_rx = log_panel::setup_tracing_subscriber();
-
-    let args = AppArgs::parse();
-
-    let cfg = AppConfiguration::new(&args)?;
-    tracing::info!("parsed app configuration");
-    tracing::trace!("{cfg:#?}");
-    tracing::info!("source directory is {:?}", cfg.src_dir);
-    let cart_path = cfg.cart_path();
-    tracing::info!("cart path is {:?}", cart_path);
-    let mut terminal = ratatui::init();
-    let log_panel_store = LogPanelStore::default();
-    tracing::info!("log-messages length: {}", log_panel_store.len());
-    // let log_panel_chunk = get_ui_rects(&mut terminal.get_frame(), log_messages.len())[1];
-    // tracing::info!("log-panel height: {}", log_panel_chunk.height);
-    // let (tx, rx) = mpsc::channel();
-    let (action_tx, action_rx) = mpsc::channel();
-    let mut event_bus = EventBus::new(action_tx);
-    event_bus.register_listener(KeyboardEventListener::default());
-    event_bus.register_listener(LogEventListener::new(log_event_rx));
-    let _input_thread = std::thread::spawn(move || {
-        // let event_bus = EventBus::new(action_tx);
-        while let Ok(()) = event_bus.update() {
-            // tracing::debug!("looping");
-            // empty loop; keeps the thread alive and breaks on disconnected channel
-        }
-    });
-    let mut model = Model {
-        src_dir: cfg.src_dir.clone(),
-        cart_path,
-        log_panel_store,
-        running_state: RunningState::Running,
-        file_loading_tracker: FileLoadingTracker {
-            paths: Default::default(),
-        },
-    };
-    while !matches!(model.running_state, RunningState::Done) {
-        terminal.draw(|frame| view(&model, frame))?;
-
-        let mut current_action = match action_rx.try_recv() {
-            Ok(val) => Some(val),
-            Err(mpsc::TryRecvError::Empty) => None,
-            Err(mpsc::TryRecvError::Disconnected) => {
-                break;
-            }
-        };
-
-        while current_action.is_some() {
-            let ctx = ActionContext {
-                log_panel_store: &mut model.log_panel_store,
-                running_state: &mut model.running_state,
-                project_source_file_path: model.cart_path.as_path(),
-                project_source_directory_path: model.src_dir.as_path(),
-            };
-
-            current_action = current_action.unwrap().invoke(ctx);
-        }
-
-        // let mut current_message = handle_event(&model)
-        //     .or_else(|| next_message(&log_event_rx).map(Message::IncomingLogLine));
-
-        // while current_message.is_some() {
-        //     current_message = update(&mut model, current_message.unwrap())
-        //         .or_else(|| next_message(&log_event_rx).map(Message::IncomingLogLine));
-        //     if matches!(
-        //         current_message.as_ref(),
-        //         Some(&Message::Input(InputMessage::ClearLog))
-        //     ) {
-        //         // terminal.swap_buffers();
-        //         // let rect = get_rect(&mut terminal.get_frame())[1];
-        //         // let mut frame = terminal.get_frame();
-        //         // let buffer = frame.buffer_mut();
-        //         // for position in rect.positions() {
-        //         //     buffer[position].set_symbol("JUNK");
-        //         // }
-        //         // terminal.swap_buffers();
-        //         terminal.clear()?;
-        //     }
-        // }
-    }
-    // loop {
-    //     terminal.draw(|frame| {
-    //         let chunks = Layout::default()
-    //             .direction(Direction::Vertical)
-    //             .constraints([
-    //                 Constraint::Min(1),         // big main-box
-    //                 Constraint::Percentage(50), // log-box
-    //             ])
-    //             .split(frame.area());
-    //         frame.render_widget(
-    //             widgets::Block::new()
-    //                 .title("main")
-    //                 .borders(widgets::Borders::ALL),
-    //             chunks[0],
-    //         );
-
-    //         if let Ok(msg) = message_rx.try_recv() {
-    //             log_state.push(msg);
-    //         }
-    //         frame.render_stateful_widget(LogWidget {}, chunks[1], &mut log_state);
-    //     })?;
-    //     if event::poll(Duration::from_millis(10))? {
-    //         match event::read()? {
-    //             event::Event::Key(key) if key.kind == event::KeyEventKind::Press => {
-    //                 match key.code {
-    //                     event::KeyCode::Char('q') => break,
-    //                     event::KeyCode::Enter => {
-    //                         let cart = pico_8_cart_builder::CartBuilder::new(&cfg.src_dir)
-    //                             .build(&cart_path)?;
-    //                         tracing::info!("got cart");
-    //                         tracing::trace!("{cart:#?}")
-    //                     }
-    //                     _ => {}
-    //                 }
-    //             }
-    //             _ => {}
-    //         }
-    //     }
-    // }
-    ratatui::restore();
-    Ok(())
-    // tracing::info!("Opening cart at {cart_path:?}");
-
-    // let mut cart_file = fs::File::open(cart_path)?;
-
-    // // Buffer for file-data
-    // let mut cart_src = vec![];
-
-    // // Copy file-data
-    // io::Read::read_to_end(&mut cart_file, &mut cart_src)?;
-
-    // let cart = pico_8_cart_model::CartData::from_cart_source(cart_src.as_ref())?;
-
-    // let cart = pico_build_rs::P8Cart::try_from_reader(&mut cart_file)
-    //     .map_err(|e| anyhow!("Failed to read cart data from file: {e}"));
-
-    // let cart = pico_8_cart_builder::CartBuilder::new(cfg.src_dir).build(&cart_path)?;
-    // tracing::info!("{cart:#?}");
-
-    // let mut file = fs::OpenOptions::new()
-    //     .write(true)
-    //     .read(true)
-    //     .append(false)
-    //     .truncate(true)
-    //     .create(true)
-    //     .open("main_dst.p8")?;
-    // let cart_src: Box<[u8]> = cart.into_cart_source();
-    // io::Write::write_all(&mut file, cart_src.as_ref())?;
-
-    // let original_file = fs::read_to_string(&cart_path)?;
-    // let copied_file = fs::read_to_string("main_dst.p8")?;
-
-    // if original_file == copied_file {
-    //     tracing::info!("The files match!")
-    // } else {
-    //     tracing::warn!("The files were different...")
-    // }
-}
-
-use crossterm::event::{self, KeyEventKind};
-use crossterm::event::{Event, KeyCode, KeyEvent};
-
-use crate::log_panel::LogEvent;
-
-fn layout(log_panel_lines: usize) -> Layout {
-    Layout::default()
-        .direction(Direction::Vertical)
-        .constraints([
-            Constraint::Min(1),                         // big main-box
-            Constraint::Length(log_panel_lines as u16), // log-box
-        ])
-}
-fn get_ui_rects(frame: &mut Frame, log_panel_lines: usize) -> std::rc::Rc<[Rect]> {
-    layout(log_panel_lines).split(frame.area())
-}
-
-const LOG_PANEL_RECT_INDEX: usize = log_panel::RECT_INDEX;
-
-type LogLine = Line<'static>;
-
-#[derive(Debug)]
-enum FileLoadingState {
-    Opened(path::PathBuf),
-}
-struct FileLoadingTracker {
-    paths: HashMap<String, FileLoadingState>,
-}
-struct Model {
-    src_dir: path::PathBuf,
-    cart_path: path::PathBuf,
-    // /// Checked during the [`handle_event`] call
-    // log_message_rx: mpsc::Receiver<log_panel::VisitPayload>,
-    /// An owned log-message
-    log_panel_store: LogPanelStore,
-
-    running_state: RunningState,
-    file_loading_tracker: FileLoadingTracker,
-}
-#[derive(Debug)]
-enum RunningState {
-    Done,
-    Running,
-}
-/// A user-command
-#[derive(Debug)]
-enum InputMessage {
-    /// A request to analyze the cartridge was made
-    Analyze {
-        cart_path: path::PathBuf,
-    },
-    /// A request to compile the cartridge was made
-    Compile {
-        src_dir: path::PathBuf,
-        cart_path: path::PathBuf,
-    },
-    /// A request to quit was made
-    Quit,
-
-    ClearLog,
-}
-/// A statement about how the model should change
-#[derive(Debug)]
-enum Message {
-    Input(InputMessage),
-    /// The analysis-request finished successfully
-    AnalysisOutput {
-        analyzed_data: Box<pico_8_cart_model::CartData<'static>>,
-    },
-    /// The compilation-request finished successfully
-    CompilationOutput {
-        compiled_data: Box<pico_8_cart_model::CartData<'static>>,
-        cart_path: path::PathBuf,
-    },
-
-    LogCleared,
-    LogUpdated,
-}
-
-/// Make a decision regarding how the model should change
-fn handle_event(model: &Model) -> Option<Message> {
-    // if let Ok(log_message) = log_message_rx.try_recv() {
-    //     return Some(Message::IncomingLogLine(Line::from(&log_message)));
-    // };
-
-    match event::poll(Duration::from_millis(10)) {
-        Err(_) | Ok(false) => None,
-        Ok(true) => {
-            if let Ok(Event::Key(key)) = event::read() {
-                handle_key(model, key).map(Message::Input)
-            } else {
-                None
-            }
-        }
-    }
-}
-fn handle_key(
-    Model {
-        src_dir, cart_path, ..
-    }: &Model,
-    key: KeyEvent,
-) -> Option<InputMessage> {
-    match key.code {
-        KeyCode::Enter if key.is_press() => Some(InputMessage::Compile {
-            src_dir: src_dir.clone(),
-            cart_path: cart_path.clone(),
-        }),
-        KeyCode::Char('a' | 'A') => Some(InputMessage::Analyze {
-            cart_path: cart_path.clone(),
-        }),
-        KeyCode::Char('q' | 'Q') => Some(InputMessage::Quit),
-        KeyCode::Char('c' | 'C') => Some(InputMessage::ClearLog),
-        _ => None,
-    }
-}
-
-fn read_event_polled(timeout: Duration) -> io::Result<Option<Event>> {
-    let event_available =
-        event::poll(timeout).inspect_err(|e| tracing::error!("Failed to poll for events: {e}"))?;
-    if event_available {
-        event::read().map(Some)
-    } else {
-        Ok(None)
-    }
-}
-
-impl KeyboardEventListener {
-    fn lookup_key_code(&self, key_code: &KeyCode) -> Option<&UserCommand> {
-        self.key_map.get(key_code)
-    }
-    fn translate(&self, key_event: KeyEvent) -> Option<InputEvent> {
-        self.lookup_key_code(&key_event.code)
-            .copied()
-            .map(|action_kind| InputEvent {
-                user_command: action_kind,
-                event_kind: key_event.kind,
-            })
-    }
-    fn poll_next(&self) -> io::Result<Option<KeyEvent>> {
-        match read_event_polled(Duration::from_millis(10)) {
-            Ok(Some(Event::Key(key_event))) => Ok(Some(key_event)),
-            Ok(_) => Ok(None),
-            Err(e) => Err(e),
-        }
-    }
-}
-
-#[derive(Default)]
-pub enum EventListenerError<T> {
-    #[default]
-    NoneAvailable,
-    Send(mpsc::SendError<T>),
-    TrySend(mpsc::TrySendError<T>),
-}
-
-impl<T> From<mpsc::SendError<T>> for EventListenerError<T> {
-    fn from(v: mpsc::SendError<T>) -> Self {
-        Self::Send(v)
-    }
-}
-
-impl<T> From<mpsc::TrySendError<T>> for EventListenerError<T> {
-    fn from(v: mpsc::TrySendError<T>) -> Self {
-        Self::TrySend(v)
-    }
-}
-
-/// Listens for some type of event and maps into an [`Action`]
-pub trait EventListener {
-    /// Checks for the arrival of an event and tries to turn it
-    /// into an action
-    fn next_action(&self) -> Option<Action>;
-    // /// Tries to get the next event
-    // fn forward_incoming(
-    //     &self,
-    //     tx: &mpsc::Sender<Self::Listened>,
-    // ) -> Result<bool, mpsc::SendError<Self::Listened>> {
-    //     match self.get_next() {
-    //         Some(event) => tx.send(event).map(|_| true),
-    //         None => Ok(false),
-    //     }
-    // }
-    // fn try_handle_incoming(
-    //     &self,
-    //     tx: &mpsc::SyncSender<Self::Listened>,
-    // ) -> Result<bool, mpsc::TrySendError<Self::Listened>> {
-    //     match self.get_next() {
-    //         Some(event) => tx.try_send(event).map(|_| true),
-    //         None => Ok(false),
-    //     }
-    // }
-}
-
-pub struct EventListenerBus(Vec<Box<dyn EventListener>>);
-// impl EventListenerBus {
-// }
-
-pub struct EventBus {
-    listeners: Vec<Box<dyn EventListener + Send>>,
-    action_tx: mpsc::Sender<Action>,
-}
-
-impl EventBus {
-    pub fn new(action_tx: mpsc::Sender<Action>) -> EventBus {
-        EventBus {
-            action_tx,
-            listeners: vec![],
-        }
-    }
-    pub fn register_listener<T>(&mut self, listener: T)
-    where
-        T: EventListener + Send + 'static,
-    {
-        self.listeners.push(Box::new(listener));
-    }
-    /// Checks all listeners for new actions and sends them
-    pub fn update(&self) -> Result<(), mpsc::SendError<Action>> {
-        for action in self
-            .listeners
-            .iter()
-            .map(Box::as_ref)
-            .filter_map(EventListener::next_action)
-        {
-            self.action_tx.send(action)?;
-        }
-        Ok(())
-    }
-    pub fn next_actions(&self) -> impl Iterator<Item = Action> {
-        self.listeners
-            .iter()
-            .map(Box::as_ref)
-            .filter_map(EventListener::next_action)
-    }
-}
-
-#[derive(Copy, Clone, Debug)]
-pub enum UserCommand {
-    Compile,
-    Analyze,
-    Quit,
-    ClearLog,
-}
-
-pub enum InputActionState {
-    Press,
-    Release,
-    Repeat,
-}
-
-#[derive(Copy, Clone, Debug)]
-pub struct InputEvent {
-    user_command: UserCommand,
-    event_kind: KeyEventKind,
-}
-
-#[derive(Debug)]
-pub struct KeyboardEventListener {
-    key_map: HashMap<KeyCode, UserCommand>,
-}
-
-impl Default for KeyboardEventListener {
-    fn default() -> Self {
-        KeyboardEventListener {
-            key_map: HashMap::from([
-                (KeyCode::Enter, UserCommand::Compile),
-                (KeyCode::Char('a'), UserCommand::Analyze),
-                (KeyCode::Char('A'), UserCommand::Analyze),
-                (KeyCode::Char('q'), UserCommand::Quit),
-                (KeyCode::Char('Q'), UserCommand::Quit),
-                (KeyCode::Char('c'), UserCommand::ClearLog),
-                (KeyCode::Char('C'), UserCommand::ClearLog),
-            ]),
-        }
-    }
-}
-
-impl EventListener for KeyboardEventListener {
-    fn next_action(&self) -> Option<Action> {
-        let Ok(Some(next_key_event)) = self.poll_next() else {
-            return None;
-        };
-
-        let InputEvent {
-            user_command,
-            event_kind,
-        } = self.translate(next_key_event)?;
-
-        event_kind.is_press().then_some(match user_command {
-            UserCommand::ClearLog => Action::ClearLogPanel,
-            UserCommand::Compile => Action::CompileCartridge,
-            UserCommand::Analyze => Action::AnalyzeCartridge,
-            UserCommand::Quit => Action::Quit,
-        })
-    }
-}
-
-pub struct LogEventListener {
-    log_event_rx: mpsc::Receiver<LogEvent>,
-}
-
-impl LogEventListener {
-    pub fn new(log_event_rx: mpsc::Receiver<LogEvent>) -> LogEventListener {
-        LogEventListener { log_event_rx }
-    }
-}
-
-impl EventListener for LogEventListener {
-    fn next_action(&self) -> Option<Action> {
-        self.log_event_rx
-            .try_recv()
-            .map(Action::UpdateLogPanel)
-            .ok()
-    }
-}
-
-trait CrosstermEventHandler {
-    fn event_filter(&self) -> Box<dyn Fn(&Event) -> bool>;
-    fn handle_event(&self, event: Event);
-
-    // type MappedEvent;
-    // fn event_filter_map(&self) -> Box<dyn Fn(&Event) -> Option<Self::MappedEvent>>;
-}
-pub struct DispatchMap(HashMap<fn(&Event) -> bool, Box<dyn CrosstermEventHandler>>);
-// impl Cr
-// impl<H> CrosstermEventHandler for H where H: EventListener, <H as EventListener>::Listened: TryFrom<Event> {
-//     fn event_filter(&self) -> Box<dyn Fn(&Event) -> bool> {
-//         Box::new(|event| {
-//             <Self as EventListener>::Listened::try_from(event.clone()).is_ok()
-//         })
-//     }
-//     fn consume_event(&self, event: Event) {
-//         if let Ok(specific_event) = <Self as EventListener>::Listened::try_from(event) {
-//             self.forward_incoming(tx)
-//         }
-//     }
-// }
-
-pub struct InputEventHandler {
-    listener: KeyboardEventListener,
-    action_tx: mpsc::Sender<Action>,
-}
-
-// impl InputEventHandler {
-
-// }
-
-// impl CrosstermEventHandler for InputEventHandler {
-//     fn event_filter(&self) -> Box<dyn Fn(&Event) -> bool> {
-//         Box::new(|event| matches!(event, &Event::Key(_)))
-//     }
-//     fn handle_event(&self, event: Event) {
-//         let Event::Key(key_event) =
-//     }
-// }
-
-#[derive(Debug)]
-pub struct CrosstermEventDispatcher {
-    input_event_listener: KeyboardEventListener,
-    input_event_tx: mpsc::Sender<InputEvent>,
-}
-
-// trait EventDispatcher {}
-
-#[derive(Debug)]
-pub struct Dispatcher {
-    action_tx: mpsc::Sender<Action>,
-
-    input_event_rx: mpsc::Receiver<InputEvent>,
-}
-
-impl Dispatcher {
-    pub fn update(&self) -> Result<(), mpsc::SendError<Action>> {
-        if let Ok(InputEvent {
-            user_command: action,
-            event_kind,
-        }) = self.input_event_rx.try_recv()
-        {
-            let action = match action {
-                UserCommand::Analyze => todo!("analyze action"),
-                UserCommand::ClearLog => todo!("clear log action"),
-                UserCommand::Quit => todo!("quit action"),
-                UserCommand::Compile => todo!("compile action"),
-            };
-            todo!()
-        }
-        todo!()
-    }
-}
-
-// impl CrosstermEventDispatcher {
-//     pub fn new(input_event_tx: mpsc::Sender<InputEvent>) -> CrosstermEventDispatcher {
-
-//     }
-//     fn dispatch_event(&self, event: Event) {
-//         match event {
-//             Event::Key(key_event) => {
-//                 self.input_event_listener
-//                     .forward_incoming(&self.input_event_tx);
-//             }
-//             _ => {}
-//         }
-//     }
-// }
-
-#[tracing::instrument(level = "trace", skip(log_panel_store, file_loading_tracker))]
-fn update(
-    Model {
-        log_panel_store,
-        running_state,
-        file_loading_tracker,
-        ..
-    }: &mut Model,
-    message: Message,
-) -> Option<Message> {
-    match message {
-        Message::Input(input_message) => match input_message {
-            InputMessage::ClearLog => {
-                log_panel_store.clear();
-                None
-            }
-
-            InputMessage::Compile { src_dir, cart_path } => {
-                tracing::info!("Writing to cart-path {cart_path:?}");
-                let source_files = match pico_build_rs::get_lua_files(src_dir.as_path()) {
-                    Ok(files) => files.inspect(|entry| {
-                        let path = entry.path();
-                        let name = path
-                            .file_name()
-                            .map(|file_name| file_name.to_string_lossy().into_owned())
-                            .unwrap_or_default();
-                        file_loading_tracker
-                            .paths
-                            .insert(name, FileLoadingState::Opened(path));
-                    }),
-                    Err(e) => {
-                        tracing::error!("Failed to get lua files {e}");
-                        return None;
-                    }
-                }
-                .filter_map(|source_entry| {
-                    FileData::try_from(source_entry)
-                        .map_err(pico_build_rs::FileDataError::Io)
-                        .inspect_err(|e| tracing::error!("Failed to convert source-entry: {e:?}"))
-                        .and_then(FileData::into_loaded_or_default)
-                        .ok()
-                });
-                match FileData::new(cart_path.as_path())
-                    .into_loaded_or_default()
-                    .and_then(|cart_file| {
-                        pico_build_rs::compile_cartridge(cart_file, source_files)
-                            .map_err(Into::into)
-                    }) {
-                    Ok(cart) => {
-                        tracing::info!("Got cart-data");
-                        Some(Message::CompilationOutput {
-                            compiled_data: Box::new(cart),
-                            cart_path,
-                        })
-                    }
-                    Err(e) => {
-                        tracing::error!("Failed to compile {e:?}");
-                        None
-                    }
-                }
-            }
-            InputMessage::Analyze { cart_path } => {
-                tracing::info!("TODO: implement analyze ({cart_path:?})");
-                None
-            }
-            InputMessage::Quit => {
-                *running_state = RunningState::Done;
-                None
-            }
-        },
-        Message::AnalysisOutput { analyzed_data } => todo!("implement analysis-output widget"),
-        Message::CompilationOutput {
-            compiled_data: cart,
-            cart_path,
-        } => {
-            // TODO: Add something here
-            // Some(Message::IncomingLogLine("Compilation successful!".into()))
-            tracing::info!("Compilation successful");
-            let Ok(mut file) = fs::OpenOptions::new()
-                .truncate(true)
-                .write(true)
-                .open(cart_path)
-            else {
-                tracing::info!("Failed to open output cart");
-                return None;
-            };
-            let buf: Vec<u8> = cart.into_cart_source();
-            tracing::debug!("Writing {} bytes to cart", buf.len());
-            match io::Write::write_all(&mut file, buf.as_slice()) {
-                Ok(_) => {
-                    tracing::info!("Successfully wrote to cart");
-                    None
-                }
-                Err(e) => {
-                    tracing::error!("Failed to write to cart: {e}");
-                    None
-                }
-            }
-        }
-
-        Message::LogCleared => todo!("log cleared"),
-        Message::LogUpdated => todo!("log updated"),
-        // Message::IncomingLogLine(log_message) => {
-        //     log_messages.overwrite(log_message);
-        //     None
-        //     // log_messages.push_front(log_message);
-        //     // log_messages
-        //     //     .len()
-        //     //     .ge(log_message_capacity)
-        //     //     .then_some(Message::Reset)
-        // }
-    }
-}
-fn view(
-    Model {
-        log_panel_store: log_messages,
-        file_loading_tracker,
-        ..
-    }: &Model,
-    frame: &mut Frame,
-) {
-    use ratatui::widgets::{Block, Borders, List};
-
-    let chunks = get_ui_rects(frame, log_messages.len());
-
-    frame.render_widget(Block::new().title("main").borders(Borders::ALL), chunks[0]);
-
-    let file_loading_list = List::from_iter(file_loading_tracker.paths.iter().map(
-        |(cartridge_name, state)| {
-            Text::styled(
-                format!("{cartridge_name}: {state:?}\n"),
-                Style::new().italic(),
-            )
-            .centered()
-        },
-    ));
-
-    frame.render_widget(file_loading_list, chunks[0]);
-
-    let log_panel_chunk = chunks[1];
-    frame.render_widget(ratatui::widgets::Clear, log_panel_chunk);
-    // use crossterm::terminal::{Clear, ClearType};
-
-    // use crossterm::terminal::{SetSize, size};
-    // let cmd = size().map(|(x, y)| SetSize(x, y)).expect("size");
-    // crossterm::execute!(io::stdout(), cmd).expect("failed cmd");
-    // // crossterm::execute!(io::stdout(), Clear(ClearType::Purge)).expect("failed purge");
-    let widget = LogPanelWidget::from_iter(
-        log_messages
-            .iter()
-            .take(log_panel_chunk.height as usize)
-            .cloned(),
-    );
-    frame.render_widget(widget, log_panel_chunk);
-}
+use core::cell::Cell;
+use core::ops::Deref;
+use core::time::Duration;
+
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path;
+use std::sync::atomic::{self, AtomicBool};
+use std::sync::{Arc, mpsc};
+
+use anyhow::anyhow;
+use clap::Parser;
+use pico_8_cart_builder::{
+    CartBuilder, Constants, IncludeResolver, SourceExclude, SourceOrder, TabOrder, TabOverflow,
+};
+use pico_8_cart_model::embed::SpriteEmbedding;
+use pico_8_cart_model::{CartData, GfxRows};
+use pico_build_rs::Fifo;
+use pico_build_rs::assets::AssetImport;
+use pico_build_rs::label::LabelFallback;
+use ratatui::prelude::*;
+
+mod analysis_panel;
+mod args;
+#[cfg(feature = "network")]
+mod bbs;
+mod bench;
+mod capture;
+mod config;
+mod dashboard;
+mod diff_panel;
+mod diff_view;
+mod event_bus;
+mod keys;
+mod log_file;
+mod log_panel;
+mod notes;
+mod pico_runner;
+mod replay;
+mod scaffold;
+#[cfg(feature = "scripting")]
+mod script;
+mod stat_panel;
+mod tasks;
+mod text;
+mod theme;
+mod todo_panel;
+mod watch;
+mod workspace;
+
+use analysis_panel::{AnalysisWidget, AnalyzedCart, FindingSelection};
+use dashboard::{BuildReport, BuildStatusStore, DashboardWidget};
+use diff_panel::{DiffPanelStore, DiffPanelWidget};
+use event_bus::{EventBus, EventListener, ListenerSchedule, Polling};
+use log_panel::{LogPanelAction, LogPanelScroll, LogPanelStore, LogPanelWidget};
+use notes::{NoteEdit, NotesStore, NotesWidget};
+use pico_runner::{PicoRunner, PicoState};
+use stat_panel::{StatPanelWidget, StatTail};
+use tasks::{TaskFailure, TaskSupervisor};
+use todo_panel::{TodoPanelStore, TodoPanelWidget};
+use workspace::ProjectStore;
+
+pub trait StoreUpdate {
+    type Action;
+
+    fn update(&mut self, action: Self::Action) -> Option<Message>;
+}
+
+use pico_build_rs::merge::MergeStrategy;
+use pico_build_rs::minify::Minification;
+use pico_build_rs::paths;
+use pico_build_rs::provenance::{self, BuildManifest};
+use pico_build_rs::source_map::{self, SourceMap};
+use pico_build_rs::strings::{Catalog, CatalogError, Localization};
+use pico_build_rs::transform::{
+    BuildProfile, TransformSummary, collect_constants, transform_source_file,
+};
+use pico_build_rs::verify::Verdict;
+use pico_build_rs::{FileData, LoadPolicy};
+
+/// The size of the log-panel in log-lines
+const LOG_LINE_COUNT: usize = 20;
+
+#[derive(Debug)]
+pub enum Action {
+    ClearLogPanel,
+    /// A lua source-file was saved, rebuilding the cart
+    SourceChanged {
+        /// Measures the save-to-cart-written latency from here
+        changed: std::time::Instant,
+    },
+    CompileCartridge,
+    SaveCompiledCartridge {
+        cartridge_data: Box<CartData<'static>>,
+        /// Where each tab and section came from, written next to the cart
+        manifest: BuildManifest,
+        /// Where each line of the compiled tabs came from, written next to the cart
+        source_map: SourceMap,
+        build_report: Box<BuildReport>,
+        /// Relaunches pico-8 with the saved cart, see [`Action::RestartPico`]
+        restart_pico: bool,
+    },
+    /// Rebuilds the cart, and relaunches pico-8 with it once saved
+    RestartPico,
+    /// Opens the next project of the workspace
+    SwitchProject,
+    /// Shows the last rebuild in the next view, see [`diff_view::DiffView`]
+    CycleDiffView,
+    ScrollLogPanel(LogPanelScroll),
+    AnalyzeCartridge,
+    DisplayAnalyzedCartridge {
+        cartridge_data: Box<CartData<'static>>,
+    },
+    SelectFinding(FindingSelection),
+    /// Opens the source-file of the selected finding at its line with `$EDITOR`, see
+    /// [`RunningState::OpeningFinding`]
+    OpenFinding,
+    ScanTodos,
+    /// Edits the entry captured for the notes, see [`notes`]
+    EditNote(NoteEdit),
+    /// Saves the notes to edit them with `$EDITOR`, see [`RunningState::EditingNotes`]
+    EditNotes,
+    DismissTaskFailures,
+    Quit,
+}
+
+pub struct ActionContext<'a> {
+    log_panel_store: &'a mut LogPanelStore,
+    todo_panel_store: &'a mut TodoPanelStore,
+    notes_store: &'a mut NotesStore,
+    running_state: &'a mut RunningState,
+    task_failures: &'a mut Vec<TaskFailure>,
+    project_source_file_path: &'a path::Path,
+    project_source_directory_path: &'a path::Path,
+    /// The directory of the project, which logged paths are relative to
+    project_root_directory_path: &'a path::Path,
+    build_profile: BuildProfile,
+    debug_calls: &'a [String],
+    tab_order: &'a TabOrder,
+    gfx_rows: GfxRows,
+    embed_sprites: Option<&'a SpriteEmbedding>,
+    assets: Option<&'a AssetImport>,
+    label_fallback: &'a LabelFallback,
+    /// The language whose string-catalog is read on each build
+    localization: Option<&'a Localization>,
+    minify: Option<&'a Minification>,
+    /// The constants of the config-file, with which those of the source-files are defined
+    constants: &'a Constants,
+    /// The cart-version the code is linted for, instead of the version of the cart
+    cart_version: Option<usize>,
+    /// Removes the top-level functions never called
+    remove_dead_code: bool,
+    include_resolver: &'a IncludeResolver,
+    exclude: &'a SourceExclude,
+    merge_strategy: MergeStrategy,
+    tab_overflow: TabOverflow,
+    /// The backups kept of the cart each save replaces
+    cart_backups: usize,
+    build_status: &'a mut BuildStatusStore,
+    pico_runner: &'a mut Option<PicoRunner>,
+    project_store: &'a mut Option<ProjectStore>,
+    analysis: &'a mut Option<AnalyzedCart>,
+    diff_panel_store: &'a mut DiffPanelStore,
+}
+
+impl Action {
+    /// The name of the action, as recorded by [`replay`] and reported by `bench`
+    pub fn name(&self) -> &'static str {
+        match self {
+            Action::ClearLogPanel => "clear-log",
+            Action::SourceChanged { .. } => "source-changed",
+            Action::CompileCartridge => "compile",
+            Action::SaveCompiledCartridge { .. } => "save-compiled-cartridge",
+            Action::RestartPico => "restart-pico",
+            Action::SwitchProject => "switch-project",
+            Action::CycleDiffView => "cycle-diff-view",
+            Action::ScrollLogPanel(LogPanelScroll::PageUp) => "scroll-log-page-up",
+            Action::ScrollLogPanel(LogPanelScroll::PageDown) => "scroll-log-page-down",
+            Action::ScrollLogPanel(LogPanelScroll::Top) => "scroll-log-top",
+            Action::ScrollLogPanel(LogPanelScroll::Bottom) => "scroll-log-bottom",
+            Action::AnalyzeCartridge => "analyze",
+            Action::DisplayAnalyzedCartridge { .. } => "display-analyzed-cartridge",
+            Action::SelectFinding(FindingSelection::Previous) => "select-previous-finding",
+            Action::SelectFinding(FindingSelection::Next) => "select-next-finding",
+            Action::OpenFinding => "open-finding",
+            Action::ScanTodos => "scan-todos",
+            Action::EditNote(_) => "edit-note",
+            Action::EditNotes => "edit-notes",
+            Action::DismissTaskFailures => "dismiss-task-failures",
+            Action::Quit => "quit",
+        }
+    }
+    /// Invokes the action, and if a follow-up is needed,
+    /// returns the next step
+    #[tracing::instrument(level = "trace", ret)]
+    pub fn invoke(
+        self,
+        ActionContext {
+            log_panel_store,
+            todo_panel_store,
+            notes_store,
+            running_state,
+            task_failures,
+            project_source_file_path,
+            project_source_directory_path,
+            project_root_directory_path,
+            build_profile,
+            debug_calls,
+            tab_order,
+            gfx_rows,
+            embed_sprites,
+            assets,
+            label_fallback,
+            localization,
+            minify,
+            constants,
+            cart_version,
+            remove_dead_code,
+            include_resolver,
+            exclude,
+            merge_strategy,
+            tab_overflow,
+            cart_backups,
+            build_status,
+            pico_runner,
+            project_store,
+            analysis,
+            diff_panel_store,
+        }: ActionContext<'_>,
+    ) -> Option<Action> {
+        match self {
+            Action::ClearLogPanel => {
+                log_panel_store.clear();
+                // TODO: Here we maybe wanna return a clear or redraw terminal action?
+                None
+            }
+            Action::SourceChanged { changed } => {
+                build_status.source_changed(changed);
+                Some(Action::CompileCartridge)
+            }
+            Action::CompileCartridge => {
+                build_status.start();
+                let restart_pico = pico_runner
+                    .as_mut()
+                    .is_some_and(PicoRunner::take_pending_restart);
+                tracing::info!(
+                    "Writing to cart-path {}",
+                    paths::relative_to(project_source_file_path, project_root_directory_path)
+                );
+                let tab_order = match tab_order.source_order() {
+                    SourceOrder::Manifest => tab_order.clone().with_manifest_order(
+                        fs::read_to_string(provenance::manifest_path(project_source_file_path))
+                            .ok()
+                            .and_then(|text| BuildManifest::parse(&text).ok())
+                            .map(|manifest| manifest.tab_file_names())
+                            .unwrap_or_default(),
+                    ),
+                    _ => tab_order.clone(),
+                };
+                let cart_builder = CartBuilder::new(project_source_directory_path)
+                    .with_tab_order(tab_order)
+                    .with_include_resolver(include_resolver.clone())
+                    .with_exclude(exclude.clone())
+                    .with_tab_overflow(tab_overflow);
+                let source_files = match cart_builder.lua_files() {
+                    Ok(files) => {
+                        let file_names: Vec<_> =
+                            files.iter().map(fs::DirEntry::file_name).collect();
+                        if let Err(e) = cart_builder
+                            .tab_overflow()
+                            .check(&file_names, merge_strategy.first_compiled_tab())
+                        {
+                            tracing::error!("{}: Failed to compile: {e}", e.code());
+                            return None;
+                        }
+                        files.into_iter()
+                    }
+                    // files.inspect(|entry| {
+                    //     let path = entry.path();
+                    //     let name = path
+                    //         .file_name()
+                    //         .map(|file_name| file_name.to_string_lossy().into_owned())
+                    //         .unwrap_or_default();
+                    //     file_loading_tracker
+                    //         .paths
+                    //         .insert(name, FileLoadingState::Opened(path));
+                    // }),
+                    Err(e) => {
+                        tracing::error!("Failed to get lua files {e}");
+                        return None;
+                    }
+                }
+                .filter_map(|source_entry| {
+                    FileData::try_from(source_entry)
+                        .map_err(pico_build_rs::FileDataError::Io)
+                        .inspect_err(|e| tracing::error!("Failed to convert source-entry: {e:?}"))
+                        .and_then(|source_file| {
+                            source_file.into_loaded_or_default(LoadPolicy::ReadOnly)
+                        })
+                        .ok()
+                });
+                let (source_files, tab_origins): (Vec<FileData<Box<[u8]>>>, Vec<_>) =
+                    match source_files
+                        .map(|source_file| {
+                            pico_build_rs::resolve_source_includes_with_origins(
+                                source_file,
+                                cart_builder.include_resolver(),
+                            )
+                        })
+                        .collect::<Result<Vec<_>, _>>()
+                    {
+                        Ok(source_files) => source_files.into_iter().unzip(),
+                        Err(e) => {
+                            tracing::error!("{}: Failed to compile: {e}", e.code());
+                            return None;
+                        }
+                    };
+                let catalog = match localization {
+                    Some(localization) => {
+                        let catalog_path = localization.catalog_path();
+                        let shown_path =
+                            paths::relative_to(&catalog_path, project_root_directory_path);
+                        match Catalog::read(&catalog_path) {
+                            Ok(catalog) => {
+                                for (key, warning) in catalog.warnings() {
+                                    tracing::warn!("W002: {shown_path}: \"{key}\": {warning}");
+                                }
+                                Some(catalog)
+                            }
+                            Err(e) => {
+                                tracing::error!("{}: {shown_path}: {e}", e.code());
+                                return None;
+                            }
+                        }
+                    }
+                    None => None,
+                };
+                let constants = match collect_constants(constants, &source_files) {
+                    Ok(constants) => constants,
+                    Err(e) => {
+                        tracing::error!("{}: Failed to compile: {e}", e.code());
+                        return None;
+                    }
+                };
+                let mut summary = TransformSummary::default();
+                let mut tab_sources = Vec::new();
+                let source_files: Vec<FileData<Box<[u8]>>> = source_files
+                    .into_iter()
+                    .map(|source_file| {
+                        let (source_file, file_summary) = transform_source_file(
+                            source_file,
+                            build_profile,
+                            debug_calls,
+                            &constants,
+                            catalog.as_ref(),
+                        );
+                        summary += file_summary;
+                        let source_path = source_file.as_path();
+                        tab_sources.push((
+                            source_path
+                                .strip_prefix(project_source_directory_path)
+                                .unwrap_or(source_path)
+                                .to_path_buf(),
+                            file_summary.applied_transforms(build_profile),
+                        ));
+                        source_file
+                    })
+                    .collect();
+                tracing::info!(
+                    "Compiling with the {build_profile:?}-profile: {} assertions, {} debug-calls stripped",
+                    summary.assertions,
+                    summary.stripped_calls
+                );
+                if !constants.is_empty() {
+                    tracing::info!(
+                        "Substituted {} constants, with {} substitutions",
+                        constants.len(),
+                        summary.constants
+                    );
+                }
+                if let Some(localization) = localization {
+                    tracing::info!(
+                        "Localized {} strings into {:?}",
+                        summary.localized_strings,
+                        localization.language
+                    );
+                }
+                match FileData::new(project_source_file_path)
+                    .into_loaded_or_default(LoadPolicy::ReadOnly)
+                    .and_then(|cart_file| {
+                        pico_build_rs::compile_cartridge(
+                            cart_file,
+                            source_files.into_iter(),
+                            merge_strategy,
+                        )
+                        .map_err(Into::into)
+                    }) {
+                    Ok(mut cart) => {
+                        tracing::info!("Got cart-data");
+                        // Before embedding, so the imported sprites can be embedded
+                        if let Some(assets) = assets {
+                            match assets.import_into(&mut cart) {
+                                Ok(imports) => {
+                                    for (png_path, import) in imports {
+                                        tracing::info!(
+                                            "Imported {}: {import}",
+                                            paths::relative_to(
+                                                &png_path,
+                                                project_root_directory_path
+                                            )
+                                        );
+                                    }
+                                }
+                                Err(e) => {
+                                    tracing::error!("{}: Failed to import assets: {e}", e.code());
+                                    return None;
+                                }
+                            }
+                        }
+                        // After the import, so a label of the assets is kept
+                        match label_fallback.generate_into(&mut cart) {
+                            Ok(Some(source)) => {
+                                tracing::info!("Generated the blank label from {source}")
+                            }
+                            Ok(None) => {}
+                            Err(e) => {
+                                tracing::warn!("{}: Failed to generate the label: {e}", e.code())
+                            }
+                        }
+                        let mut embedded_sprites = None;
+                        if let Some(embed_sprites) = embed_sprites {
+                            match cart.embed_sprites(embed_sprites) {
+                                Ok(embed_report) => {
+                                    tracing::info!("{embed_report}");
+                                    embedded_sprites = Some(embed_report);
+                                }
+                                Err(e) => {
+                                    tracing::error!("{}: Failed to embed sprites: {e}", e.code());
+                                    return None;
+                                }
+                            }
+                        }
+                        if let Err(e) = cart.set_gfx_rows(gfx_rows) {
+                            tracing::error!("{}: Failed to re-encode gfx-section: {e}", e.code());
+                            return None;
+                        }
+                        if let Err(e) = pico_build_rs::syntax::validate_cart(&cart) {
+                            let source_path = e
+                                .tab_index
+                                .checked_sub(merge_strategy.first_compiled_tab())
+                                .and_then(|index| tab_sources.get(index))
+                                .map(|(source_path, _)| paths::display(source_path).to_string());
+                            match source_path {
+                                Some(source_path) => {
+                                    tracing::error!("{}: {e} ({source_path})", e.code())
+                                }
+                                None => tracing::error!("{}: {e}", e.code()),
+                            }
+                            tracing::error!("Not saving the cart, pico-8 would fail to load it");
+                            return None;
+                        }
+                        // After validating, so a syntax-error is reported as such
+                        let mut pruned_tabs = Vec::new();
+                        if remove_dead_code {
+                            match pico_build_rs::dead_code::eliminate_dead_code(
+                                cart.code_tabs(),
+                                false,
+                            ) {
+                                Ok((code_tabs, dead_functions)) => {
+                                    for dead_function in &dead_functions {
+                                        tracing::info!("Removed unused function {dead_function}");
+                                        pruned_tabs.push(dead_function.tab_index);
+                                    }
+                                    if let Err(e) = cart.try_set_code_data(code_tabs) {
+                                        tracing::error!(
+                                            "{}: Failed to remove dead code: {e}",
+                                            e.code()
+                                        );
+                                        return None;
+                                    }
+                                }
+                                Err(e) => {
+                                    tracing::error!(
+                                        "{}: Failed to remove dead code: {e}",
+                                        e.code()
+                                    );
+                                    return None;
+                                }
+                            }
+                        }
+                        for (tab_index, token_count) in cart.tab_token_counts().iter().enumerate() {
+                            if let Some(token_count) = token_count {
+                                tracing::info!("Tab {tab_index}: {token_count} tokens");
+                            }
+                        }
+                        match cart.check_token_limit() {
+                            Ok(token_count) => tracing::info!(
+                                "{token_count}/{} tokens",
+                                cart.format_spec().token_limit
+                            ),
+                            Err(e) => {
+                                tracing::error!("{}: Failed to compile: {e}", e.code());
+                                return None;
+                            }
+                        }
+                        for pico_build_rs::lint::Finding {
+                            rule,
+                            tab_index,
+                            line_number,
+                            line,
+                        } in pico_build_rs::lint::lint_cart(
+                            &cart,
+                            pico_build_rs::lint::CORRECTNESS_TAG,
+                            cart_version,
+                        ) {
+                            tracing::warn!(
+                                "{}: Tab {tab_index}, line {line_number}: {}: {}",
+                                rule.code,
+                                rule.description,
+                                line.trim()
+                            );
+                        }
+                        let first_compiled_tab = merge_strategy.first_compiled_tab();
+                        // After the checks, so they report the lines as compiled
+                        let mut minified_tabs = Vec::new();
+                        if let Some(minify) = minify {
+                            let tab_indices: Vec<usize> = tab_sources
+                                .iter()
+                                .enumerate()
+                                .filter(|(_, (source_path, _))| minify.minifies(source_path))
+                                .map(|(index, _)| first_compiled_tab + index)
+                                .collect();
+                            match pico_build_rs::minify::minify_code_tabs(
+                                cart.code_tabs(),
+                                &tab_indices,
+                                minify.rename_locals,
+                            ) {
+                                Ok((code_tabs, minify_report)) => {
+                                    tracing::info!("{minify_report}");
+                                    if let Err(e) = cart.try_set_code_data(code_tabs) {
+                                        tracing::error!("{}: Failed to minify: {e}", e.code());
+                                        return None;
+                                    }
+                                    minified_tabs = minify_report.tab_indices;
+                                }
+                                Err(e) => {
+                                    tracing::error!("{}: Failed to minify: {e}", e.code());
+                                    return None;
+                                }
+                            }
+                        }
+                        let mut manifest = BuildManifest::default();
+                        if let Some(main_tab) = cart.code_tabs()[..first_compiled_tab]
+                            .iter()
+                            .flatten()
+                            .next()
+                        {
+                            manifest.record_cart_tab(
+                                0,
+                                project_source_file_path,
+                                &main_tab.code_data,
+                            );
+                        }
+                        for (index, (source_path, mut transforms)) in
+                            tab_sources.into_iter().enumerate()
+                        {
+                            let tab_index = first_compiled_tab + index;
+                            if pruned_tabs.contains(&tab_index) {
+                                transforms.push("dead-code");
+                            }
+                            if minified_tabs.contains(&tab_index) {
+                                transforms.push("minify");
+                            }
+                            let Some(code_tab) = cart.code_tabs().get(tab_index) else {
+                                break;
+                            };
+                            let code_data = code_tab
+                                .as_ref()
+                                .map(|tab| tab.code_data.as_ref())
+                                .unwrap_or_default();
+                            manifest.record_tab(tab_index, source_path, &transforms, code_data);
+                        }
+                        manifest.record_cart_sections(project_source_file_path, &cart);
+                        let mut source_map = SourceMap::default();
+                        let cart_directory_path = project_source_file_path
+                            .parent()
+                            .unwrap_or(path::Path::new("."));
+                        for (index, origins) in tab_origins.into_iter().enumerate() {
+                            let tab_index = first_compiled_tab + index;
+                            let Some(Some(code_tab)) = cart.code_tabs().get(tab_index) else {
+                                break;
+                            };
+                            // The lines of a minified tab are joined, those of a pruned one
+                            // no longer line up with the source-file
+                            if minified_tabs.contains(&tab_index)
+                                || pruned_tabs.contains(&tab_index)
+                            {
+                                continue;
+                            }
+                            let tab_lines = code_tab
+                                .code_data
+                                .split_inclusive(|byte| *byte == b'\n')
+                                .count();
+                            // One more for the title-line, any other difference comes from
+                            // merging kept regions, whose lines can not be traced
+                            match tab_lines.checked_sub(origins.len()) {
+                                Some(title_lines @ (0 | 1)) => source_map.record_tab(
+                                    tab_index,
+                                    1 + title_lines,
+                                    &origins,
+                                    cart_directory_path,
+                                ),
+                                _ => tracing::debug!("Not mapping the lines of tab {tab_index}"),
+                            }
+                        }
+                        let build_report = BuildReport {
+                            finished: std::time::Instant::now(),
+                            tab_count: cart.code_tabs().len(),
+                            token_count: cart.token_count(),
+                            compressed_size: cart
+                                .compressed_code_size()
+                                .inspect_err(|e| {
+                                    tracing::warn!("{}: Failed to compress code: {e}", e.code())
+                                })
+                                .ok(),
+                            embedded_sprites,
+                            bytes_written: 0,
+                        };
+                        Some(Action::SaveCompiledCartridge {
+                            cartridge_data: Box::new(cart),
+                            manifest,
+                            source_map,
+                            build_report: Box::new(build_report),
+                            restart_pico,
+                        })
+                    }
+                    Err(e) => {
+                        tracing::error!("Failed to compile {e:?}");
+                        None
+                    }
+                }
+            }
+            Action::SaveCompiledCartridge {
+                cartridge_data,
+                manifest,
+                source_map,
+                build_report,
+                restart_pico,
+            } => {
+                let manifest_path = provenance::manifest_path(project_source_file_path);
+                if let Err(e) = fs::write(&manifest_path, manifest.to_text()) {
+                    tracing::error!(
+                        "Failed to write manifest to {}: {e}",
+                        paths::relative_to(&manifest_path, project_root_directory_path)
+                    );
+                }
+                let source_map_path = source_map::source_map_path(project_source_file_path);
+                if let Err(e) = fs::write(&source_map_path, source_map.to_json()) {
+                    tracing::error!(
+                        "Failed to write source-map to {}: {e}",
+                        paths::relative_to(&source_map_path, project_root_directory_path)
+                    );
+                }
+                // Best-effort, a missing or unparsable cart is replaced without a diff
+                if let Ok(old_cart_source) = fs::read(project_source_file_path)
+                    && let Ok(old_cart) = CartData::from_cart_source(&old_cart_source)
+                {
+                    let diff = old_cart.diff(&cartridge_data);
+                    tracing::info!(
+                        "The rebuild changes {} tabs and {} sections",
+                        diff.tabs.len(),
+                        diff.sections.len()
+                    );
+                    diff_panel_store.record(&diff, *log_panel_store.palette());
+                }
+                tracing::info!("Saving compiled cartridge");
+                match pico_build_rs::cart_write::write_cart_with(
+                    project_source_file_path,
+                    cart_backups,
+                    |w| cartridge_data.write_to(w),
+                ) {
+                    Ok(size) => {
+                        tracing::info!("Successfully wrote to cart (size: {size})");
+                        build_status.finish(BuildReport {
+                            bytes_written: size as usize,
+                            ..*build_report
+                        });
+                        if restart_pico
+                            && let Some(pico_runner) = pico_runner.as_mut()
+                            && let Err(e) = pico_runner.launch(project_source_file_path)
+                        {
+                            tracing::error!("Failed to relaunch pico-8: {e}");
+                        }
+                        // Stale once the cart is rewritten, making room for the diff
+                        *analysis = None;
+                        None
+                    }
+                    Err(e) => {
+                        tracing::error!("{}: {e}", e.code());
+                        build_status.fail(match e {
+                            pico_build_rs::cart_write::CartWriteError::Locked { .. } => {
+                                "the cart is open in pico-8"
+                            }
+                            pico_build_rs::cart_write::CartWriteError::Io { .. } => {
+                                "could not write the cart"
+                            }
+                        });
+                        None
+                    }
+                }
+            }
+            Action::RestartPico => match pico_runner.as_mut() {
+                Some(pico_runner) => {
+                    pico_runner.request_restart();
+                    Some(Action::CompileCartridge)
+                }
+                None => {
+                    tracing::warn!("No pico-8 executable is configured");
+                    None
+                }
+            },
+            Action::SwitchProject => {
+                match project_store.as_mut() {
+                    Some(project_store) => {
+                        tracing::info!("Switching to {}", project_store.select_next().name);
+                        *running_state = RunningState::SwitchingProject;
+                    }
+                    None => tracing::warn!("No workspace with other projects is open"),
+                }
+                None
+            }
+            Action::CycleDiffView => {
+                diff_panel_store.cycle_view();
+                None
+            }
+            Action::ScrollLogPanel(scroll) => {
+                log_panel_store.scroll(scroll);
+                None
+            }
+            Action::AnalyzeCartridge => {
+                let cart_file = match fs::File::open(project_source_file_path) {
+                    Ok(cart_file) => cart_file,
+                    Err(e) => {
+                        tracing::error!(
+                            "Failed to open {}: {e}",
+                            paths::relative_to(
+                                project_source_file_path,
+                                project_root_directory_path
+                            )
+                        );
+                        return None;
+                    }
+                };
+                match <CartData as pico_build_rs::FromFile>::from_file(cart_file) {
+                    Ok(cart) => Some(Action::DisplayAnalyzedCartridge {
+                        cartridge_data: Box::new(cart),
+                    }),
+                    Err(e) => {
+                        tracing::error!("{}: Failed to load the cart: {e}", e.code());
+                        None
+                    }
+                }
+            }
+            Action::DisplayAnalyzedCartridge { cartridge_data } => {
+                match pico_build_rs::analysis::analyze(&cartridge_data) {
+                    Ok(cart_analysis) => {
+                        let mut findings = Vec::new();
+                        for tag in [
+                            pico_build_rs::lint::CORRECTNESS_TAG,
+                            pico_build_rs::lint::PUBLISH_TAG,
+                        ] {
+                            for finding in
+                                pico_build_rs::lint::lint_cart(&cartridge_data, tag, cart_version)
+                            {
+                                if !findings.contains(&finding) {
+                                    findings.push(finding);
+                                }
+                            }
+                        }
+                        tracing::info!(
+                            "Analyzed the cart: {} tabs, {} tokens, {} findings",
+                            cart_analysis.tabs.len(),
+                            cart_analysis.token_count(),
+                            findings.len()
+                        );
+                        *analysis = Some(AnalyzedCart::new(cart_analysis, findings));
+                    }
+                    Err(e) => tracing::error!("{}: Failed to analyze the cart: {e}", e.code()),
+                }
+                None
+            }
+            Action::SelectFinding(selection) => {
+                if let Some(analysis) = analysis.as_mut() {
+                    analysis.select(selection);
+                }
+                None
+            }
+            Action::OpenFinding => {
+                let Some(finding) = analysis.as_ref().and_then(AnalyzedCart::selected) else {
+                    tracing::warn!("No finding is selected, analyze the cart first");
+                    return None;
+                };
+                let source_map_path =
+                    pico_build_rs::source_map::source_map_path(project_source_file_path);
+                let source_map = match pico_build_rs::source_map::SourceMap::read(&source_map_path)
+                {
+                    Ok(source_map) => source_map,
+                    Err(e) => {
+                        tracing::error!("{}: Failed to read the source-map: {e}", e.code());
+                        return None;
+                    }
+                };
+                match source_map.resolve(finding.tab_index, finding.line_number) {
+                    Some((file_path, line_number)) => {
+                        // The files are relative to the directory of the cart
+                        let cart_dir = project_source_file_path
+                            .parent()
+                            .unwrap_or(path::Path::new(""));
+                        *running_state = RunningState::OpeningFinding {
+                            file_path: cart_dir.join(file_path),
+                            line_number,
+                        };
+                    }
+                    None => tracing::warn!(
+                        "Tab {}, line {} is not mapped to a source-file, it is kept from the cart or minified",
+                        finding.tab_index,
+                        finding.line_number
+                    ),
+                }
+                None
+            }
+            Action::ScanTodos => {
+                match todo_panel_store.rescan(project_source_directory_path) {
+                    Ok(count) => tracing::info!("Found {count} todo-comments"),
+                    Err(e) => tracing::error!("Failed to scan for todo-comments: {e}"),
+                }
+                None
+            }
+            Action::EditNote(edit) => {
+                notes_store.edit(edit, &notes::timestamp(std::time::SystemTime::now()));
+                None
+            }
+            Action::EditNotes => {
+                match notes_store.save() {
+                    Ok(()) => *running_state = RunningState::EditingNotes,
+                    Err(e) => tracing::error!("Failed to save the notes: {e}"),
+                }
+                None
+            }
+            Action::DismissTaskFailures => {
+                task_failures.clear();
+                None
+            }
+            Action::Quit => {
+                *running_state = RunningState::Done;
+                None
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Store {
+    log_panel_store: log_panel::LogPanelStore,
+    workspace_store: WorkspaceStore,
+    running_state: RunningState,
+}
+
+pub enum WorkspaceStoreAction {
+    Compile,
+    Analyze,
+}
+
+#[derive(Debug)]
+struct WorkspaceStore {
+    project_file: FileData<Box<pico_8_cart_model::CartData<'static>>>,
+    source_directory: path::PathBuf,
+    source_files: Box<[FileData<Box<[u8]>>]>,
+    // running_state: RunningState,
+}
+
+impl WorkspaceStore {
+    fn new(cfg: config::AppConfiguration) -> io::Result<WorkspaceStore> {
+        let project_file = FileData::new(&cfg.cart_path());
+
+        let mut app = WorkspaceStore {
+            project_file,
+            source_directory: cfg.src_dir,
+            source_files: Box::default(),
+            // running_state: RunningState::Running,
+        };
+
+        Ok(app)
+    }
+
+    /// Discovers all source files in the configured directory
+    fn discover_source_files(&self) -> io::Result<impl Iterator<Item = FileData<Box<[u8]>>>> {
+        pico_build_rs::get_lua_files(self.source_directory.as_path())
+            .map(pico_build_rs::dir_entries_to_source_files)
+    }
+
+    /// Reads the stateful files into memory
+    fn read_source_files(&mut self) -> Result<(), pico_build_rs::FileDataError<Box<[u8]>>> {
+        for source_file in self.source_files.iter_mut() {
+            source_file.load(LoadPolicy::ReadOnly)?;
+        }
+
+        Ok(())
+    }
+
+    /// Rediscovers, but does not load source files in the configured directory
+    fn reset_source_files(&mut self) -> Result<(), pico_build_rs::FileDataError<Box<[u8]>>> {
+        let source_files = self.discover_source_files().map(Box::from_iter)?;
+        self.source_files = source_files;
+        Ok(())
+    }
+
+    /// Loads all source files in the configured directory
+    fn load_source_files(&mut self) -> Result<(), pico_build_rs::FileDataError<Box<[u8]>>> {
+        self.reset_source_files()?;
+        self.read_source_files()
+    }
+
+    fn reset_project_file(&mut self) {
+        tracing::debug!("Resetting project file");
+        self.project_file.unload();
+    }
+
+    /// Creates or loads the project-file
+    fn load_project_file(
+        &mut self,
+    ) -> Result<(), pico_build_rs::FileDataError<Box<pico_8_cart_model::CartData<'static>>>> {
+        tracing::debug!("Loading project file");
+        self.project_file.load(LoadPolicy::OpenOrCreate)
+    }
+
+    /// Compile a new cartridge based on internal state
+    ///
+    /// Expects that [`ModelV2::load_source_files`] and [`ModelV2::load_project_file`] has been
+    /// called previously. Otherwise `Err` will be returned.
+    fn compile_cartridge(&self) -> io::Result<pico_8_cart_model::CartData<'static>> {
+        if !self.project_file.is_loaded() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Project file not loaded",
+            ));
+        }
+
+        if !self.source_files.iter().all(FileData::is_loaded) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Not all source-files are loaded",
+            ));
+        }
+
+        pico_build_rs::compile_cartridge(
+            self.project_file.clone(),
+            self.source_files.iter().cloned(),
+            MergeStrategy::default(),
+        )
+    }
+}
+
+impl StoreUpdate for WorkspaceStore {
+    type Action = WorkspaceStoreAction;
+    fn update(&mut self, action: Self::Action) -> Option<Message> {
+        match action {
+            WorkspaceStoreAction::Compile => todo!(),
+            WorkspaceStoreAction::Analyze => todo!(),
+        }
+    }
+}
+
+impl Store {
+    pub fn update(&mut self, action: Action) {
+        todo!()
+    }
+}
+// impl StoreUpdate for Store {
+//     type Action = Action;
+//     fn update(&mut self, action: Self::Action) -> Option<Message> {
+//         match action {
+//             Action::LogPanel(log_panel_action) => self.log_panel_store.update(log_panel_action),
+//             Action::Quit => ,
+//         }
+//     }
+// }
+
+#[derive(Debug)]
+struct ModelV2 {
+    workspace: WorkspaceStore,
+    running_state: RunningState,
+    log_messages: Fifo<Line<'static>>,
+
+    action_rx: mpsc::Receiver<Action>,
+}
+
+impl ModelV2 {
+    // /// Checks for incoming actions and handles them accordingly
+    // pub fn update(&self) -> Result<(), mpsc::TryRecvError> {
+    //     'drain: loop {
+    //         let Ok(action) = self.action_rx.try_recv() {
+    //             match action {
+    //                 Action::LogPanel(LogPanelAction::HandleIncoming(log_event)) =>
+    //             }
+    //         }
+    //     }
+    //     todo!()
+    // }
+}
+
+#[tracing::instrument(level = "info", ret)]
+fn main() -> anyhow::Result<()> {
+    use crate::args::AppArgs;
+    use crate::config::AppConfiguration;
+
+    let mut args = AppArgs::parse();
+    args.read_stdin_config()?;
+
+    if let Some(replay_path) = args.replay.as_deref() {
+        tracing_subscriber::fmt()
+            .with_writer(io::stderr)
+            .with_max_level(tracing::Level::WARN)
+            .init();
+        return replay_headless(&AppConfiguration::new(&args)?, replay_path);
+    }
+
+    if let Some(command) = args.command.as_ref() {
+        // Without the log-panel, warnings and errors go straight to stderr
+        tracing_subscriber::fmt()
+            .with_writer(io::stderr)
+            .with_max_level(tracing::Level::WARN)
+            .init();
+        // Explaining a code does not need a project to be configured
+        match command {
+            args::Command::Explain { code } => return explain(code),
+            args::Command::Diff { old, new, view } => return diff(old, new, *view),
+            args::Command::Verify { dir } => return verify(dir),
+            args::Command::Init { name } => {
+                let project_dir = scaffold::init(&args.get_root_directory()?, name)?;
+                println!("Created {}", paths::display(&project_dir));
+                return Ok(());
+            }
+            args::Command::Build { all: true } | args::Command::Preflight { all: true, .. } => {
+                return workspace::run_for_each_member(&args, |cfg| run_command(command, cfg));
+            }
+            _ => {}
+        }
+        let cfg = AppConfiguration::new(&args)?;
+        return run_command(command, &cfg);
+    }
+
+    let (log_events, log_file) = log_panel::setup_tracing_subscriber();
+
+    let cfg = AppConfiguration::new(&args)?;
+    log_file.open(&cfg.root_dir, cfg.log_file.as_ref());
+    tracing::info!("parsed app configuration");
+    if cfg.safe {
+        tracing::warn!("Safe mode: external programs and scripts are not run");
+    }
+
+    tracing::trace!("{cfg:#?}");
+    tracing::info!("source directory is {}", cfg.display_path(&cfg.src_dir));
+    let cart_path = cfg.cart_path();
+    tracing::info!("cart path is {}", cfg.display_path(&cart_path));
+    if let Some(root_path) = cfg.pico_folders().root_path {
+        tracing::info!("pico-8 carts folder is {}", paths::display(&root_path));
+    }
+    let mut terminal = ratatui::init();
+    let log_panel_store = LogPanelStore::with_events(cfg.theme, log_events);
+    tracing::info!("log-messages length: {}", log_panel_store.event_count());
+    // let log_panel_chunk = get_ui_rects(&mut terminal.get_frame(), log_messages.len())[1];
+    // tracing::info!("log-panel height: {}", log_panel_chunk.height);
+    // let (tx, rx) = mpsc::channel();
+    let (action_tx, action_rx) = mpsc::channel();
+    let mut event_bus = EventBus::new(action_tx);
+    let input_paused = Arc::new(AtomicBool::new(false));
+    let findings_listed = Arc::new(AtomicBool::new(false));
+    event_bus.register_listener(KeyboardEventListener::new(
+        &cfg.keys,
+        input_paused.clone(),
+        findings_listed.clone(),
+    ));
+    if cfg.watch {
+        match watch::WatchEventListener::new(&cfg.src_dir) {
+            Ok(watch_event_listener) => event_bus.register_listener(watch_event_listener),
+            Err(e) => tracing::error!("Failed to watch {}: {e}", cfg.display_path(&cfg.src_dir)),
+        }
+    }
+    let mut replay_recorder = match args.record.as_deref() {
+        Some(record_path) => Some(replay::ReplayRecorder::create(record_path).map_err(|e| {
+            anyhow!(
+                "Failed to create the replay-file {}: {e}",
+                paths::display(record_path)
+            )
+        })?),
+        None => None,
+    };
+    let mut task_supervisor = TaskSupervisor::new();
+    task_supervisor.spawn("input", move |shutdown_signal| {
+        // breaks on shutdown or disconnected channel
+        while !shutdown_signal.is_requested() && event_bus.update().is_ok() {}
+    })?;
+    let root_dir = args.get_root_directory()?;
+    let project_store = match workspace::Workspace::open(&root_dir)? {
+        // The projects and carts are read from config-files, which the JSON replaces
+        _ if args.get_config_json().is_some() => None,
+        Some(workspace) if args.get_project().is_some() || !config::has_config_file(&root_dir) => {
+            Some(ProjectStore::new(workspace, args.get_project()))
+        }
+        // The carts of the project are switched between like the projects of a workspace
+        _ => workspace::Workspace::of_targets(&cfg.root_dir)?
+            .map(|targets| ProjectStore::new(targets, cfg.target.as_deref())),
+    };
+    let mut pico_runner = cfg.executable.as_deref().map(|executable| {
+        PicoRunner::new(executable).with_output(cfg.root_dir.join(stat_panel::OUTPUT_FILE))
+    });
+    if cfg.open_pico
+        && let Some(pico_runner) = pico_runner.as_mut()
+        && let Err(e) = pico_runner.launch(&cart_path)
+    {
+        tracing::error!("Failed to launch pico-8: {e}");
+    }
+    let mut model = Model {
+        root_dir: cfg.root_dir.clone(),
+        log_file,
+        src_dir: cfg.src_dir.clone(),
+        cart_path,
+        log_panel_store,
+        todo_panel_store: TodoPanelStore::new(cfg.todo_markers),
+        notes_store: NotesStore::open(&cfg.root_dir),
+        stat_tail: StatTail::open(&cfg.root_dir),
+        build_profile: cfg.profile,
+        debug_calls: cfg.debug_calls.into_boxed_slice(),
+        tab_order: cfg.tab_order,
+        gfx_rows: cfg.gfx_rows,
+        embed_sprites: cfg.embed_sprites,
+        assets: cfg.assets,
+        label_fallback: cfg.label_fallback,
+        localization: cfg.localization,
+        minify: cfg.minify,
+        constants: cfg.constants,
+        cart_version: cfg.cart_version,
+        remove_dead_code: cfg.remove_dead_code,
+        include_resolver: cfg.include_resolver,
+        exclude: cfg.exclude,
+        merge_strategy: cfg.merge,
+        tab_overflow: cfg.tab_overflow,
+        cart_backups: cfg.cart_backups,
+        watch: cfg.watch,
+        build_status: BuildStatusStore::default(),
+        pico_runner,
+        project_store,
+        analysis: None,
+        diff_panel_store: DiffPanelStore::default(),
+        running_state: RunningState::Running,
+        task_failures: Vec::new(),
+        file_loading_tracker: FileLoadingTracker {
+            paths: Default::default(),
+        },
+        frame_times: bench::FrameTimes::default(),
+    };
+    while !matches!(model.running_state, RunningState::Done) {
+        if let Some(pico_runner) = model.pico_runner.as_mut() {
+            pico_runner.poll();
+        }
+        if let Err(e) = model.stat_tail.poll() {
+            tracing::warn!("Failed to read the output of pico-8: {e}");
+        }
+        for task_failure in task_supervisor.take_failures() {
+            tracing::error!("{task_failure}");
+            model.task_failures.push(task_failure);
+        }
+        model.log_panel_store.sync();
+        if let Err(e) = model.notes_store.autosave(std::time::Instant::now()) {
+            tracing::error!("Failed to save the notes: {e}");
+        }
+        let (drawn, frame_sample) =
+            bench::FrameTimes::measure(|| terminal.draw(|frame| view(&model, frame)).map(|_| ()));
+        drawn?;
+        model.frame_times.record(frame_sample);
+
+        let mut current_action = match action_rx.try_recv() {
+            Ok(val) => Some(val),
+            Err(mpsc::TryRecvError::Empty) => None,
+            Err(mpsc::TryRecvError::Disconnected) => {
+                break;
+            }
+        };
+
+        let mut is_input = true;
+        while let Some(action) = current_action {
+            if let Some(replay_recorder) = replay_recorder.as_mut() {
+                replay_recorder.record(&action, is_input);
+            }
+            is_input = false;
+            let ctx = ActionContext {
+                log_panel_store: &mut model.log_panel_store,
+                todo_panel_store: &mut model.todo_panel_store,
+                notes_store: &mut model.notes_store,
+                running_state: &mut model.running_state,
+                task_failures: &mut model.task_failures,
+                project_source_file_path: model.cart_path.as_path(),
+                project_source_directory_path: model.src_dir.as_path(),
+                project_root_directory_path: model.root_dir.as_path(),
+                build_profile: model.build_profile,
+                debug_calls: &model.debug_calls,
+                tab_order: &model.tab_order,
+                gfx_rows: model.gfx_rows,
+                embed_sprites: model.embed_sprites.as_ref(),
+                assets: model.assets.as_ref(),
+                label_fallback: &model.label_fallback,
+                localization: model.localization.as_ref(),
+                minify: model.minify.as_ref(),
+                constants: &model.constants,
+                cart_version: model.cart_version,
+                remove_dead_code: model.remove_dead_code,
+                include_resolver: &model.include_resolver,
+                exclude: &model.exclude,
+                merge_strategy: model.merge_strategy,
+                tab_overflow: model.tab_overflow,
+                cart_backups: model.cart_backups,
+                build_status: &mut model.build_status,
+                pico_runner: &mut model.pico_runner,
+                project_store: &mut model.project_store,
+                analysis: &mut model.analysis,
+                diff_panel_store: &mut model.diff_panel_store,
+            };
+
+            current_action = action.invoke(ctx);
+        }
+        if matches!(model.running_state, RunningState::SwitchingProject) {
+            model.running_state = RunningState::Running;
+            if let Some(member) = model.project_store.as_ref().map(ProjectStore::active) {
+                match AppConfiguration::for_member(&args, member) {
+                    Ok(cfg) => model.switch_project(cfg),
+                    Err(e) => tracing::error!("Failed to switch project: {e}"),
+                }
+            }
+        }
+        let opened_in_editor = match &model.running_state {
+            RunningState::EditingNotes => Some((model.notes_store.path().to_path_buf(), None)),
+            RunningState::OpeningFinding {
+                file_path,
+                line_number,
+            } => Some((file_path.clone(), Some(*line_number))),
+            _ => None,
+        };
+        if let Some((file_path, line_number)) = opened_in_editor {
+            let editing_notes = matches!(model.running_state, RunningState::EditingNotes);
+            model.running_state = RunningState::Running;
+            input_paused.store(true, atomic::Ordering::Release);
+            // Lets the keyboard-listener finish the poll it may be waiting in
+            std::thread::sleep(Duration::from_millis(20));
+            ratatui::restore();
+            match notes::open_in_editor(&file_path, line_number) {
+                Ok(status) if !status.success() => {
+                    tracing::warn!("The editor exited with {status}")
+                }
+                Ok(_) => {}
+                Err(e) => tracing::error!("Failed to run the editor: {e}"),
+            }
+            terminal = ratatui::init();
+            input_paused.store(false, atomic::Ordering::Release);
+            if editing_notes && let Err(e) = model.notes_store.reload() {
+                tracing::error!("Failed to read the notes: {e}");
+            }
+        }
+        findings_listed.store(
+            model
+                .analysis
+                .as_ref()
+                .is_some_and(|analysis| !analysis.findings.is_empty()),
+            atomic::Ordering::Release,
+        );
+
+        // let mut current_message = handle_event(&model)
+        //     .or_else(|| next_message(&log_event_rx).map(Message::IncomingLogLine));
+
+        // while current_message.is_some() {
+        //     current_message = update(&mut model, current_message.unwrap())
+        //         .or_else(|| next_message(&log_event_rx).map(Message::IncomingLogLine));
+        //     if matches!(
+        //         current_message.as_ref(),
+        //         Some(&Message::Input(InputMessage::ClearLog))
+        //     ) {
+        //         // terminal.swap_buffers();
+        //         // let rect = get_rect(&mut terminal.get_frame())[1];
+        //         // let mut frame = terminal.get_frame();
+        //         // let buffer = frame.buffer_mut();
+        //         // for position in rect.positions() {
+        //         //     buffer[position].set_symbol("JUNK");
+        //         // }
+        //         // terminal.swap_buffers();
+        //         terminal.clear()?;
+        //     }
+        // }
+    }
+    // loop {
+    //     terminal.draw(|frame| {
+    //         let chunks = Layout::default()
+    //             .direction(Direction::Vertical)
+    //             .constraints([
+    //                 Constraint::Min(1),         // big main-box
+    //                 Constraint::Percentage(50), // log-box
+    //             ])
+    //             .split(frame.area());
+    //         frame.render_widget(
+    //             widgets::Block::new()
+    //                 .title("main")
+    //                 .borders(widgets::Borders::ALL),
+    //             chunks[0],
+    //         );
+
+    //         if let Ok(msg) = message_rx.try_recv() {
+    //             log_state.push(msg);
+    //         }
+    //         frame.render_stateful_widget(LogWidget {}, chunks[1], &mut log_state);
+    //     })?;
+    //     if event::poll(Duration::from_millis(10))? {
+    //         match event::read()? {
+    //             event::Event::Key(key) if key.kind == event::KeyEventKind::Press => {
+    //                 match key.code {
+    //                     event::KeyCode::Char('q') => break,
+    //                     event::KeyCode::Enter => {
+    //                         let cart = pico_8_cart_builder::CartBuilder::new(&cfg.src_dir)
+    //                             .build(&cart_path)?;
+    //                         tracing::info!("got cart");
+    //                         tracing::trace!("{cart:#?}")
+    //                     }
+    //                     _ => {}
+    //                 }
+    //             }
+    //             _ => {}
+    //         }
+    //     }
+    // }
+    if let Some(frame_times) = model.frame_times.summary() {
+        tracing::info!(
+            "frames drawn in {} on average, {} p95, {} max, with {} allocations",
+            bench::Millis(frame_times.mean),
+            bench::Millis(frame_times.p95),
+            bench::Millis(frame_times.max),
+            bench::Allocations(frame_times.mean_allocations)
+        );
+    }
+    if let Err(e) = model.notes_store.save() {
+        tracing::error!("Failed to save the notes: {e}");
+    }
+    let task_failures = task_supervisor.shutdown();
+    ratatui::restore();
+    // The ui is gone, so the failures which were not dismissed are reported here
+    for task_failure in model.task_failures.iter().chain(&task_failures) {
+        eprintln!("{task_failure}");
+    }
+    Ok(())
+    // tracing::info!("Opening cart at {cart_path:?}");
+
+    // let mut cart_file = fs::File::open(cart_path)?;
+
+    // // Buffer for file-data
+    // let mut cart_src = vec![];
+
+    // // Copy file-data
+    // io::Read::read_to_end(&mut cart_file, &mut cart_src)?;
+
+    // let cart = pico_8_cart_model::CartData::from_cart_source(cart_src.as_ref())?;
+
+    // let cart = pico_build_rs::P8Cart::try_from_reader(&mut cart_file)
+    //     .map_err(|e| anyhow!("Failed to read cart data from file: {e}"));
+
+    // let cart = pico_8_cart_builder::CartBuilder::new(cfg.src_dir).build(&cart_path)?;
+    // tracing::info!("{cart:#?}");
+
+    // let mut file = fs::OpenOptions::new()
+    //     .write(true)
+    //     .read(true)
+    //     .append(false)
+    //     .truncate(true)
+    //     .create(true)
+    //     .open("main_dst.p8")?;
+    // let cart_src: Box<[u8]> = cart.into_cart_source();
+    // io::Write::write_all(&mut file, cart_src.as_ref())?;
+
+    // let original_file = fs::read_to_string(&cart_path)?;
+    // let copied_file = fs::read_to_string("main_dst.p8")?;
+
+    // if original_file == copied_file {
+    //     tracing::info!("The files match!")
+    // } else {
+    //     tracing::warn!("The files were different...")
+    // }
+}
+
+/// Prints the changes from the old cart to the new one, in the view
+fn diff(old: &path::Path, new: &path::Path, view: diff_view::DiffView) -> anyhow::Result<()> {
+    let load = |cart_path: &path::Path| {
+        <CartData as pico_build_rs::FromFile>::from_file(fs::File::open(cart_path)?).map_err(|e| {
+            anyhow!(
+                "{}: Failed to load {}: {e}",
+                e.code(),
+                paths::display(cart_path)
+            )
+        })
+    };
+    let (old_cart, new_cart) = (load(old)?, load(new)?);
+    let diff = old_cart.diff(&new_cart);
+    if diff.is_empty() {
+        println!("The carts are identical");
+    } else {
+        for line in view.renderer().render(&diff, &theme::Palette::default()) {
+            println!("{line}");
+        }
+    }
+    Ok(())
+}
+
+/// Prints the changes between the cart published to the BBS, as the older one, and the
+/// built cartridge
+#[cfg(feature = "network")]
+fn bbs_diff(
+    cfg: &config::AppConfiguration,
+    cart: &str,
+    refresh: bool,
+    view: diff_view::DiffView,
+) -> anyhow::Result<()> {
+    let cart_id = bbs::cart_id(cart)?;
+    let (published_cart, fetched) = bbs::fetch_cart(&cfg.root_dir, &cart_id, refresh)?;
+    match fetched {
+        bbs::Fetched::Downloaded => println!("Downloaded {}", bbs::cart_url(&cart_id)),
+        bbs::Fetched::Cached => println!("Using {cart_id} as downloaded before, see --refresh"),
+        bbs::Fetched::CachedOffline => {
+            println!("The BBS could not be reached, using {cart_id} as downloaded before")
+        }
+    }
+    let cart_path = cfg.cart_path();
+    let built_cart = <CartData as pico_build_rs::FromFile>::from_file(fs::File::open(&cart_path)?)
+        .map_err(|e| {
+            anyhow!(
+                "{}: Failed to load {}: {e}",
+                e.code(),
+                cfg.display_path(&cart_path)
+            )
+        })?;
+    let diff = published_cart.diff(&built_cart);
+    if diff.is_empty() {
+        println!("The published cart is the built cartridge");
+    } else {
+        for line in view.renderer().render(&diff, &theme::Palette::default()) {
+            println!("{line}");
+        }
+    }
+    Ok(())
+}
+
+/// Prints whether each cart below the directory round-trips, with the first difference
+/// of each which does not
+fn verify(dir: &path::Path) -> anyhow::Result<()> {
+    let verdicts = pico_build_rs::verify::verify_dir(dir)
+        .map_err(|e| anyhow!("Failed to list the carts in {}: {e}", paths::display(dir)))?;
+    if verdicts.is_empty() {
+        println!("No carts in {}", paths::display(dir));
+        return Ok(());
+    }
+    let display_path = |cart_path: &path::Path| paths::relative_to(cart_path, dir).to_string();
+    let width = verdicts
+        .iter()
+        .map(|(cart_path, _)| display_path(cart_path).len())
+        .max()
+        .unwrap_or_default();
+    for (cart_path, verdict) in &verdicts {
+        let cart_path = display_path(cart_path);
+        match verdict {
+            Verdict::Pass => println!("PASS  {cart_path}"),
+            Verdict::Diverged(divergence) => println!(
+                "FAIL  {cart_path:width$}  differs from line {}",
+                divergence.line_number
+            ),
+            Verdict::Failed(e) => println!("FAIL  {cart_path:width$}  {}: {e}", e.code()),
+        }
+    }
+    let diverged: Vec<_> = verdicts
+        .iter()
+        .filter_map(|(cart_path, verdict)| match verdict {
+            Verdict::Diverged(divergence) => Some((cart_path, divergence)),
+            _ => None,
+        })
+        .collect();
+    for (cart_path, divergence) in &diverged {
+        println!("\n{}:\n{divergence}", display_path(cart_path));
+    }
+    let failed = verdicts
+        .iter()
+        .filter(|(_, verdict)| !matches!(verdict, Verdict::Pass))
+        .count();
+    if failed == 0 {
+        println!("\nAll {} carts round-trip", verdicts.len());
+        Ok(())
+    } else {
+        Err(anyhow!(
+            "{failed} of {} carts do not round-trip",
+            verdicts.len()
+        ))
+    }
+}
+
+fn explain(code: &str) -> anyhow::Result<()> {
+    let Some(diagnostic) = pico_build_rs::diagnostics::lookup(code) else {
+        let known_codes: Vec<&str> = pico_build_rs::diagnostics::DIAGNOSTICS
+            .iter()
+            .map(|diagnostic| diagnostic.code)
+            .collect();
+        return Err(anyhow!(
+            "Unknown diagnostic-code {code:?}, expected one of {}",
+            known_codes.join(", ")
+        ));
+    };
+    println!(
+        "{}: {}\n\n{}",
+        diagnostic.code, diagnostic.summary, diagnostic.explanation
+    );
+    Ok(())
+}
+
+/// The stores the actions update without the terminal user-interface
+struct HeadlessStores {
+    log_panel_store: LogPanelStore,
+    todo_panel_store: TodoPanelStore,
+    notes_store: NotesStore,
+    running_state: RunningState,
+    task_failures: Vec<TaskFailure>,
+    build_status: BuildStatusStore,
+    analysis: Option<AnalyzedCart>,
+    diff_panel_store: DiffPanelStore,
+}
+
+impl HeadlessStores {
+    fn new(cfg: &config::AppConfiguration) -> HeadlessStores {
+        HeadlessStores {
+            log_panel_store: LogPanelStore::new(cfg.theme),
+            todo_panel_store: TodoPanelStore::new(cfg.todo_markers.iter().cloned()),
+            notes_store: NotesStore::open(&cfg.root_dir),
+            running_state: RunningState::Running,
+            task_failures: Vec::new(),
+            build_status: BuildStatusStore::default(),
+            analysis: None,
+            diff_panel_store: DiffPanelStore::default(),
+        }
+    }
+    /// Invokes the action with the project of the configuration, returning the follow-up
+    fn invoke(
+        &mut self,
+        cfg: &config::AppConfiguration,
+        build_profile: BuildProfile,
+        action: Action,
+    ) -> Option<Action> {
+        action.invoke(ActionContext {
+            log_panel_store: &mut self.log_panel_store,
+            todo_panel_store: &mut self.todo_panel_store,
+            notes_store: &mut self.notes_store,
+            running_state: &mut self.running_state,
+            task_failures: &mut self.task_failures,
+            project_source_file_path: &cfg.cart_path(),
+            project_source_directory_path: cfg.src_dir.as_path(),
+            project_root_directory_path: cfg.root_dir.as_path(),
+            build_profile,
+            debug_calls: &cfg.debug_calls,
+            tab_order: &cfg.tab_order,
+            gfx_rows: cfg.gfx_rows,
+            embed_sprites: cfg.embed_sprites.as_ref(),
+            assets: cfg.assets.as_ref(),
+            label_fallback: &cfg.label_fallback,
+            localization: cfg.localization.as_ref(),
+            minify: cfg.minify.as_ref(),
+            constants: &cfg.constants,
+            cart_version: cfg.cart_version,
+            remove_dead_code: cfg.remove_dead_code,
+            include_resolver: &cfg.include_resolver,
+            exclude: &cfg.exclude,
+            merge_strategy: cfg.merge,
+            tab_overflow: cfg.tab_overflow,
+            cart_backups: cfg.cart_backups,
+            build_status: &mut self.build_status,
+            pico_runner: &mut None,
+            project_store: &mut None,
+            analysis: &mut self.analysis,
+            diff_panel_store: &mut self.diff_panel_store,
+        })
+    }
+}
+
+/// Runs the compile-actions without the terminal user-interface,
+/// returning the report of the build unless it failed
+fn build_headless(
+    cfg: &config::AppConfiguration,
+    build_profile: BuildProfile,
+) -> Option<BuildReport> {
+    let mut stores = HeadlessStores::new(cfg);
+    let mut action = Some(Action::CompileCartridge);
+    while let Some(next_action) = action {
+        action = stores.invoke(cfg, build_profile, next_action);
+    }
+    if stores.build_status.failed() {
+        None
+    } else {
+        stores.build_status.last_build().copied()
+    }
+}
+
+/// Feeds the inputs of the replay-file back through the actions without the terminal
+/// user-interface, comparing the follow-ups against the recorded ones, see [`replay`]
+fn replay_headless(cfg: &config::AppConfiguration, replay_path: &path::Path) -> anyhow::Result<()> {
+    let steps = replay::parse(&fs::read_to_string(replay_path)?)?;
+    let mut stores = HeadlessStores::new(cfg);
+    let mut divergences = 0;
+    for replay::ReplayStep {
+        elapsed,
+        input,
+        follow_ups,
+    } in &steps
+    {
+        let mut action = Some(input.to_action().ok_or_else(|| {
+            anyhow!(
+                "{} at {}ms is not an input, and cannot be replayed",
+                input.name,
+                elapsed.as_millis()
+            )
+        })?);
+        let mut replayed_follow_ups = Vec::new();
+        while let Some(next_action) = action {
+            action = stores.invoke(cfg, cfg.profile, next_action);
+            replayed_follow_ups.extend(action.as_ref().map(replay::RecordedAction::of));
+        }
+        if replayed_follow_ups != *follow_ups {
+            divergences += 1;
+            let join = |actions: &[replay::RecordedAction]| {
+                actions
+                    .iter()
+                    .map(ToString::to_string)
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            };
+            println!(
+                "{input} at {}ms: recorded [{}], replayed [{}]",
+                elapsed.as_millis(),
+                join(follow_ups),
+                join(&replayed_follow_ups)
+            );
+        }
+    }
+    if divergences > 0 {
+        return Err(anyhow!(
+            "{divergences} of {} replayed actions differ from the recording",
+            steps.len()
+        ));
+    }
+    println!("Replayed {} actions as recorded", steps.len());
+    Ok(())
+}
+
+/// Builds the cartridge repeatedly, printing the timings of each stage, see [`bench`]
+fn bench(cfg: &config::AppConfiguration, iterations: usize) -> anyhow::Result<()> {
+    use bench::{Allocations, Millis, StageSample, StageSummary};
+
+    if iterations == 0 {
+        return Err(anyhow!("bench needs at least one build"));
+    }
+    // Rotating the backups on each build would only add to the timings
+    let cfg = config::AppConfiguration {
+        cart_backups: 0,
+        ..cfg.clone()
+    };
+    let cart_path = cfg.cart_path();
+    let mut stores = HeadlessStores::new(&cfg);
+    let mut builds: Vec<Vec<(&'static str, StageSample)>> = Vec::with_capacity(iterations);
+    for _ in 0..iterations {
+        let mut stages = Vec::new();
+        let mut action = Some(Action::CompileCartridge);
+        while let Some(next_action) = action {
+            let name = next_action.name();
+            let started = std::time::Instant::now();
+            let (follow_up, allocations) =
+                bench::count_allocations(|| stores.invoke(&cfg, cfg.profile, next_action));
+            stages.push((
+                name,
+                StageSample {
+                    time: started.elapsed(),
+                    allocations,
+                },
+            ));
+            action = follow_up;
+        }
+        if stores.build_status.failed() {
+            return Err(anyhow!("Failed to build {}", cfg.display_path(&cart_path)));
+        }
+        let total = StageSample {
+            time: stages.iter().map(|(_, sample)| sample.time).sum(),
+            allocations: stages.iter().map(|(_, sample)| sample.allocations).sum(),
+        };
+        stages.push(("total", total));
+        builds.push(stages);
+    }
+
+    println!(
+        "Benchmarked {iterations} builds of {} with the {:?}-profile",
+        cfg.display_path(&cart_path),
+        cfg.profile
+    );
+    if !cfg!(feature = "bench") {
+        println!("Allocations are only counted when built with the `bench` feature");
+    }
+    let (cold, warm) = builds.split_first().expect("at least one build");
+    println!("cold:");
+    for (name, StageSample { time, allocations }) in cold {
+        println!(
+            "  {name:<24} {:>10} {:>12} allocations",
+            Millis(*time).to_string(),
+            Allocations(*allocations).to_string()
+        );
+    }
+    if warm.is_empty() {
+        return Ok(());
+    }
+    println!("warm ({} builds):", warm.len());
+    println!(
+        "  {:<24} {:>10} {:>10} {:>10} {:>10} {:>12}",
+        "stage", "mean", "p50", "p95", "max", "allocations"
+    );
+    for (name, _) in cold {
+        let samples: Vec<StageSample> = warm
+            .iter()
+            .flat_map(|stages| stages.iter().filter(|(stage, _)| stage == name))
+            .map(|(_, sample)| *sample)
+            .collect();
+        let Some(StageSummary {
+            mean,
+            p50,
+            p95,
+            max,
+            mean_allocations,
+        }) = StageSummary::of(&samples)
+        else {
+            continue;
+        };
+        println!(
+            "  {name:<24} {:>10} {:>10} {:>10} {:>10} {:>12}",
+            Millis(mean).to_string(),
+            Millis(p50).to_string(),
+            Millis(p95).to_string(),
+            Millis(max).to_string(),
+            Allocations(mean_allocations).to_string()
+        );
+    }
+    Ok(())
+}
+
+/// Builds the cartridge, printing the stats of the build
+fn build_and_report(cfg: &config::AppConfiguration) -> anyhow::Result<()> {
+    let cart_path = cfg.cart_path();
+    let BuildReport {
+        tab_count,
+        token_count,
+        compressed_size,
+        embedded_sprites,
+        bytes_written,
+        ..
+    } = build_headless(cfg, cfg.profile)
+        .ok_or_else(|| anyhow!("Failed to build {}", cfg.display_path(&cart_path)))?;
+    let compressed_size = compressed_size
+        .map(|compressed_size| format!(", {compressed_size} bytes compressed"))
+        .unwrap_or_default();
+    println!(
+        "Built {} ({tab_count} tabs, {token_count} tokens{compressed_size}, {bytes_written} bytes written)",
+        cfg.display_path(&cart_path)
+    );
+    if let Some(embedded_sprites) = embedded_sprites {
+        println!("{embedded_sprites}");
+    }
+    Ok(())
+}
+
+fn run_command(command: &args::Command, cfg: &config::AppConfiguration) -> anyhow::Result<()> {
+    match command {
+        args::Command::Todos => {
+            for pico_build_rs::todos::TodoEntry {
+                path,
+                line_number,
+                marker,
+                text,
+            } in pico_build_rs::todos::scan_directory(&cfg.src_dir, &cfg.todo_markers)?
+            {
+                println!("{}:{line_number}: {marker} {text}", cfg.display_path(&path));
+            }
+            Ok(())
+        }
+        args::Command::Explain { code } => explain(code),
+        args::Command::Diff { old, new, view } => diff(old, new, *view),
+        args::Command::Verify { dir } => verify(dir),
+        args::Command::Init { .. } => unreachable!("init does not need a configured project"),
+        args::Command::Build { .. } => build_and_report(cfg),
+        args::Command::Watch => {
+            // Reports the failure, and keeps watching for a fix
+            if let Err(e) = build_and_report(cfg) {
+                eprintln!("{e}");
+            }
+            let watch_event_listener = watch::WatchEventListener::new(&cfg.src_dir)?;
+            println!("Watching {} for changes", cfg.display_path(&cfg.src_dir));
+            loop {
+                match watch_event_listener.next_action(Duration::ZERO) {
+                    Some(Action::SourceChanged { changed }) => match build_and_report(cfg) {
+                        Ok(()) => println!(
+                            "Save-to-cart-written in {}ms",
+                            changed.elapsed().as_millis()
+                        ),
+                        Err(e) => eprintln!("{e}"),
+                    },
+                    _ => std::thread::sleep(core::time::Duration::from_millis(50)),
+                }
+            }
+        }
+        args::Command::Analyze { out } => {
+            let cart_path = cfg.cart_path();
+            let report_format = match out {
+                Some(report_path) => Some(
+                    pico_build_rs::report::ReportFormat::from_path(report_path).ok_or_else(
+                        || {
+                            anyhow!(
+                                "Unknown report-format of {}, expected a .md or .html file",
+                                paths::display(report_path)
+                            )
+                        },
+                    )?,
+                ),
+                None => None,
+            };
+            let cart = <pico_8_cart_model::CartData as pico_build_rs::FromFile>::from_file(
+                fs::File::open(&cart_path)?,
+            )
+            .map_err(|e| {
+                anyhow!(
+                    "{}: Failed to load {}: {e}",
+                    e.code(),
+                    cfg.display_path(&cart_path)
+                )
+            })?;
+            let analysis = pico_build_rs::analysis::analyze(&cart).map_err(|e| {
+                anyhow!(
+                    "{}: Failed to analyze {}: {e}",
+                    e.code(),
+                    cfg.display_path(&cart_path)
+                )
+            })?;
+            println!("{}", cfg.display_path(&cart_path));
+            for pico_build_rs::analysis::TabStats {
+                tab_index,
+                title,
+                lines,
+                tokens,
+                chars,
+            } in &analysis.tabs
+            {
+                let title = title
+                    .as_ref()
+                    .map(|title| format!(" ({title})"))
+                    .unwrap_or_default();
+                println!("tab {tab_index}{title}: {lines} lines, {tokens} tokens, {chars} chars");
+            }
+            println!(
+                "tokens: {}/{}",
+                analysis.token_count(),
+                cart.format_spec().token_limit
+            );
+            for (section, size) in &analysis.section_sizes {
+                println!("{}: {size} bytes", <&'static str>::from(section));
+            }
+            println!(
+                "sprites: {}/{}",
+                analysis.sprites_used, analysis.sprite_count
+            );
+            println!("map: {}/{} tiles", analysis.tiles_used, analysis.tile_count);
+            let compressed_size = cart
+                .compressed_code_size()
+                .map_err(|e| anyhow!("{}: Failed to compress code: {e}", e.code()))?;
+            println!(
+                "compressed: {compressed_size}/{} bytes",
+                cart.format_spec().compressed_limit
+            );
+            if let (Some(report_path), Some(report_format)) = (out, report_format) {
+                let report = pico_build_rs::report::render(
+                    &cfg.display_path(&cart_path).to_string(),
+                    &cart,
+                    &analysis,
+                    report_format,
+                )
+                .map_err(|e| anyhow!("{}: Failed to render the report: {e}", e.code()))?;
+                fs::write(report_path, report)?;
+                println!("Wrote the report to {}", paths::display(report_path));
+            }
+            Ok(())
+        }
+        args::Command::Preflight {
+            max_tokens,
+            max_compressed,
+            ..
+        } => {
+            let cart_path = cfg.cart_path();
+            let cart = <pico_8_cart_model::CartData as pico_build_rs::FromFile>::from_file(
+                fs::File::open(&cart_path)?,
+            )
+            .map_err(|e| {
+                anyhow!(
+                    "{}: Failed to load {}: {e}",
+                    e.code(),
+                    cfg.display_path(&cart_path)
+                )
+            })?;
+            let token_count = cart
+                .check_token_limit()
+                .map_err(|e| anyhow!("{}: {}: {e}", e.code(), cfg.display_path(&cart_path)))?;
+            // The budgets below the limits of the cartridge, e.g. for a CI-job
+            let mut over_budget = 0;
+            if let Some(max_tokens) = *max_tokens {
+                println!(
+                    "{}: {token_count}/{max_tokens} tokens",
+                    cfg.display_path(&cart_path)
+                );
+                over_budget += usize::from(token_count > max_tokens);
+            }
+            if let Some(max_compressed) = *max_compressed {
+                let compressed_size = cart
+                    .compressed_code_size()
+                    .map_err(|e| anyhow!("{}: Failed to compress code: {e}", e.code()))?;
+                println!(
+                    "{}: {compressed_size}/{max_compressed} compressed bytes",
+                    cfg.display_path(&cart_path)
+                );
+                over_budget += usize::from(compressed_size > max_compressed);
+            }
+            let findings = pico_build_rs::lint::lint_cart(
+                &cart,
+                pico_build_rs::lint::PUBLISH_TAG,
+                cfg.cart_version,
+            );
+            for pico_build_rs::lint::Finding {
+                rule,
+                tab_index,
+                line_number,
+                line,
+            } in &findings
+            {
+                println!(
+                    "{}: tab {tab_index}, line {line_number}: [{} {}] {}\n    {line}",
+                    cfg.display_path(&cart_path),
+                    rule.code,
+                    rule.name,
+                    rule.description
+                );
+            }
+            if let Some(pico_build_rs::lint::Finding { rule, .. }) = findings.first() {
+                println!(
+                    "For more information, run `pico-build-rs explain {}`",
+                    rule.code
+                );
+            }
+            match findings.len() + over_budget {
+                0 => Ok(()),
+                failed => Err(anyhow!(
+                    "{failed} preflight-check(s) failed for {}",
+                    cfg.display_path(&cart_path)
+                )),
+            }
+        }
+        args::Command::Provenance { tab, section } => {
+            let manifest_path = provenance::manifest_path(&cfg.cart_path());
+            let manifest =
+                BuildManifest::parse(&fs::read_to_string(&manifest_path).map_err(|e| {
+                    anyhow!(
+                        "Failed to read {}, compile the cartridge first: {e}",
+                        cfg.display_path(&manifest_path)
+                    )
+                })?)?;
+            let section = section.as_deref().map(|section| {
+                let delimiter = format!("__{}__", section.trim_matches('_'));
+                pico_8_cart_model::section::get_line_type(&delimiter)
+                    .copied()
+                    .filter(|ty| <&'static str>::from(ty) == delimiter)
+                    .ok_or_else(|| anyhow!("Unknown section {section:?}"))
+            });
+            let target = match (tab, section) {
+                (Some(tab_index), _) => Some(provenance::Target::Tab(*tab_index)),
+                (None, Some(section)) => Some(provenance::Target::Section(section?)),
+                (None, None) => None,
+            };
+            match target {
+                Some(target) => match manifest.get(target) {
+                    Some(entry) => println!("{entry}"),
+                    None => {
+                        return Err(anyhow!(
+                            "No {target} in {}",
+                            cfg.display_path(&manifest_path)
+                        ));
+                    }
+                },
+                None => manifest
+                    .entries
+                    .iter()
+                    .for_each(|entry| println!("{entry}")),
+            }
+            Ok(())
+        }
+        args::Command::Orphans { prune } => orphans(cfg, *prune),
+        args::Command::Graph { format } => graph(cfg, *format),
+        args::Command::Strings { output } => extract_strings(cfg, output.as_deref()),
+        args::Command::DeadCode { report_only } => dead_code(cfg, *report_only),
+        args::Command::Trace { tab, line } => trace(cfg, *tab, *line),
+        args::Command::Bench { iterations } => bench(cfg, *iterations),
+        args::Command::Capture { mp4 } => capture(cfg, *mp4),
+        #[cfg(feature = "network")]
+        args::Command::BbsDiff {
+            cart,
+            refresh,
+            view,
+        } => bbs_diff(cfg, cart, *refresh, *view),
+        #[cfg(feature = "scripting")]
+        args::Command::RunScript { script } => script::run(script, cfg),
+    }
+}
+
+/// Prints the dependency-graph of the project, with the tabs in the order of the build
+fn graph(cfg: &config::AppConfiguration, format: args::GraphFormat) -> anyhow::Result<()> {
+    let cart_path = cfg.cart_path();
+    let tab_order = match cfg.tab_order.source_order() {
+        SourceOrder::Manifest => cfg.tab_order.clone().with_manifest_order(
+            fs::read_to_string(provenance::manifest_path(&cart_path))
+                .ok()
+                .and_then(|text| BuildManifest::parse(&text).ok())
+                .map(|manifest| manifest.tab_file_names())
+                .unwrap_or_default(),
+        ),
+        _ => cfg.tab_order.clone(),
+    };
+    let cart_builder = CartBuilder::new(&cfg.src_dir)
+        .with_tab_order(tab_order)
+        .with_include_resolver(cfg.include_resolver.clone())
+        .with_exclude(cfg.exclude.clone());
+    let graph = pico_build_rs::graph::DependencyGraph::of_project(
+        &cart_builder,
+        &cart_path,
+        cfg.merge.first_compiled_tab(),
+    )?;
+    // Relative to the root-directory as given, so the labels match the other commands
+    let base = fs::canonicalize(&cfg.root_dir).unwrap_or_else(|_| cfg.root_dir.clone());
+    match format {
+        args::GraphFormat::Dot => graph.write_dot(io::stdout().lock(), &base)?,
+        args::GraphFormat::Text => print!("{}", graph.display(&base)),
+    }
+    Ok(())
+}
+
+/// Prints the top-level functions of the cart never called, removing them from the cart
+/// unless only reporting
+fn dead_code(cfg: &config::AppConfiguration, report_only: bool) -> anyhow::Result<()> {
+    let cart_path = cfg.cart_path();
+    let mut cart = <CartData as pico_build_rs::FromFile>::from_file(fs::File::open(&cart_path)?)
+        .map_err(|e| {
+            anyhow!(
+                "{}: Failed to load {}: {e}",
+                e.code(),
+                cfg.display_path(&cart_path)
+            )
+        })?;
+    let (code_tabs, dead_functions) =
+        pico_build_rs::dead_code::eliminate_dead_code(cart.code_tabs(), report_only)
+            .map_err(|e| anyhow!("{}: Failed to find dead code: {e}", e.code()))?;
+    if dead_functions.is_empty() {
+        println!("No unused functions in {}", cfg.display_path(&cart_path));
+        return Ok(());
+    }
+    for dead_function in &dead_functions {
+        println!("{dead_function}");
+    }
+    let token_count: usize = dead_functions
+        .iter()
+        .map(|dead_function| dead_function.token_count)
+        .sum();
+    if report_only {
+        println!(
+            "{} unused functions, {token_count} tokens",
+            dead_functions.len()
+        );
+        return Ok(());
+    }
+    cart.set_code_data(code_tabs);
+    // The replaced cart is kept as a build keeps it, in the configured backups
+    pico_build_rs::cart_write::write_cart_with(&cart_path, cfg.cart_backups, |w| cart.write_to(w))
+        .map_err(|e| {
+            anyhow!(
+                "{}: Failed to write {}: {e}",
+                e.code(),
+                cfg.display_path(&cart_path)
+            )
+        })?;
+    println!(
+        "Removed {} unused functions from {}, saving {token_count} tokens",
+        dead_functions.len(),
+        cfg.display_path(&cart_path)
+    );
+    Ok(())
+}
+
+/// Prints the source-file and line which the line of the tab was compiled from
+fn trace(
+    cfg: &config::AppConfiguration,
+    tab_index: usize,
+    line_number: usize,
+) -> anyhow::Result<()> {
+    let cart_path = cfg.cart_path();
+    let source_map_path = source_map::source_map_path(&cart_path);
+    let source_map = SourceMap::read(&source_map_path).map_err(|e| {
+        anyhow!(
+            "{}: Failed to read {}, build the cart first: {e}",
+            e.code(),
+            cfg.display_path(&source_map_path)
+        )
+    })?;
+    let Some((source_path, source_line_number)) = source_map.resolve(tab_index, line_number) else {
+        return Err(anyhow!(
+            "Tab {tab_index}, line {line_number} was not compiled from a source-file"
+        ));
+    };
+    let cart_directory_path = cart_path.parent().unwrap_or(path::Path::new("."));
+    println!(
+        "{}:{source_line_number}",
+        cfg.display_path(&cart_directory_path.join(source_path))
+    );
+    Ok(())
+}
+
+/// Builds and launches the cart, and once pico-8 is closed collects the GIFs recorded
+/// since the launch, see [`capture`]
+fn capture(cfg: &config::AppConfiguration, mp4: bool) -> anyhow::Result<()> {
+    let Some(executable) = cfg.executable.as_deref() else {
+        return Err(anyhow!(
+            "No pico-8 executable is configured, set `executable` to capture"
+        ));
+    };
+    let Some(desktop_path) = cfg.pico_folders().desktop_path else {
+        return Err(anyhow!(
+            "The desktop-folder of pico-8 is unknown, set `desktop_path` in the `[pico8]` section"
+        ));
+    };
+    build_and_report(cfg)?;
+    let cart_path = cfg.cart_path();
+    let build_version = capture::build_version(&fs::read(&cart_path)?);
+    let launched_at = std::time::SystemTime::now();
+    let mut pico_runner = PicoRunner::new(executable);
+    pico_runner.launch(&cart_path)?;
+    println!(
+        "Recording build {build_version}: F8 starts a GIF, F9 saves it, close pico-8 when done"
+    );
+    while matches!(pico_runner.state(), PicoState::Running { .. }) {
+        std::thread::sleep(Duration::from_millis(100));
+        pico_runner.poll();
+    }
+    let recordings = capture::recorded_since(&desktop_path, launched_at).map_err(|e| {
+        anyhow!(
+            "Failed to read the desktop-folder {}: {e}",
+            paths::display(&desktop_path)
+        )
+    })?;
+    if recordings.is_empty() {
+        println!("No GIFs were saved to {}", paths::display(&desktop_path));
+        return Ok(());
+    }
+    let cart_file_name = cart_path
+        .file_name()
+        .map(|file_name| file_name.to_string_lossy())
+        .unwrap_or_default();
+    // Without the `.p8` or `.p8.png` extension
+    let cart_name = cart_file_name.split('.').next().unwrap_or_default();
+    let media_dir = cfg.root_dir.join(capture::MEDIA_DIR);
+    for media_path in capture::collect(&recordings, &media_dir, cart_name, &build_version)? {
+        println!("Collected {}", cfg.display_path(&media_path));
+        if !mp4 {
+            continue;
+        }
+        match capture::convert_to_mp4(&media_path) {
+            Ok(Some(mp4_path)) => println!("Converted {}", cfg.display_path(&mp4_path)),
+            Ok(None) => {
+                tracing::warn!("ffmpeg is not found on the path, keeping the GIFs only");
+                break;
+            }
+            Err(e) => tracing::warn!(
+                "Failed to convert {} to mp4: {e}",
+                cfg.display_path(&media_path)
+            ),
+        }
+    }
+    Ok(())
+}
+
+/// Prints the string-catalog of the source-files and the files they include, or writes
+/// it to the output, keeping the translations of the catalog already there
+fn extract_strings(
+    cfg: &config::AppConfiguration,
+    output: Option<&path::Path>,
+) -> anyhow::Result<()> {
+    let cart_builder = CartBuilder::new(&cfg.src_dir)
+        .with_tab_order(cfg.tab_order.clone())
+        .with_include_resolver(cfg.include_resolver.clone())
+        .with_exclude(cfg.exclude.clone());
+    // The files of the graph are each source-file, and each file included by one
+    let graph = pico_build_rs::graph::DependencyGraph::of_project(
+        &cart_builder,
+        &cfg.cart_path(),
+        cfg.merge.first_compiled_tab(),
+    )?;
+    let base = fs::canonicalize(&cfg.root_dir).unwrap_or_else(|_| cfg.root_dir.clone());
+    let mut catalog = Catalog::default();
+    for node in graph.nodes() {
+        if let pico_build_rs::graph::GraphNode::File(path) = node {
+            catalog.extract(
+                &paths::relative_to(path, &base).to_string(),
+                &fs::read(path)?,
+            );
+        }
+    }
+    let Some(output) = output else {
+        print!("{}", catalog.to_text());
+        return Ok(());
+    };
+    let catalog = match Catalog::read(output) {
+        Ok(existing) => catalog.with_translations_of(&existing),
+        Err(CatalogError::Io(e)) if e.kind() == io::ErrorKind::NotFound => catalog,
+        Err(e) => {
+            return Err(anyhow!("{}: {}: {e}", e.code(), cfg.display_path(output)));
+        }
+    };
+    if let Some(parent) = output.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(output, catalog.to_text())?;
+    println!(
+        "Wrote {} strings to {}",
+        catalog.entries.len(),
+        cfg.display_path(output)
+    );
+    Ok(())
+}
+
+/// Prints the orphans of the project, asking to remove each orphaned file if pruning
+fn orphans(cfg: &config::AppConfiguration, prune: bool) -> anyhow::Result<()> {
+    let cart_path = cfg.cart_path();
+    // Without a cart or manifest the stale tabs are not known, the files still are
+    let cart = fs::File::open(&cart_path)
+        .ok()
+        .and_then(|cart_file| <CartData as pico_build_rs::FromFile>::from_file(cart_file).ok());
+    let manifest = fs::read_to_string(provenance::manifest_path(&cart_path))
+        .ok()
+        .and_then(|text| BuildManifest::parse(&text).ok());
+    let cart_builder = CartBuilder::new(&cfg.src_dir)
+        .with_tab_order(cfg.tab_order.clone())
+        .with_include_resolver(cfg.include_resolver.clone())
+        .with_exclude(cfg.exclude.clone());
+    let orphans = pico_build_rs::orphans::find_orphans(
+        &cart_builder,
+        &cart_path,
+        cfg.merge.first_compiled_tab(),
+        cart.as_ref(),
+        manifest.as_ref(),
+    )?;
+    if orphans.is_empty() {
+        println!("No orphans in {}", cfg.display_path(&cfg.src_dir));
+        return Ok(());
+    }
+    for orphan in &orphans {
+        println!("{orphan}");
+    }
+    if !prune {
+        return Ok(());
+    }
+    let mut answer = String::new();
+    for path in orphans.iter().filter_map(|orphan| orphan.prunable_path()) {
+        print!("Remove {}? [y/N] ", cfg.display_path(path));
+        io::Write::flush(&mut io::stdout())?;
+        answer.clear();
+        io::stdin().read_line(&mut answer)?;
+        if answer.trim().eq_ignore_ascii_case("y") {
+            fs::remove_file(path)?;
+            println!("Removed {}", cfg.display_path(path));
+        }
+    }
+    if orphans
+        .iter()
+        .any(|orphan| matches!(orphan, pico_build_rs::orphans::Orphan::StaleTab { .. }))
+    {
+        println!("Stale tabs are dropped by building the cartridge again");
+    }
+    Ok(())
+}
+
+use crossterm::event::{self, KeyEventKind};
+use crossterm::event::{Event, KeyCode, KeyEvent};
+
+fn layout(log_panel_lines: usize) -> Layout {
+    Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Min(1),                         // big main-box
+            Constraint::Length(log_panel_lines as u16), // log-box
+        ])
+}
+fn get_ui_rects(frame: &mut Frame, log_panel_lines: usize) -> std::rc::Rc<[Rect]> {
+    layout(log_panel_lines).split(frame.area())
+}
+
+const LOG_PANEL_RECT_INDEX: usize = log_panel::RECT_INDEX;
+
+type LogLine = Line<'static>;
+
+#[derive(Debug)]
+enum FileLoadingState {
+    Opened(path::PathBuf),
+}
+impl FileLoadingState {
+    /// The state, with the path relative to the project-directory
+    fn describe(&self, root_dir: &path::Path) -> String {
+        match self {
+            FileLoadingState::Opened(path) => {
+                format!("opened {}", paths::relative_to(path, root_dir))
+            }
+        }
+    }
+}
+struct FileLoadingTracker {
+    paths: HashMap<String, FileLoadingState>,
+}
+struct Model {
+    /// The directory of the project, which shown paths are relative to
+    root_dir: path::PathBuf,
+    /// Reopened in the project-directory when switching projects
+    log_file: log_file::LogFileHandle,
+    src_dir: path::PathBuf,
+    cart_path: path::PathBuf,
+    // /// Checked during the [`handle_event`] call
+    // log_message_rx: mpsc::Receiver<log_panel::VisitPayload>,
+    /// An owned log-message
+    log_panel_store: LogPanelStore,
+    todo_panel_store: TodoPanelStore,
+    /// Saved when changed, see [`notes::AUTOSAVE_DELAY`]
+    notes_store: NotesStore,
+    /// Plotted in the stat-panel once the running cart prints stats
+    stat_tail: StatTail,
+    build_profile: BuildProfile,
+    debug_calls: Box<[String]>,
+    tab_order: TabOrder,
+    gfx_rows: GfxRows,
+    embed_sprites: Option<SpriteEmbedding>,
+    assets: Option<AssetImport>,
+    label_fallback: LabelFallback,
+    localization: Option<Localization>,
+    minify: Option<Minification>,
+    constants: Constants,
+    cart_version: Option<usize>,
+    remove_dead_code: bool,
+    include_resolver: IncludeResolver,
+    exclude: SourceExclude,
+    merge_strategy: MergeStrategy,
+    tab_overflow: TabOverflow,
+    cart_backups: usize,
+    watch: bool,
+    build_status: BuildStatusStore,
+    pico_runner: Option<PicoRunner>,
+    /// `None` unless a member of a workspace is open
+    project_store: Option<ProjectStore>,
+    /// Shown in place of the file-loading list once the cart is analyzed
+    analysis: Option<AnalyzedCart>,
+    /// Shown in place of the file-loading list once the cart is rebuilt
+    diff_panel_store: DiffPanelStore,
+
+    running_state: RunningState,
+    /// Panicked background-tasks, shown as an error-dialog until dismissed
+    task_failures: Vec<TaskFailure>,
+    file_loading_tracker: FileLoadingTracker,
+    /// Shown in the title of the log-panel
+    frame_times: bench::FrameTimes,
+}
+impl Model {
+    /// Replaces the project-specific state with the project of the configuration,
+    /// stopping the pico-8 instance of the previous project
+    ///
+    /// The file-watcher keeps watching the source-directory of the first project
+    fn switch_project(&mut self, cfg: config::AppConfiguration) {
+        if let Some(pico_runner) = self.pico_runner.as_mut()
+            && let Err(e) = pico_runner.stop()
+        {
+            tracing::warn!("Failed to stop pico-8: {e}");
+        }
+        self.log_file.open(&cfg.root_dir, cfg.log_file.as_ref());
+        self.cart_path = cfg.cart_path();
+        tracing::info!("cart path is {}", cfg.display_path(&self.cart_path));
+        self.src_dir = cfg.src_dir;
+        self.root_dir = cfg.root_dir;
+        self.todo_panel_store = TodoPanelStore::new(cfg.todo_markers);
+        if let Err(e) = self.notes_store.save() {
+            tracing::error!("Failed to save the notes: {e}");
+        }
+        self.notes_store = NotesStore::open(&self.root_dir);
+        self.build_profile = cfg.profile;
+        self.debug_calls = cfg.debug_calls.into_boxed_slice();
+        self.tab_order = cfg.tab_order;
+        self.gfx_rows = cfg.gfx_rows;
+        self.embed_sprites = cfg.embed_sprites;
+        self.assets = cfg.assets;
+        self.label_fallback = cfg.label_fallback;
+        self.localization = cfg.localization;
+        self.minify = cfg.minify;
+        self.constants = cfg.constants;
+        self.cart_version = cfg.cart_version;
+        self.remove_dead_code = cfg.remove_dead_code;
+        self.include_resolver = cfg.include_resolver;
+        self.exclude = cfg.exclude;
+        self.merge_strategy = cfg.merge;
+        self.tab_overflow = cfg.tab_overflow;
+        self.cart_backups = cfg.cart_backups;
+        self.build_status = BuildStatusStore::default();
+        self.analysis = None;
+        self.diff_panel_store = DiffPanelStore::default();
+        self.stat_tail = StatTail::open(&self.root_dir);
+        self.pico_runner = cfg.executable.as_deref().map(|executable| {
+            PicoRunner::new(executable).with_output(self.root_dir.join(stat_panel::OUTPUT_FILE))
+        });
+    }
+}
+#[derive(Debug)]
+enum RunningState {
+    Done,
+    Running,
+    /// The main-loop reloads the model with the active project of the workspace
+    SwitchingProject,
+    /// The main-loop suspends the terminal user-interface while `$EDITOR` edits the notes
+    EditingNotes,
+    /// The main-loop suspends the terminal user-interface while `$EDITOR` opens the
+    /// source-file of a finding
+    OpeningFinding {
+        file_path: path::PathBuf,
+        line_number: usize,
+    },
+}
+/// A user-command
+#[derive(Debug)]
+enum InputMessage {
+    /// A request to analyze the cartridge was made
+    Analyze {
+        cart_path: path::PathBuf,
+    },
+    /// A request to compile the cartridge was made
+    Compile {
+        src_dir: path::PathBuf,
+        cart_path: path::PathBuf,
+    },
+    /// A request to quit was made
+    Quit,
+
+    ClearLog,
+}
+/// A statement about how the model should change
+#[derive(Debug)]
+enum Message {
+    Input(InputMessage),
+    /// The analysis-request finished successfully
+    AnalysisOutput {
+        analyzed_data: Box<pico_8_cart_model::CartData<'static>>,
+    },
+    /// The compilation-request finished successfully
+    CompilationOutput {
+        compiled_data: Box<pico_8_cart_model::CartData<'static>>,
+        cart_path: path::PathBuf,
+    },
+
+    LogCleared,
+    LogUpdated,
+}
+
+/// Make a decision regarding how the model should change
+fn handle_event(model: &Model) -> Option<Message> {
+    // if let Ok(log_message) = log_message_rx.try_recv() {
+    //     return Some(Message::IncomingLogLine(Line::from(&log_message)));
+    // };
+
+    match event::poll(Duration::from_millis(10)) {
+        Err(_) | Ok(false) => None,
+        Ok(true) => {
+            if let Ok(Event::Key(key)) = event::read() {
+                handle_key(model, key).map(Message::Input)
+            } else {
+                None
+            }
+        }
+    }
+}
+fn handle_key(
+    Model {
+        src_dir, cart_path, ..
+    }: &Model,
+    key: KeyEvent,
+) -> Option<InputMessage> {
+    match key.code {
+        KeyCode::Enter if key.is_press() => Some(InputMessage::Compile {
+            src_dir: src_dir.clone(),
+            cart_path: cart_path.clone(),
+        }),
+        KeyCode::Char('a' | 'A') => Some(InputMessage::Analyze {
+            cart_path: cart_path.clone(),
+        }),
+        KeyCode::Char('q' | 'Q') => Some(InputMessage::Quit),
+        KeyCode::Char('c' | 'C') => Some(InputMessage::ClearLog),
+        _ => None,
+    }
+}
+
+fn read_event_polled(timeout: Duration) -> io::Result<Option<Event>> {
+    let event_available =
+        event::poll(timeout).inspect_err(|e| tracing::error!("Failed to poll for events: {e}"))?;
+    if event_available {
+        event::read().map(Some)
+    } else {
+        Ok(None)
+    }
+}
+
+impl KeyboardEventListener {
+    fn lookup_key_code(&self, key_code: &KeyCode) -> Option<&UserCommand> {
+        self.key_map.get(key_code)
+    }
+    fn translate(&self, key_event: KeyEvent) -> Option<InputEvent> {
+        self.lookup_key_code(&key_event.code)
+            .copied()
+            .map(|action_kind| InputEvent {
+                user_command: action_kind,
+                event_kind: key_event.kind,
+            })
+    }
+    fn poll_next(&self, timeout: Duration) -> io::Result<Option<KeyEvent>> {
+        match read_event_polled(timeout) {
+            Ok(Some(Event::Key(key_event))) => Ok(Some(key_event)),
+            Ok(_) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+#[derive(Default)]
+pub enum EventListenerError<T> {
+    #[default]
+    NoneAvailable,
+    Send(mpsc::SendError<T>),
+    TrySend(mpsc::TrySendError<T>),
+}
+
+impl<T> From<mpsc::SendError<T>> for EventListenerError<T> {
+    fn from(v: mpsc::SendError<T>) -> Self {
+        Self::Send(v)
+    }
+}
+
+impl<T> From<mpsc::TrySendError<T>> for EventListenerError<T> {
+    fn from(v: mpsc::TrySendError<T>) -> Self {
+        Self::TrySend(v)
+    }
+}
+
+pub struct EventListenerBus(Vec<Box<dyn EventListener>>);
+// impl EventListenerBus {
+// }
+
+#[derive(Copy, Clone, Debug)]
+pub enum UserCommand {
+    Compile,
+    Analyze,
+    Quit,
+    ClearLog,
+    ScanTodos,
+    DismissTaskFailures,
+    RestartPico,
+    SwitchProject,
+    CycleDiffView,
+    ScrollLog(LogPanelScroll),
+    /// Captures an entry for the notes, the keys typing it until enter or escape
+    CaptureNote,
+    EditNotes,
+    SelectFinding(FindingSelection),
+    /// Bound to enter while the analysis lists findings, in place of its binding
+    OpenFinding,
+}
+
+impl UserCommand {
+    /// Parses the name used in the `[keys]` section of the config-file, e.g. `restart-pico`
+    pub fn from_name(name: &str) -> Option<UserCommand> {
+        match name {
+            "compile" => Some(UserCommand::Compile),
+            "analyze" => Some(UserCommand::Analyze),
+            "quit" => Some(UserCommand::Quit),
+            "clear-log" => Some(UserCommand::ClearLog),
+            "scan-todos" => Some(UserCommand::ScanTodos),
+            "dismiss-task-failures" => Some(UserCommand::DismissTaskFailures),
+            "restart-pico" => Some(UserCommand::RestartPico),
+            "switch-project" => Some(UserCommand::SwitchProject),
+            "cycle-diff-view" => Some(UserCommand::CycleDiffView),
+            "capture-note" => Some(UserCommand::CaptureNote),
+            "edit-notes" => Some(UserCommand::EditNotes),
+            "select-previous-finding" => {
+                Some(UserCommand::SelectFinding(FindingSelection::Previous))
+            }
+            "select-next-finding" => Some(UserCommand::SelectFinding(FindingSelection::Next)),
+            "open-finding" => Some(UserCommand::OpenFinding),
+            "scroll-log-page-up" => Some(UserCommand::ScrollLog(LogPanelScroll::PageUp)),
+            "scroll-log-page-down" => Some(UserCommand::ScrollLog(LogPanelScroll::PageDown)),
+            "scroll-log-top" => Some(UserCommand::ScrollLog(LogPanelScroll::Top)),
+            "scroll-log-bottom" => Some(UserCommand::ScrollLog(LogPanelScroll::Bottom)),
+            _ => None,
+        }
+    }
+}
+
+pub enum InputActionState {
+    Press,
+    Release,
+    Repeat,
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct InputEvent {
+    user_command: UserCommand,
+    event_kind: KeyEventKind,
+}
+
+#[derive(Debug)]
+pub struct KeyboardEventListener {
+    key_map: HashMap<KeyCode, UserCommand>,
+    /// Whether the keys type an entry for the notes, instead of being looked up
+    capturing_note: Cell<bool>,
+    /// Set while `$EDITOR` edits the notes, reading the keys itself
+    paused: Arc<AtomicBool>,
+    /// Set while the analysis lists findings, enter opening the selected one
+    findings_listed: Arc<AtomicBool>,
+}
+
+impl Default for KeyboardEventListener {
+    fn default() -> Self {
+        KeyboardEventListener {
+            key_map: HashMap::from([
+                (KeyCode::Enter, UserCommand::Compile),
+                (KeyCode::Char('a'), UserCommand::Analyze),
+                (KeyCode::Char('A'), UserCommand::Analyze),
+                (KeyCode::Char('q'), UserCommand::Quit),
+                (KeyCode::Char('Q'), UserCommand::Quit),
+                (KeyCode::Char('c'), UserCommand::ClearLog),
+                (KeyCode::Char('C'), UserCommand::ClearLog),
+                (KeyCode::Char('t'), UserCommand::ScanTodos),
+                (KeyCode::Char('T'), UserCommand::ScanTodos),
+                (KeyCode::Esc, UserCommand::DismissTaskFailures),
+                (KeyCode::Char('r'), UserCommand::RestartPico),
+                (KeyCode::Char('R'), UserCommand::RestartPico),
+                (KeyCode::Char('p'), UserCommand::SwitchProject),
+                (KeyCode::Char('P'), UserCommand::SwitchProject),
+                (KeyCode::Char('v'), UserCommand::CycleDiffView),
+                (KeyCode::Char('V'), UserCommand::CycleDiffView),
+                (KeyCode::Char('n'), UserCommand::CaptureNote),
+                (KeyCode::Char('N'), UserCommand::CaptureNote),
+                (KeyCode::Char('e'), UserCommand::EditNotes),
+                (KeyCode::Char('E'), UserCommand::EditNotes),
+                (
+                    KeyCode::PageUp,
+                    UserCommand::ScrollLog(LogPanelScroll::PageUp),
+                ),
+                (
+                    KeyCode::PageDown,
+                    UserCommand::ScrollLog(LogPanelScroll::PageDown),
+                ),
+                (KeyCode::Home, UserCommand::ScrollLog(LogPanelScroll::Top)),
+                (KeyCode::End, UserCommand::ScrollLog(LogPanelScroll::Bottom)),
+                (
+                    KeyCode::Up,
+                    UserCommand::SelectFinding(FindingSelection::Previous),
+                ),
+                (
+                    KeyCode::Down,
+                    UserCommand::SelectFinding(FindingSelection::Next),
+                ),
+            ]),
+            capturing_note: Cell::new(false),
+            paused: Arc::default(),
+            findings_listed: Arc::default(),
+        }
+    }
+}
+
+impl KeyboardEventListener {
+    /// The default key-map, rebound by the `[keys]` section of the config-file
+    ///
+    /// No keys are read while `paused` is set, and enter opens the selected finding while
+    /// `findings_listed` is set
+    pub fn new(
+        key_bindings: &keys::KeyBindings,
+        paused: Arc<AtomicBool>,
+        findings_listed: Arc<AtomicBool>,
+    ) -> KeyboardEventListener {
+        let mut keyboard_event_listener = KeyboardEventListener {
+            paused,
+            findings_listed,
+            ..KeyboardEventListener::default()
+        };
+        key_bindings.apply(&mut keyboard_event_listener.key_map);
+        keyboard_event_listener
+    }
+    /// The edit of the captured entry the key makes, ending the capture on enter or escape
+    fn capture_key(&self, key_event: KeyEvent) -> Option<NoteEdit> {
+        if !key_event.kind.is_press() {
+            return None;
+        }
+        let note_edit = match key_event.code {
+            KeyCode::Char(c) => NoteEdit::Insert(c),
+            KeyCode::Backspace => NoteEdit::Backspace,
+            KeyCode::Enter => NoteEdit::Commit,
+            KeyCode::Esc => NoteEdit::Cancel,
+            _ => return None,
+        };
+        if matches!(note_edit, NoteEdit::Commit | NoteEdit::Cancel) {
+            self.capturing_note.set(false);
+        }
+        Some(note_edit)
+    }
+}
+
+impl EventListener for KeyboardEventListener {
+    fn next_action(&self, timeout: Duration) -> Option<Action> {
+        if self.paused.load(atomic::Ordering::Acquire) {
+            std::thread::sleep(timeout);
+            return None;
+        }
+        let Ok(Some(next_key_event)) = self.poll_next(timeout) else {
+            return None;
+        };
+        if self.capturing_note.get() {
+            return self.capture_key(next_key_event).map(Action::EditNote);
+        }
+        if next_key_event.code == KeyCode::Enter
+            && self.findings_listed.load(atomic::Ordering::Acquire)
+        {
+            return next_key_event
+                .kind
+                .is_press()
+                .then_some(Action::OpenFinding);
+        }
+
+        let InputEvent {
+            user_command,
+            event_kind,
+        } = self.translate(next_key_event)?;
+
+        event_kind.is_press().then_some(match user_command {
+            UserCommand::ClearLog => Action::ClearLogPanel,
+            UserCommand::Compile => Action::CompileCartridge,
+            UserCommand::Analyze => Action::AnalyzeCartridge,
+            UserCommand::Quit => Action::Quit,
+            UserCommand::ScanTodos => Action::ScanTodos,
+            UserCommand::DismissTaskFailures => Action::DismissTaskFailures,
+            UserCommand::RestartPico => Action::RestartPico,
+            UserCommand::SwitchProject => Action::SwitchProject,
+            UserCommand::CycleDiffView => Action::CycleDiffView,
+            UserCommand::ScrollLog(scroll) => Action::ScrollLogPanel(scroll),
+            UserCommand::CaptureNote => {
+                self.capturing_note.set(true);
+                Action::EditNote(NoteEdit::Start)
+            }
+            UserCommand::EditNotes => Action::EditNotes,
+            UserCommand::SelectFinding(selection) => Action::SelectFinding(selection),
+            UserCommand::OpenFinding => Action::OpenFinding,
+        })
+    }
+    /// Waits for key-presses, ahead of the other listeners
+    fn schedule(&self) -> ListenerSchedule {
+        ListenerSchedule {
+            priority: 1,
+            poll_interval: Duration::ZERO,
+            polling: Polling::Blocking {
+                timeout: Duration::from_millis(10),
+            },
+        }
+    }
+}
+
+trait CrosstermEventHandler {
+    fn event_filter(&self) -> Box<dyn Fn(&Event) -> bool>;
+    fn handle_event(&self, event: Event);
+
+    // type MappedEvent;
+    // fn event_filter_map(&self) -> Box<dyn Fn(&Event) -> Option<Self::MappedEvent>>;
+}
+pub struct DispatchMap(HashMap<fn(&Event) -> bool, Box<dyn CrosstermEventHandler>>);
+// impl Cr
+// impl<H> CrosstermEventHandler for H where H: EventListener, <H as EventListener>::Listened: TryFrom<Event> {
+//     fn event_filter(&self) -> Box<dyn Fn(&Event) -> bool> {
+//         Box::new(|event| {
+//             <Self as EventListener>::Listened::try_from(event.clone()).is_ok()
+//         })
+//     }
+//     fn consume_event(&self, event: Event) {
+//         if let Ok(specific_event) = <Self as EventListener>::Listened::try_from(event) {
+//             self.forward_incoming(tx)
+//         }
+//     }
+// }
+
+pub struct InputEventHandler {
+    listener: KeyboardEventListener,
+    action_tx: mpsc::Sender<Action>,
+}
+
+// impl InputEventHandler {
+
+// }
+
+// impl CrosstermEventHandler for InputEventHandler {
+//     fn event_filter(&self) -> Box<dyn Fn(&Event) -> bool> {
+//         Box::new(|event| matches!(event, &Event::Key(_)))
+//     }
+//     fn handle_event(&self, event: Event) {
+//         let Event::Key(key_event) =
+//     }
+// }
+
+#[derive(Debug)]
+pub struct CrosstermEventDispatcher {
+    input_event_listener: KeyboardEventListener,
+    input_event_tx: mpsc::Sender<InputEvent>,
+}
+
+// trait EventDispatcher {}
+
+#[derive(Debug)]
+pub struct Dispatcher {
+    action_tx: mpsc::Sender<Action>,
+
+    input_event_rx: mpsc::Receiver<InputEvent>,
+}
+
+impl Dispatcher {
+    pub fn update(&self) -> Result<(), mpsc::SendError<Action>> {
+        if let Ok(InputEvent {
+            user_command: action,
+            event_kind,
+        }) = self.input_event_rx.try_recv()
+        {
+            let action = match action {
+                UserCommand::Analyze => todo!("analyze action"),
+                UserCommand::ClearLog => todo!("clear log action"),
+                UserCommand::Quit => todo!("quit action"),
+                UserCommand::Compile => todo!("compile action"),
+                UserCommand::ScanTodos => todo!("scan todos action"),
+                UserCommand::DismissTaskFailures => todo!("dismiss task failures action"),
+                UserCommand::RestartPico => todo!("restart pico action"),
+                UserCommand::SwitchProject => todo!("switch project action"),
+                UserCommand::CycleDiffView => todo!("cycle diff view action"),
+                UserCommand::ScrollLog(_) => todo!("scroll log action"),
+                UserCommand::CaptureNote => todo!("capture note action"),
+                UserCommand::EditNotes => todo!("edit notes action"),
+                UserCommand::SelectFinding(_) => todo!("select finding action"),
+                UserCommand::OpenFinding => todo!("open finding action"),
+            };
+            todo!()
+        }
+        todo!()
+    }
+}
+
+// impl CrosstermEventDispatcher {
+//     pub fn new(input_event_tx: mpsc::Sender<InputEvent>) -> CrosstermEventDispatcher {
+
+//     }
+//     fn dispatch_event(&self, event: Event) {
+//         match event {
+//             Event::Key(key_event) => {
+//                 self.input_event_listener
+//                     .forward_incoming(&self.input_event_tx);
+//             }
+//             _ => {}
+//         }
+//     }
+// }
+
+#[tracing::instrument(level = "trace", skip(log_panel_store, file_loading_tracker))]
+fn update(
+    Model {
+        log_panel_store,
+        running_state,
+        file_loading_tracker,
+        ..
+    }: &mut Model,
+    message: Message,
+) -> Option<Message> {
+    match message {
+        Message::Input(input_message) => match input_message {
+            InputMessage::ClearLog => {
+                log_panel_store.clear();
+                None
+            }
+
+            InputMessage::Compile { src_dir, cart_path } => {
+                tracing::info!("Writing to cart-path {}", paths::display(&cart_path));
+                let source_files = match pico_build_rs::get_lua_files(src_dir.as_path()) {
+                    Ok(files) => files.inspect(|entry| {
+                        let path = entry.path();
+                        let name = path
+                            .file_name()
+                            .map(|file_name| file_name.to_string_lossy().into_owned())
+                            .unwrap_or_default();
+                        file_loading_tracker
+                            .paths
+                            .insert(name, FileLoadingState::Opened(path));
+                    }),
+                    Err(e) => {
+                        tracing::error!("Failed to get lua files {e}");
+                        return None;
+                    }
+                }
+                .filter_map(|source_entry| {
+                    FileData::try_from(source_entry)
+                        .map_err(pico_build_rs::FileDataError::Io)
+                        .inspect_err(|e| tracing::error!("Failed to convert source-entry: {e:?}"))
+                        .and_then(|source_file| {
+                            source_file.into_loaded_or_default(LoadPolicy::ReadOnly)
+                        })
+                        .ok()
+                });
+                match FileData::new(cart_path.as_path())
+                    .into_loaded_or_default(LoadPolicy::ReadOnly)
+                    .and_then(|cart_file| {
+                        pico_build_rs::compile_cartridge(
+                            cart_file,
+                            source_files,
+                            MergeStrategy::default(),
+                        )
+                        .map_err(Into::into)
+                    }) {
+                    Ok(cart) => {
+                        tracing::info!("Got cart-data");
+                        for (tab_index, token_count) in cart.tab_token_counts().iter().enumerate() {
+                            if let Some(token_count) = token_count {
+                                tracing::info!("Tab {tab_index}: {token_count} tokens");
+                            }
+                        }
+                        match cart.check_token_limit() {
+                            Ok(token_count) => tracing::info!(
+                                "{token_count}/{} tokens",
+                                pico_8_cart_model::tokens::TOKEN_LIMIT
+                            ),
+                            Err(e) => {
+                                tracing::error!("{}: Failed to compile: {e}", e.code());
+                                return None;
+                            }
+                        }
+                        Some(Message::CompilationOutput {
+                            compiled_data: Box::new(cart),
+                            cart_path,
+                        })
+                    }
+                    Err(e) => {
+                        tracing::error!("Failed to compile {e:?}");
+                        None
+                    }
+                }
+            }
+            InputMessage::Analyze { cart_path } => {
+                tracing::info!("TODO: implement analyze ({})", paths::display(&cart_path));
+                None
+            }
+            InputMessage::Quit => {
+                *running_state = RunningState::Done;
+                None
+            }
+        },
+        Message::AnalysisOutput { analyzed_data } => todo!("implement analysis-output widget"),
+        Message::CompilationOutput {
+            compiled_data: cart,
+            cart_path,
+        } => {
+            // TODO: Add something here
+            // Some(Message::IncomingLogLine("Compilation successful!".into()))
+            tracing::info!("Compilation successful");
+            let Ok(mut file) = fs::OpenOptions::new()
+                .truncate(true)
+                .write(true)
+                .open(cart_path)
+            else {
+                tracing::info!("Failed to open output cart");
+                return None;
+            };
+            let buf: Vec<u8> = cart.into_cart_source();
+            tracing::debug!("Writing {} bytes to cart", buf.len());
+            match io::Write::write_all(&mut file, buf.as_slice()) {
+                Ok(_) => {
+                    tracing::info!("Successfully wrote to cart");
+                    None
+                }
+                Err(e) => {
+                    tracing::error!("Failed to write to cart: {e}");
+                    None
+                }
+            }
+        }
+
+        Message::LogCleared => todo!("log cleared"),
+        Message::LogUpdated => todo!("log updated"),
+        // Message::IncomingLogLine(log_message) => {
+        //     log_messages.overwrite(log_message);
+        //     None
+        //     // log_messages.push_front(log_message);
+        //     // log_messages
+        //     //     .len()
+        //     //     .ge(log_message_capacity)
+        //     //     .then_some(Message::Reset)
+        // }
+    }
+}
+fn view(
+    Model {
+        root_dir,
+        log_panel_store: log_messages,
+        todo_panel_store,
+        notes_store,
+        stat_tail,
+        file_loading_tracker,
+        task_failures,
+        watch,
+        build_status,
+        pico_runner,
+        project_store,
+        analysis,
+        diff_panel_store,
+        frame_times,
+        ..
+    }: &Model,
+    frame: &mut Frame,
+) {
+    use ratatui::widgets::{Block, Borders, List};
+
+    let chunks = get_ui_rects(frame, log_panel::LINE_COUNT);
+
+    frame.render_widget(Block::new().title("main").borders(Borders::ALL), chunks[0]);
+
+    let [overview_chunk, side_chunk] =
+        Layout::horizontal([Constraint::Fill(1), Constraint::Fill(1)]).areas(chunks[0]);
+    // The stat-panel takes a row for each series, once the running cart prints stats
+    let stat_panel_height = if stat_tail.is_empty() {
+        0
+    } else {
+        stat_tail.len() as u16 + 2
+    };
+    let [todo_panel_chunk, stat_panel_chunk, notes_chunk] = Layout::vertical([
+        Constraint::Fill(1),
+        Constraint::Length(stat_panel_height),
+        Constraint::Fill(1),
+    ])
+    .areas(side_chunk);
+    let [dashboard_chunk, file_loading_chunk] =
+        Layout::vertical([Constraint::Length(11), Constraint::Fill(1)]).areas(overview_chunk);
+
+    let file_loading_list = List::from_iter(file_loading_tracker.paths.iter().map(
+        |(cartridge_name, state)| {
+            let entry = format!("{cartridge_name}: {}", state.describe(root_dir));
+            Text::styled(
+                text::truncate_to_width(&entry, file_loading_chunk.width.into()).into_owned(),
+                Style::new().italic(),
+            )
+            .centered()
+        },
+    ));
+
+    match analysis {
+        Some(analysis) => frame.render_widget(AnalysisWidget::from(analysis), file_loading_chunk),
+        None if diff_panel_store.is_recorded() => {
+            frame.render_widget(DiffPanelWidget::from(diff_panel_store), file_loading_chunk)
+        }
+        None => frame.render_widget(file_loading_list, file_loading_chunk),
+    }
+    frame.render_widget(
+        DashboardWidget::new(build_status, log_messages, todo_panel_store, *watch)
+            .with_pico_state(pico_runner.as_ref().map(PicoRunner::state))
+            .with_project(
+                project_store
+                    .as_ref()
+                    .map(|project_store| project_store.active().name.as_str()),
+            ),
+        dashboard_chunk,
+    );
+    frame.render_widget(TodoPanelWidget::from(todo_panel_store), todo_panel_chunk);
+    if !stat_tail.is_empty() {
+        frame.render_widget(StatPanelWidget::from(stat_tail), stat_panel_chunk);
+    }
+    frame.render_widget(
+        NotesWidget::new(notes_store, log_messages.palette()),
+        notes_chunk,
+    );
+
+    let log_panel_chunk = chunks[1];
+    frame.render_widget(ratatui::widgets::Clear, log_panel_chunk);
+    // use crossterm::terminal::{Clear, ClearType};
+
+    // use crossterm::terminal::{SetSize, size};
+    // let cmd = size().map(|(x, y)| SetSize(x, y)).expect("size");
+    // crossterm::execute!(io::stdout(), cmd).expect("failed cmd");
+    // // crossterm::execute!(io::stdout(), Clear(ClearType::Purge)).expect("failed purge");
+    frame.render_widget(
+        LogPanelWidget::from_store(log_messages, log_panel_chunk)
+            .with_frame_times(frame_times.summary()),
+        log_panel_chunk,
+    );
+
+    if !task_failures.is_empty() {
+        render_task_failures(task_failures, frame);
+    }
+}
+
+/// Renders the panicked tasks as an error-dialog over the main-box
+fn render_task_failures(task_failures: &[TaskFailure], frame: &mut Frame) {
+    use ratatui::widgets::{Block, Borders, Clear, Paragraph, Wrap};
+
+    let [_, area, _] = Layout::vertical([
+        Constraint::Fill(1),
+        Constraint::Percentage(40),
+        Constraint::Fill(1),
+    ])
+    .areas(frame.area());
+    let [_, area, _] = Layout::horizontal([
+        Constraint::Fill(1),
+        Constraint::Percentage(60),
+        Constraint::Fill(1),
+    ])
+    .areas(area);
+    let lines = task_failures
+        .iter()
+        .map(|task_failure| Line::from(task_failure.to_string()))
+        .chain([Line::default(), Line::from("press esc to dismiss").italic()]);
+    let dialog = Paragraph::new(Text::from_iter(lines))
+        .wrap(Wrap { trim: false })
+        .block(
+            Block::new()
+                .title("error")
+                .borders(Borders::ALL)
+                .border_style(Style::new().red()),
+        );
+    frame.render_widget(Clear, area);
+    frame.render_widget(dialog, area);
+}
@@ -0,0 +1,362 @@
+//! The notes of the project, a markdown-file kept in the [`NOTES_FILE`]
+//!
+//! The notes are shown in the notes-panel, and edited with `$EDITOR`, or added to by
+//! capturing an entry: after the capture-key, the keys type the entry until enter adds it
+//! with a timestamp, or escape drops it. The notes are saved [`AUTOSAVE_DELAY`] after the
+//! last change, and when the project is closed
+
+use core::time::Duration;
+
+use std::fs;
+use std::io;
+use std::path;
+use std::process;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+use ratatui::{
+    prelude::*,
+    widgets::{Block, Paragraph},
+};
+
+use crate::theme::Palette;
+
+/// The notes, relative to the project-directory
+pub const NOTES_FILE: &str = ".pico-build/notes.md";
+
+/// How long after the last change the notes are saved
+pub const AUTOSAVE_DELAY: Duration = Duration::from_secs(2);
+
+/// The editor the notes and findings are opened in, unless `$VISUAL` or `$EDITOR` names one
+const DEFAULT_EDITOR: &str = "vi";
+
+/// An edit of the entry being captured
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NoteEdit {
+    /// Starts an empty entry
+    Start,
+    Insert(char),
+    Backspace,
+    /// Adds the entry to the notes
+    Commit,
+    /// Drops the entry
+    Cancel,
+}
+
+/// Holds the notes of the project, and the entry being captured
+#[derive(Debug)]
+pub struct NotesStore {
+    path: path::PathBuf,
+    text: String,
+    draft: Option<String>,
+    /// The first change since the notes were last saved
+    unsaved_since: Option<Instant>,
+}
+
+impl NotesStore {
+    /// Reads the notes of the project-directory, empty until the file is created
+    pub fn open(root_dir: &path::Path) -> NotesStore {
+        let mut notes_store = NotesStore {
+            path: root_dir.join(NOTES_FILE),
+            text: String::new(),
+            draft: None,
+            unsaved_since: None,
+        };
+        if let Err(e) = notes_store.reload() {
+            tracing::error!("Failed to read the notes: {e}");
+        }
+        notes_store
+    }
+    pub fn path(&self) -> &path::Path {
+        &self.path
+    }
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+    /// The entry being captured, if any
+    pub fn draft(&self) -> Option<&str> {
+        self.draft.as_deref()
+    }
+    pub fn is_saved(&self) -> bool {
+        self.unsaved_since.is_none()
+    }
+    /// Edits the entry being captured, adding it with the timestamp on commit
+    pub fn edit(&mut self, edit: NoteEdit, timestamp: &str) {
+        match (edit, self.draft.as_mut()) {
+            (NoteEdit::Start, _) => self.draft = Some(String::new()),
+            (NoteEdit::Insert(c), Some(draft)) => draft.push(c),
+            (NoteEdit::Backspace, Some(draft)) => {
+                draft.pop();
+            }
+            (NoteEdit::Commit, Some(_)) => {
+                let draft = self.draft.take().unwrap_or_default();
+                if !draft.trim().is_empty() {
+                    self.append_entry(timestamp, draft.trim());
+                }
+            }
+            (NoteEdit::Cancel, _) => self.draft = None,
+            (NoteEdit::Insert(_) | NoteEdit::Backspace | NoteEdit::Commit, None) => {}
+        }
+    }
+    /// Adds the entry as a list-item of the notes, led by the timestamp
+    pub fn append_entry(&mut self, timestamp: &str, entry: &str) {
+        if !self.text.is_empty() && !self.text.ends_with('\n') {
+            self.text.push('\n');
+        }
+        self.text.push_str(&format!("- `{timestamp}` {entry}\n"));
+        self.unsaved_since.get_or_insert_with(Instant::now);
+    }
+    /// Saves the notes once the [`AUTOSAVE_DELAY`] passed since they were changed,
+    /// returning whether they were saved
+    pub fn autosave(&mut self, now: Instant) -> io::Result<bool> {
+        match self.unsaved_since {
+            Some(unsaved_since) if now.duration_since(unsaved_since) >= AUTOSAVE_DELAY => {
+                self.save().map(|()| true)
+            }
+            _ => Ok(false),
+        }
+    }
+    /// Writes the notes, unless they are saved already
+    pub fn save(&mut self) -> io::Result<()> {
+        if self.is_saved() {
+            return Ok(());
+        }
+        if let Some(notes_dir) = self.path.parent() {
+            fs::create_dir_all(notes_dir)?;
+        }
+        fs::write(&self.path, &self.text)?;
+        self.unsaved_since = None;
+        Ok(())
+    }
+    /// Reads the notes again, e.g. after they were edited with `$EDITOR`
+    pub fn reload(&mut self) -> io::Result<()> {
+        self.text = match fs::read_to_string(&self.path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(e),
+        };
+        self.unsaved_since = None;
+        Ok(())
+    }
+}
+
+/// The time as `YYYY-MM-DD HH:MM UTC`
+pub fn timestamp(time: SystemTime) -> String {
+    let seconds = time
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let (days, seconds_of_day) = (seconds / 86_400, seconds % 86_400);
+    // The civil date of the days since 1970-01-01, counted in eras of 400 years from
+    // 0000-03-01, so the leap-day ends each year
+    let days = days + 719_468;
+    let era = days / 146_097;
+    let day_of_era = days % 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_from_march = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_from_march + 2) / 5 + 1;
+    let month = if month_from_march < 10 {
+        month_from_march + 3
+    } else {
+        month_from_march - 9
+    };
+    let year = year_of_era + era * 400 + u64::from(month <= 2);
+    format!(
+        "{year:04}-{month:02}-{day:02} {:02}:{:02} UTC",
+        seconds_of_day / 3600,
+        seconds_of_day % 3600 / 60
+    )
+}
+
+/// Edits the file with `$VISUAL` or `$EDITOR`, waiting for the editor to exit
+///
+/// The line is passed as `+<line>`, which `vi`, `nano` and `emacs` open the file at.
+/// The editor takes over the terminal, so the terminal user-interface has to be
+/// suspended meanwhile
+pub fn open_in_editor(
+    file_path: &path::Path,
+    line_number: Option<usize>,
+) -> io::Result<process::ExitStatus> {
+    let editor = std::env::var("VISUAL")
+        .or_else(|_| std::env::var("EDITOR"))
+        .ok()
+        .filter(|editor| !editor.trim().is_empty())
+        .unwrap_or_else(|| DEFAULT_EDITOR.to_string());
+    // The editor may be configured with arguments, e.g. `code --wait`
+    let mut words = editor.split_whitespace();
+    let program = words.next().unwrap_or(DEFAULT_EDITOR);
+    if let Some(file_dir) = file_path.parent() {
+        fs::create_dir_all(file_dir)?;
+    }
+    process::Command::new(program)
+        .args(words)
+        .args(line_number.map(|line_number| format!("+{line_number}")))
+        .arg(file_path)
+        .status()
+}
+
+/// Styles the inline `code`-spans and `**strong**`-spans of the text
+fn inline_spans<'a>(text: &'a str, style: Style, palette: &Palette) -> Vec<Span<'a>> {
+    let mut spans = Vec::new();
+    let mut rest = text;
+    while !rest.is_empty() {
+        let next = [("`", palette.debug), ("**", style.bold())]
+            .into_iter()
+            .filter_map(|(delimiter, span_style)| {
+                let start = rest.find(delimiter)?;
+                let end = rest[start + delimiter.len()..].find(delimiter)?;
+                Some((start, delimiter, end, span_style))
+            })
+            .min_by_key(|(start, ..)| *start);
+        let Some((start, delimiter, end, span_style)) = next else {
+            spans.push(Span::styled(rest, style));
+            break;
+        };
+        if start > 0 {
+            spans.push(Span::styled(&rest[..start], style));
+        }
+        let inner_start = start + delimiter.len();
+        spans.push(Span::styled(
+            &rest[inner_start..inner_start + end],
+            span_style,
+        ));
+        rest = &rest[inner_start + end + delimiter.len()..];
+    }
+    spans
+}
+
+/// Renders the markdown-ish notes: headings, list-items, quotes, code-blocks and the
+/// inline `code` and `**strong**` spans
+pub fn render_lines<'a>(notes: &'a str, palette: &Palette) -> Vec<Line<'a>> {
+    let mut in_code_block = false;
+    notes
+        .lines()
+        .map(|line| {
+            let trimmed = line.trim_start();
+            if trimmed.starts_with("```") {
+                in_code_block = !in_code_block;
+                return Line::styled(line, palette.muted);
+            }
+            if in_code_block {
+                return Line::styled(line, palette.debug);
+            }
+            let heading_level = trimmed.bytes().take_while(|byte| *byte == b'#').count();
+            if (1..=6).contains(&heading_level) && trimmed[heading_level..].starts_with(' ') {
+                let heading = Style::new().bold();
+                let heading = match heading_level {
+                    1 => heading.underlined(),
+                    _ => heading,
+                };
+                return Line::from(inline_spans(
+                    trimmed[heading_level..].trim(),
+                    heading,
+                    palette,
+                ));
+            }
+            let indent = &line[..line.len() - trimmed.len()];
+            if let Some(item) = ["- ", "* ", "+ "]
+                .into_iter()
+                .find_map(|bullet| trimmed.strip_prefix(bullet))
+            {
+                let mut spans = vec![Span::raw(indent), Span::raw("• ")];
+                spans.extend(inline_spans(item, Style::new(), palette));
+                return Line::from(spans);
+            }
+            if let Some(quote) = trimmed.strip_prefix('>') {
+                let mut spans = vec![Span::raw(indent), Span::styled("│ ", palette.muted)];
+                spans.extend(inline_spans(
+                    quote.trim_start(),
+                    Style::new().italic(),
+                    palette,
+                ));
+                return Line::from(spans);
+            }
+            Line::from(inline_spans(line, Style::new(), palette))
+        })
+        .collect()
+}
+
+pub struct NotesWidget<'a> {
+    store: &'a NotesStore,
+    palette: &'a Palette,
+}
+
+impl<'a> NotesWidget<'a> {
+    pub fn new(store: &'a NotesStore, palette: &'a Palette) -> NotesWidget<'a> {
+        NotesWidget { store, palette }
+    }
+}
+
+impl Widget for NotesWidget<'_> {
+    fn render(self, area: Rect, buf: &mut Buffer)
+    where
+        Self: Sized,
+    {
+        let mut lines = render_lines(self.store.text(), self.palette);
+        if let Some(draft) = self.store.draft() {
+            lines.push(Line::from_iter([
+                Span::styled("+ ", self.palette.info),
+                Span::raw(draft),
+                Span::raw("▏").slow_blink(),
+            ]));
+        }
+        let title = match self.store.is_saved() {
+            true => "notes",
+            false => "notes*",
+        };
+        let block = Block::bordered().title(title);
+        // The latest entries are at the end
+        let scroll = lines
+            .len()
+            .saturating_sub(block.inner(area).height.into())
+            .try_into()
+            .unwrap_or(u16::MAX);
+        Widget::render(
+            Paragraph::new(lines).block(block).scroll((scroll, 0)),
+            area,
+            buf,
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn captures_timestamped_entries() {
+        assert_eq!(timestamp(UNIX_EPOCH), "1970-01-01 00:00 UTC");
+        assert_eq!(
+            timestamp(UNIX_EPOCH + Duration::from_secs(1_709_210_096)),
+            "2024-02-29 12:34 UTC"
+        );
+
+        let mut notes_store = NotesStore {
+            path: path::PathBuf::from(NOTES_FILE),
+            text: "# jam".to_string(),
+            draft: None,
+            unsaved_since: None,
+        };
+        notes_store.edit(NoteEdit::Insert('x'), "t");
+        assert_eq!(notes_store.draft(), None);
+        for edit in [
+            NoteEdit::Start,
+            NoteEdit::Insert('o'),
+            NoteEdit::Insert('k'),
+            NoteEdit::Insert('!'),
+            NoteEdit::Backspace,
+            NoteEdit::Commit,
+        ] {
+            notes_store.edit(edit, "2024-02-29 12:34 UTC");
+        }
+        assert_eq!(notes_store.text(), "# jam\n- `2024-02-29 12:34 UTC` ok\n");
+        assert!(!notes_store.is_saved());
+
+        let palette = Palette::default();
+        let lines = render_lines(notes_store.text(), &palette);
+        assert_eq!(lines[0].to_string(), "jam");
+        assert_eq!(lines[1].to_string(), "• 2024-02-29 12:34 UTC ok");
+        assert_eq!(lines[1].spans[2].style, palette.debug);
+    }
+}
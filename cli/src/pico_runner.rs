@@ -0,0 +1,109 @@
+use std::fs;
+use std::io;
+use std::path;
+use std::process;
+
+/// The last known state of the launched pico-8 instance
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PicoState {
+    NotLaunched,
+    Running { pid: u32 },
+    Exited(process::ExitStatus),
+}
+
+/// Launches the pico-8 executable with the cart, and tracks the child-process
+#[derive(Debug)]
+pub struct PicoRunner {
+    executable: path::PathBuf,
+    /// Where the output of pico-8 is written, discarded if `None`
+    output_path: Option<path::PathBuf>,
+    child: Option<process::Child>,
+    state: PicoState,
+    /// Set by a restart-request, so the next build relaunches the cart
+    pending_restart: bool,
+}
+
+impl PicoRunner {
+    pub fn new<P: Into<path::PathBuf>>(executable: P) -> PicoRunner {
+        PicoRunner {
+            executable: executable.into(),
+            output_path: None,
+            child: None,
+            state: PicoState::NotLaunched,
+            pending_restart: false,
+        }
+    }
+    /// Writes the output of pico-8 to the file, rewritten on each launch
+    pub fn with_output<P: Into<path::PathBuf>>(mut self, output_path: P) -> PicoRunner {
+        self.output_path = Some(output_path.into());
+        self
+    }
+    /// Runs the cart in a new pico-8 instance, stopping the one launched before
+    ///
+    /// The output of pico-8 would garble the terminal user-interface, so it is written to
+    /// the output-file, or discarded without one
+    #[tracing::instrument(level = "debug", skip(self))]
+    pub fn launch(&mut self, cart_path: &path::Path) -> io::Result<()> {
+        self.stop()?;
+        let (stdout, stderr) = match self.output_path.as_deref() {
+            Some(output_path) => {
+                if let Some(output_dir) = output_path.parent() {
+                    fs::create_dir_all(output_dir)?;
+                }
+                let output_file = fs::File::create(output_path)?;
+                (output_file.try_clone()?.into(), output_file.into())
+            }
+            None => (process::Stdio::null(), process::Stdio::null()),
+        };
+        let child = process::Command::new(&self.executable)
+            .arg("-run")
+            .arg(cart_path)
+            .stdin(process::Stdio::null())
+            .stdout(stdout)
+            .stderr(stderr)
+            .spawn()?;
+        tracing::info!("Launched pico-8 (pid {})", child.id());
+        self.state = PicoState::Running { pid: child.id() };
+        self.child = Some(child);
+        Ok(())
+    }
+    /// Kills the launched instance, if it is still running
+    pub fn stop(&mut self) -> io::Result<()> {
+        if let Some(mut child) = self.child.take() {
+            let exit_status = match child.try_wait()? {
+                Some(exit_status) => exit_status,
+                None => {
+                    child.kill()?;
+                    child.wait()?
+                }
+            };
+            self.state = PicoState::Exited(exit_status);
+        }
+        Ok(())
+    }
+    /// Checks whether the launched instance has exited, e.g. because it was closed
+    pub fn poll(&mut self) {
+        let Some(child) = self.child.as_mut() else {
+            return;
+        };
+        match child.try_wait() {
+            Ok(Some(exit_status)) => {
+                tracing::info!("pico-8 exited ({exit_status})");
+                self.state = PicoState::Exited(exit_status);
+                self.child = None;
+            }
+            Ok(None) => {}
+            Err(e) => tracing::warn!("Failed to check on pico-8: {e}"),
+        }
+    }
+    pub const fn state(&self) -> PicoState {
+        self.state
+    }
+    pub fn request_restart(&mut self) {
+        self.pending_restart = true;
+    }
+    /// Returns whether a restart was requested, clearing the request
+    pub fn take_pending_restart(&mut self) -> bool {
+        core::mem::take(&mut self.pending_restart)
+    }
+}
@@ -0,0 +1,221 @@
+//! Recording the actions of a session to a replay-file with `--record`, and feeding
+//! them back headlessly with `--replay`, so a bug of the terminal user-interface can
+//! be reproduced from the file attached to its report
+//!
+//! Each action is a tab-separated line of the milliseconds since the session started,
+//! whether the action came from a listener or followed up on the one before, its name,
+//! and the [`content_hash`] of its payload, or `-` without one, e.g. `1520 input compile -`
+//! followed by `1544 follow-up save-compiled-cartridge 8c3e5a1f0b2d4e67`
+//!
+//! Only the inputs are fed back, the follow-ups are derived again and compared
+//! against the recorded ones
+
+use core::fmt;
+use core::time::Duration;
+
+use std::fs;
+use std::io::{self, Write};
+use std::path;
+use std::time::Instant;
+
+use anyhow::anyhow;
+use pico_build_rs::provenance::content_hash;
+
+use crate::Action;
+use crate::analysis_panel::FindingSelection;
+use crate::log_panel::LogPanelScroll;
+
+/// An action as recorded, without the payload itself
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RecordedAction {
+    pub name: String,
+    pub payload_hash: Option<u64>,
+}
+
+impl RecordedAction {
+    pub fn of(action: &Action) -> RecordedAction {
+        let cart_hash = |cartridge_data: &pico_8_cart_model::CartData<'_>| {
+            let mut cart_source = Vec::new();
+            cartridge_data
+                .write_to(&mut cart_source)
+                .ok()
+                .map(|()| content_hash(&cart_source))
+        };
+        let payload_hash = match action {
+            Action::SaveCompiledCartridge { cartridge_data, .. }
+            | Action::DisplayAnalyzedCartridge { cartridge_data } => cart_hash(cartridge_data),
+            _ => None,
+        };
+        RecordedAction {
+            name: action.name().to_string(),
+            payload_hash,
+        }
+    }
+    /// The action to feed back, `None` for the actions only ever following up on another
+    pub fn to_action(&self) -> Option<Action> {
+        match self.name.as_str() {
+            "clear-log" => Some(Action::ClearLogPanel),
+            "source-changed" => Some(Action::SourceChanged {
+                changed: Instant::now(),
+            }),
+            "compile" => Some(Action::CompileCartridge),
+            "restart-pico" => Some(Action::RestartPico),
+            "switch-project" => Some(Action::SwitchProject),
+            "cycle-diff-view" => Some(Action::CycleDiffView),
+            "scroll-log-page-up" => Some(Action::ScrollLogPanel(LogPanelScroll::PageUp)),
+            "scroll-log-page-down" => Some(Action::ScrollLogPanel(LogPanelScroll::PageDown)),
+            "scroll-log-top" => Some(Action::ScrollLogPanel(LogPanelScroll::Top)),
+            "scroll-log-bottom" => Some(Action::ScrollLogPanel(LogPanelScroll::Bottom)),
+            "analyze" => Some(Action::AnalyzeCartridge),
+            "select-previous-finding" => Some(Action::SelectFinding(FindingSelection::Previous)),
+            "select-next-finding" => Some(Action::SelectFinding(FindingSelection::Next)),
+            "scan-todos" => Some(Action::ScanTodos),
+            "dismiss-task-failures" => Some(Action::DismissTaskFailures),
+            "quit" => Some(Action::Quit),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for RecordedAction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.payload_hash {
+            Some(payload_hash) => f.write_fmt(format_args!("{} {payload_hash:016x}", self.name)),
+            None => f.write_str(&self.name),
+        }
+    }
+}
+
+/// An action from a listener, and the actions which followed up on it
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ReplayStep {
+    pub elapsed: Duration,
+    pub input: RecordedAction,
+    pub follow_ups: Vec<RecordedAction>,
+}
+
+fn format_line(elapsed: Duration, kind: &str, action: &RecordedAction) -> String {
+    let payload_hash = action.payload_hash.map_or_else(
+        || "-".to_string(),
+        |payload_hash| format!("{payload_hash:016x}"),
+    );
+    format!(
+        "{}\t{kind}\t{}\t{payload_hash}\n",
+        elapsed.as_millis(),
+        action.name
+    )
+}
+
+/// Parses the replay-file, see the [module-documentation](self)
+pub fn parse(text: &str) -> anyhow::Result<Vec<ReplayStep>> {
+    let mut steps: Vec<ReplayStep> = Vec::new();
+    for (index, line) in text.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let invalid_line = || anyhow!("Invalid line {} in the replay-file", index + 1);
+        let [elapsed, kind, name, payload_hash] = line
+            .split('\t')
+            .collect::<Vec<_>>()
+            .try_into()
+            .map_err(|_| invalid_line())?;
+        let elapsed = Duration::from_millis(elapsed.parse().map_err(|_| invalid_line())?);
+        let action = RecordedAction {
+            name: name.to_string(),
+            payload_hash: match payload_hash {
+                "-" => None,
+                payload_hash => {
+                    Some(u64::from_str_radix(payload_hash, 16).map_err(|_| invalid_line())?)
+                }
+            },
+        };
+        match kind {
+            "input" => steps.push(ReplayStep {
+                elapsed,
+                input: action,
+                follow_ups: Vec::new(),
+            }),
+            "follow-up" => steps
+                .last_mut()
+                .ok_or_else(invalid_line)?
+                .follow_ups
+                .push(action),
+            _ => return Err(invalid_line()),
+        }
+    }
+    Ok(steps)
+}
+
+/// Appends each action of the session to the replay-file
+#[derive(Debug)]
+pub struct ReplayRecorder {
+    file: io::BufWriter<fs::File>,
+    started: Instant,
+}
+
+impl ReplayRecorder {
+    pub fn create(path: &path::Path) -> io::Result<ReplayRecorder> {
+        fs::File::create(path).map(|file| ReplayRecorder {
+            file: io::BufWriter::new(file),
+            started: Instant::now(),
+        })
+    }
+    /// Records the action, `is_input` unless it followed up on the action before
+    ///
+    /// The notes are no part of the build, so editing them is not recorded, nor is
+    /// opening a finding in `$EDITOR`
+    pub fn record(&mut self, action: &Action, is_input: bool) {
+        if matches!(
+            action,
+            Action::EditNote(_) | Action::EditNotes | Action::OpenFinding
+        ) {
+            return;
+        }
+        let kind = if is_input { "input" } else { "follow-up" };
+        let line = format_line(self.started.elapsed(), kind, &RecordedAction::of(action));
+        // Flushed per action, so the file is complete up to a crash
+        if let Err(e) = self
+            .file
+            .write_all(line.as_bytes())
+            .and_then(|()| self.file.flush())
+        {
+            tracing::warn!("Failed to record the action to the replay-file: {e}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_recorded_lines() {
+        let save = RecordedAction {
+            name: "save-compiled-cartridge".to_string(),
+            payload_hash: Some(0x8c3e_5a1f_0b2d_4e67),
+        };
+        let text = [
+            format_line(
+                Duration::from_millis(1520),
+                "input",
+                &RecordedAction::of(&Action::CompileCartridge),
+            ),
+            format_line(Duration::from_millis(1544), "follow-up", &save),
+            format_line(
+                Duration::from_millis(2010),
+                "input",
+                &RecordedAction::of(&Action::ScrollLogPanel(LogPanelScroll::Top)),
+            ),
+        ]
+        .concat();
+        let steps = parse(&text).unwrap();
+        assert_eq!(steps.len(), 2);
+        assert_eq!(steps[0].elapsed, Duration::from_millis(1520));
+        assert_eq!(steps[0].follow_ups, [save]);
+        assert!(matches!(
+            steps[1].input.to_action(),
+            Some(Action::ScrollLogPanel(LogPanelScroll::Top))
+        ));
+        assert!(parse("0\tfollow-up\tcompile\t-\n").is_err());
+    }
+}
@@ -0,0 +1,37 @@
+use anyhow::anyhow;
+
+use std::fs;
+use std::path;
+
+use pico_8_cart_model::CartData;
+
+const MAIN_LUA: &str = "\
+function _init()
+end
+
+function _update()
+end
+
+function _draw()
+  cls()
+end
+";
+
+/// Creates a project in the `name`-directory, with a config-file,
+/// a `main.lua` stubbing the game-loop and an empty cart to compile into
+pub fn init(root_dir: &path::Path, name: &str) -> anyhow::Result<path::PathBuf> {
+    let project_dir = root_dir.join(name);
+    if project_dir.exists() {
+        return Err(anyhow!("{} already exists", project_dir.display()));
+    }
+    let src_dir = project_dir.join("src");
+    fs::create_dir_all(&src_dir)?;
+    fs::write(
+        project_dir.join("pico.toml"),
+        format!("src_dir = \"src\"\ncart = \"{name}.p8\"\n"),
+    )?;
+    fs::write(src_dir.join("main.lua"), MAIN_LUA)?;
+    let cart: Vec<u8> = CartData::default().into_cart_source();
+    fs::write(src_dir.join(format!("{name}.p8")), cart)?;
+    Ok(project_dir)
+}
@@ -0,0 +1,96 @@
+//! Rhai-scripts automating the build, run by `run-script`
+//!
+//! Scripts only reach the functions registered here, on top of the rhai-language itself:
+//! - `build()` and `build(profile)` compile the cartridge, returning whether it succeeded
+//! - `tokens()` returns the token-count of the cartridge
+//! - `compressed_size()` returns the size of its code once compressed
+//! - `preflight()` returns the number of publish-lint findings in the cartridge
+//! - `todos()` returns the number of todo-comments in the source-files
+//!
+//! ```rhai
+//! if !build("release") { throw "build failed"; }
+//! if tokens() > 8000 || preflight() > 0 { throw "not ready to publish"; }
+//! ```
+
+use anyhow::anyhow;
+
+use std::fs;
+use std::path;
+use std::rc::Rc;
+
+use pico_8_cart_model::CartData;
+use pico_build_rs::transform::BuildProfile;
+use rhai::{Engine, EvalAltResult};
+
+use crate::config::AppConfiguration;
+
+type ScriptResult<T> = Result<T, Box<EvalAltResult>>;
+
+fn build(cfg: &AppConfiguration, build_profile: BuildProfile) -> bool {
+    crate::build_headless(cfg, build_profile).is_some()
+}
+
+fn load_cart(cfg: &AppConfiguration) -> ScriptResult<CartData<'static>> {
+    let cart_path = cfg.cart_path();
+    let cart_file = fs::File::open(&cart_path)
+        .map_err(|e| format!("Failed to open {}: {e}", cart_path.display()))?;
+    <CartData as pico_build_rs::FromFile>::from_file(cart_file)
+        .map_err(|e| format!("{}: Failed to load {}: {e}", e.code(), cart_path.display()).into())
+}
+
+fn to_int(count: usize) -> ScriptResult<i64> {
+    i64::try_from(count).map_err(|e| e.to_string().into())
+}
+
+fn register_api(engine: &mut Engine, cfg: Rc<AppConfiguration>) {
+    let build_cfg = Rc::clone(&cfg);
+    engine.register_fn("build", move || build(&build_cfg, build_cfg.profile));
+    let build_cfg = Rc::clone(&cfg);
+    engine.register_fn("build", move |profile: &str| -> ScriptResult<bool> {
+        let build_profile = BuildProfile::from_name(profile)
+            .ok_or_else(|| format!("Unknown profile {profile:?}"))?;
+        Ok(build(&build_cfg, build_profile))
+    });
+    let tokens_cfg = Rc::clone(&cfg);
+    engine.register_fn("tokens", move || {
+        to_int(load_cart(&tokens_cfg)?.token_count())
+    });
+    let size_cfg = Rc::clone(&cfg);
+    engine.register_fn("compressed_size", move || {
+        let size = load_cart(&size_cfg)?
+            .compressed_code_size()
+            .map_err(|e| format!("{}: {e}", e.code()))?;
+        to_int(size)
+    });
+    let preflight_cfg = Rc::clone(&cfg);
+    engine.register_fn("preflight", move || {
+        let cart = load_cart(&preflight_cfg)?;
+        to_int(
+            pico_build_rs::lint::lint_cart(
+                &cart,
+                pico_build_rs::lint::PUBLISH_TAG,
+                preflight_cfg.cart_version,
+            )
+            .len(),
+        )
+    });
+    engine.register_fn("todos", move || {
+        let entries = pico_build_rs::todos::scan_directory(&cfg.src_dir, &cfg.todo_markers)
+            .map_err(|e| format!("Failed to scan for todo-comments: {e}"))?;
+        to_int(entries.len())
+    });
+}
+
+pub fn run(script_path: &path::Path, cfg: &AppConfiguration) -> anyhow::Result<()> {
+    if cfg.safe {
+        return Err(anyhow!(
+            "Refusing to run {}, --safe does not run scripts",
+            script_path.display()
+        ));
+    }
+    let mut engine = Engine::new();
+    register_api(&mut engine, Rc::new(cfg.clone()));
+    engine
+        .run_file(script_path.to_path_buf())
+        .map_err(|e| anyhow!("{}: {e}", script_path.display()))
+}
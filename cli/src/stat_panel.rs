@@ -0,0 +1,203 @@
+//! The stat-panel, plotting the stats the running cart prints as sparklines
+//!
+//! The runner redirects the output of pico-8 into the [`OUTPUT_FILE`], which is tailed on
+//! each frame. Each line starting with `stat:` adds a sample to the named series of its
+//! `name=value` pairs, e.g. printed by the cart with
+//!
+//! ```lua
+//! printh("stat:fps="..stat(7).." cpu="..stat(1))
+//! ```
+
+use std::collections::{BTreeMap, VecDeque};
+use std::fs;
+use std::io::{self, Read, Seek};
+use std::path;
+
+use ratatui::{
+    prelude::*,
+    widgets::{Block, Sparkline},
+};
+
+/// The output of pico-8, relative to the project-directory, rewritten on each launch
+pub const OUTPUT_FILE: &str = ".pico-build/pico-8.log";
+
+/// Starts the lines which are parsed as stats
+const STAT_PREFIX: &str = "stat:";
+
+/// The samples kept of each series, the oldest are dropped first
+const SAMPLE_COUNT: usize = 128;
+
+/// The columns of the name and last value, so the sparklines line up
+const LABEL_WIDTH: u16 = 16;
+
+/// Returns the `name=value` pairs of a stat-line, separated by whitespace or commas
+fn parse_stat_line(line: &str) -> impl Iterator<Item = (&str, f64)> {
+    line.trim()
+        .strip_prefix(STAT_PREFIX)
+        .unwrap_or_default()
+        .split(|c: char| c.is_whitespace() || c == ',')
+        .filter_map(|pair| {
+            let (name, value) = pair.split_once('=')?;
+            Some((name, value.parse().ok()?))
+        })
+}
+
+/// Tails the output of pico-8, keeping the samples of each stat
+#[derive(Debug)]
+pub struct StatTail {
+    path: path::PathBuf,
+    /// The bytes of the file read so far
+    offset: u64,
+    /// The last line read, until its line-break is written
+    partial_line: String,
+    series: BTreeMap<String, VecDeque<f64>>,
+}
+
+impl StatTail {
+    /// Tails the output-file of the project-directory, empty until pico-8 is launched
+    pub fn open(root_dir: &path::Path) -> StatTail {
+        StatTail {
+            path: root_dir.join(OUTPUT_FILE),
+            offset: 0,
+            partial_line: String::new(),
+            series: BTreeMap::new(),
+        }
+    }
+    /// Reads the lines written since the last poll
+    ///
+    /// A file shorter than what was read was rewritten by a relaunch, so the samples of
+    /// the previous instance are dropped
+    pub fn poll(&mut self) -> io::Result<()> {
+        let mut file = match fs::File::open(&self.path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(e),
+        };
+        let file_length = file.metadata()?.len();
+        if file_length < self.offset {
+            self.offset = 0;
+            self.partial_line.clear();
+            self.series.clear();
+        }
+        if file_length == self.offset {
+            return Ok(());
+        }
+        file.seek(io::SeekFrom::Start(self.offset))?;
+        let mut written = Vec::new();
+        file.take(file_length - self.offset)
+            .read_to_end(&mut written)?;
+        self.offset += written.len() as u64;
+        self.partial_line
+            .push_str(&String::from_utf8_lossy(&written));
+        let Some(last_line_break) = self.partial_line.rfind('\n') else {
+            return Ok(());
+        };
+        let lines: String = self.partial_line.drain(..=last_line_break).collect();
+        for (name, value) in lines.lines().flat_map(parse_stat_line) {
+            let samples = self.series.entry(name.to_string()).or_default();
+            if samples.len() == SAMPLE_COUNT {
+                samples.pop_front();
+            }
+            samples.push_back(value);
+        }
+        Ok(())
+    }
+    pub fn is_empty(&self) -> bool {
+        self.series.is_empty()
+    }
+    /// The number of series, each plotted on its own row
+    pub fn len(&self) -> usize {
+        self.series.len()
+    }
+    pub fn series(&self) -> impl Iterator<Item = (&str, &VecDeque<f64>)> {
+        self.series
+            .iter()
+            .map(|(name, samples)| (name.as_str(), samples))
+    }
+}
+
+pub struct StatPanelWidget<'a> {
+    stat_tail: &'a StatTail,
+}
+
+impl<'a> From<&'a StatTail> for StatPanelWidget<'a> {
+    fn from(stat_tail: &'a StatTail) -> Self {
+        StatPanelWidget { stat_tail }
+    }
+}
+
+impl Widget for StatPanelWidget<'_> {
+    fn render(self, area: Rect, buf: &mut Buffer)
+    where
+        Self: Sized,
+    {
+        let block = Block::bordered().title("stats");
+        let inner = block.inner(area);
+        block.render(area, buf);
+        let rows = Layout::vertical(core::iter::repeat_n(
+            Constraint::Length(1),
+            self.stat_tail.len(),
+        ))
+        .split(inner);
+        for ((name, samples), row) in self.stat_tail.series().zip(rows.iter()) {
+            let [label_chunk, sparkline_chunk] =
+                Layout::horizontal([Constraint::Length(LABEL_WIDTH), Constraint::Fill(1)])
+                    .areas(*row);
+            let last_value = samples.back().copied().unwrap_or_default();
+            Line::from_iter([Span::raw(name).bold(), Span::raw(format!(" {last_value}"))])
+                .render(label_chunk, buf);
+            // The sparkline plots the newest samples which fit, scaled to keep fractions
+            let data = samples
+                .iter()
+                .skip(samples.len().saturating_sub(sparkline_chunk.width.into()))
+                .map(|value| (value.max(0.0) * 1000.0) as u64)
+                .collect::<Vec<_>>();
+            Sparkline::default()
+                .data(&data)
+                .render(sparkline_chunk, buf);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tails_stat_lines() {
+        assert_eq!(
+            parse_stat_line("stat:fps=58 cpu=0.5,mem=x").collect::<Vec<_>>(),
+            [("fps", 58.0), ("cpu", 0.5)]
+        );
+        assert_eq!(parse_stat_line("fps=58").count(), 0);
+
+        let root_dir = std::env::temp_dir().join(format!("pico-stat-tail-{}", std::process::id()));
+        let output_path = root_dir.join(OUTPUT_FILE);
+        fs::create_dir_all(output_path.parent().unwrap()).unwrap();
+        fs::write(&output_path, "hello\nstat:fps=60\nstat:fps=5").unwrap();
+        let mut stat_tail = StatTail::open(&root_dir);
+        stat_tail.poll().unwrap();
+        let fps = |stat_tail: &StatTail| {
+            stat_tail
+                .series()
+                .find(|(name, _)| *name == "fps")
+                .map(|(_, samples)| samples.iter().copied().collect::<Vec<_>>())
+        };
+        // The last line is read once its line-break is written
+        assert_eq!(fps(&stat_tail), Some(vec![60.0]));
+        let mut file = fs::OpenOptions::new()
+            .append(true)
+            .open(&output_path)
+            .unwrap();
+        io::Write::write_all(&mut file, b"9 cpu=1\n").unwrap();
+        stat_tail.poll().unwrap();
+        assert_eq!(fps(&stat_tail), Some(vec![60.0, 59.0]));
+        assert_eq!(stat_tail.len(), 2);
+        // A relaunch rewrites the file, dropping the samples of the previous instance
+        fs::write(&output_path, "stat:fps=30\n").unwrap();
+        stat_tail.poll().unwrap();
+        assert_eq!(fps(&stat_tail), Some(vec![30.0]));
+        assert_eq!(stat_tail.len(), 1);
+        fs::remove_dir_all(&root_dir).unwrap();
+    }
+}
@@ -0,0 +1,120 @@
+//! Supervision of the background-threads of the ui
+//!
+//! Every thread is spawned through the [`TaskSupervisor`], so that it is named,
+//! joined on shutdown, and so that a panic is reported instead of lost
+
+use core::any::Any;
+use core::fmt;
+
+use std::io;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+
+/// Checked by the tasks, set once the supervisor shuts down
+#[derive(Clone, Debug, Default)]
+pub struct ShutdownSignal(Arc<AtomicBool>);
+
+impl ShutdownSignal {
+    pub fn is_requested(&self) -> bool {
+        self.0.load(Ordering::Acquire)
+    }
+    fn request(&self) {
+        self.0.store(true, Ordering::Release);
+    }
+}
+
+/// A task which panicked
+#[derive(Clone, Debug)]
+pub struct TaskFailure {
+    pub task_name: String,
+    pub message: String,
+}
+
+impl TaskFailure {
+    fn from_panic(task_name: String, payload: Box<dyn Any + Send>) -> TaskFailure {
+        let message = payload
+            .downcast_ref::<&str>()
+            .map(|message| message.to_string())
+            .or_else(|| payload.downcast_ref::<String>().cloned())
+            .unwrap_or_else(|| "unknown panic-payload".to_string());
+        TaskFailure { task_name, message }
+    }
+}
+
+impl fmt::Display for TaskFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let TaskFailure { task_name, message } = self;
+        f.write_fmt(format_args!("task {task_name:?} panicked: {message}"))
+    }
+}
+
+struct Task {
+    name: String,
+    handle: thread::JoinHandle<()>,
+}
+
+impl Task {
+    fn join(self) -> Result<(), TaskFailure> {
+        let Task { name, handle } = self;
+        match handle.join() {
+            Ok(()) => {
+                tracing::debug!("task {name:?} finished");
+                Ok(())
+            }
+            Err(payload) => Err(TaskFailure::from_panic(name, payload)),
+        }
+    }
+}
+
+/// Owns the handles of all running tasks
+#[derive(Default)]
+pub struct TaskSupervisor {
+    tasks: Vec<Task>,
+    shutdown_signal: ShutdownSignal,
+}
+
+impl TaskSupervisor {
+    pub fn new() -> TaskSupervisor {
+        TaskSupervisor::default()
+    }
+    /// Spawns a named thread running the task
+    ///
+    /// Long-running tasks must return soon after the [`ShutdownSignal`] is requested,
+    /// as [`TaskSupervisor::shutdown`] waits for them
+    pub fn spawn<F>(&mut self, name: &str, task: F) -> io::Result<()>
+    where
+        F: FnOnce(ShutdownSignal) + Send + 'static,
+    {
+        let shutdown_signal = self.shutdown_signal.clone();
+        let handle = thread::Builder::new()
+            .name(name.to_string())
+            .spawn(move || task(shutdown_signal))?;
+        tracing::debug!("spawned task {name:?}");
+        self.tasks.push(Task {
+            name: name.to_string(),
+            handle,
+        });
+        Ok(())
+    }
+    /// Joins the tasks which have finished, returning those which panicked
+    pub fn take_failures(&mut self) -> Vec<TaskFailure> {
+        let (finished, running): (Vec<Task>, Vec<Task>) = core::mem::take(&mut self.tasks)
+            .into_iter()
+            .partition(|task| task.handle.is_finished());
+        self.tasks = running;
+        finished
+            .into_iter()
+            .filter_map(|task| task.join().err())
+            .collect()
+    }
+    /// Requests all tasks to stop, and waits for them,
+    /// returning those which panicked
+    pub fn shutdown(self) -> Vec<TaskFailure> {
+        self.shutdown_signal.request();
+        self.tasks
+            .into_iter()
+            .filter_map(|task| task.join().err())
+            .collect()
+    }
+}
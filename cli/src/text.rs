@@ -0,0 +1,81 @@
+//! Fitting text into terminal columns
+//!
+//! Wide glyphs such as CJK-characters and most emoji take two columns, so the
+//! byte- or char-length of a string is not its width on screen
+
+use std::borrow::Cow;
+
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
+
+pub const ELLIPSIS: char = '…';
+
+/// Cuts the end off the text to fit the width, ending it in an ellipsis if cut
+pub fn truncate_to_width(text: &str, max_width: usize) -> Cow<'_, str> {
+    if text.width() <= max_width {
+        return Cow::Borrowed(text);
+    }
+    let mut truncated = String::new();
+    let mut width = 0;
+    for c in text.chars() {
+        let char_width = c.width().unwrap_or_default();
+        if width + char_width + ELLIPSIS.width().unwrap_or_default() > max_width {
+            break;
+        }
+        width += char_width;
+        truncated.push(c);
+    }
+    if max_width > 0 {
+        truncated.push(ELLIPSIS);
+    }
+    Cow::Owned(truncated)
+}
+
+/// Cuts the start off the text to fit the width, starting it with an ellipsis if cut
+///
+/// Keeps the end of paths, i.e. the file-name and line-number
+pub fn truncate_start_to_width(text: &str, max_width: usize) -> Cow<'_, str> {
+    if text.width() <= max_width {
+        return Cow::Borrowed(text);
+    }
+    let mut width = 0;
+    let start = text
+        .char_indices()
+        .rev()
+        .take_while(|(_, c)| {
+            width += c.width().unwrap_or_default();
+            width + ELLIPSIS.width().unwrap_or_default() <= max_width
+        })
+        .last()
+        .map_or(text.len(), |(index, _)| index);
+    if max_width == 0 {
+        return Cow::Borrowed("");
+    }
+    Cow::Owned(format!("{ELLIPSIS}{}", &text[start..]))
+}
+
+/// Pads the text with spaces to exactly the width, truncating its start if too wide
+pub fn pad_to_width(text: &str, width: usize) -> String {
+    let text = truncate_start_to_width(text, width);
+    let padding = width.saturating_sub(text.width());
+    format!("{text}{}", " ".repeat(padding))
+}
+
+/// Pads the text with spaces to exactly the width, truncating its end if too wide
+pub fn fit_to_width(text: &str, width: usize) -> String {
+    let text = truncate_to_width(text, width);
+    let padding = width.saturating_sub(text.width());
+    format!("{text}{}", " ".repeat(padding))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fits_wide_glyphs() {
+        assert_eq!(truncate_to_width("ゲーム.lua", 5), "ゲー…");
+        assert_eq!(truncate_to_width("🦀🦀", 4), "🦀🦀");
+        assert_eq!(truncate_start_to_width("src/ゲーム.lua:12", 9), "….lua:12");
+        assert_eq!(pad_to_width("敵.lua", 8).width(), 8);
+    }
+}
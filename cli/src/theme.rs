@@ -0,0 +1,101 @@
+use ratatui::style::{Color, Modifier, Style};
+
+/// The named color-themes available for the
+/// `pico-build-rs` command-line interface
+///
+/// Set through the `theme` config-value or the `--theme` argument
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum Theme {
+    #[default]
+    Dark,
+    Light,
+    HighContrast,
+    /// Disables colors entirely, relying only on text-modifiers
+    None,
+}
+
+impl Theme {
+    /// Parses the theme-name as written in a config-file,
+    /// i.e. `"dark"`, `"light"`, `"high-contrast"` or `"none"`
+    pub fn from_name(name: &str) -> Option<Theme> {
+        <Theme as clap::ValueEnum>::from_str(name, true).ok()
+    }
+    pub const fn palette(self) -> Palette {
+        match self {
+            Theme::Dark => Palette {
+                error: Style::new().fg(Color::Red),
+                warn: Style::new().fg(Color::Yellow),
+                info: Style::new().fg(Color::Green),
+                debug: Style::new().fg(Color::Blue),
+                trace: Style::new().fg(Color::DarkGray),
+                muted: Style::new()
+                    .fg(Color::DarkGray)
+                    .add_modifier(Modifier::ITALIC),
+            },
+            Theme::Light => Palette {
+                error: Style::new().fg(Color::Red),
+                warn: Style::new().fg(Color::Magenta),
+                info: Style::new().fg(Color::Blue),
+                debug: Style::new().fg(Color::Cyan),
+                trace: Style::new().fg(Color::Gray),
+                muted: Style::new().fg(Color::Gray).add_modifier(Modifier::ITALIC),
+            },
+            Theme::HighContrast => Palette {
+                error: Style::new()
+                    .fg(Color::LightRed)
+                    .add_modifier(Modifier::BOLD),
+                warn: Style::new()
+                    .fg(Color::LightYellow)
+                    .add_modifier(Modifier::BOLD),
+                info: Style::new().fg(Color::LightGreen),
+                debug: Style::new().fg(Color::LightCyan),
+                trace: Style::new().fg(Color::White),
+                muted: Style::new().fg(Color::White).add_modifier(Modifier::ITALIC),
+            },
+            Theme::None => Palette {
+                error: Style::new().add_modifier(Modifier::BOLD.union(Modifier::REVERSED)),
+                warn: Style::new().add_modifier(Modifier::BOLD),
+                info: Style::new(),
+                debug: Style::new(),
+                trace: Style::new().add_modifier(Modifier::DIM),
+                muted: Style::new().add_modifier(Modifier::ITALIC),
+            },
+        }
+    }
+}
+
+/// The styles used when rendering, as picked by a [`Theme`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Palette {
+    pub error: Style,
+    pub warn: Style,
+    pub info: Style,
+    pub debug: Style,
+    pub trace: Style,
+    /// De-emphasized text, such as returned values in the log-panel
+    pub muted: Style,
+}
+
+impl Default for Palette {
+    fn default() -> Self {
+        Theme::default().palette()
+    }
+}
+
+impl From<Theme> for Palette {
+    fn from(theme: Theme) -> Self {
+        theme.palette()
+    }
+}
+
+impl Palette {
+    pub const fn level(&self, level: tracing::Level) -> Style {
+        match level {
+            tracing::Level::ERROR => self.error,
+            tracing::Level::WARN => self.warn,
+            tracing::Level::INFO => self.info,
+            tracing::Level::DEBUG => self.debug,
+            tracing::Level::TRACE => self.trace,
+        }
+    }
+}
@@ -0,0 +1,105 @@
+use std::io;
+use std::path;
+
+use pico_build_rs::todos::{self, TodoEntry};
+use ratatui::{
+    prelude::*,
+    widgets::{Block, List, ListItem},
+};
+
+use crate::text;
+
+/// The columns of the `file:line` location, so the markers line up
+const LOCATION_WIDTH: usize = 20;
+
+/// Holds the marker-comments last found in the source-directory
+#[derive(Debug)]
+pub struct TodoPanelStore {
+    markers: Box<[String]>,
+    entries: Vec<TodoEntry>,
+}
+
+impl TodoPanelStore {
+    pub fn new(markers: impl IntoIterator<Item = String>) -> TodoPanelStore {
+        TodoPanelStore {
+            markers: markers.into_iter().collect(),
+            entries: vec![],
+        }
+    }
+    /// Replaces the stored entries with a fresh scan of the directory
+    pub fn rescan<P: AsRef<path::Path> + ?Sized>(&mut self, src_dir: &P) -> io::Result<usize> {
+        self.entries = todos::scan_directory(src_dir, &self.markers)?;
+        Ok(self.entries.len())
+    }
+    pub fn entries(&self) -> &[TodoEntry] {
+        &self.entries
+    }
+}
+
+pub struct TodoPanelWidget<'a> {
+    entries: &'a [TodoEntry],
+}
+
+impl<'a> From<&'a TodoPanelStore> for TodoPanelWidget<'a> {
+    fn from(store: &'a TodoPanelStore) -> Self {
+        TodoPanelWidget {
+            entries: store.entries(),
+        }
+    }
+}
+
+impl Widget for TodoPanelWidget<'_> {
+    fn render(self, area: Rect, buf: &mut Buffer)
+    where
+        Self: Sized,
+    {
+        let items = self.entries.iter().map(
+            |TodoEntry {
+                 path,
+                 line_number,
+                 marker,
+                 text,
+             }| {
+                let file_name = path
+                    .file_name()
+                    .map(|file_name| file_name.to_string_lossy())
+                    .unwrap_or_default();
+                let location = format!("{file_name}:{line_number}");
+                ListItem::new(Line::from_iter([
+                    Span::raw(text::pad_to_width(&location, LOCATION_WIDTH)),
+                    Span::raw(" "),
+                    Span::raw(marker.as_str()).bold(),
+                    Span::raw(format!(" {text}")),
+                ]))
+            },
+        );
+        let list = List::new(items)
+            .block(Block::bordered().title(format!("todos ({})", self.entries.len())));
+        Widget::render(list, area, buf);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn aligns_markers_after_wide_file_names() {
+        let entry = |file_name: &str, line_number| TodoEntry {
+            path: path::PathBuf::from(file_name),
+            line_number,
+            marker: "TODO".to_string(),
+            text: "x".to_string(),
+        };
+        let store = TodoPanelStore {
+            markers: Box::default(),
+            entries: vec![entry("main.lua", 1), entry("敵🦀.lua", 12)],
+        };
+        let area = Rect::new(0, 0, 40, 4);
+        let mut buf = Buffer::empty(area);
+        TodoPanelWidget::from(&store).render(area, &mut buf);
+        let marker_column = |y| (0..area.width).find(|x| buf[(*x, y)].symbol() == "T");
+        assert!(marker_column(1).is_some());
+        assert_eq!(marker_column(1), marker_column(2));
+    }
+}
@@ -0,0 +1,77 @@
+use core::cell::Cell;
+use core::time::Duration;
+
+use std::path;
+use std::sync::mpsc;
+use std::time::Instant;
+
+use notify::{EventKind, RecursiveMode, Watcher};
+
+use crate::Action;
+use crate::event_bus::EventListener;
+
+/// How long the source-files must be unchanged before rebuilding,
+/// so an editor saving several files at once triggers a single rebuild
+///
+/// Counts towards the save-to-cart-written latency, so it is kept short
+const DEBOUNCE: Duration = Duration::from_millis(50);
+
+/// Emits [`Action::SourceChanged`] once the lua source-files stop changing
+pub struct WatchEventListener {
+    /// Stops watching when dropped
+    _watcher: notify::RecommendedWatcher,
+    event_rx: mpsc::Receiver<notify::Result<notify::Event>>,
+    /// The first change since the last rebuild, which the latency is measured from
+    first_change: Cell<Option<Instant>>,
+    last_change: Cell<Option<Instant>>,
+}
+
+impl WatchEventListener {
+    pub fn new<P: AsRef<path::Path> + ?Sized>(src_dir: &P) -> notify::Result<WatchEventListener> {
+        let (event_tx, event_rx) = mpsc::channel();
+        let mut watcher = notify::recommended_watcher(event_tx)?;
+        watcher.watch(src_dir.as_ref(), RecursiveMode::NonRecursive)?;
+        Ok(WatchEventListener {
+            _watcher: watcher,
+            event_rx,
+            first_change: Cell::new(None),
+            last_change: Cell::new(None),
+        })
+    }
+}
+
+/// Whether the event changed a lua source-file, ignoring the cart and its manifest
+fn is_source_change(event: &notify::Event) -> bool {
+    matches!(
+        event.kind,
+        EventKind::Create(_) | EventKind::Modify(_) | EventKind::Remove(_)
+    ) && event
+        .paths
+        .iter()
+        .any(|path| path.extension().is_some_and(|extension| extension == "lua"))
+}
+
+impl EventListener for WatchEventListener {
+    fn next_action(&self, _timeout: Duration) -> Option<Action> {
+        for event in self.event_rx.try_iter() {
+            match event {
+                Ok(event) if is_source_change(&event) => {
+                    tracing::debug!("Source-files changed: {:?}", event.paths);
+                    let now = Instant::now();
+                    self.first_change.set(self.first_change.get().or(Some(now)));
+                    self.last_change.set(Some(now));
+                }
+                Ok(_) => {}
+                Err(e) => tracing::warn!("File-watcher error: {e}"),
+            }
+        }
+        let last_change = self.last_change.get()?;
+        (last_change.elapsed() >= DEBOUNCE).then(|| {
+            self.last_change.set(None);
+            tracing::info!("Source-files changed, rebuilding");
+            Action::SourceChanged {
+                changed: self.first_change.take().unwrap_or(last_change),
+            }
+        })
+    }
+}
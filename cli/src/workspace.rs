@@ -0,0 +1,166 @@
+use anyhow::anyhow;
+
+use std::path;
+
+use pico_build_rs::paths;
+
+use crate::args::AppArgs;
+use crate::config::{self, AppConfiguration};
+
+/// Lists the member-projects, each a directory with its own `pico.toml` or `pico.json`
+///
+/// ```toml
+/// members = ["games/jumper", "games/racer"]
+/// ```
+pub const WORKSPACE_FILE_NAME: &str = "pico-workspace.toml";
+
+#[derive(Clone, Debug)]
+pub struct WorkspaceMember {
+    /// The directory-name of the member, as selected by `--project`
+    pub name: String,
+    pub root_dir: path::PathBuf,
+    /// The `[[cart]]` of the member's config-file, `None` for the first or only cart
+    pub target: Option<String>,
+}
+
+#[derive(Clone, Debug)]
+pub struct Workspace {
+    members: Vec<WorkspaceMember>,
+}
+
+impl Workspace {
+    /// Opens the workspace-file in the root-directory, if there is one
+    pub fn open(root_dir: &path::Path) -> anyhow::Result<Option<Workspace>> {
+        let workspace_path = root_dir.join(WORKSPACE_FILE_NAME);
+        if !workspace_path.exists() {
+            return Ok(None);
+        }
+        let members = config::try_from_path(&workspace_path)?
+            .get_array("members")
+            .map_err(|e| anyhow!("{}: {e}", workspace_path.display()))?
+            .into_iter()
+            .map(|member| {
+                let member_dir = root_dir.join(paths::from_config(&member.into_string()?));
+                let name = member_dir
+                    .file_name()
+                    .map(|name| name.to_string_lossy().into_owned())
+                    .ok_or_else(|| anyhow!("Invalid member {:?}", member_dir.display()))?;
+                Ok(WorkspaceMember {
+                    name,
+                    root_dir: member_dir,
+                    target: None,
+                })
+            })
+            .collect::<anyhow::Result<Vec<_>>>()
+            .map_err(|e| anyhow!("{}: {e}", workspace_path.display()))?;
+        if members.is_empty() {
+            return Err(anyhow!("{}: No members listed", workspace_path.display()));
+        }
+        Ok(Some(Workspace { members }))
+    }
+    /// The `[[cart]]`s of the config-file in the root-directory as the members,
+    /// `None` without any, for switching between the carts like between projects
+    pub fn of_targets(root_dir: &path::Path) -> anyhow::Result<Option<Workspace>> {
+        let members: Vec<WorkspaceMember> = config::target_names(root_dir)?
+            .into_iter()
+            .map(|name| WorkspaceMember {
+                root_dir: root_dir.to_path_buf(),
+                target: Some(name.clone()),
+                name,
+            })
+            .collect();
+        Ok((!members.is_empty()).then_some(Workspace { members }))
+    }
+    pub fn members(&self) -> &[WorkspaceMember] {
+        &self.members
+    }
+    pub fn find(&self, name: &str) -> anyhow::Result<&WorkspaceMember> {
+        self.members
+            .iter()
+            .find(|member| member.name == name)
+            .ok_or_else(|| anyhow!("Unknown project {name:?} in workspace"))
+    }
+}
+
+/// Holds the workspace and the member currently open in the terminal user-interface
+#[derive(Debug)]
+pub struct ProjectStore {
+    workspace: Workspace,
+    active: usize,
+}
+
+impl ProjectStore {
+    pub fn new(workspace: Workspace, active_name: Option<&str>) -> ProjectStore {
+        let active = active_name
+            .and_then(|name| {
+                workspace
+                    .members
+                    .iter()
+                    .position(|member| member.name == name)
+            })
+            .unwrap_or_default();
+        ProjectStore { workspace, active }
+    }
+    pub fn active(&self) -> &WorkspaceMember {
+        &self.workspace.members[self.active]
+    }
+    /// Selects the member after the active one, wrapping around
+    pub fn select_next(&mut self) -> &WorkspaceMember {
+        self.active = (self.active + 1) % self.workspace.members.len();
+        self.active()
+    }
+}
+
+/// Runs the command for each member, printing a report-line per member
+///
+/// Without a workspace-file, each `[[cart]]` of the config-file is run as a member
+///
+/// Fails if the command failed for any member, after running it for all of them
+pub fn run_for_each_member(
+    args: &AppArgs,
+    mut run: impl FnMut(&AppConfiguration) -> anyhow::Result<()>,
+) -> anyhow::Result<()> {
+    if args.get_config_json().is_some() {
+        return Err(anyhow!(
+            "--all reads the config-files of the projects, and can not be combined with a JSON-configuration"
+        ));
+    }
+    let root_dir = args.get_root_directory()?;
+    let workspace = match Workspace::open(&root_dir)? {
+        Some(workspace) => workspace,
+        None => Workspace::of_targets(&root_dir)?.ok_or_else(|| {
+            anyhow!(
+                "No {WORKSPACE_FILE_NAME} or [[cart]] found in {}",
+                root_dir.display()
+            )
+        })?,
+    };
+    let reports: Vec<(&str, anyhow::Result<()>)> = workspace
+        .members()
+        .iter()
+        .map(|member| {
+            println!("== {} ==", member.name);
+            let result = AppConfiguration::for_member(args, member).and_then(|cfg| run(&cfg));
+            (member.name.as_str(), result)
+        })
+        .collect();
+    println!("== summary ==");
+    let mut failures = 0;
+    for (name, result) in &reports {
+        match result {
+            Ok(()) => println!("{name}: ok"),
+            Err(e) => {
+                failures += 1;
+                println!("{name}: failed: {e}");
+            }
+        }
+    }
+    if failures == 0 {
+        Ok(())
+    } else {
+        Err(anyhow!(
+            "{failures} of {} project(s) failed",
+            workspace.members().len()
+        ))
+    }
+}
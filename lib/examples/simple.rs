@@ -36,22 +36,41 @@ fn get_cartridge_main_file_path<P: AsRef<path::Path>>(file_name: P) -> Option<pa
         })
 }
 
-fn open_cartridge_file<P: AsRef<path::Path>>(file_name: P) -> Option<fs::File> {
+/// Reads the cart with each constructor of the cart-model, all of which should agree
+fn read_cartridge_file<P: AsRef<path::Path>>(
+    file_name: P,
+) -> Option<pico_8_cart_model::CartData<'static>> {
     let Some(cartridge_file_path) = get_cartridge_main_file_path(file_name) else {
         tracing::warn!("Failed to find cargo manifest-directory in environment");
         return None;
     };
-    fs::File::open(cartridge_file_path)
-        .inspect_err(|e| tracing::warn!("Failed to open p8-file: {e}"))
-        .ok()
-}
+    let cart_bytes = fs::read(&cartridge_file_path)
+        .inspect_err(|e| tracing::warn!("Failed to read p8-file: {e}"))
+        .ok()?;
+    let cart_text = String::from_utf8_lossy(&cart_bytes);
 
-fn read_cartridge_file<P: AsRef<path::Path>>(
-    file_name: P,
-) -> Option<pico_8_cart_model::CartData<'static>> {
-    open_cartridge_file(file_name).and_then(|cartridge_file| {
-        pico_8_cart_model::CartData::from_file(cartridge_file)
-            .inspect_err(|e| tracing::warn!("Failed to read cartridge file: {e}"))
-            .ok()
-    })
+    let from_bytes = pico_8_cart_model::CartData::from_bytes(&cart_bytes);
+    let from_str = pico_8_cart_model::CartData::from_str(&cart_text);
+    let from_reader = pico_8_cart_model::CartData::from_reader(cart_bytes.as_slice());
+    let from_path = pico_8_cart_model::CartData::from_path(&cartridge_file_path);
+    for (constructor, cart) in [
+        (
+            "from_bytes",
+            from_bytes.map(pico_8_cart_model::CartData::into_owned),
+        ),
+        (
+            "from_str",
+            from_str.map(pico_8_cart_model::CartData::into_owned),
+        ),
+        ("from_reader", from_reader),
+    ] {
+        match (cart, &from_path) {
+            (Ok(cart), Ok(from_path)) if format!("{cart:?}") == format!("{from_path:?}") => {}
+            (Ok(_), Ok(_)) => tracing::warn!("{constructor} read another cart than from_path"),
+            (cart, _) => tracing::warn!("{constructor} and from_path disagree: {:?}", cart.err()),
+        }
+    }
+    from_path
+        .inspect_err(|e| tracing::warn!("Failed to read cartridge file: {e}"))
+        .ok()
 }
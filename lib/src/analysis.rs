@@ -0,0 +1,133 @@
+//! Statistics of a cart, shown by the analyze action and the `analyze` command
+
+use pico_8_cart_model::{CartData, HexError, SectionType, Tab};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TabStats {
+    pub tab_index: usize,
+    /// The title of the tab, see [`Tab::title`]
+    pub title: Option<String>,
+    pub lines: usize,
+    pub tokens: usize,
+    /// The number of p8scii-characters, one byte each
+    pub chars: usize,
+}
+
+impl TabStats {
+    fn new(tab_index: usize, tab: &Tab<'_>, tokens: usize) -> TabStats {
+        let code_data = tab.code_data.as_ref();
+        TabStats {
+            tab_index,
+            title: tab.title().map(str::to_string),
+            lines: code_data.split(|byte| *byte == b'\n').count()
+                - usize::from(code_data.ends_with(b"\n")),
+            tokens,
+            chars: code_data.len(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CartAnalysis {
+    /// The stats of each tab with code
+    pub tabs: Vec<TabStats>,
+    /// The size of each section present in the cart, as written in the `.p8`-file
+    pub section_sizes: Vec<(SectionType, usize)>,
+    /// The number of sprites with any non-transparent pixel
+    pub sprites_used: usize,
+    pub sprite_count: usize,
+    /// The number of non-zero tiles, including the rows shared with the sprite-sheet
+    pub tiles_used: usize,
+    pub tile_count: usize,
+}
+
+impl CartAnalysis {
+    pub fn token_count(&self) -> usize {
+        self.tabs.iter().map(|tab| tab.tokens).sum()
+    }
+    pub fn section_size(&self, section: SectionType) -> Option<usize> {
+        self.section_sizes
+            .iter()
+            .find(|(section_type, _)| *section_type == section)
+            .map(|(_, size)| *size)
+    }
+}
+
+/// Decodes the sprite-sheet and map to compute the statistics of the cart
+#[tracing::instrument(level = "debug", skip(cart))]
+pub fn analyze(cart: &CartData<'_>) -> Result<CartAnalysis, HexError> {
+    let tab_token_counts = cart.tab_token_counts();
+    let tabs: Vec<TabStats> = cart
+        .code_tabs()
+        .iter_present()
+        .map(|(tab_index, tab)| {
+            let tokens = tab_token_counts[tab_index].unwrap_or_default();
+            TabStats::new(tab_index, tab, tokens)
+        })
+        .collect();
+    let lua_size = cart
+        .code_tabs()
+        .iter_present()
+        .map(|(_, tab)| tab.code_data.len())
+        .sum();
+    let section_sizes = core::iter::once((SectionType::Lua, lua_size))
+        .chain(cart.format_spec().asset_sections().filter_map(|section| {
+            cart.section_data(section)
+                .map(|section_data| (section, section_data.len()))
+        }))
+        .collect();
+
+    let gfx_sheet = cart.gfx_sheet()?;
+    let sprite_count = gfx_sheet.sprite_count();
+    let sprites_used = (0..sprite_count)
+        .filter_map(|n| gfx_sheet.sprite(n))
+        .filter(|sprite| sprite.iter().flatten().any(|color| *color != 0))
+        .count();
+    let map_data = cart.map_data()?;
+    let (tiles_used, tile_count) = map_data
+        .rows()
+        .flatten()
+        .fold((0, 0), |(tiles_used, tile_count), tile| {
+            (tiles_used + usize::from(*tile != 0), tile_count + 1)
+        });
+
+    Ok(CartAnalysis {
+        tabs,
+        section_sizes,
+        sprites_used,
+        sprite_count,
+        tiles_used,
+        tile_count,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn analyzes_tabs_and_assets() {
+        const CART: &[u8] = b"pico-8 cartridge // http://www.pico-8.com
+version 43
+__lua__
+x=1
+print(x)
+-->8
+y=2
+__gfx__
+00000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000
+00700000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000
+__map__
+0001000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000
+";
+        let cart = CartData::from_cart_source(CART).unwrap();
+        let analysis = analyze(&cart).unwrap();
+        assert_eq!(analysis.tabs.len(), 2);
+        assert_eq!(analysis.tabs[0].lines, 2);
+        assert_eq!(analysis.tabs[1].chars, b"y=2\n".len());
+        assert_eq!(analysis.sprites_used, 1);
+        assert_eq!(analysis.tiles_used, 1);
+        assert!(analysis.section_size(SectionType::Map).is_some());
+        assert_eq!(analysis.section_size(SectionType::Sfx), None);
+    }
+}
@@ -0,0 +1,555 @@
+//! Importing png-spritesheets into `__gfx__`, and Tiled maps into `__map__`, as a step
+//! of the build
+//!
+//! Each `.png`, `.ase` and `.aseprite` in the assets-directory is drawn into the
+//! sprite-sheet of the compiled cart in order of file-name, at the sprite configured for
+//! it or else at sprite 0, so sprites can be drawn in Aseprite or GIMP instead of the
+//! sprite-editor of pico-8. The pixels are mapped to the nearest color of the palette,
+//! see [`GfxSheet::import_png`](pico_8_cart_model::GfxSheet::import_png). The frames of
+//! an aseprite-file are drawn side by side, see [`AseFile`]. The image configured as the
+//! label is drawn into `__label__` instead
+//!
+//! Each `.tmx` and `.csv` in the assets-directory is then set into the map, at the tile
+//! configured for it or else at tile 0,0, see [`tiled`](crate::tiled). Maps reaching
+//! below row 31 overwrite the lower half of the sprite-sheet
+//!
+//! Sprites drawn in the sprite-editor of pico-8 can be locked, so the images never
+//! overwrite them: the locked sprites of the cart are restored over each image drawn
+//! across them, with a warning. Regions of the map drawn in the map-editor are locked
+//! alike
+
+use core::fmt;
+use core::ops::RangeInclusive;
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path;
+
+use pico_8_cart_model::gfx::{GfxImport, Sprite};
+use pico_8_cart_model::label::LabelPngError;
+use pico_8_cart_model::{
+    AseError, AseFile, CartData, GfxPngError, GfxSheet, HexError, LabelImage, MapData, MapImport,
+};
+
+use crate::paths;
+use crate::tiled::{TiledError, TiledLayer};
+
+/// The assets-directory, relative to the project-directory, if not configured
+pub const DEFAULT_ASSETS_DIR: &str = "assets";
+
+#[derive(Debug)]
+pub enum AssetError {
+    /// The image, or the assets-directory, could not be read or drawn
+    Image {
+        path: path::PathBuf,
+        error: GfxPngError,
+    },
+    /// The aseprite-file could not be read
+    Aseprite {
+        path: path::PathBuf,
+        error: AseError,
+    },
+    /// The label could not be read, or is not 128x128 pixels
+    Label {
+        path: path::PathBuf,
+        error: LabelPngError,
+    },
+    /// The map could not be read, or set into the map of the cart
+    Map {
+        path: path::PathBuf,
+        error: TiledError,
+    },
+    /// The sprite-sheet of the cart could not be decoded
+    Hex(HexError),
+}
+
+impl From<HexError> for AssetError {
+    fn from(v: HexError) -> Self {
+        Self::Hex(v)
+    }
+}
+
+impl fmt::Display for AssetError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let description = "Asset error";
+        let reason = match self {
+            AssetError::Image { path, error } => format!("{}: {error}", paths::display(path)),
+            AssetError::Aseprite { path, error } => {
+                format!("{}: {error}", paths::display(path))
+            }
+            AssetError::Label { path, error } => format!("{}: {error}", paths::display(path)),
+            AssetError::Map { path, error } => format!("{}: {error}", paths::display(path)),
+            AssetError::Hex(e) => e.to_string(),
+        };
+        f.write_fmt(format_args!("{description}: {reason}"))
+    }
+}
+
+impl core::error::Error for AssetError {}
+
+impl AssetError {
+    /// The stable diagnostic-code, see `pico-build-rs explain`
+    pub const fn code(&self) -> &'static str {
+        match self {
+            AssetError::Image { error, .. } => error.code(),
+            AssetError::Aseprite { error, .. } => error.code(),
+            AssetError::Label { error, .. } => error.code(),
+            AssetError::Map { error, .. } => error.code(),
+            AssetError::Hex(e) => e.code(),
+        }
+    }
+}
+
+/// A rectangle of tiles of the map
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MapRegion {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+}
+
+impl MapRegion {
+    /// The positions of the tiles of the region, row by row
+    fn positions(&self) -> impl Iterator<Item = (usize, usize)> {
+        let MapRegion {
+            x,
+            y,
+            width,
+            height,
+        } = *self;
+        (y..y + height).flat_map(move |tile_y| (x..x + width).map(move |tile_x| (tile_x, tile_y)))
+    }
+}
+
+/// What [`AssetImport::import_into`] imported from a file
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Imported {
+    Image(GfxImport),
+    Label,
+    Map(MapImport),
+}
+
+impl fmt::Display for Imported {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Imported::Image(import) => import.fmt(f),
+            Imported::Label => f.write_str("the label"),
+            Imported::Map(import) => import.fmt(f),
+        }
+    }
+}
+
+/// The images and maps imported by the build, from the `[assets]` section of the
+/// config-file
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AssetImport {
+    pub assets_dir: path::PathBuf,
+    /// The sprite each image is drawn at, by its file-stem, e.g. `player` for `player.png`
+    pub sprites: BTreeMap<String, usize>,
+    /// The tile each map is placed at, by its file-stem, e.g. `level` for `level.tmx`
+    pub maps: BTreeMap<String, (usize, usize)>,
+    /// The image drawn into `__label__` instead of the sprite-sheet, within the
+    /// assets-directory
+    pub label: Option<path::PathBuf>,
+    /// The sprites kept from the cart, e.g. `0..=15` if drawn in pico-8
+    pub locked_sprites: Vec<RangeInclusive<usize>>,
+    /// The regions of the map kept from the cart
+    pub locked_maps: Vec<MapRegion>,
+}
+
+impl AssetImport {
+    pub fn new<P: Into<path::PathBuf>>(assets_dir: P) -> AssetImport {
+        AssetImport {
+            assets_dir: assets_dir.into(),
+            sprites: BTreeMap::new(),
+            maps: BTreeMap::new(),
+            label: None,
+            locked_sprites: Vec::new(),
+            locked_maps: Vec::new(),
+        }
+    }
+    pub fn with_sprite<S: Into<String>>(mut self, file_stem: S, sprite: usize) -> AssetImport {
+        self.sprites.insert(file_stem.into(), sprite);
+        self
+    }
+    pub fn with_map<S: Into<String>>(mut self, file_stem: S, x: usize, y: usize) -> AssetImport {
+        self.maps.insert(file_stem.into(), (x, y));
+        self
+    }
+    /// Keeps the sprites from the cart, instead of drawing the images over them
+    pub fn with_locked_sprites(mut self, sprites: RangeInclusive<usize>) -> AssetImport {
+        self.locked_sprites.push(sprites);
+        self
+    }
+    /// Keeps the region of the map from the cart, instead of setting the maps into it
+    pub fn with_locked_map(mut self, region: MapRegion) -> AssetImport {
+        self.locked_maps.push(region);
+        self
+    }
+    /// Draws the image, relative to the assets-directory, into the label
+    pub fn with_label<P: AsRef<path::Path>>(mut self, label_path: P) -> AssetImport {
+        self.label = Some(self.assets_dir.join(label_path));
+        self
+    }
+    /// The `.png`, `.ase` and `.aseprite` files of the assets-directory sorted by name,
+    /// without the label, none if it does not exist
+    pub fn image_files(&self) -> io::Result<Vec<path::PathBuf>> {
+        let mut image_files = self.files_with_extensions(&["png", "ase", "aseprite"])?;
+        image_files.retain(|image_path| Some(image_path) != self.label.as_ref());
+        Ok(image_files)
+    }
+    /// The `.tmx` and `.csv` files of the assets-directory sorted by name, none if it
+    /// does not exist
+    pub fn map_files(&self) -> io::Result<Vec<path::PathBuf>> {
+        self.files_with_extensions(&["tmx", "csv"])
+    }
+    fn files_with_extensions(&self, extensions: &[&str]) -> io::Result<Vec<path::PathBuf>> {
+        let dir_entries = match fs::read_dir(&self.assets_dir) {
+            Ok(dir_entries) => dir_entries,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e),
+        };
+        let mut files = Vec::new();
+        for dir_entry in dir_entries {
+            let path = dir_entry?.path();
+            if path.extension().is_some_and(|extension| {
+                extensions
+                    .iter()
+                    .any(|other| extension.eq_ignore_ascii_case(other))
+            }) {
+                files.push(path);
+            }
+        }
+        files.sort();
+        Ok(files)
+    }
+    /// The sprite the image is drawn at, 0 if not configured
+    pub fn sprite_of(&self, png_path: &path::Path) -> usize {
+        png_path
+            .file_stem()
+            .and_then(|file_stem| self.sprites.get(file_stem.to_string_lossy().as_ref()))
+            .copied()
+            .unwrap_or_default()
+    }
+    /// The tile the map is placed at, 0,0 if not configured
+    pub fn map_origin_of(&self, map_path: &path::Path) -> (usize, usize) {
+        map_path
+            .file_stem()
+            .and_then(|file_stem| self.maps.get(file_stem.to_string_lossy().as_ref()))
+            .copied()
+            .unwrap_or_default()
+    }
+    /// Draws each image into the sprite-sheet of the cart, then sets each map into its
+    /// map, returning what was imported
+    ///
+    /// The sections are left as is without any images or maps. Tiles above 255 are set
+    /// to 0, with a warning
+    #[tracing::instrument(level = "debug", skip(self, cart))]
+    pub fn import_into(
+        &self,
+        cart: &mut CartData<'_>,
+    ) -> Result<Vec<(path::PathBuf, Imported)>, AssetError> {
+        let mut imports = self.import_images_into(cart)?;
+        if let Some(label_path) = &self.label {
+            cart.set_label_image(&read_label(label_path)?);
+            imports.push((label_path.clone(), Imported::Label));
+        }
+        imports.extend(self.import_maps_into(cart)?);
+        Ok(imports)
+    }
+    fn import_images_into(
+        &self,
+        cart: &mut CartData<'_>,
+    ) -> Result<Vec<(path::PathBuf, Imported)>, AssetError> {
+        let image_files = self.image_files().map_err(|e| AssetError::Image {
+            path: self.assets_dir.clone(),
+            error: GfxPngError::Decoding(e.into()),
+        })?;
+        if image_files.is_empty() {
+            return Ok(Vec::new());
+        }
+        let mut gfx_sheet = cart.gfx_sheet()?;
+        let locked_sprites: Vec<(usize, Sprite)> = self
+            .locked_sprites
+            .iter()
+            .cloned()
+            .flatten()
+            .filter_map(|n| gfx_sheet.sprite(n).map(|sprite| (n, sprite)))
+            .collect();
+        let mut imports = Vec::with_capacity(image_files.len());
+        for image_path in image_files {
+            let import = import_image(&mut gfx_sheet, &image_path, self.sprite_of(&image_path))?;
+            let overwritten: Vec<usize> = locked_sprites
+                .iter()
+                .filter(|(n, sprite)| gfx_sheet.sprite(*n).as_ref() != Some(sprite))
+                .map(|(n, _)| *n)
+                .collect();
+            if let (Some(first), Some(last)) = (overwritten.first(), overwritten.last()) {
+                tracing::warn!(
+                    "{}: differs from the cart in {} locked sprite(s), {first} to {last}, keeping those of the cart",
+                    paths::display(&image_path),
+                    overwritten.len()
+                );
+                for (n, sprite) in &locked_sprites {
+                    gfx_sheet.set_sprite(*n, sprite);
+                }
+            }
+            imports.push((image_path, Imported::Image(import)));
+        }
+        cart.set_gfx_sheet(&gfx_sheet);
+        Ok(imports)
+    }
+    fn import_maps_into(
+        &self,
+        cart: &mut CartData<'_>,
+    ) -> Result<Vec<(path::PathBuf, Imported)>, AssetError> {
+        let map_files = self.map_files().map_err(|e| AssetError::Map {
+            path: self.assets_dir.clone(),
+            error: e.into(),
+        })?;
+        if map_files.is_empty() {
+            return Ok(Vec::new());
+        }
+        let mut map_data = cart.map_data()?;
+        let locked_tiles: Vec<(usize, usize, u8)> = self
+            .locked_maps
+            .iter()
+            .flat_map(MapRegion::positions)
+            .filter_map(|(x, y)| map_data.tile_at(x, y).map(|tile| (x, y, tile)))
+            .collect();
+        let mut imports = Vec::with_capacity(map_files.len());
+        for map_path in map_files {
+            match self.import_map(&map_path, &mut map_data) {
+                Ok(import) => {
+                    let overwritten = locked_tiles
+                        .iter()
+                        .filter(|(x, y, tile)| map_data.tile_at(*x, *y) != Some(*tile))
+                        .count();
+                    if overwritten > 0 {
+                        tracing::warn!(
+                            "{}: differs from the cart in {overwritten} tile(s) of the locked map-regions, keeping those of the cart",
+                            paths::display(&map_path)
+                        );
+                        for (x, y, tile) in &locked_tiles {
+                            map_data.set_tile(*x, *y, *tile);
+                        }
+                    }
+                    if let Some((x, y, tile)) = import.oversized.first() {
+                        tracing::warn!(
+                            "{}: {} tiles above 255 were set to 0, the first {tile} at {x},{y}",
+                            paths::display(&map_path),
+                            import.oversized.len()
+                        );
+                    }
+                    imports.push((map_path, Imported::Map(import)));
+                }
+                Err(error) => {
+                    return Err(AssetError::Map {
+                        path: map_path,
+                        error,
+                    });
+                }
+            }
+        }
+        cart.set_map_data(&map_data)?;
+        Ok(imports)
+    }
+    fn import_map(
+        &self,
+        map_path: &path::Path,
+        map_data: &mut MapData,
+    ) -> Result<MapImport, TiledError> {
+        let text = fs::read_to_string(map_path)?;
+        let layer = match map_path.extension() {
+            Some(extension) if extension.eq_ignore_ascii_case("tmx") => {
+                TiledLayer::from_tmx(&text)?
+            }
+            _ => TiledLayer::from_csv(&text)?,
+        };
+        let (x, y) = self.map_origin_of(map_path);
+        map_data
+            .import_tiles(x, y, layer.width, &layer.tiles)
+            .ok_or(TiledError::OutOfMap {
+                x,
+                y,
+                width: layer.width,
+                height: layer.height,
+            })
+    }
+}
+
+fn is_aseprite(image_path: &path::Path) -> bool {
+    image_path.extension().is_some_and(|extension| {
+        extension.eq_ignore_ascii_case("ase") || extension.eq_ignore_ascii_case("aseprite")
+    })
+}
+
+fn read_aseprite(image_path: &path::Path) -> Result<AseFile, AssetError> {
+    fs::File::open(image_path)
+        .map_err(AseError::from)
+        .and_then(|ase_file| AseFile::read(io::BufReader::new(ase_file)))
+        .map_err(|error| AssetError::Aseprite {
+            path: image_path.to_path_buf(),
+            error,
+        })
+}
+
+/// Draws the png, or the frames of the aseprite-file, into the sheet at the sprite
+fn import_image(
+    gfx_sheet: &mut GfxSheet,
+    image_path: &path::Path,
+    sprite: usize,
+) -> Result<GfxImport, AssetError> {
+    let import = if is_aseprite(image_path) {
+        let ase = read_aseprite(image_path)?;
+        gfx_sheet.import_rgba(
+            ase.width * ase.frames.len(),
+            ase.height,
+            &ase.frame_strip(),
+            sprite,
+        )
+    } else {
+        fs::File::open(image_path)
+            .map_err(|e| GfxPngError::Decoding(e.into()))
+            .and_then(|png_file| gfx_sheet.import_png(io::BufReader::new(png_file), sprite))
+    };
+    import.map_err(|error| AssetError::Image {
+        path: image_path.to_path_buf(),
+        error,
+    })
+}
+
+/// Reads the 128x128 png, or the first frame of the aseprite-file, as a label
+fn read_label(label_path: &path::Path) -> Result<LabelImage, AssetError> {
+    let label_image = if is_aseprite(label_path) {
+        let ase = read_aseprite(label_path)?;
+        ase.frames
+            .first()
+            .and_then(|rgba| {
+                let rgb: Vec<u8> = rgba
+                    .chunks_exact(4)
+                    .flat_map(|pixel| [pixel[0], pixel[1], pixel[2]])
+                    .collect();
+                LabelImage::from_rgb(&rgb)
+            })
+            .ok_or(LabelPngError::InvalidSize {
+                width: ase.width as u32,
+                height: ase.height as u32,
+            })
+    } else {
+        fs::File::open(label_path)
+            .map_err(|e| LabelPngError::Decoding(e.into()))
+            .and_then(|png_file| LabelImage::read_png(io::BufReader::new(png_file)))
+    };
+    label_image.map_err(|error| AssetError::Label {
+        path: label_path.to_path_buf(),
+        error,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use pico_8_cart_model::LabelImage;
+
+    #[test]
+    fn imports_by_file_name() {
+        let assets_dir = std::env::temp_dir().join(format!("pico-assets-{}", std::process::id()));
+        fs::create_dir_all(&assets_dir).unwrap();
+        let mut image = LabelImage::new();
+        image.set_pixel(5, 7, 8);
+        let mut png_data = Vec::new();
+        image.write_png(&mut png_data).unwrap();
+        fs::write(assets_dir.join("sheet.png"), &png_data).unwrap();
+
+        let mut cart = CartData::from_bytes(
+            b"pico-8 cartridge // http://www.pico-8.com\nversion 43\n__lua__\n__gfx__\n",
+        )
+        .unwrap();
+        let asset_import = AssetImport::new(&assets_dir);
+        let imports = asset_import.import_into(&mut cart).unwrap();
+        assert_eq!(imports.len(), 1);
+        assert_eq!(cart.gfx_sheet().unwrap().get_pixel(5, 7), Some(8));
+
+        fs::write(assets_dir.join("tiles.png"), &png_data).unwrap();
+        let asset_import = asset_import.with_sprite("tiles", 16);
+        assert!(matches!(
+            asset_import.import_into(&mut cart),
+            Err(AssetError::Image {
+                error: GfxPngError::OutOfSheet { sprite: 16, .. },
+                ..
+            })
+        ));
+
+        fs::remove_file(assets_dir.join("tiles.png")).unwrap();
+        fs::write(assets_dir.join("level.csv"), "-1,7\n300,1\n").unwrap();
+        let asset_import = asset_import.with_map("level", 3, 31);
+        let imports = asset_import.import_into(&mut cart).unwrap();
+        assert!(matches!(&imports[1].1, Imported::Map(import) if import.oversized.len() == 1));
+        let map_data = cart.map_data().unwrap();
+        assert_eq!(map_data.tile_at(4, 31), Some(7));
+        assert_eq!(map_data.tile_at(4, 32), Some(1));
+
+        fs::rename(assets_dir.join("sheet.png"), assets_dir.join("title.png")).unwrap();
+        let asset_import = asset_import.with_label("title.png");
+        assert_eq!(
+            asset_import.image_files().unwrap(),
+            [] as [path::PathBuf; 0]
+        );
+        asset_import.import_into(&mut cart).unwrap();
+        assert_eq!(
+            cart.label_image().unwrap().unwrap().get_pixel(5, 7),
+            Some(8)
+        );
+
+        fs::remove_dir_all(&assets_dir).unwrap();
+    }
+
+    #[test]
+    fn keeps_locked_regions() {
+        let assets_dir =
+            std::env::temp_dir().join(format!("pico-assets-locked-{}", std::process::id()));
+        fs::create_dir_all(&assets_dir).unwrap();
+        let mut image = LabelImage::new();
+        image.set_pixel(9, 0, 8);
+        let mut png_data = Vec::new();
+        image.write_png(&mut png_data).unwrap();
+        fs::write(assets_dir.join("sheet.png"), &png_data).unwrap();
+
+        // Sprite 0 and 1 drawn in pico-8, of which only sprite 1 is locked
+        let cart_text = format!(
+            "pico-8 cartridge // http://www.pico-8.com\nversion 43\n__lua__\n__gfx__\n0c0000000c{}\n",
+            "0".repeat(118)
+        );
+        let mut cart = CartData::from_bytes(cart_text.as_bytes()).unwrap();
+        let asset_import = AssetImport::new(&assets_dir).with_locked_sprites(1..=1);
+        asset_import.import_into(&mut cart).unwrap();
+        let gfx_sheet = cart.gfx_sheet().unwrap();
+        assert_eq!(gfx_sheet.get_pixel(1, 0), Some(0));
+        assert_eq!(gfx_sheet.get_pixel(9, 0), Some(12));
+
+        // Tile 1,0 set in pico-8, within the locked region
+        fs::remove_file(assets_dir.join("sheet.png")).unwrap();
+        fs::write(assets_dir.join("level.csv"), "5,5,5\n5,5,5\n").unwrap();
+        let mut map_data = cart.map_data().unwrap();
+        map_data.set_tile(1, 0, 9);
+        cart.set_map_data(&map_data).unwrap();
+        let asset_import = asset_import.with_locked_map(MapRegion {
+            x: 1,
+            y: 0,
+            width: 2,
+            height: 1,
+        });
+        asset_import.import_into(&mut cart).unwrap();
+        let map_data = cart.map_data().unwrap();
+        assert_eq!(
+            [(0, 0), (1, 0), (2, 0), (1, 1)].map(|(x, y)| map_data.tile_at(x, y)),
+            [Some(5), Some(9), Some(0), Some(5)]
+        );
+
+        fs::remove_dir_all(&assets_dir).unwrap();
+    }
+}
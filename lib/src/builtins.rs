@@ -0,0 +1,85 @@
+//! The names of the pico-8 API, by the cart-version which added them
+//!
+//! Carts are saved with the version of the cart-format of the pico-8 release saving them,
+//! so the functions available to a cart are those added at or below its version. New
+//! releases append a group with their cart-version, keeping [`BUILTINS`] sorted by it.
+//! Functions superseded by an operator are listed in [`DEPRECATED`] as well
+
+/// The names added by each cart-version separated by whitespace, sorted by version
+pub const BUILTINS: &[(usize, &str)] = &[
+    (
+        0,
+        "camera circ circfill clip cls color cursor fget fillp flip fset line map mget mset \
+         pal palt pget print pset rect rectfill sget spr sset sspr \
+         add all count del foreach pairs \
+         btn btnp music sfx \
+         abs atan2 band bnot bor bxor cos flr max mid min rnd sgn shl shr sin sqrt srand \
+         sub tonum tostr \
+         cartdata cstore dget dset memcpy memset peek poke reload \
+         cd extcmd folder load ls menuitem printh run stat stop t time \
+         assert cocreate coresume costatus getmetatable rawequal rawget rawlen rawset \
+         setmetatable type yield",
+    ),
+    (
+        18,
+        "ceil chr deli lshr ord oval ovalfill peek2 peek4 poke2 poke4 rotl rotr split tline",
+    ),
+    (29, "serial"),
+    (33, "pack select unpack"),
+    (42, "rrect rrectfill"),
+];
+
+/// The functions superseded by an operator, by the cart-version which added the
+/// operator, and the operator
+///
+/// They still work, but cost a call where the operator costs nothing
+pub const DEPRECATED: &[(usize, &str, &str)] = &[
+    (18, "band", "&"),
+    (18, "bor", "|"),
+    (18, "bxor", "^^"),
+    (18, "bnot", "~"),
+    (18, "shl", "<<"),
+    (18, "shr", ">>"),
+    (18, "lshr", ">>>"),
+    (18, "rotl", "<<>"),
+    (18, "rotr", ">><"),
+];
+
+/// Whether the name is a function of the API available to carts of the version
+pub fn is_builtin(name: &str, cart_version: usize) -> bool {
+    added_in(name).is_some_and(|version| version <= cart_version)
+}
+
+/// The cart-version which added the function of the API, `None` if it is not one
+pub fn added_in(name: &str) -> Option<usize> {
+    BUILTINS
+        .iter()
+        .find(|(_, names)| names.split_whitespace().any(|other| other == name))
+        .map(|(version, _)| *version)
+}
+
+/// The operator superseding the function in carts of the version, if it is deprecated
+pub fn replacement(name: &str, cart_version: usize) -> Option<&'static str> {
+    DEPRECATED
+        .iter()
+        .find(|(version, deprecated, _)| *deprecated == name && *version <= cart_version)
+        .map(|(_, _, operator)| *operator)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builtins_by_version() {
+        assert!(BUILTINS.is_sorted_by_key(|(version, _)| *version));
+        assert!(is_builtin("map", 0));
+        assert!(!is_builtin("split", 16));
+        assert!(is_builtin("split", 18));
+        assert!(is_builtin("rrect", 43));
+        assert!(!is_builtin("player", 43));
+        assert_eq!(added_in("serial"), Some(29));
+        assert_eq!(replacement("shl", 43), Some("<<"));
+        assert_eq!(replacement("shl", 16), None);
+    }
+}
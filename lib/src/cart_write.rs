@@ -0,0 +1,231 @@
+//! Writing the compiled cart while pico-8 may have it open
+//!
+//! The cart is written to a temporary file next to it, which is then renamed over it,
+//! so pico-8 never loads a half-written cart. On windows the rename fails with a
+//! sharing-violation while pico-8 holds the cart, which is retried with a backoff
+//!
+//! The temporary file is synced to disk before the rename, so a crash leaves either
+//! the old or the new cart, and the replaced cart can be kept as `game.p8.bak`
+
+use core::fmt;
+use core::time::Duration;
+
+use std::fs;
+use std::io::{self, Write};
+use std::path;
+use std::thread;
+
+/// Appended to the cart-name, for the temporary file the cart is written to first
+pub const TEMP_SUFFIX: &str = ".tmp";
+
+/// Appended to the cart-name, for the backups of the carts it replaced
+pub const BACKUP_SUFFIX: &str = ".bak";
+
+/// How many times the rename is attempted before giving up on a locked cart
+const RETRY_ATTEMPTS: u32 = 5;
+/// Doubled after each failed attempt, giving up after ~600ms
+const INITIAL_BACKOFF: Duration = Duration::from_millis(20);
+
+#[derive(Debug)]
+pub enum CartWriteError {
+    /// The cart stayed locked by another process, usually pico-8, through all attempts
+    Locked { path: path::PathBuf, attempts: u32 },
+    Io {
+        path: path::PathBuf,
+        error: io::Error,
+    },
+}
+
+impl fmt::Display for CartWriteError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let description = "Cart write error";
+        let reason = match self {
+            CartWriteError::Locked { path, attempts } => format!(
+                "{} is open in another program, probably pico-8 (gave up after {attempts} attempts)",
+                path.display()
+            ),
+            CartWriteError::Io { path, error } => {
+                format!("failed to write {}: {error}", path.display())
+            }
+        };
+        f.write_fmt(format_args!("{description}: {reason}"))
+    }
+}
+
+impl core::error::Error for CartWriteError {}
+
+impl CartWriteError {
+    /// The stable diagnostic-code, see `pico-build-rs explain`
+    pub const fn code(&self) -> &'static str {
+        "E012"
+    }
+}
+
+/// Whether the error is another process holding the file, which clears once it lets go
+#[cfg(windows)]
+pub fn is_sharing_violation(error: &io::Error) -> bool {
+    const ERROR_SHARING_VIOLATION: i32 = 32;
+    const ERROR_LOCK_VIOLATION: i32 = 33;
+    matches!(
+        error.raw_os_error(),
+        Some(ERROR_SHARING_VIOLATION | ERROR_LOCK_VIOLATION)
+    )
+}
+
+/// Whether the error is another process holding the file, which clears once it lets go
+///
+/// Open files do not block renaming them on unix
+#[cfg(not(windows))]
+pub fn is_sharing_violation(_error: &io::Error) -> bool {
+    false
+}
+
+/// The temporary file next to the cart, hidden on unix
+pub fn temp_path(cart_path: &path::Path) -> path::PathBuf {
+    let mut file_name = std::ffi::OsString::from(".");
+    file_name.push(cart_path.file_name().unwrap_or_default());
+    file_name.push(TEMP_SUFFIX);
+    cart_path.with_file_name(file_name)
+}
+
+/// The backup of the cart, `game.p8.bak` for the latest and `game.p8.bak.1` and on
+/// for the older ones
+pub fn backup_path(cart_path: &path::Path, index: usize) -> path::PathBuf {
+    let mut file_name = cart_path.file_name().unwrap_or_default().to_os_string();
+    file_name.push(BACKUP_SUFFIX);
+    if index > 0 {
+        file_name.push(format!(".{index}"));
+    }
+    cart_path.with_file_name(file_name)
+}
+
+/// Copies the cart to its latest backup, shifting the older ones along and
+/// dropping the oldest, so that the backups kept number at most `backups`
+///
+/// The cart is copied rather than renamed, so it stays in place until replaced
+fn back_up(cart_path: &path::Path, backups: usize) -> io::Result<()> {
+    if backups == 0 || !cart_path.exists() {
+        return Ok(());
+    }
+    // Not existing when fewer backups were made yet
+    let _ = fs::remove_file(backup_path(cart_path, backups - 1));
+    for index in (0..backups - 1).rev() {
+        let _ = fs::rename(
+            backup_path(cart_path, index),
+            backup_path(cart_path, index + 1),
+        );
+    }
+    fs::copy(cart_path, backup_path(cart_path, 0)).map(|_| ())
+}
+
+/// Writes the cart-source through a temporary file, see the [module-documentation](self)
+///
+/// Keeps the permissions of the cart it replaces
+#[tracing::instrument(level = "debug", skip(cart_source))]
+pub fn write_cart(cart_path: &path::Path, cart_source: &[u8]) -> Result<(), CartWriteError> {
+    write_cart_with(cart_path, 0, |w| w.write_all(cart_source)).map(|_| ())
+}
+
+/// Writes the cart through a temporary file as [`write_cart`] does, streaming it into
+/// the temporary file instead of taking the collected cart-source
+///
+/// The replaced cart is kept in up to `backups` backups, see [`backup_path`].
+/// Returns the size of the written cart
+#[tracing::instrument(level = "debug", skip(write))]
+pub fn write_cart_with(
+    cart_path: &path::Path,
+    backups: usize,
+    write: impl FnOnce(&mut io::BufWriter<fs::File>) -> io::Result<()>,
+) -> Result<u64, CartWriteError> {
+    let io_error = |error| CartWriteError::Io {
+        path: cart_path.to_path_buf(),
+        error,
+    };
+    let temp_path = temp_path(cart_path);
+    let mut temp_file = io::BufWriter::new(fs::File::create(&temp_path).map_err(io_error)?);
+    let size = write(&mut temp_file)
+        .and_then(|()| temp_file.flush())
+        .and_then(|()| temp_file.get_ref().sync_all())
+        .and_then(|()| temp_file.get_ref().metadata())
+        .map(|metadata| metadata.len());
+    drop(temp_file);
+    let size = match size {
+        Ok(size) => size,
+        Err(error) => {
+            let _ = fs::remove_file(&temp_path);
+            return Err(io_error(error));
+        }
+    };
+    if let Ok(metadata) = fs::metadata(cart_path) {
+        fs::set_permissions(&temp_path, metadata.permissions()).map_err(io_error)?;
+    }
+    // A failed backup is no reason to lose the build
+    if let Err(e) = back_up(cart_path, backups) {
+        tracing::warn!("Failed to back up {}: {e}", cart_path.display());
+    }
+    let mut backoff = INITIAL_BACKOFF;
+    for attempt in 1..=RETRY_ATTEMPTS {
+        match fs::rename(&temp_path, cart_path) {
+            Ok(()) => return Ok(size),
+            Err(error) if is_sharing_violation(&error) && attempt < RETRY_ATTEMPTS => {
+                tracing::warn!(
+                    "{} is open in another program, retrying in {}ms",
+                    cart_path.display(),
+                    backoff.as_millis()
+                );
+                thread::sleep(backoff);
+                backoff *= 2;
+            }
+            Err(error) => {
+                // Best-effort, the temporary file is overwritten by the next write anyway
+                let _ = fs::remove_file(&temp_path);
+                return Err(if is_sharing_violation(&error) {
+                    CartWriteError::Locked {
+                        path: cart_path.to_path_buf(),
+                        attempts: attempt,
+                    }
+                } else {
+                    io_error(error)
+                });
+            }
+        }
+    }
+    unreachable!("the last attempt returns")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn replaces_cart_through_temp_file() {
+        let dir = std::env::temp_dir().join(format!("cart-write-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let cart_path = dir.join("game.p8");
+        fs::write(&cart_path, b"old").unwrap();
+
+        write_cart(&cart_path, b"new").unwrap();
+        assert_eq!(fs::read(&cart_path).unwrap(), b"new");
+        assert!(!temp_path(&cart_path).exists());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn keeps_backups() {
+        let dir = std::env::temp_dir().join(format!("cart-backup-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let cart_path = dir.join("game.p8");
+
+        for cart_source in ["first", "second", "third", "fourth"] {
+            write_cart_with(&cart_path, 2, |w| w.write_all(cart_source.as_bytes())).unwrap();
+        }
+        let read = |path: path::PathBuf| fs::read_to_string(path).unwrap();
+        assert_eq!(read(cart_path.clone()), "fourth");
+        assert_eq!(read(backup_path(&cart_path, 0)), "third");
+        assert_eq!(read(backup_path(&cart_path, 1)), "second");
+        assert!(!backup_path(&cart_path, 2).exists());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
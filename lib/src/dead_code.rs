@@ -0,0 +1,243 @@
+//! Finding the top-level functions never called, and removing them from the compiled tabs
+//!
+//! A function is kept if its name is referred to from the top-level code, from one of the
+//! callbacks pico-8 calls, or from a kept function. Only names count as references, the
+//! text of strings and comments does not, so a function only called through
+//! `_ENV["name"]` or a string passed to a helper is reported as well. Functions declared
+//! as fields or methods, `function obj.f()` and `function obj:f()`, are never reported
+
+use core::fmt;
+
+use alloc::collections::BTreeSet;
+
+use pico_8_cart_model::CodeTabs;
+use pico_8_cart_model::Tab;
+use pico_8_cart_model::tokens::count_tokens;
+
+use crate::syntax::{self, Chunk, FunctionStatement, SyntaxError, Token, TokenKind};
+
+/// The callbacks pico-8 calls, kept though the code never refers to them
+pub const CALLBACKS: &[&str] = &["_init", "_update", "_update60", "_draw"];
+
+/// A top-level function never called
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DeadFunction {
+    pub name: String,
+    pub tab_index: usize,
+    /// The line of the tab, from 1, where the function is declared
+    pub line_number: usize,
+    /// The tokens the function counts for
+    pub token_count: usize,
+}
+
+impl fmt::Display for DeadFunction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let DeadFunction {
+            name,
+            tab_index,
+            line_number,
+            token_count,
+        } = self;
+        f.write_fmt(format_args!(
+            "{name} (tab {tab_index}, line {line_number}), {token_count} tokens"
+        ))
+    }
+}
+
+/// Whether the token is a name referring to the function
+fn refers_to(
+    tokens: &[Token<'_>],
+    references: &[(usize, usize)],
+    token_index: usize,
+    function: &FunctionStatement,
+) -> bool {
+    let token = &tokens[token_index];
+    if token.kind != TokenKind::Name || token_index == function.name {
+        return false;
+    }
+    let declaration = references
+        .iter()
+        .find(|(reference, _)| *reference == token_index)
+        .map(|(_, declaration)| *declaration);
+    match function.declaration {
+        Some(_) => declaration == function.declaration,
+        // A global, not a local of the same name or a field
+        None => {
+            declaration.is_none()
+                && token.text == tokens[function.name].text
+                && !token_index
+                    .checked_sub(1)
+                    .is_some_and(|previous| matches!(tokens[previous].text, "." | ":" | "::"))
+        }
+    }
+}
+
+/// The top-level functions of the tokens never called, by their index in the statements
+fn dead_function_indices(
+    tokens: &[Token<'_>],
+    functions: &[FunctionStatement],
+    references: &[(usize, usize)],
+) -> Vec<usize> {
+    let containing = |token_index: usize| {
+        functions
+            .iter()
+            .position(|function| function.start <= token_index && token_index <= function.end)
+    };
+    let referred_from = |function: &FunctionStatement| -> Vec<Option<usize>> {
+        (0..tokens.len())
+            .filter(|token_index| refers_to(tokens, references, *token_index, function))
+            .map(containing)
+            .collect()
+    };
+    let referrers: Vec<Vec<Option<usize>>> = functions.iter().map(referred_from).collect();
+
+    let mut kept: BTreeSet<usize> = functions
+        .iter()
+        .enumerate()
+        .filter(|(function_index, function)| {
+            (function.declaration.is_none() && CALLBACKS.contains(&tokens[function.name].text))
+                || referrers[*function_index].contains(&None)
+        })
+        .map(|(function_index, _)| function_index)
+        .collect();
+    let mut pending: Vec<usize> = kept.iter().copied().collect();
+    while let Some(kept_index) = pending.pop() {
+        for (function_index, referrers) in referrers.iter().enumerate() {
+            if !kept.contains(&function_index) && referrers.contains(&Some(kept_index)) {
+                kept.insert(function_index);
+                pending.push(function_index);
+            }
+        }
+    }
+    (0..functions.len())
+        .filter(|function_index| !kept.contains(function_index))
+        .collect()
+}
+
+/// Extends the byte-range to the whole lines it spans, if nothing else is on them
+fn whole_lines(src: &str, range: core::ops::Range<usize>) -> core::ops::Range<usize> {
+    let line_start = src[..range.start].rfind('\n').map_or(0, |index| index + 1);
+    let line_end = src[range.end..]
+        .find('\n')
+        .map_or(src.len(), |index| range.end + index + 1);
+    if src[line_start..range.start].trim().is_empty() && src[range.end..line_end].trim().is_empty()
+    {
+        line_start..line_end
+    } else {
+        range
+    }
+}
+
+/// Finds the top-level functions never called, removing them from the tabs unless
+/// `report_only`, see the module-documentation
+///
+/// Returns the tabs with the functions removed, or as they were if `report_only`, and the
+/// functions found
+#[tracing::instrument(level = "debug", skip(code_tabs))]
+pub fn eliminate_dead_code(
+    code_tabs: &CodeTabs<'_>,
+    report_only: bool,
+) -> Result<(CodeTabs<'static>, Vec<DeadFunction>), SyntaxError> {
+    let chunk = Chunk::of_code_tabs(code_tabs);
+    let src = String::from_utf8_lossy(&chunk.src);
+    let tokens = syntax::tokenize(&src).map_err(|e| chunk.error(e))?;
+    let locals = syntax::resolve_locals(tokens.clone()).map_err(|e| chunk.error(e))?;
+    let dead_indices = dead_function_indices(&tokens, &locals.functions, &locals.references);
+
+    let mut removed_ranges = Vec::new();
+    let mut dead_functions = Vec::new();
+    for function in dead_indices.iter().map(|index| &locals.functions[*index]) {
+        let start = tokens[function.start].offset;
+        let end = tokens[function.end].offset + tokens[function.end].text.len();
+        let (tab_index, line_number) = chunk.tab_line(tokens[function.start].line);
+        dead_functions.push(DeadFunction {
+            name: tokens[function.name].text.to_string(),
+            tab_index,
+            line_number,
+            token_count: count_tokens(src[start..end].as_bytes()),
+        });
+        removed_ranges.push(whole_lines(&src, start..end));
+    }
+
+    let mut eliminated: CodeTabs<'static> = Default::default();
+    for (tab_index, tab_range) in chunk.tab_ranges() {
+        let Some(tab) = &code_tabs[tab_index] else {
+            continue;
+        };
+        let removed: Vec<_> = removed_ranges
+            .iter()
+            .filter(|range| tab_range.contains(&range.start))
+            .collect();
+        if report_only || removed.is_empty() {
+            eliminated.insert(tab_index, tab.clone().into_owned());
+            continue;
+        }
+        let mut code = String::new();
+        let mut position = tab_range.start;
+        for range in removed {
+            code.push_str(&src[position..range.start]);
+            position = range.end;
+        }
+        // The range of the last line may include the newline closing the tab in the chunk
+        code.push_str(&src[position.min(tab_range.end)..tab_range.end]);
+        eliminated.insert(
+            tab_index,
+            Tab {
+                line_number: tab.line_number,
+                code_data: code.into_bytes().into(),
+            },
+        );
+    }
+    Ok((eliminated, dead_functions))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn removes_functions_never_called() {
+        let mut code_tabs: CodeTabs<'_> = Default::default();
+        for (tab_index, code) in [
+            (
+                0,
+                "function _init() setup() end\nfunction setup() helper() end\nfunction unused()\n  setup()\n  unused()\nend\n",
+            ),
+            (
+                1,
+                "local function helper() end\nlocal function spare() end\nfunction obj.f() end\nprint(\"orphan\") x = t.orphan\nfunction orphan() end",
+            ),
+        ] {
+            code_tabs[tab_index] = Some(Tab {
+                line_number: 0,
+                code_data: code.as_bytes().into(),
+            });
+        }
+        let (eliminated, dead_functions) = eliminate_dead_code(&code_tabs, false).unwrap();
+        let names: Vec<_> = dead_functions
+            .iter()
+            .map(|dead_function| (dead_function.name.as_str(), dead_function.tab_index))
+            .collect();
+        // `helper` is global where `setup` calls it, as the local is declared after it
+        assert_eq!(
+            names,
+            [("unused", 0), ("helper", 1), ("spare", 1), ("orphan", 1)]
+        );
+        let code = |tab_index: usize| {
+            String::from_utf8(eliminated[tab_index].as_ref().unwrap().code_data.to_vec()).unwrap()
+        };
+        assert_eq!(
+            code(0),
+            "function _init() setup() end\nfunction setup() helper() end\n"
+        );
+        assert_eq!(
+            code(1),
+            "function obj.f() end\nprint(\"orphan\") x = t.orphan\n"
+        );
+        let (kept, _) = eliminate_dead_code(&code_tabs, true).unwrap();
+        assert_eq!(
+            kept[0].as_ref().unwrap().code_data,
+            code_tabs[0].as_ref().unwrap().code_data
+        );
+    }
+}
@@ -0,0 +1,531 @@
+//! The registry of diagnostic-codes, with the longer explanations printed by `explain`
+//!
+//! Every check reporting a diagnostic must register its code here
+
+/// A registered diagnostic
+#[derive(Debug)]
+pub struct Diagnostic {
+    /// The stable code, e.g. `L004`
+    ///
+    /// Prefixed by `E` for errors, `W` for warnings, and `L` for lint-rules
+    pub code: &'static str,
+    pub summary: &'static str,
+    /// What causes the diagnostic, with an example and the typical fix
+    pub explanation: &'static str,
+}
+
+pub const DIAGNOSTICS: &[Diagnostic] = &[
+    Diagnostic {
+        code: "E001",
+        summary: "malformed cartridge-header",
+        explanation: "\
+A .p8 file must start with the two header-lines written by pico-8:
+
+    pico-8 cartridge // http://www.pico-8.com
+    version 43
+
+This is reported when the first line is missing or different, or when the
+version-line is missing or not valid utf-8. Re-saving the cart from pico-8
+restores the header.",
+    },
+    Diagnostic {
+        code: "E002",
+        summary: "missing gfx-section",
+        explanation: "\
+Every cart saved by pico-8 has a `__gfx__` section, even an empty one, so a
+cart without it is most likely truncated or not a cart at all.
+
+Add an empty section to the end of the file:
+
+    __gfx__",
+    },
+    Diagnostic {
+        code: "E003",
+        summary: "malformed asset-data",
+        explanation: "\
+An asset-section (`__gfx__`, `__gff__`, `__label__`, `__map__`, `__sfx__` or
+`__music__`) has a row of the wrong length, a character which is not a digit,
+or more rows than the section can hold. For example, each `__gfx__` row must
+be exactly 128 hex-digits:
+
+    __gfx__
+    00000000000000000000000000000000... (128 digits)
+
+This usually comes from a bad merge or a hand-edit. Re-saving the cart from
+pico-8 rewrites the sections.",
+    },
+    Diagnostic {
+        code: "E004",
+        summary: "failed to read the cart",
+        explanation: "\
+The cart-file could not be read, e.g. because it does not exist or is not
+readable. Check the `src_dir` and `cart` configuration-values.",
+    },
+    Diagnostic {
+        code: "E005",
+        summary: "invalid rom-data",
+        explanation: "\
+The memory-image of a cart, e.g. the data hidden in a .p8.png, is too small or
+its code-region could not be decompressed. A .p8.png must be the unmodified
+160x205 image saved by pico-8; re-encoding or resizing the image destroys the
+hidden data.",
+    },
+    Diagnostic {
+        code: "E006",
+        summary: "code exceeds the code-region",
+        explanation: "\
+The code of a binary cart (.p8.png) is compressed into a code-region of 15616
+bytes. This is reported when the compressed code does not fit, or when the
+code is longer than the 65535 characters the compressed format can hold.
+
+Reduce the code-size, or distribute the cart as a .p8 file instead.",
+    },
+    Diagnostic {
+        code: "E007",
+        summary: "token limit exceeded",
+        explanation: "\
+The code of a cart may hold at most 8192 tokens, counted as the pico-8
+code-editor does: every name, literal, keyword and operator is a token, while
+commas, periods, closing brackets, `local` and `end` are free.
+
+    x = a + 1 -- 5 tokens
+
+pico-8 refuses to run a cart over the limit. Build with the release-profile
+(`--release`) to strip debug-calls, or move repeated code into functions.",
+    },
+    Diagnostic {
+        code: "E008",
+        summary: "malformed picotron cart",
+        explanation: "\
+A .p64 file must start with the header-line written by picotron:
+
+    picotron cartridge // www.picotron.net
+
+and each of its files must be introduced by a `:: <path>` line with a utf-8
+path. Re-saving the cart from picotron restores the format.",
+    },
+    Diagnostic {
+        code: "E009",
+        summary: "malformed section-layout",
+        explanation: "\
+A section-delimiter such as `__gfx__` is cut off at the end of the file.
+
+This usually comes from a truncated file or a bad merge. Restore the end of the
+file.",
+    },
+    Diagnostic {
+        code: "E010",
+        summary: "not a pico-8 cart",
+        explanation: "\
+The file is not a .p8 cart, but looks like another format which is often
+mistaken for one: a png-image such as a .p8.png cart, an html-export, a
+zip-archive, or a voxatron or picotron cart. The error names the detected
+format, and what to load instead.
+
+Check that the `cart` configuration-value points to the .p8 file.",
+    },
+    Diagnostic {
+        code: "E011",
+        summary: "unresolved `#include`",
+        explanation: "\
+An `#include` directive names a file which was not found, or files include
+each other in a cycle. Included files are looked up next to the including
+file first, then in each directory of the `include_paths` configuration-value:
+
+    include_paths = [\"../shared\"]
+
+A cycle is reported with the chain of files, e.g. `a.lua -> b.lua -> a.lua`;
+remove one of the directives to break it.
+
+With `--safe`, files outside the project-directory are refused as well; copy
+the shared files into the project to build it in safe mode.",
+    },
+    Diagnostic {
+        code: "E012",
+        summary: "the cart could not be written",
+        explanation: "\
+The compiled cart is written to a temporary file next to the cart, which then
+replaces it. On windows this fails while another program holds the cart open,
+usually pico-8 after `load`ing it; the replace is retried for a short while
+before giving up.
+
+Close the cart in pico-8, or load another one, and build again. pico-8 picks up
+the new cart on the next `load`, or on ctrl+r.",
+    },
+    Diagnostic {
+        code: "E013",
+        summary: "generated code could not be spliced into its tab",
+        explanation: "\
+Generated code replaces the lines between the markers of its anchor, leaving
+the rest of the tab as written:
+
+    --@generated levels
+    levels={...}
+    --@end
+
+The anchor must mark exactly one region, and each region must be closed by
+`--@end` before the next `--@generated` marker or the end of the tab. An end
+marker naming an anchor, e.g. `--@end levels`, must name the region it closes.
+
+Add the markers where the code belongs, or fix the marker the error points to.",
+    },
+    Diagnostic {
+        code: "E014",
+        summary: "sprites could not be embedded into the code",
+        explanation: "\
+The sprites of the `[embed_sprites]` configuration are moved into a generated
+tab, which draws them back into the sprite-sheet when the cart starts:
+
+    [embed_sprites]
+    sprites = [\"16-31\", \"64\"]
+
+The sprites must be within the sheet, 0-127 for a half-sheet and 0-255 for a
+full one, and a tab must be left after the last tab of code for the generated
+one.
+
+The generated tab is read back on every build, restoring the sprites which are
+still blank in `__gfx__`. Edit the sprites in the sprite-editor rather than the
+strings of the generated tab, or remove the tab to drop the embedded sprites.",
+    },
+    Diagnostic {
+        code: "E015",
+        summary: "code is not utf-8",
+        explanation: "\
+The cart was parsed with `TextPolicy::ValidateAndError`, and its code holds bytes
+which are not utf-8. pico-8 saves its glyphs as utf-8, so such bytes usually come from
+a tool writing raw p8scii, or from a cart converted from a rom:
+
+    s=\"\\x83\"    -- the byte 0x83 instead of the glyph ⬇️
+
+Re-save the cart from pico-8 to write the glyphs as utf-8, or parse with
+`TextPolicy::ValidateAndReplace` to replace the invalid bytes.",
+    },
+    Diagnostic {
+        code: "E016",
+        summary: "image could not be imported",
+        explanation: "\
+The images of the `[assets]` configuration are drawn into `__gfx__` on each build,
+at the sprite configured for each file-name, or else at sprite 0. The image named
+as the label is drawn into `__label__` instead, and must be 128x128 pixels:
+
+    [assets]
+    dir = \"assets\"
+    sprites = { player = 16, tiles = 64 }
+    label = \"title.png\"
+
+An image must be at most 128x128 pixels, and fit into the sheet from the top-left
+corner of its sprite: a 16x16 image at sprite 15 overflows the right edge. Images
+reaching below sprite 127 overwrite the lower half of the map.
+
+Each pixel is mapped to the nearest of the 16 colors, and transparent pixels to
+color 0. Crop the image, or move it to a sprite leaving room for it.",
+    },
+    Diagnostic {
+        code: "E017",
+        summary: "Tiled map could not be imported",
+        explanation: "\
+The `.tmx` and `.csv` maps of the `[assets]` configuration are set into `__map__` on
+each build, at the tile configured for each file-name, or else at tile 0,0:
+
+    [assets]
+    maps = { level1 = [0, 0], level2 = [32, 0] }
+
+A `.tmx` map is read from its first layer, which must use the csv layer-format; set
+it in the map-properties of Tiled instead of base64 or zlib. A layer must fit into the
+128x64 tiles of the map from its tile. Rows 32 to 63 are shared with the lower half
+of the sprite-sheet, which they overwrite.
+
+Tiles above 255 do not fit into a map-cell, and are set to 0 with a warning.",
+    },
+    Diagnostic {
+        code: "E018",
+        summary: "Aseprite file could not be read",
+        explanation: "\
+The `.ase` and `.aseprite` files of the `[assets]` configuration are read without
+exporting them to png first. The visible layers of each frame are flattened, and the
+frames drawn side by side, so a 4-frame 8x8 animation fills 4 sprites in a row.
+
+Sprites in the RGBA, grayscale and indexed color-modes are read. Re-save the file
+with a current Aseprite if it is reported as truncated or not an aseprite-file.",
+    },
+    Diagnostic {
+        code: "E019",
+        summary: "invalid string-catalog",
+        explanation: "\
+The catalog of the language configured in `[strings]` is read on each build, e.g.
+`lang/de.txt` for `language = \"de\"`. Each line is a comment starting with `#`,
+or an entry of the original literal and its translation:
+
+    # src/title.lua:4
+    \"press ❎ to start\" = \"drücke ❎ zum starten\"
+
+Quotes inside either must be escaped as `\\\"`. Running `pico-build-rs strings
+--output lang/de.txt` rewrites the catalog, keeping its translations.",
+    },
+    Diagnostic {
+        code: "E020",
+        summary: "syntax error in the compiled code",
+        explanation: "\
+The code of each build is parsed before the cart is written, so pico-8 never loads
+a cart which fails with a syntax error. The cart is left as it was, and the error is
+reported with the tab and line of the compiled code, and the source-file of the tab:
+
+    E020: Syntax error: tab 2, line 5: 'end' expected (to close 'function' at tab 2,
+    line 1) near <eof> (player.lua)
+
+The lines of a tab are the lines of its source-file, unless it has `#include`s.
+The shorthands of pico-8, such as `+=`, `!=`, `?` and `if (cond) stmt`, are
+understood. The tabs run as one chunk, so a block may span tabs.",
+    },
+    Diagnostic {
+        code: "E021",
+        summary: "invalid source-map",
+        explanation: "\
+Each build writes a source-map next to the cart, e.g. `game.p8.map.json`, which
+`pico-build-rs trace` reads to find the source-file of a line of the cart:
+
+    pico-build-rs trace 2 12
+
+prints the file and line of line 12 of tab 2, as reported by pico-8 for a runtime
+error. This is reported when the source-map is missing or not valid JSON, or was
+written by a different version. Rebuilding the cart rewrites it.",
+    },
+    Diagnostic {
+        code: "E022",
+        summary: "invalid build-time constant",
+        explanation: "\
+A constant is defined on a line of its own, or in the `[constants]` section of the
+config-file, and substituted for its name in the code of each build:
+
+    --[[const]] TILE = 8
+    --[[const]] ROOM = TILE * 16
+    [constants]
+    DEBUG = false
+
+The value must be a literal, e.g. a number, a string, `true` or `nil`, or arithmetic
+of numbers and the constants defined before it. A constant defined again with
+another value is reported, one of the config-file takes precedence over the source.
+Names of the config-file must be names of lua, and no keyword.",
+    },
+    Diagnostic {
+        code: "E023",
+        summary: "too many tabs",
+        explanation: "\
+The code-editor of pico-8 has 16 tabs, and each source-file is compiled into a tab of
+its own, so a source-directory with more lua-files than tabs does not fit. The files
+past the last tab are listed. Either combine files, e.g. with `#include`, or have them
+merged into the last tab:
+
+    tab_overflow = \"merge-into-last\"
+
+With `preserve-main-tab` as the merge-strategy the first tab is kept from the cart,
+leaving 15 tabs for the source-files.",
+    },
+    Diagnostic {
+        code: "E024",
+        summary: "code-tabs overflow",
+        explanation: "\
+The code of a cart is kept in one slot per tab of the code-editor, 16 in all. Filling
+the code-tabs of a cart from a list of tabs, e.g. with `CodeTabs::try_from_iter`, fails
+when the list is longer, reporting the count of tabs it had. Unlike E023 nothing is
+merged, so the caller decides which tabs to drop or combine before building the cart.",
+    },
+    Diagnostic {
+        code: "W001",
+        summary: "malformed assert-directive",
+        explanation: "\
+An assert-directive must be a single line of the form
+
+    --#assert(cond, msg)
+
+with balanced parentheses. Malformed directives are left as comments, so the
+assertion is never checked. Multi-line conditions can be moved into a local:
+
+    local alive = e.hp > 0
+    --#assert(alive, \"dead enemy hit\")",
+    },
+    Diagnostic {
+        code: "W002",
+        summary: "translation pico-8 cannot print as written",
+        explanation: "\
+A translation of the string-catalog uses a character without a P8SCII-glyph, or has
+a line wider than the 32 columns of the screen, counting the wide glyphs such as ❎
+as 2 columns. Characters without a glyph print as something else, e.g. `ü` of
+
+    \"press ❎ to start\" = \"drücke ❎ zum starten\"
+
+can be written as `ue`. Lines can be broken with `\\n`. Lines which are already as
+wide untranslated are not reported.",
+    },
+    Diagnostic {
+        code: "L001",
+        summary: "`extcmd` is unavailable when published",
+        explanation: "\
+`extcmd` (screenshots, recording, folder-commands, ...) does nothing in the web
+player, so carts relying on it behave differently on the BBS.
+
+    extcmd(\"rec\") -- flagged
+
+Remove the call, or guard the feature behind a menu-item only used locally.",
+    },
+    Diagnostic {
+        code: "L002",
+        summary: "filesystem-functions are unavailable when published",
+        explanation: "\
+`ls`, `cd` and `folder` access the host filesystem, which the web player does
+not have.
+
+    ls() -- flagged
+
+Remove the call before publishing.",
+    },
+    Diagnostic {
+        code: "L003",
+        summary: "`#include` is resolved locally",
+        explanation: "\
+`#include` is resolved from the local filesystem when pico-8 loads the cart,
+so a cart uploaded with `#include` lines is missing that code.
+
+    #include lib.lua -- flagged
+
+Build the cart with pico-build-rs, which inlines the included files.",
+    },
+    Diagnostic {
+        code: "L004",
+        summary: "`printh` is a debug-call",
+        explanation: "\
+`printh` writes to the host terminal, which has no output in the web player,
+and costs tokens.
+
+    printh(\"x=\"..x) -- flagged
+
+Build with the release-profile (`--release`) to strip the calls.",
+    },
+    Diagnostic {
+        code: "L005",
+        summary: "a pico-8 built-in is shadowed",
+        explanation: "\
+A local, parameter, function or global named like a function of the pico-8 API
+hides the built-in from there on, so later calls run the wrong code:
+
+    local map = {}     -- map(0, 0) now fails: attempt to call a table value
+    for t = 0, 10 do   -- t() is the number of the loop inside it
+
+Rename the variable, e.g. `level_map` or `tick`. The built-ins are taken from the
+version of the cart, so functions added by a later pico-8 are only reported for carts
+of that version. Fields and methods, e.g. `self.map`, do not shadow the built-in.",
+    },
+    Diagnostic {
+        code: "L006",
+        summary: "a pico-8 built-in is unavailable to the version",
+        explanation: "\
+The function was added by a later pico-8 than the version of the cart, or the
+`cart_version` of the config-file, so older players, such as an older web player
+embedded on a site, fail with `attempt to call a nil value`:
+
+    cart_version = 16
+    parts = split(\"a,b\") -- flagged, added by version 18
+
+Replace the call, or declare a function of the name, which is then not reported.
+Run `pico-build-rs analyze` to check the cart for the version it is published as.",
+    },
+    Diagnostic {
+        code: "L007",
+        summary: "a deprecated pico-8 built-in is called",
+        explanation: "\
+The bitwise functions are superseded by the operators added by version 18, which
+do the same without the cost of a call:
+
+    x = shl(1, 4) -- flagged, write 1 << 4
+
+`band`, `bor`, `bxor`, `bnot`, `shl`, `shr`, `lshr`, `rotl` and `rotr` are `&`, `|`,
+`^^`, `~`, `<<`, `>>`, `>>>`, `<<>` and `>><`. Carts targeting an older version with
+`cart_version` are not reported.",
+    },
+];
+
+/// Returns the registered diagnostic, matching the code case-insensitively
+pub fn lookup(code: &str) -> Option<&'static Diagnostic> {
+    DIAGNOSTICS
+        .iter()
+        .find(|diagnostic| diagnostic.code.eq_ignore_ascii_case(code.trim()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn codes_are_registered() {
+        let codes = crate::lint::RULES.iter().map(|rule| rule.code).chain([
+            pico_8_cart_model::CartParseError::MissingGfxSection.code(),
+            pico_8_cart_model::CartParseError::InvalidUtf8 { line_number: 0 }.code(),
+            pico_8_cart_model::CartParseError::ForeignFormat(
+                pico_8_cart_model::sniff::ForeignFormat::Zip,
+            )
+            .code(),
+            pico_8_cart_model::CartDataError::Io(std::io::ErrorKind::NotFound.into()).code(),
+            pico_8_cart_model::HexError::TooManyRows {
+                rows: 0,
+                max_rows: 0,
+            }
+            .code(),
+            pico_8_cart_model::RomError::InvalidSize { size: 0 }.code(),
+            pico_8_cart_model::TokenLimitError {
+                token_count: 0,
+                token_limit: 0,
+            }
+            .code(),
+            pico_8_cart_builder::IncludeError::Cycle { chain: Vec::new() }.code(),
+            crate::cart_write::CartWriteError::Locked {
+                path: std::path::PathBuf::new(),
+                attempts: 0,
+            }
+            .code(),
+            pico_8_cart_model::SpliceError::MissingTab { tab_index: 0 }.code(),
+            pico_8_cart_model::EmbedError::NoFreeTab.code(),
+            pico_8_cart_model::GfxPngError::InvalidSize {
+                width: 0,
+                height: 0,
+            }
+            .code(),
+            crate::tiled::TiledError::MissingLayer.code(),
+            pico_8_cart_model::AseError::Truncated.code(),
+            crate::strings::CatalogError::InvalidEntry { line_number: 0 }.code(),
+            crate::syntax::SyntaxError {
+                tab_index: 0,
+                line_number: 0,
+                message: String::new(),
+            }
+            .code(),
+            crate::source_map::SourceMapError::InvalidJson { offset: 0 }.code(),
+            pico_8_cart_builder::ConstantError::InvalidName {
+                name: String::new(),
+            }
+            .code(),
+            pico_8_cart_model::RomError::CodeTooLarge {
+                size: 0,
+                max_size: 0,
+            }
+            .code(),
+            pico_8_cart_builder::TooManyTabsError {
+                room: 0,
+                overflowing: Vec::new(),
+            }
+            .code(),
+            pico_8_cart_model::TabOverflowError { tab_count: 0 }.code(),
+        ]);
+        for code in codes {
+            assert!(lookup(code).is_some(), "{code} is not registered");
+        }
+        for (index, diagnostic) in DIAGNOSTICS.iter().enumerate() {
+            assert!(
+                DIAGNOSTICS[index + 1..]
+                    .iter()
+                    .all(|other| other.code != diagnostic.code),
+                "{} is registered twice",
+                diagnostic.code
+            );
+        }
+    }
+}
@@ -0,0 +1,198 @@
+//! The dependency-graph of a project, for `pico-build-rs graph`
+//!
+//! The cart depends on each compiled tab, each tab on the source-file compiled into it,
+//! and each file on the files it includes with `#include`. Files shared by several tabs
+//! are a single node, so the graph shows which tabs rebuild when a shared file changes.
+//! It is written in the DOT-language of Graphviz, e.g. for
+//! `pico-build-rs graph | dot -Tsvg > graph.svg`
+
+use core::fmt;
+
+use std::fs;
+use std::io;
+use std::path;
+
+use pico_8_cart_builder::CartBuilder;
+use pico_8_cart_model::P8_MAX_CODE_EDITOR_TAB_COUNT;
+
+use crate::paths;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum GraphNode {
+    Cart(path::PathBuf),
+    Tab(usize),
+    /// A source-file, or a file included by one, by its canonical path
+    File(path::PathBuf),
+}
+
+impl GraphNode {
+    /// The label of the node, with paths relative to the base
+    fn label(&self, base: &path::Path) -> String {
+        match self {
+            GraphNode::Cart(path) | GraphNode::File(path) => {
+                paths::relative_to(path, base).to_string()
+            }
+            GraphNode::Tab(tab_index) => format!("tab {tab_index}"),
+        }
+    }
+}
+
+/// The nodes of the project, and the edges from each node to the nodes it depends on
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct DependencyGraph {
+    nodes: Vec<GraphNode>,
+    edges: Vec<(usize, usize)>,
+}
+
+impl DependencyGraph {
+    /// Builds the graph of the project, from the files the builder compiles into the
+    /// tabs from `first_compiled_tab` on
+    ///
+    /// The source-files past the last tab have no tab depending on them
+    #[tracing::instrument(level = "debug")]
+    pub fn of_project(
+        cart_builder: &CartBuilder,
+        cart_path: &path::Path,
+        first_compiled_tab: usize,
+    ) -> io::Result<DependencyGraph> {
+        let mut graph = DependencyGraph::default();
+        let cart = graph.node(GraphNode::Cart(cart_path.to_path_buf()));
+        let mut pending = Vec::new();
+        for (index, dir_entry) in cart_builder.lua_files()?.iter().enumerate() {
+            let path = dir_entry.path();
+            let file = graph.node(GraphNode::File(
+                fs::canonicalize(&path).unwrap_or_else(|_| path.clone()),
+            ));
+            let tab_index = first_compiled_tab + index;
+            if tab_index < P8_MAX_CODE_EDITOR_TAB_COUNT {
+                let tab = graph.node(GraphNode::Tab(tab_index));
+                graph.edges.push((cart, tab));
+                graph.edges.push((tab, file));
+            }
+            pending.push((file, path));
+        }
+        let mut visited = Vec::new();
+        while let Some((file, path)) = pending.pop() {
+            if visited.contains(&file) {
+                continue;
+            }
+            visited.push(file);
+            // Unreadable files are reported when compiling, and have no edges here
+            let Ok(src) = fs::read(&path) else {
+                continue;
+            };
+            for included_path in cart_builder.include_resolver().direct_includes(&path, &src) {
+                let included = graph.node(GraphNode::File(included_path.clone()));
+                if !graph.edges.contains(&(file, included)) {
+                    graph.edges.push((file, included));
+                }
+                pending.push((included, included_path));
+            }
+        }
+        Ok(graph)
+    }
+    /// The index of the node, added if not in the graph yet
+    fn node(&mut self, node: GraphNode) -> usize {
+        match self.nodes.iter().position(|other| *other == node) {
+            Some(index) => index,
+            None => {
+                self.nodes.push(node);
+                self.nodes.len() - 1
+            }
+        }
+    }
+    pub fn nodes(&self) -> &[GraphNode] {
+        &self.nodes
+    }
+    /// Each node, and a node it depends on
+    pub fn edges(&self) -> impl Iterator<Item = (&GraphNode, &GraphNode)> {
+        self.edges
+            .iter()
+            .map(|(from, to)| (&self.nodes[*from], &self.nodes[*to]))
+    }
+    /// Writes the graph in the DOT-language, with paths relative to the base
+    pub fn write_dot<W: io::Write>(&self, mut writer: W, base: &path::Path) -> io::Result<()> {
+        writeln!(writer, "digraph dependencies {{")?;
+        writeln!(writer, "    rankdir=LR;")?;
+        for (index, node) in self.nodes.iter().enumerate() {
+            let shape = match node {
+                GraphNode::Cart(_) => "box3d",
+                GraphNode::Tab(_) => "ellipse",
+                GraphNode::File(_) => "note",
+            };
+            writeln!(
+                writer,
+                "    n{index} [label=\"{}\", shape={shape}];",
+                escape(&node.label(base))
+            )?;
+        }
+        for (from, to) in &self.edges {
+            writeln!(writer, "    n{from} -> n{to};")?;
+        }
+        writeln!(writer, "}}")
+    }
+    /// Shows the graph as a line per edge, with paths relative to the base
+    pub fn display<'a>(&'a self, base: &'a path::Path) -> impl fmt::Display + 'a {
+        DisplayGraph { graph: self, base }
+    }
+}
+
+struct DisplayGraph<'a> {
+    graph: &'a DependencyGraph,
+    base: &'a path::Path,
+}
+
+impl fmt::Display for DisplayGraph<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (from, to) in self.graph.edges() {
+            writeln!(f, "{} -> {}", from.label(self.base), to.label(self.base))?;
+        }
+        Ok(())
+    }
+}
+
+/// Escapes the label for a quoted DOT-string
+fn escape(label: &str) -> String {
+    label.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use pico_8_cart_builder::IncludeResolver;
+
+    #[test]
+    fn shares_included_files() {
+        let root = std::env::temp_dir().join(format!("pico-build-graph-{}", std::process::id()));
+        let (src_dir, lib_dir) = (root.join("src"), root.join("lib"));
+        fs::create_dir_all(&src_dir).unwrap();
+        fs::create_dir_all(&lib_dir).unwrap();
+        fs::write(src_dir.join("enemy.lua"), "#include vec.lua\n").unwrap();
+        fs::write(src_dir.join("player.lua"), "#include vec.lua\n").unwrap();
+        fs::write(lib_dir.join("vec.lua"), "#include math.lua\n").unwrap();
+        fs::write(lib_dir.join("math.lua"), "function sq(x) return x*x end\n").unwrap();
+
+        let cart_builder = CartBuilder::new(&src_dir)
+            .with_include_resolver(IncludeResolver::new([&lib_dir]).with_root(&root));
+        let graph =
+            DependencyGraph::of_project(&cart_builder, &src_dir.join("game.p8"), 1).unwrap();
+        let root = fs::canonicalize(&root).unwrap();
+        let edges = graph.display(&root).to_string();
+        assert_eq!(
+            edges,
+            "src/game.p8 -> tab 1\ntab 1 -> src/enemy.lua\nsrc/game.p8 -> tab 2\n\
+             tab 2 -> src/player.lua\nsrc/player.lua -> lib/vec.lua\n\
+             lib/vec.lua -> lib/math.lua\nsrc/enemy.lua -> lib/vec.lua\n"
+        );
+        assert_eq!(graph.nodes().len(), 7);
+
+        let mut dot = Vec::new();
+        graph.write_dot(&mut dot, &root).unwrap();
+        let dot = String::from_utf8(dot).unwrap();
+        assert!(dot.starts_with("digraph dependencies {\n"));
+        assert!(dot.contains("    n3 [label=\"src/player.lua\", shape=note];\n"));
+        assert!(dot.contains("    n4 -> n3;\n"));
+        fs::remove_dir_all(root).unwrap();
+    }
+}
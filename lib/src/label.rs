@@ -0,0 +1,100 @@
+//! Generating a label for carts without one, as a step of the build
+//!
+//! pico-8 only writes `__label__` once a screenshot is captured with `F7`, so a cart
+//! published before that shows a blank label. When the cart has no label, or a blank
+//! one, and none was imported from the assets-directory, the build renders the top-left
+//! 16x16 tiles of the map into it, or a configured arrangement of sprites
+
+use pico_8_cart_model::{CartData, HexError, LabelImage};
+
+/// What the label of a cart without one is rendered from, from the `[label]` section
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum LabelFallback {
+    /// Leaves the label blank
+    None,
+    /// The 16x16 tiles of the map from the tile
+    Map { x: usize, y: usize },
+    /// The rows of sprites, scaled up to fill the label
+    Sprites(Vec<Vec<usize>>),
+}
+
+impl Default for LabelFallback {
+    fn default() -> Self {
+        LabelFallback::Map { x: 0, y: 0 }
+    }
+}
+
+impl LabelFallback {
+    /// Renders the label into the cart if it has none, or a blank one
+    ///
+    /// Returns what it was rendered from, `None` if the label was kept or the rendered
+    /// one would be blank as well, e.g. for an empty map
+    #[tracing::instrument(level = "debug", skip(cart))]
+    pub fn generate_into(&self, cart: &mut CartData<'_>) -> Result<Option<String>, HexError> {
+        if cart
+            .label_image()?
+            .is_some_and(|label_image| !label_image.is_blank())
+        {
+            return Ok(None);
+        }
+        let (label_image, source) = match self {
+            LabelFallback::None => return Ok(None),
+            LabelFallback::Map { x, y } => (
+                LabelImage::render_map(&cart.gfx_sheet()?, &cart.map_data()?, *x, *y),
+                format!("the map at tile {x},{y}"),
+            ),
+            LabelFallback::Sprites(rows) => (
+                LabelImage::render_sprites(&cart.gfx_sheet()?, rows),
+                format!("{} rows of sprites", rows.len()),
+            ),
+        };
+        if label_image.is_blank() {
+            return Ok(None);
+        }
+        cart.set_label_image(&label_image);
+        Ok(Some(source))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use pico_8_cart_model::MapData;
+
+    #[test]
+    fn generates_only_blank_labels() {
+        let mut cart = CartData::from_bytes(
+            b"pico-8 cartridge // http://www.pico-8.com\nversion 43\n__lua__\n__gfx__\n",
+        )
+        .unwrap();
+        let mut gfx_sheet = cart.gfx_sheet().unwrap();
+        gfx_sheet.set_pixel(8 + 2, 1, 8);
+        cart.set_gfx_sheet(&gfx_sheet);
+        let mut map_data = MapData::new();
+        map_data.set_tile(1, 0, 1);
+        cart.set_map_data(&map_data).unwrap();
+        assert_eq!(
+            LabelFallback::default().generate_into(&mut cart).unwrap(),
+            Some("the map at tile 0,0".to_string())
+        );
+        let label_image = cart.label_image().unwrap().unwrap();
+        assert_eq!(label_image.get_pixel(8 + 2, 1), Some(8));
+
+        let sprites = LabelFallback::Sprites(vec![vec![1]]);
+        assert_eq!(sprites.generate_into(&mut cart).unwrap(), None);
+        cart.set_label_image(&LabelImage::new());
+        assert!(sprites.generate_into(&mut cart).unwrap().is_some());
+        assert_eq!(
+            cart.label_image().unwrap().unwrap().get_pixel(2 * 16, 16),
+            Some(8)
+        );
+        cart.set_label_image(&LabelImage::new());
+        assert_eq!(
+            LabelFallback::Map { x: 4, y: 0 }
+                .generate_into(&mut cart)
+                .unwrap(),
+            None
+        );
+    }
+}
@@ -1,54 +1,69 @@
 extern crate alloc;
 
-use core::iter;
 use core::ops::Deref;
-use core::slice;
 
 use alloc::borrow::Cow;
+use alloc::collections::{VecDeque, vec_deque};
 use alloc::vec;
 
 use std::ffi;
 use std::fs;
 use std::io;
 use std::path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError, TryLockError};
 
 use pico_8_cart_model::section;
 
-/// A fixed-size collection
+pub mod analysis;
+pub mod assets;
+pub mod builtins;
+pub mod cart_write;
+pub mod dead_code;
+pub mod diagnostics;
+pub mod graph;
+pub mod label;
+pub mod lint;
+pub mod merge;
+pub mod minify;
+pub mod orphans;
+pub mod paths;
+pub mod pico_folders;
+#[cfg(feature = "picotron")]
+pub mod picotron;
+pub mod provenance;
+pub mod report;
+pub mod source_map;
+pub mod strings;
+pub mod syntax;
+pub mod tab_title;
+pub mod tiled;
+pub mod todos;
+pub mod transform;
+pub mod verify;
+
+/// A ring-buffer of a fixed capacity
 /// acting like a `fifo`
+///
+/// Values are pushed up to the capacity, after which [`Fifo::overwrite`]
+/// drops the oldest value for the newest one
 #[derive(Debug)]
 pub struct Fifo<T> {
-    inner: Box<[T]>,
-    cursor: usize,
+    inner: VecDeque<T>,
+    capacity: usize,
 }
 
 impl<T> Fifo<T> {
-    /// Returns the value of the incremented cursor
-    ///
-    /// The cursor will be incremented such that
-    /// it always refers to a region within the buffer
-    const fn incremented_cursor_value(&self) -> usize {
-        let Fifo { inner, cursor } = self;
-
-        let naively_incremented = *cursor + 1;
-
-        // In this first case, once incremented we point outside the buffer-region
-        if naively_incremented == inner.len() {
-            // Thus, we just return zero
-            0
-        } else {
-            // In this second case, we are still bounded
-            naively_incremented
+    /// An empty buffer, allocating as values are pushed
+    pub const fn with_capacity(capacity: usize) -> Fifo<T> {
+        Fifo {
+            inner: VecDeque::new(),
+            capacity,
         }
     }
 
-    fn get_at_cursor_mut(&mut self) -> &mut T {
-        unsafe { self.inner.get_unchecked_mut(self.cursor) }
-    }
-
-    /// Returns an iterator over a contiguous region
-    /// with the contained `cursor` corresponding
-    /// to the iterator-region's origin
+    /// Returns an iterator over the values,
+    /// from the oldest to the newest
     pub fn iter(&self) -> impl Iterator<Item = &T> {
         self.into_iter()
     }
@@ -57,51 +72,105 @@ impl<T> Fifo<T> {
         self.into_iter()
     }
 
-    /// Overwrites the element pointed to by the
-    /// cursor value currently.
-    ///
-    /// Returns the index of the written item
+    /// Pushes the value as the newest one, dropping the oldest
+    /// if the buffer is full
     ///
-    pub fn overwrite(&mut self, value: T) -> usize {
-        *self.get_at_cursor_mut() = value;
-        let write_index = self.cursor;
-        self.cursor = self.incremented_cursor_value();
-        write_index
+    /// Returns the dropped value, which is the value itself for a buffer
+    /// of no capacity, see [`Fifo::try_overwrite`]
+    pub fn overwrite(&mut self, value: T) -> Option<T> {
+        self.try_overwrite(value).unwrap_or_else(Some)
+    }
+
+    /// Like [`Fifo::overwrite`], handing the value back if the buffer
+    /// has no capacity to hold it
+    pub fn try_overwrite(&mut self, value: T) -> Result<Option<T>, T> {
+        if self.capacity == 0 {
+            return Err(value);
+        }
+        let oldest = if self.is_full() {
+            self.inner.pop_front()
+        } else {
+            None
+        };
+        self.inner.push_back(value);
+        Ok(oldest)
+    }
+
+    /// Pushes the value as the newest one,
+    /// handing it back if the buffer is full
+    pub fn push(&mut self, value: T) -> Result<(), T> {
+        if self.is_full() {
+            return Err(value);
+        }
+        self.inner.push_back(value);
+        Ok(())
     }
 
+    pub fn pop_oldest(&mut self) -> Option<T> {
+        self.inner.pop_front()
+    }
+
+    pub fn peek_latest(&self) -> Option<&T> {
+        self.inner.back()
+    }
+
+    /// Changes the capacity, dropping the oldest values
+    /// which no longer fit
+    pub fn resize(&mut self, capacity: usize) {
+        let excess = self.inner.len().saturating_sub(capacity);
+        self.inner.drain(..excess);
+        self.capacity = capacity;
+    }
+
+    /// Removes the values, from the oldest to the newest
+    pub fn drain(&mut self) -> vec_deque::Drain<'_, T> {
+        self.inner.drain(..)
+    }
+
+    pub fn clear(&mut self) {
+        self.inner.clear();
+    }
+
+    /// Whether no values are pushed yet
     #[inline]
-    pub const fn is_empty(&self) -> bool {
+    pub fn is_empty(&self) -> bool {
         self.inner.is_empty()
     }
 
     #[inline]
-    pub const fn len(&self) -> usize {
+    pub fn is_full(&self) -> bool {
+        self.inner.len() >= self.capacity
+    }
+
+    /// The values pushed, up to the [capacity](Fifo::capacity)
+    #[inline]
+    pub fn len(&self) -> usize {
         self.inner.len()
     }
 
-    pub const fn reset_cursor(&mut self) {
-        self.cursor = 0;
+    #[inline]
+    pub const fn capacity(&self) -> usize {
+        self.capacity
     }
 }
 
 impl<T> Default for Fifo<T> {
     fn default() -> Self {
-        Fifo {
-            inner: Box::default(),
-            cursor: usize::default(),
-        }
+        Fifo::with_capacity(0)
     }
 }
 
+/// A full buffer of the values
 impl<T> From<Box<[T]>> for Fifo<T> {
     fn from(value: Box<[T]>) -> Self {
         Fifo {
-            inner: value,
-            ..Default::default()
+            capacity: value.len(),
+            inner: VecDeque::from(value.into_vec()),
         }
     }
 }
 
+/// A full buffer of the values
 impl<T> FromIterator<T> for Fifo<T> {
     fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
         let inner: Box<[T]> = Box::from_iter(iter);
@@ -111,37 +180,93 @@ impl<T> FromIterator<T> for Fifo<T> {
 
 impl<'a, T> IntoIterator for &'a Fifo<T> {
     type Item = &'a T;
-    type IntoIter = iter::Chain<slice::Iter<'a, T>, slice::Iter<'a, T>>;
+    type IntoIter = vec_deque::Iter<'a, T>;
     fn into_iter(self) -> Self::IntoIter {
-        let (until_cursor, cursor_and_onwards) = self.inner.split_at(self.cursor);
-
-        cursor_and_onwards.iter().chain(until_cursor)
+        self.inner.iter()
     }
 }
 
 impl<'a, T> IntoIterator for &'a mut Fifo<T> {
     type Item = &'a mut T;
-    type IntoIter = iter::Chain<slice::IterMut<'a, T>, slice::IterMut<'a, T>>;
+    type IntoIter = vec_deque::IterMut<'a, T>;
     fn into_iter(self) -> Self::IntoIter {
-        let (until_cursor, cursor_and_onwards) = self.inner.split_at_mut(self.cursor);
-
-        cursor_and_onwards.iter_mut().chain(until_cursor)
+        self.inner.iter_mut()
     }
 }
 
-impl<T> IntoIterator for Fifo<T>
-where
-    T: Clone,
-{
+impl<T> IntoIterator for Fifo<T> {
     type Item = T;
-    type IntoIter = iter::Chain<vec::IntoIter<T>, vec::IntoIter<T>>;
+    type IntoIter = vec_deque::IntoIter<T>;
     fn into_iter(self) -> Self::IntoIter {
-        let (until_cursor, cursor_and_onwards) = self.inner.split_at(self.cursor);
+        self.inner.into_iter()
+    }
+}
 
-        let (until_cursor, cursor_and_onwards) =
-            (until_cursor.to_vec(), cursor_and_onwards.to_vec());
+/// A [`Fifo`] shared between threads, written from any of them
+///
+/// Clones share the same buffer
+#[derive(Debug)]
+pub struct SyncFifo<T> {
+    inner: Arc<Mutex<Fifo<T>>>,
+    /// The values written since created, including those overwritten since
+    written: Arc<AtomicUsize>,
+}
 
-        cursor_and_onwards.into_iter().chain(until_cursor)
+impl<T> Clone for SyncFifo<T> {
+    fn clone(&self) -> Self {
+        SyncFifo {
+            inner: Arc::clone(&self.inner),
+            written: Arc::clone(&self.written),
+        }
+    }
+}
+
+impl<T> SyncFifo<T> {
+    pub fn with_capacity(capacity: usize) -> SyncFifo<T> {
+        SyncFifo {
+            inner: Arc::new(Mutex::new(Fifo::with_capacity(capacity))),
+            written: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+
+    /// Locks the buffer, waiting for other threads to release it
+    ///
+    /// A thread panicking while holding the lock leaves the buffer as it was,
+    /// so it is used as is
+    pub fn lock(&self) -> MutexGuard<'_, Fifo<T>> {
+        self.inner.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Like [`Fifo::overwrite`], waiting for other threads to release the buffer
+    pub fn overwrite(&self, value: T) -> Option<T> {
+        let mut fifo = self.lock();
+        let dropped = fifo.overwrite(value);
+        self.written.fetch_add(1, Ordering::Release);
+        drop(fifo);
+        dropped
+    }
+
+    /// Like [`Fifo::try_overwrite`], handing the value back instead of waiting
+    /// if the buffer is locked
+    ///
+    /// Waiting on a buffer locked by the same thread would never return
+    pub fn try_overwrite(&self, value: T) -> Result<Option<T>, T> {
+        let mut fifo = match self.inner.try_lock() {
+            Ok(fifo) => fifo,
+            Err(TryLockError::Poisoned(e)) => e.into_inner(),
+            Err(TryLockError::WouldBlock) => return Err(value),
+        };
+        let dropped = fifo.try_overwrite(value)?;
+        self.written.fetch_add(1, Ordering::Release);
+        Ok(dropped)
+    }
+
+    /// The values written by [`SyncFifo::overwrite`] and [`SyncFifo::try_overwrite`],
+    /// for telling how many are new since last checked
+    ///
+    /// Counted while holding the lock, so it matches the buffer while it is locked
+    pub fn written(&self) -> usize {
+        self.written.load(Ordering::Acquire)
     }
 }
 
@@ -182,6 +307,130 @@ mod tests {
         fifo.overwrite(5);
         assert_indices!(fifo, 2);
     }
+
+    #[test]
+    fn push_pop_and_resize() {
+        let mut fifo = Fifo::with_capacity(2);
+        assert_eq!(fifo.push(1), Ok(()));
+        assert_eq!(fifo.push(2), Ok(()));
+        assert_eq!(fifo.push(3), Err(3));
+        assert_eq!(fifo.overwrite(3), Some(1));
+        assert_eq!(fifo.peek_latest(), Some(&3));
+
+        fifo.resize(3);
+        assert_eq!(fifo.push(4), Ok(()));
+        assert_eq!((fifo.len(), fifo.capacity()), (3, 3));
+        assert_eq!(fifo.pop_oldest(), Some(2));
+
+        fifo.resize(1);
+        assert_eq!(fifo.drain().collect::<Vec<_>>(), [4]);
+        assert!(fifo.is_empty());
+    }
+
+    #[test]
+    fn overwrites_without_capacity() {
+        let mut fifo = Fifo::from_iter(core::iter::empty());
+        assert_eq!(fifo.try_overwrite(1), Err(1));
+        assert_eq!(fifo.overwrite(1), Some(1));
+        assert_eq!((fifo.len(), fifo.peek_latest()), (0, None));
+
+        fifo.resize(1);
+        assert_eq!(fifo.try_overwrite(2), Ok(None));
+        assert_eq!(fifo.try_overwrite(3), Ok(Some(2)));
+        assert_eq!(fifo.iter().collect::<Vec<_>>(), [&3]);
+    }
+
+    #[test]
+    fn load_policies() {
+        let dir = std::env::temp_dir().join(format!("pico-build-load-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let existing = dir.join("main.lua");
+        fs::write(&existing, "x=1").unwrap();
+        let missing = dir.join("missing.lua");
+
+        let loaded = FileData::<Box<[u8]>>::new(&existing)
+            .into_loaded(LoadPolicy::ReadOnly)
+            .unwrap();
+        assert_eq!(loaded.unwrap_loaded_data_deref(), b"x=1");
+        assert_eq!(fs::read(&existing).unwrap(), b"x=1");
+
+        let defaulted = FileData::<Box<[u8]>>::new(&missing)
+            .into_loaded_or_default(LoadPolicy::ReadOnly)
+            .unwrap();
+        assert!(defaulted.unwrap_loaded_data_deref().is_empty());
+        assert!(!missing.exists());
+        assert!(
+            FileData::<Box<[u8]>>::new(&missing)
+                .into_loaded(LoadPolicy::ReadOnly)
+                .is_err()
+        );
+
+        assert!(create_new(&existing).is_err());
+        FileData::<Box<[u8]>>::new(&missing)
+            .into_loaded(LoadPolicy::OpenOrCreate)
+            .unwrap();
+        assert!(missing.exists());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn sync_fifo_across_threads() {
+        let fifo = SyncFifo::with_capacity(8);
+        let writers: Vec<_> = (0..4)
+            .map(|writer| {
+                let fifo = fifo.clone();
+                std::thread::spawn(move || {
+                    for value in 0..4 {
+                        fifo.overwrite(writer * 4 + value);
+                    }
+                })
+            })
+            .collect();
+        for writer in writers {
+            writer.join().unwrap();
+        }
+        assert_eq!((fifo.written(), fifo.lock().len()), (16, 8));
+
+        let locked = fifo.lock();
+        assert_eq!(fifo.try_overwrite(16), Err(16));
+        drop(locked);
+        assert!(fifo.try_overwrite(16).is_ok());
+    }
+
+    #[test]
+    fn compiles_overflowing_tabs() {
+        use pico_8_cart_builder::TabOverflow;
+
+        let tabs = || {
+            (0..18).map(|index| pico_8_cart_model::Tab {
+                line_number: 0,
+                code_data: Cow::Owned(match index {
+                    16 => b"-- extra\nx=16\n".to_vec(),
+                    _ => format!("x={index}\n").into_bytes(),
+                }),
+            })
+        };
+        let e = compile_tabs(tabs(), TabOverflow::Error).unwrap_err();
+        assert_eq!(e.room, pico_8_cart_model::P8_MAX_CODE_EDITOR_TAB_COUNT);
+        assert_eq!(
+            e.overflowing,
+            [path::PathBuf::from("extra"), path::PathBuf::from("tab 17")]
+        );
+
+        let code_tabs = compile_tabs(tabs(), TabOverflow::MergeIntoLast).unwrap();
+        assert_eq!(code_tabs.len(), 16);
+        assert_eq!(
+            code_tabs[15].as_ref().unwrap().code_data.as_ref(),
+            b"x=15\n-- extra\nx=16\nx=17\n"
+        );
+        assert_eq!(
+            compile_tabs(tabs().take(16), TabOverflow::Error)
+                .unwrap()
+                .len(),
+            16
+        );
+    }
 }
 
 #[tracing::instrument(level = "debug", skip(path), ret)]
@@ -265,24 +514,68 @@ pub fn source_files_to_tabs(
     })
 }
 
+/// Merges the tabs past the first `room` into the last of them, see
+/// [`pico_8_cart_builder::TabOverflow::MergeIntoLast`]
+pub fn merge_overflowing_tabs<'a>(
+    tabs: impl IntoIterator<Item = pico_8_cart_model::Tab<'a>>,
+    room: usize,
+) -> Vec<pico_8_cart_model::Tab<'a>> {
+    let mut merged: Vec<pico_8_cart_model::Tab<'a>> = Vec::new();
+    for tab in tabs {
+        if merged.len() < room {
+            merged.push(tab);
+            continue;
+        }
+        let Some(last) = merged.last_mut() else {
+            tracing::warn!("Dropping a compiled tab, there is no room for it");
+            continue;
+        };
+        tracing::warn!("Merging a compiled tab into tab {}, the last", room - 1);
+        let code_data = last.code_data.to_mut();
+        if !code_data.is_empty() && !code_data.ends_with(b"\n") {
+            code_data.push(b'\n');
+        }
+        code_data.extend_from_slice(&tab.code_data);
+    }
+    merged
+}
+
+/// Places the tabs into the code-tabs, those past the last handled by the `tab_overflow`
+///
+/// The tabs past the last are reported by their title, or as `tab <index>` without one
 #[tracing::instrument(level = "debug", skip(tabs))]
 pub fn compile_tabs<'a>(
     tabs: impl IntoIterator<Item = pico_8_cart_model::Tab<'a>>,
-) -> pico_8_cart_model::CodeTabs<'a> {
-    tabs.into_iter()
+    tab_overflow: pico_8_cart_builder::TabOverflow,
+) -> Result<pico_8_cart_model::CodeTabs<'a>, pico_8_cart_builder::TooManyTabsError> {
+    let tabs: Vec<_> = tabs.into_iter().collect();
+    let tab_names: Vec<String> = tabs
+        .iter()
         .enumerate()
-        .fold(Default::default(), |mut tabs, (tab_index, code_tab)| {
-            tracing::info!("compiling tab {tab_index}");
-            tabs[tab_index] = Some(code_tab);
-            tabs
+        .map(|(tab_index, tab)| {
+            tab.title()
+                .map_or_else(|| format!("tab {tab_index}"), str::to_string)
         })
+        .collect();
+    tab_overflow.check(&tab_names, 0)?;
+    Ok(
+        merge_overflowing_tabs(tabs, pico_8_cart_model::P8_MAX_CODE_EDITOR_TAB_COUNT)
+            .into_iter()
+            .enumerate()
+            .fold(Default::default(), |mut tabs, (tab_index, code_tab)| {
+                tracing::info!("compiling tab {tab_index}");
+                tabs.insert(tab_index, code_tab);
+                tabs
+            }),
+    )
 }
 
 #[tracing::instrument(level = "debug", skip(source_files))]
 fn compile_source_files_to_tabs(
     source_files: impl IntoIterator<Item = FileData<Box<[u8]>>>,
-) -> pico_8_cart_model::CodeTabs<'static> {
-    compile_tabs(source_files_to_tabs(source_files))
+    tab_overflow: pico_8_cart_builder::TabOverflow,
+) -> Result<pico_8_cart_model::CodeTabs<'static>, pico_8_cart_builder::TooManyTabsError> {
+    compile_tabs(source_files_to_tabs(source_files), tab_overflow)
 }
 
 #[tracing::instrument(level = "debug", skip(dir_entries))]
@@ -306,16 +599,57 @@ pub fn get_tab_data_from_files_in_directory<P: AsRef<path::Path> + ?Sized>(
     source_files_in_directory(path).map(source_files_to_tabs)
 }
 
+/// Each lua source-file in the directory as a tab, in the tab-order
 pub fn get_source_tabs<P: AsRef<path::Path> + ?Sized>(
     src_dir: &P,
+    tab_order: &pico_8_cart_builder::TabOrder,
 ) -> io::Result<impl Iterator<Item = pico_8_cart_model::Tab<'static>>> {
-    get_lua_files(src_dir).map(dir_entries_to_tabs)
+    get_lua_files(src_dir).map(|lua_files| dir_entries_to_tabs(tab_order.sort(lua_files)))
+}
+
+/// Inlines the `#include`-directives of a loaded source-file
+#[tracing::instrument(level = "debug", skip(source_file, include_resolver))]
+pub fn resolve_source_includes(
+    source_file: FileData<Box<[u8]>>,
+    include_resolver: &pico_8_cart_builder::IncludeResolver,
+) -> Result<FileData<Box<[u8]>>, pico_8_cart_builder::IncludeError> {
+    let FileData::Loaded { path, data } = source_file else {
+        return Ok(source_file);
+    };
+    let data = match include_resolver.resolve(&path, &data)? {
+        Cow::Owned(resolved) => resolved.into_boxed_slice(),
+        Cow::Borrowed(_) => data,
+    };
+    Ok(FileData::Loaded { path, data })
+}
+
+/// A source-file with its includes inlined, and the file and line each line came from
+pub type ResolvedSourceFile = (
+    FileData<Box<[u8]>>,
+    pico_8_cart_builder::include::LineOrigins,
+);
+
+/// Like [`resolve_source_includes`], also returning the file and line each line of the
+/// resolved source-file came from, for the [`source_map::SourceMap`]
+pub fn resolve_source_includes_with_origins(
+    source_file: FileData<Box<[u8]>>,
+    include_resolver: &pico_8_cart_builder::IncludeResolver,
+) -> Result<ResolvedSourceFile, pico_8_cart_builder::IncludeError> {
+    let FileData::Loaded { path, data } = source_file else {
+        return Ok((source_file, Vec::new()));
+    };
+    let (data, origins) = match include_resolver.resolve_with_origins(&path, &data)? {
+        (Cow::Owned(resolved), origins) => (resolved.into_boxed_slice(), origins),
+        (Cow::Borrowed(_), origins) => (data, origins),
+    };
+    Ok((FileData::Loaded { path, data }, origins))
 }
 
 pub fn compile_tabs_to_cart_data<'a>(
     tabs: impl IntoIterator<Item = pico_8_cart_model::Tab<'a>>,
-) -> pico_8_cart_model::CartData<'a> {
-    pico_8_cart_model::CartData::default_with_code_tabs(compile_tabs(tabs))
+    tab_overflow: pico_8_cart_builder::TabOverflow,
+) -> Result<pico_8_cart_model::CartData<'a>, pico_8_cart_builder::TooManyTabsError> {
+    compile_tabs(tabs, tab_overflow).map(pico_8_cart_model::CartData::default_with_code_tabs)
 }
 
 pub trait FromFile {
@@ -369,7 +703,7 @@ impl FromFile for Box<[u8]> {
 }
 
 impl FromFile for pico_8_cart_model::CartData<'static> {
-    type Error = pico_8_cart_model::CartDataError<'static>;
+    type Error = pico_8_cart_model::CartDataError;
     #[tracing::instrument(level = "debug", ret)]
     fn from_file(file: fs::File) -> Result<Self, Self::Error>
     where
@@ -379,6 +713,56 @@ impl FromFile for pico_8_cart_model::CartData<'static> {
     }
 }
 
+/// Opens the file for reading, failing if it does not exist
+pub fn open_readonly<P: AsRef<path::Path> + ?Sized>(path: &P) -> io::Result<fs::File> {
+    fs::File::open(path)
+}
+
+/// Opens the file for reading and writing, creating it empty if it does not exist
+///
+/// An existing file is kept as it is
+pub fn open_or_create<P: AsRef<path::Path> + ?Sized>(path: &P) -> io::Result<fs::File> {
+    fs::OpenOptions::new()
+        .create(true)
+        .read(true)
+        .write(true)
+        .truncate(false)
+        .open(path)
+}
+
+/// Creates the file empty for reading and writing, failing if it already exists
+pub fn create_new<P: AsRef<path::Path> + ?Sized>(path: &P) -> io::Result<fs::File> {
+    fs::OpenOptions::new()
+        .create_new(true)
+        .read(true)
+        .write(true)
+        .open(path)
+}
+
+/// Whether loading a [`FileData`] may create the file
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum LoadPolicy {
+    /// Only reads the file, see [`open_readonly`]
+    ///
+    /// A missing file is loaded as the default, when loading with one
+    #[default]
+    ReadOnly,
+    /// Creates the file if it does not exist, see [`open_or_create`]
+    OpenOrCreate,
+    /// Creates the file, which must not exist yet, see [`create_new`]
+    CreateNew,
+}
+
+impl LoadPolicy {
+    pub fn open<P: AsRef<path::Path> + ?Sized>(self, path: &P) -> io::Result<fs::File> {
+        match self {
+            LoadPolicy::ReadOnly => open_readonly(path),
+            LoadPolicy::OpenOrCreate => open_or_create(path),
+            LoadPolicy::CreateNew => create_new(path),
+        }
+    }
+}
+
 #[derive(Clone, Debug)]
 pub enum FileData<T> {
     Unloaded(path::PathBuf),
@@ -464,6 +848,7 @@ impl<T> FileData<T> {
 
     fn load_inner<P: AsRef<path::Path> + ?Sized>(
         path: &P,
+        load_policy: LoadPolicy,
         default: Option<T>,
     ) -> Result<T, FileDataError<T>>
     where
@@ -474,13 +859,14 @@ impl<T> FileData<T> {
             core::any::type_name::<T>(),
             path.as_ref()
         );
-        let file = fs::OpenOptions::new()
-            .create(true)
-            .read(true)
-            .write(true)
-            .truncate(false)
-            // .truncate(true)
-            .open(path)?;
+        let (file, default) = match (load_policy.open(path), default) {
+            (Ok(file), default) => (file, default),
+            (Err(e), Some(val)) if e.kind() == io::ErrorKind::NotFound => {
+                tracing::debug!("No file at {:?}, using the default value", path.as_ref());
+                return Ok(val);
+            }
+            (Err(e), _) => return Err(e.into()),
+        };
         match T::from_file(file).map_err(FileDataError::OnFromFile) {
             Ok(val) => {
                 tracing::debug!(
@@ -503,13 +889,13 @@ impl<T> FileData<T> {
         }
     }
     #[tracing::instrument(level = "debug", skip(self))]
-    pub fn load_or_default(&mut self) -> Result<(), FileDataError<T>>
+    pub fn load_or_default(&mut self, load_policy: LoadPolicy) -> Result<(), FileDataError<T>>
     where
         T: FromFile + Default,
     {
         match self {
             FileData::Unloaded(path) => {
-                let data = FileData::load_inner(path, Some(T::default()))?;
+                let data = FileData::load_inner(path, load_policy, Some(T::default()))?;
 
                 *self = FileData::Loaded {
                     path: path.to_path_buf(),
@@ -531,13 +917,13 @@ impl<T> FileData<T> {
         }
     }
     #[tracing::instrument(level = "debug", skip(self))]
-    pub fn load(&mut self) -> Result<(), FileDataError<T>>
+    pub fn load(&mut self, load_policy: LoadPolicy) -> Result<(), FileDataError<T>>
     where
         T: FromFile,
     {
         match self {
             FileData::Unloaded(path) => {
-                let data = FileData::load_inner(path, None)?;
+                let data = FileData::load_inner(path, load_policy, None)?;
 
                 *self = FileData::Loaded {
                     path: path.to_path_buf(),
@@ -553,14 +939,18 @@ impl<T> FileData<T> {
         }
     }
 
-    fn into_loaded_inner(self, default: Option<T>) -> Result<FileData<T>, FileDataError<T>>
+    fn into_loaded_inner(
+        self,
+        load_policy: LoadPolicy,
+        default: Option<T>,
+    ) -> Result<FileData<T>, FileDataError<T>>
     where
         T: FromFile,
     {
         tracing::info!("Loading file: {:?}", self.as_path());
 
         match self {
-            FileData::Unloaded(path) => FileData::load_inner(path.as_path(), default)
+            FileData::Unloaded(path) => FileData::load_inner(path.as_path(), load_policy, default)
                 .map(|data| FileData::Loaded { path, data }),
             _ => {
                 tracing::debug!("Loaded already");
@@ -570,18 +960,21 @@ impl<T> FileData<T> {
     }
 
     #[tracing::instrument(level = "debug", skip(self))]
-    pub fn into_loaded(self) -> Result<FileData<T>, FileDataError<T>>
+    pub fn into_loaded(self, load_policy: LoadPolicy) -> Result<FileData<T>, FileDataError<T>>
     where
         T: FromFile,
     {
-        self.into_loaded_inner(None)
+        self.into_loaded_inner(load_policy, None)
     }
     #[tracing::instrument(level = "debug", skip(self))]
-    pub fn into_loaded_or_default(self) -> Result<FileData<T>, FileDataError<T>>
+    pub fn into_loaded_or_default(
+        self,
+        load_policy: LoadPolicy,
+    ) -> Result<FileData<T>, FileDataError<T>>
     where
         T: FromFile + Default,
     {
-        self.into_loaded_inner(Some(T::default()))
+        self.into_loaded_inner(load_policy, Some(T::default()))
     }
 
     pub fn as_path(&self) -> &path::Path {
@@ -622,22 +1015,11 @@ where
     #[tracing::instrument(level = "debug", skip(self))]
     #[inline(always)]
     fn collect_into<U: FromIterator<u8>>(self) -> U {
-        let file_data = self.unwrap_loaded_data_ref();
-        if file_data.as_ref().starts_with(b"--") {
-            self.unwrap_loaded_data().into_iter().collect()
-        } else {
-            let name = self
-                .get_name()
-                .expect("failed to get name for stateful file when collecting");
-
-            // So the pico-8 editor gets a nice title view too, QoL i guess...
-            let name_as_comment = format!("-- {name}\n");
-            name_as_comment
-                .into_bytes()
-                .into_iter()
-                .chain(self.unwrap_loaded_data())
-                .collect()
-        }
+        let name = self
+            .get_name()
+            .expect("failed to get name for stateful file when collecting");
+        // So the pico-8 editor gets a nice title view too, see [`tab_title`]
+        tab_title::with_title(name, self.unwrap_loaded_data_ref().as_ref())
     }
 }
 impl<T> TryFrom<fs::DirEntry> for FileData<T> {
@@ -709,85 +1091,56 @@ impl TryFrom<fs::DirEntry> for SourceFile {
 }
 
 impl SourceFile {
+    /// The file-name, without its extension
     fn get_name(&self) -> String {
         self.src_entry
-            .file_name()
-            .into_string()
+            .path()
+            .file_stem()
+            .and_then(ffi::OsStr::to_str)
             .expect("pico-8 source file name contained invalid utf-8")
+            .to_string()
     }
 
     #[tracing::instrument(level = "debug", skip(self))]
     #[inline(always)]
     pub fn collect_into<T: FromIterator<u8>>(self) -> T {
-        if self.file_data.starts_with(b"--") {
-            self.file_data.into_iter().collect()
-        } else {
-            let name = self.get_name();
-            let SourceFile { file_data, .. } = self;
-            // So the pico-8 editor gets a nice title view too, QoL i guess...
-            let name_as_comment = format!("-- {name}\n");
-            name_as_comment
-                .into_bytes()
-                .into_iter()
-                .chain(file_data)
-                .collect()
-        }
+        // So the pico-8 editor gets a nice title view too, see [`tab_title`]
+        tab_title::with_title(&self.get_name(), &self.file_data)
     }
 
     #[expect(dead_code)]
     fn into_boxed_slice(self) -> Box<[u8]> {
-        if self.file_data.starts_with(b"--") {
-            self.file_data
-        } else {
-            let name = self.get_name();
-            let SourceFile { file_data, .. } = self;
-            // So the pico-8 editor gets a nice title view too, QoL i guess...
-            let name_as_comment = format!("-- {name}\n");
-            name_as_comment
-                .into_bytes()
-                .into_iter()
-                .chain(file_data)
-                .collect()
-        }
+        // So the pico-8 editor gets a nice title view too, see [`tab_title`]
+        tab_title::with_title(&self.get_name(), &self.file_data)
     }
 }
 
 /// Takes an iterator over files selected to
 /// be compiled, and the output cart-path
 ///
-/// Attempts to merge together the code sections
-///
-/// TODO: Proper merge-logic
+/// Merges the code into the code of the cart by the merge-strategy, see [`merge::MergeStrategy`]
 pub fn compile_cartridge(
     cart_file: FileData<Box<pico_8_cart_model::CartData<'static>>>,
     source_files: impl Iterator<Item = FileData<Box<[u8]>>>,
+    merge_strategy: merge::MergeStrategy,
 ) -> io::Result<pico_8_cart_model::CartData<'static>> {
     // construct the tabs
-    let tabs = source_files_to_tabs(source_files);
-
-    // Compile the code-tabs
-    let code_tabs: pico_8_cart_model::CodeTabs =
-        tabs.enumerate()
-            .fold(Default::default(), |mut tabs, (tab_index, code_tab)| {
-                tracing::info!("compiling tab {tab_index}");
-                tabs[tab_index] = Some(code_tab);
-                tabs
-            });
-
-    let code_tab_count = code_tabs.iter().filter(|elt| elt.is_some()).count();
-    tracing::info!("Compiling {code_tab_count} tabs");
-
-    // We don't want to ignore the content of the pico-8 `main`
-    // file and overwrite it without modifying it first
-    //
-    // TODO: Merge code-sections (might be really really complicated)
-    // let mut cart = pico_8_cart_model::CartData::from_path_or_default(cart_file)?;
+    let tabs: Vec<_> = source_files_to_tabs(source_files).collect();
+    tracing::info!("Compiling {} tabs with {merge_strategy:?}", tabs.len());
 
     let mut cart = *cart_file.unwrap_loaded_data();
+    // Replacing the code would drop the sprites embedded into it
+    match cart.restore_embedded_sprites() {
+        Ok(0) => {}
+        Ok(restored) => tracing::info!("Restored {restored} embedded sprites"),
+        Err(e) => return Err(io::Error::other(e)),
+    }
 
     // Overwrite the cart-data and recopy it
-    if code_tabs.iter().any(Option::is_some) {
-        cart.set_code_data(code_tabs);
+    if !tabs.is_empty() {
+        let code_tabs = merge_strategy.merge(cart.code_tabs(), tabs);
+        cart.try_set_code_data(code_tabs)
+            .map_err(io::Error::other)?;
     }
     Ok(cart)
 }
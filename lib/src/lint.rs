@@ -0,0 +1,383 @@
+//! Line-based checks over the code-tabs of a cart
+
+use alloc::collections::BTreeSet;
+
+use pico_8_cart_model::{CartData, CodeTabs};
+
+use crate::builtins;
+
+/// Rules checking for things which break once a cart is published to the BBS
+pub const PUBLISH_TAG: &str = "publish";
+/// Rules checking for code which runs, but likely not as intended
+pub const CORRECTNESS_TAG: &str = "correctness";
+
+/// A check performed on each line of code
+#[derive(Debug)]
+pub struct Rule {
+    /// The stable diagnostic-code, registered in [`crate::diagnostics::DIAGNOSTICS`]
+    pub code: &'static str,
+    pub name: &'static str,
+    pub tags: &'static [&'static str],
+    pub description: &'static str,
+    /// Receives the line with comments stripped
+    check: fn(&str, &LintContext) -> bool,
+}
+
+impl Rule {
+    pub fn has_tag(&self, tag: &str) -> bool {
+        self.tags.contains(&tag)
+    }
+    pub fn check(&self, line: &str, context: &LintContext) -> bool {
+        (self.check)(line, context)
+    }
+}
+
+/// What the rules know of the code beyond the line
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct LintContext {
+    /// The version of the cart, or the version targeted
+    pub cart_version: usize,
+    /// The names declared anywhere in the code, see [`declared_names`]
+    pub declared_names: BTreeSet<String>,
+}
+
+impl LintContext {
+    pub fn new(cart_version: usize) -> LintContext {
+        LintContext {
+            cart_version,
+            declared_names: BTreeSet::new(),
+        }
+    }
+    /// Whether the function is the one of the pico-8 API, and not declared by the code,
+    /// e.g. a replacement of one added by a later version
+    fn calls_builtin(&self, name: &str) -> bool {
+        builtins::added_in(name).is_some() && !self.declared_names.contains(name)
+    }
+}
+
+pub const RULES: &[Rule] = &[
+    Rule {
+        code: "L001",
+        name: "extcmd",
+        tags: &[PUBLISH_TAG],
+        description: "extcmd is not available in the web player",
+        check: |line, _| calls_any(line, &["extcmd"]),
+    },
+    Rule {
+        code: "L002",
+        name: "filesystem",
+        tags: &[PUBLISH_TAG],
+        description: "filesystem functions are not available in the web player",
+        check: |line, _| calls_any(line, &["ls", "cd", "folder"]),
+    },
+    Rule {
+        code: "L003",
+        name: "include",
+        tags: &[PUBLISH_TAG],
+        description: "#include is resolved from the local filesystem when loading the cart",
+        check: |line, _| line.trim_start().starts_with("#include"),
+    },
+    Rule {
+        code: "L004",
+        name: "printh",
+        tags: &[PUBLISH_TAG],
+        description: "printh is a debug-call, and has no output in the web player",
+        check: |line, _| calls_any(line, &["printh"]),
+    },
+    Rule {
+        code: "L005",
+        name: "shadowed-builtin",
+        tags: &[CORRECTNESS_TAG],
+        description: "a variable, parameter or function shadows a pico-8 built-in",
+        check: |line, context| {
+            declared_names(line)
+                .into_iter()
+                .any(|name| builtins::is_builtin(name, context.cart_version))
+        },
+    },
+    Rule {
+        code: "L006",
+        name: "unavailable-builtin",
+        tags: &[CORRECTNESS_TAG, PUBLISH_TAG],
+        description: "calls a pico-8 built-in added after the version of the cart",
+        check: |line, context| {
+            called_names(line).into_iter().any(|name| {
+                context.calls_builtin(name)
+                    && builtins::added_in(name)
+                        .is_some_and(|version| version > context.cart_version)
+            })
+        },
+    },
+    Rule {
+        code: "L007",
+        name: "deprecated-builtin",
+        tags: &[CORRECTNESS_TAG],
+        description: "calls a pico-8 built-in superseded by an operator",
+        check: |line, context| {
+            called_names(line).into_iter().any(|name| {
+                context.calls_builtin(name)
+                    && builtins::replacement(name, context.cart_version).is_some()
+            })
+        },
+    },
+];
+
+/// Returns the line up until a `--` comment
+fn strip_comment(line: &str) -> &str {
+    line.split_once("--").map_or(line, |(code, _)| code)
+}
+
+const fn is_identifier_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_'
+}
+
+/// Checks if the line contains a call to any of the named functions
+fn calls_any(line: &str, names: &[&str]) -> bool {
+    names.iter().any(|name| {
+        line.match_indices(name).any(|(index, _)| {
+            let preceded_by_identifier = line[..index].chars().next_back().is_some_and(|c| {
+                // Method-calls, e.g. `obj:ls()`, are not the builtin
+                is_identifier_char(c) || c == '.' || c == ':'
+            });
+            let followed_by_call = line[index + name.len()..]
+                .trim_start()
+                .starts_with(['(', '"', '\'']);
+            !preceded_by_identifier && followed_by_call
+        })
+    })
+}
+
+/// The names of the functions the line calls, without methods and fields, e.g. `p:ls()`
+fn called_names(line: &str) -> Vec<&str> {
+    let tokens = tokens(line);
+    tokens
+        .iter()
+        .enumerate()
+        .filter(|(index, token)| {
+            token.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+                && !index
+                    .checked_sub(1)
+                    .is_some_and(|previous| matches!(tokens[previous], "." | ":"))
+                && tokens
+                    .get(index + 1)
+                    .is_some_and(|next| next.starts_with(['(', '"', '\'', '{']))
+        })
+        .map(|(_, token)| *token)
+        .collect()
+}
+
+/// The identifiers, strings and punctuation of the line, without the contents of strings
+fn tokens(line: &str) -> Vec<&str> {
+    let mut tokens = Vec::new();
+    let mut rest = line;
+    while let Some(c) = rest.chars().next() {
+        let len = if is_identifier_char(c) {
+            rest.find(|c| !is_identifier_char(c)).unwrap_or(rest.len())
+        } else if c == '"' || c == '\'' {
+            let mut escaped = false;
+            let end = rest[1..].find(|other| {
+                let is_end = other == c && !escaped;
+                escaped = other == '\\' && !escaped;
+                is_end
+            });
+            end.map_or(rest.len(), |end| end + 2)
+        } else if rest.starts_with("==") || rest.starts_with("~=") || rest.starts_with("!=") {
+            2
+        } else {
+            c.len_utf8()
+        };
+        let (token, others) = rest.split_at(len);
+        if !token.trim().is_empty() {
+            tokens.push(token);
+        }
+        rest = others;
+    }
+    tokens
+}
+
+/// The names the line declares or assigns as locals, parameters, functions or globals
+///
+/// Fields and methods, e.g. `p.map = 1`, are not declared
+fn declared_names(line: &str) -> Vec<&str> {
+    let tokens = tokens(line);
+    let is_name = |token: &&str| token.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_');
+    // A comma-separated list of names from the index
+    let names_from = |mut index: usize| -> Vec<&str> {
+        let mut names = Vec::new();
+        while let Some(name) = tokens.get(index).filter(|token| is_name(token)) {
+            names.push(*name);
+            if tokens.get(index + 1) != Some(&",") {
+                break;
+            }
+            index += 2;
+        }
+        names
+    };
+    let mut names = Vec::new();
+    for (index, token) in tokens.iter().enumerate() {
+        match *token {
+            "local" | "for" if tokens.get(index + 1) != Some(&"function") => {
+                names.extend(names_from(index + 1));
+            }
+            "function" => {
+                let is_field = matches!(tokens.get(index + 2), Some(&("." | ":")));
+                if let Some(name) = tokens.get(index + 1).filter(|token| is_name(token))
+                    && !is_field
+                {
+                    names.push(name);
+                }
+                if let Some(parameters) = tokens[index..].iter().position(|token| *token == "(") {
+                    names.extend(names_from(index + parameters + 1));
+                }
+            }
+            _ => {}
+        }
+    }
+    // A global assignment, e.g. `map = {}` or `x, t = 0, 0`
+    let assigned = names_from(0);
+    if !assigned.is_empty() && tokens.get(assigned.len() * 2 - 1) == Some(&"=") {
+        names.extend(assigned);
+    }
+    names
+}
+
+/// A rule which matched a line of code
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Finding {
+    pub rule: &'static Rule,
+    /// The (0-based) index of the tab
+    pub tab_index: usize,
+    /// The (1-based) line-number within the tab
+    pub line_number: usize,
+    pub line: String,
+}
+
+impl PartialEq for Rule {
+    fn eq(&self, other: &Self) -> bool {
+        self.code == other.code
+    }
+}
+
+impl Eq for Rule {}
+
+/// Checks the code-tabs of the cart against the rules with the tag, for the targeted
+/// version, e.g. `cart_version` of the config-file, or else the version of the cart
+pub fn lint_cart(cart: &CartData<'_>, tag: &str, target_version: Option<usize>) -> Vec<Finding> {
+    let cart_version = target_version.unwrap_or_else(|| cart.version().unwrap_or_default());
+    lint_code_tabs(cart.code_tabs(), tag, cart_version)
+}
+
+/// Checks all code-tabs against the rules with the tag, for carts of the version
+#[tracing::instrument(level = "debug", skip(code_tabs))]
+pub fn lint_code_tabs(code_tabs: &CodeTabs<'_>, tag: &str, cart_version: usize) -> Vec<Finding> {
+    let rules: Vec<&'static Rule> = RULES.iter().filter(|rule| rule.has_tag(tag)).collect();
+    let rules = rules.as_slice();
+    let mut context = LintContext::new(cart_version);
+    for (_, tab) in code_tabs.iter_present() {
+        for line in bytes::NewlineIter::new(tab.code_data.as_ref()) {
+            let line = String::from_utf8_lossy(line);
+            context.declared_names.extend(
+                declared_names(strip_comment(&line))
+                    .into_iter()
+                    .map(ToString::to_string),
+            );
+        }
+    }
+    let context = &context;
+    code_tabs
+        .iter_present()
+        .flat_map(|(tab_index, tab)| {
+            bytes::NewlineIter::new(tab.code_data.as_ref())
+                .enumerate()
+                .flat_map(move |(line_index, line)| {
+                    let line = String::from_utf8_lossy(line);
+                    let code = strip_comment(&line);
+                    rules
+                        .iter()
+                        .filter(|rule| rule.check(code, context))
+                        .map(|rule| Finding {
+                            rule,
+                            tab_index,
+                            line_number: line_index + 1,
+                            line: line.trim_end().to_string(),
+                        })
+                        .collect::<Vec<_>>()
+                })
+                .collect::<Vec<_>>()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn publish_rules() {
+        let mut code_tabs: CodeTabs<'_> = Default::default();
+        code_tabs[0] = Some(pico_8_cart_model::Tab {
+            line_number: 3,
+            code_data: br#"#include lib.lua
+printh("debug")
+-- printh("commented out")
+player:ls()
+extcmd "rec"
+"#
+            .as_slice()
+            .into(),
+        });
+        let found: Vec<(&str, usize)> = lint_code_tabs(&code_tabs, PUBLISH_TAG, 43)
+            .into_iter()
+            .map(|finding| (finding.rule.name, finding.line_number))
+            .collect();
+        assert_eq!(found, [("include", 1), ("printh", 2), ("extcmd", 5)]);
+    }
+
+    #[test]
+    fn shadowed_builtins() {
+        assert_eq!(declared_names("local map, x = {}, 0"), ["map", "x"]);
+        assert_eq!(declared_names("for t=0,1 do"), ["t"]);
+        assert_eq!(declared_names("for k, v in pairs(all)"), ["k", "v"]);
+        assert_eq!(declared_names("function add(a, b)"), ["add", "a", "b"]);
+        assert_eq!(declared_names("local function run() end"), ["run"]);
+        assert_eq!(declared_names("function p:draw(t)"), ["t"]);
+        assert_eq!(declared_names("time, y = 0, 1"), ["time", "y"]);
+        assert_eq!(declared_names("map(0, 0)").len(), 0);
+        assert_eq!(declared_names("if t == 0 then"), [] as [&str; 0]);
+        assert_eq!(declared_names("p.map = \"local map\""), [] as [&str; 0]);
+
+        let rule = RULES.iter().find(|rule| rule.code == "L005").unwrap();
+        assert!(rule.check("local split = 1", &LintContext::new(43)));
+        assert!(!rule.check("local split = 1", &LintContext::new(16)));
+        assert!(!rule.check("local player = {}", &LintContext::new(43)));
+    }
+
+    #[test]
+    fn versioned_builtins() {
+        let mut code_tabs: CodeTabs<'_> = Default::default();
+        code_tabs[0] = Some(pico_8_cart_model::Tab {
+            line_number: 0,
+            code_data: br#"parts = split("a,b")
+x = shl(1, 4) + obj:serial()
+o = ord "a"
+function chr(n) return "?" end
+print(chr(65))
+"#
+            .as_slice()
+            .into(),
+        });
+        let found = |cart_version| -> Vec<(&str, usize)> {
+            lint_code_tabs(&code_tabs, CORRECTNESS_TAG, cart_version)
+                .into_iter()
+                .filter(|finding| finding.rule.code != "L005")
+                .map(|finding| (finding.rule.name, finding.line_number))
+                .collect()
+        };
+        // `chr` is declared by the cart, a replacement for older versions
+        assert_eq!(
+            found(16),
+            [("unavailable-builtin", 1), ("unavailable-builtin", 3)]
+        );
+        assert_eq!(found(43), [("deprecated-builtin", 2)]);
+    }
+}
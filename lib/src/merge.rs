@@ -0,0 +1,176 @@
+//! Merging the compiled code-tabs into the code of the cart compiled into
+//!
+//! Without merging, code edited in the pico-8 code-editor is overwritten on each build
+
+use alloc::borrow::Cow;
+
+use pico_8_cart_model::{CodeTabs, Tab};
+
+/// Opens a region kept by [`MergeStrategy::MergeByMarker`], followed by its name
+pub const KEEP_DIRECTIVE: &[u8] = b"--#keep";
+/// Closes the region opened by the last [`KEEP_DIRECTIVE`]
+pub const END_KEEP_DIRECTIVE: &[u8] = b"--#endkeep";
+
+/// How [`crate::compile_cartridge`] treats the code already in the cart
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum MergeStrategy {
+    /// Replaces all code of the cart with the compiled tabs
+    #[default]
+    ReplaceCode,
+    /// Keeps the first tab of the cart, followed by the compiled tabs
+    PreserveMainTab,
+    /// Replaces all code, except the bodies of the regions between `--#keep <name>` and
+    /// `--#endkeep`, which are kept from the region of the same name in the cart
+    MergeByMarker,
+}
+
+impl MergeStrategy {
+    pub fn from_name(name: &str) -> Option<MergeStrategy> {
+        match name.to_ascii_lowercase().as_str() {
+            "replace-code" => Some(MergeStrategy::ReplaceCode),
+            "preserve-main-tab" => Some(MergeStrategy::PreserveMainTab),
+            "merge-by-marker" => Some(MergeStrategy::MergeByMarker),
+            _ => None,
+        }
+    }
+    /// The tab-index of the first compiled tab
+    pub const fn first_compiled_tab(&self) -> usize {
+        match self {
+            MergeStrategy::PreserveMainTab => 1,
+            MergeStrategy::ReplaceCode | MergeStrategy::MergeByMarker => 0,
+        }
+    }
+    /// Merges the compiled tabs with the tabs of the cart
+    ///
+    /// Compiled tabs which do not fit into the cart are merged into its last tab with a
+    /// warning, see [`crate::merge_overflowing_tabs`]
+    #[tracing::instrument(level = "debug", skip(cart_tabs, compiled_tabs))]
+    pub fn merge(
+        &self,
+        cart_tabs: &CodeTabs<'_>,
+        compiled_tabs: impl IntoIterator<Item = Tab<'static>>,
+    ) -> CodeTabs<'static> {
+        let mut code_tabs: CodeTabs<'static> = Default::default();
+        if *self == MergeStrategy::PreserveMainTab {
+            code_tabs[0] = cart_tabs[0].clone().map(Tab::into_owned);
+        }
+        let kept_regions = match self {
+            MergeStrategy::MergeByMarker => keep_regions(cart_tabs),
+            MergeStrategy::ReplaceCode | MergeStrategy::PreserveMainTab => Vec::new(),
+        };
+        let first_compiled_tab = self.first_compiled_tab();
+        let room = code_tabs.capacity().saturating_sub(first_compiled_tab);
+        for (index, mut tab) in crate::merge_overflowing_tabs(compiled_tabs, room)
+            .into_iter()
+            .enumerate()
+        {
+            if !kept_regions.is_empty() {
+                tab.code_data = Cow::Owned(merge_regions(&tab.code_data, &kept_regions));
+            }
+            code_tabs.insert(first_compiled_tab + index, tab);
+        }
+        code_tabs
+    }
+}
+
+/// Returns the region-name, if the line opens a region
+fn keep_region_name(line: &[u8]) -> Option<&[u8]> {
+    let name = line
+        .trim_ascii()
+        .strip_prefix(KEEP_DIRECTIVE)?
+        .trim_ascii_start();
+    (!name.is_empty()).then_some(name)
+}
+
+fn is_end_keep(line: &[u8]) -> bool {
+    line.trim_ascii() == END_KEEP_DIRECTIVE
+}
+
+/// The name and body of each region in the tabs
+fn keep_regions<'a>(code_tabs: &'a CodeTabs<'_>) -> Vec<(&'a [u8], Vec<u8>)> {
+    let mut regions = Vec::new();
+    for (_, tab) in code_tabs.iter_present() {
+        let mut open: Option<(&[u8], Vec<u8>)> = None;
+        for line in tab.code_data.split_inclusive(|byte| *byte == b'\n') {
+            match open.as_mut() {
+                Some(_) if is_end_keep(line) => regions.extend(open.take()),
+                Some((_, body)) => body.extend_from_slice(line),
+                None => open = keep_region_name(line).map(|name| (name, Vec::new())),
+            }
+        }
+    }
+    regions
+}
+
+/// Replaces the body of each region in the code with the kept body of the same name
+fn merge_regions(code_data: &[u8], kept_regions: &[(&[u8], Vec<u8>)]) -> Vec<u8> {
+    let mut merged = Vec::with_capacity(code_data.len());
+    let mut skipping = false;
+    for line in code_data.split_inclusive(|byte| *byte == b'\n') {
+        if skipping && !is_end_keep(line) {
+            continue;
+        }
+        skipping = false;
+        merged.extend_from_slice(line);
+        let Some(name) = keep_region_name(line) else {
+            continue;
+        };
+        if let Some((_, body)) = kept_regions.iter().find(|(kept, _)| *kept == name) {
+            merged.extend_from_slice(body);
+            skipping = true;
+        }
+    }
+    merged
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tab(code: &str) -> Tab<'static> {
+        Tab {
+            line_number: 0,
+            code_data: Cow::Owned(code.as_bytes().to_vec()),
+        }
+    }
+
+    fn code<'a>(code_tabs: &'a CodeTabs<'_>, tab_index: usize) -> Option<&'a [u8]> {
+        code_tabs[tab_index]
+            .as_ref()
+            .map(|tab| tab.code_data.as_ref())
+    }
+
+    #[test]
+    fn merges_by_strategy() {
+        let mut cart_tabs: CodeTabs<'static> = Default::default();
+        cart_tabs[0] = Some(tab("--#keep tuning\nspeed=3\n--#endkeep\nedited=true\n"));
+        let compiled = || {
+            [
+                tab("--#keep tuning\nspeed=1\n--#endkeep\nx=1\n"),
+                tab("y=2\n"),
+            ]
+        };
+
+        let replaced = MergeStrategy::ReplaceCode.merge(&cart_tabs, compiled());
+        assert_eq!(
+            code(&replaced, 0),
+            Some(b"--#keep tuning\nspeed=1\n--#endkeep\nx=1\n".as_slice())
+        );
+
+        let preserved = MergeStrategy::PreserveMainTab.merge(&cart_tabs, compiled());
+        assert_eq!(code(&preserved, 0), code(&cart_tabs, 0));
+        assert_eq!(code(&preserved, 2), Some(b"y=2\n".as_slice()));
+
+        let merged = MergeStrategy::MergeByMarker.merge(&cart_tabs, compiled());
+        assert_eq!(
+            code(&merged, 0),
+            Some(b"--#keep tuning\nspeed=3\n--#endkeep\nx=1\n".as_slice())
+        );
+        assert_eq!(code(&merged, 1), Some(b"y=2\n".as_slice()));
+
+        // The compiled tabs past the last tab are merged into it
+        let overflowing = MergeStrategy::PreserveMainTab
+            .merge(&cart_tabs, (0..16).map(|index| tab(&format!("t={index}"))));
+        assert_eq!(code(&overflowing, 15), Some(b"t=14\nt=15".as_slice()));
+    }
+}
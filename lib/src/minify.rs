@@ -0,0 +1,297 @@
+//! Minifying the compiled tabs, as an optional step of the build
+//!
+//! The comments, indentation and blank lines are removed, and the tokens of a line are
+//! joined with a space only where they would run together otherwise. Line-breaks
+//! between the remaining lines are kept, so the single-line shorthands of pico-8, such
+//! as `if (cond) stmt` and `?`, end where they did. The locals can also be renamed to
+//! the shortest names not used anywhere in the code
+//!
+//! The token-count is unchanged, the characters and the compressed size shrink
+
+use core::fmt;
+
+use alloc::collections::{BTreeMap, BTreeSet};
+
+use std::path;
+
+use pico_8_cart_builder::lex::KEYWORDS;
+use pico_8_cart_model::CodeTabs;
+use pico_8_cart_model::Tab;
+use pico_8_cart_model::tokens::count_tokens;
+
+use crate::paths;
+use crate::syntax::{self, Chunk, SyntaxError, Token, TokenKind};
+
+/// The characters a renamed local may start with
+const NAME_START: &[u8] = b"abcdefghijklmnopqrstuvwxyz";
+/// The characters following the first of a renamed local
+const NAME_REST: &[u8] = b"abcdefghijklmnopqrstuvwxyz0123456789_";
+
+/// The `[minify]` section, minifying the compiled tabs on each build
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Minification {
+    /// Renames the locals, see [`minify_code_tabs`]
+    pub rename_locals: bool,
+    /// The source-files whose tabs are kept as written, by file-name or by path
+    /// relative to the source-directory
+    pub skip: Vec<String>,
+}
+
+impl Minification {
+    /// Whether the tab compiled from the source-file, relative to the source-directory,
+    /// is minified
+    pub fn minifies(&self, source_path: &path::Path) -> bool {
+        let shown_path = paths::display(source_path).to_string();
+        let file_name = source_path
+            .file_name()
+            .and_then(|file_name| file_name.to_str());
+        !self
+            .skip
+            .iter()
+            .any(|skipped| *skipped == shown_path || Some(skipped.as_str()) == file_name)
+    }
+}
+
+/// What minifying the tabs saved
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct MinifyReport {
+    /// The indices of the minified tabs
+    pub tab_indices: Vec<usize>,
+    pub chars_saved: usize,
+    pub tokens_saved: usize,
+    pub renamed_locals: usize,
+}
+
+impl fmt::Display for MinifyReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let MinifyReport {
+            tab_indices,
+            chars_saved,
+            tokens_saved,
+            renamed_locals,
+        } = self;
+        f.write_fmt(format_args!(
+            "Minified {} tabs, saving {chars_saved} chars and {tokens_saved} tokens, renaming {renamed_locals} locals",
+            tab_indices.len()
+        ))
+    }
+}
+
+fn is_word(token: &Token<'_>) -> bool {
+    matches!(
+        token.kind,
+        TokenKind::Name | TokenKind::Keyword | TokenKind::Number
+    )
+}
+
+/// Whether the tokens must be separated by a space, so they are read as written
+fn needs_space(previous: &Token<'_>, token: &Token<'_>) -> bool {
+    if is_word(previous) && is_word(token) {
+        return true;
+    }
+    // pico-8 reads `1..x` as a malformed number
+    if previous.kind == TokenKind::Number && token.text.starts_with('.') {
+        return true;
+    }
+    // e.g. `-` `-` would start a comment, and `[` `[[s]]` a long-string
+    let joined = format!("{}{}", previous.text, token.text);
+    match syntax::tokenize(&joined).as_deref() {
+        Ok([first, second, _]) => first.text != previous.text || second.text != token.text,
+        _ => true,
+    }
+}
+
+/// The `index`-th of the short names, `a` to `z`, then `aa`, `ab`, ...
+fn short_name(mut index: usize) -> String {
+    let mut name = vec![NAME_START[index % NAME_START.len()]];
+    index /= NAME_START.len();
+    while index > 0 {
+        index -= 1;
+        name.push(NAME_REST[index % NAME_REST.len()]);
+        index /= NAME_REST.len();
+    }
+    String::from_utf8(name).unwrap_or_default()
+}
+
+/// The new names of the locals declared and only referred to in the minified tabs,
+/// by the token-index of each declaration and reference
+///
+/// Each local gets a name of its own, used nowhere else in the chunk, so no local is
+/// shadowed differently than before. The most referred to get the shortest names
+fn rename_locals(
+    tokens: &[Token<'_>],
+    token_tabs: &[usize],
+    minified_tabs: &BTreeSet<usize>,
+) -> Result<(BTreeMap<usize, String>, usize), (usize, String)> {
+    let locals = syntax::resolve_locals(tokens.to_vec())?;
+    let mut references: Vec<Vec<usize>> = vec![Vec::new(); locals.declarations.len()];
+    for (token_index, declaration) in &locals.references {
+        references[*declaration].push(*token_index);
+    }
+    let mut renamed: Vec<(usize, Vec<usize>)> = locals
+        .declarations
+        .iter()
+        .zip(references)
+        .filter_map(|(declaration, references)| Some(((*declaration)?, references)))
+        .filter(|(declaration, references)| {
+            core::iter::once(declaration)
+                .chain(references)
+                .all(|token_index| minified_tabs.contains(&token_tabs[*token_index]))
+        })
+        .collect();
+    renamed.sort_by_key(|(_, references)| core::cmp::Reverse(references.len()));
+    let used_names: BTreeSet<&str> = tokens
+        .iter()
+        .filter(|token| token.kind == TokenKind::Name)
+        .map(|token| token.text)
+        .collect();
+    let mut short_names = (0..)
+        .map(short_name)
+        .filter(|name| !used_names.contains(name.as_str()))
+        .filter(|name| !KEYWORDS.contains(&name.as_str()));
+    let mut names = BTreeMap::new();
+    for (declaration, references) in &renamed {
+        let name = short_names.next().unwrap_or_default();
+        for token_index in core::iter::once(declaration).chain(references) {
+            names.insert(*token_index, name.clone());
+        }
+    }
+    Ok((names, renamed.len()))
+}
+
+/// Joins the tokens of a tab, keeping a line-break between tokens of different lines
+fn join_tokens<'a>(
+    tokens: impl Iterator<Item = (usize, &'a Token<'a>)>,
+    names: &BTreeMap<usize, String>,
+) -> String {
+    let mut code = String::new();
+    let mut previous: Option<&Token<'_>> = None;
+    for (token_index, token) in tokens {
+        if let Some(previous) = previous {
+            let previous_end_line = previous.line + previous.text.matches('\n').count();
+            if token.line > previous_end_line {
+                code.push('\n');
+            } else if needs_space(previous, token) {
+                code.push(' ');
+            }
+        }
+        match names.get(&token_index) {
+            Some(name) => code.push_str(name),
+            None => code.push_str(token.text),
+        }
+        previous = Some(token);
+    }
+    code
+}
+
+/// Minifies the tabs of the indices, renaming the locals if `rename_locals`, see the
+/// module-documentation
+///
+/// The tabs run as one chunk, so a local is only renamed if it is declared and referred
+/// to in minified tabs only. Tabs with an `#include`, resolved by pico-8 when it runs,
+/// are kept as written
+#[tracing::instrument(level = "debug", skip(code_tabs))]
+pub fn minify_code_tabs(
+    code_tabs: &CodeTabs<'_>,
+    tab_indices: &[usize],
+    rename_locals: bool,
+) -> Result<(CodeTabs<'static>, MinifyReport), SyntaxError> {
+    let minified_tabs: BTreeSet<usize> = tab_indices
+        .iter()
+        .copied()
+        .filter(|tab_index| {
+            code_tabs
+                .get(*tab_index)
+                .and_then(Option::as_ref)
+                .is_some_and(|tab| {
+                    !tab.code_data
+                        .split(|byte| *byte == b'\n')
+                        .any(|line| line.trim_ascii_start().starts_with(b"#include"))
+                })
+        })
+        .collect();
+    let chunk = Chunk::of_code_tabs(code_tabs);
+    let src = String::from_utf8_lossy(&chunk.src);
+    let tokens = syntax::tokenize(&src).map_err(|e| chunk.error(e))?;
+    let token_tabs: Vec<usize> = tokens
+        .iter()
+        .map(|token| chunk.tab_line(token.line).0)
+        .collect();
+    let (names, renamed_locals) = match rename_locals {
+        true => {
+            self::rename_locals(&tokens, &token_tabs, &minified_tabs).map_err(|e| chunk.error(e))?
+        }
+        false => (BTreeMap::new(), 0),
+    };
+
+    let mut report = MinifyReport {
+        renamed_locals,
+        ..MinifyReport::default()
+    };
+    let mut minified: CodeTabs<'static> = Default::default();
+    for (tab_index, tab) in code_tabs.iter_present() {
+        if !minified_tabs.contains(&tab_index) {
+            minified.insert(tab_index, tab.clone().into_owned());
+            continue;
+        }
+        let mut code = join_tokens(
+            tokens.iter().enumerate().filter(|(token_index, token)| {
+                token.kind != TokenKind::Eof && token_tabs[*token_index] == tab_index
+            }),
+            &names,
+        );
+        if tab.code_data.ends_with(b"\n") {
+            code.push('\n');
+        }
+        report.tab_indices.push(tab_index);
+        report.chars_saved += tab.code_data.len().saturating_sub(code.len());
+        report.tokens_saved +=
+            count_tokens(&tab.code_data).saturating_sub(count_tokens(code.as_bytes()));
+        minified.insert(
+            tab_index,
+            Tab {
+                line_number: tab.line_number,
+                code_data: code.into_bytes().into(),
+            },
+        );
+    }
+    Ok((minified, report))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn minifies_and_renames_locals() {
+        let mut code_tabs: CodeTabs<'_> = Default::default();
+        for (tab_index, code) in [
+            (
+                0,
+                "-- main\nlocal speed = 2 -- px\n\nfunction move(player)\n  if (btn(0)) player.x -= speed\n  local x = x - - 1\n  return x .. 1 ..[[\n]]\nend\n",
+            ),
+            (1, "local shared = 1\nprint(speed)\n"),
+        ] {
+            code_tabs[tab_index] = Some(Tab {
+                line_number: 0,
+                code_data: code.as_bytes().into(),
+            });
+        }
+        let (minified, report) = minify_code_tabs(&code_tabs, &[0], true).unwrap();
+        let code = |tab_index: usize| {
+            String::from_utf8(minified[tab_index].as_ref().unwrap().code_data.to_vec()).unwrap()
+        };
+        // `speed` is read in the kept tab, `x` reads the global `x`
+        assert_eq!(
+            code(0),
+            "local speed=2\nfunction move(a)\nif(btn(0))a.x-=speed\nlocal b=x- -1\nreturn b..1 ..[[\n]]\nend\n"
+        );
+        assert_eq!(code(1), "local shared = 1\nprint(speed)\n");
+        assert_eq!(report.tab_indices, [0]);
+        assert_eq!(report.renamed_locals, 2);
+        assert_eq!(
+            syntax::validate(&minified[0].as_ref().unwrap().code_data),
+            Ok(())
+        );
+    }
+}
@@ -0,0 +1,260 @@
+//! Finding source-files and tabs which no longer take part in the build
+//!
+//! Every lua-file directly in the source-directory is compiled into a tab, so the
+//! orphans are the files past the last tab, the lua-files of sub-directories which
+//! no tab includes, the tabs of the cart left over from sources since removed, and
+//! the temporary carts left by interrupted writes
+
+use core::fmt;
+
+use std::fs;
+use std::io;
+use std::path;
+
+use pico_8_cart_builder::CartBuilder;
+use pico_8_cart_model::{CartData, P8_MAX_CODE_EDITOR_TAB_COUNT};
+
+use crate::cart_write;
+use crate::paths;
+use crate::provenance::{BuildManifest, Origin, Target};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Orphan {
+    /// A source-file past the last tab, which is dropped on each build
+    OverTabLimit {
+        path: path::PathBuf,
+        tab_index: usize,
+    },
+    /// A lua-file of a sub-directory which no tab includes
+    Unreferenced { path: path::PathBuf },
+    /// A tab of the cart which no source-file compiles into anymore, with the
+    /// source-file it was compiled from as recorded in the manifest
+    StaleTab {
+        tab_index: usize,
+        source: Option<path::PathBuf>,
+    },
+    /// A temporary cart left by an interrupted write
+    TempCart { path: path::PathBuf },
+}
+
+impl Orphan {
+    /// The file removed when pruning the orphan, `None` for the tabs which are dropped
+    /// by rebuilding instead
+    pub fn prunable_path(&self) -> Option<&path::Path> {
+        match self {
+            Orphan::OverTabLimit { path, .. }
+            | Orphan::Unreferenced { path }
+            | Orphan::TempCart { path } => Some(path),
+            Orphan::StaleTab { .. } => None,
+        }
+    }
+}
+
+impl fmt::Display for Orphan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Orphan::OverTabLimit { path, tab_index } => f.write_fmt(format_args!(
+                "{} would be tab {tab_index}, past the last tab {}",
+                paths::display(path),
+                P8_MAX_CODE_EDITOR_TAB_COUNT - 1
+            )),
+            Orphan::Unreferenced { path } => f.write_fmt(format_args!(
+                "{} is neither a tab nor included by one",
+                paths::display(path)
+            )),
+            Orphan::StaleTab {
+                tab_index,
+                source: Some(source),
+            } => f.write_fmt(format_args!(
+                "tab {tab_index} was compiled from {}, which is gone",
+                paths::display(source)
+            )),
+            Orphan::StaleTab {
+                tab_index,
+                source: None,
+            } => f.write_fmt(format_args!(
+                "tab {tab_index} has no source-file, and is dropped on the next build"
+            )),
+            Orphan::TempCart { path } => f.write_fmt(format_args!(
+                "{} was left by an interrupted write",
+                paths::display(path)
+            )),
+        }
+    }
+}
+
+/// The lua-files of the directory and its sub-directories
+fn lua_files_below(directory: &path::Path) -> io::Result<Vec<path::PathBuf>> {
+    let mut lua_files = Vec::new();
+    for dir_entry in fs::read_dir(directory)?.filter_map(Result::ok) {
+        let path = dir_entry.path();
+        if path.is_dir() {
+            lua_files.extend(lua_files_below(&path)?);
+        } else if path.extension().is_some_and(|extension| extension == "lua") {
+            lua_files.push(path);
+        }
+    }
+    Ok(lua_files)
+}
+
+/// The lua-files of the sub-directories of the directory, which are not compiled into tabs
+fn nested_lua_files(directory: &path::Path) -> io::Result<Vec<path::PathBuf>> {
+    let mut lua_files = Vec::new();
+    for dir_entry in fs::read_dir(directory)?.filter_map(Result::ok) {
+        let path = dir_entry.path();
+        if path.is_dir() {
+            lua_files.extend(lua_files_below(&path)?);
+        }
+    }
+    Ok(lua_files)
+}
+
+/// Finds the orphans of the project, see the [module-documentation](self)
+///
+/// The builder is that of the project, and the cart and its manifest are those on disk,
+/// `None` if not built yet
+#[tracing::instrument(level = "debug", skip(cart, manifest))]
+pub fn find_orphans(
+    cart_builder: &CartBuilder,
+    cart_path: &path::Path,
+    first_compiled_tab: usize,
+    cart: Option<&CartData<'_>>,
+    manifest: Option<&BuildManifest>,
+) -> io::Result<Vec<Orphan>> {
+    let src_dir = cart_builder.src_dir();
+    let include_resolver = cart_builder.include_resolver();
+    let exclude = cart_builder.exclude();
+    let lua_files = match manifest {
+        Some(manifest) => cart_builder.clone().with_tab_order(
+            cart_builder
+                .tab_order()
+                .clone()
+                .with_manifest_order(manifest.tab_file_names()),
+        ),
+        None => cart_builder.clone(),
+    }
+    .lua_files()?;
+    let mut orphans: Vec<Orphan> = lua_files
+        .iter()
+        .enumerate()
+        .map(|(index, dir_entry)| (first_compiled_tab + index, dir_entry.path()))
+        .filter(|(tab_index, _)| *tab_index >= P8_MAX_CODE_EDITOR_TAB_COUNT)
+        .map(|(tab_index, path)| Orphan::OverTabLimit { path, tab_index })
+        .collect();
+
+    let included: Vec<path::PathBuf> = lua_files
+        .iter()
+        .flat_map(|dir_entry| {
+            let path = dir_entry.path();
+            let src = fs::read(&path).unwrap_or_default();
+            include_resolver.included_paths(&path, &src)
+        })
+        .collect();
+    let mut unreferenced: Vec<path::PathBuf> = nested_lua_files(src_dir)?
+        .into_iter()
+        .filter(|path| !exclude.is_excluded(path.strip_prefix(src_dir).unwrap_or(path)))
+        .filter(|path| {
+            let canonical_path = fs::canonicalize(path).unwrap_or_else(|_| path.clone());
+            !included.contains(&canonical_path)
+        })
+        .collect();
+    // Directory iteration-order is not stable
+    unreferenced.sort();
+    orphans.extend(
+        unreferenced
+            .into_iter()
+            .map(|path| Orphan::Unreferenced { path }),
+    );
+
+    if let Some(cart) = cart {
+        let compiled_tabs = first_compiled_tab..first_compiled_tab + lua_files.len();
+        let source_of = |tab_index| {
+            manifest
+                .and_then(|manifest| manifest.get(Target::Tab(tab_index)))
+                .and_then(|entry| match &entry.origin {
+                    Origin::SourceFile(source) => Some(source.clone()),
+                    Origin::Cart(_) => None,
+                })
+        };
+        // The embedded sprites are generated on each build
+        let embedded_sprites_tab = cart.embedded_sprites_tab();
+        for (tab_index, _) in cart
+            .code_tabs()
+            .iter_present()
+            .filter(|(tab_index, _)| Some(*tab_index) != embedded_sprites_tab)
+        {
+            let source = source_of(tab_index);
+            let is_stale = if compiled_tabs.contains(&tab_index) {
+                source
+                    .as_ref()
+                    .is_some_and(|source| !src_dir.join(source).exists())
+            } else {
+                tab_index >= first_compiled_tab
+            };
+            if is_stale {
+                orphans.push(Orphan::StaleTab { tab_index, source });
+            }
+        }
+    }
+
+    let temp_path = cart_write::temp_path(cart_path);
+    if temp_path.exists() {
+        orphans.push(Orphan::TempCart { path: temp_path });
+    }
+    Ok(orphans)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_unreferenced_and_stale() {
+        let src_dir =
+            std::env::temp_dir().join(format!("pico-build-orphans-{}", std::process::id()));
+        fs::create_dir_all(src_dir.join("lib")).unwrap();
+        fs::write(src_dir.join("main.lua"), "#include lib/vec.lua\n").unwrap();
+        fs::write(src_dir.join("lib/vec.lua"), "v={}\n").unwrap();
+        fs::write(src_dir.join("lib/old.lua"), "o={}\n").unwrap();
+        let cart_path = src_dir.join("game.p8");
+        fs::write(cart_write::temp_path(&cart_path), "").unwrap();
+
+        let mut cart = CartData::default();
+        let mut code_tabs: pico_8_cart_model::CodeTabs<'static> = Default::default();
+        for tab_index in [0, 1] {
+            code_tabs[tab_index] = Some(pico_8_cart_model::Tab {
+                line_number: 0,
+                code_data: b"x=1".as_slice().into(),
+            });
+        }
+        cart.set_code_data(code_tabs);
+        let mut manifest = BuildManifest::default();
+        manifest.record_tab(1, "enemies.lua", &[], b"x=1");
+
+        let orphans = find_orphans(
+            &CartBuilder::new(&src_dir),
+            &cart_path,
+            0,
+            Some(&cart),
+            Some(&manifest),
+        )
+        .unwrap();
+        assert_eq!(
+            orphans,
+            [
+                Orphan::Unreferenced {
+                    path: src_dir.join("lib/old.lua")
+                },
+                Orphan::StaleTab {
+                    tab_index: 1,
+                    source: Some("enemies.lua".into())
+                },
+                Orphan::TempCart {
+                    path: cart_write::temp_path(&cart_path)
+                },
+            ]
+        );
+
+        fs::remove_dir_all(&src_dir).unwrap();
+    }
+}
@@ -0,0 +1,111 @@
+//! Showing paths the same way on every platform, and reading them from configuration
+//!
+//! Paths reach the user from the arguments, the config-file and directory-listings, so
+//! they mix absolute and relative forms, and `\` and `/` on windows. Shown through
+//! [`display`] or [`relative_to`], they are relative to the project where possible and
+//! always separated by `/`
+
+use core::fmt;
+
+use std::path;
+
+/// Shows the path with `/` separators, relative to the base if inside it
+#[derive(Clone, Copy, Debug)]
+pub struct DisplayPath<'a> {
+    path: &'a path::Path,
+    base: Option<&'a path::Path>,
+}
+
+/// Shows the path with `/` separators
+pub fn display<P: AsRef<path::Path> + ?Sized>(path: &P) -> DisplayPath<'_> {
+    DisplayPath {
+        path: path.as_ref(),
+        base: None,
+    }
+}
+
+/// Shows the path relative to the base, e.g. the project-directory, if inside it
+pub fn relative_to<'a, P: AsRef<path::Path> + ?Sized, B: AsRef<path::Path> + ?Sized>(
+    path: &'a P,
+    base: &'a B,
+) -> DisplayPath<'a> {
+    DisplayPath {
+        path: path.as_ref(),
+        base: Some(base.as_ref()),
+    }
+}
+
+impl DisplayPath<'_> {
+    /// The path relative to the base, comparing absolute forms if the forms differ
+    fn relative(&self) -> path::PathBuf {
+        let Some(base) = self.base else {
+            return self.path.to_path_buf();
+        };
+        let base = base.strip_prefix(".").unwrap_or(base);
+        if let Ok(relative) = self.path.strip_prefix(base) {
+            return relative.to_path_buf();
+        }
+        match (path::absolute(self.path), path::absolute(base)) {
+            (Ok(path), Ok(base)) => path
+                .strip_prefix(&base)
+                .map_or_else(|_| self.path.to_path_buf(), path::Path::to_path_buf),
+            _ => self.path.to_path_buf(),
+        }
+    }
+}
+
+impl fmt::Display for DisplayPath<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let relative = self.relative();
+        let mut separate = false;
+        for component in relative.components() {
+            match component {
+                path::Component::Prefix(prefix) => {
+                    f.write_str(&prefix.as_os_str().to_string_lossy())?;
+                }
+                path::Component::RootDir => f.write_str("/")?,
+                path::Component::CurDir => continue,
+                path::Component::ParentDir | path::Component::Normal(_) => {
+                    if separate {
+                        f.write_str("/")?;
+                    }
+                    f.write_str(&component.as_os_str().to_string_lossy())?;
+                    separate = true;
+                }
+            }
+        }
+        if relative.components().all(|c| c == path::Component::CurDir) {
+            f.write_str(".")?;
+        }
+        Ok(())
+    }
+}
+
+/// Reads a path from the config-file, accepting both `\` and `/` as separators
+/// on every platform
+pub fn from_config(value: &str) -> path::PathBuf {
+    path::PathBuf::from(value.replace(['/', '\\'], path::MAIN_SEPARATOR_STR))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn displays_relative_with_slashes() {
+        let project = path::Path::new("games/jam");
+        assert_eq!(
+            relative_to(&project.join("src").join("main.lua"), project).to_string(),
+            "src/main.lua"
+        );
+        assert_eq!(
+            relative_to("elsewhere/x.p8", project).to_string(),
+            "elsewhere/x.p8"
+        );
+        assert_eq!(relative_to(project, project).to_string(), ".");
+        assert_eq!(
+            display(&from_config(r"src\lib/vec.lua")).to_string(),
+            "src/lib/vec.lua"
+        );
+    }
+}
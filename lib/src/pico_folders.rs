@@ -0,0 +1,141 @@
+//! The folders of the PICO-8 installation, as named by its `config.txt`
+//!
+//! PICO-8 writes the `config.txt` into its configuration-directory on first launch:
+//! `%APPDATA%/pico-8` on windows, `~/Library/Application Support/pico-8` on macos, and
+//! `~/.lexaloffle/pico-8` elsewhere. Each folder can be overridden, and falls back to the
+//! default of PICO-8 when the `config.txt` is missing or does not name it
+
+use std::env;
+use std::fs;
+use std::io;
+use std::path;
+
+use crate::paths;
+
+pub const CONFIG_FILE_NAME: &str = "config.txt";
+
+/// The configuration-directory of PICO-8, `None` without a home-directory
+pub fn config_dir() -> Option<path::PathBuf> {
+    if cfg!(windows) {
+        env::var_os("APPDATA").map(|app_data| path::PathBuf::from(app_data).join("pico-8"))
+    } else if cfg!(target_os = "macos") {
+        env::var_os("HOME")
+            .map(|home| path::PathBuf::from(home).join("Library/Application Support/pico-8"))
+    } else {
+        env::var_os("HOME").map(|home| path::PathBuf::from(home).join(".lexaloffle/pico-8"))
+    }
+}
+
+/// The folders PICO-8 uses
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct PicoFolders {
+    /// Where `load` and `save` are relative to, the `carts` folder by default
+    pub root_path: Option<path::PathBuf>,
+    /// Where screenshots, gifs and captured labels are saved, the desktop by default
+    pub desktop_path: Option<path::PathBuf>,
+    /// Where carts persist their data with `cartdata`
+    pub cdata_path: Option<path::PathBuf>,
+}
+
+impl PicoFolders {
+    /// Reads the folders named in the text of a `config.txt`
+    ///
+    /// Each setting is a line of its name and value, with `//` starting a comment-line
+    pub fn parse(text: &str) -> PicoFolders {
+        let mut folders = PicoFolders::default();
+        for line in text.lines().map(str::trim) {
+            if line.starts_with("//") {
+                continue;
+            }
+            let Some((name, value)) = line.split_once(char::is_whitespace) else {
+                continue;
+            };
+            let value = value.trim();
+            if value.is_empty() {
+                continue;
+            }
+            let folder = match name {
+                "root_path" => &mut folders.root_path,
+                "desktop_path" => &mut folders.desktop_path,
+                "cdata_path" => &mut folders.cdata_path,
+                _ => continue,
+            };
+            *folder = Some(paths::from_config(value));
+        }
+        folders
+    }
+    /// The defaults of PICO-8, for the folders a `config.txt` does not name
+    pub fn defaults() -> PicoFolders {
+        let config_dir = config_dir();
+        PicoFolders {
+            root_path: config_dir
+                .as_ref()
+                .map(|config_dir| config_dir.join("carts")),
+            desktop_path: env::var_os(if cfg!(windows) { "USERPROFILE" } else { "HOME" })
+                .map(|home| path::PathBuf::from(home).join("Desktop")),
+            cdata_path: config_dir.map(|config_dir| config_dir.join("cdata")),
+        }
+    }
+    /// Takes each folder from `self`, falling back to the other folders
+    pub fn or(self, other: PicoFolders) -> PicoFolders {
+        PicoFolders {
+            root_path: self.root_path.or(other.root_path),
+            desktop_path: self.desktop_path.or(other.desktop_path),
+            cdata_path: self.cdata_path.or(other.cdata_path),
+        }
+    }
+    /// Discovers the folders, preferring the overrides over the `config.txt`, at the
+    /// given path or else in the [`config_dir`], over the defaults of PICO-8
+    #[tracing::instrument(level = "debug")]
+    pub fn discover(overrides: PicoFolders, config_txt: Option<&path::Path>) -> PicoFolders {
+        let config_txt = config_txt
+            .map(path::Path::to_path_buf)
+            .or_else(|| config_dir().map(|config_dir| config_dir.join(CONFIG_FILE_NAME)));
+        let configured = match config_txt.as_deref().map(fs::read_to_string) {
+            Some(Ok(text)) => PicoFolders::parse(&text),
+            Some(Err(e)) => {
+                if e.kind() != io::ErrorKind::NotFound {
+                    tracing::warn!(
+                        "Failed to read the PICO-8 {CONFIG_FILE_NAME}, using the default folders: {e}"
+                    );
+                }
+                PicoFolders::default()
+            }
+            None => PicoFolders::default(),
+        };
+        overrides.or(configured).or(PicoFolders::defaults())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn discovers_with_overrides() {
+        let dir = std::env::temp_dir().join(format!("pico-folders-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let config_txt = dir.join(CONFIG_FILE_NAME);
+        fs::write(
+            &config_txt,
+            "// Location of pico-8's root folder\nroot_path /games/carts/\n\
+             // Specify where to save screenshots and .gif recordings\ndesktop_path /shots/\n\
+             window_size 0 0\n",
+        )
+        .unwrap();
+
+        let overrides = PicoFolders {
+            desktop_path: Some("captures".into()),
+            ..PicoFolders::default()
+        };
+        let folders = PicoFolders::discover(overrides, Some(&config_txt));
+        assert_eq!(folders.root_path, Some(paths::from_config("/games/carts/")));
+        assert_eq!(folders.desktop_path, Some("captures".into()));
+        assert_eq!(folders.cdata_path, PicoFolders::defaults().cdata_path);
+
+        let missing = PicoFolders::discover(PicoFolders::default(), Some(&dir.join("missing")));
+        assert_eq!(missing, PicoFolders::defaults());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,29 @@
+//! Compiling source-files into picotron `.p64` carts
+//!
+//! Picotron has no token-limit, so unlike pico-8 carts nothing is counted
+
+use crate::FileData;
+
+pub use picotron_cart_model::{CartFile, P64Cart, P64Error};
+
+/// Injects each loaded lua source-file into the cart-file of the same name,
+/// adding the files the cart does not have yet
+#[tracing::instrument(level = "debug", skip(cart, source_files))]
+pub fn compile_cartridge<'a>(
+    mut cart: P64Cart<'a>,
+    source_files: impl Iterator<Item = FileData<Box<[u8]>>>,
+) -> P64Cart<'a> {
+    for source_file in source_files {
+        let FileData::Loaded { path, data } = source_file else {
+            tracing::warn!("Skipping unloaded source-file {:?}", source_file.as_path());
+            continue;
+        };
+        let Some(file_name) = path.file_name().and_then(|file_name| file_name.to_str()) else {
+            tracing::warn!("Skipping source-file without a utf-8 name {path:?}");
+            continue;
+        };
+        tracing::info!("Injecting {file_name} into the picotron cart");
+        cart.set_lua_file(file_name, &data);
+    }
+    cart
+}
@@ -0,0 +1,280 @@
+//! The build-manifest, recording where each tab and section of a compiled cart came from
+//!
+//! Written next to the cart as `<cart>.manifest`, one tab-separated entry per line
+
+use core::fmt;
+
+use std::path;
+
+use pico_8_cart_model::{CartData, SectionType};
+
+/// Appended to the cart-path for the path of its manifest
+pub const MANIFEST_EXTENSION: &str = "manifest";
+
+/// The 64-bit FNV-1a hash of the data
+///
+/// Unlike the std-hashers it is stable, so hashes can be compared across builds
+pub fn content_hash(data: &[u8]) -> u64 {
+    data.iter().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(0x0000_0100_0000_01b3)
+    })
+}
+
+/// Returns the manifest-path of the cart, e.g. `game.p8.manifest`
+pub fn manifest_path<P: AsRef<path::Path> + ?Sized>(cart_path: &P) -> path::PathBuf {
+    let mut manifest_path = cart_path.as_ref().as_os_str().to_owned();
+    manifest_path.push(".");
+    manifest_path.push(MANIFEST_EXTENSION);
+    manifest_path.into()
+}
+
+/// The part of the cart an entry describes
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Target {
+    Tab(usize),
+    Section(SectionType),
+}
+
+impl fmt::Display for Target {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Target::Tab(tab_index) => f.write_fmt(format_args!("tab {tab_index}")),
+            Target::Section(section) => f.write_str(<&'static str>::from(section)),
+        }
+    }
+}
+
+/// How the data entered the cart
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Origin {
+    /// Compiled from a lua source-file
+    SourceFile(path::PathBuf),
+    /// Kept from the cart which was compiled into
+    Cart(path::PathBuf),
+}
+
+impl Origin {
+    const fn importer(&self) -> &'static str {
+        match self {
+            Origin::SourceFile(_) => "source-file",
+            Origin::Cart(_) => "cart",
+        }
+    }
+    fn path(&self) -> &path::Path {
+        match self {
+            Origin::SourceFile(path) | Origin::Cart(path) => path,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Entry {
+    pub target: Target,
+    pub origin: Origin,
+    /// The transforms which changed the data, in the order they were applied
+    pub transforms: Vec<String>,
+    /// The [`content_hash`] of the data as written to the cart
+    pub content_hash: u64,
+}
+
+impl fmt::Display for Entry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Entry {
+            target,
+            origin,
+            transforms,
+            content_hash,
+        } = self;
+        f.write_fmt(format_args!(
+            "{target}: {} ({})",
+            origin.path().display(),
+            origin.importer()
+        ))?;
+        if !transforms.is_empty() {
+            f.write_fmt(format_args!(" [{}]", transforms.join(", ")))?;
+        }
+        f.write_fmt(format_args!(" {content_hash:016x}"))
+    }
+}
+
+/// A manifest which could not be parsed
+#[derive(Debug, PartialEq, Eq)]
+pub enum ManifestError {
+    InvalidLine { line_number: usize },
+}
+
+impl fmt::Display for ManifestError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let description = "Manifest error";
+        let reason = match self {
+            ManifestError::InvalidLine { line_number } => {
+                format!("line {line_number} is not a valid entry")
+            }
+        };
+        f.write_fmt(format_args!("{description}: {reason}"))
+    }
+}
+
+impl core::error::Error for ManifestError {}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct BuildManifest {
+    pub entries: Vec<Entry>,
+}
+
+impl BuildManifest {
+    pub fn record_tab<P: Into<path::PathBuf>>(
+        &mut self,
+        tab_index: usize,
+        source_path: P,
+        transforms: &[&str],
+        code_data: &[u8],
+    ) {
+        self.entries.push(Entry {
+            target: Target::Tab(tab_index),
+            origin: Origin::SourceFile(source_path.into()),
+            transforms: transforms.iter().map(ToString::to_string).collect(),
+            content_hash: content_hash(code_data),
+        });
+    }
+    /// Records a tab as kept from the cart at the path, see [`crate::merge::MergeStrategy`]
+    pub fn record_cart_tab<P: AsRef<path::Path> + ?Sized>(
+        &mut self,
+        tab_index: usize,
+        cart_path: &P,
+        code_data: &[u8],
+    ) {
+        self.entries.push(Entry {
+            target: Target::Tab(tab_index),
+            origin: Origin::Cart(cart_path.as_ref().to_path_buf()),
+            transforms: Vec::new(),
+            content_hash: content_hash(code_data),
+        });
+    }
+    /// Records the asset-sections of the cart as kept from the cart at the path
+    pub fn record_cart_sections<P: AsRef<path::Path> + ?Sized>(
+        &mut self,
+        cart_path: &P,
+        cart: &CartData<'_>,
+    ) {
+        for section in cart.format_spec().asset_sections() {
+            if let Some(section_data) = cart.section_data(section) {
+                self.entries.push(Entry {
+                    target: Target::Section(section),
+                    origin: Origin::Cart(cart_path.as_ref().to_path_buf()),
+                    transforms: Vec::new(),
+                    content_hash: content_hash(section_data),
+                });
+            }
+        }
+    }
+    pub fn get(&self, target: Target) -> Option<&Entry> {
+        self.entries.iter().find(|entry| entry.target == target)
+    }
+    /// The file-names of the source-files compiled into the tabs, in tab-order, for
+    /// [`pico_8_cart_builder::SourceOrder::Manifest`]
+    pub fn tab_file_names(&self) -> Vec<String> {
+        let mut tab_files: Vec<(usize, String)> = self
+            .entries
+            .iter()
+            .filter_map(|entry| match (entry.target, &entry.origin) {
+                (Target::Tab(tab_index), Origin::SourceFile(path)) => path
+                    .file_name()
+                    .map(|file_name| (tab_index, file_name.to_string_lossy().into_owned())),
+                _ => None,
+            })
+            .collect();
+        tab_files.sort();
+        tab_files
+            .into_iter()
+            .map(|(_, file_name)| file_name)
+            .collect()
+    }
+    pub fn to_text(&self) -> String {
+        self.entries
+            .iter()
+            .map(|entry| {
+                let target = match entry.target {
+                    Target::Tab(tab_index) => tab_index.to_string(),
+                    Target::Section(section) => <&'static str>::from(section).to_string(),
+                };
+                let transforms = match entry.transforms.as_slice() {
+                    [] => "-".to_string(),
+                    transforms => transforms.join(","),
+                };
+                format!(
+                    "{target}\t{}\t{}\t{transforms}\t{:016x}\n",
+                    entry.origin.importer(),
+                    entry.origin.path().display(),
+                    entry.content_hash
+                )
+            })
+            .collect()
+    }
+    pub fn parse(text: &str) -> Result<BuildManifest, ManifestError> {
+        let parse_entry = |line: &str| -> Option<Entry> {
+            let [target, importer, path, transforms, content_hash] =
+                line.split('\t').collect::<Vec<_>>().try_into().ok()?;
+            let target = match target.parse() {
+                Ok(tab_index) => Target::Tab(tab_index),
+                Err(_) => Target::Section(
+                    pico_8_cart_model::section::get_line_type(target)
+                        .copied()
+                        .filter(|section| <&'static str>::from(section) == target)?,
+                ),
+            };
+            let origin = match importer {
+                "source-file" => Origin::SourceFile(path.into()),
+                "cart" => Origin::Cart(path.into()),
+                _ => return None,
+            };
+            let transforms = match transforms {
+                "-" => Vec::new(),
+                transforms => transforms.split(',').map(ToString::to_string).collect(),
+            };
+            Some(Entry {
+                target,
+                origin,
+                transforms,
+                content_hash: u64::from_str_radix(content_hash, 16).ok()?,
+            })
+        };
+        text.lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty())
+            .map(|(index, line)| {
+                parse_entry(line).ok_or(ManifestError::InvalidLine {
+                    line_number: index + 1,
+                })
+            })
+            .collect::<Result<_, _>>()
+            .map(|entries| BuildManifest { entries })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        assert_eq!(content_hash(b"a"), 0xaf63_dc4c_8601_ec8c);
+
+        let mut manifest = BuildManifest::default();
+        manifest.record_tab(0, "main.lua", &["strip-debug-calls"], b"x=1\n");
+        manifest.record_cart_sections("game.p8", &CartData::default());
+        let parsed = BuildManifest::parse(&manifest.to_text()).unwrap();
+        assert_eq!(parsed, manifest);
+        assert_eq!(
+            parsed
+                .get(Target::Section(SectionType::Gfx))
+                .unwrap()
+                .origin,
+            Origin::Cart("game.p8".into())
+        );
+        assert_eq!(
+            BuildManifest::parse("0\tsource-file\tmain.lua\n"),
+            Err(ManifestError::InvalidLine { line_number: 1 })
+        );
+    }
+}
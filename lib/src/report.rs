@@ -0,0 +1,354 @@
+//! The report written by `analyze --out`, rendering the [`CartAnalysis`] of a cart as
+//! markdown or html
+//!
+//! Both list the statistics of the tabs and sections as tables, and the usage of each
+//! limit as a gauge. The html embeds the label and the sprite-sheet as png-images, so
+//! the report is a single file
+
+use core::fmt::{self, Write};
+
+use std::path;
+
+use pico_8_cart_model::label::LabelPngError;
+use pico_8_cart_model::{CartData, GfxPngError, HexError, RomError};
+
+use crate::analysis::{CartAnalysis, TabStats};
+
+/// The characters of the gauge-bars of the markdown
+const GAUGE_WIDTH: usize = 20;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ReportFormat {
+    Markdown,
+    Html,
+}
+
+impl ReportFormat {
+    /// The format of the report-file by its extension, `.md` or `.html`
+    pub fn from_path<P: AsRef<path::Path> + ?Sized>(report_path: &P) -> Option<ReportFormat> {
+        let extension = report_path.as_ref().extension()?.to_str()?;
+        match extension.to_ascii_lowercase().as_str() {
+            "md" | "markdown" => Some(ReportFormat::Markdown),
+            "html" | "htm" => Some(ReportFormat::Html),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ReportError {
+    Hex(HexError),
+    /// The code could not be compressed to measure its compressed size
+    Rom(RomError),
+    LabelPng(LabelPngError),
+    GfxPng(GfxPngError),
+}
+
+impl From<HexError> for ReportError {
+    fn from(v: HexError) -> Self {
+        Self::Hex(v)
+    }
+}
+
+impl From<RomError> for ReportError {
+    fn from(v: RomError) -> Self {
+        Self::Rom(v)
+    }
+}
+
+impl From<LabelPngError> for ReportError {
+    fn from(v: LabelPngError) -> Self {
+        Self::LabelPng(v)
+    }
+}
+
+impl From<GfxPngError> for ReportError {
+    fn from(v: GfxPngError) -> Self {
+        Self::GfxPng(v)
+    }
+}
+
+impl fmt::Display for ReportError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let description = "Report error";
+        let reason = match self {
+            ReportError::Hex(e) => e.to_string(),
+            ReportError::Rom(e) => e.to_string(),
+            ReportError::LabelPng(e) => e.to_string(),
+            ReportError::GfxPng(e) => e.to_string(),
+        };
+        f.write_fmt(format_args!("{description}: {reason}"))
+    }
+}
+
+impl core::error::Error for ReportError {}
+
+impl ReportError {
+    /// The stable diagnostic-code, see `pico-build-rs explain`
+    pub const fn code(&self) -> &'static str {
+        match self {
+            ReportError::Hex(e) => e.code(),
+            ReportError::Rom(e) => e.code(),
+            ReportError::LabelPng(e) => e.code(),
+            ReportError::GfxPng(e) => e.code(),
+        }
+    }
+}
+
+/// The usage of a limit of the cart
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Gauge {
+    name: &'static str,
+    used: usize,
+    limit: usize,
+}
+
+impl Gauge {
+    fn percentage(&self) -> usize {
+        (self.used * 100)
+            .checked_div(self.limit)
+            .unwrap_or_default()
+    }
+    /// The bar of the markdown, e.g. `█████░░░░░░░░░░░░░░░`
+    fn bar(&self) -> String {
+        let filled = (self.used * GAUGE_WIDTH)
+            .checked_div(self.limit)
+            .unwrap_or_default()
+            .min(GAUGE_WIDTH);
+        "█".repeat(filled) + &"░".repeat(GAUGE_WIDTH - filled)
+    }
+}
+
+fn gauges(cart: &CartData<'_>, analysis: &CartAnalysis) -> Result<Vec<Gauge>, ReportError> {
+    let format_spec = cart.format_spec();
+    Ok(vec![
+        Gauge {
+            name: "tokens",
+            used: analysis.token_count(),
+            limit: format_spec.token_limit,
+        },
+        Gauge {
+            name: "chars",
+            used: analysis.tabs.iter().map(|tab| tab.chars).sum(),
+            limit: format_spec.char_limit,
+        },
+        Gauge {
+            name: "compressed bytes",
+            used: cart.compressed_code_size()?,
+            limit: format_spec.compressed_limit,
+        },
+        Gauge {
+            name: "sprites",
+            used: analysis.sprites_used,
+            limit: analysis.sprite_count,
+        },
+        Gauge {
+            name: "map tiles",
+            used: analysis.tiles_used,
+            limit: analysis.tile_count,
+        },
+    ])
+}
+
+/// Renders the report of the cart, titled e.g. by the path of the cart
+#[tracing::instrument(level = "debug", skip(cart, analysis))]
+pub fn render(
+    title: &str,
+    cart: &CartData<'_>,
+    analysis: &CartAnalysis,
+    format: ReportFormat,
+) -> Result<String, ReportError> {
+    let gauges = gauges(cart, analysis)?;
+    match format {
+        ReportFormat::Markdown => Ok(render_markdown(title, analysis, &gauges)),
+        ReportFormat::Html => {
+            let mut label_png = Vec::new();
+            if let Some(label_image) = cart.label_image()? {
+                label_image.write_png(&mut label_png)?;
+            }
+            let mut gfx_png = Vec::new();
+            cart.gfx_sheet()?.write_png(&mut gfx_png)?;
+            Ok(render_html(title, analysis, &gauges, &label_png, &gfx_png))
+        }
+    }
+}
+
+/// A cell of a markdown-table, whose `|` would end the cell
+fn markdown_cell(text: &str) -> String {
+    text.replace('|', "\\|")
+}
+
+fn render_markdown(title: &str, analysis: &CartAnalysis, gauges: &[Gauge]) -> String {
+    let mut markdown = String::new();
+    // Writing into a string does not fail
+    let _ = writeln!(markdown, "# {}\n", markdown_cell(title));
+    markdown.push_str("## Limits\n\n| limit | used | of | usage |\n|---|---:|---:|---|\n");
+    for gauge in gauges {
+        let _ = writeln!(
+            markdown,
+            "| {} | {} | {} | `{}` {}% |",
+            gauge.name,
+            gauge.used,
+            gauge.limit,
+            gauge.bar(),
+            gauge.percentage()
+        );
+    }
+    markdown.push_str(
+        "\n## Tabs\n\n| tab | title | lines | tokens | chars |\n|---:|---|---:|---:|---:|\n",
+    );
+    for TabStats {
+        tab_index,
+        title,
+        lines,
+        tokens,
+        chars,
+    } in &analysis.tabs
+    {
+        let title = markdown_cell(title.as_deref().unwrap_or_default());
+        let _ = writeln!(
+            markdown,
+            "| {tab_index} | {title} | {lines} | {tokens} | {chars} |"
+        );
+    }
+    markdown.push_str("\n## Sections\n\n| section | bytes |\n|---|---:|\n");
+    for (section, size) in &analysis.section_sizes {
+        let name = <&'static str>::from(section).trim_matches('_');
+        let _ = writeln!(markdown, "| {name} | {size} |");
+    }
+    markdown
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn render_html(
+    title: &str,
+    analysis: &CartAnalysis,
+    gauges: &[Gauge],
+    label_png: &[u8],
+    gfx_png: &[u8],
+) -> String {
+    let title = escape_html(title);
+    let mut html = String::new();
+    let _ = write!(
+        html,
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{title}</title>\n<style>\n\
+         body {{ font-family: monospace; }}\n\
+         td, th {{ padding: 0 0.5em; text-align: right; }}\n\
+         img {{ image-rendering: pixelated; width: 256px; margin-right: 1em; }}\n\
+         </style>\n</head>\n<body>\n<h1>{title}</h1>\n"
+    );
+    html.push_str("<p>\n");
+    if !label_png.is_empty() {
+        let _ = writeln!(
+            html,
+            "<img alt=\"label\" src=\"data:image/png;base64,{}\">",
+            encode_base64(label_png)
+        );
+    }
+    let _ = writeln!(
+        html,
+        "<img alt=\"sprite-sheet\" src=\"data:image/png;base64,{}\">",
+        encode_base64(gfx_png)
+    );
+    html.push_str("</p>\n<h2>Limits</h2>\n<table>\n<tr><th>limit</th><th>used</th><th>of</th><th>usage</th></tr>\n");
+    for gauge in gauges {
+        let _ = writeln!(
+            html,
+            "<tr><td>{}</td><td>{}</td><td>{}</td><td><meter value=\"{}\" max=\"{}\"></meter> {}%</td></tr>",
+            gauge.name,
+            gauge.used,
+            gauge.limit,
+            gauge.used,
+            gauge.limit,
+            gauge.percentage()
+        );
+    }
+    html.push_str("</table>\n<h2>Tabs</h2>\n<table>\n<tr><th>tab</th><th>title</th><th>lines</th><th>tokens</th><th>chars</th></tr>\n");
+    for TabStats {
+        tab_index,
+        title,
+        lines,
+        tokens,
+        chars,
+    } in &analysis.tabs
+    {
+        let title = escape_html(title.as_deref().unwrap_or_default());
+        let _ = writeln!(
+            html,
+            "<tr><td>{tab_index}</td><td>{title}</td><td>{lines}</td><td>{tokens}</td><td>{chars}</td></tr>"
+        );
+    }
+    html.push_str(
+        "</table>\n<h2>Sections</h2>\n<table>\n<tr><th>section</th><th>bytes</th></tr>\n",
+    );
+    for (section, size) in &analysis.section_sizes {
+        let name = <&'static str>::from(section).trim_matches('_');
+        let _ = writeln!(html, "<tr><td>{name}</td><td>{size}</td></tr>");
+    }
+    html.push_str("</table>\n</body>\n</html>\n");
+    html
+}
+
+/// Encodes the data as the base64 of the `data:`-urls of the embedded images
+fn encode_base64(data: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut encoded = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let bits = chunk.iter().enumerate().fold(0u32, |bits, (index, byte)| {
+            bits | u32::from(*byte) << (16 - 8 * index)
+        });
+        for index in 0..4 {
+            match index <= chunk.len() {
+                true => encoded.push(ALPHABET[(bits >> (18 - 6 * index) & 0x3f) as usize] as char),
+                false => encoded.push('='),
+            }
+        }
+    }
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renders_markdown_and_html() {
+        assert_eq!(encode_base64(b"Man"), "TWFu");
+        assert_eq!(encode_base64(b"Ma"), "TWE=");
+        assert_eq!(encode_base64(b"M"), "TQ==");
+        assert_eq!(
+            ReportFormat::from_path("out/report.HTML"),
+            Some(ReportFormat::Html)
+        );
+        assert_eq!(ReportFormat::from_path("report.txt"), None);
+
+        let cart_text = format!(
+            "pico-8 cartridge // http://www.pico-8.com\nversion 43\n__lua__\n-- main | loop\nx=1\n-->8\ny=2\n__gfx__\n00700000{}\n__label__\n{}",
+            "0".repeat(120),
+            format!("{}\n", "0".repeat(128)).repeat(128)
+        );
+        let cart = CartData::from_cart_source(cart_text.as_bytes()).unwrap();
+        let analysis = crate::analysis::analyze(&cart).unwrap();
+        let markdown = render("game.p8", &cart, &analysis, ReportFormat::Markdown).unwrap();
+        assert!(markdown.starts_with("# game.p8\n\n## Limits\n"));
+        assert!(markdown.contains("| sprites | 1 | 128 | `░░░░░░░░░░░░░░░░░░░░` 0% |\n"));
+        assert!(markdown.contains("| 0 | main \\| loop | 2 | "));
+        assert!(markdown.contains("| gfx | 129 |\n"));
+
+        let html = render("<game>", &cart, &analysis, ReportFormat::Html).unwrap();
+        assert!(html.contains("<h1>&lt;game&gt;</h1>"));
+        // The signature of a png, as the base64 of a data-url
+        assert_eq!(
+            html.matches("src=\"data:image/png;base64,iVBORw0KGgo")
+                .count(),
+            2
+        );
+        assert!(html.contains("<meter value=\"1\" max=\"128\"></meter> 0%"));
+    }
+}
@@ -0,0 +1,486 @@
+//! The source-map of a compiled cart, tracing the lines of its tabs back to the lua-files
+//!
+//! pico-8 reports runtime-errors with the tab and line of the cart, e.g. `line 12 (tab
+//! 2)`, which differ from the source-file once it has `#include`s. Written next to the
+//! cart as `<cart>.map.json`:
+//!
+//! ```text
+//! {"version": 1, "files": ["main.lua", "lib/vec.lua"],
+//!  "tabs": [{"tab": 0, "lines": [[1, 1, 1, 4], [5, 0, 2, 30]]}]}
+//! ```
+//!
+//! Each entry of `lines` is a range of the tab: its first line, the index of the file,
+//! the first line in the file, and the number of lines. Tabs kept from the cart by the
+//! merge-strategy, tabs whose lines were moved by `merge-by-marker`, and minified tabs
+//! are not mapped
+
+use core::fmt;
+
+use alloc::collections::BTreeMap;
+
+use std::fs;
+use std::io;
+use std::path;
+
+use crate::paths;
+
+/// Appended to the cart-path for the path of its source-map
+pub const SOURCE_MAP_EXTENSION: &str = "map.json";
+
+/// The version of the format written by [`SourceMap::to_json`]
+const FORMAT_VERSION: u64 = 1;
+
+/// Returns the source-map-path of the cart, e.g. `game.p8.map.json`
+pub fn source_map_path<P: AsRef<path::Path> + ?Sized>(cart_path: &P) -> path::PathBuf {
+    let mut source_map_path = cart_path.as_ref().as_os_str().to_owned();
+    source_map_path.push(".");
+    source_map_path.push(SOURCE_MAP_EXTENSION);
+    source_map_path.into()
+}
+
+#[derive(Debug)]
+pub enum SourceMapError {
+    Io(io::Error),
+    /// The text is not valid JSON
+    InvalidJson {
+        offset: usize,
+    },
+    /// The JSON is not a source-map of a supported version
+    InvalidFormat(&'static str),
+}
+
+impl From<io::Error> for SourceMapError {
+    fn from(v: io::Error) -> Self {
+        Self::Io(v)
+    }
+}
+
+impl fmt::Display for SourceMapError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let description = "Source-map error";
+        let reason = match self {
+            SourceMapError::Io(e) => e.to_string(),
+            SourceMapError::InvalidJson { offset } => format!("invalid JSON at byte {offset}"),
+            SourceMapError::InvalidFormat(reason) => reason.to_string(),
+        };
+        f.write_fmt(format_args!("{description}: {reason}"))
+    }
+}
+
+impl core::error::Error for SourceMapError {}
+
+impl SourceMapError {
+    /// The stable diagnostic-code, see `pico-build-rs explain`
+    pub const fn code(&self) -> &'static str {
+        "E021"
+    }
+}
+
+/// Consecutive lines of a tab, coming from consecutive lines of a file
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct LineRange {
+    /// The first line of the tab, from 1
+    tab_line: usize,
+    /// The index into [`SourceMap::files`]
+    file: usize,
+    /// The first line of the file, from 1
+    file_line: usize,
+    count: usize,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SourceMap {
+    files: Vec<path::PathBuf>,
+    /// The ranges of each mapped tab, ordered by their first line
+    tabs: BTreeMap<usize, Vec<LineRange>>,
+}
+
+impl SourceMap {
+    /// Records the file and line each line of the tab came from, as returned by
+    /// [`pico_8_cart_builder::IncludeResolver::resolve_with_origins`]
+    ///
+    /// The first origin is of the first line of the tab, or of the second, after the
+    /// `-- name` title added to a source-file not starting with a comment. The paths are
+    /// recorded relative to the base, the directory of the cart, or absolute if outside it
+    pub fn record_tab<B: AsRef<path::Path> + ?Sized>(
+        &mut self,
+        tab_index: usize,
+        first_tab_line: usize,
+        origins: &[(path::PathBuf, usize)],
+        base: &B,
+    ) {
+        let mut ranges: Vec<LineRange> = Vec::new();
+        for (index, (path, file_line)) in origins.iter().enumerate() {
+            let path = relative_path(path, base.as_ref());
+            let file = match self.files.iter().position(|file| *file == path) {
+                Some(file) => file,
+                None => {
+                    self.files.push(path);
+                    self.files.len() - 1
+                }
+            };
+            match ranges.last_mut() {
+                Some(range)
+                    if range.file == file && range.file_line + range.count == *file_line =>
+                {
+                    range.count += 1
+                }
+                _ => ranges.push(LineRange {
+                    tab_line: first_tab_line + index,
+                    file,
+                    file_line: *file_line,
+                    count: 1,
+                }),
+            }
+        }
+        self.tabs.insert(tab_index, ranges);
+    }
+    /// The file, relative to the directory of the cart, and the line of it, from 1, which the line of
+    /// the tab came from
+    ///
+    /// `None` if the tab is not mapped, or has fewer lines
+    pub fn resolve(&self, tab_index: usize, line_number: usize) -> Option<(&path::Path, usize)> {
+        let ranges = self.tabs.get(&tab_index)?;
+        let range = ranges
+            .iter()
+            .take_while(|range| range.tab_line <= line_number)
+            .last()
+            .filter(|range| line_number < range.tab_line + range.count)?;
+        Some((
+            &self.files[range.file],
+            range.file_line + (line_number - range.tab_line),
+        ))
+    }
+    pub fn is_empty(&self) -> bool {
+        self.tabs.is_empty()
+    }
+    pub fn to_json(&self) -> String {
+        let mut json = format!("{{\n  \"version\": {FORMAT_VERSION},\n  \"files\": [");
+        for (index, file) in self.files.iter().enumerate() {
+            if index > 0 {
+                json.push_str(", ");
+            }
+            push_json_string(&mut json, &paths::display(file).to_string());
+        }
+        json.push_str("],\n  \"tabs\": [");
+        for (index, (tab_index, ranges)) in self.tabs.iter().enumerate() {
+            if index > 0 {
+                json.push(',');
+            }
+            json.push_str(&format!("\n    {{\"tab\": {tab_index}, \"lines\": ["));
+            for (index, range) in ranges.iter().enumerate() {
+                if index > 0 {
+                    json.push_str(", ");
+                }
+                json.push_str(&format!(
+                    "[{}, {}, {}, {}]",
+                    range.tab_line, range.file, range.file_line, range.count
+                ));
+            }
+            json.push_str("]}");
+        }
+        json.push_str("\n  ]\n}\n");
+        json
+    }
+    /// Parses the JSON written by [`SourceMap::to_json`]
+    pub fn parse(json: &str) -> Result<SourceMap, SourceMapError> {
+        let mut parser = JsonParser { json, offset: 0 };
+        let value = parser.value()?;
+        parser.skip_whitespace();
+        if parser.offset < json.len() {
+            return Err(SourceMapError::InvalidJson {
+                offset: parser.offset,
+            });
+        }
+        if value.member("version").and_then(Json::as_usize) != Some(FORMAT_VERSION as usize) {
+            return Err(SourceMapError::InvalidFormat("unsupported version"));
+        }
+        let files = value
+            .member("files")
+            .and_then(Json::as_array)
+            .ok_or(SourceMapError::InvalidFormat("missing files"))?
+            .iter()
+            .map(|file| match file {
+                Json::String(file) => Ok(paths::from_config(file)),
+                _ => Err(SourceMapError::InvalidFormat("a file is not a string")),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let mut tabs = BTreeMap::new();
+        for tab in value
+            .member("tabs")
+            .and_then(Json::as_array)
+            .ok_or(SourceMapError::InvalidFormat("missing tabs"))?
+        {
+            let tab_index = tab
+                .member("tab")
+                .and_then(Json::as_usize)
+                .ok_or(SourceMapError::InvalidFormat("a tab has no index"))?;
+            let mut ranges = Vec::new();
+            for range in tab
+                .member("lines")
+                .and_then(Json::as_array)
+                .ok_or(SourceMapError::InvalidFormat("a tab has no lines"))?
+            {
+                let fields: Option<Vec<usize>> = range
+                    .as_array()
+                    .map(|fields| fields.iter().map(Json::as_usize).collect())
+                    .unwrap_or_default();
+                let Some(&[tab_line, file, file_line, count]) = fields.as_deref() else {
+                    return Err(SourceMapError::InvalidFormat(
+                        "a line-range is not 4 numbers",
+                    ));
+                };
+                if file >= files.len() {
+                    return Err(SourceMapError::InvalidFormat(
+                        "a line-range refers to a missing file",
+                    ));
+                }
+                ranges.push(LineRange {
+                    tab_line,
+                    file,
+                    file_line,
+                    count,
+                });
+            }
+            ranges.sort_by_key(|range| range.tab_line);
+            tabs.insert(tab_index, ranges);
+        }
+        Ok(SourceMap { files, tabs })
+    }
+    pub fn read<P: AsRef<path::Path> + ?Sized>(path: &P) -> Result<SourceMap, SourceMapError> {
+        SourceMap::parse(&fs::read_to_string(path)?)
+    }
+}
+
+fn relative_path(path: &path::Path, base: &path::Path) -> path::PathBuf {
+    match (path::absolute(path), path::absolute(base)) {
+        (Ok(path), Ok(base)) => match path.strip_prefix(&base) {
+            Ok(relative) => relative.to_path_buf(),
+            Err(_) => path,
+        },
+        _ => path.to_path_buf(),
+    }
+}
+
+/// Appends the string as a json-string, with quotes
+fn push_json_string(json: &mut String, value: &str) {
+    json.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            c if c.is_control() => json.push_str(&format!("\\u{:04x}", c as u32)),
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+}
+
+/// The JSON-values of a source-map
+#[derive(Debug)]
+enum Json {
+    Null,
+    Bool,
+    Number(f64),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    fn member(&self, name: &str) -> Option<&Json> {
+        match self {
+            Json::Object(members) => members
+                .iter()
+                .find_map(|(key, value)| (key == name).then_some(value)),
+            _ => None,
+        }
+    }
+    fn as_array(&self) -> Option<&[Json]> {
+        match self {
+            Json::Array(values) => Some(values),
+            _ => None,
+        }
+    }
+    fn as_usize(&self) -> Option<usize> {
+        match self {
+            Json::Number(number) if *number >= 0.0 && number.fract() == 0.0 => {
+                Some(*number as usize)
+            }
+            _ => None,
+        }
+    }
+}
+
+struct JsonParser<'a> {
+    json: &'a str,
+    offset: usize,
+}
+
+impl JsonParser<'_> {
+    fn invalid(&self) -> SourceMapError {
+        SourceMapError::InvalidJson {
+            offset: self.offset,
+        }
+    }
+    fn skip_whitespace(&mut self) {
+        let rest = &self.json[self.offset..];
+        self.offset += rest.len() - rest.trim_start().len();
+    }
+    fn peek(&self) -> Option<u8> {
+        self.json.as_bytes().get(self.offset).copied()
+    }
+    fn expect(&mut self, byte: u8) -> Result<(), SourceMapError> {
+        self.skip_whitespace();
+        match self.peek() == Some(byte) {
+            true => {
+                self.offset += 1;
+                Ok(())
+            }
+            false => Err(self.invalid()),
+        }
+    }
+    fn value(&mut self) -> Result<Json, SourceMapError> {
+        self.skip_whitespace();
+        let rest = &self.json[self.offset..];
+        match self.peek().ok_or_else(|| self.invalid())? {
+            b'{' => {
+                self.offset += 1;
+                let mut members = Vec::new();
+                self.skip_whitespace();
+                if self.peek() == Some(b'}') {
+                    self.offset += 1;
+                    return Ok(Json::Object(members));
+                }
+                loop {
+                    self.skip_whitespace();
+                    let key = self.string()?;
+                    self.expect(b':')?;
+                    members.push((key, self.value()?));
+                    self.skip_whitespace();
+                    match self.peek() {
+                        Some(b',') => self.offset += 1,
+                        Some(b'}') => {
+                            self.offset += 1;
+                            return Ok(Json::Object(members));
+                        }
+                        _ => return Err(self.invalid()),
+                    }
+                }
+            }
+            b'[' => {
+                self.offset += 1;
+                let mut values = Vec::new();
+                self.skip_whitespace();
+                if self.peek() == Some(b']') {
+                    self.offset += 1;
+                    return Ok(Json::Array(values));
+                }
+                loop {
+                    values.push(self.value()?);
+                    self.skip_whitespace();
+                    match self.peek() {
+                        Some(b',') => self.offset += 1,
+                        Some(b']') => {
+                            self.offset += 1;
+                            return Ok(Json::Array(values));
+                        }
+                        _ => return Err(self.invalid()),
+                    }
+                }
+            }
+            b'"' => self.string().map(Json::String),
+            _ if rest.starts_with("null") => {
+                self.offset += 4;
+                Ok(Json::Null)
+            }
+            _ if rest.starts_with("true") || rest.starts_with("false") => {
+                self.offset += if rest.starts_with("true") { 4 } else { 5 };
+                Ok(Json::Bool)
+            }
+            _ => {
+                let length = rest
+                    .find(|c: char| !matches!(c, '0'..='9' | '-' | '+' | '.' | 'e' | 'E'))
+                    .unwrap_or(rest.len());
+                let number = rest[..length].parse().map_err(|_| self.invalid())?;
+                self.offset += length;
+                Ok(Json::Number(number))
+            }
+        }
+    }
+    fn string(&mut self) -> Result<String, SourceMapError> {
+        if self.peek() != Some(b'"') {
+            return Err(self.invalid());
+        }
+        self.offset += 1;
+        let mut string = String::new();
+        let mut chars = self.json[self.offset..].char_indices();
+        while let Some((index, c)) = chars.next() {
+            match c {
+                '"' => {
+                    self.offset += index + 1;
+                    return Ok(string);
+                }
+                '\\' => match chars.next().map(|(_, c)| c) {
+                    Some('n') => string.push('\n'),
+                    Some('r') => string.push('\r'),
+                    Some('t') => string.push('\t'),
+                    Some('b') => string.push('\u{8}'),
+                    Some('f') => string.push('\u{c}'),
+                    Some('u') => {
+                        let hex: String = chars.by_ref().take(4).map(|(_, c)| c).collect();
+                        let c = u32::from_str_radix(&hex, 16)
+                            .ok()
+                            .and_then(char::from_u32)
+                            .unwrap_or(char::REPLACEMENT_CHARACTER);
+                        string.push(c);
+                    }
+                    Some(c) => string.push(c),
+                    None => break,
+                },
+                c => string.push(c),
+            }
+        }
+        self.offset = self.json.len();
+        Err(self.invalid())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolves_lines_through_json() {
+        let cart_dir = path::Path::new("games/jam");
+        let (main_path, vec_path) = (cart_dir.join("main.lua"), cart_dir.join("lib/vec.lua"));
+        let mut source_map = SourceMap::default();
+        source_map.record_tab(
+            1,
+            2,
+            &[
+                (vec_path.clone(), 1),
+                (vec_path, 2),
+                (main_path.clone(), 2),
+                (main_path, 3),
+            ],
+            cart_dir,
+        );
+        let source_map = SourceMap::parse(&source_map.to_json()).unwrap();
+        assert_eq!(
+            source_map.resolve(1, 3),
+            Some((path::Path::new("lib").join("vec.lua").as_path(), 2))
+        );
+        assert_eq!(
+            source_map.resolve(1, 5),
+            Some((path::Path::new("main.lua"), 3))
+        );
+        assert_eq!(source_map.resolve(1, 1), None);
+        assert_eq!(source_map.resolve(1, 6), None);
+        assert_eq!(source_map.resolve(0, 1), None);
+        assert!(matches!(
+            SourceMap::parse("{\"version\": 1, \"files\": [}"),
+            Err(SourceMapError::InvalidJson { offset: 25 })
+        ));
+    }
+}
@@ -0,0 +1,389 @@
+//! Extracting the string-literals of the source-files into a catalog, and swapping in
+//! the catalog of a language when building, for localized carts from one source-tree
+//!
+//! A catalog is a text-file of entries keyed by the literal as written in the source,
+//! each followed by its translation, with the files and lines using it as comments:
+//!
+//! ```text
+//! # src/title.lua:4
+//! "press ❎ to start" = "drücke ❎ zum starten"
+//! ```
+//!
+//! Both are written in the double-quoted form of a lua string-literal, so escapes such as
+//! `\n` are kept as is. Literals in long-brackets, e.g. `[[...]]`, are not extracted.
+//! Entries whose translation is the key itself are left untouched when building, so a
+//! freshly extracted catalog changes nothing until it is translated
+
+use core::fmt;
+
+use alloc::borrow::Cow;
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path;
+
+use pico_8_cart_builder::lex::{skip_comment, skip_long_bracket, skip_quoted};
+use pico_8_cart_model::p8scii;
+
+/// The directory of the catalogs, relative to the project-directory, if not configured
+pub const DEFAULT_STRINGS_DIR: &str = "lang";
+
+/// The columns of a line of text printed across the 128 pixels of the screen, with the
+/// 4 pixels of a narrow glyph per column
+pub const LINE_COLUMNS: usize = 32;
+
+#[derive(Debug)]
+pub enum CatalogError {
+    Io(io::Error),
+    /// A line of the catalog is neither a comment nor a `"key" = "text"` entry
+    InvalidEntry {
+        line_number: usize,
+    },
+}
+
+impl From<io::Error> for CatalogError {
+    fn from(v: io::Error) -> Self {
+        Self::Io(v)
+    }
+}
+
+impl fmt::Display for CatalogError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let description = "String-catalog error";
+        let reason = match self {
+            CatalogError::Io(e) => e.to_string(),
+            CatalogError::InvalidEntry { line_number } => {
+                format!("line {line_number} is not a \"key\" = \"text\" entry")
+            }
+        };
+        f.write_fmt(format_args!("{description}: {reason}"))
+    }
+}
+
+impl core::error::Error for CatalogError {}
+
+impl CatalogError {
+    /// The stable diagnostic-code, see `pico-build-rs explain`
+    pub const fn code(&self) -> &'static str {
+        "E019"
+    }
+}
+
+/// A string-literal of a source-file
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct StringLiteral {
+    /// The byte-range of the literal, including its quotes
+    pub start: usize,
+    pub end: usize,
+    /// The literal in double-quoted form, without the quotes
+    pub key: String,
+    /// The line of the source-file it starts on, from 1
+    pub line_number: usize,
+}
+
+/// The quoted string-literals of the source, skipping comments and long-brackets
+pub fn string_literals(src: &[u8]) -> Vec<StringLiteral> {
+    let mut literals = Vec::new();
+    let mut index = 0;
+    while index < src.len() {
+        match src[index] {
+            b'-' if src.get(index + 1) == Some(&b'-') => index = skip_comment(src, index),
+            b'[' => index = skip_long_bracket(src, index).unwrap_or(index + 1),
+            b'"' | b'\'' => {
+                let end = skip_quoted(src, index);
+                // Unterminated literals are left for pico-8 to report
+                if end > index + 1 && src[end - 1] == src[index] {
+                    literals.push(StringLiteral {
+                        start: index,
+                        end,
+                        key: double_quoted(&src[index + 1..end - 1], src[index]),
+                        line_number: src[..index].iter().filter(|byte| **byte == b'\n').count() + 1,
+                    });
+                }
+                index = end;
+            }
+            _ => index += 1,
+        }
+    }
+    literals
+}
+
+/// The body of a literal quoted by `quote`, as the body of a double-quoted one
+fn double_quoted(body: &[u8], quote: u8) -> String {
+    if quote == b'"' {
+        return String::from_utf8_lossy(body).into_owned();
+    }
+    let mut converted = Vec::with_capacity(body.len());
+    let mut index = 0;
+    while index < body.len() {
+        match (body[index], body.get(index + 1)) {
+            (b'\\', Some(b'\'')) => {
+                converted.push(b'\'');
+                index += 2;
+            }
+            (b'\\', Some(escaped)) => {
+                converted.extend([b'\\', *escaped]);
+                index += 2;
+            }
+            (b'"', _) => {
+                converted.extend(b"\\\"");
+                index += 1;
+            }
+            (byte, _) => {
+                converted.push(byte);
+                index += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&converted).into_owned()
+}
+
+/// An entry of a [`Catalog`]
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct CatalogEntry {
+    pub text: String,
+    /// Where the key is used, e.g. `src/title.lua:4`
+    pub locations: Vec<String>,
+}
+
+/// The translations of the string-literals, by the literal as written in the source
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Catalog {
+    pub entries: BTreeMap<String, CatalogEntry>,
+}
+
+impl Catalog {
+    /// Adds the literals of a source-file, each translated as itself
+    pub fn extract(&mut self, file_name: &str, src: &[u8]) {
+        for literal in string_literals(src) {
+            if literal.key.is_empty() {
+                continue;
+            }
+            let entry = self
+                .entries
+                .entry(literal.key.clone())
+                .or_insert_with(|| CatalogEntry {
+                    text: literal.key,
+                    locations: Vec::new(),
+                });
+            entry
+                .locations
+                .push(format!("{file_name}:{}", literal.line_number));
+        }
+    }
+    /// Keeps the translations of the catalog for the keys still extracted, so
+    /// re-extracting does not lose them
+    pub fn with_translations_of(mut self, existing: &Catalog) -> Catalog {
+        for (key, entry) in &mut self.entries {
+            if let Some(existing) = existing.entries.get(key) {
+                entry.text.clone_from(&existing.text);
+            }
+        }
+        self
+    }
+    /// The translation of the key, `None` if it is not translated
+    pub fn translation(&self, key: &str) -> Option<&str> {
+        self.entries
+            .get(key)
+            .map(|entry| entry.text.as_str())
+            .filter(|text| *text != key)
+    }
+    pub fn parse(text: &str) -> Result<Catalog, CatalogError> {
+        let mut catalog = Catalog::default();
+        let mut locations = Vec::new();
+        for (index, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+            if let Some(location) = line.strip_prefix('#') {
+                locations.push(location.trim().to_string());
+                continue;
+            }
+            let invalid = || CatalogError::InvalidEntry {
+                line_number: index + 1,
+            };
+            let (key, rest) = quoted_prefix(line).ok_or_else(invalid)?;
+            let rest = rest.trim_start().strip_prefix('=').ok_or_else(invalid)?;
+            let (text, rest) = quoted_prefix(rest.trim_start()).ok_or_else(invalid)?;
+            if !rest.trim().is_empty() {
+                return Err(invalid());
+            }
+            catalog.entries.insert(
+                key.to_string(),
+                CatalogEntry {
+                    text: text.to_string(),
+                    locations: core::mem::take(&mut locations),
+                },
+            );
+        }
+        Ok(catalog)
+    }
+    pub fn read(catalog_path: &path::Path) -> Result<Catalog, CatalogError> {
+        Catalog::parse(&fs::read_to_string(catalog_path)?)
+    }
+    /// The catalog as written to a file, see [`Catalog::parse`]
+    pub fn to_text(&self) -> String {
+        let mut text = String::new();
+        for (key, entry) in &self.entries {
+            for location in &entry.locations {
+                text.push_str(&format!("# {location}\n"));
+            }
+            text.push_str(&format!("\"{key}\" = \"{}\"\n", entry.text));
+        }
+        text
+    }
+    /// The translations pico-8 cannot print as written, as the key and the reason
+    ///
+    /// A translation is reported for a character without a P8SCII-glyph, or for a line
+    /// wider than the screen, counting the wide glyphs as 2 columns
+    pub fn warnings(&self) -> Vec<(&str, String)> {
+        let mut warnings = Vec::new();
+        for (key, entry) in &self.entries {
+            if let Some(c) = p8scii::first_unsupported(&entry.text) {
+                warnings.push((key.as_str(), format!("{c:?} has no P8SCII-glyph")));
+            }
+            let (columns, key_columns) = (widest_line(&entry.text), widest_line(key));
+            // Untranslated lines are as wide as the game already prints them
+            if columns > LINE_COLUMNS && columns > key_columns {
+                warnings.push((
+                    key.as_str(),
+                    format!(
+                        "a line of {columns} columns is wider than the {LINE_COLUMNS} of the screen"
+                    ),
+                ));
+            }
+        }
+        warnings
+    }
+}
+
+/// The columns of the widest line of the literal, split at its `\\n` escapes
+fn widest_line(body: &str) -> usize {
+    body.split("\\n")
+        .map(|line| {
+            let mut escaped = false;
+            line.chars()
+                .map(|c| match c {
+                    _ if escaped => {
+                        escaped = false;
+                        1
+                    }
+                    '\\' => {
+                        escaped = true;
+                        0
+                    }
+                    c if c.is_ascii() => 1,
+                    _ => 2,
+                })
+                .sum::<usize>()
+        })
+        .max()
+        .unwrap_or_default()
+}
+
+/// The body of the double-quoted string opening the text, and the text following it
+fn quoted_prefix(text: &str) -> Option<(&str, &str)> {
+    let body = text.strip_prefix('"')?;
+    let mut escaped = false;
+    for (index, c) in body.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            '"' => return Some((&body[..index], &body[index + 1..])),
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Replaces the string-literals translated by the catalog with their translation
+///
+/// Returns the localized source and the number of literals replaced
+pub fn localize<'a>(src: &'a [u8], catalog: &Catalog) -> (Cow<'a, [u8]>, usize) {
+    let mut output: Option<Vec<u8>> = None;
+    let mut copied_until = 0;
+    let mut localized = 0;
+    for literal in string_literals(src) {
+        let Some(text) = catalog.translation(&literal.key) else {
+            continue;
+        };
+        let output = output.get_or_insert_with(|| Vec::with_capacity(src.len()));
+        output.extend_from_slice(&src[copied_until..literal.start]);
+        output.push(b'"');
+        output.extend_from_slice(text.as_bytes());
+        output.push(b'"');
+        copied_until = literal.end;
+        localized += 1;
+    }
+    match output {
+        Some(mut output) => {
+            output.extend_from_slice(&src[copied_until..]);
+            (Cow::Owned(output), localized)
+        }
+        None => (Cow::Borrowed(src), 0),
+    }
+}
+
+/// The language built, from the `[strings]` section of the config-file
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Localization {
+    pub strings_dir: path::PathBuf,
+    pub language: String,
+}
+
+impl Localization {
+    /// The catalog of the language, e.g. `lang/de.txt`
+    pub fn catalog_path(&self) -> path::PathBuf {
+        self.strings_dir.join(format!("{}.txt", self.language))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn extracts_and_localizes() {
+        const SRC: &str = r#"-- "not extracted"
+function _draw()
+ print("press ❎ to start")
+ print('it\'s "on"', 0, 8)
+ print([[long]]..s, 0, 16)
+ print("press ❎ to start", 0, 24)
+end"#;
+        let mut catalog = Catalog::default();
+        catalog.extract("src/title.lua", SRC.as_bytes());
+        assert_eq!(catalog.entries.len(), 2);
+        assert_eq!(
+            catalog.entries["press ❎ to start"].locations,
+            ["src/title.lua:3", "src/title.lua:6"]
+        );
+        let text = catalog.to_text();
+        assert!(text.contains("# src/title.lua:4\n\"it's \\\"on\\\"\" = \"it's \\\"on\\\"\"\n"));
+        assert_eq!(Catalog::parse(&text).unwrap(), catalog);
+        assert_eq!(localize(SRC.as_bytes(), &catalog).1, 0);
+
+        let translated = Catalog::parse(
+            "\"press ❎ to start\" = \"drücke ❎ zum starten\"\n\
+             \"it's \\\"on\\\"\" = \"c'est \\\"parti\\\" pour de bon, vraiment\"\n",
+        )
+        .unwrap();
+        let catalog = catalog.with_translations_of(&translated);
+        let (localized, count) = localize(SRC.as_bytes(), &catalog);
+        assert_eq!(count, 3);
+        assert!(
+            core::str::from_utf8(&localized)
+                .unwrap()
+                .contains(" print(\"c'est \\\"parti\\\" pour de bon, vraiment\", 0, 8)\n")
+        );
+        let warnings = catalog.warnings();
+        assert_eq!(warnings.len(), 2);
+        assert_eq!(warnings[1].1, "'ü' has no P8SCII-glyph");
+        assert!(matches!(
+            Catalog::parse("\"key\" = text"),
+            Err(CatalogError::InvalidEntry { line_number: 1 })
+        ));
+    }
+}
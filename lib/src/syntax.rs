@@ -0,0 +1,1019 @@
+//! Validating the syntax of the compiled code before the cart is written, so pico-8
+//! never loads a cart failing with a syntax error
+//!
+//! The code is parsed as the lua 5.2 of pico-8, with its shorthands: the compound
+//! assignments such as `+=` and `..=`, `!=`, the integer-division `\`, the bitwise
+//! operators such as `^^` and `>>>`, the peek-operators `@`, `%` and `$`, `//`-comments,
+//! the `?`-print, and the single-line `if (cond) stmt` and `while (cond) stmt`. The
+//! tabs of a cart run as one chunk, so a block may open in one tab and close in another
+
+use core::fmt;
+
+use pico_8_cart_builder::lex::{KEYWORDS, long_bracket_level};
+use pico_8_cart_model::CartData;
+use pico_8_cart_model::CodeTabs;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SyntaxError {
+    pub tab_index: usize,
+    /// The line of the tab, from 1
+    pub line_number: usize,
+    /// What is wrong, as reported by lua, e.g. `'end' expected near <eof>`
+    pub message: String,
+}
+
+impl fmt::Display for SyntaxError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let description = "Syntax error";
+        let reason = format!(
+            "tab {}, line {}: {}",
+            self.tab_index, self.line_number, self.message
+        );
+        f.write_fmt(format_args!("{description}: {reason}"))
+    }
+}
+
+impl core::error::Error for SyntaxError {}
+
+impl SyntaxError {
+    /// The stable diagnostic-code, see `pico-build-rs explain`
+    pub const fn code(&self) -> &'static str {
+        "E020"
+    }
+}
+
+/// Operators and punctuation, longest first so the longest match is taken
+const SYMBOLS: &[&str] = &[
+    ">>>=", "<<>=", ">><=", "...", "..=", ">>>", "<<>", ">><", "^^=", "<<=", ">>=", "..", "==",
+    "~=", "!=", "<=", ">=", "+=", "-=", "*=", "/=", "\\=", "%=", "^=", "|=", "&=", "<<", ">>",
+    "^^", "::", "+", "-", "*", "/", "\\", "%", "^", "#", "&", "|", "~", "<", ">", "=", "(", ")",
+    "{", "}", "[", "]", ";", ":", ",", ".", "@", "$", "?",
+];
+
+const COMPOUND_ASSIGNMENTS: &[&str] = &[
+    "+=", "-=", "*=", "/=", "\\=", "%=", "^=", "..=", "|=", "&=", "^^=", "<<=", ">>=", ">>>=",
+    "<<>=", ">><=",
+];
+
+const BINARY_OPERATORS: &[&str] = &[
+    "or", "and", "<", ">", "<=", ">=", "~=", "!=", "==", "|", "~", "^^", "&", "<<", ">>", ">>>",
+    "<<>", ">><", "..", "+", "-", "*", "/", "\\", "%", "^",
+];
+
+const UNARY_OPERATORS: &[&str] = &["not", "#", "-", "~", "@", "%", "$"];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum TokenKind {
+    Name,
+    Keyword,
+    Number,
+    String,
+    Symbol,
+    Eof,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct Token<'a> {
+    pub(crate) kind: TokenKind,
+    pub(crate) text: &'a str,
+    /// The line of the chunk, from 1
+    pub(crate) line: usize,
+    /// The byte-offset in the chunk
+    pub(crate) offset: usize,
+}
+
+impl Token<'_> {
+    pub(crate) fn is(&self, text: &str) -> bool {
+        matches!(self.kind, TokenKind::Keyword | TokenKind::Symbol) && self.text == text
+    }
+    /// The token as lua names it in a message
+    fn near(&self) -> String {
+        match self.kind {
+            TokenKind::Eof => "<eof>".to_string(),
+            _ => format!("'{}'", self.text),
+        }
+    }
+}
+
+/// An error of the chunk, by its line
+pub(crate) type ChunkError = (usize, String);
+
+fn is_name_byte(byte: u8) -> bool {
+    byte.is_ascii_alphanumeric() || byte == b'_' || !byte.is_ascii()
+}
+
+pub(crate) fn tokenize(src: &str) -> Result<Vec<Token<'_>>, ChunkError> {
+    let bytes = src.as_bytes();
+    let mut tokens = Vec::new();
+    let mut line = 1;
+    let mut index = 0;
+    let mut line_start = true;
+    while index < bytes.len() {
+        let byte = bytes[index];
+        if byte == b'\n' {
+            line += 1;
+            index += 1;
+            line_start = true;
+            continue;
+        }
+        if byte.is_ascii_whitespace() {
+            index += 1;
+            continue;
+        }
+        // Directives are resolved before the code runs, e.g. `#include` of a kept main-tab
+        if line_start && src[index..].starts_with("#include") {
+            index = src[index..]
+                .find('\n')
+                .map_or(bytes.len(), |end| index + end);
+            continue;
+        }
+        line_start = false;
+        let start = index;
+        let start_line = line;
+        let kind = match byte {
+            b'-' if bytes.get(index + 1) == Some(&b'-') => {
+                let long_bracket = (bytes.get(index + 2) == Some(&b'['))
+                    .then(|| long_bracket_level(bytes, index + 2))
+                    .flatten();
+                index = match long_bracket {
+                    Some(level) => skip_long_bracket(src, index + 2, level, &mut line)
+                        .ok_or((start_line, "unfinished long comment near <eof>".to_string()))?,
+                    None => src[index..]
+                        .find('\n')
+                        .map_or(bytes.len(), |end| index + end),
+                };
+                continue;
+            }
+            b'/' if bytes.get(index + 1) == Some(&b'/') => {
+                index = src[index..]
+                    .find('\n')
+                    .map_or(bytes.len(), |end| index + end);
+                continue;
+            }
+            b'[' if long_bracket_level(bytes, index).is_some() => {
+                let level = long_bracket_level(bytes, index).unwrap_or_default();
+                index = skip_long_bracket(src, index, level, &mut line)
+                    .ok_or((start_line, "unfinished long string near <eof>".to_string()))?;
+                TokenKind::String
+            }
+            b'"' | b'\'' => {
+                index += 1;
+                loop {
+                    match bytes.get(index) {
+                        Some(b'\\') => {
+                            // `\z` skips the whitespace following it, line-breaks included
+                            let skipped = match bytes.get(index + 1) {
+                                Some(b'z') => bytes[index + 2..]
+                                    .iter()
+                                    .take_while(|byte| byte.is_ascii_whitespace())
+                                    .count(),
+                                _ => 0,
+                            };
+                            line += bytes[index + 1..(index + 2 + skipped).min(bytes.len())]
+                                .iter()
+                                .filter(|byte| **byte == b'\n')
+                                .count();
+                            index += 2 + skipped;
+                        }
+                        Some(quote) if *quote == byte => {
+                            index += 1;
+                            break;
+                        }
+                        Some(b'\n') | None => {
+                            return Err((
+                                start_line,
+                                format!(
+                                    "unfinished string near '{}'",
+                                    String::from_utf8_lossy(&bytes[start..index.min(bytes.len())])
+                                ),
+                            ));
+                        }
+                        Some(_) => index += 1,
+                    }
+                }
+                TokenKind::String
+            }
+            byte if byte.is_ascii_digit()
+                || (byte == b'.' && bytes.get(index + 1).is_some_and(u8::is_ascii_digit)) =>
+            {
+                index = number_end(bytes, index).ok_or_else(|| {
+                    let end = bytes[index..]
+                        .iter()
+                        .position(|byte| !is_name_byte(*byte) && *byte != b'.')
+                        .map_or(bytes.len(), |length| index + length);
+                    (
+                        line,
+                        format!("malformed number near '{}'", &src[index..end]),
+                    )
+                })?;
+                TokenKind::Number
+            }
+            byte if is_name_byte(byte) => {
+                index = bytes[index..]
+                    .iter()
+                    .position(|byte| !is_name_byte(*byte))
+                    .map_or(bytes.len(), |length| index + length);
+                if KEYWORDS.contains(&&src[start..index]) {
+                    TokenKind::Keyword
+                } else {
+                    TokenKind::Name
+                }
+            }
+            _ => match SYMBOLS
+                .iter()
+                .find(|symbol| src[index..].starts_with(*symbol))
+            {
+                Some(symbol) => {
+                    index += symbol.len();
+                    TokenKind::Symbol
+                }
+                None => {
+                    let c = src[index..].chars().next().unwrap_or_default();
+                    return Err((line, format!("unexpected symbol near '{c}'")));
+                }
+            },
+        };
+        tokens.push(Token {
+            kind,
+            text: &src[start..index],
+            line: start_line,
+            offset: start,
+        });
+    }
+    tokens.push(Token {
+        kind: TokenKind::Eof,
+        text: "",
+        line,
+        offset: bytes.len(),
+    });
+    Ok(tokens)
+}
+
+/// The index following the long-bracket of the level opening at `start`, counting the
+/// lines it spans, `None` if it is not closed
+fn skip_long_bracket(src: &str, start: usize, level: usize, line: &mut usize) -> Option<usize> {
+    let close = format!("]{}]", "=".repeat(level));
+    let body_start = start + level + 2;
+    let body_length = src[body_start..].find(&close)?;
+    *line += src[body_start..body_start + body_length]
+        .bytes()
+        .filter(|byte| *byte == b'\n')
+        .count();
+    Some(body_start + body_length + close.len())
+}
+
+/// The index following the number-literal at `start`, with hex-, binary- and
+/// fractional-parts, `None` if it runs into a name or a second point
+fn number_end(src: &[u8], start: usize) -> Option<usize> {
+    let radix_prefix = src.get(start..start + 2).map(<[u8]>::to_ascii_lowercase);
+    let (mut index, is_digit, exponent): (usize, fn(&u8) -> bool, Option<u8>) =
+        match radix_prefix.as_deref() {
+            Some(b"0x") => (start + 2, u8::is_ascii_hexdigit, Some(b'p')),
+            Some(b"0b") => (start + 2, |byte| matches!(byte, b'0' | b'1'), None),
+            _ => (start, u8::is_ascii_digit, Some(b'e')),
+        };
+    let mut seen_point = false;
+    while let Some(byte) = src.get(index) {
+        match byte {
+            b'.' if src.get(index + 1) == Some(&b'.') => break,
+            b'.' if !seen_point => seen_point = true,
+            byte if is_digit(byte) => {}
+            // The exponents of lua, e.g. `1e3` and `0x1p4`
+            byte if Some(byte.to_ascii_lowercase()) == exponent => {
+                index += usize::from(matches!(src.get(index + 1), Some(b'+' | b'-')));
+            }
+            _ => break,
+        }
+        index += 1;
+    }
+    match src.get(index) {
+        Some(byte) if is_name_byte(*byte) || *byte == b'.' && src.get(index + 1) != Some(&b'.') => {
+            None
+        }
+        _ => Some(index),
+    }
+}
+
+/// The local variables of a chunk, and the names referring to them
+#[derive(Debug, Default)]
+pub(crate) struct Locals<'a> {
+    /// The declarations visible in each open scope, the innermost last
+    scopes: Vec<Vec<(&'a str, usize)>>,
+    /// The token-index of each declaration, `None` for the implicit `self` of a method
+    pub(crate) declarations: Vec<Option<usize>>,
+    /// The token-index of each name referring to a declaration, and the declaration
+    pub(crate) references: Vec<(usize, usize)>,
+    /// The functions declared by a name at the top-level of the chunk
+    pub(crate) functions: Vec<FunctionStatement>,
+}
+
+/// A `function name()` or `local function name()` statement, by its token-indices
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct FunctionStatement {
+    /// The `function`, or the `local` before it
+    pub(crate) start: usize,
+    pub(crate) name: usize,
+    /// The `end` closing the body
+    pub(crate) end: usize,
+    /// The declaration of a local function
+    pub(crate) declaration: Option<usize>,
+}
+
+struct Parser<'a, 'l> {
+    tokens: Vec<Token<'a>>,
+    position: usize,
+    /// Names a line of the chunk in a message, e.g. `line 3`
+    line_label: &'l dyn Fn(usize) -> String,
+    /// Resolves the names to the locals while parsing, if set
+    locals: Option<Locals<'a>>,
+}
+
+type ParseResult<T = ()> = Result<T, ChunkError>;
+
+impl<'a> Parser<'a, '_> {
+    fn peek(&self) -> Token<'a> {
+        self.tokens[self.position]
+    }
+    fn peek_at(&self, offset: usize) -> Token<'a> {
+        self.tokens[(self.position + offset).min(self.tokens.len() - 1)]
+    }
+    fn previous(&self) -> Token<'a> {
+        self.tokens[self.position.saturating_sub(1)]
+    }
+    fn advance(&mut self) -> Token<'a> {
+        let token = self.peek();
+        if token.kind != TokenKind::Eof {
+            self.position += 1;
+        }
+        token
+    }
+    fn accept(&mut self, text: &str) -> bool {
+        let accepted = self.peek().is(text);
+        if accepted {
+            self.advance();
+        }
+        accepted
+    }
+    fn error<T>(&self, message: String) -> ParseResult<T> {
+        Err((
+            self.peek().line,
+            format!("{message} near {}", self.peek().near()),
+        ))
+    }
+    fn expect(&mut self, text: &str) -> ParseResult {
+        if self.accept(text) {
+            Ok(())
+        } else {
+            self.error(format!("'{text}' expected"))
+        }
+    }
+    /// Expects the token closing the block opened by `opener` at the line
+    fn expect_closing(&mut self, text: &str, opener: &str, line: usize) -> ParseResult {
+        if self.accept(text) {
+            Ok(())
+        } else if self.peek().line == line {
+            self.error(format!("'{text}' expected"))
+        } else {
+            self.error(format!(
+                "'{text}' expected (to close '{opener}' at {})",
+                (self.line_label)(line)
+            ))
+        }
+    }
+    fn expect_name(&mut self) -> ParseResult<Token<'a>> {
+        match self.peek().kind {
+            TokenKind::Name => Ok(self.advance()),
+            _ => self.error("<name> expected".to_string()),
+        }
+    }
+    fn enter_scope(&mut self) {
+        if let Some(locals) = self.locals.as_mut() {
+            locals.scopes.push(Vec::new());
+        }
+    }
+    fn exit_scope(&mut self) {
+        if let Some(locals) = self.locals.as_mut() {
+            locals.scopes.pop();
+        }
+    }
+    /// Declares a local in the innermost scope, by the token-index of its name
+    fn declare(&mut self, token_index: usize) {
+        let name = self.tokens[token_index].text;
+        self.declare_name(name, Some(token_index));
+    }
+    fn declare_name(&mut self, name: &'a str, token_index: Option<usize>) {
+        if let Some(locals) = self.locals.as_mut() {
+            let declaration = locals.declarations.len();
+            locals.declarations.push(token_index);
+            if let Some(scope) = locals.scopes.last_mut() {
+                scope.push((name, declaration));
+            }
+        }
+    }
+    /// Resolves the name at the token-index to the innermost local of the name, if any
+    fn reference(&mut self, token_index: usize) {
+        let name = self.tokens[token_index].text;
+        if let Some(locals) = self.locals.as_mut()
+            && let Some(declaration) = locals
+                .scopes
+                .iter()
+                .rev()
+                .flat_map(|scope| scope.iter().rev())
+                .find_map(|(local, declaration)| (*local == name).then_some(*declaration))
+        {
+            locals.references.push((token_index, declaration));
+        }
+    }
+    /// Records the function-statement if it is at the top-level of the chunk
+    fn function_statement(&mut self, start: usize, name: usize, local: bool) {
+        let end = self.position - 1;
+        if let Some(locals) = self.locals.as_mut()
+            && locals.scopes.len() == 1
+        {
+            let declaration = local.then(|| locals.declarations.len() - 1);
+            locals.functions.push(FunctionStatement {
+                start,
+                name,
+                end,
+                declaration,
+            });
+        }
+    }
+    fn block_follows(&self) -> bool {
+        let token = self.peek();
+        token.kind == TokenKind::Eof
+            || ["end", "else", "elseif", "until"]
+                .iter()
+                .any(|text| token.is(text))
+    }
+    fn chunk(&mut self) -> ParseResult {
+        self.block()?;
+        match self.peek().kind {
+            TokenKind::Eof => Ok(()),
+            _ => self.error("'<eof>' expected".to_string()),
+        }
+    }
+    fn block(&mut self) -> ParseResult {
+        self.enter_scope();
+        self.statements()?;
+        self.exit_scope();
+        Ok(())
+    }
+    fn statements(&mut self) -> ParseResult {
+        while !self.block_follows() {
+            if self.peek().is("return") {
+                self.return_statement(None)?;
+                // Nothing may follow the return of a block, in lua 5.2
+                if !self.block_follows() {
+                    return self.error("'end' expected".to_string());
+                }
+                return Ok(());
+            }
+            self.statement()?;
+        }
+        Ok(())
+    }
+    /// A `return`, of the values on the line only in a shorthand
+    fn return_statement(&mut self, shorthand_line: Option<usize>) -> ParseResult {
+        self.advance();
+        if !self.block_follows()
+            && !self.peek().is(";")
+            && shorthand_line.is_none_or(|line| self.peek().line == line)
+        {
+            self.expression_list()?;
+        }
+        self.accept(";");
+        Ok(())
+    }
+    /// The statements of a shorthand on the line, e.g. the body of `if (cond) stmt`
+    fn line_block(&mut self, line: usize) -> ParseResult {
+        self.enter_scope();
+        while self.peek().line == line && !self.block_follows() {
+            if self.peek().is("return") {
+                self.return_statement(Some(line))?;
+                break;
+            }
+            self.statement()?;
+        }
+        self.exit_scope();
+        Ok(())
+    }
+    fn statement(&mut self) -> ParseResult {
+        let token = self.peek();
+        match token.text {
+            ";" if token.kind == TokenKind::Symbol => {
+                self.advance();
+            }
+            "::" if token.kind == TokenKind::Symbol => {
+                self.advance();
+                self.expect_name()?;
+                self.expect("::")?;
+            }
+            "?" if token.kind == TokenKind::Symbol => {
+                self.advance();
+                if self.peek().line == token.line && !self.block_follows() {
+                    self.expression_list()?;
+                }
+            }
+            "break" if token.kind == TokenKind::Keyword => {
+                self.advance();
+            }
+            "goto" if token.kind == TokenKind::Keyword => {
+                self.advance();
+                self.expect_name()?;
+            }
+            "do" if token.kind == TokenKind::Keyword => {
+                self.advance();
+                self.block()?;
+                self.expect_closing("end", "do", token.line)?;
+            }
+            "while" if token.kind == TokenKind::Keyword => {
+                self.advance();
+                let parenthesized = self.peek().is("(");
+                self.expression()?;
+                if self.accept("do") {
+                    self.block()?;
+                    self.expect_closing("end", "while", token.line)?;
+                } else if parenthesized && self.peek().line == self.previous().line {
+                    let line = self.previous().line;
+                    self.line_block(line)?;
+                } else {
+                    return self.error("'do' expected".to_string());
+                }
+            }
+            "repeat" if token.kind == TokenKind::Keyword => {
+                self.advance();
+                // The condition sees the locals of the block
+                self.enter_scope();
+                self.statements()?;
+                self.expect_closing("until", "repeat", token.line)?;
+                self.expression()?;
+                self.exit_scope();
+            }
+            "if" if token.kind == TokenKind::Keyword => self.if_statement(token)?,
+            "for" if token.kind == TokenKind::Keyword => {
+                self.advance();
+                self.expect_name()?;
+                let mut names = vec![self.position - 1];
+                if self.accept("=") {
+                    self.expression()?;
+                    self.expect(",")?;
+                    self.expression()?;
+                    if self.accept(",") {
+                        self.expression()?;
+                    }
+                } else {
+                    while self.accept(",") {
+                        self.expect_name()?;
+                        names.push(self.position - 1);
+                    }
+                    if !self.accept("in") {
+                        return self.error("'=' or 'in' expected".to_string());
+                    }
+                    self.expression_list()?;
+                }
+                self.expect("do")?;
+                self.enter_scope();
+                for name in names {
+                    self.declare(name);
+                }
+                self.block()?;
+                self.exit_scope();
+                self.expect_closing("end", "for", token.line)?;
+            }
+            "function" if token.kind == TokenKind::Keyword => {
+                let start = self.position;
+                self.advance();
+                self.expect_name()?;
+                let name = self.position - 1;
+                self.reference(name);
+                let mut field = false;
+                while self.accept(".") {
+                    self.expect_name()?;
+                    field = true;
+                }
+                let method = self.accept(":");
+                if method {
+                    self.expect_name()?;
+                }
+                self.function_body(token.line, method)?;
+                if !field && !method {
+                    self.function_statement(start, name, false);
+                }
+            }
+            "local" if token.kind == TokenKind::Keyword => {
+                let start = self.position;
+                self.advance();
+                if self.accept("function") {
+                    self.expect_name()?;
+                    let name = self.position - 1;
+                    // Visible in its own body, for recursion
+                    self.declare(name);
+                    self.function_body(token.line, false)?;
+                    self.function_statement(start, name, true);
+                } else {
+                    self.expect_name()?;
+                    let mut names = vec![self.position - 1];
+                    while self.accept(",") {
+                        self.expect_name()?;
+                        names.push(self.position - 1);
+                    }
+                    if self.accept("=") {
+                        self.expression_list()?;
+                    }
+                    // Visible after the statement, `local x = x` reads the outer `x`
+                    for name in names {
+                        self.declare(name);
+                    }
+                }
+            }
+            _ => self.expression_statement()?,
+        }
+        Ok(())
+    }
+    fn if_statement(&mut self, token: Token<'a>) -> ParseResult {
+        self.advance();
+        let parenthesized = self.peek().is("(");
+        self.expression()?;
+        if self.accept("then") {
+            self.block()?;
+            while self.peek().is("elseif") {
+                self.advance();
+                self.expression()?;
+                self.expect("then")?;
+                self.block()?;
+            }
+            if self.accept("else") {
+                self.block()?;
+            }
+            return self.expect_closing("end", "if", token.line);
+        }
+        let line = self.previous().line;
+        if !parenthesized || self.peek().line != line {
+            return self.error("'then' expected".to_string());
+        }
+        self.line_block(line)?;
+        if self.peek().line == line && self.accept("else") {
+            self.line_block(line)?;
+        }
+        Ok(())
+    }
+    /// An assignment, a compound assignment, or a call
+    fn expression_statement(&mut self) -> ParseResult {
+        let assignable = self.suffixed_expression()?;
+        let token = self.peek();
+        if token.is("=") || token.is(",") {
+            if !assignable {
+                return self.error("syntax error".to_string());
+            }
+            while self.accept(",") {
+                if !self.suffixed_expression()? {
+                    return self.error("syntax error".to_string());
+                }
+            }
+            self.expect("=")?;
+            return self.expression_list();
+        }
+        if token.kind == TokenKind::Symbol && COMPOUND_ASSIGNMENTS.contains(&token.text) {
+            if !assignable {
+                return self.error("syntax error".to_string());
+            }
+            self.advance();
+            return self.expression();
+        }
+        match self.previous().text {
+            ")" | "}" => Ok(()),
+            _ if self.previous().kind == TokenKind::String => Ok(()),
+            _ => self.error("syntax error".to_string()),
+        }
+    }
+    fn function_body(&mut self, line: usize, method: bool) -> ParseResult {
+        self.enter_scope();
+        if method {
+            self.declare_name("self", None);
+        }
+        self.expect("(")?;
+        if !self.peek().is(")") {
+            loop {
+                if self.accept("...") {
+                    break;
+                }
+                self.expect_name()?;
+                self.declare(self.position - 1);
+                if !self.accept(",") {
+                    break;
+                }
+            }
+        }
+        self.expect(")")?;
+        self.block()?;
+        self.exit_scope();
+        self.expect_closing("end", "function", line)
+    }
+    fn expression_list(&mut self) -> ParseResult {
+        self.expression()?;
+        while self.accept(",") {
+            self.expression()?;
+        }
+        Ok(())
+    }
+    fn expression(&mut self) -> ParseResult {
+        loop {
+            while self.peek().kind != TokenKind::String
+                && UNARY_OPERATORS.contains(&self.peek().text)
+                && matches!(self.peek().kind, TokenKind::Keyword | TokenKind::Symbol)
+            {
+                self.advance();
+            }
+            self.simple_expression()?;
+            let token = self.peek();
+            if matches!(token.kind, TokenKind::Keyword | TokenKind::Symbol)
+                && BINARY_OPERATORS.contains(&token.text)
+            {
+                self.advance();
+            } else {
+                return Ok(());
+            }
+        }
+    }
+    fn simple_expression(&mut self) -> ParseResult {
+        let token = self.peek();
+        match token.kind {
+            TokenKind::Number | TokenKind::String => {
+                self.advance();
+            }
+            TokenKind::Keyword if ["nil", "true", "false"].contains(&token.text) => {
+                self.advance();
+            }
+            TokenKind::Keyword if token.text == "function" => {
+                self.advance();
+                self.function_body(token.line, false)?;
+            }
+            TokenKind::Symbol if token.text == "..." => {
+                self.advance();
+            }
+            TokenKind::Symbol if token.text == "{" => self.table_constructor()?,
+            _ => {
+                self.suffixed_expression()?;
+            }
+        }
+        Ok(())
+    }
+    /// A name or parenthesized expression, with its fields, indexes and calls,
+    /// returning whether it can be assigned to
+    fn suffixed_expression(&mut self) -> ParseResult<bool> {
+        let token = self.peek();
+        let mut assignable = match token.kind {
+            TokenKind::Name => {
+                self.advance();
+                self.reference(self.position - 1);
+                true
+            }
+            TokenKind::Symbol if token.text == "(" => {
+                self.advance();
+                self.expression()?;
+                self.expect_closing(")", "(", token.line)?;
+                false
+            }
+            _ => return self.error("unexpected symbol".to_string()),
+        };
+        loop {
+            let token = self.peek();
+            match token.kind {
+                TokenKind::Symbol if token.text == "." => {
+                    self.advance();
+                    self.expect_name()?;
+                    assignable = true;
+                }
+                TokenKind::Symbol if token.text == "[" => {
+                    self.advance();
+                    self.expression()?;
+                    self.expect("]")?;
+                    assignable = true;
+                }
+                TokenKind::Symbol if token.text == ":" => {
+                    self.advance();
+                    self.expect_name()?;
+                    self.call_arguments()?;
+                    assignable = false;
+                }
+                TokenKind::Symbol if token.text == "(" || token.text == "{" => {
+                    self.call_arguments()?;
+                    assignable = false;
+                }
+                TokenKind::String => {
+                    self.advance();
+                    assignable = false;
+                }
+                _ => return Ok(assignable),
+            }
+        }
+    }
+    fn call_arguments(&mut self) -> ParseResult {
+        let token = self.peek();
+        match token.kind {
+            TokenKind::String => {
+                self.advance();
+                Ok(())
+            }
+            TokenKind::Symbol if token.text == "{" => self.table_constructor(),
+            TokenKind::Symbol if token.text == "(" => {
+                self.advance();
+                if !self.peek().is(")") {
+                    self.expression_list()?;
+                }
+                self.expect_closing(")", "(", token.line)
+            }
+            _ => self.error("function arguments expected".to_string()),
+        }
+    }
+    fn table_constructor(&mut self) -> ParseResult {
+        let line = self.advance().line;
+        while !self.peek().is("}") {
+            if self.accept("[") {
+                self.expression()?;
+                self.expect("]")?;
+                self.expect("=")?;
+            } else if self.peek().kind == TokenKind::Name && self.peek_at(1).is("=") {
+                self.advance();
+                self.advance();
+            }
+            self.expression()?;
+            if !self.accept(",") && !self.accept(";") {
+                break;
+            }
+        }
+        self.expect_closing("}", "{", line)
+    }
+}
+
+/// Validates the lua-source as a single chunk, returning the line and message of the
+/// first syntax error
+pub fn validate(lua_src: &[u8]) -> Result<(), (usize, String)> {
+    validate_with_labels(lua_src, &|line| format!("line {line}"))
+}
+
+fn validate_with_labels(
+    lua_src: &[u8],
+    line_label: &dyn Fn(usize) -> String,
+) -> Result<(), (usize, String)> {
+    let lua_src = String::from_utf8_lossy(lua_src);
+    let tokens = tokenize(&lua_src)?;
+    Parser {
+        tokens,
+        position: 0,
+        line_label,
+        locals: None,
+    }
+    .chunk()
+}
+
+/// Parses the tokens of a valid chunk, resolving each name to the local it refers to
+pub(crate) fn resolve_locals(tokens: Vec<Token<'_>>) -> Result<Locals<'_>, ChunkError> {
+    let line_label = |line| format!("line {line}");
+    let mut parser = Parser {
+        tokens,
+        position: 0,
+        line_label: &line_label,
+        locals: Some(Locals::default()),
+    };
+    parser.chunk()?;
+    Ok(parser.locals.unwrap_or_default())
+}
+
+/// The code-tabs joined into the chunk they run as
+pub(crate) struct Chunk {
+    pub(crate) src: Vec<u8>,
+    /// The index, the first line in the chunk, from 1, and the byte-range of the code of
+    /// each tab
+    tab_lines: Vec<(usize, usize, core::ops::Range<usize>)>,
+}
+
+impl Chunk {
+    pub(crate) fn of_code_tabs(code_tabs: &CodeTabs<'_>) -> Chunk {
+        let mut src = Vec::new();
+        let mut tab_lines = Vec::new();
+        let mut line_count = 0;
+        for (tab_index, tab) in code_tabs.iter_present() {
+            let offset = src.len();
+            src.extend_from_slice(&tab.code_data);
+            tab_lines.push((tab_index, line_count + 1, offset..src.len()));
+            if !src.ends_with(b"\n") {
+                src.push(b'\n');
+            }
+            line_count += tab.code_data.split(|byte| *byte == b'\n').count()
+                - usize::from(tab.code_data.ends_with(b"\n"));
+        }
+        Chunk { src, tab_lines }
+    }
+    /// The tab of the line of the chunk, and its line of the tab
+    pub(crate) fn tab_line(&self, line: usize) -> (usize, usize) {
+        let (tab_index, first_line) = self
+            .tab_lines
+            .iter()
+            .rev()
+            .find(|(_, first_line, _)| *first_line <= line)
+            .map(|(tab_index, first_line, _)| (*tab_index, *first_line))
+            .unwrap_or((0, 1));
+        (tab_index, line - first_line + 1)
+    }
+    /// The index and the byte-range in the chunk of the code of each tab
+    pub(crate) fn tab_ranges(&self) -> impl Iterator<Item = (usize, core::ops::Range<usize>)> {
+        self.tab_lines
+            .iter()
+            .map(|(tab_index, _, range)| (*tab_index, range.clone()))
+    }
+    pub(crate) fn error(&self, (line, message): ChunkError) -> SyntaxError {
+        let (tab_index, line_number) = self.tab_line(line);
+        SyntaxError {
+            tab_index,
+            line_number,
+            message,
+        }
+    }
+}
+
+/// Validates the code-tabs, run as one chunk
+#[tracing::instrument(level = "debug", skip(code_tabs))]
+pub fn validate_code_tabs(code_tabs: &CodeTabs<'_>) -> Result<(), SyntaxError> {
+    let chunk = Chunk::of_code_tabs(code_tabs);
+    let line_label = |line| {
+        let (tab_index, line_number) = chunk.tab_line(line);
+        format!("tab {tab_index}, line {line_number}")
+    };
+    validate_with_labels(&chunk.src, &line_label).map_err(|e| chunk.error(e))
+}
+
+/// Validates the code of the cart, see [`validate_code_tabs`]
+pub fn validate_cart(cart: &CartData<'_>) -> Result<(), SyntaxError> {
+    validate_code_tabs(cart.code_tabs())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn validates_pico_8_lua() {
+        const VALID: &str = r#"-- shorthands
+function _update()
+ if (btn(⬅️)) x-=1
+ if (x != 0) x\=2 else y..="a"
+ while (x > 0) x -= 1
+ ?"score: "..score, 0, 0
+ a = @0x5f00 + %0x6000 + $0 ^^ 3 >>> 1
+ t = {1, [2]=3, k="v"; f=function(...) return ... end,}
+ obj:move(1, 2).pos[1] = 0x1.8 + 0b101 + .5
+ for k, v in pairs(t) do goto skip ::skip:: end
+ s = [[long
+string]] // comment
+ --[[ long
+ comment ]]
+ repeat local i = i + 1 until i > 3
+ print "hi" print{}
+end
+"#;
+        assert_eq!(validate(VALID.as_bytes()), Ok(()));
+        assert_eq!(
+            validate(b"function hit(e)\n if(e.dead)return\n e.hp-=1\nend"),
+            Ok(())
+        );
+        let cases: &[(&str, usize, &str)] = &[
+            (
+                "function f()\n x=1\n",
+                3,
+                "'end' expected (to close 'function' at line 1) near <eof>",
+            ),
+            ("x = = 1", 1, "unexpected symbol near '='"),
+            ("x = \"open\ny = 2", 1, "unfinished string near '\"open'"),
+            ("if x y=1 end", 1, "'then' expected near 'y'"),
+            ("f() = 1", 1, "syntax error near '='"),
+            ("x = 1.2.3", 1, "malformed number near '1.2.3'"),
+            ("return 1\nx = 2", 2, "'end' expected near 'x'"),
+            ("x = 1 end", 1, "'<eof>' expected near 'end'"),
+            ("x`", 1, "unexpected symbol near '`'"),
+        ];
+        let mut code_tabs: CodeTabs<'_> = Default::default();
+        for (tab_index, code) in [(0, "x=1\nfunction f()\n"), (1, " y=2\nend\nif x\n")] {
+            code_tabs[tab_index] = Some(pico_8_cart_model::Tab {
+                line_number: 0,
+                code_data: code.as_bytes().into(),
+            });
+        }
+        assert_eq!(
+            validate_code_tabs(&code_tabs),
+            Err(SyntaxError {
+                tab_index: 1,
+                line_number: 4,
+                message: "'then' expected near <eof>".to_string(),
+            })
+        );
+        for (src, line, message) in cases {
+            assert_eq!(
+                validate(src.as_bytes()),
+                Err((*line, message.to_string())),
+                "{src:?}"
+            );
+        }
+    }
+}
@@ -0,0 +1,73 @@
+//! The titles of the compiled tabs, the `-- name` comment on the first line of each
+//!
+//! The pico-8 code-editor shows the comment on the first line of a tab as its title, see
+//! [`pico_8_cart_model::title_of`]. Each tab compiled from a source-file is titled:
+//! - by a `--#tab <name>` directive on the first line of the source-file, which is
+//!   replaced by the title, keeping the lines of the file where they were
+//! - by the comment the source-file starts with, kept as it is
+//! - otherwise by the name of the source-file, without its extension, on a line added
+//!   before the code
+
+use pico_8_cart_model::title_of;
+
+/// The directive naming the tab of a source-file, on its first line
+pub const TAB_DIRECTIVE: &str = "--#tab";
+
+/// The name given by the directive on the first line of the code, empty if it names none
+pub fn directive_title(code_data: &[u8]) -> Option<&str> {
+    let first_line = code_data.split(|byte| *byte == b'\n').next()?;
+    let name = first_line.strip_prefix(TAB_DIRECTIVE.as_bytes())?;
+    // Only a whole word, not e.g. `--#tabs`
+    if !name.is_empty() && !name[0].is_ascii_whitespace() {
+        return None;
+    }
+    str::from_utf8(name).ok().map(str::trim)
+}
+
+/// The code of the source-file, with its title on the first line, see the
+/// module-documentation
+///
+/// The `name` titles the tab if the code names none itself
+pub fn with_title<U: FromIterator<u8>>(name: &str, code_data: &[u8]) -> U {
+    let after_first_line = code_data
+        .iter()
+        .position(|byte| *byte == b'\n')
+        .map_or(&[][..], |newline| &code_data[newline + 1..]);
+    let (title, code) = match directive_title(code_data) {
+        Some("") => (name, after_first_line),
+        Some(title) => (title, after_first_line),
+        None if title_of(code_data).is_some() => return code_data.iter().copied().collect(),
+        None => (name, code_data),
+    };
+    format!("-- {title}\n")
+        .into_bytes()
+        .into_iter()
+        .chain(code.iter().copied())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn titles_source_files() {
+        let titled = |name: &str, code: &str| {
+            String::from_utf8(with_title::<Vec<u8>>(name, code.as_bytes())).unwrap()
+        };
+        assert_eq!(
+            titled("player", "--#tab the player\nx = 1\n"),
+            "-- the player\nx = 1\n"
+        );
+        assert_eq!(titled("player", "--#tab\nx = 1"), "-- player\nx = 1");
+        assert_eq!(titled("player", "-- hero\nx = 1\n"), "-- hero\nx = 1\n");
+        assert_eq!(titled("player", "x = 1\n"), "-- player\nx = 1\n");
+        // A long comment or a bare `--` is no title
+        assert_eq!(
+            titled("player", "--[[ state ]]\n"),
+            "-- player\n--[[ state ]]\n"
+        );
+        // Not the directive, so kept as the comment it is
+        assert_eq!(titled("player", "--#tabs\n"), "--#tabs\n");
+    }
+}
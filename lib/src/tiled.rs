@@ -0,0 +1,242 @@
+//! Reading the tile-layers of maps drawn in Tiled, for importing them into `__map__`
+//!
+//! A `.tmx` map is read from its first `<layer>`, which must be saved with the csv
+//! layer-format. Its global tile-ids are counted from the `firstgid` of the first tileset,
+//! with the flip-flags cleared, so the first tile of the tileset is sprite 0. A `.csv`
+//! file, as exported by Tiled, holds the tile-ids of a layer row by row, with `-1` for an
+//! empty cell. Empty cells are imported as tile 0
+
+use core::fmt;
+
+use std::io;
+
+/// The bits of a global tile-id flipping the tile, which pico-8 has no use for
+const FLIP_FLAGS: u32 = 0xf000_0000;
+
+#[derive(Debug)]
+pub enum TiledError {
+    Io(io::Error),
+    /// The map has no `<layer>`, or its layer no `<data>`
+    MissingLayer,
+    /// The layer is not saved with the csv layer-format, e.g. `base64`
+    UnsupportedEncoding(String),
+    /// A cell of the layer is not a tile-id
+    InvalidTile {
+        row: usize,
+        value: String,
+    },
+    /// The rows of the layer differ in length, or do not match the size of the layer
+    InvalidSize {
+        width: usize,
+        height: usize,
+        tile_count: usize,
+    },
+    /// The layer placed at the tile does not fit into the 128x64 tiles of the map
+    OutOfMap {
+        x: usize,
+        y: usize,
+        width: usize,
+        height: usize,
+    },
+}
+
+impl From<io::Error> for TiledError {
+    fn from(v: io::Error) -> Self {
+        Self::Io(v)
+    }
+}
+
+impl fmt::Display for TiledError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let description = "Tiled map error";
+        let reason = match self {
+            TiledError::Io(e) => e.to_string(),
+            TiledError::MissingLayer => "no tile-layer with data".to_string(),
+            TiledError::UnsupportedEncoding(encoding) => {
+                format!("the layer is encoded as {encoding}, expected csv")
+            }
+            TiledError::InvalidTile { row, value } => {
+                format!("invalid tile {value:?} in row {row}")
+            }
+            TiledError::InvalidSize {
+                width,
+                height,
+                tile_count,
+            } => format!("{tile_count} tiles do not make up a {width}x{height} layer"),
+            TiledError::OutOfMap {
+                x,
+                y,
+                width,
+                height,
+            } => format!("a {width}x{height} layer at tile {x},{y} overflows the map"),
+        };
+        f.write_fmt(format_args!("{description}: {reason}"))
+    }
+}
+
+impl core::error::Error for TiledError {}
+
+impl TiledError {
+    /// The stable diagnostic-code, see `pico-build-rs explain`
+    pub const fn code(&self) -> &'static str {
+        "E017"
+    }
+}
+
+/// The tile-ids of a layer, row by row
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TiledLayer {
+    pub width: usize,
+    pub height: usize,
+    pub tiles: Vec<u32>,
+}
+
+impl TiledLayer {
+    /// Reads a layer exported as csv, one row per line
+    pub fn from_csv(text: &str) -> Result<TiledLayer, TiledError> {
+        let mut tiles = Vec::new();
+        let mut width = None;
+        let mut height = 0;
+        for line in text.lines().map(str::trim).filter(|line| !line.is_empty()) {
+            let row = cells(line.trim_end_matches(','), height)?;
+            if *width.get_or_insert(row.len()) != row.len() {
+                return Err(TiledError::InvalidSize {
+                    width: width.unwrap_or_default(),
+                    height: height + 1,
+                    tile_count: tiles.len() + row.len(),
+                });
+            }
+            tiles.extend(
+                row.into_iter()
+                    .map(|tile| u32::try_from(tile).unwrap_or_default()),
+            );
+            height += 1;
+        }
+        Ok(TiledLayer {
+            width: width.unwrap_or_default(),
+            height,
+            tiles,
+        })
+    }
+    /// Reads the first layer of a `.tmx` map, saved with the csv layer-format
+    pub fn from_tmx(text: &str) -> Result<TiledLayer, TiledError> {
+        let first_gid = start_tag(text, "tileset")
+            .and_then(|tag| attribute(tag, "firstgid"))
+            .and_then(|first_gid| first_gid.parse::<u32>().ok())
+            .unwrap_or(1);
+        let layer_start = text.find("<layer").ok_or(TiledError::MissingLayer)?;
+        let layer = &text[layer_start..];
+        let layer_tag = start_tag(layer, "layer").ok_or(TiledError::MissingLayer)?;
+        let size = |name| {
+            attribute(layer_tag, name)
+                .and_then(|value| value.parse::<usize>().ok())
+                .ok_or(TiledError::MissingLayer)
+        };
+        let (width, height) = (size("width")?, size("height")?);
+        let data_tag = start_tag(layer, "data").ok_or(TiledError::MissingLayer)?;
+        match attribute(data_tag, "encoding") {
+            Some("csv") => {}
+            Some(encoding) => return Err(TiledError::UnsupportedEncoding(encoding.to_string())),
+            None => return Err(TiledError::UnsupportedEncoding("xml".to_string())),
+        }
+        let data_start = layer.find(data_tag).unwrap_or_default() + data_tag.len();
+        let data_end = layer[data_start..]
+            .find("</data>")
+            .ok_or(TiledError::MissingLayer)?;
+        let mut tiles = Vec::with_capacity(width * height);
+        for (row, line) in layer[data_start..data_start + data_end]
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+            .enumerate()
+        {
+            for gid in cells(line.trim_end_matches(','), row)? {
+                let gid = u32::try_from(gid).unwrap_or_default() & !FLIP_FLAGS;
+                tiles.push(match gid {
+                    0 => 0,
+                    gid => gid.saturating_sub(first_gid),
+                });
+            }
+        }
+        if tiles.len() != width * height {
+            return Err(TiledError::InvalidSize {
+                width,
+                height,
+                tile_count: tiles.len(),
+            });
+        }
+        Ok(TiledLayer {
+            width,
+            height,
+            tiles,
+        })
+    }
+}
+
+/// The comma-separated tile-ids of a row, `-1` for an empty cell
+fn cells(line: &str, row: usize) -> Result<Vec<i64>, TiledError> {
+    line.split(',')
+        .map(str::trim)
+        .map(|value| {
+            value
+                .parse::<i64>()
+                .ok()
+                .filter(|tile| *tile >= -1 && *tile <= i64::from(u32::MAX))
+                .ok_or_else(|| TiledError::InvalidTile {
+                    row,
+                    value: value.to_string(),
+                })
+        })
+        .collect()
+}
+
+/// The first start-tag of the element, up to and including its `>`
+fn start_tag<'a>(text: &'a str, name: &str) -> Option<&'a str> {
+    let start = text.find(&format!("<{name}"))?;
+    let end = text[start..].find('>')?;
+    Some(&text[start..=start + end])
+}
+
+/// The value of the attribute of a start-tag
+fn attribute<'a>(tag: &'a str, name: &str) -> Option<&'a str> {
+    let pattern = format!(" {name}=\"");
+    let start = tag.find(&pattern)? + pattern.len();
+    let end = tag[start..].find('"')?;
+    Some(&tag[start..start + end])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_tmx_and_csv() {
+        const TMX: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<map version="1.10" orientation="orthogonal" width="3" height="2" tilewidth="8" tileheight="8">
+ <tileset firstgid="1" source="sprites.tsx"/>
+ <layer id="1" name="ground" width="3" height="2">
+  <data encoding="csv">
+0,2,3,
+2147483650,1,300
+</data>
+ </layer>
+</map>
+"#;
+        let layer = TiledLayer::from_tmx(TMX).unwrap();
+        assert_eq!((layer.width, layer.height), (3, 2));
+        assert_eq!(layer.tiles, [0, 1, 2, 1, 0, 299]);
+
+        let layer = TiledLayer::from_csv("-1,1,2\n3,-1,4\n").unwrap();
+        assert_eq!((layer.width, layer.height), (3, 2));
+        assert_eq!(layer.tiles, [0, 1, 2, 3, 0, 4]);
+
+        assert!(matches!(
+            TiledLayer::from_csv("1,2\n3\n"),
+            Err(TiledError::InvalidSize { .. })
+        ));
+        assert!(matches!(
+            TiledLayer::from_tmx(&TMX.replace("csv", "base64")),
+            Err(TiledError::UnsupportedEncoding(encoding)) if encoding == "base64"
+        ));
+    }
+}
@@ -0,0 +1,128 @@
+//! Scanning of lua source-files for `-- TODO`/`-- FIXME` style comments
+
+use std::io;
+use std::path;
+
+use crate::{FileData, LoadPolicy};
+
+/// The markers searched for when none are configured
+pub const DEFAULT_MARKERS: &[&str] = &["TODO", "FIXME"];
+
+/// A marker-comment found in a source-file
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TodoEntry {
+    pub path: path::PathBuf,
+    /// The (1-based) line-number of the comment
+    pub line_number: usize,
+    /// The marker which was matched, e.g. `TODO`
+    pub marker: String,
+    /// The remainder of the comment following the marker
+    pub text: String,
+}
+
+/// Returns the comment-part of a line, following the `--`
+fn get_comment(line: &[u8]) -> Option<&[u8]> {
+    bytes::find_sequence(line, b"--").map(|comment_index| &line[comment_index + 2..])
+}
+
+/// Checks if the comment starts with the marker, returning the remaining text
+fn strip_marker<'a>(comment: &'a str, marker: &str) -> Option<&'a str> {
+    let remainder = comment.trim_start().strip_prefix(marker)?;
+    // Avoid matching e.g. `TODOS` when looking for `TODO`
+    if remainder
+        .chars()
+        .next()
+        .is_some_and(|next| next.is_alphanumeric() || next == '_')
+    {
+        return None;
+    }
+    let remainder = match remainder.strip_prefix('(') {
+        // Skip owner-annotations, such as `TODO(name)`
+        Some(annotated) => annotated
+            .split_once(')')
+            .map_or(annotated, |(_, remainder)| remainder),
+        None => remainder,
+    };
+    Some(remainder.trim_start().trim_start_matches(':').trim())
+}
+
+/// Scans lua source for comments starting with any of the markers
+///
+/// Returns the (1-based) line-number, the matched marker and the comment-text
+pub fn scan_source<'a, M: AsRef<str>>(
+    src: &'a [u8],
+    markers: &'a [M],
+) -> impl Iterator<Item = (usize, &'a str, String)> + 'a {
+    bytes::NewlineIter::new(src)
+        .enumerate()
+        .filter_map(move |(line_index, line)| {
+            let comment = String::from_utf8_lossy(get_comment(line)?);
+            markers.iter().map(AsRef::as_ref).find_map(|marker| {
+                strip_marker(&comment, marker)
+                    .map(|text| (line_index + 1, marker, text.to_string()))
+            })
+        })
+}
+
+/// Scans the loaded source-file for marker-comments
+pub fn scan_source_file<T: AsRef<[u8]>, M: AsRef<str>>(
+    source_file: &FileData<T>,
+    markers: &[M],
+) -> Vec<TodoEntry> {
+    let src = source_file.unwrap_loaded_data_ref().as_ref();
+    scan_source(src, markers)
+        .map(|(line_number, marker, text)| TodoEntry {
+            path: source_file.as_path().to_path_buf(),
+            line_number,
+            marker: marker.to_string(),
+            text,
+        })
+        .collect()
+}
+
+/// Scans all lua source-files in the directory for marker-comments
+///
+/// Files which fail to load are skipped with a warning
+#[tracing::instrument(level = "debug", skip(directory_path, markers))]
+pub fn scan_directory<P: AsRef<path::Path> + ?Sized, M: AsRef<str>>(
+    directory_path: &P,
+    markers: &[M],
+) -> io::Result<Vec<TodoEntry>> {
+    let mut entries: Vec<TodoEntry> = crate::get_lua_files(directory_path)
+        .map(crate::dir_entries_to_source_files)?
+        .filter_map(|source_file: FileData<Box<[u8]>>| {
+            source_file
+                .into_loaded(LoadPolicy::ReadOnly)
+                .inspect_err(|e| tracing::warn!("Skipping source-file when scanning: {e:?}"))
+                .ok()
+        })
+        .flat_map(|source_file| scan_source_file(&source_file, markers))
+        .collect();
+    // Directory iteration-order is not stable
+    entries.sort_by(|a, b| (&a.path, a.line_number).cmp(&(&b.path, b.line_number)));
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn markers() {
+        const SRC: &[u8] = br"function _init()
+ -- TODO: spawn enemies
+ x=1 -- FIXME(herman) off by one
+ -- TODOS are not todos
+ y=2
+end
+-- todo lowercase is ignored";
+        let found: Vec<(usize, &str, String)> = scan_source(SRC, DEFAULT_MARKERS).collect();
+        assert_eq!(
+            found,
+            [
+                (2, "TODO", "spawn enemies".to_string()),
+                (3, "FIXME", "off by one".to_string()),
+            ]
+        );
+    }
+}
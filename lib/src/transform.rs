@@ -0,0 +1,382 @@
+//! Source-transforms applied to the lua source-files before compiling them into tabs
+
+use alloc::borrow::Cow;
+
+use pico_8_cart_builder::lex;
+use pico_8_cart_builder::{ConstantError, Constants};
+
+use crate::FileData;
+use crate::strings::{self, Catalog};
+use crate::syntax::{self, Token, TokenKind};
+
+/// The calls stripped from release-builds when none are configured
+pub const DEFAULT_DEBUG_CALLS: &[&str] = &["printh"];
+
+/// Which transforms to apply when compiling the cartridge
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum BuildProfile {
+    /// Keeps the source as written
+    #[default]
+    Debug,
+    /// Strips debug-calls and assertions from the source
+    Release,
+}
+
+impl BuildProfile {
+    /// Parses the profile-name as written in a config-file, i.e. `"debug"` or `"release"`
+    pub fn from_name(name: &str) -> Option<BuildProfile> {
+        match name.to_ascii_lowercase().as_str() {
+            "debug" => Some(BuildProfile::Debug),
+            "release" => Some(BuildProfile::Release),
+            _ => None,
+        }
+    }
+}
+
+/// Returns the index following the parenthesis matching the one opening at `start`
+fn find_closing_paren(src: &[u8], start: usize) -> Option<usize> {
+    let mut depth = 0usize;
+    let mut index = start;
+    while index < src.len() {
+        if let Some(end) = lex::skip_non_code(src, index) {
+            index = end;
+            continue;
+        }
+        match src[index] {
+            b'(' => depth += 1,
+            b')' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(index + 1);
+                }
+            }
+            _ => {}
+        }
+        index += 1;
+    }
+    None
+}
+
+/// Whether a statement may start after the token, i.e. it leaves no expression pending,
+/// as an operator or `return` does
+fn ends_statement(token: &Token<'_>) -> bool {
+    match token.kind {
+        TokenKind::Name | TokenKind::Number | TokenKind::String => true,
+        TokenKind::Keyword => matches!(
+            token.text,
+            "end" | "do" | "then" | "else" | "repeat" | "break" | "true" | "false" | "nil"
+        ),
+        TokenKind::Symbol => matches!(token.text, ")" | "]" | "}" | ";"),
+        TokenKind::Eof => false,
+    }
+}
+
+/// Removes statement-calls to any of the named functions
+///
+/// A call is removed only where it starts a statement, so never as a value, e.g.
+/// `x = 1 + printh(y)` or `if printh(x) then`, nor as the body of a single-line
+/// `if (cond) printh(x)`. Line-breaks inside a removed call are kept, so
+/// line-numbers are unchanged. Returns the transformed source and the number of
+/// calls removed
+pub fn strip_debug_calls<'a, N: AsRef<str>>(src: &'a [u8], names: &[N]) -> (Cow<'a, [u8]>, usize) {
+    let tokens = match core::str::from_utf8(src)
+        .map_err(|e| e.to_string())
+        .and_then(|src| {
+            syntax::tokenize(src).map_err(|(line, message)| format!("line {line}: {message}"))
+        }) {
+        Ok(tokens) => tokens,
+        Err(reason) => {
+            tracing::warn!("Keeping the debug-calls, the source is not lua: {reason}");
+            return (Cow::Borrowed(src), 0);
+        }
+    };
+    // Each `(` still open, by whether it opens the condition of a
+    // single-line `if (cond) stmt` or `while (cond) stmt`
+    let mut open_parens: Vec<bool> = Vec::new();
+    // The `)` closing such a condition, as the index of its token
+    let mut closed_condition = None;
+    let mut output: Option<Vec<u8>> = None;
+    let mut copied_until = 0;
+    let mut stripped = 0;
+    let mut index = 0;
+    while index < tokens.len() {
+        let token = &tokens[index];
+        let previous = index
+            .checked_sub(1)
+            .map(|previous_index| &tokens[previous_index]);
+        match (token.kind, token.text) {
+            (TokenKind::Symbol, "(") => open_parens
+                .push(previous.is_some_and(|previous| previous.is("if") || previous.is("while"))),
+            (TokenKind::Symbol, ")") if open_parens.pop() == Some(true) => {
+                closed_condition = Some(index);
+            }
+            _ => {}
+        }
+        let starts_statement = previous
+            .is_none_or(|previous| ends_statement(previous) && closed_condition != Some(index - 1));
+        let is_debug_call = token.kind == TokenKind::Name
+            && tokens.get(index + 1).is_some_and(|next| next.is("("))
+            && names.iter().any(|name| name.as_ref() == token.text);
+        if !(starts_statement && is_debug_call) {
+            index += 1;
+            continue;
+        }
+        let mut depth = 0usize;
+        let Some(close_index) = tokens[index + 1..].iter().position(|token| {
+            match token.text {
+                _ if token.kind != TokenKind::Symbol => {}
+                "(" => depth += 1,
+                ")" => depth -= 1,
+                _ => {}
+            }
+            depth == 0
+        }) else {
+            break;
+        };
+        let close_index = index + 1 + close_index;
+        // The value of the call is used if it is called or indexed, e.g. `printh(x).y()`
+        let is_continued = tokens.get(close_index + 1).is_some_and(|next| {
+            next.kind == TokenKind::String
+                || ["(", "{", "[", ".", ":"]
+                    .into_iter()
+                    .any(|text| next.is(text))
+        });
+        if is_continued {
+            index += 1;
+            continue;
+        }
+        let call_start = token.offset;
+        let call_end = tokens[close_index].offset + 1;
+        let output = output.get_or_insert_with(|| Vec::with_capacity(src.len()));
+        output.extend_from_slice(&src[copied_until..call_start]);
+        output.extend(
+            src[call_start..call_end]
+                .iter()
+                .filter(|byte| **byte == b'\n'),
+        );
+        copied_until = call_end;
+        stripped += 1;
+        index = close_index + 1;
+    }
+    match output {
+        Some(mut output) => {
+            output.extend_from_slice(&src[copied_until..]);
+            (Cow::Owned(output), stripped)
+        }
+        None => (Cow::Borrowed(src), 0),
+    }
+}
+
+const ASSERT_DIRECTIVE: &[u8] = b"--#assert";
+
+/// Expands `--#assert(cond, msg)` directive-lines into calls to `assert` in the
+/// debug-profile, and removes them in the release-profile
+///
+/// A directive must be on a single line, and is not one inside a long comment or
+/// long string, e.g. `--[[ ... ]]`. Removed directives keep their line-break, so
+/// line-numbers are unchanged. Returns the transformed source and the number of
+/// directives found
+pub fn expand_assertions(src: &[u8], profile: BuildProfile) -> (Cow<'_, [u8]>, usize) {
+    let mut output: Option<Vec<u8>> = None;
+    let mut copied_until = 0;
+    let mut found = 0;
+    let mut index = 0;
+    while index < src.len() {
+        if !(lex::starts_line(src, index) && src[index..].starts_with(ASSERT_DIRECTIVE)) {
+            index = lex::skip_non_code(src, index).unwrap_or(index + 1);
+            continue;
+        }
+        let line_end = bytes::find_sequence(&src[index..], b"\n")
+            .map_or(src.len(), |newline_index| index + newline_index);
+        let directive = &src[index..line_end];
+        let arguments = directive[ASSERT_DIRECTIVE.len()..].trim_ascii();
+        if !arguments.starts_with(b"(") || find_closing_paren(arguments, 0) != Some(arguments.len())
+        {
+            tracing::warn!(
+                "W001: Ignoring malformed assert-directive {:?}",
+                String::from_utf8_lossy(directive.trim_ascii())
+            );
+            index = line_end;
+            continue;
+        }
+        let output = output.get_or_insert_with(|| Vec::with_capacity(src.len()));
+        output.extend_from_slice(&src[copied_until..index]);
+        if profile == BuildProfile::Debug {
+            output.extend_from_slice(b"assert");
+            output.extend_from_slice(arguments);
+        }
+        copied_until = line_end;
+        found += 1;
+        index = line_end;
+    }
+    match output {
+        Some(mut output) => {
+            output.extend_from_slice(&src[copied_until..]);
+            (Cow::Owned(output), found)
+        }
+        None => (Cow::Borrowed(src), 0),
+    }
+}
+
+/// The changes made by [`transform_source_file`]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct TransformSummary {
+    pub stripped_calls: usize,
+    pub assertions: usize,
+    /// The definitions removed, names substituted and expressions folded
+    pub constants: usize,
+    pub localized_strings: usize,
+}
+
+impl core::ops::AddAssign for TransformSummary {
+    fn add_assign(&mut self, rhs: Self) {
+        self.stripped_calls += rhs.stripped_calls;
+        self.assertions += rhs.assertions;
+        self.constants += rhs.constants;
+        self.localized_strings += rhs.localized_strings;
+    }
+}
+
+impl TransformSummary {
+    /// The names of the transforms which changed the source, in the order they were applied
+    pub fn applied_transforms(&self, profile: BuildProfile) -> Vec<&'static str> {
+        let mut applied_transforms = Vec::new();
+        if self.assertions > 0 {
+            applied_transforms.push(match profile {
+                BuildProfile::Debug => "expand-assertions",
+                BuildProfile::Release => "remove-assertions",
+            });
+        }
+        if self.stripped_calls > 0 {
+            applied_transforms.push("strip-debug-calls");
+        }
+        if self.constants > 0 {
+            applied_transforms.push("substitute-constants");
+        }
+        if self.localized_strings > 0 {
+            applied_transforms.push("localize-strings");
+        }
+        applied_transforms
+    }
+}
+
+/// The constants of the config-file, with those defined in the loaded source-files, see
+/// [`Constants::define_from_source`]
+#[tracing::instrument(level = "debug", skip_all)]
+pub fn collect_constants(
+    constants: &Constants,
+    source_files: &[FileData<Box<[u8]>>],
+) -> Result<Constants, ConstantError> {
+    let mut constants = constants.clone();
+    for source_file in source_files {
+        if let FileData::Loaded { path, data } = source_file {
+            constants.define_from_source(path, data)?;
+        }
+    }
+    Ok(constants)
+}
+
+/// Applies the transforms of the profile to a loaded source-file, substitutes the
+/// constants, see [`Constants::substitute`], then swaps in the translated
+/// string-literals of the catalog, see [`strings::localize`]
+#[tracing::instrument(level = "debug", skip(source_file, debug_calls, constants, catalog))]
+pub fn transform_source_file<N: AsRef<str>>(
+    source_file: FileData<Box<[u8]>>,
+    profile: BuildProfile,
+    debug_calls: &[N],
+    constants: &Constants,
+    catalog: Option<&Catalog>,
+) -> (FileData<Box<[u8]>>, TransformSummary) {
+    let FileData::Loaded { path, data } = source_file else {
+        return (source_file, TransformSummary::default());
+    };
+    let (expanded, assertions) = expand_assertions(&data, profile);
+    let (transformed, stripped_calls) = match profile {
+        BuildProfile::Debug => (expanded, 0),
+        BuildProfile::Release => match strip_debug_calls(&expanded, debug_calls) {
+            (Cow::Owned(stripped), count) => (Cow::Owned(stripped), count),
+            (Cow::Borrowed(_), _) => (expanded, 0),
+        },
+    };
+    // After the assertions are expanded, so their conditions are substituted as well
+    let (transformed, constants) = match constants.substitute(&transformed) {
+        (Cow::Owned(substituted), count) => (Cow::Owned(substituted), count),
+        (Cow::Borrowed(_), _) => (transformed, 0),
+    };
+    let (transformed, localized_strings) = match catalog {
+        Some(catalog) => match strings::localize(&transformed, catalog) {
+            (Cow::Owned(localized), count) => (Cow::Owned(localized), count),
+            (Cow::Borrowed(_), _) => (transformed, 0),
+        },
+        None => (transformed, 0),
+    };
+    let summary = TransformSummary {
+        stripped_calls,
+        assertions,
+        constants,
+        localized_strings,
+    };
+    tracing::debug!("Transformed {path:?}: {summary:?}");
+    let data = match transformed {
+        Cow::Owned(transformed) => transformed.into_boxed_slice(),
+        Cow::Borrowed(_) => data,
+    };
+    (FileData::Loaded { path, data }, summary)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn strips_statement_calls() {
+        const SRC: &[u8] = br#"function _update()
+ printh("x="..x)
+ x+=1 printh(
+  "multi (line)"
+ )
+ -- printh("in a comment")
+ s="printh(1)"
+ t=printh("used as a value")
+ log.printh("method")
+end"#;
+        let (stripped, count) = strip_debug_calls(SRC, DEFAULT_DEBUG_CALLS);
+        assert_eq!(count, 2);
+        assert_eq!(
+            core::str::from_utf8(&stripped).unwrap(),
+            "function _update()\n \n x+=1 \n\n\n -- printh(\"in a comment\")\n s=\"printh(1)\"\n t=printh(\"used as a value\")\n log.printh(\"method\")\nend"
+        );
+
+        // Only where a statement starts, so never as a value
+        const VALUES: &str = "if dbg(x) then dbg(1) end\nx = 1 + dbg(y)\nwhile dbg(x) do end\nrepeat dbg(2) until dbg(x)\nif a then elseif dbg(x) then else dbg(3) end\nfor k in dbg(t) do end\nb = c <\n dbg(x)\nb = c == dbg(x) or c ~= dbg(x) or c != dbg(x)\nb = 2 - dbg(x) * dbg(x) / dbg(x) % dbg(x) ^ dbg(x) > dbg(x)\nif (x) dbg(1)\nwhile (x) dbg(1)\nf(x) dbg(4); dbg(5)\nfunction dbg(s) end\ndbg(6):m()";
+        let (stripped, count) = strip_debug_calls(VALUES.as_bytes(), &["dbg"]);
+        assert_eq!(count, 5);
+        assert_eq!(
+            core::str::from_utf8(&stripped).unwrap(),
+            "if dbg(x) then  end\nx = 1 + dbg(y)\nwhile dbg(x) do end\nrepeat  until dbg(x)\nif a then elseif dbg(x) then else  end\nfor k in dbg(t) do end\nb = c <\n dbg(x)\nb = c == dbg(x) or c ~= dbg(x) or c != dbg(x)\nb = 2 - dbg(x) * dbg(x) / dbg(x) % dbg(x) ^ dbg(x) > dbg(x)\nif (x) dbg(1)\nwhile (x) dbg(1)\nf(x) ; \nfunction dbg(s) end\ndbg(6):m()"
+        );
+    }
+
+    #[test]
+    fn assertions() {
+        const SRC: &[u8] =
+            b"function hit(e)\n --#assert(e.hp>0, \"dead enemy hit\")\n e.hp-=1\nend";
+        let (debug, count) = expand_assertions(SRC, BuildProfile::Debug);
+        assert_eq!(count, 1);
+        assert_eq!(
+            debug.as_ref(),
+            b"function hit(e)\n assert(e.hp>0, \"dead enemy hit\")\n e.hp-=1\nend"
+        );
+        let (release, _) = expand_assertions(SRC, BuildProfile::Release);
+        assert_eq!(release.as_ref(), b"function hit(e)\n \n e.hp-=1\nend");
+
+        const LONG_BRACKETS: &[u8] =
+            b"--[[\n--#assert(false, \"commented out\")\n]]\ns=[==[\n --#assert(x)\n]==]\n--#assert(y)";
+        let (debug, count) = expand_assertions(LONG_BRACKETS, BuildProfile::Debug);
+        assert_eq!(count, 1);
+        assert_eq!(
+            debug.as_ref(),
+            b"--[[\n--#assert(false, \"commented out\")\n]]\ns=[==[\n --#assert(x)\n]==]\nassert(y)"
+        );
+    }
+}
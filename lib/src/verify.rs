@@ -0,0 +1,141 @@
+//! Round-tripping the carts of a directory, for `pico-build-rs verify`
+//!
+//! Each `.p8` file below the directory is parsed and written back, which must reproduce
+//! it byte for byte. A cart which does not is reported with the lines around the first
+//! difference
+
+use core::fmt;
+
+use std::fs;
+use std::io;
+use std::path;
+
+use pico_8_cart_model::{CartData, CartDataError};
+
+use crate::paths;
+
+/// The lines shown before the first differing line
+const CONTEXT_LINES: usize = 2;
+
+/// Where the written cart first differs from the original one
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Divergence {
+    /// The first differing line, counted from one
+    pub line_number: usize,
+    /// The lines of the original cart, from the context up to the differing line
+    pub original: Vec<String>,
+    /// The lines of the written cart, from the context up to the differing line
+    pub written: Vec<String>,
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let first_line = self.line_number - CONTEXT_LINES.min(self.line_number - 1);
+        for (marker, lines) in [("-", &self.original), ("+", &self.written)] {
+            for (index, line) in lines.iter().enumerate() {
+                writeln!(f, "{marker}{:>5} | {line}", first_line + index)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Returns where the written cart first differs from the original, `None` if it does not
+pub fn first_divergence(original: &[u8], written: &[u8]) -> Option<Divergence> {
+    let original_lines: Vec<&[u8]> = bytes::NewlineIter::new(original).collect();
+    let written_lines: Vec<&[u8]> = bytes::NewlineIter::new(written).collect();
+    let line_index = (0..original_lines.len().max(written_lines.len()))
+        .find(|index| original_lines.get(*index) != written_lines.get(*index))?;
+    let context = |lines: &[&[u8]]| -> Vec<String> {
+        lines
+            .iter()
+            .take(line_index + 1)
+            .skip(line_index.saturating_sub(CONTEXT_LINES))
+            .map(|line| String::from_utf8_lossy(line).trim_end().to_string())
+            .collect()
+    };
+    Some(Divergence {
+        line_number: line_index + 1,
+        original: context(&original_lines),
+        written: context(&written_lines),
+    })
+}
+
+#[derive(Debug)]
+pub enum Verdict {
+    /// Written back byte for byte
+    Pass,
+    /// Written back with a difference
+    Diverged(Divergence),
+    /// Not parsed or written at all
+    Failed(CartDataError),
+}
+
+/// Parses the cart-source and writes it back, comparing the two
+pub fn verify_cart(cart_source: &[u8]) -> Verdict {
+    let cart = match CartData::from_bytes(cart_source) {
+        Ok(cart) => cart,
+        Err(e) => return Verdict::Failed(e),
+    };
+    let mut written = Vec::with_capacity(cart_source.len());
+    if let Err(e) = cart.write_to(&mut written) {
+        return Verdict::Failed(e.into());
+    }
+    match first_divergence(cart_source, &written) {
+        Some(divergence) => Verdict::Diverged(divergence),
+        None => Verdict::Pass,
+    }
+}
+
+/// The `.p8` files of the directory and its sub-directories, sorted by path
+fn carts_below(directory: &path::Path) -> io::Result<Vec<path::PathBuf>> {
+    let mut carts = Vec::new();
+    for dir_entry in fs::read_dir(directory)?.filter_map(Result::ok) {
+        let path = dir_entry.path();
+        if path.is_dir() {
+            carts.extend(carts_below(&path)?);
+        } else if path.extension().is_some_and(|extension| extension == "p8") {
+            carts.push(path);
+        }
+    }
+    carts.sort();
+    Ok(carts)
+}
+
+/// Verifies each cart below the directory, in order of path
+#[tracing::instrument(level = "debug")]
+pub fn verify_dir(directory: &path::Path) -> io::Result<Vec<(path::PathBuf, Verdict)>> {
+    Ok(carts_below(directory)?
+        .into_iter()
+        .map(|cart_path| {
+            let verdict = match fs::read(&cart_path) {
+                Ok(cart_source) => verify_cart(&cart_source),
+                Err(e) => Verdict::Failed(e.into()),
+            };
+            tracing::debug!("{}: {verdict:?}", paths::display(&cart_path));
+            (cart_path, verdict)
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_first_divergence() {
+        const CART: &[u8] = b"pico-8 cartridge // http://www.pico-8.com\nversion 43\n\
+            __lua__\nx=1\n-->8\ny=2\n__gfx__\n00\n";
+        assert!(matches!(verify_cart(CART), Verdict::Pass));
+        assert!(matches!(verify_cart(b"not a cart"), Verdict::Failed(_)));
+
+        let written: Vec<u8> = CART
+            .iter()
+            .map(|byte| if *byte == b'2' { b'3' } else { *byte })
+            .collect();
+        let divergence = first_divergence(CART, &written).unwrap();
+        assert_eq!(divergence.line_number, 6);
+        assert_eq!(divergence.original, ["x=1", "-->8", "y=2"]);
+        assert_eq!(divergence.written, ["x=1", "-->8", "y=3"]);
+    }
+}
@@ -0,0 +1,793 @@
+//! Build-time constants, substituted for their names in the source-files
+//!
+//! A constant is defined on a line of its own as `--[[const]] name = value`, or in the
+//! `[constants]` section of the config-file. The value is a literal, e.g. `3`, `"hi"` or
+//! `true`, or arithmetic of numbers and the constants defined before it. Without the
+//! build the line still runs, as the assignment of a global behind a comment
+//!
+//! The directive-lines are removed, keeping their line-break so line-numbers are
+//! unchanged, and each name of a constant read in the code is replaced by its value.
+//! Arithmetic of numbers and constants alone, `+`, `-`, `*`, `/`, `\` and `%`, is folded
+//! into a number where the operators around it bind less tightly. It is computed in the
+//! 16.16 fixed-point numbers of pico-8, operations which pico-8 would round or overflow
+//! are kept as written
+//!
+//! Names are replaced regardless of scopes, so a local or parameter named as a constant
+//! is replaced as well, and fails the build with a syntax-error. The names of constants
+//! are best kept apart, e.g. upper-case
+
+use core::fmt;
+use core::ops::Range;
+
+use std::borrow::Cow;
+use std::collections::{BTreeMap, BTreeSet};
+use std::path;
+
+use crate::lex::{self, KEYWORDS};
+
+/// Opens a definition, followed by the name and value of the constant
+pub const CONST_DIRECTIVE: &[u8] = b"--[[const]]";
+
+/// The symbols of more than one character, longest first
+const SYMBOLS: &[&str] = &[
+    ">>>=", "<<>=", ">><=", "...", "..=", ">>>", "<<>", ">><", ">>=", "<<=", "^^=", "..", "==",
+    "~=", "!=", "<=", ">=", "<<", ">>", "^^", "::", "+=", "-=", "*=", "/=", "\\=", "%=", "^=",
+    "|=", "&=",
+];
+
+/// How tightly the unary operators bind, e.g. `-` in `-x`
+const UNARY: u8 = 11;
+/// How tightly `^` binds, tighter than the unary operators
+const POWER: u8 = 12;
+/// How tightly a single operand binds
+const OPERAND: u8 = u8::MAX;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConstantError {
+    /// The name is not a name of lua, or is a keyword
+    InvalidName { name: String },
+    /// The directive is not of the form `--[[const]] name = value`
+    Malformed {
+        path: path::PathBuf,
+        line_number: usize,
+    },
+    /// The value is neither a literal, nor arithmetic of numbers and constants
+    InvalidValue {
+        path: path::PathBuf,
+        line_number: usize,
+        name: String,
+    },
+    /// The constant was defined before, with another value
+    Redefined {
+        path: path::PathBuf,
+        line_number: usize,
+        name: String,
+    },
+}
+
+impl fmt::Display for ConstantError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let description = "Constant error";
+        let reason = match self {
+            ConstantError::InvalidName { name } => format!("{name:?} is not a valid name"),
+            ConstantError::Malformed { path, line_number } => format!(
+                "{}:{line_number} is not of the form `--[[const]] name = value`",
+                path.display()
+            ),
+            ConstantError::InvalidValue {
+                path,
+                line_number,
+                name,
+            } => format!(
+                "the value of {name} at {}:{line_number} is neither a literal, nor arithmetic of numbers and constants",
+                path.display()
+            ),
+            ConstantError::Redefined {
+                path,
+                line_number,
+                name,
+            } => format!(
+                "{name} is defined again at {}:{line_number}, with another value",
+                path.display()
+            ),
+        };
+        f.write_fmt(format_args!("{description}: {reason}"))
+    }
+}
+
+impl core::error::Error for ConstantError {}
+
+impl ConstantError {
+    /// The stable diagnostic-code, see `pico-build-rs explain`
+    pub const fn code(&self) -> &'static str {
+        "E022"
+    }
+}
+
+/// The value of a constant
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ConstantValue {
+    /// A number, as the 16.16 fixed-point bits pico-8 computes with
+    Number(i32),
+    /// Any other literal as written in the code, e.g. `"hi"`, `nil`, or a number pico-8
+    /// rounds such as `0.1`
+    Literal(String),
+}
+
+impl ConstantValue {
+    pub fn number(number: f64) -> ConstantValue {
+        let bits = number * 65536.0;
+        if bits.fract() == 0.0 && (f64::from(i32::MIN)..=f64::from(i32::MAX)).contains(&bits) {
+            ConstantValue::Number(bits as i32)
+        } else {
+            ConstantValue::Literal(number.to_string())
+        }
+    }
+    pub fn string(string: &str) -> ConstantValue {
+        let escaped = string
+            .replace('\\', "\\\\")
+            .replace('"', "\\\"")
+            .replace('\n', "\\n");
+        ConstantValue::Literal(format!("\"{escaped}\""))
+    }
+    pub fn boolean(boolean: bool) -> ConstantValue {
+        ConstantValue::Literal(boolean.to_string())
+    }
+}
+
+impl fmt::Display for ConstantValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConstantValue::Number(bits) => f.write_str(&format_number(*bits)),
+            ConstantValue::Literal(literal) => f.write_str(literal),
+        }
+    }
+}
+
+/// Formats the number as pico-8 reads it back exactly, in hexadecimal if its decimal
+/// fraction would be longer than 4 digits
+fn format_number(bits: i32) -> String {
+    let sign = if bits < 0 { "-" } else { "" };
+    let magnitude = i64::from(bits).unsigned_abs();
+    let (integer, fraction) = (magnitude >> 16, magnitude & 0xffff);
+    if fraction == 0 {
+        return format!("{sign}{integer}");
+    }
+    // fraction / 2^16 = fraction * 5^16 / 10^16
+    let decimal = format!("{:016}", fraction * 5u64.pow(16));
+    let decimal = decimal.trim_end_matches('0');
+    if decimal.len() <= 4 {
+        return format!("{sign}{integer}.{decimal}");
+    }
+    let hexadecimal = format!("{fraction:04x}");
+    format!("{sign}0x{integer:x}.{}", hexadecimal.trim_end_matches('0'))
+}
+
+/// Parses the number-literal, `None` if pico-8 would round it or it is out of range
+fn parse_number(text: &str) -> Option<i32> {
+    let (digits, radix) = match text.get(..2) {
+        Some("0x" | "0X") => (&text[2..], 16),
+        Some("0b" | "0B") => (&text[2..], 2),
+        _ => (text, 10),
+    };
+    let (integer, fraction) = digits.split_once('.').unwrap_or((digits, ""));
+    if (integer.is_empty() && fraction.is_empty()) || fraction.len() > 20 {
+        return None;
+    }
+    let integer = match integer {
+        "" => 0,
+        integer => i64::from_str_radix(integer, radix).ok()?,
+    };
+    if integer > 0x7fff {
+        return None;
+    }
+    let (mut numerator, mut denominator) = (0u128, 1u128);
+    for digit in fraction.chars() {
+        numerator = numerator * u128::from(radix) + u128::from(digit.to_digit(radix)?);
+        denominator *= u128::from(radix);
+    }
+    let scaled = numerator << 16;
+    if scaled % denominator != 0 {
+        return None;
+    }
+    i32::try_from((integer << 16) + i64::try_from(scaled / denominator).ok()?).ok()
+}
+
+/// Applies the operator as pico-8 does, `None` if it would round or overflow
+fn apply(operator: &str, left: i32, right: i32) -> Option<i32> {
+    let (left, right) = (i64::from(left), i64::from(right));
+    let bits = match operator {
+        "+" => left + right,
+        "-" => left - right,
+        "*" => {
+            let product = left * right;
+            if product % 65536 != 0 {
+                return None;
+            }
+            product / 65536
+        }
+        "/" | "\\" => {
+            let dividend = left * 65536;
+            if right == 0 || dividend % right != 0 {
+                return None;
+            }
+            match operator {
+                "/" => dividend / right,
+                _ => (dividend / right).div_euclid(65536) * 65536,
+            }
+        }
+        "%" if right > 0 => left.rem_euclid(right),
+        _ => return None,
+    };
+    i32::try_from(bits).ok()
+}
+
+/// The precedence of the binary operator, higher binding tighter
+fn binary_precedence(text: &str) -> Option<u8> {
+    Some(match text {
+        "or" => 1,
+        "and" => 2,
+        "<" | ">" | "<=" | ">=" | "~=" | "!=" | "==" => 3,
+        "|" => 4,
+        "^^" => 5,
+        "&" => 6,
+        "<<" | ">>" | ">>>" | "<<>" | ">><" => 7,
+        ".." => 8,
+        "+" | "-" => 9,
+        "*" | "/" | "\\" | "%" => 10,
+        "^" => POWER,
+        _ => return None,
+    })
+}
+
+/// Whether the symbol assigns to the name before it, e.g. `=` or `+=`
+fn is_assignment(text: &str) -> bool {
+    text.ends_with('=') && !matches!(text, "==" | "~=" | "!=" | "<=" | ">=")
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum TokenKind {
+    Name,
+    Number,
+    String,
+    Symbol,
+    Comment,
+    Space,
+}
+
+#[derive(Clone, Debug)]
+struct Token {
+    kind: TokenKind,
+    range: Range<usize>,
+}
+
+/// The directive-lines of the source, by the range of the line without its line-break
+///
+/// A line starting with the directive inside a long comment or long string is none
+fn directive_lines(src: &[u8]) -> Vec<Range<usize>> {
+    let mut directive_lines = Vec::new();
+    let mut index = 0;
+    while index < src.len() {
+        if !(lex::starts_line(src, index) && src[index..].starts_with(CONST_DIRECTIVE)) {
+            index = lex::skip_non_code(src, index).unwrap_or(index + 1);
+            continue;
+        }
+        let line_start = src[..index]
+            .iter()
+            .rposition(|byte| *byte == b'\n')
+            .map_or(0, |newline_index| newline_index + 1);
+        let line_end = bytes::find_sequence(&src[index..], b"\n")
+            .map_or(src.len(), |newline_index| index + newline_index);
+        directive_lines.push(line_start..line_end);
+        index = line_end;
+    }
+    directive_lines
+}
+
+fn tokenize(src: &[u8]) -> Vec<Token> {
+    let is_name_byte = |byte: &u8| lex::is_name_byte(*byte);
+    let mut tokens = Vec::new();
+    let mut index = 0;
+    while index < src.len() {
+        let start = index;
+        let byte = src[index];
+        let kind = if byte.is_ascii_whitespace() {
+            index += src[index..]
+                .iter()
+                .take_while(|byte| byte.is_ascii_whitespace())
+                .count();
+            TokenKind::Space
+        } else if src[index..].starts_with(b"--") {
+            index = match src.get(index + 2) {
+                Some(b'[') => lex::skip_long_bracket(src, index + 2),
+                _ => None,
+            }
+            .unwrap_or_else(|| {
+                bytes::find_sequence(&src[index..], b"\n").map_or(src.len(), |end| index + end)
+            });
+            TokenKind::Comment
+        } else if byte == b'"' || byte == b'\'' {
+            index += 1;
+            while let Some(next) = src.get(index) {
+                index += match *next {
+                    b'\\' => 2,
+                    b'\n' => break,
+                    next if next == byte => {
+                        index += 1;
+                        break;
+                    }
+                    _ => 1,
+                };
+            }
+            index = index.min(src.len());
+            TokenKind::String
+        } else if byte == b'['
+            && let Some(end) = lex::skip_long_bracket(src, index)
+        {
+            index = end;
+            TokenKind::String
+        } else if byte.is_ascii_digit()
+            || (byte == b'.' && src.get(index + 1).is_some_and(u8::is_ascii_digit))
+        {
+            index += src[index..]
+                .iter()
+                .take_while(|byte| is_name_byte(byte) || **byte == b'.')
+                .count();
+            TokenKind::Number
+        } else if is_name_byte(&byte) {
+            index += src[index..]
+                .iter()
+                .take_while(|byte| is_name_byte(byte))
+                .count();
+            TokenKind::Name
+        } else {
+            index += SYMBOLS
+                .iter()
+                .find(|symbol| src[index..].starts_with(symbol.as_bytes()))
+                .map_or(1, |symbol| symbol.len());
+            TokenKind::Symbol
+        };
+        tokens.push(Token {
+            kind,
+            range: start..index,
+        });
+    }
+    tokens
+}
+
+/// A folded expression, ending before the code-token `next`
+struct Folded {
+    value: i32,
+    next: usize,
+    /// How tightly the loosest operator of the expression binds
+    binding: u8,
+    /// Whether anything was computed or substituted
+    folded: bool,
+}
+
+/// Folds the code-tokens of a source, the tokens other than spaces and comments
+struct Folder<'a> {
+    src: &'a [u8],
+    tokens: &'a [Token],
+    code: Vec<usize>,
+    constants: &'a Constants,
+}
+
+impl<'a> Folder<'a> {
+    fn new(src: &'a [u8], tokens: &'a [Token], constants: &'a Constants) -> Folder<'a> {
+        let code = (0..tokens.len())
+            .filter(|index| !matches!(tokens[*index].kind, TokenKind::Space | TokenKind::Comment))
+            .collect();
+        Folder {
+            src,
+            tokens,
+            code,
+            constants,
+        }
+    }
+    fn token(&self, position: usize) -> Option<&Token> {
+        Some(&self.tokens[*self.code.get(position)?])
+    }
+    fn text(&self, position: usize) -> Option<&'a str> {
+        let range = self.tokens[*self.code.get(position)?].range.clone();
+        core::str::from_utf8(&self.src[range]).ok()
+    }
+    /// Whether the code-token ends an operand, so a `-` following it is binary and a
+    /// `(` following it calls it
+    fn ends_operand(&self, position: usize) -> bool {
+        match (self.token(position), self.text(position)) {
+            (Some(token), Some(text)) => match token.kind {
+                TokenKind::Number | TokenKind::String => true,
+                TokenKind::Name => {
+                    !KEYWORDS.contains(&text) || matches!(text, "nil" | "true" | "false")
+                }
+                _ => matches!(text, ")" | "]" | "}" | "..."),
+            },
+            _ => false,
+        }
+    }
+    fn follows_operand(&self, position: usize) -> bool {
+        position
+            .checked_sub(1)
+            .is_some_and(|previous| self.ends_operand(previous))
+    }
+    /// The constant read by the name at the code-token, not one assigned to or a field
+    fn constant(&self, position: usize) -> Option<&'a ConstantValue> {
+        if self.token(position)?.kind != TokenKind::Name {
+            return None;
+        }
+        let value = self.constants.values.get(self.text(position)?)?;
+        let previous = position
+            .checked_sub(1)
+            .and_then(|previous| self.text(previous));
+        if matches!(
+            previous,
+            Some("." | ":" | "::" | "goto" | "local" | "function" | "for")
+        ) {
+            return None;
+        }
+        if let Some(next) = self.token(position + 1)
+            && let Some(text) = self.text(position + 1)
+            && (next.kind == TokenKind::String
+                || matches!(text, "." | ":" | "[" | "(" | "{")
+                || is_assignment(text))
+        {
+            return None;
+        }
+        Some(value)
+    }
+    fn operand(&self, position: usize) -> Option<Folded> {
+        let token = self.token(position)?;
+        let text = self.text(position)?;
+        let folded = |value, next, folded| Folded {
+            value,
+            next,
+            binding: OPERAND,
+            folded,
+        };
+        match (token.kind, text) {
+            (TokenKind::Number, _) => Some(folded(parse_number(text)?, position + 1, false)),
+            (TokenKind::Name, _) => match self.constant(position)? {
+                ConstantValue::Number(value) => Some(folded(*value, position + 1, true)),
+                ConstantValue::Literal(_) => None,
+            },
+            (TokenKind::Symbol, "-") if !self.follows_operand(position) => {
+                let negated = self.expression(position + 1, UNARY)?;
+                Some(Folded {
+                    value: negated.value.checked_neg()?,
+                    binding: UNARY,
+                    folded: true,
+                    ..negated
+                })
+            }
+            (TokenKind::Symbol, "(") if !self.follows_operand(position) => {
+                let inner = self.expression(position + 1, 0)?;
+                (self.text(inner.next)? == ")").then(|| folded(inner.value, inner.next + 1, true))
+            }
+            _ => None,
+        }
+    }
+    /// Folds the operators binding at least as tightly as `min_binding`, failing if any
+    /// of them can not be folded
+    fn expression(&self, position: usize, min_binding: u8) -> Option<Folded> {
+        let mut folded = self.operand(position)?;
+        while let Some(operator) = self.text(folded.next)
+            && matches!(operator, "+" | "-" | "*" | "/" | "\\" | "%")
+            && let Some(binding) = binary_precedence(operator)
+            && binding >= min_binding
+        {
+            let right = self.expression(folded.next + 1, binding + 1)?;
+            folded = Folded {
+                value: apply(operator, folded.value, right.value)?,
+                next: right.next,
+                binding: folded.binding.min(binding).min(right.binding),
+                folded: true,
+            };
+        }
+        Some(folded)
+    }
+    /// How tightly the operator before the code-token binds
+    fn left_binding(&self, position: usize) -> u8 {
+        let Some(previous) = position.checked_sub(1) else {
+            return 0;
+        };
+        match self.text(previous) {
+            Some("not" | "#" | "@" | "$" | "~") => UNARY,
+            Some("-" | "%") if !self.follows_operand(previous) => UNARY,
+            Some(text) => binary_precedence(text).unwrap_or(0),
+            None => 0,
+        }
+    }
+    /// How tightly the operator at the code-token binds
+    fn right_binding(&self, position: usize) -> u8 {
+        self.text(position).and_then(binary_precedence).unwrap_or(0)
+    }
+    /// The value as written between the operators, in parentheses if a negative number
+    /// would be read differently
+    fn write_value(&self, value: &ConstantValue, position: usize, next: usize) -> String {
+        let text = value.to_string();
+        match text.starts_with('-')
+            && (self.left_binding(position) > 0 || self.right_binding(next) >= POWER)
+        {
+            true => format!("({text})"),
+            false => text,
+        }
+    }
+    /// The code replacing the code-tokens from the position, and the code-token
+    /// following them
+    fn replacement(&self, position: usize) -> Option<(String, usize)> {
+        // A `(` after a keyword is kept, for the shorthands `if (cond) stmt` and `while`
+        let keeps_parenthesis = self.text(position) == Some("(")
+            && position.checked_sub(1).is_some_and(|previous| {
+                self.token(previous)
+                    .is_some_and(|token| token.kind == TokenKind::Name)
+            });
+        if !keeps_parenthesis
+            && let Some(folded) = self.expression(position, 0)
+            && folded.folded
+            && folded.binding > self.left_binding(position)
+            && folded.binding >= self.right_binding(folded.next)
+        {
+            let value = ConstantValue::Number(folded.value);
+            return Some((self.write_value(&value, position, folded.next), folded.next));
+        }
+        let value = self.constant(position)?;
+        Some((
+            self.write_value(value, position, position + 1),
+            position + 1,
+        ))
+    }
+}
+
+/// The constants substituted into the source-files, see the [module-documentation](self)
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Constants {
+    values: BTreeMap<String, ConstantValue>,
+    /// The constants of the config-file, which take precedence over the directives
+    configured: BTreeSet<String>,
+}
+
+impl Constants {
+    pub fn new() -> Constants {
+        Constants::default()
+    }
+    /// Defines the constant, taking precedence over a directive of the same name
+    pub fn with_constant<S: Into<String>>(
+        mut self,
+        name: S,
+        value: ConstantValue,
+    ) -> Result<Constants, ConstantError> {
+        let name = name.into();
+        let is_name = name.starts_with(|char: char| char.is_ascii_alphabetic() || char == '_')
+            && name
+                .chars()
+                .all(|char| char.is_ascii_alphanumeric() || char == '_');
+        if !is_name || KEYWORDS.contains(&name.as_str()) {
+            return Err(ConstantError::InvalidName { name });
+        }
+        self.configured.insert(name.clone());
+        self.values.insert(name, value);
+        Ok(self)
+    }
+    pub fn get(&self, name: &str) -> Option<&ConstantValue> {
+        self.values.get(name)
+    }
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+    /// Defines the constants of the directive-lines of the source-file
+    ///
+    /// Returns the count of directive-lines
+    #[tracing::instrument(level = "debug", skip(self, src))]
+    pub fn define_from_source(
+        &mut self,
+        path: &path::Path,
+        src: &[u8],
+    ) -> Result<usize, ConstantError> {
+        let mut found = 0;
+        for line in directive_lines(src) {
+            let Some(definition) = src[line.clone()]
+                .trim_ascii_start()
+                .strip_prefix(CONST_DIRECTIVE)
+            else {
+                continue;
+            };
+            found += 1;
+            let line_number = src[..line.start]
+                .iter()
+                .filter(|byte| **byte == b'\n')
+                .count()
+                + 1;
+            let tokens = tokenize(definition);
+            let folder = Folder::new(definition, &tokens, self);
+            let offset = usize::from(folder.text(0) == Some("local"));
+            let (Some(name), Some("=")) = (folder.token(offset), folder.text(offset + 1)) else {
+                return Err(ConstantError::Malformed {
+                    path: path.to_path_buf(),
+                    line_number,
+                });
+            };
+            let name_text = folder.text(offset).unwrap_or_default().to_string();
+            if name.kind != TokenKind::Name || KEYWORDS.contains(&name_text.as_str()) {
+                return Err(ConstantError::Malformed {
+                    path: path.to_path_buf(),
+                    line_number,
+                });
+            }
+            let value_start = offset + 2;
+            let value_tokens = &folder.code[value_start.min(folder.code.len())..];
+            let literal = match value_tokens {
+                [token] => Some(&tokens[*token]),
+                // A negative number pico-8 rounds, e.g. `-0.1`
+                [minus, token] if folder.text(value_start) == Some("-") => Some(&tokens[*token])
+                    .filter(|token| token.kind == TokenKind::Number)
+                    .filter(|token| tokens[*minus].range.end == token.range.start),
+                _ => None,
+            };
+            let value = match folder.expression(value_start, 0) {
+                Some(folded) if folded.next == folder.code.len() => {
+                    Some(ConstantValue::Number(folded.value))
+                }
+                _ => match literal {
+                    Some(token) if token.kind == TokenKind::Name => {
+                        folder.constant(value_start).cloned().or_else(|| {
+                            let text = folder.text(value_start)?;
+                            matches!(text, "nil" | "true" | "false")
+                                .then(|| ConstantValue::Literal(text.to_string()))
+                        })
+                    }
+                    Some(token) if matches!(token.kind, TokenKind::Number | TokenKind::String) => {
+                        let start = tokens[value_tokens[0]].range.start;
+                        core::str::from_utf8(&definition[start..token.range.end])
+                            .ok()
+                            .map(|text| ConstantValue::Literal(text.to_string()))
+                    }
+                    _ => None,
+                },
+            };
+            let Some(value) = value else {
+                return Err(ConstantError::InvalidValue {
+                    path: path.to_path_buf(),
+                    line_number,
+                    name: name_text,
+                });
+            };
+            if self.configured.contains(&name_text) {
+                continue;
+            }
+            match self.values.get(&name_text) {
+                Some(defined) if *defined != value => {
+                    return Err(ConstantError::Redefined {
+                        path: path.to_path_buf(),
+                        line_number,
+                        name: name_text,
+                    });
+                }
+                _ => {
+                    self.values.insert(name_text, value);
+                }
+            }
+        }
+        Ok(found)
+    }
+    /// Removes the directive-lines and substitutes the constants into the source, see
+    /// the [module-documentation](self)
+    ///
+    /// Returns the substituted source, and the count of directive-lines removed, names
+    /// substituted and expressions folded
+    pub fn substitute<'a>(&self, src: &'a [u8]) -> (Cow<'a, [u8]>, usize) {
+        if self.values.is_empty() {
+            return (Cow::Borrowed(src), 0);
+        }
+        let directive_lines = directive_lines(src);
+        let mut found = directive_lines.len();
+        let src: Cow<'a, [u8]> = match directive_lines.is_empty() {
+            true => Cow::Borrowed(src),
+            // Keeping the line-break of each directive-line
+            false => {
+                let mut without_directives = Vec::with_capacity(src.len());
+                let mut copied_until = 0;
+                for line in directive_lines {
+                    without_directives.extend_from_slice(&src[copied_until..line.start]);
+                    copied_until = line.end;
+                }
+                without_directives.extend_from_slice(&src[copied_until..]);
+                Cow::Owned(without_directives)
+            }
+        };
+
+        let tokens = tokenize(&src);
+        let folder = Folder::new(&src, &tokens, self);
+        let mut output: Option<Vec<u8>> = None;
+        let mut copied_until = 0;
+        let mut position = 0;
+        while position < folder.code.len() {
+            let Some((code, next)) = folder.replacement(position) else {
+                position += 1;
+                continue;
+            };
+            let range =
+                tokens[folder.code[position]].range.start..tokens[folder.code[next - 1]].range.end;
+            position = next;
+            let replaced = &src[range.clone()];
+            if replaced == code.as_bytes() {
+                continue;
+            }
+            let output = output.get_or_insert_with(|| Vec::with_capacity(src.len()));
+            output.extend_from_slice(&src[copied_until..range.start]);
+            output.extend_from_slice(code.as_bytes());
+            // e.g. `8..s` would be read as a malformed number
+            if src.get(range.end) == Some(&b'.') {
+                output.push(b' ');
+            }
+            output.extend(replaced.iter().filter(|byte| **byte == b'\n'));
+            copied_until = range.end;
+            found += 1;
+        }
+        match output {
+            Some(mut output) => {
+                output.extend_from_slice(&src[copied_until..]);
+                (Cow::Owned(output), found)
+            }
+            None => (src, found),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn substitutes_and_folds() {
+        let mut constants = Constants::new()
+            .with_constant("DEBUG", ConstantValue::boolean(false))
+            .unwrap();
+        let src = b"--[[const]] W = 8\n--[[const]] local H = W * 2 -- px\n--[[const]] DEBUG = true\n--[[const]] NAME = \"cat\"\n--[[const]] STEP = -0.1\nx = W*H + 1 y = a - W*3\nz = (H - W) / 4 .. NAME\nif (W) printh(W..DEBUG, t.W)\nw = 2 * W ^ 2 - STEP W = 1\nh = 0x10 + 1/4 k = 1/3\n";
+        assert_eq!(
+            constants.define_from_source(path::Path::new("main.lua"), src),
+            Ok(5)
+        );
+        assert_eq!(constants.get("H"), Some(&ConstantValue::Number(16 << 16)));
+        assert_eq!(
+            constants.get("DEBUG"),
+            Some(&ConstantValue::Literal("false".to_string()))
+        );
+        let (substituted, count) = constants.substitute(src);
+        assert_eq!(
+            core::str::from_utf8(&substituted).unwrap(),
+            "\n\n\n\n\nx = 129 y = a - 24\nz = 2 .. \"cat\"\nif (8) printh(8 ..false, t.W)\nw = 2 * 8 ^ 2 - (-0.1) W = 1\nh = 16.25 k = 1/3\n"
+        );
+        assert_eq!(count, 5 + 10);
+
+        let mut redefined = constants.clone();
+        assert_eq!(
+            redefined
+                .define_from_source(path::Path::new("player.lua"), b"--[[const]] W = 9")
+                .unwrap_err()
+                .code(),
+            "E022"
+        );
+    }
+
+    #[test]
+    fn skips_directives_in_long_brackets() {
+        let src = b"s = [=[\n--[[const]] A = 1\n]=]\n--[==[\n  --[[const]] B = 2\n]==]\n--[[const]] C = 3\nx = C\n";
+        let mut constants = Constants::new();
+        assert_eq!(
+            constants.define_from_source(path::Path::new("main.lua"), src),
+            Ok(1)
+        );
+        assert_eq!(constants.get("A"), None);
+        assert_eq!(constants.get("B"), None);
+        let (substituted, count) = constants.substitute(src);
+        assert_eq!(
+            core::str::from_utf8(&substituted).unwrap(),
+            "s = [=[\n--[[const]] A = 1\n]=]\n--[==[\n  --[[const]] B = 2\n]==]\n\nx = 3\n"
+        );
+        assert_eq!(count, 1 + 1);
+    }
+}
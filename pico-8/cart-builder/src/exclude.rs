@@ -0,0 +1,106 @@
+//! Excluding source-files from discovery, such as generated or test-only lua-files
+//!
+//! The patterns are globs over the path relative to the source-directory, `/`-separated
+//! on all platforms: `*` matches within a path-component, `?` a single character, and a
+//! `**` component any number of components, e.g. `**/_*.lua` or `tests/**`
+
+use std::fs;
+use std::io;
+use std::path;
+
+/// Lists patterns to exclude, one per line, in the source-directory
+pub const IGNORE_FILE_NAME: &str = ".picoignore";
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SourceExclude {
+    patterns: Vec<String>,
+}
+
+impl SourceExclude {
+    pub fn new<I: IntoIterator<Item = S>, S: Into<String>>(patterns: I) -> SourceExclude {
+        SourceExclude {
+            patterns: patterns.into_iter().map(Into::into).collect(),
+        }
+    }
+    /// Adds the patterns of the [`IGNORE_FILE_NAME`] in the source-directory, if there is one
+    ///
+    /// Blank lines, and lines starting with `#`, are skipped
+    pub fn with_ignore_file<P: AsRef<path::Path> + ?Sized>(
+        mut self,
+        src_dir: &P,
+    ) -> io::Result<SourceExclude> {
+        let text = match fs::read_to_string(src_dir.as_ref().join(IGNORE_FILE_NAME)) {
+            Ok(text) => text,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(self),
+            Err(e) => return Err(e),
+        };
+        self.patterns.extend(
+            text.lines()
+                .map(str::trim)
+                .filter(|line| !line.is_empty() && !line.starts_with('#'))
+                .map(ToString::to_string),
+        );
+        Ok(self)
+    }
+    pub fn patterns(&self) -> &[String] {
+        &self.patterns
+    }
+    /// Whether the path, relative to the source-directory, matches one of the patterns
+    pub fn is_excluded<P: AsRef<path::Path> + ?Sized>(&self, relative_path: &P) -> bool {
+        let components: Vec<String> = relative_path
+            .as_ref()
+            .components()
+            .map(|component| component.as_os_str().to_string_lossy().into_owned())
+            .collect();
+        let components: Vec<&str> = components.iter().map(String::as_str).collect();
+        self.patterns.iter().any(|pattern| {
+            let pattern: Vec<&str> = pattern.split('/').filter(|c| !c.is_empty()).collect();
+            matches_components(&pattern, &components)
+        })
+    }
+}
+
+fn matches_components(pattern: &[&str], components: &[&str]) -> bool {
+    match pattern.split_first() {
+        None => components.is_empty(),
+        Some((&"**", rest)) => {
+            (0..=components.len()).any(|skipped| matches_components(rest, &components[skipped..]))
+        }
+        Some((first, rest)) => components.split_first().is_some_and(|(component, others)| {
+            matches_component(first.as_bytes(), component.as_bytes())
+                && matches_components(rest, others)
+        }),
+    }
+}
+
+fn matches_component(pattern: &[u8], component: &[u8]) -> bool {
+    match pattern.split_first() {
+        None => component.is_empty(),
+        Some((b'*', rest)) => {
+            (0..=component.len()).any(|skipped| matches_component(rest, &component[skipped..]))
+        }
+        Some((b'?', rest)) => !component.is_empty() && matches_component(rest, &component[1..]),
+        Some((byte, rest)) => {
+            component.first() == Some(byte) && matches_component(rest, &component[1..])
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_globs() {
+        let exclude = SourceExclude::new(["**/_*.lua", "tests/**", "gen?.lua"]);
+        assert!(exclude.is_excluded("_generated.lua"));
+        assert!(exclude.is_excluded("lib/_generated.lua"));
+        assert!(exclude.is_excluded("tests/player_test.lua"));
+        assert!(exclude.is_excluded("tests/unit/enemy.lua"));
+        assert!(exclude.is_excluded("gen1.lua"));
+        assert!(!exclude.is_excluded("gen10.lua"));
+        assert!(!exclude.is_excluded("main.lua"));
+        assert!(!exclude.is_excluded("lib/util.lua"));
+        assert!(!exclude.is_excluded("tests.lua"));
+    }
+}
@@ -0,0 +1,331 @@
+//! Resolution of `#include` directives, inlining the included files
+//!
+//! pico-8 resolves `#include` from the local filesystem when loading a cart, so the
+//! builder inlines them instead. An included file is looked up relative to the
+//! including file first, then in each include-path in order
+
+use core::fmt;
+
+use std::borrow::Cow;
+use std::fs;
+use std::io;
+use std::path;
+
+/// The directive, followed by the path of the included file
+pub const INCLUDE_DIRECTIVE: &[u8] = b"#include";
+
+#[derive(Debug)]
+pub enum IncludeError {
+    /// The included file was found neither next to the including file,
+    /// nor in any of the include-paths
+    NotFound {
+        path: path::PathBuf,
+        line_number: usize,
+        included: String,
+    },
+    /// A file includes itself, directly or through other files
+    Cycle { chain: Vec<path::PathBuf> },
+    /// The included file is outside the root-directory, see [`IncludeResolver::with_root`]
+    OutsideRoot {
+        path: path::PathBuf,
+        line_number: usize,
+        included: String,
+    },
+    Io {
+        path: path::PathBuf,
+        error: io::Error,
+    },
+}
+
+impl fmt::Display for IncludeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let description = "Include error";
+        let reason = match self {
+            IncludeError::NotFound {
+                path,
+                line_number,
+                included,
+            } => format!(
+                "{included:?} included at {}:{line_number} was not found",
+                path.display()
+            ),
+            IncludeError::Cycle { chain } => {
+                let chain: Vec<_> = chain
+                    .iter()
+                    .map(|path| path.display().to_string())
+                    .collect();
+                format!("include-cycle {}", chain.join(" -> "))
+            }
+            IncludeError::OutsideRoot {
+                path,
+                line_number,
+                included,
+            } => format!(
+                "{included:?} included at {}:{line_number} is outside the project-directory",
+                path.display()
+            ),
+            IncludeError::Io { path, error } => {
+                format!("failed to read {}: {error}", path.display())
+            }
+        };
+        f.write_fmt(format_args!("{description}: {reason}"))
+    }
+}
+
+impl core::error::Error for IncludeError {}
+
+impl IncludeError {
+    /// The stable diagnostic-code, see `pico-build-rs explain`
+    pub const fn code(&self) -> &'static str {
+        "E011"
+    }
+}
+
+/// Returns the included path, if the line is an `#include`-directive
+fn included_path(line: &[u8]) -> Option<&[u8]> {
+    let argument = line.trim_ascii_start().strip_prefix(INCLUDE_DIRECTIVE)?;
+    let included = argument.trim_ascii();
+    (argument.first().is_some_and(u8::is_ascii_whitespace) && !included.is_empty())
+        .then_some(included)
+}
+
+/// The file and line, from 1, of each line of a resolved source
+pub type LineOrigins = Vec<(path::PathBuf, usize)>;
+
+/// Inlines `#include`-directives, see the [module-documentation](self)
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct IncludeResolver {
+    include_paths: Vec<path::PathBuf>,
+    /// Files outside the root are refused, for projects which are not trusted
+    root: Option<path::PathBuf>,
+}
+
+impl IncludeResolver {
+    /// Looks up included files in the directories, after the directory of the including file
+    pub fn new<I: IntoIterator<Item = P>, P: Into<path::PathBuf>>(
+        include_paths: I,
+    ) -> IncludeResolver {
+        IncludeResolver {
+            include_paths: include_paths.into_iter().map(Into::into).collect(),
+            root: None,
+        }
+    }
+    /// Refuses to include files outside the directory
+    pub fn with_root<P: AsRef<path::Path> + ?Sized>(self, root: &P) -> IncludeResolver {
+        let root = root.as_ref();
+        IncludeResolver {
+            root: Some(fs::canonicalize(root).unwrap_or_else(|_| root.to_path_buf())),
+            ..self
+        }
+    }
+    pub fn include_paths(&self) -> &[path::PathBuf] {
+        &self.include_paths
+    }
+    /// Inlines the included files of the source-file at the path, recursively
+    ///
+    /// The source is returned as is if it has no `#include`-directives
+    #[tracing::instrument(level = "debug", skip(self, src))]
+    pub fn resolve<'a, P: AsRef<path::Path> + ?Sized + fmt::Debug>(
+        &self,
+        path: &P,
+        src: &'a [u8],
+    ) -> Result<Cow<'a, [u8]>, IncludeError> {
+        let has_includes = src
+            .split(|byte| *byte == b'\n')
+            .any(|line| included_path(line).is_some());
+        if !has_includes {
+            return Ok(Cow::Borrowed(src));
+        }
+        let path = path.as_ref();
+        let mut chain = vec![fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf())];
+        let mut resolved = Vec::with_capacity(src.len());
+        self.resolve_into(path, src, &mut chain, &mut resolved, None)?;
+        Ok(Cow::Owned(resolved))
+    }
+    /// Like [`IncludeResolver::resolve`], also returning the file and line, from 1,
+    /// each line of the resolved source came from
+    #[tracing::instrument(level = "debug", skip(self, src))]
+    pub fn resolve_with_origins<'a, P: AsRef<path::Path> + ?Sized + fmt::Debug>(
+        &self,
+        path: &P,
+        src: &'a [u8],
+    ) -> Result<(Cow<'a, [u8]>, LineOrigins), IncludeError> {
+        let path = path.as_ref();
+        let mut chain = vec![fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf())];
+        let mut resolved = Vec::with_capacity(src.len());
+        let mut origins = Vec::new();
+        self.resolve_into(path, src, &mut chain, &mut resolved, Some(&mut origins))?;
+        match resolved == src {
+            true => Ok((Cow::Borrowed(src), origins)),
+            false => Ok((Cow::Owned(resolved), origins)),
+        }
+    }
+    fn resolve_into(
+        &self,
+        path: &path::Path,
+        src: &[u8],
+        chain: &mut Vec<path::PathBuf>,
+        resolved: &mut Vec<u8>,
+        mut origins: Option<&mut LineOrigins>,
+    ) -> Result<(), IncludeError> {
+        for (line_index, line) in src.split_inclusive(|byte| *byte == b'\n').enumerate() {
+            let Some(included) = included_path(line) else {
+                resolved.extend_from_slice(line);
+                if let Some(origins) = origins.as_deref_mut() {
+                    origins.push((path.to_path_buf(), line_index + 1));
+                }
+                continue;
+            };
+            let included = String::from_utf8_lossy(included);
+            let included_path =
+                self.find(path, &included)
+                    .ok_or_else(|| IncludeError::NotFound {
+                        path: path.to_path_buf(),
+                        line_number: line_index + 1,
+                        included: included.to_string(),
+                    })?;
+            let canonical_path =
+                fs::canonicalize(&included_path).unwrap_or_else(|_| included_path.clone());
+            if !self.is_within_root(&canonical_path) {
+                return Err(IncludeError::OutsideRoot {
+                    path: path.to_path_buf(),
+                    line_number: line_index + 1,
+                    included: included.to_string(),
+                });
+            }
+            if chain.contains(&canonical_path) {
+                chain.push(canonical_path);
+                return Err(IncludeError::Cycle {
+                    chain: core::mem::take(chain),
+                });
+            }
+            let included_src = fs::read(&included_path).map_err(|error| IncludeError::Io {
+                path: included_path.clone(),
+                error,
+            })?;
+            tracing::debug!("Inlining {included_path:?} into {path:?}");
+            chain.push(canonical_path);
+            self.resolve_into(
+                &included_path,
+                &included_src,
+                chain,
+                resolved,
+                origins.as_deref_mut(),
+            )?;
+            chain.pop();
+            if !resolved.ends_with(b"\n") {
+                // Only starts a line of its own if nothing was included yet
+                if resolved.is_empty()
+                    && let Some(origins) = origins.as_deref_mut()
+                {
+                    origins.push((path.to_path_buf(), line_index + 1));
+                }
+                resolved.push(b'\n');
+            }
+        }
+        Ok(())
+    }
+    /// The files included by the source-file at the path, recursively, each once
+    ///
+    /// Includes which are not found are skipped, as they are reported when compiling
+    pub fn included_paths<P: AsRef<path::Path> + ?Sized>(
+        &self,
+        path: &P,
+        src: &[u8],
+    ) -> Vec<path::PathBuf> {
+        let mut included_paths = Vec::new();
+        let mut pending = vec![(path.as_ref().to_path_buf(), Cow::Borrowed(src))];
+        while let Some((path, src)) = pending.pop() {
+            for canonical_path in self.direct_includes(&path, &src) {
+                if included_paths.contains(&canonical_path) {
+                    continue;
+                }
+                if let Ok(included_src) = fs::read(&canonical_path) {
+                    pending.push((canonical_path.clone(), Cow::Owned(included_src)));
+                }
+                included_paths.push(canonical_path);
+            }
+        }
+        included_paths
+    }
+    /// The files included by the source-file at the path itself, in order, without
+    /// those they include in turn
+    ///
+    /// Includes which are not found are skipped, as they are reported when compiling
+    pub fn direct_includes<P: AsRef<path::Path> + ?Sized>(
+        &self,
+        path: &P,
+        src: &[u8],
+    ) -> Vec<path::PathBuf> {
+        src.split(|byte| *byte == b'\n')
+            .filter_map(included_path)
+            .filter_map(|included| self.find(path.as_ref(), &String::from_utf8_lossy(included)))
+            .map(|found| fs::canonicalize(&found).unwrap_or(found))
+            .filter(|canonical_path| self.is_within_root(canonical_path))
+            .collect()
+    }
+    fn is_within_root(&self, canonical_path: &path::Path) -> bool {
+        self.root
+            .as_ref()
+            .is_none_or(|root| canonical_path.starts_with(root))
+    }
+    /// The path of the included file, if it exists
+    fn find(&self, including_path: &path::Path, included: &str) -> Option<path::PathBuf> {
+        including_path
+            .parent()
+            .into_iter()
+            .chain(self.include_paths.iter().map(path::PathBuf::as_path))
+            .map(|directory| directory.join(included))
+            .find(|path| path.is_file())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn inlines_and_detects_cycles() {
+        let root = std::env::temp_dir().join(format!("pico-build-include-{}", std::process::id()));
+        let (src_dir, lib_dir) = (root.join("src"), root.join("lib"));
+        fs::create_dir_all(&src_dir).unwrap();
+        fs::create_dir_all(&lib_dir).unwrap();
+        fs::write(
+            lib_dir.join("vec.lua"),
+            "function v2(x,y) return {x=x,y=y} end",
+        )
+        .unwrap();
+        fs::write(lib_dir.join("a.lua"), "#include b.lua\n").unwrap();
+        fs::write(lib_dir.join("b.lua"), "#include a.lua\n").unwrap();
+
+        let resolver = IncludeResolver::new([&lib_dir]);
+        let main_path = src_dir.join("main.lua");
+        let resolved = resolver
+            .resolve(&main_path, b"#include vec.lua\np=v2(1,2)\n")
+            .unwrap();
+        assert_eq!(
+            resolved.as_ref(),
+            b"function v2(x,y) return {x=x,y=y} end\np=v2(1,2)\n"
+        );
+        let (_, origins) = resolver
+            .resolve_with_origins(&main_path, b"#include vec.lua\np=v2(1,2)\n")
+            .unwrap();
+        assert_eq!(
+            origins,
+            [(lib_dir.join("vec.lua"), 1), (main_path.clone(), 2)]
+        );
+        assert!(matches!(
+            resolver.resolve(&main_path, b"-- #include vec.lua\n"),
+            Ok(Cow::Borrowed(_))
+        ));
+        assert!(matches!(
+            resolver.resolve(&main_path, b"#include a.lua\n"),
+            Err(IncludeError::Cycle { chain }) if chain.len() == 4
+        ));
+        assert!(matches!(
+            resolver.resolve(&main_path, b"x=1\n #include missing.lua\n"),
+            Err(IncludeError::NotFound { line_number: 2, .. })
+        ));
+        fs::remove_dir_all(root).unwrap();
+    }
+}
@@ -0,0 +1,108 @@
+//! The lexical primitives of the lua of pico-8, shared by the passes over the source
+//!
+//! A pass rewriting a source-file, e.g. substituting [`crate::constants`], has to tell
+//! code from comments and string-literals without parsing it. The index returned by each
+//! `skip_*` function follows the comment or literal, or is the end of the source if it
+//! is not closed
+
+/// The keywords of lua, which are no names
+pub const KEYWORDS: &[&str] = &[
+    "and", "break", "do", "else", "elseif", "end", "false", "for", "function", "goto", "if", "in",
+    "local", "nil", "not", "or", "repeat", "return", "then", "true", "until", "while",
+];
+
+/// Whether the byte can be part of a name, or of a number
+pub const fn is_name_byte(byte: u8) -> bool {
+    byte.is_ascii_alphanumeric() || byte == b'_'
+}
+
+/// The level of the long-bracket opening at `start`, e.g. 2 for `[==[`
+pub fn long_bracket_level(src: &[u8], start: usize) -> Option<usize> {
+    let level = src[start + 1..]
+        .iter()
+        .take_while(|byte| **byte == b'=')
+        .count();
+    (src.get(start + 1 + level) == Some(&b'[')).then_some(level)
+}
+
+/// Returns the index following a long-bracket, e.g. `[[...]]` or `[==[...]==]`,
+/// if one opens at `start`
+pub fn skip_long_bracket(src: &[u8], start: usize) -> Option<usize> {
+    let level = long_bracket_level(src, start)?;
+    let mut close = vec![b']'];
+    close.extend(core::iter::repeat_n(b'=', level));
+    close.push(b']');
+    let body_start = start + level + 2;
+    Some(
+        bytes::find_sequence(&src[body_start..], &close).map_or(src.len(), |close_index| {
+            body_start + close_index + close.len()
+        }),
+    )
+}
+
+/// Returns the index following the string-literal opening at `start`
+pub fn skip_quoted(src: &[u8], start: usize) -> usize {
+    let quote = src[start];
+    let mut index = start + 1;
+    while let Some(byte) = src.get(index) {
+        match *byte {
+            b'\\' => index += 2,
+            b'\n' => return index,
+            byte if byte == quote => return index + 1,
+            _ => index += 1,
+        }
+    }
+    src.len()
+}
+
+/// Returns the index following a comment opening at `start`
+pub fn skip_comment(src: &[u8], start: usize) -> usize {
+    let body_start = start + 2;
+    if src.get(body_start) == Some(&b'[')
+        && let Some(end) = skip_long_bracket(src, body_start)
+    {
+        return end;
+    }
+    bytes::find_sequence(&src[body_start..], b"\n")
+        .map_or(src.len(), |newline_index| body_start + newline_index)
+}
+
+/// If a comment or string-literal opens at `index`, returns the index following it
+pub fn skip_non_code(src: &[u8], index: usize) -> Option<usize> {
+    match src[index] {
+        b'-' if src.get(index + 1) == Some(&b'-') => Some(skip_comment(src, index)),
+        b'"' | b'\'' => Some(skip_quoted(src, index)),
+        b'[' => skip_long_bracket(src, index),
+        _ => None,
+    }
+}
+
+/// Whether only whitespace precedes the index on its line
+pub fn starts_line(src: &[u8], index: usize) -> bool {
+    src[..index]
+        .iter()
+        .rev()
+        .take_while(|byte| **byte != b'\n')
+        .all(u8::is_ascii_whitespace)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn skips_comments_and_strings() {
+        let src = b"a -- b\n--[==[ c ]] ]==] \"d\\\"\" 'e [[f\ng]] [=[h";
+        assert_eq!(skip_non_code(src, 0), None);
+        assert_eq!(skip_non_code(src, 2), Some(6));
+        assert_eq!(skip_non_code(src, 7), Some(23));
+        assert_eq!(skip_non_code(src, 24), Some(29));
+        // A quoted string ends at the line-break, a long-bracket spans it
+        assert_eq!(skip_non_code(src, 30), Some(36));
+        assert_eq!(skip_non_code(src, 33), Some(40));
+        assert_eq!(skip_non_code(src, 41), Some(src.len()));
+        assert_eq!(skip_non_code(b"t[1]", 1), None);
+        assert!(starts_line(b"x\n  --", 4));
+        assert!(!starts_line(b"x --", 2));
+    }
+}
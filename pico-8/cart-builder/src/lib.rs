@@ -1,16 +1,265 @@
 //! # `pico-8-cart-builder`
 //!
 //! - [`CartBuilder`][`CartBuilder`]: Main '_compiler implementation_'
+//! - [`TabOrder`][`TabOrder`]: Pins source-files to tab-indices
+//! - [`SourceOrder`][`SourceOrder`]: Orders the source-files which are not pinned
+//! - [`IncludeResolver`][`IncludeResolver`]: Inlines `#include`-directives
+//! - [`SourceExclude`][`SourceExclude`]: Skips source-files matching glob-patterns
+//! - [`Constants`][`Constants`]: Substitutes build-time constants into the source-files
+//! - [`TabOverflow`][`TabOverflow`]: Handles the source-files past the last tab
+
+pub mod constants;
+pub use constants::{ConstantError, ConstantValue, Constants};
+
+pub mod exclude;
+pub use exclude::SourceExclude;
+
+pub mod include;
+pub use include::{IncludeError, IncludeResolver};
+
+pub mod lex;
+
+pub mod overflow;
+pub use overflow::{TabOverflow, TooManyTabsError};
 
 use std::ffi;
+use std::fs;
 use std::io;
 use std::path;
-use std::{borrow::Cow, fs};
+use std::time;
 
 /// Constructs/compiles pico-8 carts
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct CartBuilder {
     src_dir: path::PathBuf,
+    tab_order: TabOrder,
+    include_resolver: IncludeResolver,
+    exclude: SourceExclude,
+    tab_overflow: TabOverflow,
+}
+
+impl CartBuilder {
+    pub fn new<P: AsRef<path::Path> + ?Sized>(src_dir: &P) -> CartBuilder {
+        CartBuilder {
+            src_dir: src_dir.as_ref().to_path_buf(),
+            tab_order: TabOrder::default(),
+            include_resolver: IncludeResolver::default(),
+            exclude: SourceExclude::default(),
+            tab_overflow: TabOverflow::default(),
+        }
+    }
+    pub fn with_tab_order(self, tab_order: TabOrder) -> CartBuilder {
+        CartBuilder { tab_order, ..self }
+    }
+    pub fn with_include_resolver(self, include_resolver: IncludeResolver) -> CartBuilder {
+        CartBuilder {
+            include_resolver,
+            ..self
+        }
+    }
+    pub fn with_exclude(self, exclude: SourceExclude) -> CartBuilder {
+        CartBuilder { exclude, ..self }
+    }
+    pub fn with_tab_overflow(self, tab_overflow: TabOverflow) -> CartBuilder {
+        CartBuilder {
+            tab_overflow,
+            ..self
+        }
+    }
+    pub fn src_dir(&self) -> &path::Path {
+        &self.src_dir
+    }
+    pub fn tab_order(&self) -> &TabOrder {
+        &self.tab_order
+    }
+    pub fn include_resolver(&self) -> &IncludeResolver {
+        &self.include_resolver
+    }
+    pub fn exclude(&self) -> &SourceExclude {
+        &self.exclude
+    }
+    pub fn tab_overflow(&self) -> TabOverflow {
+        self.tab_overflow
+    }
+    /// The lua source-files of the source-directory, in [`TabOrder`], without those
+    /// the [`SourceExclude`] matches
+    #[tracing::instrument(level = "debug")]
+    pub fn lua_files(&self) -> io::Result<Vec<fs::DirEntry>> {
+        get_lua_files(&self.src_dir).map(|lua_files| {
+            self.tab_order.sort(lua_files.filter(|dir_entry| {
+                let is_excluded = self.exclude.is_excluded(&dir_entry.file_name());
+                if is_excluded {
+                    tracing::debug!("Excluding {}", dir_entry.path().display());
+                }
+                !is_excluded
+            }))
+        })
+    }
+}
+
+/// How the source-files which are not pinned by the [`TabOrder`] are ordered
+///
+/// Ties are broken by name, so the order never depends on directory-iteration
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SourceOrder {
+    /// By file-name
+    #[default]
+    Alphabetical,
+    /// By the number the file-name starts with, e.g. `2-player.lua` before `10-enemy.lua`,
+    /// followed by the files without one
+    ByNumberPrefix,
+    /// As the tabs of the previous build, see [`TabOrder::with_manifest_order`], followed
+    /// by the new files, so adding a file does not move the tabs after it
+    Manifest,
+    /// By the last modification, the least recently modified first
+    ModifiedTime,
+}
+
+impl SourceOrder {
+    pub fn from_name(name: &str) -> Option<SourceOrder> {
+        match name.to_ascii_lowercase().as_str() {
+            "alphabetical" => Some(SourceOrder::Alphabetical),
+            "number-prefix" => Some(SourceOrder::ByNumberPrefix),
+            "manifest" => Some(SourceOrder::Manifest),
+            "modified-time" => Some(SourceOrder::ModifiedTime),
+            _ => None,
+        }
+    }
+}
+
+/// The order of the source-files in the cart, one tab per file
+///
+/// Directory-iteration order differs between filesystems, so the pinned files
+/// come first in the order listed, followed by the rest in [`SourceOrder`]
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct TabOrder {
+    pinned: Vec<String>,
+    source_order: SourceOrder,
+    manifest_order: Vec<String>,
+}
+
+impl TabOrder {
+    /// Pins the file-names, e.g. `["main.lua", "player.lua"]`, to the first tabs
+    pub fn new<I: IntoIterator<Item = S>, S: Into<String>>(pinned: I) -> TabOrder {
+        TabOrder {
+            pinned: pinned.into_iter().map(Into::into).collect(),
+            ..TabOrder::default()
+        }
+    }
+    pub fn with_source_order(self, source_order: SourceOrder) -> TabOrder {
+        TabOrder {
+            source_order,
+            ..self
+        }
+    }
+    /// The file-names of the tabs of the previous build, in tab-order, for
+    /// [`SourceOrder::Manifest`]
+    pub fn with_manifest_order<I: IntoIterator<Item = S>, S: Into<String>>(
+        self,
+        manifest_order: I,
+    ) -> TabOrder {
+        TabOrder {
+            manifest_order: manifest_order.into_iter().map(Into::into).collect(),
+            ..self
+        }
+    }
+    pub fn pinned(&self) -> &[String] {
+        &self.pinned
+    }
+    pub fn source_order(&self) -> SourceOrder {
+        self.source_order
+    }
+    /// Returns the tab-index of each file-name, in the same order
+    ///
+    /// Without the directory-entries the files are not ordered by [`SourceOrder::ModifiedTime`]
+    pub fn tab_indices<S: AsRef<str>>(&self, file_names: &[S]) -> Vec<usize> {
+        let mut sorted: Vec<(usize, &str)> =
+            file_names.iter().map(AsRef::as_ref).enumerate().collect();
+        sorted.sort_by_key(|(_, file_name)| self.sort_key(file_name, None));
+        let mut tab_indices = vec![0; file_names.len()];
+        for (tab_index, (file_index, _)) in sorted.into_iter().enumerate() {
+            tab_indices[file_index] = tab_index;
+        }
+        tab_indices
+    }
+    /// Sorts the directory-entries into tab-order
+    pub fn sort(&self, dir_entries: impl IntoIterator<Item = fs::DirEntry>) -> Vec<fs::DirEntry> {
+        let mut dir_entries: Vec<(String, Option<time::SystemTime>, fs::DirEntry)> = dir_entries
+            .into_iter()
+            .map(|dir_entry| {
+                let modified = match self.source_order {
+                    SourceOrder::ModifiedTime => dir_entry
+                        .metadata()
+                        .and_then(|metadata| metadata.modified())
+                        .inspect_err(|e| {
+                            tracing::warn!(
+                                "Failed to get the modification-time of {}: {e}",
+                                dir_entry.path().display()
+                            );
+                        })
+                        .ok(),
+                    _ => None,
+                };
+                (
+                    dir_entry.file_name().to_string_lossy().into_owned(),
+                    modified,
+                    dir_entry,
+                )
+            })
+            .collect();
+        dir_entries.sort_by(|(a, a_modified, _), (b, b_modified, _)| {
+            self.sort_key(a, *a_modified)
+                .cmp(&self.sort_key(b, *b_modified))
+        });
+        for pinned in &self.pinned {
+            if !dir_entries
+                .iter()
+                .any(|(file_name, ..)| file_name == pinned)
+            {
+                tracing::warn!("Pinned tab-file {pinned:?} was not found");
+            }
+        }
+        dir_entries
+            .into_iter()
+            .map(|(.., dir_entry)| dir_entry)
+            .collect()
+    }
+    /// The pinned index, the rank in [`SourceOrder`], and the name to break ties
+    fn sort_key<'a>(
+        &self,
+        file_name: &'a str,
+        modified: Option<time::SystemTime>,
+    ) -> (usize, u128, &'a str) {
+        let pinned_index = self
+            .pinned
+            .iter()
+            .position(|pinned| pinned == file_name)
+            .unwrap_or(usize::MAX);
+        let rank = match self.source_order {
+            SourceOrder::Alphabetical => 0,
+            SourceOrder::ByNumberPrefix => {
+                let digits = file_name.len()
+                    - file_name
+                        .trim_start_matches(|c: char| c.is_ascii_digit())
+                        .len();
+                file_name[..digits].parse().unwrap_or(u128::MAX)
+            }
+            SourceOrder::Manifest => self
+                .manifest_order
+                .iter()
+                .position(|tab_file| tab_file == file_name)
+                .map_or(u128::MAX, |tab_index| tab_index as u128),
+            SourceOrder::ModifiedTime => modified
+                .map(|modified| {
+                    modified
+                        .duration_since(time::UNIX_EPOCH)
+                        .unwrap_or_default()
+                        .as_nanos()
+                })
+                .unwrap_or(u128::MAX),
+        };
+        (pinned_index, rank, file_name)
+    }
 }
 
 #[tracing::instrument(level = "debug", skip(path), ret)]
@@ -153,3 +402,34 @@ pub fn get_lua_files<P: AsRef<path::Path> + ?Sized>(
 //         Ok(cart)
 //     }
 // }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pinned_files_come_first() {
+        let tab_order = TabOrder::new(["main.lua", "player.lua"]);
+        assert_eq!(
+            tab_order.tab_indices(&["util.lua", "player.lua", "enemy.lua", "main.lua"]),
+            [3, 1, 2, 0]
+        );
+
+        let file_names = ["10-enemy.lua", "util.lua", "2-player.lua", "main.lua"];
+        let tab_order = TabOrder::new(["main.lua"]);
+        assert_eq!(
+            tab_order
+                .clone()
+                .with_source_order(SourceOrder::ByNumberPrefix)
+                .tab_indices(&file_names),
+            [2, 3, 1, 0]
+        );
+        assert_eq!(
+            tab_order
+                .with_source_order(SourceOrder::Manifest)
+                .with_manifest_order(["main.lua", "util.lua", "10-enemy.lua"])
+                .tab_indices(&file_names),
+            [2, 1, 3, 0]
+        );
+    }
+}
@@ -0,0 +1,106 @@
+//! The source-files past the last tab of the pico-8 code-editor
+//!
+//! The code-editor has [`P8_MAX_CODE_EDITOR_TAB_COUNT`] tabs, one per source-file, so a
+//! source-directory with more files either fails to build, or has the files past the last
+//! tab merged into it
+
+use core::fmt;
+
+use std::path;
+
+use pico_8_cart_model::P8_MAX_CODE_EDITOR_TAB_COUNT;
+
+/// What becomes of the source-files past the last tab, see the module-documentation
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TabOverflow {
+    /// Fails the build with a [`TooManyTabsError`]
+    #[default]
+    Error,
+    /// Merges the source-files past the last tab into it
+    MergeIntoLast,
+}
+
+impl TabOverflow {
+    pub fn from_name(name: &str) -> Option<TabOverflow> {
+        match name.to_ascii_lowercase().as_str() {
+            "error" => Some(TabOverflow::Error),
+            "merge-into-last" => Some(TabOverflow::MergeIntoLast),
+            _ => None,
+        }
+    }
+    /// Checks that the source-files, in tab-order, fit into the tabs from the `first_tab`
+    ///
+    /// Always succeeds with [`TabOverflow::MergeIntoLast`]
+    pub fn check<P: AsRef<path::Path>>(
+        self,
+        source_paths: &[P],
+        first_tab: usize,
+    ) -> Result<(), TooManyTabsError> {
+        let room = P8_MAX_CODE_EDITOR_TAB_COUNT.saturating_sub(first_tab);
+        match self {
+            TabOverflow::Error if source_paths.len() > room => Err(TooManyTabsError {
+                room,
+                overflowing: source_paths[room..]
+                    .iter()
+                    .map(|source_path| source_path.as_ref().to_path_buf())
+                    .collect(),
+            }),
+            TabOverflow::Error | TabOverflow::MergeIntoLast => Ok(()),
+        }
+    }
+}
+
+/// More source-files than tabs to compile them into
+#[derive(Debug, PartialEq, Eq)]
+pub struct TooManyTabsError {
+    /// The tabs the source-files are compiled into
+    pub room: usize,
+    /// The source-files past the last tab
+    pub overflowing: Vec<path::PathBuf>,
+}
+
+impl fmt::Display for TooManyTabsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let description = "Too many tabs";
+        let overflowing: Vec<_> = self
+            .overflowing
+            .iter()
+            .map(|source_path| source_path.display().to_string())
+            .collect();
+        let reason = format!(
+            "{} source-files do not fit into the {} tabs: {}",
+            overflowing.len(),
+            self.room,
+            overflowing.join(", ")
+        );
+        f.write_fmt(format_args!("{description}: {reason}"))
+    }
+}
+
+impl core::error::Error for TooManyTabsError {}
+
+impl TooManyTabsError {
+    /// The stable diagnostic-code, see `pico-build-rs explain`
+    pub const fn code(&self) -> &'static str {
+        "E023"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lists_the_overflowing_files() {
+        let source_paths: Vec<String> = (0..17).map(|index| format!("{index}.lua")).collect();
+        assert_eq!(TabOverflow::Error.check(&source_paths[..16], 0), Ok(()));
+        assert_eq!(
+            TabOverflow::Error.check(&source_paths, 1),
+            Err(TooManyTabsError {
+                room: 15,
+                overflowing: vec!["15.lua".into(), "16.lua".into()],
+            })
+        );
+        assert_eq!(TabOverflow::MergeIntoLast.check(&source_paths, 1), Ok(()));
+    }
+}
@@ -0,0 +1,478 @@
+//! Reading the frames of an Aseprite `.ase`/`.aseprite` file as RGBA-pixels
+//!
+//! Each frame is flattened from the cels of its visible layers, blended in the normal
+//! mode with the opacity of the layer and the cel. Indexed sprites are mapped through
+//! their palette, with the transparent index as a transparent pixel. Other blend-modes
+//! and tilemap-layers are drawn as normal layers, or skipped respectively
+
+use core::fmt;
+
+use std::io::{self, Read};
+
+/// The magic-number of the file-header
+const FILE_MAGIC: u16 = 0xa5e0;
+/// The magic-number of each frame-header
+const FRAME_MAGIC: u16 = 0xf1fa;
+const HEADER_SIZE: usize = 128;
+const FRAME_HEADER_SIZE: usize = 16;
+
+const OLD_PALETTE_CHUNK: u16 = 0x0004;
+const LAYER_CHUNK: u16 = 0x2004;
+const CEL_CHUNK: u16 = 0x2005;
+const PALETTE_CHUNK: u16 = 0x2019;
+
+/// The layer is drawn, unless a group it is in is hidden
+const LAYER_VISIBLE: u16 = 1;
+/// The opacity of the layers is valid, files of older versions leave it at 0
+const HEADER_LAYER_OPACITY: u32 = 1;
+
+#[derive(Debug)]
+pub enum AseError {
+    Io(io::Error),
+    /// The file, or one of its frames, does not start with the magic-number
+    InvalidMagic,
+    /// The color-depth is not 32 (RGBA), 16 (grayscale) or 8 (indexed) bits
+    UnsupportedColorDepth(u16),
+    /// A chunk, or a cel, is shorter than its header says
+    Truncated,
+}
+
+impl From<io::Error> for AseError {
+    fn from(v: io::Error) -> Self {
+        Self::Io(v)
+    }
+}
+
+impl fmt::Display for AseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let description = "Aseprite error";
+        let reason = match self {
+            AseError::Io(e) => e.to_string(),
+            AseError::InvalidMagic => "not an aseprite-file".to_string(),
+            AseError::UnsupportedColorDepth(depth) => {
+                format!("unsupported color-depth of {depth} bits")
+            }
+            AseError::Truncated => "the file is truncated".to_string(),
+        };
+        f.write_fmt(format_args!("{description}: {reason}"))
+    }
+}
+
+impl core::error::Error for AseError {}
+
+impl AseError {
+    /// The stable diagnostic-code, see `pico-build-rs explain`
+    pub const fn code(&self) -> &'static str {
+        "E018"
+    }
+}
+
+/// The flattened frames of an aseprite-file
+#[derive(Clone, PartialEq, Eq)]
+pub struct AseFile {
+    pub width: usize,
+    pub height: usize,
+    /// The RGBA-pixels of each frame, row by row
+    pub frames: Vec<Box<[u8]>>,
+}
+
+impl fmt::Debug for AseFile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AseFile")
+            .field("width", &self.width)
+            .field("height", &self.height)
+            .field("frames", &self.frames.len())
+            .finish()
+    }
+}
+
+/// A layer of the sprite, from the layer-chunks of the first frame
+struct Layer {
+    visible: bool,
+    is_image: bool,
+    opacity: u8,
+}
+
+/// The pixels of a cel, in the color-depth of the file
+#[derive(Clone)]
+struct Cel {
+    layer_index: usize,
+    x: i64,
+    y: i64,
+    opacity: u8,
+    width: usize,
+    pixels: Vec<u8>,
+}
+
+/// Reads the little-endian fields of a chunk
+struct Fields<'a>(&'a [u8]);
+
+impl<'a> Fields<'a> {
+    fn bytes(&mut self, count: usize) -> Result<&'a [u8], AseError> {
+        let (bytes, rest) = self.0.split_at_checked(count).ok_or(AseError::Truncated)?;
+        self.0 = rest;
+        Ok(bytes)
+    }
+    fn byte(&mut self) -> Result<u8, AseError> {
+        Ok(self.bytes(1)?[0])
+    }
+    fn word(&mut self) -> Result<u16, AseError> {
+        Ok(u16::from_le_bytes([self.byte()?, self.byte()?]))
+    }
+    fn short(&mut self) -> Result<i16, AseError> {
+        Ok(self.word()? as i16)
+    }
+    fn dword(&mut self) -> Result<u32, AseError> {
+        Ok(u32::from_le_bytes([
+            self.byte()?,
+            self.byte()?,
+            self.byte()?,
+            self.byte()?,
+        ]))
+    }
+    fn skip_string(&mut self) -> Result<(), AseError> {
+        let len = self.word()?;
+        self.bytes(len.into()).map(drop)
+    }
+}
+
+impl AseFile {
+    /// Reads the file, flattening each frame
+    #[tracing::instrument(level = "debug", skip(reader))]
+    pub fn read<R: io::Read>(mut reader: R) -> Result<AseFile, AseError> {
+        let mut data = Vec::new();
+        reader.read_to_end(&mut data)?;
+        let mut header = Fields(data.get(..HEADER_SIZE).ok_or(AseError::Truncated)?);
+        header.dword()?;
+        if header.word()? != FILE_MAGIC {
+            return Err(AseError::InvalidMagic);
+        }
+        let frame_count = header.word()?;
+        let width = usize::from(header.word()?);
+        let height = usize::from(header.word()?);
+        let color_depth = header.word()?;
+        let bytes_per_pixel = match color_depth {
+            32 => 4,
+            16 => 2,
+            8 => 1,
+            color_depth => return Err(AseError::UnsupportedColorDepth(color_depth)),
+        };
+        let flags = header.dword()?;
+        header.bytes(10)?;
+        let transparent_index = header.byte()?;
+
+        let mut layers = Vec::new();
+        let mut palette = vec![[0, 0, 0, u8::MAX]; 256];
+        let mut frame_cels: Vec<Vec<Cel>> = Vec::with_capacity(frame_count.into());
+        let mut rest = &data[HEADER_SIZE..];
+        for _ in 0..frame_count {
+            let mut frame_header =
+                Fields(rest.get(..FRAME_HEADER_SIZE).ok_or(AseError::Truncated)?);
+            let frame_size = frame_header.dword()? as usize;
+            if frame_header.word()? != FRAME_MAGIC {
+                return Err(AseError::InvalidMagic);
+            }
+            let old_chunk_count = frame_header.word()?;
+            frame_header.bytes(4)?;
+            let chunk_count = match frame_header.dword()? {
+                0 => u32::from(old_chunk_count),
+                chunk_count => chunk_count,
+            };
+            let frame = rest
+                .get(FRAME_HEADER_SIZE..frame_size)
+                .ok_or(AseError::Truncated)?;
+            rest = &rest[frame_size..];
+
+            let mut cels = Vec::new();
+            let mut chunks = frame;
+            for _ in 0..chunk_count {
+                let mut chunk_header = Fields(chunks);
+                let chunk_size = chunk_header.dword()? as usize;
+                let chunk_type = chunk_header.word()?;
+                let chunk = chunks.get(6..chunk_size).ok_or(AseError::Truncated)?;
+                chunks = &chunks[chunk_size..];
+                let mut fields = Fields(chunk);
+                match chunk_type {
+                    LAYER_CHUNK => {
+                        let layer_flags = fields.word()?;
+                        let layer_type = fields.word()?;
+                        let child_level = usize::from(fields.word()?);
+                        fields.bytes(6)?;
+                        let opacity = fields.byte()?;
+                        // Hidden groups hide their children, which follow them
+                        let parent_visible = layers
+                            .iter()
+                            .rev()
+                            .find_map(|(level, layer): &(usize, Layer)| {
+                                (*level < child_level).then_some(layer.visible)
+                            })
+                            .unwrap_or(true);
+                        layers.push((
+                            child_level,
+                            Layer {
+                                visible: parent_visible && layer_flags & LAYER_VISIBLE != 0,
+                                is_image: layer_type == 0,
+                                opacity: if flags & HEADER_LAYER_OPACITY != 0 {
+                                    opacity
+                                } else {
+                                    u8::MAX
+                                },
+                            },
+                        ));
+                    }
+                    CEL_CHUNK => {
+                        let layer_index = usize::from(fields.word()?);
+                        let x = i64::from(fields.short()?);
+                        let y = i64::from(fields.short()?);
+                        let opacity = fields.byte()?;
+                        let cel_type = fields.word()?;
+                        fields.bytes(7)?;
+                        let cel = match cel_type {
+                            0 | 2 => {
+                                let cel_width = usize::from(fields.word()?);
+                                let cel_height = usize::from(fields.word()?);
+                                let size = cel_width * cel_height * bytes_per_pixel;
+                                let pixels = if cel_type == 0 {
+                                    fields.bytes(size)?.to_vec()
+                                } else {
+                                    let mut pixels = Vec::with_capacity(size);
+                                    flate2::read::ZlibDecoder::new(fields.0)
+                                        .take(size as u64)
+                                        .read_to_end(&mut pixels)?;
+                                    pixels
+                                };
+                                if pixels.len() != size {
+                                    return Err(AseError::Truncated);
+                                }
+                                Cel {
+                                    layer_index,
+                                    x,
+                                    y,
+                                    opacity,
+                                    width: cel_width,
+                                    pixels,
+                                }
+                            }
+                            1 => {
+                                let linked_frame = usize::from(fields.word()?);
+                                let Some(linked) = frame_cels.get(linked_frame).and_then(|cels| {
+                                    cels.iter().find(|cel| cel.layer_index == layer_index)
+                                }) else {
+                                    continue;
+                                };
+                                Cel {
+                                    x,
+                                    y,
+                                    opacity,
+                                    ..linked.clone()
+                                }
+                            }
+                            // Tilemaps
+                            _ => continue,
+                        };
+                        cels.push(cel);
+                    }
+                    PALETTE_CHUNK => {
+                        fields.dword()?;
+                        let first = fields.dword()? as usize;
+                        let last = fields.dword()? as usize;
+                        fields.bytes(8)?;
+                        for index in first..=last {
+                            let entry_flags = fields.word()?;
+                            let color = [
+                                fields.byte()?,
+                                fields.byte()?,
+                                fields.byte()?,
+                                fields.byte()?,
+                            ];
+                            if let Some(entry) = palette.get_mut(index) {
+                                *entry = color;
+                            }
+                            if entry_flags & 1 != 0 {
+                                fields.skip_string()?;
+                            }
+                        }
+                    }
+                    OLD_PALETTE_CHUNK => {
+                        let mut index = 0;
+                        for _ in 0..fields.word()? {
+                            index += usize::from(fields.byte()?);
+                            let count = match fields.byte()? {
+                                0 => 256,
+                                count => usize::from(count),
+                            };
+                            for _ in 0..count {
+                                let color =
+                                    [fields.byte()?, fields.byte()?, fields.byte()?, u8::MAX];
+                                if let Some(entry) = palette.get_mut(index) {
+                                    *entry = color;
+                                }
+                                index += 1;
+                            }
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            frame_cels.push(cels);
+        }
+
+        let to_rgba = |pixel: &[u8]| -> [u8; 4] {
+            match *pixel {
+                [red, green, blue, alpha] => [red, green, blue, alpha],
+                [value, alpha] => [value, value, value, alpha],
+                [index] if index == transparent_index => [0; 4],
+                [index] => palette[usize::from(index)],
+                _ => unreachable!("chunks of the bytes per pixel"),
+            }
+        };
+        let frames = frame_cels
+            .iter()
+            .map(|cels| {
+                let mut rgba = vec![0; width * height * 4];
+                let mut cels: Vec<&Cel> = cels.iter().collect();
+                cels.sort_by_key(|cel| cel.layer_index);
+                for cel in cels {
+                    let Some((_, layer)) = layers.get(cel.layer_index) else {
+                        continue;
+                    };
+                    if !layer.visible || !layer.is_image {
+                        continue;
+                    }
+                    let opacity = u32::from(cel.opacity) * u32::from(layer.opacity) / 255;
+                    for (index, pixel) in cel.pixels.chunks_exact(bytes_per_pixel).enumerate() {
+                        let x = cel.x + (index % cel.width) as i64;
+                        let y = cel.y + (index / cel.width) as i64;
+                        if x < 0 || y < 0 || x as usize >= width || y as usize >= height {
+                            continue;
+                        }
+                        let offset = (y as usize * width + x as usize) * 4;
+                        blend(&mut rgba[offset..offset + 4], to_rgba(pixel), opacity);
+                    }
+                }
+                rgba.into_boxed_slice()
+            })
+            .collect();
+        Ok(AseFile {
+            width,
+            height,
+            frames,
+        })
+    }
+    /// The RGBA-pixels of the frames side by side, from the first frame at the left,
+    /// e.g. the sprites of an animation
+    pub fn frame_strip(&self) -> Vec<u8> {
+        let row_size = self.width * 4;
+        (0..self.height)
+            .flat_map(|y| {
+                self.frames
+                    .iter()
+                    .flat_map(move |frame| &frame[y * row_size..(y + 1) * row_size])
+            })
+            .copied()
+            .collect()
+    }
+}
+
+/// Draws the source over the destination, with the source-alpha scaled by the opacity
+fn blend(destination: &mut [u8], [red, green, blue, alpha]: [u8; 4], opacity: u32) {
+    let source_alpha = u32::from(alpha) * opacity / 255;
+    if source_alpha == 0 {
+        return;
+    }
+    let destination_alpha = u32::from(destination[3]) * (255 - source_alpha) / 255;
+    let alpha = source_alpha + destination_alpha;
+    for (channel, source) in destination.iter_mut().zip([red, green, blue]) {
+        *channel = ((u32::from(source) * source_alpha + u32::from(*channel) * destination_alpha)
+            / alpha) as u8;
+    }
+    destination[3] = alpha as u8;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::io::Write;
+
+    /// A chunk with its header
+    fn chunk(chunk_type: u16, data: &[u8]) -> Vec<u8> {
+        let mut chunk = ((data.len() + 6) as u32).to_le_bytes().to_vec();
+        chunk.extend(chunk_type.to_le_bytes());
+        chunk.extend(data);
+        chunk
+    }
+
+    /// A cel of the layer at the position
+    fn cel(layer_index: u16, x: i16, cel_type: u16, pixels: &[u8]) -> Vec<u8> {
+        let mut data = layer_index.to_le_bytes().to_vec();
+        data.extend(x.to_le_bytes());
+        data.extend(0i16.to_le_bytes());
+        data.push(u8::MAX);
+        data.extend(cel_type.to_le_bytes());
+        data.extend([0; 7]);
+        data.extend(2u16.to_le_bytes());
+        data.extend(1u16.to_le_bytes());
+        data.extend(pixels);
+        data
+    }
+
+    #[test]
+    fn flattens_indexed_layers() {
+        let layer = |flags: u16| {
+            let mut data = flags.to_le_bytes().to_vec();
+            data.extend([0; 10]);
+            data.push(u8::MAX);
+            data.extend([0; 3]);
+            data.extend(0u16.to_le_bytes());
+            data
+        };
+        let mut palette = [1u32.to_le_bytes(), 1u32.to_le_bytes(), 2u32.to_le_bytes()].concat();
+        palette.extend([0; 8]);
+        for color in [[0, 0, 0, 255], [255, 0, 77, 255]] {
+            palette.extend([0, 0]);
+            palette.extend(color);
+        }
+        let mut compressed = flate2::write::ZlibEncoder::new(Vec::new(), Default::default());
+        compressed.write_all(&[2, 0]).unwrap();
+        let chunks = [
+            chunk(PALETTE_CHUNK, &palette),
+            chunk(LAYER_CHUNK, &layer(LAYER_VISIBLE)),
+            chunk(LAYER_CHUNK, &layer(LAYER_VISIBLE)),
+            chunk(LAYER_CHUNK, &layer(0)),
+            chunk(CEL_CHUNK, &cel(0, 0, 0, &[1, 1])),
+            chunk(CEL_CHUNK, &cel(1, 1, 2, &compressed.finish().unwrap())),
+            chunk(CEL_CHUNK, &cel(2, 0, 0, &[2, 2])),
+        ]
+        .concat();
+
+        let mut frame = ((FRAME_HEADER_SIZE + chunks.len()) as u32)
+            .to_le_bytes()
+            .to_vec();
+        frame.extend(FRAME_MAGIC.to_le_bytes());
+        frame.extend(7u16.to_le_bytes());
+        frame.extend([0; 4]);
+        frame.extend(0u32.to_le_bytes());
+        frame.extend(chunks);
+        let mut file = vec![0; 4];
+        file.extend(FILE_MAGIC.to_le_bytes());
+        for word in [1u16, 3, 1, 8] {
+            file.extend(word.to_le_bytes());
+        }
+        file.extend(HEADER_LAYER_OPACITY.to_le_bytes());
+        file.resize(HEADER_SIZE, 0);
+        file.extend(frame);
+
+        let ase = AseFile::read(file.as_slice()).unwrap();
+        assert_eq!((ase.width, ase.height, ase.frames.len()), (3, 1, 1));
+        // The second layer draws over the first, and the hidden third not at all
+        assert_eq!(
+            ase.frame_strip(),
+            [0, 0, 0, 255, 255, 0, 77, 255, 0, 0, 0, 0]
+        );
+        assert!(matches!(
+            AseFile::read(&file[..HEADER_SIZE + 20]),
+            Err(AseError::Truncated)
+        ));
+    }
+}
@@ -0,0 +1,228 @@
+//! The text-codecs of the asset-sections
+//!
+//! [`CartData`] reads and writes its asset-sections through these, see
+//! [`CartData::decode_section`] and [`CartData::encode_section`]. The [`CodecRegistry`]
+//! looks codecs up by section, for tooling working on sections it does not know
+//! the model of
+
+use core::fmt;
+
+use crate::{
+    CartData, GfxSheet, HexError, LabelImage, MapData, MusicData, SectionType, SfxData, SpriteFlags,
+};
+
+/// Decodes an asset-section into its model, and encodes it back into text
+pub trait SectionCodec {
+    /// The section the codec reads and writes
+    const SECTION_TYPE: SectionType;
+    type Model;
+
+    fn decode(section_data: &[u8]) -> Result<Self::Model, HexError>;
+    fn encode(model: &Self::Model) -> Box<[u8]>;
+    /// Checks the section-data without keeping the model
+    fn validate(section_data: &[u8]) -> Result<(), HexError> {
+        Self::decode(section_data).map(drop)
+    }
+}
+
+/// The `__gfx__` sprite-sheet
+pub struct GfxCodec;
+
+impl SectionCodec for GfxCodec {
+    const SECTION_TYPE: SectionType = SectionType::Gfx;
+    type Model = GfxSheet;
+
+    fn decode(section_data: &[u8]) -> Result<GfxSheet, HexError> {
+        GfxSheet::from_section_data(section_data)
+    }
+    fn encode(gfx_sheet: &GfxSheet) -> Box<[u8]> {
+        gfx_sheet.to_section_data()
+    }
+}
+
+/// The `__label__` image, in the 16-color palette
+pub struct LabelCodec;
+
+impl SectionCodec for LabelCodec {
+    const SECTION_TYPE: SectionType = SectionType::Label;
+    type Model = LabelImage;
+
+    fn decode(section_data: &[u8]) -> Result<LabelImage, HexError> {
+        LabelImage::from_section_data(section_data)
+    }
+    fn encode(label_image: &LabelImage) -> Box<[u8]> {
+        label_image.to_section_data()
+    }
+}
+
+/// The `__gff__` sprite-flags
+pub struct GffCodec;
+
+impl SectionCodec for GffCodec {
+    const SECTION_TYPE: SectionType = SectionType::Gff;
+    type Model = SpriteFlags;
+
+    fn decode(section_data: &[u8]) -> Result<SpriteFlags, HexError> {
+        SpriteFlags::from_section_data(section_data)
+    }
+    fn encode(sprite_flags: &SpriteFlags) -> Box<[u8]> {
+        sprite_flags.to_section_data()
+    }
+}
+
+/// The `__map__` section, without the rows shared with the sprite-sheet
+pub struct MapCodec;
+
+impl SectionCodec for MapCodec {
+    const SECTION_TYPE: SectionType = SectionType::Map;
+    type Model = MapData;
+
+    fn decode(section_data: &[u8]) -> Result<MapData, HexError> {
+        MapData::from_section_data(section_data)
+    }
+    fn encode(map_data: &MapData) -> Box<[u8]> {
+        map_data.to_section_data()
+    }
+}
+
+/// The `__sfx__` sound-effects
+pub struct SfxCodec;
+
+impl SectionCodec for SfxCodec {
+    const SECTION_TYPE: SectionType = SectionType::Sfx;
+    type Model = SfxData;
+
+    fn decode(section_data: &[u8]) -> Result<SfxData, HexError> {
+        SfxData::from_section_data(section_data)
+    }
+    fn encode(sfx_data: &SfxData) -> Box<[u8]> {
+        sfx_data.to_section_data()
+    }
+}
+
+/// The `__music__` patterns
+pub struct MusicCodec;
+
+impl SectionCodec for MusicCodec {
+    const SECTION_TYPE: SectionType = SectionType::Music;
+    type Model = MusicData;
+
+    fn decode(section_data: &[u8]) -> Result<MusicData, HexError> {
+        MusicData::from_section_data(section_data)
+    }
+    fn encode(music_data: &MusicData) -> Box<[u8]> {
+        music_data.to_section_data()
+    }
+}
+
+/// A codec in the [`CodecRegistry`], with its model erased
+#[derive(Clone, Copy)]
+pub struct RegisteredCodec {
+    pub name: &'static str,
+    pub section_type: SectionType,
+    validate: fn(&[u8]) -> Result<(), HexError>,
+}
+
+impl RegisteredCodec {
+    pub fn of<C: SectionCodec>(name: &'static str) -> RegisteredCodec {
+        RegisteredCodec {
+            name,
+            section_type: C::SECTION_TYPE,
+            validate: C::validate,
+        }
+    }
+    pub fn validate(&self, section_data: &[u8]) -> Result<(), HexError> {
+        (self.validate)(section_data)
+    }
+}
+
+impl fmt::Debug for RegisteredCodec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RegisteredCodec")
+            .field("name", &self.name)
+            .field("section_type", &self.section_type)
+            .finish_non_exhaustive()
+    }
+}
+
+/// The codecs by section
+///
+/// Registering a codec for a section which already has one replaces it
+#[derive(Clone, Debug)]
+pub struct CodecRegistry {
+    codecs: Vec<RegisteredCodec>,
+}
+
+impl CodecRegistry {
+    /// A registry without any codecs, see [`CodecRegistry::default`] for the built-in ones
+    pub const fn new() -> CodecRegistry {
+        CodecRegistry { codecs: Vec::new() }
+    }
+    pub fn register<C: SectionCodec>(&mut self, name: &'static str) {
+        self.codecs
+            .retain(|codec| codec.section_type != C::SECTION_TYPE);
+        self.codecs.push(RegisteredCodec::of::<C>(name));
+    }
+    pub fn get(&self, section_type: SectionType) -> Option<&RegisteredCodec> {
+        self.codecs
+            .iter()
+            .find(|codec| codec.section_type == section_type)
+    }
+    pub fn codecs(&self) -> &[RegisteredCodec] {
+        &self.codecs
+    }
+    /// Validates each section of the cart which has a codec,
+    /// returning the sections which failed
+    pub fn validate_cart(&self, cart: &CartData<'_>) -> Vec<(SectionType, HexError)> {
+        self.codecs
+            .iter()
+            .filter_map(|codec| {
+                let section_data = cart.section_data(codec.section_type)?;
+                codec
+                    .validate(section_data)
+                    .err()
+                    .map(|e| (codec.section_type, e))
+            })
+            .collect()
+    }
+}
+
+impl Default for CodecRegistry {
+    /// The codecs [`CartData`] uses
+    fn default() -> Self {
+        let mut registry = CodecRegistry::new();
+        registry.register::<GfxCodec>("gfx");
+        registry.register::<LabelCodec>("label");
+        registry.register::<GffCodec>("gff");
+        registry.register::<MapCodec>("map");
+        registry.register::<SfxCodec>("sfx");
+        registry.register::<MusicCodec>("music");
+        registry
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn validates_registered_sections() {
+        let mut cart = CartData::default();
+        let mut sfx_data = SfxData::new();
+        sfx_data.get_mut(0).unwrap().speed = 4;
+        cart.encode_section::<SfxCodec>(&sfx_data);
+        let decoded = cart.decode_section::<SfxCodec>().unwrap().unwrap();
+        assert_eq!(decoded.get(0), sfx_data.get(0));
+
+        let registry = CodecRegistry::default();
+        assert!(registry.validate_cart(&cart).is_empty());
+        assert_eq!(
+            registry.get(SectionType::Gfx).unwrap().validate(b"0g\n"),
+            Err(HexError::InvalidRowLength {
+                row: 0,
+                length: 2,
+                expected: 128
+            })
+        );
+    }
+}
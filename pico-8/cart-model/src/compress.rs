@@ -0,0 +1,468 @@
+//! The compressed code-formats of the pico-8 ROM
+//!
+//! The code-region holds either plain P8SCII, the legacy `:c:` format,
+//! or the pxa format used since pico-8 0.2.0
+
+use core::cmp::Reverse;
+use core::fmt;
+
+use std::collections::HashMap;
+
+/// The size of the code-region of the ROM
+pub const CODE_REGION_SIZE: usize = 0x3d00;
+
+const LEGACY_HEADER: &[u8; 4] = b":c:\0";
+const PXA_HEADER: &[u8; 4] = b"\0pxa";
+/// The header-bytes followed by the big-endian decompressed and compressed lengths
+const HEADER_SIZE: usize = 8;
+
+/// The shortest back-reference of the pxa format
+const MIN_BACK_REFERENCE_LENGTH: usize = 3;
+/// The furthest back-reference of the pxa format
+const MAX_BACK_REFERENCE_OFFSET: usize = 1 << 15;
+/// The earlier occurrences checked for each back-reference, bounding the compression-time
+const MAX_BACK_REFERENCE_CANDIDATES: usize = 256;
+
+/// The characters of the legacy format, indexed by the byte minus one
+const LEGACY_CHARACTERS: &[u8; 0x3b] =
+    b"\n 0123456789abcdefghijklmnopqrstuvwxyz!#%(){}[]<>+=/*:;.,~_";
+
+/// The format of the code-region
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CodeFormat {
+    /// Uncompressed P8SCII, terminated by a zero-byte or the end of the region
+    Plain,
+    /// The `:c:` format of pico-8 versions before 0.2.0
+    Legacy,
+    Pxa,
+}
+
+impl CodeFormat {
+    pub fn detect(code_region: &[u8]) -> CodeFormat {
+        if code_region.starts_with(LEGACY_HEADER) {
+            CodeFormat::Legacy
+        } else if code_region.starts_with(PXA_HEADER) {
+            CodeFormat::Pxa
+        } else {
+            CodeFormat::Plain
+        }
+    }
+}
+
+/// A code-region which could not be decompressed
+#[derive(Debug, PartialEq, Eq)]
+pub enum DecompressError {
+    /// The data ended before the decompressed length was reached
+    Truncated { format: CodeFormat },
+    /// A back-reference pointed before the start of the code
+    InvalidBackReference { offset: usize, position: usize },
+}
+
+impl fmt::Display for DecompressError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let description = "Decompression error";
+        let reason = match self {
+            DecompressError::Truncated { format } => {
+                format!("{format:?}-data ended before the decompressed length")
+            }
+            DecompressError::InvalidBackReference { offset, position } => {
+                format!("back-reference of offset {offset} at position {position}")
+            }
+        };
+        f.write_fmt(format_args!("{description}: {reason}"))
+    }
+}
+
+impl core::error::Error for DecompressError {}
+
+/// Code which could not be compressed
+#[derive(Debug, PartialEq, Eq)]
+pub enum CompressError {
+    /// The code, or the compressed code, was too long for the 16-bit lengths of the header
+    TooLarge { length: usize },
+}
+
+impl fmt::Display for CompressError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let description = "Compression error";
+        let reason = match self {
+            CompressError::TooLarge { length } => {
+                format!("{length} bytes exceed the {} bytes of the header", u16::MAX)
+            }
+        };
+        f.write_fmt(format_args!("{description}: {reason}"))
+    }
+}
+
+impl core::error::Error for CompressError {}
+
+/// Decompresses the code-region into P8SCII, detecting the format
+#[tracing::instrument(level = "debug", skip(code_region))]
+pub fn decompress(code_region: &[u8]) -> Result<Vec<u8>, DecompressError> {
+    let format = CodeFormat::detect(code_region);
+    tracing::debug!("code-region is in the {format:?}-format");
+    match format {
+        CodeFormat::Plain => {
+            let length = code_region
+                .iter()
+                .position(|byte| *byte == 0)
+                .unwrap_or(code_region.len());
+            Ok(code_region[..length].to_vec())
+        }
+        CodeFormat::Legacy => decompress_legacy(code_region),
+        CodeFormat::Pxa => decompress_pxa(code_region),
+    }
+}
+
+fn decompressed_length(code_region: &[u8], format: CodeFormat) -> Result<usize, DecompressError> {
+    match code_region.get(4..6) {
+        Some(&[high, low]) => Ok(usize::from(u16::from_be_bytes([high, low]))),
+        _ => Err(DecompressError::Truncated { format }),
+    }
+}
+
+/// Copies `length` bytes starting `offset` bytes back, the copied range may overlap the output
+fn copy_back_reference(
+    code: &mut Vec<u8>,
+    offset: usize,
+    length: usize,
+) -> Result<(), DecompressError> {
+    let start = code
+        .len()
+        .checked_sub(offset)
+        .filter(|_| offset != 0)
+        .ok_or(DecompressError::InvalidBackReference {
+            offset,
+            position: code.len(),
+        })?;
+    for index in start..start + length {
+        code.push(code[index]);
+    }
+    Ok(())
+}
+
+fn decompress_legacy(code_region: &[u8]) -> Result<Vec<u8>, DecompressError> {
+    let format = CodeFormat::Legacy;
+    let length = decompressed_length(code_region, format)?;
+    let mut code = Vec::with_capacity(length);
+    let mut bytes = code_region
+        .get(HEADER_SIZE..)
+        .unwrap_or_default()
+        .iter()
+        .copied();
+    let mut next_byte = || bytes.next().ok_or(DecompressError::Truncated { format });
+    while code.len() < length {
+        match next_byte()? {
+            0x00 => code.push(next_byte()?),
+            byte @ 0x01..0x3c => code.push(LEGACY_CHARACTERS[(byte - 1) as usize]),
+            byte => {
+                let next = next_byte()?;
+                let offset = usize::from(byte - 0x3c) * 16 + usize::from(next & 0x0f);
+                copy_back_reference(&mut code, offset, usize::from(next >> 4) + 2)?;
+            }
+        }
+    }
+    code.truncate(length);
+    Ok(code)
+}
+
+/// Reads the bits of each byte least-significant first
+struct BitReader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl BitReader<'_> {
+    fn bit(&mut self) -> Result<bool, DecompressError> {
+        let byte = self
+            .bytes
+            .get(self.position / 8)
+            .ok_or(DecompressError::Truncated {
+                format: CodeFormat::Pxa,
+            })?;
+        let bit = byte >> (self.position % 8) & 1 != 0;
+        self.position += 1;
+        Ok(bit)
+    }
+    fn bits(&mut self, count: u32) -> Result<usize, DecompressError> {
+        (0..count).try_fold(0, |value, index| {
+            self.bit().map(|bit| value | (usize::from(bit) << index))
+        })
+    }
+}
+
+fn decompress_pxa(code_region: &[u8]) -> Result<Vec<u8>, DecompressError> {
+    let length = decompressed_length(code_region, CodeFormat::Pxa)?;
+    let mut code = Vec::with_capacity(length);
+    let mut move_to_front: Vec<u8> = (0..=u8::MAX).collect();
+    let mut reader = BitReader {
+        bytes: &code_region[HEADER_SIZE.min(code_region.len())..],
+        position: 0,
+    };
+    while code.len() < length {
+        if reader.bit()? {
+            // A literal, as an index into the move-to-front table
+            let mut extra_bits = 0;
+            while reader.bit()? {
+                extra_bits += 1;
+            }
+            let index = reader.bits(4 + extra_bits)? + (((1 << extra_bits) - 1) << 4);
+            if index >= move_to_front.len() {
+                return Err(DecompressError::Truncated {
+                    format: CodeFormat::Pxa,
+                });
+            }
+            let byte = move_to_front.remove(index);
+            move_to_front.insert(0, byte);
+            code.push(byte);
+            continue;
+        }
+        let offset_bits = match reader.bit()? {
+            true if reader.bit()? => 5,
+            true => 10,
+            false => 15,
+        };
+        let offset = reader.bits(offset_bits)? + 1;
+        if offset_bits == 10 && offset == 1 {
+            // An uncompressed block, terminated by a zero-byte
+            loop {
+                match reader.bits(8)? {
+                    0 => break,
+                    byte => code.push(byte as u8),
+                }
+            }
+            continue;
+        }
+        let mut back_reference_length = 3;
+        loop {
+            let part = reader.bits(3)?;
+            back_reference_length += part;
+            if part != 7 {
+                break;
+            }
+        }
+        copy_back_reference(&mut code, offset, back_reference_length)?;
+    }
+    code.truncate(length);
+    Ok(code)
+}
+
+/// Writes the bits of each byte least-significant first
+#[derive(Default)]
+struct BitWriter {
+    bytes: Vec<u8>,
+    position: usize,
+}
+
+impl BitWriter {
+    fn bit(&mut self, bit: bool) {
+        if self.position.is_multiple_of(8) {
+            self.bytes.push(0);
+        }
+        if let Some(byte) = self.bytes.last_mut() {
+            *byte |= u8::from(bit) << (self.position % 8);
+        }
+        self.position += 1;
+    }
+    fn bits(&mut self, count: u32, value: usize) {
+        for index in 0..count {
+            self.bit(value >> index & 1 != 0);
+        }
+    }
+}
+
+/// The bits added to the 4 index-bits of a literal at the move-to-front index
+fn literal_extra_bits(index: usize) -> u32 {
+    let mut extra_bits = 0;
+    while index >= ((1 << (extra_bits + 1)) - 1) << 4 {
+        extra_bits += 1;
+    }
+    extra_bits
+}
+
+fn literal_cost(index: usize) -> usize {
+    let extra_bits = literal_extra_bits(index) as usize;
+    1 + (extra_bits + 1) + (4 + extra_bits)
+}
+
+fn offset_bits(offset: usize) -> u32 {
+    match offset - 1 {
+        0..32 => 5,
+        32..1024 => 10,
+        _ => 15,
+    }
+}
+
+fn back_reference_cost(offset: usize, length: usize) -> usize {
+    let offset_bits = offset_bits(offset) as usize;
+    let prefix_bits = if offset_bits == 15 { 1 } else { 2 };
+    1 + prefix_bits + offset_bits + 3 * ((length - MIN_BACK_REFERENCE_LENGTH) / 7 + 1)
+}
+
+/// Returns the offset and length of the longest earlier occurrence of the code at the position
+fn find_back_reference(
+    code: &[u8],
+    position: usize,
+    occurrences: &HashMap<&[u8], Vec<usize>>,
+) -> Option<(usize, usize)> {
+    let candidates = occurrences.get(code.get(position..position + MIN_BACK_REFERENCE_LENGTH)?)?;
+    candidates
+        .iter()
+        .rev()
+        .take(MAX_BACK_REFERENCE_CANDIDATES)
+        .map(|candidate| position - candidate)
+        .take_while(|offset| *offset <= MAX_BACK_REFERENCE_OFFSET)
+        .map(|offset| {
+            // The occurrence may overlap the position, like the copy when decompressing
+            let length = (position..code.len())
+                .take_while(|index| code[index - offset] == code[*index])
+                .count();
+            (offset, length)
+        })
+        .max_by_key(|(offset, length)| (*length, Reverse(*offset)))
+}
+
+/// Compresses P8SCII-code into the pxa format
+#[tracing::instrument(level = "debug", skip(code))]
+pub fn compress(code: &[u8]) -> Result<Vec<u8>, CompressError> {
+    let decompressed_length =
+        u16::try_from(code.len()).map_err(|_| CompressError::TooLarge { length: code.len() })?;
+    let mut writer = BitWriter::default();
+    let mut move_to_front: Vec<u8> = (0..=u8::MAX).collect();
+    let mut occurrences: HashMap<&[u8], Vec<usize>> = HashMap::new();
+    let mut position = 0;
+    while position < code.len() {
+        let back_reference =
+            find_back_reference(code, position, &occurrences).filter(|(offset, length)| {
+                let literals_cost: usize = code[position..position + length]
+                    .iter()
+                    .map(|byte| {
+                        let index = move_to_front.iter().position(|other| other == byte);
+                        literal_cost(index.unwrap_or_default())
+                    })
+                    .sum();
+                *length >= MIN_BACK_REFERENCE_LENGTH
+                    && back_reference_cost(*offset, *length) < literals_cost
+            });
+        let length = match back_reference {
+            Some((offset, length)) => {
+                writer.bit(false);
+                let offset_bits = offset_bits(offset);
+                match offset_bits {
+                    5 => writer.bits(2, 0b11),
+                    10 => writer.bits(2, 0b01),
+                    _ => writer.bit(false),
+                }
+                writer.bits(offset_bits, offset - 1);
+                let mut remaining_length = length - MIN_BACK_REFERENCE_LENGTH;
+                while remaining_length >= 7 {
+                    writer.bits(3, 7);
+                    remaining_length -= 7;
+                }
+                writer.bits(3, remaining_length);
+                length
+            }
+            None => {
+                let byte = code[position];
+                let index = move_to_front
+                    .iter()
+                    .position(|other| *other == byte)
+                    .expect("the table holds every byte");
+                let extra_bits = literal_extra_bits(index);
+                writer.bit(true);
+                writer.bits(extra_bits, (1 << extra_bits) - 1);
+                writer.bit(false);
+                writer.bits(4 + extra_bits, index - (((1 << extra_bits) - 1) << 4));
+                move_to_front.remove(index);
+                move_to_front.insert(0, byte);
+                1
+            }
+        };
+        for start in position..position + length {
+            if let Some(key) = code.get(start..start + MIN_BACK_REFERENCE_LENGTH) {
+                occurrences.entry(key).or_default().push(start);
+            }
+        }
+        position += length;
+    }
+
+    let compressed_length = HEADER_SIZE + writer.bytes.len();
+    let compressed_length =
+        u16::try_from(compressed_length).map_err(|_| CompressError::TooLarge {
+            length: compressed_length,
+        })?;
+    let mut compressed = PXA_HEADER.to_vec();
+    compressed.extend(decompressed_length.to_be_bytes());
+    compressed.extend(compressed_length.to_be_bytes());
+    compressed.extend(writer.bytes);
+    Ok(compressed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn legacy() {
+        // "abc" followed by a back-reference of offset 3 and length 3, then a literal `!`
+        let mut code_region = b":c:\0\0\x07\0\0".to_vec();
+        code_region.extend([0x0d, 0x0e, 0x0f, 0x3c, 0x13, 0x00, b'!']);
+        assert_eq!(decompress(&code_region).unwrap(), b"abcabc!");
+        assert_eq!(decompress(b"print(1)\0\0").unwrap(), b"print(1)");
+    }
+
+    #[test]
+    fn pxa_known_answer() {
+        // Hand-assembled as (count, value) fields, each value least-significant bit first
+        let fields = [
+            // The literals `abc`, at the move-to-front indices 97, 98 and 99: the literal-bit,
+            // the unary `110` for 2 extra index-bits, then the index minus 48 in 6 bits
+            (1, 1),
+            (3, 0b011),
+            (6, 97 - 48),
+            (1, 1),
+            (3, 0b011),
+            (6, 98 - 48),
+            (1, 1),
+            (3, 0b011),
+            (6, 99 - 48),
+            // Back-reference with the 5-bit prefix `1,1`, offset 3 and length 3: `abc`
+            (1, 0),
+            (2, 0b11),
+            (5, 3 - 1),
+            (3, 0),
+            // Back-reference with the 10-bit prefix `1,0`, offset 2 and length 3: `bcb`
+            (1, 0),
+            (2, 0b01),
+            (10, 2 - 1),
+            (3, 0),
+            // Back-reference with the 15-bit prefix `0`, offset 9 and length 3: `abc`
+            (1, 0),
+            (1, 0),
+            (15, 9 - 1),
+            (3, 0),
+        ];
+        let mut bits = Vec::new();
+        for (count, value) in fields {
+            bits.extend((0..count).map(|index| value >> index & 1));
+        }
+        let mut code_region = b"\0pxa\0\x0c\0\0".to_vec();
+        code_region.extend(bits.chunks(8).map(|chunk| {
+            chunk
+                .iter()
+                .enumerate()
+                .fold(0u8, |byte, (index, bit)| byte | (*bit as u8) << index)
+        }));
+        assert_eq!(decompress(&code_region).unwrap(), b"abcabcbcbabc");
+    }
+
+    #[test]
+    fn pxa_round_trip() {
+        let code = include_bytes!("../tests/carts/reference.p8");
+        let compressed = compress(code).unwrap();
+        assert_eq!(CodeFormat::detect(&compressed), CodeFormat::Pxa);
+        assert!(compressed.len() < code.len() / 2);
+        assert_eq!(decompress(&compressed).unwrap(), code);
+        assert_eq!(decompress(&compress(b"").unwrap()).unwrap(), b"");
+    }
+}
@@ -0,0 +1,309 @@
+//! Comparing two carts, as returned by [`CartData::diff`]
+//!
+//! Asset-sections are compared as a whole, code-tabs line by line, and the image-sections
+//! (`__gfx__` and `__label__`) also pixel by pixel. How the diff is shown is left to the caller
+
+use core::fmt;
+
+use crate::section::SectionType;
+use crate::{CartData, gfx, label};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DiffLine<'a> {
+    Unchanged(&'a [u8]),
+    Added(&'a [u8]),
+    Removed(&'a [u8]),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TabStatus {
+    Added,
+    Removed,
+    Changed,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TabDiff<'a> {
+    pub tab_index: usize,
+    pub status: TabStatus,
+    pub lines: Vec<DiffLine<'a>>,
+}
+
+/// The pixels changed in an image-section, a missing section counting as all zeros
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ImageDiff {
+    pub section: SectionType,
+    pub width: usize,
+    pub height: usize,
+    changed: Vec<bool>,
+}
+
+impl ImageDiff {
+    fn new(
+        section: SectionType,
+        width: usize,
+        height: usize,
+        old_pixel: impl Fn(usize, usize) -> Option<u8>,
+        new_pixel: impl Fn(usize, usize) -> Option<u8>,
+    ) -> ImageDiff {
+        let changed = (0..height)
+            .flat_map(|y| (0..width).map(move |x| (x, y)))
+            .map(|(x, y)| {
+                old_pixel(x, y).unwrap_or_default() != new_pixel(x, y).unwrap_or_default()
+            })
+            .collect();
+        ImageDiff {
+            section,
+            width,
+            height,
+            changed,
+        }
+    }
+    pub fn is_changed(&self, x: usize, y: usize) -> bool {
+        x < self.width && y < self.height && self.changed[y * self.width + x]
+    }
+    pub fn changed_count(&self) -> usize {
+        self.changed.iter().filter(|changed| **changed).count()
+    }
+    /// Whether any pixel changed in the square block, e.g. a sprite for a block-size of 8
+    pub fn is_block_changed(&self, block_x: usize, block_y: usize, block_size: usize) -> bool {
+        (0..block_size).any(|y| {
+            (0..block_size)
+                .any(|x| self.is_changed(block_x * block_size + x, block_y * block_size + y))
+        })
+    }
+}
+
+/// The changes from one cart to another
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct CartDiff<'a> {
+    /// The asset-sections which were changed, added or removed
+    pub sections: Vec<SectionType>,
+    /// The tabs which were changed, added or removed
+    pub tabs: Vec<TabDiff<'a>>,
+    /// The changed image-sections which could be decoded
+    pub images: Vec<ImageDiff>,
+}
+
+impl CartDiff<'_> {
+    pub fn is_empty(&self) -> bool {
+        self.sections.is_empty() && self.tabs.is_empty()
+    }
+}
+
+/// The pixel-diff of the image-section, `None` if either cart fails to decode it
+fn image_diff(old: &CartData<'_>, new: &CartData<'_>, section: SectionType) -> Option<ImageDiff> {
+    match section {
+        SectionType::Gfx => {
+            let (old_sheet, new_sheet) = (old.gfx_sheet().ok()?, new.gfx_sheet().ok()?);
+            Some(ImageDiff::new(
+                section,
+                gfx::WIDTH,
+                old_sheet.height().max(new_sheet.height()),
+                |x, y| old_sheet.get_pixel(x, y),
+                |x, y| new_sheet.get_pixel(x, y),
+            ))
+        }
+        SectionType::Label => {
+            let old_label = old.label_image().ok()?.unwrap_or_default();
+            let new_label = new.label_image().ok()?.unwrap_or_default();
+            Some(ImageDiff::new(
+                section,
+                label::SIZE,
+                label::SIZE,
+                |x, y| old_label.get_pixel(x, y),
+                |x, y| new_label.get_pixel(x, y),
+            ))
+        }
+        _ => None,
+    }
+}
+
+fn lines(code_data: &[u8]) -> Vec<&[u8]> {
+    let mut lines: Vec<&[u8]> = code_data.split(|byte| *byte == b'\n').collect();
+    if code_data.ends_with(b"\n") {
+        lines.pop();
+    }
+    lines
+}
+
+/// The line-diff by longest common subsequence, after skipping the common prefix and suffix
+fn diff_lines<'a>(old: &[&'a [u8]], new: &[&'a [u8]]) -> Vec<DiffLine<'a>> {
+    let prefix = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let old_middle = &old[prefix..old.len() - suffix];
+    let new_middle = &new[prefix..new.len() - suffix];
+
+    // lcs[i][j] is the length of the common subsequence of old_middle[i..] and new_middle[j..]
+    let width = new_middle.len() + 1;
+    let mut lcs = vec![0u32; (old_middle.len() + 1) * width];
+    for i in (0..old_middle.len()).rev() {
+        for j in (0..new_middle.len()).rev() {
+            lcs[i * width + j] = if old_middle[i] == new_middle[j] {
+                lcs[(i + 1) * width + j + 1] + 1
+            } else {
+                lcs[(i + 1) * width + j].max(lcs[i * width + j + 1])
+            };
+        }
+    }
+
+    let mut diff: Vec<DiffLine<'a>> = old[..prefix]
+        .iter()
+        .map(|line| DiffLine::Unchanged(line))
+        .collect();
+    let (mut i, mut j) = (0, 0);
+    while i < old_middle.len() || j < new_middle.len() {
+        if i < old_middle.len() && j < new_middle.len() && old_middle[i] == new_middle[j] {
+            diff.push(DiffLine::Unchanged(old_middle[i]));
+            i += 1;
+            j += 1;
+        } else if i < old_middle.len()
+            && (j == new_middle.len() || lcs[(i + 1) * width + j] >= lcs[i * width + j + 1])
+        {
+            diff.push(DiffLine::Removed(old_middle[i]));
+            i += 1;
+        } else {
+            diff.push(DiffLine::Added(new_middle[j]));
+            j += 1;
+        }
+    }
+    diff.extend(
+        old[old.len() - suffix..]
+            .iter()
+            .map(|line| DiffLine::Unchanged(line)),
+    );
+    diff
+}
+
+impl<'a> CartData<'a> {
+    /// Compares the cart with the other one, which is taken as the newer one
+    #[tracing::instrument(level = "debug", skip_all)]
+    pub fn diff<'s>(&'s self, other: &'s CartData<'_>) -> CartDiff<'s> {
+        let sections: Vec<SectionType> = self
+            .format_spec()
+            .asset_sections()
+            .filter(|section| self.section_data(*section) != other.section_data(*section))
+            .collect();
+        let images = sections
+            .iter()
+            .filter_map(|section| image_diff(self, other, *section))
+            .collect();
+        let tabs = self
+            .code_tabs
+            .iter()
+            .zip(other.code_tabs.iter())
+            .enumerate()
+            .filter_map(|(tab_index, (old, new))| {
+                let old_lines = old.as_ref().map(|tab| lines(&tab.code_data));
+                let new_lines = new.as_ref().map(|tab| lines(&tab.code_data));
+                let (status, lines) = match (old_lines, new_lines) {
+                    (None, None) => return None,
+                    (Some(old_lines), Some(new_lines)) if old_lines == new_lines => return None,
+                    (Some(old_lines), Some(new_lines)) => {
+                        (TabStatus::Changed, diff_lines(&old_lines, &new_lines))
+                    }
+                    (None, Some(new_lines)) => (
+                        TabStatus::Added,
+                        new_lines.into_iter().map(DiffLine::Added).collect(),
+                    ),
+                    (Some(old_lines), None) => (
+                        TabStatus::Removed,
+                        old_lines.into_iter().map(DiffLine::Removed).collect(),
+                    ),
+                };
+                Some(TabDiff {
+                    tab_index,
+                    status,
+                    lines,
+                })
+            })
+            .collect();
+        CartDiff {
+            sections,
+            tabs,
+            images,
+        }
+    }
+}
+
+/// Prints the changed sections and tabs, with the added and removed lines of each tab
+impl fmt::Display for CartDiff<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for section in &self.sections {
+            writeln!(f, "{} changed", <&'static str>::from(section))?;
+        }
+        for TabDiff {
+            tab_index,
+            status,
+            lines,
+        } in &self.tabs
+        {
+            let status = match status {
+                TabStatus::Added => "added",
+                TabStatus::Removed => "removed",
+                TabStatus::Changed => "changed",
+            };
+            writeln!(f, "tab {tab_index} {status}")?;
+            for line in lines {
+                match line {
+                    DiffLine::Unchanged(_) => {}
+                    DiffLine::Added(line) => writeln!(f, "+{}", String::from_utf8_lossy(line))?,
+                    DiffLine::Removed(line) => writeln!(f, "-{}", String::from_utf8_lossy(line))?,
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn diffs_tabs_and_sections() {
+        const OLD: &[u8] = b"pico-8 cartridge // http://www.pico-8.com
+version 43
+__lua__
+a=1
+b=2
+c=3
+-->8
+gone=true
+__gfx__
+00000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000
+";
+        const NEW: &[u8] = b"pico-8 cartridge // http://www.pico-8.com
+version 43
+__lua__
+a=1
+b=5
+c=3
+__gfx__
+00700000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000
+";
+        let old = CartData::from_cart_source(OLD).unwrap();
+        let new = CartData::from_cart_source(NEW).unwrap();
+        let diff = old.diff(&new);
+        assert_eq!(diff.sections, [SectionType::Gfx]);
+        assert_eq!(
+            diff.tabs[0].lines,
+            [
+                DiffLine::Unchanged(b"a=1"),
+                DiffLine::Removed(b"b=2"),
+                DiffLine::Added(b"b=5"),
+                DiffLine::Unchanged(b"c=3"),
+            ]
+        );
+        assert_eq!(diff.tabs[1].status, TabStatus::Removed);
+        assert_eq!(diff.images[0].changed_count(), 1);
+        assert!(diff.images[0].is_changed(2, 0));
+        assert!(diff.images[0].is_block_changed(0, 0, 8));
+        assert!(old.diff(&old).is_empty());
+    }
+}
@@ -0,0 +1,385 @@
+//! Embedding sprites into the code, as done by [`CartData::embed_sprites`]
+//!
+//! The embedded sprites are blanked in `__gfx__`, and a generated tab holds them as
+//! run-length encoded strings, with a loader drawing them back with `sset`:
+//!
+//! ```lua
+//! -- @embedded-sprites
+//! __embedded_sprites={
+//! {16,"#3&..."},
+//! }
+//! function load_sprites() ... end
+//! load_sprites()
+//! ```
+//!
+//! A string costs a single token however long it is, so the sprites cost the tokens
+//! of the loader, and the characters of the strings. The tab is read back by
+//! [`CartData::restore_embedded_sprites`] on the next build, so the cart keeps
+//! the sprites either way
+
+use core::fmt;
+use core::ops::RangeInclusive;
+
+use std::borrow::Cow;
+
+use crate::gfx::{SPRITE_SIZE, Sprite};
+use crate::hex::HexError;
+use crate::{CartData, Tab, tokens};
+
+/// The first line of the generated tab
+pub const EMBED_HEADER: &[u8] = b"-- @embedded-sprites";
+
+/// The first character of the encoding, after `"`
+const FIRST_CHAR: u8 = b'#';
+/// The longest run of a color a single character encodes
+const MAX_RUN: usize = 5;
+
+#[derive(Debug)]
+pub enum EmbedError {
+    /// The sprite is not in the sprite-sheet
+    SpriteOutOfRange {
+        sprite: usize,
+        sprite_count: usize,
+    },
+    /// Every tab is used, leaving none for the generated code
+    NoFreeTab,
+    /// The generated tab was edited, and the sprites cannot be read back
+    MalformedTab {
+        tab_index: usize,
+        line_number: usize,
+    },
+    Hex(HexError),
+}
+
+impl From<HexError> for EmbedError {
+    fn from(v: HexError) -> Self {
+        Self::Hex(v)
+    }
+}
+
+impl fmt::Display for EmbedError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let description = "Embed error";
+        let reason = match self {
+            EmbedError::SpriteOutOfRange {
+                sprite,
+                sprite_count,
+            } => format!("sprite {sprite} is not one of the {sprite_count} of the sprite-sheet"),
+            EmbedError::NoFreeTab => "no tab is left for the embedded sprites".to_string(),
+            EmbedError::MalformedTab {
+                tab_index,
+                line_number,
+            } => {
+                format!("line {line_number} of the embedded sprites in tab {tab_index} was edited")
+            }
+            EmbedError::Hex(e) => e.to_string(),
+        };
+        f.write_fmt(format_args!("{description}: {reason}"))
+    }
+}
+
+impl core::error::Error for EmbedError {}
+
+impl EmbedError {
+    /// The stable diagnostic-code, see `pico-build-rs explain`
+    pub const fn code(&self) -> &'static str {
+        match self {
+            EmbedError::Hex(e) => e.code(),
+            _ => "E014",
+        }
+    }
+}
+
+/// Parses a sprite, e.g. `16`, or an inclusive range of sprites, e.g. `16-31`
+pub fn parse_sprite_range(value: &str) -> Option<RangeInclusive<usize>> {
+    let (first, last) = value.split_once('-').unwrap_or((value, value));
+    let (first, last) = (first.trim().parse().ok()?, last.trim().parse().ok()?);
+    (first <= last).then_some(first..=last)
+}
+
+/// Which sprites to embed, and how they are loaded
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SpriteEmbedding {
+    pub ranges: Vec<RangeInclusive<usize>>,
+    /// The name of the generated function drawing the sprites back into the sheet
+    pub loader: String,
+    /// Whether the loader is called when the cart starts, rather than by the cart itself
+    pub load_at_init: bool,
+}
+
+impl Default for SpriteEmbedding {
+    fn default() -> Self {
+        SpriteEmbedding {
+            ranges: Vec::new(),
+            loader: "load_sprites".to_string(),
+            load_at_init: true,
+        }
+    }
+}
+
+/// The tradeoff of embedding the sprites, as reported after the build
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct EmbedReport {
+    pub tab_index: usize,
+    pub sprite_count: usize,
+    /// The bytes of sprite-memory blanked, 32 per sprite
+    pub gfx_bytes: usize,
+    pub code_chars: usize,
+    pub token_count: usize,
+}
+
+impl fmt::Display for EmbedReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let EmbedReport {
+            tab_index,
+            sprite_count,
+            gfx_bytes,
+            code_chars,
+            token_count,
+        } = self;
+        f.write_fmt(format_args!(
+            "Embedded {sprite_count} sprites ({gfx_bytes} bytes of __gfx__) into tab {tab_index}, costing {code_chars} chars and {token_count} tokens"
+        ))
+    }
+}
+
+/// Encodes runs of a color, up to [`MAX_RUN`] long, as a character each,
+/// skipping the `\` which would need escaping
+fn encode_pixels(pixels: &[u8]) -> String {
+    let mut encoded = String::new();
+    let mut index = 0;
+    while let Some(&color) = pixels.get(index) {
+        let run = pixels[index..]
+            .iter()
+            .take(MAX_RUN)
+            .take_while(|pixel| **pixel == color)
+            .count();
+        let mut byte = FIRST_CHAR + ((run - 1) * 16) as u8 + color;
+        if byte >= b'\\' {
+            byte += 1;
+        }
+        encoded.push(byte as char);
+        index += run;
+    }
+    encoded
+}
+
+fn decode_pixels(encoded: &[u8]) -> Option<Vec<u8>> {
+    let mut pixels = Vec::new();
+    for byte in encoded {
+        let mut value = byte.checked_sub(FIRST_CHAR)?;
+        if *byte > b'\\' {
+            value -= 1;
+        }
+        let run = usize::from(value / 16) + 1;
+        if run > MAX_RUN || *byte == b'\\' {
+            return None;
+        }
+        pixels.extend(core::iter::repeat_n(value % 16, run));
+    }
+    Some(pixels)
+}
+
+fn generated_tab(entries: &[(usize, String)], embedding: &SpriteEmbedding) -> Vec<u8> {
+    let mut code = String::from_utf8_lossy(EMBED_HEADER).into_owned();
+    code.push_str("\n-- generated by pico-build-rs, the sprites are blank in __gfx__\n");
+    code.push_str("__embedded_sprites={\n");
+    for (first_sprite, encoded) in entries {
+        code.push_str(&format!("{{{first_sprite},\"{encoded}\"}},\n"));
+    }
+    code.push_str("}\n");
+    let loader = &embedding.loader;
+    code.push_str(&format!(
+        "\
+function {loader}()
+ for e in all(__embedded_sprites) do
+  local n,s,k=e[1],e[2],0
+  for i=1,#s do
+   local v=ord(s,i)-35
+   if(v>57) v-=1
+   for _=0,v\\16 do
+    local m=n+k\\64
+    sset(m%16*8+k%8,m\\16*8+k\\8%8,v%16)
+    k+=1
+   end
+  end
+ end
+end
+"
+    ));
+    if embedding.load_at_init {
+        code.push_str(&format!("{loader}()\n"));
+    }
+    code.into_bytes()
+}
+
+/// The sprites of the generated tab, as the first sprite of each entry and its pixels
+fn parse_generated_tab(code: &[u8], tab_index: usize) -> Result<Vec<(usize, Vec<u8>)>, EmbedError> {
+    code.split(|byte| *byte == b'\n')
+        .enumerate()
+        .filter(|(_, line)| line.starts_with(b"{"))
+        .map(|(index, line)| {
+            let malformed = EmbedError::MalformedTab {
+                tab_index,
+                line_number: index + 1,
+            };
+            let entry = line
+                .strip_prefix(b"{")
+                .and_then(|line| line.strip_suffix(b"\"},"));
+            let Some((first_sprite, encoded)) = entry.and_then(|entry| {
+                let comma = entry.iter().position(|byte| *byte == b',')?;
+                let first_sprite = core::str::from_utf8(&entry[..comma]).ok()?.parse().ok()?;
+                Some((first_sprite, entry[comma + 1..].strip_prefix(b"\"")?))
+            }) else {
+                return Err(malformed);
+            };
+            decode_pixels(encoded)
+                .map(|pixels| (first_sprite, pixels))
+                .ok_or(malformed)
+        })
+        .collect()
+}
+
+impl CartData<'_> {
+    /// The index of the generated tab, if the cart has embedded sprites
+    pub fn embedded_sprites_tab(&self) -> Option<usize> {
+        self.code_tabs
+            .iter_present()
+            .find(|(_, tab)| tab.code_data.starts_with(EMBED_HEADER))
+            .map(|(tab_index, _)| tab_index)
+    }
+    /// Draws the embedded sprites back into `__gfx__`, and removes the generated tab
+    ///
+    /// A sprite drawn over since it was blanked keeps the new pixels.
+    /// Returns the number of sprites restored
+    #[tracing::instrument(level = "debug", skip(self))]
+    pub fn restore_embedded_sprites(&mut self) -> Result<usize, EmbedError> {
+        let Some(tab_index) = self.embedded_sprites_tab() else {
+            return Ok(0);
+        };
+        let code = self.code_tabs[tab_index]
+            .as_ref()
+            .map(|tab| tab.code_data.as_ref())
+            .unwrap_or_default();
+        let entries = parse_generated_tab(code, tab_index)?;
+        let mut gfx_sheet = self.gfx_sheet()?;
+        let mut restored = 0;
+        for (first_sprite, pixels) in entries {
+            for (offset, sprite_pixels) in
+                pixels.chunks_exact(SPRITE_SIZE * SPRITE_SIZE).enumerate()
+            {
+                let n = first_sprite + offset;
+                let is_blank = gfx_sheet
+                    .sprite(n)
+                    .is_some_and(|sprite| sprite.as_flattened().iter().all(|pixel| *pixel == 0));
+                if !is_blank {
+                    continue;
+                }
+                let sprite: Sprite = core::array::from_fn(|y| {
+                    core::array::from_fn(|x| sprite_pixels[y * SPRITE_SIZE + x])
+                });
+                gfx_sheet.set_sprite(n, &sprite);
+                restored += 1;
+            }
+        }
+        self.set_gfx_sheet(&gfx_sheet);
+        self.code_tabs.remove(tab_index);
+        Ok(restored)
+    }
+    /// Moves the sprites of the embedding into a generated tab, blanking them in `__gfx__`,
+    /// see the [module-documentation](crate::embed)
+    ///
+    /// Sprites embedded before are restored first, so embedding again gives the same cart
+    #[tracing::instrument(level = "debug", skip(self))]
+    pub fn embed_sprites(
+        &mut self,
+        embedding: &SpriteEmbedding,
+    ) -> Result<EmbedReport, EmbedError> {
+        self.restore_embedded_sprites()?;
+        let mut gfx_sheet = self.gfx_sheet()?;
+        let sprite_count = gfx_sheet.sprite_count();
+        let mut entries = Vec::new();
+        let mut embedded_count = 0;
+        for range in &embedding.ranges {
+            if *range.end() >= sprite_count {
+                return Err(EmbedError::SpriteOutOfRange {
+                    sprite: *range.end(),
+                    sprite_count,
+                });
+            }
+            let mut pixels = Vec::new();
+            for n in range.clone() {
+                if let Some(sprite) = gfx_sheet.sprite(n) {
+                    pixels.extend_from_slice(sprite.as_flattened());
+                    gfx_sheet.set_sprite(n, &Sprite::default());
+                    embedded_count += 1;
+                }
+            }
+            entries.push((*range.start(), encode_pixels(&pixels)));
+        }
+        let tab_index = self
+            .code_tabs
+            .iter_present()
+            .next_back()
+            .map_or(0, |(last_tab, _)| last_tab + 1);
+        if tab_index >= self.code_tabs.capacity() {
+            return Err(EmbedError::NoFreeTab);
+        }
+        let code = generated_tab(&entries, embedding);
+        let report = EmbedReport {
+            tab_index,
+            sprite_count: embedded_count,
+            gfx_bytes: embedded_count * SPRITE_SIZE * SPRITE_SIZE / 2,
+            code_chars: code.len(),
+            token_count: tokens::count_tokens(&code),
+        };
+        self.set_gfx_sheet(&gfx_sheet);
+        self.code_tabs.insert(
+            tab_index,
+            Tab {
+                line_number: 0,
+                code_data: Cow::Owned(code),
+            },
+        );
+        Ok(report)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn embeds_and_restores() {
+        let mut cart = CartData::default();
+        let mut gfx_sheet = cart.gfx_sheet().unwrap();
+        let mut sprite = Sprite::default();
+        sprite[3] = [7, 7, 7, 7, 7, 7, 7, 1];
+        sprite[7][0] = 0xc;
+        gfx_sheet.set_sprite(17, &sprite);
+        cart.set_gfx_sheet(&gfx_sheet);
+        let original_gfx = cart.gfx_sheet().unwrap();
+
+        let embedding = SpriteEmbedding {
+            ranges: vec![parse_sprite_range("16-17").unwrap()],
+            ..SpriteEmbedding::default()
+        };
+        let report = cart.embed_sprites(&embedding).unwrap();
+        assert_eq!((report.tab_index, report.sprite_count), (0, 2));
+        assert_eq!(
+            cart.gfx_sheet().unwrap().sprite(17),
+            Some(Sprite::default())
+        );
+
+        let embedded_code = cart.code_tabs()[0].as_ref().unwrap().code_data.clone();
+        cart.embed_sprites(&embedding).unwrap();
+        assert_eq!(
+            cart.code_tabs()[0].as_ref().unwrap().code_data,
+            embedded_code
+        );
+
+        assert_eq!(cart.restore_embedded_sprites().unwrap(), 2);
+        assert_eq!(cart.gfx_sheet().unwrap(), original_gfx);
+        assert_eq!(cart.embedded_sprites_tab(), None);
+    }
+}
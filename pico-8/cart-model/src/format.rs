@@ -0,0 +1,149 @@
+//! The cart-format of each pico-8 release, as one table
+//!
+//! The constants which differ between releases, the compression of the code, the sections
+//! of the `.p8`-file and the limits, are kept in a [`Spec`] per format, selected by the
+//! version of a cart or by the release-channel. Supporting a new release is adding its
+//! spec to [`SPECS`], with changes to the codecs only where the format itself changed.
+//!
+//! Carts of a version past [`LATEST_STABLE_VERSION`] were saved by a nightly build, and
+//! read with the [`NIGHTLY`] spec until a stable release pins the changes down
+
+use crate::compress::{CODE_REGION_SIZE, CodeFormat};
+use crate::section::SectionType;
+
+/// The version saved by the latest stable release known, as written by pico-8 0.2.6
+pub const LATEST_STABLE_VERSION: usize = 43;
+
+/// The sections of any `.p8`-file, in the order of the cart
+pub const SECTIONS: &[SectionType] = &[
+    SectionType::Lua,
+    SectionType::Gfx,
+    SectionType::Label,
+    SectionType::Gff,
+    SectionType::Map,
+    SectionType::Sfx,
+    SectionType::Music,
+];
+
+/// The release-channel of pico-8
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Channel {
+    #[default]
+    Stable,
+    /// The builds released to testers, whose format may change before the next stable
+    /// release
+    Nightly,
+}
+
+impl Channel {
+    pub fn from_name(name: &str) -> Option<Channel> {
+        match name.to_ascii_lowercase().as_str() {
+            "stable" => Some(Channel::Stable),
+            "nightly" => Some(Channel::Nightly),
+            _ => None,
+        }
+    }
+}
+
+/// The cart-format of the releases from a version on
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Spec {
+    /// The first version of the format, as written on the `version`-line of the header
+    pub first_version: usize,
+    /// The release introducing the format, e.g. `0.2.0`
+    pub release: &'static str,
+    pub channel: Channel,
+    /// The format the code is compressed with in the ROM
+    pub code_format: CodeFormat,
+    /// The sections of the `.p8`-file, in the order of the cart
+    pub sections: &'static [SectionType],
+    pub token_limit: usize,
+    /// The characters of the code the compressed format holds
+    pub char_limit: usize,
+    /// The bytes of the ROM the compressed code has to fit into
+    pub compressed_limit: usize,
+    /// The tabs of the code-editor
+    pub code_tab_count: usize,
+}
+
+/// The format of the 0.1 releases, compressing the code in the legacy `:c:` format
+pub const PICO_8_0_1: Spec = Spec {
+    first_version: 0,
+    release: "0.1.0",
+    channel: Channel::Stable,
+    code_format: CodeFormat::Legacy,
+    sections: SECTIONS,
+    token_limit: 8192,
+    char_limit: u16::MAX as usize,
+    compressed_limit: CODE_REGION_SIZE,
+    code_tab_count: 16,
+};
+
+/// The format since 0.2.0, compressing the code in the pxa format
+pub const PICO_8_0_2: Spec = Spec {
+    first_version: 18,
+    release: "0.2.0",
+    code_format: CodeFormat::Pxa,
+    ..PICO_8_0_1
+};
+
+/// The format of the latest stable release, see [`LATEST_STABLE_VERSION`]
+pub const LATEST_STABLE: Spec = PICO_8_0_2;
+
+/// The format of the nightly builds, as the latest stable one until a change of theirs is
+/// supported
+pub const NIGHTLY: Spec = Spec {
+    first_version: LATEST_STABLE_VERSION + 1,
+    release: "nightly",
+    channel: Channel::Nightly,
+    ..LATEST_STABLE
+};
+
+/// The specs, sorted by their first version
+pub const SPECS: &[Spec] = &[PICO_8_0_1, PICO_8_0_2, NIGHTLY];
+
+impl Spec {
+    /// The spec of carts of the version
+    pub fn for_version(version: usize) -> &'static Spec {
+        SPECS
+            .iter()
+            .rfind(|spec| spec.first_version <= version)
+            .unwrap_or(&SPECS[0])
+    }
+    /// The spec of new carts, saved by the latest release of the channel
+    pub fn for_channel(channel: Channel) -> &'static Spec {
+        SPECS
+            .iter()
+            .rfind(|spec| spec.channel == channel)
+            .unwrap_or(&SPECS[SPECS.len() - 1])
+    }
+    /// The sections of the assets, all but the code
+    pub fn asset_sections(&self) -> impl Iterator<Item = SectionType> + 'static {
+        self.sections
+            .iter()
+            .copied()
+            .filter(|section| *section != SectionType::Lua)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn selects_specs_by_version() {
+        assert!(SPECS.is_sorted_by_key(|spec| spec.first_version));
+        assert_eq!(Spec::for_version(16).code_format, CodeFormat::Legacy);
+        assert_eq!(Spec::for_version(18).release, "0.2.0");
+        assert_eq!(
+            Spec::for_version(LATEST_STABLE_VERSION).channel,
+            Channel::Stable
+        );
+        assert_eq!(
+            Spec::for_version(LATEST_STABLE_VERSION + 1).channel,
+            Channel::Nightly
+        );
+        assert_eq!(Spec::for_channel(Channel::Stable), &LATEST_STABLE);
+        assert_eq!(Spec::for_channel(Channel::Nightly), &NIGHTLY);
+    }
+}
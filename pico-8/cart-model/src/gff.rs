@@ -0,0 +1,131 @@
+//! A typed model of the `__gff__` sprite-flag section
+
+use core::fmt;
+
+use crate::hex::{self, HexError};
+
+/// The number of sprites with flags
+pub const SPRITE_COUNT: usize = 256;
+/// The number of flags per sprite
+pub const FLAG_COUNT: u8 = 8;
+/// The number of sprites per row of the section
+const SPRITES_PER_ROW: usize = 128;
+
+/// The flag-byte of every sprite, bit `n` being flag `n`
+#[derive(Clone, PartialEq, Eq)]
+pub struct SpriteFlags {
+    flags: Box<[u8]>,
+    /// The number of rows found when decoding,
+    /// kept so re-encoding does not add rows which were not there
+    source_rows: usize,
+}
+
+impl SpriteFlags {
+    pub fn new() -> SpriteFlags {
+        SpriteFlags {
+            flags: vec![0; SPRITE_COUNT].into_boxed_slice(),
+            source_rows: 0,
+        }
+    }
+    /// Decodes the hex-rows of a `__gff__` section, without the section-delimiter
+    #[tracing::instrument(level = "debug", skip(section_data))]
+    pub fn from_section_data(section_data: &[u8]) -> Result<SpriteFlags, HexError> {
+        let rows = hex::rows(
+            section_data,
+            SPRITES_PER_ROW * 2,
+            SPRITE_COUNT / SPRITES_PER_ROW,
+        )?;
+        let mut sprite_flags = SpriteFlags {
+            source_rows: rows.len(),
+            ..SpriteFlags::new()
+        };
+        for (row_index, row) in rows.into_iter().enumerate() {
+            let flags = hex::decode_bytes(row, row_index)?;
+            sprite_flags.flags[row_index * SPRITES_PER_ROW..(row_index + 1) * SPRITES_PER_ROW]
+                .copy_from_slice(&flags);
+        }
+        Ok(sprite_flags)
+    }
+    /// Encodes the rows of the `__gff__` section, each row terminated by a newline
+    ///
+    /// A trailing empty row beyond the rows originally decoded is left out
+    pub fn to_section_data(&self) -> Box<[u8]> {
+        let last_used_row = self
+            .flags
+            .chunks(SPRITES_PER_ROW)
+            .rposition(|row| row.iter().any(|flags| *flags != 0))
+            .map_or(0, |row_index| row_index + 1);
+        let row_count = last_used_row.max(self.source_rows);
+        self.flags
+            .chunks(SPRITES_PER_ROW)
+            .take(row_count)
+            .flat_map(|row| hex::encode_bytes(row).chain(core::iter::once(b'\n')))
+            .collect()
+    }
+    /// Returns the flag-byte of sprite `n`, or `0` if there is no such sprite
+    pub fn flags_for_sprite(&self, n: usize) -> u8 {
+        self.flags.get(n).copied().unwrap_or_default()
+    }
+    /// Overwrites the flag-byte of sprite `n`, sprites outside of `0..256` are ignored
+    pub fn set_flags_for_sprite(&mut self, n: usize, flags: u8) {
+        if let Some(sprite_flags) = self.flags.get_mut(n) {
+            *sprite_flags = flags;
+        }
+    }
+    /// Checks flag `flag` (`0..8`) of sprite `n`, like `fget(n, flag)`
+    pub fn get_flag(&self, n: usize, flag: u8) -> bool {
+        flag < FLAG_COUNT && self.flags_for_sprite(n) & (1 << flag) != 0
+    }
+    /// Sets flag `flag` (`0..8`) of sprite `n`, like `fset(n, flag, true)`
+    pub fn set_flag(&mut self, n: usize, flag: u8) {
+        if flag < FLAG_COUNT {
+            self.set_flags_for_sprite(n, self.flags_for_sprite(n) | (1 << flag));
+        }
+    }
+    /// Clears flag `flag` (`0..8`) of sprite `n`, like `fset(n, flag, false)`
+    pub fn clear_flag(&mut self, n: usize, flag: u8) {
+        if flag < FLAG_COUNT {
+            self.set_flags_for_sprite(n, self.flags_for_sprite(n) & !(1 << flag));
+        }
+    }
+}
+
+impl Default for SpriteFlags {
+    fn default() -> Self {
+        SpriteFlags::new()
+    }
+}
+
+impl fmt::Debug for SpriteFlags {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SpriteFlags")
+            .field("source_rows", &self.source_rows)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn flags() {
+        let mut section_data = b"0081".to_vec();
+        section_data.extend(core::iter::repeat_n(b'0', SPRITES_PER_ROW * 2 - 4));
+        section_data.push(b'\n');
+        let mut sprite_flags = SpriteFlags::from_section_data(&section_data).unwrap();
+        assert_eq!(sprite_flags.flags_for_sprite(1), 0x81);
+        assert!(sprite_flags.get_flag(1, 0) && sprite_flags.get_flag(1, 7));
+        assert_eq!(
+            sprite_flags.to_section_data().as_ref(),
+            section_data.as_slice()
+        );
+
+        sprite_flags.clear_flag(1, 7);
+        sprite_flags.set_flag(200, 2);
+        assert_eq!(sprite_flags.flags_for_sprite(1), 0x01);
+        let encoded = sprite_flags.to_section_data();
+        assert_eq!(bytes::NewlineIter::new(&encoded).count(), 2);
+        assert_eq!(&encoded[(SPRITES_PER_ROW * 2 + 1) + 72 * 2..][..2], b"04");
+    }
+}
@@ -0,0 +1,489 @@
+//! A typed model of the `__gfx__` sprite-sheet section
+
+use core::fmt;
+
+use crate::hex::{self, HexError};
+
+/// The width of the sprite-sheet in pixels
+pub const WIDTH: usize = 128;
+/// The height of the full sprite-sheet in pixels
+pub const FULL_HEIGHT: usize = 128;
+/// The height of the sprite-sheet when the lower half is not present,
+/// as the lower half is shared with the map-data
+pub const HALF_HEIGHT: usize = 64;
+/// The width and height of a single sprite in pixels
+pub const SPRITE_SIZE: usize = 8;
+
+/// A single 8x8 sprite, indexed by `[y][x]`
+pub type Sprite = [[u8; SPRITE_SIZE]; SPRITE_SIZE];
+
+/// How many rows [`GfxSheet::to_section_data_with`] writes
+///
+/// Carts saved by pico-8 only store the rows up to the last used one,
+/// while other tools may write all 128
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum GfxRows {
+    /// As many rows as were decoded, or more if pixels were set below them
+    #[default]
+    Preserve,
+    /// Only the rows up to the last used one
+    Minimal,
+    /// All 128 rows, including the lower half of a half-sheet as empty rows
+    Full,
+}
+
+impl GfxRows {
+    pub fn from_name(name: &str) -> Option<GfxRows> {
+        match name.to_ascii_lowercase().as_str() {
+            "preserve" => Some(GfxRows::Preserve),
+            "minimal" => Some(GfxRows::Minimal),
+            "full" => Some(GfxRows::Full),
+            _ => None,
+        }
+    }
+}
+
+/// The decoded pixels of a sprite-sheet, one palette-index (`0..16`) per pixel
+#[derive(Clone, PartialEq, Eq)]
+pub struct GfxSheet {
+    pixels: Box<[u8]>,
+    height: usize,
+    /// The number of rows found when decoding,
+    /// kept so re-encoding does not add rows which were not there
+    source_rows: usize,
+}
+
+impl GfxSheet {
+    /// An empty sheet of the given height
+    pub fn new(height: usize) -> GfxSheet {
+        GfxSheet {
+            pixels: vec![0; WIDTH * height].into_boxed_slice(),
+            height,
+            source_rows: 0,
+        }
+    }
+    /// Decodes the hex-rows of a `__gfx__` section, without the section-delimiter
+    ///
+    /// Sheets of up to 64 rows are decoded as 128x64, any more as 128x128
+    #[tracing::instrument(level = "debug", skip(section_data))]
+    pub fn from_section_data(section_data: &[u8]) -> Result<GfxSheet, HexError> {
+        let rows = hex::rows(section_data, WIDTH, FULL_HEIGHT)?;
+        let height = if rows.len() > HALF_HEIGHT {
+            FULL_HEIGHT
+        } else {
+            HALF_HEIGHT
+        };
+        let mut sheet = GfxSheet {
+            source_rows: rows.len(),
+            ..GfxSheet::new(height)
+        };
+        for (row_index, row) in rows.into_iter().enumerate() {
+            let pixels = hex::decode_nibbles(row, row_index)?;
+            sheet.pixels[row_index * WIDTH..(row_index + 1) * WIDTH].copy_from_slice(&pixels);
+        }
+        Ok(sheet)
+    }
+    /// Encodes the sheet back into the hex-rows of a `__gfx__` section,
+    /// each row terminated by a newline
+    ///
+    /// Trailing empty rows beyond the rows originally decoded are left out,
+    /// see [`GfxRows::Preserve`]
+    pub fn to_section_data(&self) -> Box<[u8]> {
+        self.to_section_data_with(GfxRows::Preserve)
+    }
+    /// Encodes the sheet like [`GfxSheet::to_section_data`], with the given rows
+    pub fn to_section_data_with(&self, gfx_rows: GfxRows) -> Box<[u8]> {
+        let row_count = match gfx_rows {
+            GfxRows::Preserve => self.rows_used().max(self.source_rows),
+            GfxRows::Minimal => self.rows_used(),
+            GfxRows::Full => FULL_HEIGHT,
+        };
+        let empty_row = [0; WIDTH];
+        self.pixels
+            .chunks(WIDTH)
+            .chain(core::iter::repeat(empty_row.as_slice()))
+            .take(row_count)
+            .flat_map(|row| {
+                row.iter()
+                    .map(|pixel| hex::DIGITS[*pixel as usize])
+                    .chain(core::iter::once(b'\n'))
+            })
+            .collect()
+    }
+    /// The number of rows up to and including the last row with a non-zero pixel
+    pub fn rows_used(&self) -> usize {
+        self.pixels
+            .chunks(WIDTH)
+            .rposition(|row| row.iter().any(|pixel| *pixel != 0))
+            .map_or(0, |row_index| row_index + 1)
+    }
+    /// The number of rows the sheet was decoded from, 0 for a new sheet
+    pub const fn source_rows(&self) -> usize {
+        self.source_rows
+    }
+    pub const fn height(&self) -> usize {
+        self.height
+    }
+    /// The number of sprites in the sheet, 128 for a half-sheet or 256 for a full one
+    pub const fn sprite_count(&self) -> usize {
+        (WIDTH / SPRITE_SIZE) * (self.height / SPRITE_SIZE)
+    }
+    /// Returns the palette-index of the pixel, if within the sheet
+    pub fn get_pixel(&self, x: usize, y: usize) -> Option<u8> {
+        (x < WIDTH && y < self.height).then(|| self.pixels[y * WIDTH + x])
+    }
+    /// Sets the palette-index of the pixel, returning the previous one
+    ///
+    /// Returns `None` without modifying the sheet if the pixel is outside the sheet,
+    /// or if the color is not a palette-index
+    pub fn set_pixel(&mut self, x: usize, y: usize, color: u8) -> Option<u8> {
+        if x >= WIDTH || y >= self.height || color as usize >= hex::DIGITS.len() {
+            return None;
+        }
+        Some(core::mem::replace(&mut self.pixels[y * WIDTH + x], color))
+    }
+    /// Extends a half-sheet to a full one, with the lower half empty
+    pub(crate) fn extend_to_full_height(&mut self) {
+        if self.height < FULL_HEIGHT {
+            let mut pixels = self.pixels.to_vec();
+            pixels.resize(WIDTH * FULL_HEIGHT, 0);
+            self.pixels = pixels.into_boxed_slice();
+            self.height = FULL_HEIGHT;
+        }
+    }
+    /// The pixel-coordinates of the top-left corner of the sprite
+    const fn sprite_origin(n: usize) -> (usize, usize) {
+        let sprites_per_row = WIDTH / SPRITE_SIZE;
+        (
+            (n % sprites_per_row) * SPRITE_SIZE,
+            (n / sprites_per_row) * SPRITE_SIZE,
+        )
+    }
+    /// Returns the pixels of sprite `n`, counting left-to-right, top-to-bottom
+    pub fn sprite(&self, n: usize) -> Option<Sprite> {
+        if n >= self.sprite_count() {
+            return None;
+        }
+        let (origin_x, origin_y) = GfxSheet::sprite_origin(n);
+        Some(core::array::from_fn(|y| {
+            core::array::from_fn(|x| self.pixels[(origin_y + y) * WIDTH + origin_x + x])
+        }))
+    }
+    /// Overwrites the pixels of sprite `n`, returning `None` if there is no such sprite
+    pub fn set_sprite(&mut self, n: usize, sprite: &Sprite) -> Option<()> {
+        if n >= self.sprite_count() {
+            return None;
+        }
+        let (origin_x, origin_y) = GfxSheet::sprite_origin(n);
+        for (y, row) in sprite.iter().enumerate() {
+            for (x, color) in row.iter().enumerate() {
+                self.pixels[(origin_y + y) * WIDTH + origin_x + x] = color & 0x0f;
+            }
+        }
+        Some(())
+    }
+}
+
+impl fmt::Debug for GfxSheet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("GfxSheet")
+            .field("height", &self.height)
+            .field("source_rows", &self.source_rows)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(feature = "png")]
+mod png_io {
+    use core::fmt;
+
+    use std::io;
+
+    use super::{FULL_HEIGHT, GfxSheet, HALF_HEIGHT, WIDTH};
+    use crate::hex::HexError;
+    use crate::label;
+
+    /// Pixels at most this opaque are imported as color `0`, the transparent one
+    const TRANSPARENT_ALPHA: u8 = 0x7f;
+
+    #[derive(Debug)]
+    pub enum GfxPngError {
+        Decoding(png::DecodingError),
+        Encoding(png::EncodingError),
+        /// The image is wider or taller than the sprite-sheet
+        InvalidSize {
+            width: u32,
+            height: u32,
+        },
+        /// The image placed at the sprite does not fit into the sprite-sheet
+        OutOfSheet {
+            sprite: usize,
+            width: u32,
+            height: u32,
+        },
+        /// The image could not be expanded into 8-bit gray or RGB, with or without alpha
+        UnsupportedColorType(png::ColorType),
+        Hex(HexError),
+    }
+
+    impl From<png::DecodingError> for GfxPngError {
+        fn from(v: png::DecodingError) -> Self {
+            Self::Decoding(v)
+        }
+    }
+
+    impl From<png::EncodingError> for GfxPngError {
+        fn from(v: png::EncodingError) -> Self {
+            Self::Encoding(v)
+        }
+    }
+
+    impl From<HexError> for GfxPngError {
+        fn from(v: HexError) -> Self {
+            Self::Hex(v)
+        }
+    }
+
+    impl fmt::Display for GfxPngError {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            let description = "Sprite-sheet png error";
+            let reason = match self {
+                GfxPngError::Decoding(e) => e.to_string(),
+                GfxPngError::Encoding(e) => e.to_string(),
+                GfxPngError::InvalidSize { width, height } => {
+                    format!("image is {width}x{height}, expected at most {WIDTH}x{FULL_HEIGHT}")
+                }
+                GfxPngError::OutOfSheet {
+                    sprite,
+                    width,
+                    height,
+                } => format!("a {width}x{height} image at sprite {sprite} overflows the sheet"),
+                GfxPngError::UnsupportedColorType(color_type) => {
+                    format!("unsupported color-type {color_type:?}")
+                }
+                GfxPngError::Hex(e) => e.to_string(),
+            };
+            f.write_fmt(format_args!("{description}: {reason}"))
+        }
+    }
+
+    impl core::error::Error for GfxPngError {}
+
+    impl GfxPngError {
+        /// The stable diagnostic-code, see `pico-build-rs explain`
+        pub const fn code(&self) -> &'static str {
+            match self {
+                GfxPngError::Hex(e) => e.code(),
+                _ => "E016",
+            }
+        }
+    }
+
+    /// What [`GfxSheet::import_png`] drew into the sheet
+    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+    pub struct GfxImport {
+        pub sprite: usize,
+        pub width: usize,
+        pub height: usize,
+        /// The opaque pixels which were not exactly a color of the palette
+        pub approximated: usize,
+    }
+
+    impl fmt::Display for GfxImport {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.write_fmt(format_args!(
+                "{}x{} pixels at sprite {}",
+                self.width, self.height, self.sprite
+            ))?;
+            if self.approximated > 0 {
+                f.write_fmt(format_args!(
+                    ", {} mapped to the nearest color",
+                    self.approximated
+                ))?;
+            }
+            Ok(())
+        }
+    }
+
+    impl GfxSheet {
+        /// Writes the sheet as a RGB png, 128 pixels wide and as high as the sheet
+        #[tracing::instrument(level = "debug", skip(self, writer))]
+        pub fn write_png<W: io::Write>(&self, writer: W) -> Result<(), GfxPngError> {
+            let mut encoder = png::Encoder::new(writer, WIDTH as u32, self.height as u32);
+            encoder.set_color(png::ColorType::Rgb);
+            encoder.set_depth(png::BitDepth::Eight);
+            let mut png_writer = encoder.write_header()?;
+            let rgb: Vec<u8> = self
+                .pixels
+                .iter()
+                .flat_map(|pixel| label::PALETTE[*pixel as usize])
+                .collect();
+            png_writer.write_image_data(&rgb)?;
+            png_writer.finish().map_err(Into::into)
+        }
+        /// Draws a png of at most 128x128 pixels into the sheet, its top-left corner
+        /// at the sprite, mapping each pixel to the nearest color of the palette
+        ///
+        /// Transparent pixels are drawn as color `0`. A half-sheet is extended to a
+        /// full one if the image reaches into the lower half, shared with the map
+        #[tracing::instrument(level = "debug", skip(self, reader))]
+        pub fn import_png<R: io::Read>(
+            &mut self,
+            reader: R,
+            sprite: usize,
+        ) -> Result<GfxImport, GfxPngError> {
+            let mut decoder = png::Decoder::new(reader);
+            decoder
+                .set_transformations(png::Transformations::EXPAND | png::Transformations::STRIP_16);
+            let mut png_reader = decoder.read_info()?;
+            let mut buf = vec![0; png_reader.output_buffer_size()];
+            let frame = png_reader.next_frame(&mut buf)?;
+            let channels = match frame.color_type {
+                png::ColorType::Grayscale => 1,
+                png::ColorType::GrayscaleAlpha => 2,
+                png::ColorType::Rgb => 3,
+                png::ColorType::Rgba => 4,
+                color_type => return Err(GfxPngError::UnsupportedColorType(color_type)),
+            };
+            let rgba: Vec<u8> = buf[..frame.buffer_size()]
+                .chunks_exact(channels)
+                .flat_map(|pixel| match *pixel {
+                    [gray] => [gray, gray, gray, u8::MAX],
+                    [gray, alpha] => [gray, gray, gray, alpha],
+                    [red, green, blue] => [red, green, blue, u8::MAX],
+                    [red, green, blue, alpha] => [red, green, blue, alpha],
+                    _ => unreachable!("chunks of the channels"),
+                })
+                .collect();
+            self.import_rgba(frame.width as usize, frame.height as usize, &rgba, sprite)
+        }
+        /// Draws RGBA-pixels, row by row, of an image of at most 128x128 pixels into the
+        /// sheet, like [`GfxSheet::import_png`]
+        #[tracing::instrument(level = "debug", skip(self, rgba))]
+        pub fn import_rgba(
+            &mut self,
+            width: usize,
+            height: usize,
+            rgba: &[u8],
+            sprite: usize,
+        ) -> Result<GfxImport, GfxPngError> {
+            if width > WIDTH || height > FULL_HEIGHT || rgba.len() != width * height * 4 {
+                return Err(GfxPngError::InvalidSize {
+                    width: width as u32,
+                    height: height as u32,
+                });
+            }
+            let (origin_x, origin_y) = GfxSheet::sprite_origin(sprite);
+            if origin_x + width > WIDTH || origin_y + height > FULL_HEIGHT {
+                return Err(GfxPngError::OutOfSheet {
+                    sprite,
+                    width: width as u32,
+                    height: height as u32,
+                });
+            }
+            if origin_y + height > HALF_HEIGHT {
+                self.extend_to_full_height();
+            }
+            let mut approximated = 0;
+            for (index, pixel) in rgba.chunks_exact(4).enumerate() {
+                let (rgb, alpha) = ([pixel[0], pixel[1], pixel[2]], pixel[3]);
+                let color = if alpha <= TRANSPARENT_ALPHA {
+                    0
+                } else {
+                    let color = label::nearest_standard_color(rgb);
+                    if label::PALETTE[color as usize] != rgb {
+                        approximated += 1;
+                    }
+                    color
+                };
+                self.set_pixel(origin_x + index % width, origin_y + index / width, color);
+            }
+            Ok(GfxImport {
+                sprite,
+                width,
+                height,
+                approximated,
+            })
+        }
+    }
+}
+
+#[cfg(feature = "png")]
+pub use png_io::{GfxImport, GfxPngError};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        const DEFAULT_GFX: &[u8] = b"00000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000
+00000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000
+00700700000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000
+00077000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000
+";
+        let mut sheet = GfxSheet::from_section_data(DEFAULT_GFX).unwrap();
+        assert_eq!(sheet.height(), HALF_HEIGHT);
+        assert_eq!(sheet.get_pixel(2, 2), Some(7));
+        assert_eq!(sheet.sprite(0).unwrap()[3][3], 7);
+        assert_eq!(sheet.to_section_data().as_ref(), DEFAULT_GFX);
+
+        assert_eq!(sheet.set_pixel(127, 5, 0xc), Some(0));
+        let encoded = sheet.to_section_data();
+        assert_eq!(bytes::NewlineIter::new(&encoded).count(), 6);
+        assert_eq!(encoded[5 * (WIDTH + 1) + 127], b'c');
+        assert_eq!(sheet.rows_used(), 6);
+        assert_eq!(sheet.source_rows(), 4);
+
+        sheet.set_pixel(127, 5, 0);
+        sheet.set_pixel(2, 2, 0);
+        sheet.set_pixel(3, 3, 0);
+        sheet.set_pixel(4, 3, 0);
+        sheet.set_pixel(5, 2, 0);
+        assert_eq!(sheet.rows_used(), 0);
+        assert_eq!(sheet.to_section_data_with(GfxRows::Minimal).as_ref(), b"");
+        let full = sheet.to_section_data_with(GfxRows::Full);
+        assert_eq!(bytes::NewlineIter::new(&full).count(), FULL_HEIGHT);
+        let reparsed = GfxSheet::from_section_data(&full).unwrap();
+        assert_eq!(reparsed.height(), FULL_HEIGHT);
+        assert_eq!(reparsed.to_section_data(), full);
+    }
+
+    #[cfg(feature = "png")]
+    #[test]
+    fn imports_png() {
+        // A 16x16 image of color 8, with a transparent and an off-palette pixel
+        let mut rgba: Vec<u8> = [0xff, 0x00, 0x4d, 0xff].repeat(16 * 16);
+        rgba[..4].copy_from_slice(&[0xff, 0xff, 0xff, 0x00]);
+        rgba[4..8].copy_from_slice(&[0xf0, 0x10, 0x50, 0xff]);
+        let mut png_data = Vec::new();
+        let mut encoder = png::Encoder::new(&mut png_data, 16, 16);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut png_writer = encoder.write_header().unwrap();
+        png_writer.write_image_data(&rgba).unwrap();
+        png_writer.finish().unwrap();
+
+        let mut sheet = GfxSheet::new(HALF_HEIGHT);
+        let import = sheet.import_png(png_data.as_slice(), 17).unwrap();
+        assert_eq!(import.approximated, 1);
+        assert_eq!(sheet.get_pixel(8, 8), Some(0));
+        assert_eq!(sheet.get_pixel(9, 8), Some(8));
+        assert_eq!(sheet.get_pixel(23, 23), Some(8));
+        assert_eq!(sheet.get_pixel(24, 23), Some(0));
+        assert_eq!(sheet.height(), HALF_HEIGHT);
+
+        sheet.import_png(png_data.as_slice(), 238).unwrap();
+        assert_eq!(sheet.height(), FULL_HEIGHT);
+        assert!(matches!(
+            sheet.import_png(png_data.as_slice(), 127),
+            Err(GfxPngError::OutOfSheet { sprite: 127, .. })
+        ));
+
+        let mut written = Vec::new();
+        sheet.write_png(&mut written).unwrap();
+        let mut reimported = GfxSheet::new(HALF_HEIGHT);
+        reimported.import_png(written.as_slice(), 0).unwrap();
+        assert!(reimported == sheet);
+    }
+}
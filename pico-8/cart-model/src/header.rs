@@ -1,25 +1,41 @@
+//! The header of a cart, its marker-line and version-line
+//!
+//! The [`Header`] and [`Version`] are slice-types borrowed from the cart-source, unless
+//! the `forbid-unsafe` feature is enabled, which copies them out of it instead. Both are
+//! used through the same functions, [`split_from`] and [`try_split_from`] returning the
+//! header as a [`Cow`]
+
+#[cfg(not(feature = "forbid-unsafe"))]
 use core::borrow::Borrow;
 use core::fmt;
+#[cfg(not(feature = "forbid-unsafe"))]
 use core::mem::transmute;
+#[cfg(not(feature = "forbid-unsafe"))]
 use core::ops::Deref;
 
 use alloc::borrow::Cow;
 
 // use crate::bytes;
 
+/// The header of carts created without one, e.g. [`crate::CartData::default`]
+const DEFAULT_HEADER: &[u8] = b"pico-8 cartridge // http://www.pico-8.com\nversion 43\n";
+
 // Type definitions,
 // conversion constructors,
 // and boilerplate for Owned/Borrow
 
+#[cfg(not(feature = "forbid-unsafe"))]
 #[repr(transparent)]
 pub struct Header([u8]);
 
+#[cfg(not(feature = "forbid-unsafe"))]
 impl AsRef<[u8]> for Header {
     fn as_ref(&self) -> &[u8] {
         &self.0
     }
 }
 
+#[cfg(not(feature = "forbid-unsafe"))]
 impl Header {
     pub fn copy_to_boxed_slice(&self) -> Box<[u8]> {
         let Header(bytes) = self;
@@ -38,6 +54,7 @@ impl Header {
     }
 }
 
+#[cfg(not(feature = "forbid-unsafe"))]
 impl ToOwned for Header {
     type Owned = HeaderBuf;
     fn to_owned(&self) -> Self::Owned {
@@ -45,29 +62,27 @@ impl ToOwned for Header {
     }
 }
 
+#[cfg(not(feature = "forbid-unsafe"))]
 impl Default for &'static Header {
     fn default() -> Self {
-        const DEFAULT_HEADER: &Header = unsafe {
-            core::mem::transmute(
-                br"pico-8 cartridge // http://www.pico-8.com
-version 43
-"
-                .as_slice(),
-            )
-        };
-        DEFAULT_HEADER
+        // SAFETY: The default header is a valid header
+        const DEFAULT: &Header = unsafe { Header::from_slice(DEFAULT_HEADER) };
+        DEFAULT
     }
 }
 
+#[cfg(not(feature = "forbid-unsafe"))]
 #[repr(transparent)]
 pub struct HeaderBuf(Box<[u8]>);
 
+#[cfg(not(feature = "forbid-unsafe"))]
 impl From<Box<[u8]>> for HeaderBuf {
     fn from(value: Box<[u8]>) -> Self {
         HeaderBuf(value)
     }
 }
 
+#[cfg(not(feature = "forbid-unsafe"))]
 impl Deref for HeaderBuf {
     type Target = Header;
     fn deref(&self) -> &Self::Target {
@@ -79,27 +94,22 @@ impl Deref for HeaderBuf {
     }
 }
 
+#[cfg(not(feature = "forbid-unsafe"))]
 impl Borrow<Header> for HeaderBuf {
     fn borrow(&self) -> &Header {
         self
     }
 }
 
+#[cfg(not(feature = "forbid-unsafe"))]
 #[repr(transparent)]
 pub struct Version([u8]);
+
+#[cfg(not(feature = "forbid-unsafe"))]
 impl Version {
-    // #[tracing::instrument(level = "debug", ret)]
-    pub fn parse(&self) -> Result<usize, <usize as core::str::FromStr>::Err> {
+    fn as_str(&self) -> &str {
         // SAFETY: This version-line will always be utf-8 according to pico-8 spec
-        let src = unsafe { core::str::from_utf8_unchecked(&self.0) };
-        let ("version ", version_number_string) = src.split_at("version ".len()) else {
-            panic!("malformed version encountered");
-        };
-        version_number_string
-            // There will be trailing newline
-            .trim_end()
-            .parse()
-            .inspect_err(|e| tracing::error!("failed to parse version-number {e}"))
+        unsafe { core::str::from_utf8_unchecked(&self.0) }
     }
     /// # Safety
     /// This function is primarly inteded for slice-conversion
@@ -114,6 +124,126 @@ impl Version {
     }
 }
 
+/// Borrows the header from the slice of the cart-source
+#[cfg(not(feature = "forbid-unsafe"))]
+fn header_of(slice: &[u8]) -> Cow<'_, Header> {
+    // SAFETY: The slice was checked to be a marker- and version-line by the caller
+    Cow::Borrowed(unsafe { Header::from_slice(slice) })
+}
+
+#[cfg(not(feature = "forbid-unsafe"))]
+impl Header {
+    fn get_as_tuple(&self) -> Option<(&[u8], &Version)> {
+        self.0
+            .split_at_checked(CARTRIDGE_MARKER.len())
+            .filter(|(marker, _)| *marker == CARTRIDGE_MARKER)
+            .map(|(marker, version)| {
+                // SAFETY: The remainder of a header following its marker is the
+                // version-line
+                (marker, unsafe { Version::from_slice(version) })
+            })
+    }
+    pub fn get_version(&self) -> Option<&Version> {
+        self.get_as_tuple().map(|(_, v)| v)
+    }
+}
+
+#[cfg(not(feature = "forbid-unsafe"))]
+impl fmt::Debug for HeaderBuf {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let h: &Header = self;
+        h.fmt(f)
+    }
+}
+
+/// The header, copied out of the cart-source, as its slice-type can not be borrowed
+/// from it without `unsafe`
+#[cfg(feature = "forbid-unsafe")]
+#[derive(Clone)]
+pub struct Header {
+    bytes: Cow<'static, [u8]>,
+    version: Option<Version>,
+}
+
+/// The owned header, the same type as the [`Header`] with `forbid-unsafe`
+#[cfg(feature = "forbid-unsafe")]
+pub type HeaderBuf = Header;
+
+#[cfg(feature = "forbid-unsafe")]
+impl AsRef<[u8]> for Header {
+    fn as_ref(&self) -> &[u8] {
+        &self.bytes
+    }
+}
+
+#[cfg(feature = "forbid-unsafe")]
+impl Header {
+    pub fn copy_to_boxed_slice(&self) -> Box<[u8]> {
+        Box::from(self.bytes.as_ref())
+    }
+    pub fn get_version(&self) -> Option<&Version> {
+        self.version.as_ref()
+    }
+}
+
+#[cfg(feature = "forbid-unsafe")]
+impl From<Box<[u8]>> for Header {
+    fn from(value: Box<[u8]>) -> Self {
+        let version = value
+            .strip_prefix(CARTRIDGE_MARKER)
+            .map(|version| Version(Cow::Owned(version.to_vec())));
+        Header {
+            bytes: Cow::Owned(value.into_vec()),
+            version,
+        }
+    }
+}
+
+#[cfg(feature = "forbid-unsafe")]
+impl Default for &'static Header {
+    fn default() -> Self {
+        static DEFAULT: Header = Header {
+            bytes: Cow::Borrowed(DEFAULT_HEADER),
+            version: Some(Version(Cow::Borrowed(
+                DEFAULT_HEADER.split_at(CARTRIDGE_MARKER.len()).1,
+            ))),
+        };
+        &DEFAULT
+    }
+}
+
+#[cfg(feature = "forbid-unsafe")]
+#[derive(Clone)]
+pub struct Version(Cow<'static, [u8]>);
+
+#[cfg(feature = "forbid-unsafe")]
+impl Version {
+    fn as_str(&self) -> &str {
+        // Checked, though a version-line is always utf-8 according to pico-8 spec
+        core::str::from_utf8(&self.0).unwrap_or_default()
+    }
+}
+
+/// Copies the header out of the slice of the cart-source
+#[cfg(feature = "forbid-unsafe")]
+fn header_of(slice: &[u8]) -> Cow<'_, Header> {
+    Cow::Owned(Header::from(Box::from(slice)))
+}
+
+impl Version {
+    // #[tracing::instrument(level = "debug", ret)]
+    pub fn parse(&self) -> Result<usize, <usize as core::str::FromStr>::Err> {
+        let src = self.as_str();
+        // A line without the prefix fails to parse as a number
+        let version_number_string = src.strip_prefix(VERSION_PREFIX).unwrap_or(src);
+        version_number_string
+            // There will be trailing newline
+            .trim_end()
+            .parse()
+            .inspect_err(|e| tracing::error!("failed to parse version-number {e}"))
+    }
+}
+
 impl fmt::Debug for Version {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         if let Ok(num) = self.parse() {
@@ -125,26 +255,11 @@ impl fmt::Debug for Version {
 }
 
 const CARTRIDGE_MARKER: &[u8] = b"pico-8 cartridge // http://www.pico-8.com\n";
+const VERSION_PREFIX: &str = "version ";
 
 // Main header implementation
 //
 
-impl Header {
-    fn get_as_tuple(&self) -> Option<(&[u8], &Version)> {
-        let (CARTRIDGE_MARKER, version) = self
-            .0
-            .split_at_checked(CARTRIDGE_MARKER.len())
-            .map(|(marker, version)| (marker, unsafe { Version::from_slice(version) }))?
-        else {
-            panic!("encountered malformed cartridge marker in header");
-        };
-        Some((CARTRIDGE_MARKER, version))
-    }
-    pub fn get_version(&self) -> Option<&Version> {
-        self.get_as_tuple().map(|(_, v)| v)
-    }
-}
-
 impl fmt::Debug for Header {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         if let Some(version) = self.get_version() {
@@ -153,16 +268,11 @@ impl fmt::Debug for Header {
                 // Cartridge-marker will always be identical
                 .finish_non_exhaustive()
         } else {
-            f.debug_tuple("Header").field(&&self.0).finish()
+            let bytes: &[u8] = self.as_ref();
+            f.debug_tuple("Header").field(&bytes).finish()
         }
     }
 }
-impl fmt::Debug for HeaderBuf {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        let h: &Header = self;
-        h.fmt(f)
-    }
-}
 
 #[derive(Debug)]
 pub enum HeaderError<'a> {
@@ -172,6 +282,10 @@ pub enum HeaderError<'a> {
 }
 
 impl HeaderError<'_> {
+    /// The stable diagnostic-code, see `pico-build-rs explain`
+    pub const fn code(&self) -> &'static str {
+        "E001"
+    }
     pub fn into_owned(self) -> HeaderError<'static> {
         match self {
             HeaderError::MalformedCartridgeMarker(cow) => {
@@ -195,7 +309,7 @@ impl core::fmt::Display for HeaderError<'_> {
                 format!("malformed cartridge marker {actual:?}")
             }
             HeaderError::InvalidVersion(actual) => {
-                format!("invalid utf8 in version data {actual:?}")
+                format!("invalid version-line {actual:?}")
             }
             HeaderError::NotEnoughData(value) => format!("not enough input data {value:?}"),
         };
@@ -206,7 +320,7 @@ impl core::fmt::Display for HeaderError<'_> {
 impl core::error::Error for HeaderError<'_> {}
 
 #[tracing::instrument(level = "debug", skip(src))]
-pub fn split_from<T: AsRef<[u8]> + ?Sized>(src: &T) -> Option<(&Header, &[u8])> {
+pub fn split_from<T: AsRef<[u8]> + ?Sized>(src: &T) -> Option<(Cow<'_, Header>, &[u8])> {
     // Stash slice for later use
     let slice = src.as_ref();
 
@@ -215,7 +329,8 @@ pub fn split_from<T: AsRef<[u8]> + ?Sized>(src: &T) -> Option<(&Header, &[u8])>
 
     // Assert marker
     let CARTRIDGE_MARKER = nl_iter.next_const()? else {
-        panic!("encountered malformed cartridge marker in header");
+        tracing::warn!("Malformed cartridge-marker in header");
+        return None;
     };
 
     // Check if version is valid utf-8 (minimal correctness check)
@@ -228,7 +343,7 @@ pub fn split_from<T: AsRef<[u8]> + ?Sized>(src: &T) -> Option<(&Header, &[u8])>
     let header_len = CARTRIDGE_MARKER.len() + version.len();
     let (slice, remainder) = slice.split_at(header_len);
 
-    let header = unsafe { Header::from_slice(slice) };
+    let header = header_of(slice);
     tracing::debug!("{header:?}");
     Some((header, remainder))
 }
@@ -236,10 +351,15 @@ pub fn split_from<T: AsRef<[u8]> + ?Sized>(src: &T) -> Option<(&Header, &[u8])>
 #[tracing::instrument(level = "debug", skip(src))]
 pub fn try_split_from<T: AsRef<[u8]> + ?Sized>(
     src: &T,
-) -> Result<(&Header, &[u8]), HeaderError<'_>> {
+) -> Result<(Cow<'_, Header>, &[u8]), HeaderError<'_>> {
     // Stash slice for later use
     let slice = src.as_ref();
 
+    // A source cut off within the marker is missing data, rather than malformed
+    if CARTRIDGE_MARKER.starts_with(slice) {
+        return Err(HeaderError::NotEnoughData(Cow::Borrowed(slice)));
+    }
+
     // Make iterator for taking some lines off
     let mut nl_iter = bytes::NewlineIter::new(slice);
 
@@ -260,11 +380,14 @@ pub fn try_split_from<T: AsRef<[u8]> + ?Sized>(
         tracing::warn!("Invalid utf-8 in version-bytes: {version:?}");
         return Err(HeaderError::InvalidVersion(Cow::Borrowed(version)));
     }
+    if !version.starts_with(VERSION_PREFIX.as_bytes()) {
+        return Err(HeaderError::InvalidVersion(Cow::Borrowed(version)));
+    }
 
     let header_len = CARTRIDGE_MARKER.len() + version.len();
     let (slice, remainder) = slice.split_at(header_len);
 
-    let header = unsafe { Header::from_slice(slice) };
+    let header = header_of(slice);
     tracing::debug!("{header:?}");
     Ok((header, remainder))
 }
@@ -0,0 +1,120 @@
+//! Decoding and encoding of the hex-digit rows used by the asset-sections
+
+use core::fmt;
+
+pub(crate) const DIGITS: &[u8; 16] = b"0123456789abcdef";
+
+/// An asset-section which could not be decoded
+#[derive(Debug, PartialEq, Eq)]
+pub enum HexError {
+    /// A row did not have the expected number of hex-digits
+    InvalidRowLength {
+        row: usize,
+        length: usize,
+        expected: usize,
+    },
+    /// A character in the row was not a hex-digit
+    InvalidDigit { row: usize, column: usize, byte: u8 },
+    /// There were more rows than the section can hold
+    TooManyRows { rows: usize, max_rows: usize },
+}
+
+impl fmt::Display for HexError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let description = "Hex-data error";
+        let reason = match self {
+            HexError::InvalidRowLength {
+                row,
+                length,
+                expected,
+            } => format!("row {row} has {length} digits, expected {expected}"),
+            HexError::InvalidDigit { row, column, byte } => format!(
+                "invalid hex-digit {:?} at row {row}, column {column}",
+                *byte as char
+            ),
+            HexError::TooManyRows { rows, max_rows } => {
+                format!("{rows} rows, expected at most {max_rows}")
+            }
+        };
+        f.write_fmt(format_args!("{description}: {reason}"))
+    }
+}
+
+impl core::error::Error for HexError {}
+
+impl HexError {
+    /// The stable diagnostic-code, see `pico-build-rs explain`
+    pub const fn code(&self) -> &'static str {
+        "E003"
+    }
+}
+
+pub(crate) const fn decode_digit(byte: u8) -> Option<u8> {
+    match byte {
+        b'0'..=b'9' => Some(byte - b'0'),
+        b'a'..=b'f' => Some(byte - b'a' + 10),
+        b'A'..=b'F' => Some(byte - b'A' + 10),
+        _ => None,
+    }
+}
+
+/// Splits the section-data into its non-empty rows, checking the row-count and -length
+pub(crate) fn rows(
+    section_data: &[u8],
+    row_length: usize,
+    max_rows: usize,
+) -> Result<Vec<&[u8]>, HexError> {
+    let rows: Vec<&[u8]> = bytes::NewlineIter::new(section_data)
+        .map(<[u8]>::trim_ascii)
+        .filter(|row| !row.is_empty())
+        .collect();
+    if rows.len() > max_rows {
+        return Err(HexError::TooManyRows {
+            rows: rows.len(),
+            max_rows,
+        });
+    }
+    if let Some((row, length)) = rows
+        .iter()
+        .map(|row| row.len())
+        .enumerate()
+        .find(|(_, length)| *length != row_length)
+    {
+        return Err(HexError::InvalidRowLength {
+            row,
+            length,
+            expected: row_length,
+        });
+    }
+    Ok(rows)
+}
+
+/// Decodes each digit of the row into a nibble
+pub(crate) fn decode_nibbles(row: &[u8], row_index: usize) -> Result<Vec<u8>, HexError> {
+    row.iter()
+        .enumerate()
+        .map(|(column, byte)| {
+            decode_digit(*byte).ok_or(HexError::InvalidDigit {
+                row: row_index,
+                column,
+                byte: *byte,
+            })
+        })
+        .collect()
+}
+
+/// Decodes each pair of digits in the row into a byte, high nibble first
+pub(crate) fn decode_bytes(row: &[u8], row_index: usize) -> Result<Vec<u8>, HexError> {
+    decode_nibbles(row, row_index).map(|nibbles| {
+        nibbles
+            .chunks_exact(2)
+            .map(|pair| (pair[0] << 4) | pair[1])
+            .collect()
+    })
+}
+
+/// Encodes the bytes as pairs of digits, high nibble first
+pub(crate) fn encode_bytes(row: &[u8]) -> impl Iterator<Item = u8> + '_ {
+    row.iter()
+        .flat_map(|byte| [DIGITS[(byte >> 4) as usize], DIGITS[(byte & 0x0f) as usize]])
+}
@@ -0,0 +1,402 @@
+//! A typed model of the `__label__` section
+
+use core::fmt;
+
+use crate::gfx::{GfxSheet, SPRITE_SIZE};
+use crate::hex::HexError;
+use crate::map::MapData;
+
+/// The width and height of the label in pixels
+pub const SIZE: usize = 128;
+
+/// The RGB-colors of the label-digits `0..32`
+///
+/// Digits `0..16` are the standard palette, and `16..32` the extended palette
+/// (colors `128..144` in pico-8)
+pub const PALETTE: [[u8; 3]; 32] = [
+    [0x00, 0x00, 0x00],
+    [0x1d, 0x2b, 0x53],
+    [0x7e, 0x25, 0x53],
+    [0x00, 0x87, 0x51],
+    [0xab, 0x52, 0x36],
+    [0x5f, 0x57, 0x4f],
+    [0xc2, 0xc3, 0xc7],
+    [0xff, 0xf1, 0xe8],
+    [0xff, 0x00, 0x4d],
+    [0xff, 0xa3, 0x00],
+    [0xff, 0xec, 0x27],
+    [0x00, 0xe4, 0x36],
+    [0x29, 0xad, 0xff],
+    [0x83, 0x76, 0x9c],
+    [0xff, 0x77, 0xa8],
+    [0xff, 0xcc, 0xaa],
+    [0x29, 0x18, 0x14],
+    [0x11, 0x1d, 0x35],
+    [0x42, 0x21, 0x36],
+    [0x12, 0x53, 0x59],
+    [0x74, 0x2f, 0x29],
+    [0x49, 0x33, 0x3b],
+    [0xa2, 0x88, 0x79],
+    [0xf3, 0xef, 0x7d],
+    [0xbe, 0x12, 0x50],
+    [0xff, 0x6c, 0x24],
+    [0xa8, 0xe7, 0x2e],
+    [0x00, 0xb5, 0x43],
+    [0x06, 0x5a, 0xb5],
+    [0x75, 0x46, 0x65],
+    [0xff, 0x6e, 0x59],
+    [0xff, 0x9d, 0x81],
+];
+
+/// The label uses a digit per pixel, extending hex up to `v` for the extended palette
+const DIGITS: &[u8; 32] = b"0123456789abcdefghijklmnopqrstuv";
+
+const fn decode_digit(byte: u8) -> Option<u8> {
+    match byte {
+        b'0'..=b'9' => Some(byte - b'0'),
+        b'a'..=b'v' => Some(byte - b'a' + 10),
+        b'A'..=b'V' => Some(byte - b'A' + 10),
+        _ => None,
+    }
+}
+
+/// Returns the label-digit of the palette-color closest to the RGB-color
+pub fn nearest_color(rgb: [u8; 3]) -> u8 {
+    nearest_in(&PALETTE, rgb)
+}
+
+/// Returns the color of the standard palette closest to the RGB-color,
+/// for the 16 colors of the sprite-sheet
+pub fn nearest_standard_color(rgb: [u8; 3]) -> u8 {
+    nearest_in(&PALETTE[..16], rgb)
+}
+
+fn nearest_in(palette: &[[u8; 3]], [red, green, blue]: [u8; 3]) -> u8 {
+    let distance = |[r, g, b]: &[u8; 3]| {
+        let delta = |a: u8, b: u8| (i32::from(a) - i32::from(b)).pow(2);
+        delta(*r, red) + delta(*g, green) + delta(*b, blue)
+    };
+    palette
+        .iter()
+        .enumerate()
+        .min_by_key(|(_, color)| distance(color))
+        .map_or(0, |(digit, _)| digit as u8)
+}
+
+/// The decoded pixels of a label, one label-digit (`0..32`) per pixel
+#[derive(Clone, PartialEq, Eq)]
+pub struct LabelImage {
+    pixels: Box<[u8]>,
+}
+
+impl LabelImage {
+    pub fn new() -> LabelImage {
+        LabelImage {
+            pixels: vec![0; SIZE * SIZE].into_boxed_slice(),
+        }
+    }
+    /// Decodes the rows of a `__label__` section, without the section-delimiter
+    ///
+    /// Missing rows are left as color `0`
+    #[tracing::instrument(level = "debug", skip(section_data))]
+    pub fn from_section_data(section_data: &[u8]) -> Result<LabelImage, HexError> {
+        let rows = crate::hex::rows(section_data, SIZE, SIZE)?;
+        let mut label_image = LabelImage::new();
+        for (row_index, row) in rows.into_iter().enumerate() {
+            for (column, byte) in row.iter().copied().enumerate() {
+                label_image.pixels[row_index * SIZE + column] =
+                    decode_digit(byte).ok_or(HexError::InvalidDigit {
+                        row: row_index,
+                        column,
+                        byte,
+                    })?;
+            }
+        }
+        Ok(label_image)
+    }
+    /// Encodes all rows of the `__label__` section, each row terminated by a newline
+    pub fn to_section_data(&self) -> Box<[u8]> {
+        self.pixels
+            .chunks(SIZE)
+            .flat_map(|row| {
+                row.iter()
+                    .map(|pixel| DIGITS[*pixel as usize])
+                    .chain(core::iter::once(b'\n'))
+            })
+            .collect()
+    }
+    /// Returns the label-digit of the pixel, if within the label
+    pub fn get_pixel(&self, x: usize, y: usize) -> Option<u8> {
+        (x < SIZE && y < SIZE).then(|| self.pixels[y * SIZE + x])
+    }
+    /// Sets the label-digit of the pixel, returning the previous one
+    ///
+    /// Returns `None` without modifying the label if the pixel is outside the label,
+    /// or if the color is not a label-digit
+    pub fn set_pixel(&mut self, x: usize, y: usize, color: u8) -> Option<u8> {
+        if x >= SIZE || y >= SIZE || color as usize >= PALETTE.len() {
+            return None;
+        }
+        Some(core::mem::replace(&mut self.pixels[y * SIZE + x], color))
+    }
+    /// The RGB-colors of the pixels, row by row
+    pub fn to_rgb(&self) -> Vec<u8> {
+        self.pixels
+            .iter()
+            .flat_map(|pixel| PALETTE[*pixel as usize])
+            .collect()
+    }
+    /// Whether every pixel is color `0`, like the label of a cart never captured
+    pub fn is_blank(&self) -> bool {
+        self.pixels.iter().all(|pixel| *pixel == 0)
+    }
+    /// Draws the sprite scaled up, with its top-left corner at the pixel, clipped to
+    /// the label
+    fn draw_sprite(&mut self, gfx_sheet: &GfxSheet, n: usize, x: usize, y: usize, scale: usize) {
+        let Some(sprite) = gfx_sheet.sprite(n) else {
+            return;
+        };
+        for (sprite_y, row) in sprite.iter().enumerate() {
+            for (sprite_x, color) in row.iter().enumerate() {
+                for offset in 0..scale * scale {
+                    self.set_pixel(
+                        x + sprite_x * scale + offset % scale,
+                        y + sprite_y * scale + offset / scale,
+                        *color,
+                    );
+                }
+            }
+        }
+    }
+    /// Renders the 16x16 tiles of the map from the tile, as `map()` draws them onto the
+    /// screen, leaving tile 0 empty
+    pub fn render_map(
+        gfx_sheet: &GfxSheet,
+        map_data: &MapData,
+        tile_x: usize,
+        tile_y: usize,
+    ) -> LabelImage {
+        let mut label_image = LabelImage::new();
+        let tiles = SIZE / SPRITE_SIZE;
+        for y in 0..tiles {
+            for x in 0..tiles {
+                match map_data.tile_at(tile_x + x, tile_y + y) {
+                    None | Some(0) => {}
+                    Some(tile) => label_image.draw_sprite(
+                        gfx_sheet,
+                        tile as usize,
+                        x * SPRITE_SIZE,
+                        y * SPRITE_SIZE,
+                        1,
+                    ),
+                }
+            }
+        }
+        label_image
+    }
+    /// Renders the rows of sprites centered, scaled up by the largest whole factor
+    /// fitting them into the label
+    pub fn render_sprites(gfx_sheet: &GfxSheet, rows: &[Vec<usize>]) -> LabelImage {
+        let mut label_image = LabelImage::new();
+        let columns = rows.iter().map(Vec::len).max().unwrap_or_default();
+        let size = columns.max(rows.len()) * SPRITE_SIZE;
+        if size == 0 {
+            return label_image;
+        }
+        let scale = (SIZE / size).max(1);
+        let left = SIZE.saturating_sub(columns * SPRITE_SIZE * scale) / 2;
+        let top = SIZE.saturating_sub(rows.len() * SPRITE_SIZE * scale) / 2;
+        for (y, row) in rows.iter().enumerate() {
+            for (x, n) in row.iter().enumerate() {
+                label_image.draw_sprite(
+                    gfx_sheet,
+                    *n,
+                    left + x * SPRITE_SIZE * scale,
+                    top + y * SPRITE_SIZE * scale,
+                    scale,
+                );
+            }
+        }
+        label_image
+    }
+    /// Maps RGB-colors, row by row, to the nearest palette-colors
+    pub fn from_rgb(rgb: &[u8]) -> Option<LabelImage> {
+        (rgb.len() == SIZE * SIZE * 3).then(|| LabelImage {
+            pixels: rgb
+                .chunks_exact(3)
+                .map(|color| nearest_color([color[0], color[1], color[2]]))
+                .collect(),
+        })
+    }
+}
+
+impl Default for LabelImage {
+    fn default() -> Self {
+        LabelImage::new()
+    }
+}
+
+impl fmt::Debug for LabelImage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LabelImage").finish_non_exhaustive()
+    }
+}
+
+#[cfg(feature = "png")]
+mod png_io {
+    use core::fmt;
+
+    use std::io;
+
+    use super::{LabelImage, SIZE};
+
+    #[derive(Debug)]
+    pub enum LabelPngError {
+        Decoding(png::DecodingError),
+        Encoding(png::EncodingError),
+        /// The image was not 128x128 pixels
+        InvalidSize {
+            width: u32,
+            height: u32,
+        },
+        /// The image could not be expanded into 8-bit RGB(A)
+        UnsupportedColorType(png::ColorType),
+    }
+
+    impl From<png::DecodingError> for LabelPngError {
+        fn from(v: png::DecodingError) -> Self {
+            Self::Decoding(v)
+        }
+    }
+
+    impl From<png::EncodingError> for LabelPngError {
+        fn from(v: png::EncodingError) -> Self {
+            Self::Encoding(v)
+        }
+    }
+
+    impl fmt::Display for LabelPngError {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            let description = "Label png error";
+            let reason = match self {
+                LabelPngError::Decoding(e) => e.to_string(),
+                LabelPngError::Encoding(e) => e.to_string(),
+                LabelPngError::InvalidSize { width, height } => {
+                    format!("image is {width}x{height}, expected {SIZE}x{SIZE}")
+                }
+                LabelPngError::UnsupportedColorType(color_type) => {
+                    format!("unsupported color-type {color_type:?}")
+                }
+            };
+            f.write_fmt(format_args!("{description}: {reason}"))
+        }
+    }
+
+    impl core::error::Error for LabelPngError {}
+
+    impl LabelPngError {
+        /// The stable diagnostic-code, see `pico-build-rs explain`
+        pub const fn code(&self) -> &'static str {
+            "E016"
+        }
+    }
+
+    impl LabelImage {
+        /// Writes the label as a 128x128 RGB png
+        #[tracing::instrument(level = "debug", skip(self, writer))]
+        pub fn write_png<W: io::Write>(&self, writer: W) -> Result<(), LabelPngError> {
+            let mut encoder = png::Encoder::new(writer, SIZE as u32, SIZE as u32);
+            encoder.set_color(png::ColorType::Rgb);
+            encoder.set_depth(png::BitDepth::Eight);
+            let mut png_writer = encoder.write_header()?;
+            png_writer.write_image_data(&self.to_rgb())?;
+            png_writer.finish().map_err(Into::into)
+        }
+        /// Reads a 128x128 png, mapping each pixel to the nearest palette-color
+        #[tracing::instrument(level = "debug", skip(reader))]
+        pub fn read_png<R: io::Read>(reader: R) -> Result<LabelImage, LabelPngError> {
+            let mut decoder = png::Decoder::new(reader);
+            decoder
+                .set_transformations(png::Transformations::EXPAND | png::Transformations::STRIP_16);
+            let mut png_reader = decoder.read_info()?;
+            let mut buf = vec![0; png_reader.output_buffer_size()];
+            let frame = png_reader.next_frame(&mut buf)?;
+            if (frame.width, frame.height) != (SIZE as u32, SIZE as u32) {
+                return Err(LabelPngError::InvalidSize {
+                    width: frame.width,
+                    height: frame.height,
+                });
+            }
+            let channels = match frame.color_type {
+                png::ColorType::Rgb => 3,
+                png::ColorType::Rgba => 4,
+                color_type => return Err(LabelPngError::UnsupportedColorType(color_type)),
+            };
+            let rgb: Vec<u8> = buf[..frame.buffer_size()]
+                .chunks_exact(channels)
+                .flat_map(|pixel| [pixel[0], pixel[1], pixel[2]])
+                .collect();
+            Ok(LabelImage::from_rgb(&rgb).expect("size was checked"))
+        }
+    }
+}
+
+#[cfg(feature = "png")]
+pub use png_io::LabelPngError;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let mut section_data = Vec::new();
+        for row in 0..SIZE {
+            let digit = DIGITS[row % DIGITS.len()];
+            section_data.extend(core::iter::repeat_n(digit, SIZE));
+            section_data.push(b'\n');
+        }
+        let label_image = LabelImage::from_section_data(&section_data).unwrap();
+        assert_eq!(label_image.get_pixel(0, 31), Some(31));
+        assert_eq!(
+            label_image.to_section_data().as_ref(),
+            section_data.as_slice()
+        );
+        assert_eq!(
+            LabelImage::from_rgb(&label_image.to_rgb()).as_ref(),
+            Some(&label_image)
+        );
+    }
+
+    #[test]
+    fn renders_map_and_sprites() {
+        let mut gfx_sheet = GfxSheet::new(crate::gfx::HALF_HEIGHT);
+        gfx_sheet.set_pixel(8 + 1, 2, 12);
+        let mut map_data = MapData::new();
+        map_data.set_tile(3, 4, 1);
+        map_data.set_tile(5, 4, 0);
+        let label_image = LabelImage::render_map(&gfx_sheet, &map_data, 2, 0);
+        assert!(!label_image.is_blank());
+        assert_eq!(label_image.get_pixel(8 + 1, 4 * 8 + 2), Some(12));
+        assert!(LabelImage::render_map(&gfx_sheet, &map_data, 4, 0).is_blank());
+
+        // 2x1 sprites are scaled by 8 and centered vertically
+        let label_image = LabelImage::render_sprites(&gfx_sheet, &[vec![0, 1]]);
+        assert_eq!(label_image.get_pixel(64 + 8, 32 + 16), Some(12));
+        assert_eq!(label_image.get_pixel(64 + 15, 32 + 23), Some(12));
+        assert_eq!(label_image.get_pixel(64 + 16, 32 + 16), Some(0));
+    }
+
+    #[cfg(feature = "png")]
+    #[test]
+    fn png_round_trip() {
+        let mut label_image = LabelImage::new();
+        label_image.set_pixel(5, 7, 20);
+        let mut png_data = Vec::new();
+        label_image.write_png(&mut png_data).unwrap();
+        assert_eq!(
+            LabelImage::read_png(png_data.as_slice()).unwrap(),
+            label_image
+        );
+    }
+}
@@ -1,4 +1,6 @@
 #![feature(debug_closure_helpers)]
+#![deny(unsafe_op_in_unsafe_fn)]
+#![cfg_attr(feature = "forbid-unsafe", forbid(unsafe_code))]
 
 extern crate alloc;
 
@@ -10,12 +12,78 @@ use std::fs;
 use std::io;
 use std::path;
 
+#[cfg(feature = "aseprite")]
+pub mod ase;
+#[cfg(feature = "aseprite")]
+pub use ase::{AseError, AseFile};
+
+pub mod codec;
+use codec::{GffCodec, GfxCodec, LabelCodec, MapCodec, MusicCodec, SectionCodec, SfxCodec};
+
+pub mod compress;
+
+pub mod diff;
+pub use diff::CartDiff;
+
+pub mod embed;
+pub use embed::EmbedError;
+
+pub mod format;
+
+pub mod gff;
+pub use gff::SpriteFlags;
+
+pub mod gfx;
+#[cfg(feature = "png")]
+pub use gfx::GfxPngError;
+pub use gfx::{GfxRows, GfxSheet};
+
 pub mod header;
 pub use header::Header;
 
+pub mod hex;
+pub use hex::HexError;
+
+pub mod label;
+pub use label::LabelImage;
+
+pub mod map;
+pub use map::{MapData, MapImport};
+
+pub mod music;
+pub use music::MusicData;
+
+pub mod p8scii;
+
+#[cfg(feature = "png")]
+pub mod png;
+
+pub mod rom;
+pub use rom::RomError;
+
 pub mod section;
 pub use section::{Section, SectionDelimiter, SectionType};
 
+pub mod sfx;
+pub use sfx::SfxData;
+
+pub mod sniff;
+
+pub mod source;
+pub use source::CartSource;
+
+pub mod splice;
+pub use splice::SpliceError;
+
+pub mod tabs;
+pub use tabs::{CodeTabs, TabOverflowError};
+
+pub mod text;
+pub use text::{TextError, TextPolicy};
+
+pub mod tokens;
+pub use tokens::TokenLimitError;
+
 #[tracing::instrument(skip(cart_src))]
 pub fn get_section_delimiters(
     cart_src: &[u8],
@@ -49,7 +117,7 @@ pub fn get_section_delimiters(
 pub fn get_sections(
     cart_src: &[u8],
     delimiters: impl IntoIterator<Item = SectionDelimiter>,
-) -> impl Iterator<Item = Section<'_>> + '_ {
+) -> impl Iterator<Item = Result<Section<'_>, CartParseError>> + '_ {
     // Collect so that we may sort
     let mut sorted_delimiters = Vec::from_iter(delimiters);
 
@@ -65,7 +133,7 @@ pub fn get_sections(
         // section length
         .rev()
         .enumerate()
-        .filter_map(
+        .map(
             move |(
                 idx,
                 SectionDelimiter {
@@ -85,7 +153,11 @@ pub fn get_sections(
                     cart_src.get(offset_without_type_marker..)
                 } else {
                     cart_src.get(offset_without_type_marker..next_section_offset)
-                }?;
+                }
+                .ok_or(CartParseError::TruncatedSection {
+                    section: r#type,
+                    line_number,
+                })?;
 
                 next_section_offset = byte_offset;
 
@@ -96,7 +168,7 @@ pub fn get_sections(
                     section_src.len(),
                     offset_without_type_marker + section_src.len()
                 );
-                Some(section)
+                Ok(section)
             },
         )
 }
@@ -118,7 +190,9 @@ where
         .field(format!("{data_label}.len()").as_str(), &&data.len())
 }
 
-const P8_MAX_CODE_EDITOR_TAB_COUNT: usize = 16;
+/// The tabs of the pico-8 code-editor, the tabs of a lua-section past the last one
+/// are merged into it
+pub const P8_MAX_CODE_EDITOR_TAB_COUNT: usize = 16;
 
 /// Always of the `lua` type
 #[derive(Clone)]
@@ -127,7 +201,27 @@ pub struct Tab<'file> {
     pub code_data: Cow<'file, [u8]>,
 }
 
+/// The title of the code, the `--` comment on its first line, as shown by the pico-8
+/// code-editor for its tab
+///
+/// A long comment, `--[[`, is no title, nor a comment without text
+pub fn title_of(code_data: &[u8]) -> Option<&str> {
+    let first_line = code_data.split(|byte| *byte == b'\n').next()?;
+    let comment = first_line.strip_prefix(b"--")?;
+    if comment.starts_with(b"[[") || comment.starts_with(b"[=") {
+        return None;
+    }
+    str::from_utf8(comment)
+        .ok()
+        .map(str::trim)
+        .filter(|title| !title.is_empty())
+}
+
 impl Tab<'_> {
+    /// The title of the tab, see [`title_of`]
+    pub fn title(&self) -> Option<&str> {
+        title_of(&self.code_data)
+    }
     #[tracing::instrument(level = "debug", ret)]
     pub fn into_owned(self) -> Tab<'static> {
         let Tab {
@@ -154,20 +248,31 @@ impl fmt::Debug for Tab<'_> {
     }
 }
 
-pub type CodeTabs<'a> = [Option<Tab<'a>>; P8_MAX_CODE_EDITOR_TAB_COUNT];
-
 #[tracing::instrument(level = "debug", skip(section_data))]
 pub fn get_code_tabs_from_lua_section<T: AsRef<[u8]> + ?Sized>(
     mut line_number: usize,
     section_data: &T,
-) -> CodeTabs<'_> {
+) -> Result<CodeTabs<'_>, CartParseError> {
+    let section_data = section_data.as_ref();
     let mut tabs: CodeTabs<'_> = Default::default();
 
     // Increment over the __lua__ marker
     line_number += 1;
 
-    for (tab_index, tab_data) in bytes::TabIter::from(section_data).enumerate() {
+    // The offset of the tab in the section, for merging the tabs past the last one
+    let mut tab_offset = 0;
+    for (tab_index, mut tab_data) in bytes::TabIter::from(section_data).enumerate() {
         tracing::debug!("Tab {tab_index} of lua-code starts at {line_number}");
+        if tab_index == P8_MAX_CODE_EDITOR_TAB_COUNT - 1
+            && tab_offset + tab_data.len() < section_data.len()
+        {
+            // As pico-8 does, keeping the separators of the merged tabs
+            tracing::warn!(
+                "The lua-section has {} tabs, merging the tabs past tab {tab_index} into it",
+                bytes::TabIter::from(section_data).count()
+            );
+            tab_data = &section_data[tab_offset..];
+        }
         let tab = Tab {
             line_number,
             code_data: Cow::Borrowed(tab_data),
@@ -177,14 +282,19 @@ pub fn get_code_tabs_from_lua_section<T: AsRef<[u8]> + ?Sized>(
         if tab_index != 0 {
             line_number += 1;
         };
-        tabs[tab_index] = Some(tab);
+        tabs.insert(tab_index, tab);
+        if tab_index == P8_MAX_CODE_EDITOR_TAB_COUNT - 1 {
+            break;
+        }
 
         let lines_in_section = bytes::NewlineIter::new(tab_data).count();
 
         line_number += lines_in_section;
+        tab_offset += tab_data.len();
+        tab_offset += bytes::tab_separator_len(&section_data[tab_offset..]);
     }
 
-    tabs
+    Ok(tabs)
 }
 
 #[derive(Clone, Debug)]
@@ -248,7 +358,7 @@ pub struct CartData<'a> {
     /// Optional field
     label: Option<Label<'a>>,
     /// All the lua-data in this cart
-    code_tabs: [Option<Tab<'a>>; P8_MAX_CODE_EDITOR_TAB_COUNT],
+    code_tabs: CodeTabs<'a>,
 
     /// Always found in even empty pico-8 cartridge files
     ///
@@ -258,21 +368,19 @@ pub struct CartData<'a> {
     map: Option<Asset<'a>>,
     sfx: Option<Asset<'a>>,
     music: Option<Asset<'a>>,
+    /// How the text of the code-tabs was checked when parsed
+    text_policy: TextPolicy,
 }
 
 impl<'a> CartData<'a> {
     #[tracing::instrument(level = "debug", skip(gfx_data), ret)]
     pub fn from_parts(
         header: &'a Header,
-        code_tabs: [Option<Tab<'a>>; P8_MAX_CODE_EDITOR_TAB_COUNT],
+        code_tabs: CodeTabs<'a>,
         gfx_data: &'a [u8],
     ) -> CartData<'a> {
         let lines_in_header: usize = bytes::NewlineIter::from(header).count();
-        let lines_in_code: usize = code_tabs
-            .iter()
-            .filter_map(Option::as_ref)
-            .map(|Tab { code_data, .. }| bytes::NewlineIter::from(code_data.as_ref()).count())
-            .sum();
+        let lines_in_code = code_tabs.total_lines();
         let gfx_line_number = lines_in_header + lines_in_code;
         let gfx = Asset {
             line_number: gfx_line_number,
@@ -287,6 +395,7 @@ impl<'a> CartData<'a> {
             map: None,
             sfx: None,
             music: None,
+            text_policy: TextPolicy::default(),
         }
     }
 }
@@ -303,6 +412,7 @@ impl fmt::Debug for CartData<'_> {
             map,
             sfx,
             music,
+            text_policy,
         } = self;
 
         f.debug_struct("Cart")
@@ -375,54 +485,130 @@ impl fmt::Debug for CartData<'_> {
                     music.fmt(f)
                 }
             })
+            .field("text_policy", text_policy)
             .finish()
     }
 }
 
-#[derive(Debug)]
-pub enum CartDataError<'a> {
-    Header(header::HeaderError<'a>),
+/// A cart-source which could not be parsed
+#[derive(Debug, PartialEq, Eq)]
+pub enum CartParseError {
+    /// The source ended before the two header-lines
+    MissingHeader,
+    MalformedCartridgeMarker,
+    /// The version-line was not `version <n>` in utf-8
+    InvalidVersion,
     MissingGfxSection,
+    /// A section-delimiter was cut off at the end of the source
+    TruncatedSection {
+        section: SectionType,
+        line_number: usize,
+    },
+    /// The source is not a `.p8` cart, but another known format
+    ForeignFormat(sniff::ForeignFormat),
+    /// The code was not utf-8 from the line on, see [`TextPolicy::ValidateAndError`]
+    InvalidUtf8 {
+        line_number: usize,
+    },
+}
+
+impl CartParseError {
+    /// The stable diagnostic-code, see `pico-build-rs explain`
+    pub const fn code(&self) -> &'static str {
+        match self {
+            CartParseError::MissingHeader
+            | CartParseError::MalformedCartridgeMarker
+            | CartParseError::InvalidVersion => "E001",
+            CartParseError::MissingGfxSection => "E002",
+            CartParseError::TruncatedSection { .. } => "E009",
+            CartParseError::ForeignFormat(_) => "E010",
+            CartParseError::InvalidUtf8 { .. } => "E015",
+        }
+    }
+}
+
+impl From<header::HeaderError<'_>> for CartParseError {
+    fn from(v: header::HeaderError<'_>) -> Self {
+        match v {
+            header::HeaderError::NotEnoughData(_) => CartParseError::MissingHeader,
+            header::HeaderError::MalformedCartridgeMarker(_) => {
+                CartParseError::MalformedCartridgeMarker
+            }
+            header::HeaderError::InvalidVersion(_) => CartParseError::InvalidVersion,
+        }
+    }
+}
+
+impl core::fmt::Display for CartParseError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        let description = "Cart parse error";
+        let reason = match self {
+            CartParseError::MissingHeader => "missing cartridge-header".to_string(),
+            CartParseError::MalformedCartridgeMarker => "malformed cartridge-marker".to_string(),
+            CartParseError::InvalidVersion => "invalid version-line".to_string(),
+            CartParseError::MissingGfxSection => "missing gfx-section".to_string(),
+            CartParseError::TruncatedSection {
+                section,
+                line_number,
+            } => format!(
+                "{} at line {line_number} is truncated",
+                <&'static str>::from(section)
+            ),
+            CartParseError::ForeignFormat(format) => {
+                format!("this looks like {format}; {}", format.hint())
+            }
+            CartParseError::InvalidUtf8 { line_number } => {
+                format!("the code at line {line_number} is not utf-8")
+            }
+        };
+        f.write_fmt(format_args!("{description}: {reason}"))
+    }
+}
+
+impl core::error::Error for CartParseError {}
+
+#[derive(Debug)]
+pub enum CartDataError {
+    Parse(CartParseError),
     Io(io::Error),
 }
 
-impl CartDataError<'_> {
-    pub fn into_owned(self) -> CartDataError<'static> {
+impl CartDataError {
+    /// The stable diagnostic-code, see `pico-build-rs explain`
+    pub const fn code(&self) -> &'static str {
         match self {
-            CartDataError::Header(header_error) => CartDataError::Header(header_error.into_owned()),
-            CartDataError::MissingGfxSection => CartDataError::MissingGfxSection,
-            CartDataError::Io(e) => CartDataError::Io(e),
+            CartDataError::Parse(e) => e.code(),
+            CartDataError::Io(_) => "E004",
         }
     }
 }
 
-impl<'a> From<header::HeaderError<'a>> for CartDataError<'a> {
-    fn from(v: header::HeaderError<'a>) -> Self {
-        Self::Header(v)
+impl From<CartParseError> for CartDataError {
+    fn from(v: CartParseError) -> Self {
+        Self::Parse(v)
     }
 }
 
-impl From<io::Error> for CartDataError<'_> {
+impl From<io::Error> for CartDataError {
     fn from(v: io::Error) -> Self {
         Self::Io(v)
     }
 }
 
-impl core::fmt::Display for CartDataError<'_> {
+impl core::fmt::Display for CartDataError {
     fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         let description = "Cart data error";
         let reason = match self {
-            CartDataError::Header(e) => e.to_string(),
-            CartDataError::MissingGfxSection => "missing gfx-section".to_string(),
+            CartDataError::Parse(e) => e.to_string(),
             CartDataError::Io(e) => e.to_string(),
         };
         f.write_fmt(format_args!("{description}: {reason}"))
     }
 }
 
-impl core::error::Error for CartDataError<'_> {}
+impl core::error::Error for CartDataError {}
 
-pub type CartDataResult<'a, T> = Result<T, CartDataError<'a>>;
+pub type CartDataResult<T> = Result<T, CartDataError>;
 
 impl<'a> CartData<'a> {
     pub fn into_owned(self) -> CartData<'static> {
@@ -435,45 +621,74 @@ impl<'a> CartData<'a> {
             map,
             sfx,
             music,
+            text_policy,
         } = self;
-        let mut owned_tabs = <[Option<Tab<'_>>; P8_MAX_CODE_EDITOR_TAB_COUNT]>::default();
-        for (tab_idx, tab_section) in code_tabs.into_iter().enumerate() {
-            owned_tabs[tab_idx] = tab_section.map(Tab::into_owned);
-        }
         CartData {
             header: Cow::Owned(header.into_owned()),
             label: label.map(Label::into_owned),
-            code_tabs: owned_tabs,
+            code_tabs: code_tabs.into_owned(),
             gfx: gfx.into_owned(),
             gff: gff.map(Asset::into_owned),
             map: map.map(Asset::into_owned),
             sfx: sfx.map(Asset::into_owned),
             music: music.map(Asset::into_owned),
+            text_policy,
         }
     }
-    #[tracing::instrument(level = "trace")]
-    pub fn from_file(mut cart_file: fs::File) -> Result<CartData<'static>, CartDataError<'static>> {
+    /// Parses the cart-source, borrowing the code and assets from it
+    #[tracing::instrument(level = "trace", skip(cart_src))]
+    pub fn from_bytes<T: AsRef<[u8]> + ?Sized>(
+        cart_src: &'a T,
+    ) -> Result<CartData<'a>, CartDataError> {
+        CartData::from_bytes_with(cart_src, TextPolicy::default())
+    }
+    /// Parses the cart-source, checking the text of the code with the policy
+    #[tracing::instrument(level = "trace", skip(cart_src))]
+    pub fn from_bytes_with<T: AsRef<[u8]> + ?Sized>(
+        cart_src: &'a T,
+        text_policy: TextPolicy,
+    ) -> Result<CartData<'a>, CartDataError> {
+        let mut cart = CartData::from_cart_source(cart_src.as_ref())?;
+        cart.code_tabs = text_policy.apply(cart.code_tabs)?;
+        cart.text_policy = text_policy;
+        Ok(cart)
+    }
+    /// Parses the cart-source, as [`CartData::from_bytes`]
+    #[allow(clippy::should_implement_trait)]
+    pub fn from_str(cart_src: &'a str) -> Result<CartData<'a>, CartDataError> {
+        CartData::from_bytes(cart_src)
+    }
+    /// Reads the cart-source to its end and parses it
+    #[tracing::instrument(level = "trace", skip(reader))]
+    pub fn from_reader<R: io::Read>(mut reader: R) -> Result<CartData<'static>, CartDataError> {
         let mut cart_source = vec![];
 
-        io::Read::read_to_end(&mut cart_file, &mut cart_source)?;
+        reader.read_to_end(&mut cart_source)?;
 
-        tracing::debug!(
-            "{} bytes of cart-data in file {cart_file:?}",
-            cart_source.len()
-        );
+        tracing::debug!("{} bytes of cart-data", cart_source.len());
 
-        CartData::from_cart_source(cart_source.as_slice())
-            .map(CartData::into_owned)
-            .map_err(CartDataError::into_owned)
+        CartData::from_bytes(&cart_source).map(CartData::into_owned)
+    }
+    /// Opens and parses the cart at the path
+    #[tracing::instrument(level = "trace", skip(path))]
+    pub fn from_path<P: AsRef<path::Path> + ?Sized>(
+        path: &P,
+    ) -> Result<CartData<'static>, CartDataError> {
+        fs::File::open(path)
+            .map_err(Into::into)
+            .and_then(CartData::from_file)
+    }
+    /// Reads and parses the cart-file, as [`CartData::from_reader`]
+    #[tracing::instrument(level = "trace")]
+    pub fn from_file(cart_file: fs::File) -> Result<CartData<'static>, CartDataError> {
+        CartData::from_reader(cart_file)
     }
     #[tracing::instrument(level = "trace", skip(path))]
     pub fn from_path_or_default<P: AsRef<path::Path> + ?Sized>(
         path: &P,
-    ) -> Result<CartData<'static>, CartDataError<'static>> {
+    ) -> Result<CartData<'static>, CartDataError> {
         if path.as_ref().exists() {
-            fs::File::open(path)
-                .map_err(Into::into)
-                .and_then(CartData::from_file)
+            CartData::from_path(path)
         } else {
             Ok(CartData::default())
         }
@@ -485,38 +700,271 @@ impl<'a> CartData<'a> {
         }
     }
     #[tracing::instrument(level = "trace", skip(cart_src))]
-    pub fn from_cart_source(cart_src: &'a [u8]) -> Result<CartData<'a>, CartDataError<'a>> {
-        tracing::debug!("FROM SOURCE");
-        let (header, remainder) =
-            header::try_split_from(cart_src).inspect_err(|e| tracing::error!("{e}"))?;
-
-        //     .ok_or_else(|| {
-        //     io::Error::new(
-        //         io::ErrorKind::InvalidData,
-        //         format!(
-        //             "Failed to parse header from cart-source of {} bytes",
-        //             cart_src.len()
-        //         ),
-        //     )
-        // })?;
+    pub fn from_cart_source(cart_src: &'a [u8]) -> Result<CartData<'a>, CartParseError> {
+        let (header, remainder) = header::try_split_from(cart_src).map_err(|e| {
+            tracing::error!("{e}");
+            match sniff::sniff(cart_src) {
+                Some(format) => CartParseError::ForeignFormat(format),
+                None => e.into(),
+            }
+        })?;
         tracing::debug!("header={:?}; remainder.len()={}", header, remainder.len());
-        CartDataBuilder::from_iter(get_sections(
+        CartDataBuilder::try_from_sections(get_sections(
             remainder,
             get_section_delimiters(remainder, Some(2)),
-        ))
+        ))?
         .build_with(header)
-        .ok_or({
-            CartDataError::MissingGfxSection
-            // io::Error::new(
-            //     io::ErrorKind::InvalidData,
-            //     "Gfx was missing when building cart (or something else idk really)",
-            // )
-        })
     }
-    /// Caution, will overwrite entirely
+    pub fn code_tabs(&self) -> &CodeTabs<'a> {
+        &self.code_tabs
+    }
+    /// How the text of the code-tabs was checked, see [`TextPolicy::guarantees_utf8`]
+    pub fn text_policy(&self) -> TextPolicy {
+        self.text_policy
+    }
+    /// The code of the tab as text, only replacing invalid utf-8 if the
+    /// [`TextPolicy`] does not guarantee it
+    pub fn code_text(&self, tab_index: usize) -> Option<Cow<'_, str>> {
+        self.code_tabs
+            .get(tab_index)?
+            .as_ref()
+            .map(|tab| String::from_utf8_lossy(&tab.code_data))
+    }
+    /// The tokens of each code-tab, see [`tokens::count_tokens`]
+    pub fn tab_token_counts(&self) -> [Option<usize>; P8_MAX_CODE_EDITOR_TAB_COUNT] {
+        self.code_tabs
+            .as_array()
+            .each_ref()
+            .map(|tab| tab.as_ref().map(|tab| tokens::count_tokens(&tab.code_data)))
+    }
+    /// The title of each code-tab, see [`Tab::title`]
+    pub fn tab_titles(&self) -> [Option<&str>; P8_MAX_CODE_EDITOR_TAB_COUNT] {
+        self.code_tabs
+            .as_array()
+            .each_ref()
+            .map(|tab| tab.as_ref().and_then(Tab::title))
+    }
+    /// The tokens of all code-tabs, as shown by the pico-8 code-editor
+    pub fn token_count(&self) -> usize {
+        self.tab_token_counts().into_iter().flatten().sum()
+    }
+    /// Returns the token-count, or an error if it exceeds the limit of the format of the
+    /// cart, see [`CartData::format_spec`]
+    pub fn check_token_limit(&self) -> Result<usize, TokenLimitError> {
+        let token_limit = self.format_spec().token_limit;
+        match self.token_count() {
+            token_count if token_count > token_limit => Err(TokenLimitError {
+                token_count,
+                token_limit,
+            }),
+            token_count => Ok(token_count),
+        }
+    }
+    /// The version of the cart-format from the header, e.g. 43
+    pub fn version(&self) -> Option<usize> {
+        self.header
+            .get_version()
+            .and_then(|version| version.parse().ok())
+    }
+    /// The format of the cart by its version, that of the latest stable release if it
+    /// has none
+    pub fn format_spec(&self) -> &'static format::Spec {
+        self.version()
+            .map_or(&format::LATEST_STABLE, format::Spec::for_version)
+    }
+    /// Decodes the `__gfx__` section into a sprite-sheet
+    pub fn gfx_sheet(&self) -> Result<GfxSheet, HexError> {
+        GfxCodec::decode(self.gfx.asset_data.as_ref())
+    }
+    /// Re-encodes the sprite-sheet into the `__gfx__` section
+    #[tracing::instrument(level = "debug", skip(self))]
+    pub fn set_gfx_sheet(&mut self, gfx_sheet: &GfxSheet) {
+        self.encode_section::<GfxCodec>(gfx_sheet);
+    }
+    /// Re-encodes the `__gfx__` section with the given rows, see [`GfxRows`]
+    ///
+    /// The section is left as is for [`GfxRows::Preserve`]
+    #[tracing::instrument(level = "debug", skip(self))]
+    pub fn set_gfx_rows(&mut self, gfx_rows: GfxRows) -> Result<(), HexError> {
+        if gfx_rows != GfxRows::Preserve {
+            let section_data = self.gfx_sheet()?.to_section_data_with(gfx_rows);
+            self.gfx.asset_data = Cow::Owned(section_data.into_vec());
+        }
+        Ok(())
+    }
+    /// Decodes the `__map__` section, including the rows shared with the sprite-sheet
+    /// if it has a lower half
+    pub fn map_data(&self) -> Result<MapData, HexError> {
+        let mut map_data = self.decode_section::<MapCodec>()?.unwrap_or_default();
+        map_data.read_shared(&self.gfx_sheet()?);
+        Ok(map_data)
+    }
+    /// Decodes the `__sfx__` section
+    pub fn sfx_data(&self) -> Result<SfxData, HexError> {
+        self.decode_section::<SfxCodec>()
+            .map(Option::unwrap_or_default)
+    }
+    /// Re-encodes the sound-effects into the `__sfx__` section
+    #[tracing::instrument(level = "debug", skip(self))]
+    pub fn set_sfx_data(&mut self, sfx_data: &SfxData) {
+        self.encode_section::<SfxCodec>(sfx_data);
+    }
+    /// Decodes the `__music__` section
+    pub fn music(&self) -> Result<MusicData, HexError> {
+        self.decode_section::<MusicCodec>()
+            .map(Option::unwrap_or_default)
+    }
+    /// Re-encodes the patterns into the `__music__` section
+    #[tracing::instrument(level = "debug", skip(self))]
+    pub fn set_music(&mut self, music_data: &MusicData) {
+        self.encode_section::<MusicCodec>(music_data);
+    }
+    /// Decodes the `__gff__` section
+    pub fn sprite_flags(&self) -> Result<SpriteFlags, HexError> {
+        self.decode_section::<GffCodec>()
+            .map(Option::unwrap_or_default)
+    }
+    /// Re-encodes the flags into the `__gff__` section
+    #[tracing::instrument(level = "debug", skip(self))]
+    pub fn set_sprite_flags(&mut self, sprite_flags: &SpriteFlags) {
+        self.encode_section::<GffCodec>(sprite_flags);
+    }
+    /// Decodes the `__label__` section, if the cart has a label
+    pub fn label_image(&self) -> Result<Option<LabelImage>, HexError> {
+        self.decode_section::<LabelCodec>()
+    }
+    /// Re-encodes the image into the `__label__` section
+    #[tracing::instrument(level = "debug", skip(self))]
+    pub fn set_label_image(&mut self, label_image: &LabelImage) {
+        self.encode_section::<LabelCodec>(label_image);
+    }
+    /// Re-encodes the map into the `__map__` section, and the shared rows
+    /// into the sprite-sheet
+    ///
+    /// A half sprite-sheet is extended to a full one if the map has the shared rows
+    #[tracing::instrument(level = "debug", skip(self))]
+    pub fn set_map_data(&mut self, map_data: &MapData) -> Result<(), HexError> {
+        let mut gfx_sheet = self.gfx_sheet()?;
+        if map_data.height() == map::FULL_HEIGHT {
+            gfx_sheet.extend_to_full_height();
+        }
+        if map_data.write_shared(&mut gfx_sheet) {
+            self.set_gfx_sheet(&gfx_sheet);
+        }
+        self.encode_section::<MapCodec>(map_data);
+        Ok(())
+    }
+    /// Decodes the section of the codec, if the cart has it
+    pub fn decode_section<C: SectionCodec>(&self) -> Result<Option<C::Model>, HexError> {
+        self.section_data(C::SECTION_TYPE)
+            .map(C::decode)
+            .transpose()
+    }
+    /// Re-encodes the model into the section of the codec, adding the section if missing
+    ///
+    /// The lua-section is set through [`CartData::set_code_data`] instead
+    pub fn encode_section<C: SectionCodec>(&mut self, model: &C::Model) {
+        let section_data = Cow::Owned(C::encode(model).into_vec());
+        let asset = match C::SECTION_TYPE {
+            SectionType::Lua => {
+                tracing::error!("Codecs can not encode the lua-section");
+                return;
+            }
+            SectionType::Gfx => {
+                self.gfx.asset_data = section_data;
+                return;
+            }
+            SectionType::Label => {
+                match &mut self.label {
+                    Some(label) => label.label_data = section_data,
+                    None => {
+                        self.label = Some(Label {
+                            line_number: 0,
+                            label_data: section_data,
+                        })
+                    }
+                }
+                return;
+            }
+            SectionType::Gff => &mut self.gff,
+            SectionType::Map => &mut self.map,
+            SectionType::Sfx => &mut self.sfx,
+            SectionType::Music => &mut self.music,
+        };
+        match asset {
+            Some(asset) => asset.asset_data = section_data,
+            None => {
+                *asset = Some(Asset {
+                    line_number: 0,
+                    asset_data: section_data,
+                })
+            }
+        }
+    }
+    /// The raw data of an asset-section, if the cart has it
+    ///
+    /// Returns `None` for the lua-section
+    pub fn section_data(&self, section: SectionType) -> Option<&[u8]> {
+        match section {
+            SectionType::Gfx => Some(self.gfx.asset_data.as_ref()),
+            SectionType::Label => self.label.as_ref().map(|label| label.label_data.as_ref()),
+            SectionType::Gff => self.gff.as_ref().map(|gff| gff.asset_data.as_ref()),
+            SectionType::Map => self.map.as_ref().map(|map| map.asset_data.as_ref()),
+            SectionType::Sfx => self.sfx.as_ref().map(|sfx| sfx.asset_data.as_ref()),
+            SectionType::Music => self.music.as_ref().map(|music| music.asset_data.as_ref()),
+            SectionType::Lua => None,
+        }
+    }
+    /// Lays out the cart in the 32K memory-layout of pico-8, see [`rom`]
+    ///
+    /// The code is compressed, and must fit the code-region
+    pub fn to_rom_bytes(&self) -> Result<Box<[u8]>, RomError> {
+        rom::cart_to_rom(self).map(Vec::into_boxed_slice)
+    }
+    /// The size of the code once compressed into the code-region,
+    /// which holds [`compress::CODE_REGION_SIZE`] bytes
+    pub fn compressed_code_size(&self) -> Result<usize, RomError> {
+        rom::compress_code(&self.code_tabs).map(|code| code.len())
+    }
+    /// Builds a cart from the 32K memory-layout of pico-8, see [`rom`]
+    ///
+    /// The header uses the version-byte following the ROM if present, as in `.p8.png` carts
+    pub fn from_rom_bytes(rom: &[u8]) -> Result<CartData<'static>, RomError> {
+        rom::cart_from_rom(rom)
+    }
+    /// Overwrites the code-tabs entirely, checked with the [`TextPolicy`] of the cart
+    ///
+    /// Code which fails the check of [`TextPolicy::ValidateAndError`] is kept as is, and
+    /// the cart falls back to [`TextPolicy::Raw`], see [`CartData::try_set_code_data`] to
+    /// fail instead
     #[tracing::instrument(level = "debug")]
     pub fn set_code_data(&mut self, code_tabs: CodeTabs<'a>) {
-        self.code_tabs = code_tabs;
+        if self.text_policy == TextPolicy::ValidateAndError
+            && let Some(line_number) = text::first_invalid_line(&code_tabs)
+        {
+            tracing::warn!(
+                "The code is not utf-8 from line {line_number}, no longer guaranteeing it"
+            );
+            self.text_policy = TextPolicy::Raw;
+        }
+        self.code_tabs = match self.text_policy {
+            TextPolicy::ValidateAndReplace => text::replace_invalid(code_tabs),
+            _ => code_tabs,
+        };
+    }
+    /// Overwrites the code-tabs entirely, checked with the [`TextPolicy`] of the cart
+    ///
+    /// Code which fails the check of [`TextPolicy::ValidateAndError`] is an error, leaving
+    /// the code of the cart as it was
+    #[tracing::instrument(level = "debug")]
+    pub fn try_set_code_data(&mut self, code_tabs: CodeTabs<'a>) -> Result<(), TextError> {
+        if self.text_policy == TextPolicy::ValidateAndError
+            && let Some(line_number) = text::first_invalid_line(&code_tabs)
+        {
+            return Err(TextError { line_number });
+        }
+        self.set_code_data(code_tabs);
+        Ok(())
     }
     #[tracing::instrument(level = "debug")]
     pub fn into_cart_source<T: FromIterator<u8>>(self) -> T {
@@ -530,6 +978,7 @@ impl<'a> CartData<'a> {
             map,
             sfx,
             music,
+            text_policy: _,
         } = self;
         // 1. Header
         let header = header.into_owned().copy_to_boxed_slice();
@@ -599,6 +1048,55 @@ impl<'a> CartData<'a> {
         // Collect finally
         iter.collect()
     }
+    /// Writes the cart-source section by section, as [`CartData::into_cart_source`]
+    /// does, without collecting it first
+    #[tracing::instrument(level = "debug", skip_all)]
+    pub fn write_to<W: io::Write>(&self, mut w: W) -> io::Result<()> {
+        fn write_section<W: io::Write>(
+            w: &mut W,
+            section: SectionType,
+            data: &[u8],
+        ) -> io::Result<()> {
+            w.write_all(<&'static str as From<&SectionType>>::from(&section).as_bytes())?;
+            w.write_all(b"\n")?;
+            w.write_all(data)
+        }
+        // 1. Header
+        w.write_all(self.header.as_ref().as_ref())?;
+        // 2. Lua, the section marker only if there is data to follow it
+        let has_lua = self
+            .code_tabs
+            .iter_present()
+            .any(|(idx, tab)| idx > 0 || !tab.code_data.is_empty());
+        if has_lua {
+            write_section(&mut w, SectionType::Lua, &[])?;
+            for (idx, Tab { code_data, .. }) in self.code_tabs.iter_present() {
+                if idx > 0 {
+                    w.write_all(bytes::TAB_SEQUENCE)?;
+                    w.write_all(b"\n")?;
+                }
+                w.write_all(code_data)?;
+            }
+        }
+        // 3. Gfx
+        write_section(&mut w, SectionType::Gfx, &self.gfx.asset_data)?;
+        // 4. Label (based on experimentation)
+        if let Some(Label { label_data, .. }) = &self.label {
+            write_section(&mut w, SectionType::Label, label_data)?;
+        }
+        // 5. - 8. Gff, map, sfx and music
+        for (section, asset) in [
+            (SectionType::Gff, &self.gff),
+            (SectionType::Map, &self.map),
+            (SectionType::Sfx, &self.sfx),
+            (SectionType::Music, &self.music),
+        ] {
+            if let Some(Asset { asset_data, .. }) = asset {
+                write_section(&mut w, section, asset_data)?;
+            }
+        }
+        w.flush()
+    }
 }
 impl Default for CartData<'static> {
     fn default() -> Self {
@@ -636,86 +1134,93 @@ struct CartDataBuilder<'a> {
     music: Option<Asset<'a>>,
 
     /// All the lua-data in this cart
-    code_tabs: [Option<Tab<'a>>; P8_MAX_CODE_EDITOR_TAB_COUNT],
+    code_tabs: CodeTabs<'a>,
 }
 
-impl<'a> FromIterator<Section<'a>> for CartDataBuilder<'a> {
-    fn from_iter<T: IntoIterator<Item = Section<'a>>>(iter: T) -> Self {
-        iter.into_iter()
-            .fold(Default::default(), |acc, section| match section {
-                Section::Lua {
-                    line_number,
-                    section_data,
-                } => {
-                    let Cow::Borrowed(section_data) = section_data else {
-                        panic!("Why is there a vector here");
-                    };
-
-                    CartDataBuilder {
-                        code_tabs: get_code_tabs_from_lua_section(line_number, section_data),
-                        ..acc
+impl<'a> CartDataBuilder<'a> {
+    fn try_from_sections<T: IntoIterator<Item = Result<Section<'a>, CartParseError>>>(
+        sections: T,
+    ) -> Result<Self, CartParseError> {
+        sections
+            .into_iter()
+            .try_fold(Default::default(), |acc, section| {
+                Ok(match section? {
+                    Section::Lua {
+                        line_number,
+                        section_data,
+                    } => {
+                        let code_tabs = match section_data {
+                            Cow::Borrowed(section_data) => {
+                                get_code_tabs_from_lua_section(line_number, section_data)?
+                            }
+                            Cow::Owned(section_data) => {
+                                get_code_tabs_from_lua_section(line_number, &section_data)?
+                                    .into_owned()
+                            }
+                        };
+                        CartDataBuilder { code_tabs, ..acc }
                     }
-                }
-                Section::Gfx {
-                    line_number,
-                    section_data,
-                } => CartDataBuilder {
-                    gfx: Some(Asset {
+                    Section::Gfx {
                         line_number,
-                        asset_data: section_data,
-                    }),
-                    ..acc
-                },
-                Section::Gff {
-                    line_number,
-                    section_data,
-                } => CartDataBuilder {
-                    gff: Some(Asset {
+                        section_data,
+                    } => CartDataBuilder {
+                        gfx: Some(Asset {
+                            line_number,
+                            asset_data: section_data,
+                        }),
+                        ..acc
+                    },
+                    Section::Gff {
                         line_number,
-                        asset_data: section_data,
-                    }),
-                    ..acc
-                },
-                Section::Sfx {
-                    line_number,
-                    section_data,
-                } => CartDataBuilder {
-                    sfx: Some(Asset {
+                        section_data,
+                    } => CartDataBuilder {
+                        gff: Some(Asset {
+                            line_number,
+                            asset_data: section_data,
+                        }),
+                        ..acc
+                    },
+                    Section::Sfx {
                         line_number,
-                        asset_data: section_data,
-                    }),
-                    ..acc
-                },
-                Section::Map {
-                    line_number,
-                    section_data,
-                } => CartDataBuilder {
-                    map: Some(Asset {
+                        section_data,
+                    } => CartDataBuilder {
+                        sfx: Some(Asset {
+                            line_number,
+                            asset_data: section_data,
+                        }),
+                        ..acc
+                    },
+                    Section::Map {
                         line_number,
-                        asset_data: section_data,
-                    }),
-                    ..acc
-                },
-                Section::Music {
-                    line_number,
-                    section_data,
-                } => CartDataBuilder {
-                    music: Some(Asset {
+                        section_data,
+                    } => CartDataBuilder {
+                        map: Some(Asset {
+                            line_number,
+                            asset_data: section_data,
+                        }),
+                        ..acc
+                    },
+                    Section::Music {
                         line_number,
-                        asset_data: section_data,
-                    }),
-                    ..acc
-                },
-                Section::Label {
-                    line_number,
-                    section_data,
-                } => CartDataBuilder {
-                    label: Some(Label {
+                        section_data,
+                    } => CartDataBuilder {
+                        music: Some(Asset {
+                            line_number,
+                            asset_data: section_data,
+                        }),
+                        ..acc
+                    },
+                    Section::Label {
                         line_number,
-                        label_data: section_data,
-                    }),
-                    ..acc
-                },
+                        section_data,
+                    } => CartDataBuilder {
+                        label: Some(Label {
+                            line_number,
+                            label_data: section_data,
+                        }),
+                        ..acc
+                    },
+                })
             })
     }
 }
@@ -723,7 +1228,7 @@ impl<'a> FromIterator<Section<'a>> for CartDataBuilder<'a> {
 impl<'a> CartDataBuilder<'a> {
     /// requires header to start
     #[tracing::instrument(level = "debug")]
-    fn build_with(self, header: &'a Header) -> Option<CartData<'a>> {
+    fn build_with(self, header: Cow<'a, Header>) -> Result<CartData<'a>, CartParseError> {
         let CartDataBuilder {
             label,
             gfx,
@@ -734,17 +1239,74 @@ impl<'a> CartDataBuilder<'a> {
             code_tabs,
         } = self;
 
-        Some(CartData {
-            header: Cow::Borrowed(header),
+        Ok(CartData {
+            header,
             label,
 
-            gfx: gfx?,
+            gfx: gfx.ok_or(CartParseError::MissingGfxSection)?,
             gff,
             map,
             sfx,
             music,
 
             code_tabs,
+            text_policy: TextPolicy::default(),
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_errors() {
+        const HEADER: &str = "pico-8 cartridge // http://www.pico-8.com\nversion 43\n";
+        let parse = |cart_src: &str| CartData::from_cart_source(cart_src.as_bytes()).err();
+        assert_eq!(parse(&format!("{HEADER}__gfx__\n")), None);
+        assert_eq!(
+            parse("pico-8 cartridge"),
+            Some(CartParseError::MissingHeader)
+        );
+        assert_eq!(
+            parse("pico-8 cartridge // http://www.pico-8.com\nversio 43\n__gfx__\n"),
+            Some(CartParseError::InvalidVersion)
+        );
+        assert_eq!(
+            parse(&format!("{HEADER}__lua__\n")),
+            Some(CartParseError::MissingGfxSection)
+        );
+        assert_eq!(
+            parse(&format!("{HEADER}__gfx__")),
+            Some(CartParseError::TruncatedSection {
+                section: SectionType::Gfx,
+                line_number: 3
+            })
+        );
+        assert_eq!(
+            parse("<!doctype html>\n<html>"),
+            Some(CartParseError::ForeignFormat(sniff::ForeignFormat::Html))
+        );
+    }
+
+    #[test]
+    fn merges_crlf_tabs_past_the_limit() {
+        let lua_section: String = (0..18)
+            .map(|tab_index| format!("t{tab_index}={tab_index}\r\n"))
+            .collect::<Vec<_>>()
+            .join("-->8\r\n");
+        let code_tabs = get_code_tabs_from_lua_section(0, &lua_section).unwrap();
+        assert_eq!(
+            code_tabs[1].as_ref().unwrap().code_data.as_ref(),
+            b"t1=1\r\n"
+        );
+        assert_eq!(
+            code_tabs[P8_MAX_CODE_EDITOR_TAB_COUNT - 1]
+                .as_ref()
+                .unwrap()
+                .code_data
+                .as_ref(),
+            b"t15=15\r\n-->8\r\nt16=16\r\n-->8\r\nt17=17\r\n"
+        );
+    }
+}
@@ -0,0 +1,257 @@
+//! A typed model of the `__map__` tile-grid section
+
+use core::fmt;
+
+use crate::gfx::{self, GfxSheet};
+use crate::hex::{self, HexError};
+
+/// The width of the map in tiles
+pub const WIDTH: usize = 128;
+/// The number of tile-rows stored in the `__map__` section
+pub const SECTION_HEIGHT: usize = 32;
+/// The number of tile-rows including the lower half, which is shared with
+/// the lower half of the sprite-sheet
+pub const FULL_HEIGHT: usize = 64;
+
+/// The tile-indices of the map, one byte per tile
+#[derive(Clone, PartialEq, Eq)]
+pub struct MapData {
+    tiles: Box<[u8]>,
+    height: usize,
+    /// The number of rows found when decoding,
+    /// kept so re-encoding does not add rows which were not there
+    source_rows: usize,
+}
+
+/// The pixel-coordinates in the sprite-sheet holding the two nibbles of a shared tile,
+/// low nibble first
+///
+/// Shared map-row `32 + r` occupies the same memory as sprite-sheet rows `64 + 2r` and
+/// `64 + 2r + 1`, each pixel being a nibble with the left pixel in the low nibble
+const fn shared_pixels(x: usize, y: usize) -> ((usize, usize), (usize, usize)) {
+    let memory_offset = (y - SECTION_HEIGHT) * WIDTH + x;
+    let bytes_per_gfx_row = gfx::WIDTH / 2;
+    let gfx_y = gfx::HALF_HEIGHT + memory_offset / bytes_per_gfx_row;
+    let gfx_x = (memory_offset % bytes_per_gfx_row) * 2;
+    ((gfx_x, gfx_y), (gfx_x + 1, gfx_y))
+}
+
+/// What [`MapData::import_tiles`] set in the map
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MapImport {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+    /// The tile-indices above 255 with their position in the map, set to 0 instead
+    pub oversized: Vec<(usize, usize, u32)>,
+}
+
+impl fmt::Display for MapImport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_fmt(format_args!(
+            "{}x{} tiles at {},{}",
+            self.width, self.height, self.x, self.y
+        ))?;
+        if !self.oversized.is_empty() {
+            f.write_fmt(format_args!(
+                ", {} above 255 set to 0",
+                self.oversized.len()
+            ))?;
+        }
+        Ok(())
+    }
+}
+
+impl MapData {
+    /// An empty map with only the rows of the `__map__` section
+    pub fn new() -> MapData {
+        MapData {
+            tiles: vec![0; WIDTH * SECTION_HEIGHT].into_boxed_slice(),
+            height: SECTION_HEIGHT,
+            source_rows: 0,
+        }
+    }
+    /// Decodes the hex-rows of a `__map__` section, without the section-delimiter
+    #[tracing::instrument(level = "debug", skip(section_data))]
+    pub fn from_section_data(section_data: &[u8]) -> Result<MapData, HexError> {
+        let rows = hex::rows(section_data, WIDTH * 2, SECTION_HEIGHT)?;
+        let mut map = MapData {
+            source_rows: rows.len(),
+            ..MapData::new()
+        };
+        for (row_index, row) in rows.into_iter().enumerate() {
+            let tiles = hex::decode_bytes(row, row_index)?;
+            map.tiles[row_index * WIDTH..(row_index + 1) * WIDTH].copy_from_slice(&tiles);
+        }
+        Ok(map)
+    }
+    /// Encodes the rows of the `__map__` section, each row terminated by a newline
+    ///
+    /// Rows shared with the sprite-sheet are not included, see [`MapData::write_shared`]
+    pub fn to_section_data(&self) -> Box<[u8]> {
+        let section_tiles = &self.tiles[..WIDTH * SECTION_HEIGHT];
+        let last_used_row = section_tiles
+            .chunks(WIDTH)
+            .rposition(|row| row.iter().any(|tile| *tile != 0))
+            .map_or(0, |row_index| row_index + 1);
+        let row_count = last_used_row.max(self.source_rows);
+        section_tiles
+            .chunks(WIDTH)
+            .take(row_count)
+            .flat_map(|row| hex::encode_bytes(row).chain(core::iter::once(b'\n')))
+            .collect()
+    }
+    /// Extends the map with the rows shared with the lower half of the sprite-sheet
+    ///
+    /// Does nothing if the sprite-sheet has no lower half
+    pub fn read_shared(&mut self, gfx_sheet: &GfxSheet) {
+        if gfx_sheet.height() < gfx::FULL_HEIGHT {
+            return;
+        }
+        let mut tiles = self.tiles[..WIDTH * SECTION_HEIGHT].to_vec();
+        for y in SECTION_HEIGHT..FULL_HEIGHT {
+            for x in 0..WIDTH {
+                let ((low_x, low_y), (high_x, high_y)) = shared_pixels(x, y);
+                let low = gfx_sheet.get_pixel(low_x, low_y).unwrap_or_default();
+                let high = gfx_sheet.get_pixel(high_x, high_y).unwrap_or_default();
+                tiles.push((high << 4) | low);
+            }
+        }
+        self.tiles = tiles.into_boxed_slice();
+        self.height = FULL_HEIGHT;
+    }
+    /// Writes the shared rows into the lower half of the sprite-sheet
+    ///
+    /// Returns `false` without writing if either the map or the sprite-sheet lack the shared half
+    pub fn write_shared(&self, gfx_sheet: &mut GfxSheet) -> bool {
+        if self.height < FULL_HEIGHT || gfx_sheet.height() < gfx::FULL_HEIGHT {
+            return false;
+        }
+        for y in SECTION_HEIGHT..FULL_HEIGHT {
+            for x in 0..WIDTH {
+                let tile = self.tiles[y * WIDTH + x];
+                let ((low_x, low_y), (high_x, high_y)) = shared_pixels(x, y);
+                gfx_sheet.set_pixel(low_x, low_y, tile & 0x0f);
+                gfx_sheet.set_pixel(high_x, high_y, tile >> 4);
+            }
+        }
+        true
+    }
+    /// The number of tile-rows, 32 or 64 if the shared rows have been read
+    pub const fn height(&self) -> usize {
+        self.height
+    }
+    /// Returns the tile-index at the position, if within the map
+    pub fn tile_at(&self, x: usize, y: usize) -> Option<u8> {
+        (x < WIDTH && y < self.height).then(|| self.tiles[y * WIDTH + x])
+    }
+    /// Sets the tile-index at the position, returning the previous one
+    ///
+    /// Returns `None` without modifying the map if the position is outside the map
+    pub fn set_tile(&mut self, x: usize, y: usize, tile: u8) -> Option<u8> {
+        if x >= WIDTH || y >= self.height {
+            return None;
+        }
+        Some(core::mem::replace(&mut self.tiles[y * WIDTH + x], tile))
+    }
+    /// Sets the tiles of a grid `width` tiles wide, its top-left corner at the position,
+    /// the tiles given row by row
+    ///
+    /// The map is extended with the shared rows if the grid reaches into them. Returns
+    /// `None` without modifying the map if the grid does not fit into the 128x64 tiles
+    pub fn import_tiles(
+        &mut self,
+        x: usize,
+        y: usize,
+        width: usize,
+        tiles: &[u32],
+    ) -> Option<MapImport> {
+        let height = tiles.len().checked_div(width).unwrap_or_default();
+        if x + width > WIDTH || y + height > FULL_HEIGHT || width * height != tiles.len() {
+            return None;
+        }
+        if y + height > self.height {
+            let mut map_tiles = self.tiles.to_vec();
+            map_tiles.resize(WIDTH * FULL_HEIGHT, 0);
+            self.tiles = map_tiles.into_boxed_slice();
+            self.height = FULL_HEIGHT;
+        }
+        let mut oversized = Vec::new();
+        for (index, tile) in tiles.iter().enumerate() {
+            let (tile_x, tile_y) = (x + index % width, y + index / width);
+            let tile = u8::try_from(*tile).unwrap_or_else(|_| {
+                oversized.push((tile_x, tile_y, *tile));
+                0
+            });
+            self.tiles[tile_y * WIDTH + tile_x] = tile;
+        }
+        Some(MapImport {
+            x,
+            y,
+            width,
+            height,
+            oversized,
+        })
+    }
+    /// Iterates the tile-rows from the top
+    pub fn rows(&self) -> impl Iterator<Item = &[u8]> {
+        self.tiles.chunks(WIDTH)
+    }
+}
+
+impl Default for MapData {
+    fn default() -> Self {
+        MapData::new()
+    }
+}
+
+impl fmt::Debug for MapData {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MapData")
+            .field("height", &self.height)
+            .field("source_rows", &self.source_rows)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let mut section_data = b"0102".to_vec();
+        section_data.extend(core::iter::repeat_n(b'0', WIDTH * 2 - 6));
+        section_data.extend(b"ff\n");
+        let mut map = MapData::from_section_data(&section_data).unwrap();
+        assert_eq!(map.tile_at(1, 0), Some(2));
+        assert_eq!(map.tile_at(WIDTH - 1, 0), Some(0xff));
+        assert_eq!(map.to_section_data().as_ref(), section_data.as_slice());
+
+        let mut gfx_sheet = GfxSheet::new(gfx::FULL_HEIGHT);
+        gfx_sheet.set_pixel(2, 64, 0xd);
+        gfx_sheet.set_pixel(3, 64, 0x3);
+        map.read_shared(&gfx_sheet);
+        assert_eq!(map.tile_at(1, 32), Some(0x3d));
+
+        map.set_tile(WIDTH - 1, 33, 0x21);
+        assert!(map.write_shared(&mut gfx_sheet));
+        assert_eq!(gfx_sheet.get_pixel(126, 67), Some(0x1));
+        assert_eq!(gfx_sheet.get_pixel(127, 67), Some(0x2));
+    }
+
+    #[test]
+    fn imports_tiles() {
+        let mut map = MapData::new();
+        let import = map.import_tiles(WIDTH - 2, 31, 2, &[1, 2, 300, 4]).unwrap();
+        assert_eq!((import.width, import.height), (2, 2));
+        assert_eq!(import.oversized, [(WIDTH - 2, 32, 300)]);
+        assert_eq!(map.height(), FULL_HEIGHT);
+        assert_eq!(map.tile_at(WIDTH - 1, 31), Some(2));
+        assert_eq!(map.tile_at(WIDTH - 2, 32), Some(0));
+        assert_eq!(map.tile_at(WIDTH - 1, 32), Some(4));
+        assert!(map.import_tiles(WIDTH - 1, 0, 2, &[1, 2]).is_none());
+        assert!(map.import_tiles(0, FULL_HEIGHT - 1, 1, &[1, 2]).is_none());
+    }
+}
@@ -0,0 +1,230 @@
+//! A typed model of the `__music__` section
+
+use core::fmt;
+
+use crate::hex::{self, HexError};
+
+/// The number of patterns in a cart
+pub const PATTERN_COUNT: usize = 64;
+/// The number of channels played by each pattern
+pub const CHANNEL_COUNT: usize = 4;
+
+/// The row-length, e.g. `00 41424344`
+const ROW_LENGTH: usize = 2 + 1 + CHANNEL_COUNT * 2;
+
+const LOOP_START_FLAG: u8 = 0b001;
+const LOOP_END_FLAG: u8 = 0b010;
+const STOP_FLAG: u8 = 0b100;
+/// Set on the channel-byte if the channel is silent
+const CHANNEL_DISABLED: u8 = 0x40;
+const CHANNEL_SFX_MASK: u8 = 0x3f;
+
+/// A single pattern-frame of the music
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Pattern {
+    flags: u8,
+    /// The raw channel-bytes, kept as-is so disabled channels round-trip
+    channels: [u8; CHANNEL_COUNT],
+}
+
+impl Default for Pattern {
+    /// An empty pattern as created by the pico-8 editor
+    fn default() -> Self {
+        Pattern {
+            flags: 0,
+            channels: [0x41, 0x42, 0x43, 0x44],
+        }
+    }
+}
+
+impl Pattern {
+    /// A pattern playing the sound-effects, with `None` for silent channels
+    pub fn new(channel_sfx: [Option<u8>; CHANNEL_COUNT]) -> Pattern {
+        let mut pattern = Pattern::default();
+        for (channel, sfx) in channel_sfx.into_iter().enumerate() {
+            pattern.set_channel_sfx(channel, sfx);
+        }
+        pattern
+    }
+    const fn has_flag(&self, flag: u8) -> bool {
+        self.flags & flag != 0
+    }
+    const fn set_flag(&mut self, flag: u8, value: bool) {
+        if value {
+            self.flags |= flag;
+        } else {
+            self.flags &= !flag;
+        }
+    }
+    pub const fn loop_start(&self) -> bool {
+        self.has_flag(LOOP_START_FLAG)
+    }
+    pub const fn set_loop_start(&mut self, value: bool) {
+        self.set_flag(LOOP_START_FLAG, value);
+    }
+    pub const fn loop_end(&self) -> bool {
+        self.has_flag(LOOP_END_FLAG)
+    }
+    pub const fn set_loop_end(&mut self, value: bool) {
+        self.set_flag(LOOP_END_FLAG, value);
+    }
+    pub const fn stop(&self) -> bool {
+        self.has_flag(STOP_FLAG)
+    }
+    pub const fn set_stop(&mut self, value: bool) {
+        self.set_flag(STOP_FLAG, value);
+    }
+    /// Returns the sound-effect played on the channel, or `None` if it is silent
+    pub fn channel_sfx(&self, channel: usize) -> Option<u8> {
+        let channel_byte = *self.channels.get(channel)?;
+        (channel_byte & CHANNEL_DISABLED == 0).then_some(channel_byte & CHANNEL_SFX_MASK)
+    }
+    /// Sets the sound-effect played on the channel, or silences it with `None`
+    ///
+    /// Channels outside of `0..4` are ignored
+    pub fn set_channel_sfx(&mut self, channel: usize, sfx: Option<u8>) {
+        let Some(channel_byte) = self.channels.get_mut(channel) else {
+            return;
+        };
+        *channel_byte = match sfx {
+            Some(sfx) => sfx & CHANNEL_SFX_MASK,
+            // Keep the previous index, like the pico-8 editor does
+            None => *channel_byte | CHANNEL_DISABLED,
+        };
+    }
+    fn from_row(row: &[u8], row_index: usize) -> Result<Pattern, HexError> {
+        if row.get(2) != Some(&b' ') {
+            return Err(HexError::InvalidDigit {
+                row: row_index,
+                column: 2,
+                byte: row.get(2).copied().unwrap_or_default(),
+            });
+        }
+        let flags = hex::decode_bytes(&row[..2], row_index)?[0];
+        let channels = hex::decode_bytes(&row[3..], row_index)?;
+        Ok(Pattern {
+            flags,
+            channels: core::array::from_fn(|channel| channels[channel]),
+        })
+    }
+    /// Decodes the ROM-bytes of the pattern, each holding a flag in the high bit
+    pub(crate) fn from_rom_bytes(rom_bytes: [u8; CHANNEL_COUNT]) -> Pattern {
+        Pattern {
+            flags: rom_bytes
+                .iter()
+                .enumerate()
+                .fold(0, |flags, (index, byte)| flags | ((byte >> 7) << index)),
+            channels: rom_bytes.map(|byte| byte & 0x7f),
+        }
+    }
+    pub(crate) fn to_rom_bytes(self) -> [u8; CHANNEL_COUNT] {
+        core::array::from_fn(|index| {
+            (self.channels[index] & 0x7f) | (((self.flags >> index) & 1) << 7)
+        })
+    }
+    fn encode_row(&self) -> Vec<u8> {
+        hex::encode_bytes(&[self.flags])
+            .chain(core::iter::once(b' '))
+            .chain(hex::encode_bytes(&self.channels))
+            .collect()
+    }
+}
+
+/// All pattern-frames of a cart
+#[derive(Clone, PartialEq, Eq)]
+pub struct MusicData {
+    patterns: Box<[Pattern]>,
+    /// The number of rows found when decoding,
+    /// kept so re-encoding does not add rows which were not there
+    source_rows: usize,
+}
+
+impl MusicData {
+    pub fn new() -> MusicData {
+        MusicData {
+            patterns: vec![Pattern::default(); PATTERN_COUNT].into_boxed_slice(),
+            source_rows: 0,
+        }
+    }
+    /// Decodes the rows of a `__music__` section, without the section-delimiter
+    #[tracing::instrument(level = "debug", skip(section_data))]
+    pub fn from_section_data(section_data: &[u8]) -> Result<MusicData, HexError> {
+        let rows = hex::rows(section_data, ROW_LENGTH, PATTERN_COUNT)?;
+        let mut music_data = MusicData {
+            source_rows: rows.len(),
+            ..MusicData::new()
+        };
+        for (row_index, row) in rows.into_iter().enumerate() {
+            music_data.patterns[row_index] = Pattern::from_row(row, row_index)?;
+        }
+        Ok(music_data)
+    }
+    /// Encodes the rows of the `__music__` section, each row terminated by a newline
+    ///
+    /// Trailing empty patterns beyond the rows originally decoded are left out
+    pub fn to_section_data(&self) -> Box<[u8]> {
+        let last_used_row = self
+            .patterns
+            .iter()
+            .rposition(|pattern| *pattern != Pattern::default())
+            .map_or(0, |row_index| row_index + 1);
+        let row_count = last_used_row.max(self.source_rows);
+        self.patterns
+            .iter()
+            .take(row_count)
+            .flat_map(|pattern| {
+                pattern
+                    .encode_row()
+                    .into_iter()
+                    .chain(core::iter::once(b'\n'))
+            })
+            .collect()
+    }
+    pub fn get(&self, n: usize) -> Option<&Pattern> {
+        self.patterns.get(n)
+    }
+    pub fn get_mut(&mut self, n: usize) -> Option<&mut Pattern> {
+        self.patterns.get_mut(n)
+    }
+    pub fn iter(&self) -> impl Iterator<Item = &Pattern> {
+        self.patterns.iter()
+    }
+}
+
+impl Default for MusicData {
+    fn default() -> Self {
+        MusicData::new()
+    }
+}
+
+impl fmt::Debug for MusicData {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MusicData")
+            .field("source_rows", &self.source_rows)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        const SECTION_DATA: &[u8] = b"01 00014344\n02 02034344\n";
+        let mut music_data = MusicData::from_section_data(SECTION_DATA).unwrap();
+        let pattern = music_data.get(0).unwrap();
+        assert!(pattern.loop_start() && !pattern.loop_end());
+        assert_eq!(pattern.channel_sfx(1), Some(1));
+        assert_eq!(pattern.channel_sfx(2), None);
+        assert_eq!(music_data.to_section_data().as_ref(), SECTION_DATA);
+
+        let pattern = music_data.get_mut(1).unwrap();
+        pattern.set_stop(true);
+        pattern.set_channel_sfx(0, None);
+        assert_eq!(
+            music_data.to_section_data().as_ref(),
+            b"01 00014344\n06 42034344\n"
+        );
+    }
+}
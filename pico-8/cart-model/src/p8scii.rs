@@ -0,0 +1,100 @@
+//! Conversion between P8SCII, the single-byte charset of the pico-8 memory,
+//! and the UTF-8 used for the code in `.p8` files
+//!
+//! Only the glyphs `0x80..=0xff` are converted, lower bytes are the same in both
+
+/// The UTF-8 glyphs of the bytes `0x80..=0xff`, as written by pico-8
+#[rustfmt::skip]
+const GLYPHS: [&str; 128] = [
+    "█", "▒", "🐱", "⬇️", "░", "✽", "●", "♥", "☉", "웃", "⌂", "⬅️", "😐", "♪", "🅾️", "◆",
+    "…", "➡️", "★", "⧗", "⬆️", "ˇ", "∧", "❎", "▤", "▥", "あ", "い", "う", "え", "お", "か",
+    "き", "く", "け", "こ", "さ", "し", "す", "せ", "そ", "た", "ち", "つ", "て", "と", "な", "に",
+    "ぬ", "ね", "の", "は", "ひ", "ふ", "へ", "ほ", "ま", "み", "む", "め", "も", "や", "ゆ", "よ",
+    "ら", "り", "る", "れ", "ろ", "わ", "を", "ん", "っ", "ゃ", "ゅ", "ょ", "ア", "イ", "ウ", "エ",
+    "オ", "カ", "キ", "ク", "ケ", "コ", "サ", "シ", "ス", "セ", "ソ", "タ", "チ", "ツ", "テ", "ト",
+    "ナ", "ニ", "ヌ", "ネ", "ノ", "ハ", "ヒ", "フ", "ヘ", "ホ", "マ", "ミ", "ム", "メ", "モ", "ヤ",
+    "ユ", "ヨ", "ラ", "リ", "ル", "レ", "ロ", "ワ", "ヲ", "ン", "ッ", "ャ", "ュ", "ョ", "◜", "◝",
+];
+
+/// Follows some of the glyphs, and is optional when converting back
+const VARIATION_SELECTOR: &str = "\u{fe0f}";
+
+/// Converts P8SCII into UTF-8
+pub fn to_utf8(p8scii: &[u8]) -> Vec<u8> {
+    let mut utf8 = Vec::with_capacity(p8scii.len());
+    for byte in p8scii.iter().copied() {
+        match byte {
+            0x80.. => utf8.extend_from_slice(GLYPHS[(byte - 0x80) as usize].as_bytes()),
+            _ => utf8.push(byte),
+        }
+    }
+    utf8
+}
+
+/// Converts UTF-8 into P8SCII
+///
+/// Characters without a P8SCII glyph are kept as their UTF-8 bytes
+pub fn from_utf8(utf8: &[u8]) -> Vec<u8> {
+    let mut p8scii = Vec::with_capacity(utf8.len());
+    let mut remainder = utf8;
+    while let Some((byte, tail)) = remainder.split_first() {
+        if byte.is_ascii() {
+            p8scii.push(*byte);
+            remainder = tail;
+            continue;
+        }
+        match glyph_prefix(remainder) {
+            Some((glyph_byte, tail)) => {
+                p8scii.push(glyph_byte);
+                remainder = tail;
+            }
+            None => {
+                p8scii.push(*byte);
+                remainder = tail;
+            }
+        }
+    }
+    p8scii
+}
+
+/// The P8SCII-byte of the glyph the UTF-8 starts with, and the UTF-8 following it
+fn glyph_prefix(utf8: &[u8]) -> Option<(u8, &[u8])> {
+    GLYPHS.iter().enumerate().find_map(|(index, glyph)| {
+        let glyph = glyph.strip_suffix(VARIATION_SELECTOR).unwrap_or(glyph);
+        utf8.strip_prefix(glyph.as_bytes()).map(|tail| {
+            let tail = tail
+                .strip_prefix(VARIATION_SELECTOR.as_bytes())
+                .unwrap_or(tail);
+            (index as u8 + 0x80, tail)
+        })
+    })
+}
+
+/// The first character of the text without a P8SCII glyph, `None` if all have one
+pub fn first_unsupported(utf8: &str) -> Option<char> {
+    let mut remainder = utf8;
+    while let Some(c) = remainder.chars().next() {
+        remainder = match glyph_prefix(remainder.as_bytes()) {
+            Some((_, tail)) => &remainder[remainder.len() - tail.len()..],
+            None if c.is_ascii() => &remainder[1..],
+            None => return Some(c),
+        };
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let p8scii: Vec<u8> = (0x20..=0xff).collect();
+        let utf8 = to_utf8(&p8scii);
+        assert!(core::str::from_utf8(&utf8).is_ok());
+        assert_eq!(from_utf8(&utf8), p8scii);
+        assert_eq!(from_utf8("⬇ ⬇️".as_bytes()), [0x83, b' ', 0x83]);
+        assert_eq!(first_unsupported("press ❎ ★"), None);
+        assert_eq!(first_unsupported("drücke ❎"), Some('ü'));
+    }
+}
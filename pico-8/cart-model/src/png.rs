@@ -0,0 +1,243 @@
+//! Reading and writing `.p8.png` carts
+//!
+//! The ROM is hidden in the two lowest bits of each channel of the 160x205 cart-image,
+//! one byte per pixel in the order alpha, red, green, blue
+
+use core::fmt;
+
+use std::io;
+
+use crate::CartData;
+use crate::label::{self, LabelImage};
+use crate::rom::{self, RomError};
+
+pub const WIDTH: usize = 160;
+pub const HEIGHT: usize = 205;
+/// The top-left corner of the label in the cart-image
+pub const LABEL_POSITION: (usize, usize) = (16, 24);
+
+#[derive(Debug)]
+pub enum PngCartError {
+    Decoding(::png::DecodingError),
+    Encoding(::png::EncodingError),
+    /// The image was not 160x205 pixels
+    InvalidSize {
+        width: u32,
+        height: u32,
+    },
+    /// The image could not be expanded into 8-bit RGB(A)
+    UnsupportedColorType(::png::ColorType),
+    Rom(RomError),
+}
+
+impl From<::png::DecodingError> for PngCartError {
+    fn from(v: ::png::DecodingError) -> Self {
+        Self::Decoding(v)
+    }
+}
+
+impl From<::png::EncodingError> for PngCartError {
+    fn from(v: ::png::EncodingError) -> Self {
+        Self::Encoding(v)
+    }
+}
+
+impl From<RomError> for PngCartError {
+    fn from(v: RomError) -> Self {
+        Self::Rom(v)
+    }
+}
+
+impl fmt::Display for PngCartError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let description = "Png cart error";
+        let reason = match self {
+            PngCartError::Decoding(e) => e.to_string(),
+            PngCartError::Encoding(e) => e.to_string(),
+            PngCartError::InvalidSize { width, height } => {
+                format!("image is {width}x{height}, expected {WIDTH}x{HEIGHT}")
+            }
+            PngCartError::UnsupportedColorType(color_type) => {
+                format!("unsupported color-type {color_type:?}")
+            }
+            PngCartError::Rom(e) => e.to_string(),
+        };
+        f.write_fmt(format_args!("{description}: {reason}"))
+    }
+}
+
+impl core::error::Error for PngCartError {}
+
+impl PngCartError {
+    /// The stable diagnostic-code, see `pico-build-rs explain`
+    pub const fn code(&self) -> &'static str {
+        match self {
+            PngCartError::Rom(e) => e.code(),
+            _ => "E005",
+        }
+    }
+}
+
+/// Reads a 160x205 png as RGBA
+fn read_rgba<R: io::Read>(reader: R) -> Result<Box<[u8]>, PngCartError> {
+    let mut decoder = ::png::Decoder::new(reader);
+    decoder.set_transformations(::png::Transformations::EXPAND | ::png::Transformations::STRIP_16);
+    let mut png_reader = decoder.read_info()?;
+    let mut buf = vec![0; png_reader.output_buffer_size()];
+    let frame = png_reader.next_frame(&mut buf)?;
+    if (frame.width, frame.height) != (WIDTH as u32, HEIGHT as u32) {
+        return Err(PngCartError::InvalidSize {
+            width: frame.width,
+            height: frame.height,
+        });
+    }
+    let pixels = &buf[..frame.buffer_size()];
+    match frame.color_type {
+        ::png::ColorType::Rgba => Ok(Box::from(pixels)),
+        ::png::ColorType::Rgb => Ok(pixels
+            .chunks_exact(3)
+            .flat_map(|pixel| [pixel[0], pixel[1], pixel[2], u8::MAX])
+            .collect()),
+        color_type => Err(PngCartError::UnsupportedColorType(color_type)),
+    }
+}
+
+/// The image the cart-data is hidden in, with the label drawn on top when writing
+#[derive(Clone, PartialEq, Eq)]
+pub struct CartTemplate {
+    rgba: Box<[u8]>,
+}
+
+impl CartTemplate {
+    /// Reads a 160x205 png to use as the cart-image
+    #[tracing::instrument(level = "debug", skip(reader))]
+    pub fn read_png<R: io::Read>(reader: R) -> Result<CartTemplate, PngCartError> {
+        read_rgba(reader).map(|rgba| CartTemplate { rgba })
+    }
+}
+
+impl Default for CartTemplate {
+    /// A plain cart-image in the darkest blue of the palette
+    fn default() -> Self {
+        let [red, green, blue] = label::PALETTE[1];
+        CartTemplate {
+            rgba: [red, green, blue, u8::MAX].repeat(WIDTH * HEIGHT).into(),
+        }
+    }
+}
+
+impl fmt::Debug for CartTemplate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CartTemplate").finish_non_exhaustive()
+    }
+}
+
+/// The version of the cart-header, saturated to a byte
+fn version_byte(cart: &CartData<'_>) -> u8 {
+    cart.header
+        .get_version()
+        .and_then(|version| version.parse().ok())
+        .map_or(0, |version: usize| version.min(u8::MAX.into()) as u8)
+}
+
+/// Reads the cart hidden in a `.p8.png`, with the label taken from the cart-image
+#[tracing::instrument(level = "debug", skip(reader))]
+pub fn read_cart_png<R: io::Read>(reader: R) -> Result<CartData<'static>, PngCartError> {
+    let rgba = read_rgba(reader)?;
+    let data: Vec<u8> = rgba
+        .chunks_exact(4)
+        .map(|pixel| {
+            let [red, green, blue, alpha] = [pixel[0], pixel[1], pixel[2], pixel[3]];
+            (alpha & 3) << 6 | (red & 3) << 4 | (green & 3) << 2 | (blue & 3)
+        })
+        .collect();
+    let mut cart = rom::cart_from_rom(&data)?;
+
+    let (label_x, label_y) = LABEL_POSITION;
+    let label_rgb: Vec<u8> = (0..label::SIZE)
+        .flat_map(|y| {
+            let start = ((label_y + y) * WIDTH + label_x) * 4;
+            rgba[start..start + label::SIZE * 4]
+                .chunks_exact(4)
+                .flat_map(|pixel| [pixel[0], pixel[1], pixel[2]])
+        })
+        .collect();
+    let label_image = LabelImage::from_rgb(&label_rgb).expect("label-size is fixed");
+    cart.set_label_image(&label_image);
+    Ok(cart)
+}
+
+/// Writes the cart as a `.p8.png`, drawing its label onto the template
+///
+/// The code is stored compressed, and must fit the code-region
+#[tracing::instrument(level = "debug", skip(cart, template, writer))]
+pub fn write_cart_png<W: io::Write>(
+    cart: &CartData<'_>,
+    template: &CartTemplate,
+    writer: W,
+) -> Result<(), PngCartError> {
+    let mut data = rom::cart_to_rom(cart)?;
+    data.push(version_byte(cart));
+
+    let mut rgba = template.rgba.clone();
+    if let Some(label_image) = cart.label_image().map_err(RomError::from)? {
+        let (label_x, label_y) = LABEL_POSITION;
+        for (index, [red, green, blue]) in label_image
+            .to_rgb()
+            .chunks_exact(3)
+            .map(|color| [color[0], color[1], color[2]])
+            .enumerate()
+        {
+            let (x, y) = (label_x + index % label::SIZE, label_y + index / label::SIZE);
+            let start = (y * WIDTH + x) * 4;
+            rgba[start..start + 4].copy_from_slice(&[red, green, blue, u8::MAX]);
+        }
+    }
+    for (pixel, byte) in rgba
+        .chunks_exact_mut(4)
+        .zip(data.into_iter().chain(core::iter::repeat(0)))
+    {
+        let [red, green, blue, alpha] =
+            [byte >> 4, byte >> 2, byte, byte >> 6].map(|bits| bits & 3);
+        for (channel, bits) in pixel.iter_mut().zip([red, green, blue, alpha]) {
+            *channel = (*channel & !3) | bits;
+        }
+    }
+
+    let mut encoder = ::png::Encoder::new(writer, WIDTH as u32, HEIGHT as u32);
+    encoder.set_color(::png::ColorType::Rgba);
+    encoder.set_depth(::png::BitDepth::Eight);
+    let mut png_writer = encoder.write_header()?;
+    png_writer.write_image_data(&rgba)?;
+    png_writer.finish().map_err(Into::into)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let mut cart = CartData::default();
+        let mut label_image = LabelImage::new();
+        label_image.set_pixel(3, 4, 8);
+        cart.set_label_image(&label_image);
+        let code = "print(\"hello\")\n";
+        cart.set_code_data(
+            crate::get_code_tabs_from_lua_section(0, code.as_bytes())
+                .unwrap()
+                .into_owned(),
+        );
+
+        let mut png_data = Vec::new();
+        write_cart_png(&cart, &CartTemplate::default(), &mut png_data).unwrap();
+        let decoded = read_cart_png(png_data.as_slice()).unwrap();
+        assert_eq!(decoded.label_image().unwrap(), Some(label_image));
+        assert_eq!(decoded.gfx_sheet().unwrap(), cart.gfx_sheet().unwrap());
+        assert_eq!(
+            decoded.code_tabs()[0].as_ref().unwrap().code_data.as_ref(),
+            code.as_bytes()
+        );
+        assert_eq!(decoded.header.get_version().unwrap().parse(), Ok(43));
+    }
+}
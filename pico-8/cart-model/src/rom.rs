@@ -0,0 +1,328 @@
+//! The memory-layout of a cart, as stored in `.p8.png` carts
+//!
+//! | Offset   | Size     | Data                                   |
+//! |----------|----------|----------------------------------------|
+//! | `0x0000` | `0x2000` | sprite-sheet, the lower half shared with the map |
+//! | `0x2000` | `0x1000` | map, the upper 32 rows                 |
+//! | `0x3000` | `0x0100` | sprite-flags                           |
+//! | `0x3100` | `0x0100` | music                                  |
+//! | `0x3200` | `0x1100` | sound-effects                          |
+//! | `0x4300` | `0x3d00` | code, usually compressed               |
+
+use core::fmt;
+
+use alloc::borrow::Cow;
+
+use crate::compress::{self, CompressError, DecompressError};
+use crate::header::HeaderBuf;
+use crate::hex::HexError;
+use crate::{
+    CartData, CartParseError, CodeTabs, GfxSheet, MapData, MusicData, SfxData, SpriteFlags,
+};
+use crate::{gfx, map, music, sfx};
+
+/// The size of the memory-mapped ROM
+pub const ROM_SIZE: usize = 0x8000;
+pub const GFX_OFFSET: usize = 0x0000;
+pub const MAP_OFFSET: usize = 0x2000;
+pub const GFF_OFFSET: usize = 0x3000;
+pub const MUSIC_OFFSET: usize = 0x3100;
+pub const SFX_OFFSET: usize = 0x3200;
+pub const CODE_OFFSET: usize = 0x4300;
+/// The version-byte following the ROM in `.p8.png` carts
+pub const VERSION_OFFSET: usize = ROM_SIZE;
+
+/// ROM-data which could not be converted to or from a cart
+#[derive(Debug)]
+pub enum RomError {
+    /// The ROM was smaller than [`ROM_SIZE`]
+    InvalidSize {
+        size: usize,
+    },
+    Decompress(DecompressError),
+    Compress(CompressError),
+    Hex(HexError),
+    /// The decompressed code could not be split into tabs
+    Parse(CartParseError),
+    /// The compressed code did not fit the code-region
+    CodeTooLarge {
+        size: usize,
+        max_size: usize,
+    },
+}
+
+impl From<DecompressError> for RomError {
+    fn from(v: DecompressError) -> Self {
+        Self::Decompress(v)
+    }
+}
+
+impl From<CompressError> for RomError {
+    fn from(v: CompressError) -> Self {
+        Self::Compress(v)
+    }
+}
+
+impl From<HexError> for RomError {
+    fn from(v: HexError) -> Self {
+        Self::Hex(v)
+    }
+}
+
+impl From<CartParseError> for RomError {
+    fn from(v: CartParseError) -> Self {
+        Self::Parse(v)
+    }
+}
+
+impl fmt::Display for RomError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let description = "Rom error";
+        let reason = match self {
+            RomError::InvalidSize { size } => {
+                format!("{size} bytes of rom-data, expected at least {ROM_SIZE}")
+            }
+            RomError::Decompress(e) => e.to_string(),
+            RomError::Compress(e) => e.to_string(),
+            RomError::Hex(e) => e.to_string(),
+            RomError::Parse(e) => e.to_string(),
+            RomError::CodeTooLarge { size, max_size } => {
+                format!("{size} bytes of compressed code, the code-region holds {max_size}")
+            }
+        };
+        f.write_fmt(format_args!("{description}: {reason}"))
+    }
+}
+
+impl core::error::Error for RomError {}
+
+impl RomError {
+    /// The stable diagnostic-code, see `pico-build-rs explain`
+    pub const fn code(&self) -> &'static str {
+        match self {
+            RomError::InvalidSize { .. } | RomError::Decompress(_) => "E005",
+            RomError::Hex(e) => e.code(),
+            RomError::Parse(e) => e.code(),
+            RomError::Compress(_) | RomError::CodeTooLarge { .. } => "E006",
+        }
+    }
+}
+
+/// Joins the tabs like the `__lua__` section, separated by the tab-sequence
+fn join_code_tabs(code_tabs: &CodeTabs<'_>) -> Vec<u8> {
+    let mut code = Vec::new();
+    for (index, tab) in code_tabs.iter_present() {
+        if index != 0 {
+            code.extend_from_slice(bytes::TAB_SEQUENCE);
+            code.push(b'\n');
+        }
+        code.extend_from_slice(tab.code_data.as_ref());
+    }
+    code
+}
+
+/// Builds a cart from the ROM, with the header of the version-byte following it if any
+#[tracing::instrument(level = "debug", skip(rom))]
+pub(crate) fn cart_from_rom(rom: &[u8]) -> Result<CartData<'static>, RomError> {
+    let version = rom
+        .get(VERSION_OFFSET)
+        .copied()
+        .filter(|version| *version != 0);
+    let rom = rom
+        .get(..ROM_SIZE)
+        .ok_or(RomError::InvalidSize { size: rom.len() })?;
+    let mut cart = CartData::default();
+    if let Some(version) = version {
+        let header =
+            format!("pico-8 cartridge // http://www.pico-8.com\nversion {version}\n").into_bytes();
+        cart.header = Cow::Owned(HeaderBuf::from(header.into_boxed_slice()));
+    }
+
+    let mut gfx_sheet = GfxSheet::new(gfx::FULL_HEIGHT);
+    for (index, byte) in rom[GFX_OFFSET..MAP_OFFSET].iter().enumerate() {
+        let (x, y) = ((index * 2) % gfx::WIDTH, (index * 2) / gfx::WIDTH);
+        gfx_sheet.set_pixel(x, y, byte & 0x0f);
+        gfx_sheet.set_pixel(x + 1, y, byte >> 4);
+    }
+    cart.set_gfx_sheet(&gfx_sheet);
+
+    let mut map_data = MapData::new();
+    for (index, tile) in rom[MAP_OFFSET..GFF_OFFSET].iter().enumerate() {
+        map_data.set_tile(index % map::WIDTH, index / map::WIDTH, *tile);
+    }
+    if map_data != MapData::new() {
+        cart.set_map_data(&map_data)?;
+    }
+
+    let mut sprite_flags = SpriteFlags::new();
+    for (n, flags) in rom[GFF_OFFSET..MUSIC_OFFSET].iter().enumerate() {
+        sprite_flags.set_flags_for_sprite(n, *flags);
+    }
+    if sprite_flags != SpriteFlags::new() {
+        cart.set_sprite_flags(&sprite_flags);
+    }
+
+    let mut music_data = MusicData::new();
+    for (n, rom_bytes) in rom[MUSIC_OFFSET..SFX_OFFSET]
+        .chunks_exact(music::CHANNEL_COUNT)
+        .enumerate()
+    {
+        if let Some(pattern) = music_data.get_mut(n) {
+            *pattern = music::Pattern::from_rom_bytes(rom_bytes.try_into().expect("exact chunk"));
+        }
+    }
+    if music_data != MusicData::new() {
+        cart.set_music(&music_data);
+    }
+
+    let mut sfx_data = SfxData::new();
+    for (n, rom_bytes) in rom[SFX_OFFSET..CODE_OFFSET]
+        .chunks_exact(sfx::ROM_SIZE)
+        .enumerate()
+    {
+        if let Some(sfx) = sfx_data.get_mut(n) {
+            *sfx = sfx::Sfx::from_rom_bytes(rom_bytes.try_into().expect("exact chunk"));
+        }
+    }
+    if sfx_data != SfxData::new() {
+        cart.set_sfx_data(&sfx_data);
+    }
+
+    let mut code = crate::p8scii::to_utf8(&compress::decompress(&rom[CODE_OFFSET..])?);
+    if !code.is_empty() && !code.ends_with(b"\n") {
+        code.push(b'\n');
+    }
+    let code_tabs = crate::get_code_tabs_from_lua_section(0, &code)?.into_owned();
+    cart.set_code_data(code_tabs);
+    Ok(cart)
+}
+
+/// Compresses the code-tabs as they are stored in the code-region
+pub(crate) fn compress_code(code_tabs: &CodeTabs<'_>) -> Result<Vec<u8>, RomError> {
+    let code = join_code_tabs(code_tabs);
+    let code = crate::p8scii::from_utf8(code.strip_suffix(b"\n").unwrap_or(&code));
+    Ok(compress::compress(&code)?)
+}
+
+/// Lays out the cart in a ROM of [`ROM_SIZE`] bytes, with the code compressed
+#[tracing::instrument(level = "debug", skip(cart))]
+pub(crate) fn cart_to_rom(cart: &CartData<'_>) -> Result<Vec<u8>, RomError> {
+    let mut rom = vec![0; ROM_SIZE];
+
+    let gfx_sheet = cart.gfx_sheet()?;
+    for (index, byte) in rom[GFX_OFFSET..MAP_OFFSET].iter_mut().enumerate() {
+        let (x, y) = ((index * 2) % gfx::WIDTH, (index * 2) / gfx::WIDTH);
+        let left = gfx_sheet.get_pixel(x, y).unwrap_or_default();
+        let right = gfx_sheet.get_pixel(x + 1, y).unwrap_or_default();
+        *byte = (right << 4) | left;
+    }
+
+    let map_data = cart.map_data()?;
+    for (rom_row, row) in rom[MAP_OFFSET..GFF_OFFSET]
+        .chunks_exact_mut(map::WIDTH)
+        .zip(map_data.rows())
+    {
+        rom_row.copy_from_slice(row);
+    }
+
+    let sprite_flags = cart.sprite_flags()?;
+    for (n, flags) in rom[GFF_OFFSET..MUSIC_OFFSET].iter_mut().enumerate() {
+        *flags = sprite_flags.flags_for_sprite(n);
+    }
+
+    for (rom_bytes, pattern) in rom[MUSIC_OFFSET..SFX_OFFSET]
+        .chunks_exact_mut(music::CHANNEL_COUNT)
+        .zip(cart.music()?.iter())
+    {
+        rom_bytes.copy_from_slice(&pattern.to_rom_bytes());
+    }
+
+    for (rom_bytes, sfx) in rom[SFX_OFFSET..CODE_OFFSET]
+        .chunks_exact_mut(sfx::ROM_SIZE)
+        .zip(cart.sfx_data()?.iter())
+    {
+        rom_bytes.copy_from_slice(&sfx.to_rom_bytes());
+    }
+
+    let code = compress_code(cart.code_tabs())?;
+    if code.len() > compress::CODE_REGION_SIZE {
+        return Err(RomError::CodeTooLarge {
+            size: code.len(),
+            max_size: compress::CODE_REGION_SIZE,
+        });
+    }
+    rom[CODE_OFFSET..CODE_OFFSET + code.len()].copy_from_slice(&code);
+    Ok(rom)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let mut cart = CartData::default();
+        let mut sfx_data = SfxData::new();
+        let sfx = sfx_data.get_mut(3).unwrap();
+        sfx.notes[0] = sfx::Note {
+            pitch: 24,
+            waveform: 9,
+            volume: 5,
+            effect: 2,
+        };
+        cart.set_sfx_data(&sfx_data);
+        let mut music_data = MusicData::new();
+        let pattern = music_data.get_mut(0).unwrap();
+        pattern.set_loop_end(true);
+        pattern.set_channel_sfx(0, Some(3));
+        cart.set_music(&music_data);
+        let code = "print(\"⬇️\")\n-->8\nx=1\n";
+        cart.set_code_data(
+            crate::get_code_tabs_from_lua_section(0, code.as_bytes())
+                .unwrap()
+                .into_owned(),
+        );
+
+        let mut rom = cart_to_rom(&cart).unwrap();
+        assert!(
+            compress::decompress(&rom[CODE_OFFSET..])
+                .unwrap()
+                .starts_with(b"print(\"\x83\")\n")
+        );
+        rom.push(42);
+        let decoded = cart_from_rom(&rom).unwrap();
+        assert_eq!(decoded.header.get_version().unwrap().parse(), Ok(42));
+        rom.pop();
+        assert!(decoded.sfx_data().unwrap().iter().eq(sfx_data.iter()));
+        assert!(decoded.music().unwrap().iter().eq(music_data.iter()));
+        assert_eq!(decoded.gfx_sheet().unwrap(), cart.gfx_sheet().unwrap());
+        assert_eq!(join_code_tabs(decoded.code_tabs()), code.as_bytes());
+        assert_eq!(cart_to_rom(&decoded).unwrap(), rom);
+    }
+
+    #[test]
+    fn decodes_legacy_code() {
+        // Laid out by hand, with the code in the `:c:` format of pico-8 before 0.2.0
+        let mut rom = vec![0; ROM_SIZE];
+        rom[GFX_OFFSET] = 0x8c;
+        let code_region = [
+            b":c:\0\0\x0c\0\0".as_slice(),
+            // `a=1\n`, by the index into the character-table plus one
+            &[0x0d, 0x33, 0x04, 0x01],
+            // `-->8\n`, with the `-` missing from the table escaped by a zero-byte
+            &[0x00, b'-', 0x00, b'-', 0x31, 0x0b, 0x01],
+            // `a=1`, as a back-reference of offset 9 and length 3
+            &[0x3c, 0x19],
+        ]
+        .concat();
+        rom[CODE_OFFSET..CODE_OFFSET + code_region.len()].copy_from_slice(&code_region);
+
+        let decoded = cart_from_rom(&rom).unwrap();
+        assert_eq!(join_code_tabs(decoded.code_tabs()), b"a=1\n-->8\na=1\n");
+        let gfx_sheet = decoded.gfx_sheet().unwrap();
+        assert_eq!(
+            (gfx_sheet.get_pixel(0, 0), gfx_sheet.get_pixel(1, 0)),
+            (Some(12), Some(8))
+        );
+    }
+}
@@ -43,18 +43,8 @@ impl SectionType {
     }
 }
 
-const SECTION_TYPES: &[SectionType] = &[
-    SectionType::Lua,
-    SectionType::Gfx,
-    SectionType::Gff,
-    SectionType::Label,
-    SectionType::Map,
-    SectionType::Sfx,
-    SectionType::Music,
-];
-
 pub fn get_line_type<T: AsRef<[u8]> + ?Sized>(line_src: &T) -> Option<&'static SectionType> {
-    SECTION_TYPES.iter().find(|ty| {
+    crate::format::SECTIONS.iter().find(|ty| {
         let needle = <&'static str as From<&SectionType>>::from(*ty).as_bytes();
         line_src.as_ref().starts_with(needle)
     })
@@ -0,0 +1,239 @@
+//! A typed model of the `__sfx__` section
+
+use core::fmt;
+
+use crate::hex::{self, HexError};
+
+/// The number of sound-effects in a cart
+pub const SFX_COUNT: usize = 64;
+/// The number of notes in a single sound-effect
+pub const NOTE_COUNT: usize = 32;
+
+/// The hex-digits of the header-bytes preceding the notes
+const HEADER_DIGITS: usize = 8;
+/// The hex-digits of a single note
+const NOTE_DIGITS: usize = 5;
+const ROW_LENGTH: usize = HEADER_DIGITS + NOTE_COUNT * NOTE_DIGITS;
+/// The size of a single sound-effect in the ROM
+pub(crate) const ROM_SIZE: usize = NOTE_COUNT * 2 + 4;
+
+/// A single note of a sound-effect
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Note {
+    /// `0..64`, where `0` is C-0
+    pub pitch: u8,
+    /// `0..8` for the built-in waveforms, `8..16` for the custom instruments
+    pub waveform: u8,
+    /// `0..8`, where `0` is silent
+    pub volume: u8,
+    /// `0..8`, where `0` is no effect
+    pub effect: u8,
+}
+
+/// A single sound-effect
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Sfx {
+    /// The editor-mode and filter-flags
+    pub flags: u8,
+    /// The duration of each note, in 1/128ths of a second
+    pub speed: u8,
+    pub loop_start: u8,
+    pub loop_end: u8,
+    pub notes: [Note; NOTE_COUNT],
+}
+
+impl Default for Sfx {
+    /// An empty sound-effect as created by the pico-8 editor
+    fn default() -> Self {
+        Sfx {
+            flags: 0,
+            speed: 16,
+            loop_start: 0,
+            loop_end: 0,
+            notes: [Note::default(); NOTE_COUNT],
+        }
+    }
+}
+
+impl Sfx {
+    fn from_row(row: &[u8], row_index: usize) -> Result<Sfx, HexError> {
+        let nibbles = hex::decode_nibbles(row, row_index)?;
+        let (header, notes) = nibbles.split_at(HEADER_DIGITS);
+        let header_byte = |index: usize| (header[index * 2] << 4) | header[index * 2 + 1];
+        let mut note_digits = notes.chunks_exact(NOTE_DIGITS);
+        Ok(Sfx {
+            flags: header_byte(0),
+            speed: header_byte(1),
+            loop_start: header_byte(2),
+            loop_end: header_byte(3),
+            notes: core::array::from_fn(|_| {
+                let digits = note_digits.next().unwrap_or(&[0; NOTE_DIGITS]);
+                Note {
+                    pitch: (digits[0] << 4) | digits[1],
+                    waveform: digits[2],
+                    volume: digits[3],
+                    effect: digits[4],
+                }
+            }),
+        })
+    }
+    /// Decodes the ROM-bytes of the sound-effect, a little-endian word per note
+    /// followed by the header-bytes
+    pub(crate) fn from_rom_bytes(rom_bytes: &[u8; ROM_SIZE]) -> Sfx {
+        let (notes, header) = rom_bytes.split_at(NOTE_COUNT * 2);
+        let mut words = notes
+            .chunks_exact(2)
+            .map(|word| u16::from_le_bytes([word[0], word[1]]));
+        Sfx {
+            flags: header[0],
+            speed: header[1],
+            loop_start: header[2],
+            loop_end: header[3],
+            notes: core::array::from_fn(|_| {
+                let word = words.next().unwrap_or_default();
+                Note {
+                    pitch: (word & 0x3f) as u8,
+                    // The custom-instrument bit is the highest bit of the word
+                    waveform: ((word >> 6 & 0x07) | (word >> 12 & 0x08)) as u8,
+                    volume: (word >> 9 & 0x07) as u8,
+                    effect: (word >> 12 & 0x07) as u8,
+                }
+            }),
+        }
+    }
+    pub(crate) fn to_rom_bytes(self) -> [u8; ROM_SIZE] {
+        let mut rom_bytes = [0; ROM_SIZE];
+        for (note, word) in self.notes.iter().zip(rom_bytes.chunks_exact_mut(2)) {
+            let waveform = u16::from(note.waveform);
+            let note_word = u16::from(note.pitch & 0x3f)
+                | (waveform & 0x07) << 6
+                | u16::from(note.volume & 0x07) << 9
+                | u16::from(note.effect & 0x07) << 12
+                | (waveform & 0x08) << 12;
+            word.copy_from_slice(&note_word.to_le_bytes());
+        }
+        rom_bytes[NOTE_COUNT * 2..].copy_from_slice(&[
+            self.flags,
+            self.speed,
+            self.loop_start,
+            self.loop_end,
+        ]);
+        rom_bytes
+    }
+    fn encode_row(&self) -> Vec<u8> {
+        let header = [self.flags, self.speed, self.loop_start, self.loop_end];
+        hex::encode_bytes(&header)
+            .chain(self.notes.iter().flat_map(|note| {
+                [
+                    hex::DIGITS[(note.pitch >> 4 & 0x0f) as usize],
+                    hex::DIGITS[(note.pitch & 0x0f) as usize],
+                    hex::DIGITS[(note.waveform & 0x0f) as usize],
+                    hex::DIGITS[(note.volume & 0x0f) as usize],
+                    hex::DIGITS[(note.effect & 0x0f) as usize],
+                ]
+            }))
+            .collect()
+    }
+}
+
+/// All sound-effects of a cart
+#[derive(Clone, PartialEq, Eq)]
+pub struct SfxData {
+    sfx: Box<[Sfx]>,
+    /// The number of rows found when decoding,
+    /// kept so re-encoding does not add rows which were not there
+    source_rows: usize,
+}
+
+impl SfxData {
+    pub fn new() -> SfxData {
+        SfxData {
+            sfx: vec![Sfx::default(); SFX_COUNT].into_boxed_slice(),
+            source_rows: 0,
+        }
+    }
+    /// Decodes the hex-rows of a `__sfx__` section, without the section-delimiter
+    #[tracing::instrument(level = "debug", skip(section_data))]
+    pub fn from_section_data(section_data: &[u8]) -> Result<SfxData, HexError> {
+        let rows = hex::rows(section_data, ROW_LENGTH, SFX_COUNT)?;
+        let mut sfx_data = SfxData {
+            source_rows: rows.len(),
+            ..SfxData::new()
+        };
+        for (row_index, row) in rows.into_iter().enumerate() {
+            sfx_data.sfx[row_index] = Sfx::from_row(row, row_index)?;
+        }
+        Ok(sfx_data)
+    }
+    /// Encodes the rows of the `__sfx__` section, each row terminated by a newline
+    ///
+    /// Trailing empty sound-effects beyond the rows originally decoded are left out
+    pub fn to_section_data(&self) -> Box<[u8]> {
+        let last_used_row = self
+            .sfx
+            .iter()
+            .rposition(|sfx| *sfx != Sfx::default())
+            .map_or(0, |row_index| row_index + 1);
+        let row_count = last_used_row.max(self.source_rows);
+        self.sfx
+            .iter()
+            .take(row_count)
+            .flat_map(|sfx| sfx.encode_row().into_iter().chain(core::iter::once(b'\n')))
+            .collect()
+    }
+    pub fn get(&self, n: usize) -> Option<&Sfx> {
+        self.sfx.get(n)
+    }
+    pub fn get_mut(&mut self, n: usize) -> Option<&mut Sfx> {
+        self.sfx.get_mut(n)
+    }
+    pub fn iter(&self) -> impl Iterator<Item = &Sfx> {
+        self.sfx.iter()
+    }
+}
+
+impl Default for SfxData {
+    fn default() -> Self {
+        SfxData::new()
+    }
+}
+
+impl fmt::Debug for SfxData {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SfxData")
+            .field("source_rows", &self.source_rows)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let mut section_data = b"000c0008".to_vec();
+        section_data.extend(b"1b347");
+        section_data.extend(core::iter::repeat_n(b'0', (NOTE_COUNT - 1) * NOTE_DIGITS));
+        section_data.push(b'\n');
+        let mut sfx_data = SfxData::from_section_data(&section_data).unwrap();
+        let sfx = sfx_data.get(0).unwrap();
+        assert_eq!((sfx.speed, sfx.loop_start, sfx.loop_end), (12, 0, 8));
+        assert_eq!(
+            sfx.notes[0],
+            Note {
+                pitch: 0x1b,
+                waveform: 3,
+                volume: 4,
+                effect: 7
+            }
+        );
+        assert_eq!(sfx_data.to_section_data().as_ref(), section_data.as_slice());
+
+        sfx_data.get_mut(2).unwrap().notes[1].volume = 5;
+        assert_eq!(
+            bytes::NewlineIter::new(&sfx_data.to_section_data()).count(),
+            3
+        );
+    }
+}
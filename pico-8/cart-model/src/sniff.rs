@@ -0,0 +1,98 @@
+//! Detection of files which are not `.p8` carts, for targeted errors
+//!
+//! Only consulted once the header fails to parse, so a valid cart is never sniffed
+
+use core::fmt;
+
+/// A format commonly mistaken for a `.p8` cart
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ForeignFormat {
+    /// A `.p8.png` cart, or any other png-image
+    Png,
+    /// An html-export of a cart
+    Html,
+    /// A zip-archive, e.g. a zipped web- or binary-export
+    Zip,
+    Voxatron,
+    Picotron,
+}
+
+impl ForeignFormat {
+    /// What to do instead, shown after the detected format
+    pub const fn hint(&self) -> &'static str {
+        match self {
+            ForeignFormat::Png => {
+                "binary carts are read with the `png` feature, or re-saved as .p8 from pico-8 with `save cart.p8`"
+            }
+            ForeignFormat::Html => {
+                "the cart is embedded in the exported .js file, load the original .p8 or .p8.png instead"
+            }
+            ForeignFormat::Zip => "extract the archive, and point to the .p8 file inside it",
+            ForeignFormat::Voxatron => "voxatron carts are not supported, only pico-8 carts",
+            ForeignFormat::Picotron => {
+                "picotron carts are compiled with the `picotron` feature of pico-build-rs"
+            }
+        }
+    }
+}
+
+impl fmt::Display for ForeignFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ForeignFormat::Png => "a png-image",
+            ForeignFormat::Html => "an html-export",
+            ForeignFormat::Zip => "a zip-archive",
+            ForeignFormat::Voxatron => "a voxatron cart",
+            ForeignFormat::Picotron => "a picotron cart",
+        })
+    }
+}
+
+const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+const ZIP_SIGNATURE: &[u8] = b"PK\x03\x04";
+
+/// Returns the format the source looks like, if it is one of the known wrong formats
+pub fn sniff(src: &[u8]) -> Option<ForeignFormat> {
+    // Text-formats may be preceded by a byte-order-mark and whitespace
+    let text = src
+        .strip_prefix(b"\xef\xbb\xbf")
+        .unwrap_or(src)
+        .trim_ascii_start();
+    let starts_with_ignore_case = |prefix: &[u8]| {
+        text.get(..prefix.len())
+            .is_some_and(|start| start.eq_ignore_ascii_case(prefix))
+    };
+    if src.starts_with(PNG_SIGNATURE) {
+        Some(ForeignFormat::Png)
+    } else if src.starts_with(ZIP_SIGNATURE) {
+        Some(ForeignFormat::Zip)
+    } else if starts_with_ignore_case(b"<!doctype html") || starts_with_ignore_case(b"<html") {
+        Some(ForeignFormat::Html)
+    } else if starts_with_ignore_case(b"voxatron") {
+        Some(ForeignFormat::Voxatron)
+    } else if starts_with_ignore_case(b"picotron cartridge") {
+        Some(ForeignFormat::Picotron)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sniffs_known_formats() {
+        assert_eq!(sniff(b"\x89PNG\r\n\x1a\n\0\0"), Some(ForeignFormat::Png));
+        assert_eq!(sniff(b"PK\x03\x04rest"), Some(ForeignFormat::Zip));
+        assert_eq!(
+            sniff(b"\xef\xbb\xbf\n<!DOCTYPE html><html>"),
+            Some(ForeignFormat::Html)
+        );
+        assert_eq!(
+            sniff(b"picotron cartridge // www.picotron.net\n"),
+            Some(ForeignFormat::Picotron)
+        );
+        assert_eq!(sniff(b"pico-8 cartridge // http://www.pico-8.com\n"), None);
+    }
+}
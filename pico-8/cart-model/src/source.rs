@@ -0,0 +1,84 @@
+//! The bytes of a cart-file, for parsing a [`CartData`](crate::CartData) which borrows
+//! its code and assets from them
+//!
+//! With the `mmap` feature the cart-file is memory-mapped instead of read, so a large
+//! cart is parsed without copying it first. Memory-mapping a file is unsafe, as it may
+//! change under the mapping, so with the `forbid-unsafe` feature as well it is read
+
+use core::ops;
+
+use std::fs;
+use std::io;
+use std::path;
+
+#[derive(Debug)]
+pub enum CartSource {
+    /// The cart-file as read into memory
+    Read(Vec<u8>),
+    /// The cart-file as mapped into memory
+    #[cfg(all(feature = "mmap", not(feature = "forbid-unsafe")))]
+    Mapped(memmap2::Mmap),
+}
+
+impl CartSource {
+    /// Maps the cart-file with the `mmap` feature, and reads it otherwise, see the
+    /// module-documentation
+    #[tracing::instrument(level = "trace", skip(path))]
+    pub fn open<P: AsRef<path::Path> + ?Sized>(path: &P) -> io::Result<CartSource> {
+        #[cfg(all(feature = "mmap", not(feature = "forbid-unsafe")))]
+        {
+            let cart_file = fs::File::open(path)?;
+            // An empty file can not be mapped on all platforms
+            if cart_file.metadata()?.len() > 0 {
+                // SAFETY: The map is only read, the cart changing on disk while it is
+                // parsed yields a malformed cart at worst, as pico-8 may save it meanwhile
+                return unsafe { memmap2::Mmap::map(&cart_file) }.map(CartSource::Mapped);
+            }
+        }
+        fs::read(path).map(CartSource::Read)
+    }
+}
+
+impl ops::Deref for CartSource {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self {
+            CartSource::Read(cart_source) => cart_source,
+            #[cfg(all(feature = "mmap", not(feature = "forbid-unsafe")))]
+            CartSource::Mapped(cart_source) => cart_source,
+        }
+    }
+}
+
+impl AsRef<[u8]> for CartSource {
+    fn as_ref(&self) -> &[u8] {
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use alloc::borrow::Cow;
+
+    #[test]
+    fn parses_without_copying() {
+        let cart_path =
+            path::Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/carts/reference.p8");
+        let cart_source = CartSource::open(&cart_path).unwrap();
+        assert_eq!(cart_source.as_ref(), fs::read(&cart_path).unwrap());
+        #[cfg(all(feature = "mmap", not(feature = "forbid-unsafe")))]
+        assert!(matches!(cart_source, CartSource::Mapped(_)));
+        #[cfg(feature = "forbid-unsafe")]
+        assert!(matches!(cart_source, CartSource::Read(_)));
+
+        let cart = crate::CartData::from_bytes(&cart_source).unwrap();
+        let Some(Cow::Borrowed(code_data)) = cart.code_tabs()[0].as_ref().map(|tab| &tab.code_data)
+        else {
+            panic!("the first tab is not borrowed from the cart-source");
+        };
+        assert!(cart_source.as_ptr_range().contains(&code_data.as_ptr()));
+    }
+}
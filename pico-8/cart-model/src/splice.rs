@@ -0,0 +1,238 @@
+//! Splicing generated code into a tab, as done by [`CartData::splice_code`]
+//!
+//! The generated region is marked by comments, so the rest of the tab stays hand-written:
+//!
+//! ```lua
+//! --@generated levels
+//! levels={...}
+//! --@end
+//! ```
+//!
+//! The markers may be indented, and the end-marker may repeat the anchor, e.g. `--@end levels`
+
+use core::fmt;
+
+use std::borrow::Cow;
+
+use crate::CartData;
+
+/// Opens a generated region, followed by the anchor naming it
+pub const GENERATED_MARKER: &[u8] = b"--@generated";
+/// Closes the generated region opened last
+pub const END_MARKER: &[u8] = b"--@end";
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum SpliceError {
+    /// The cart has no tab with the index
+    MissingTab { tab_index: usize },
+    /// The tab has no `--@generated` marker with the anchor
+    MissingAnchor { anchor: String },
+    /// The anchor marks more than one region, so which to replace is ambiguous
+    DuplicateAnchor {
+        anchor: String,
+        line_numbers: Vec<usize>,
+    },
+    /// The region is not closed by an `--@end` marker before the next region or the end of the tab
+    UnterminatedRegion { anchor: String, line_number: usize },
+    /// The `--@end` marker names another anchor than the region it closes
+    MismatchedEnd {
+        anchor: String,
+        end_anchor: String,
+        line_number: usize,
+    },
+}
+
+impl fmt::Display for SpliceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let description = "Splice error";
+        let reason = match self {
+            SpliceError::MissingTab { tab_index } => format!("the cart has no tab {tab_index}"),
+            SpliceError::MissingAnchor { anchor } => {
+                format!("no `--@generated {anchor}` marker in the tab")
+            }
+            SpliceError::DuplicateAnchor {
+                anchor,
+                line_numbers,
+            } => format!("`--@generated {anchor}` is marked on lines {line_numbers:?}"),
+            SpliceError::UnterminatedRegion {
+                anchor,
+                line_number,
+            } => format!("`--@generated {anchor}` on line {line_number} has no `--@end` marker"),
+            SpliceError::MismatchedEnd {
+                anchor,
+                end_anchor,
+                line_number,
+            } => format!("`--@end {end_anchor}` on line {line_number} closes `{anchor}`"),
+        };
+        f.write_fmt(format_args!("{description}: {reason}"))
+    }
+}
+
+impl core::error::Error for SpliceError {}
+
+impl SpliceError {
+    /// The stable diagnostic-code, see `pico-build-rs explain`
+    pub const fn code(&self) -> &'static str {
+        "E013"
+    }
+}
+
+/// A marker-line, with what follows the marker
+#[derive(Debug, PartialEq, Eq)]
+enum Marker<'a> {
+    Generated(&'a [u8]),
+    End(&'a [u8]),
+}
+
+/// Parses the line as a marker, ignoring surrounding whitespace
+///
+/// The marker must be followed by whitespace or end the line, so `--@endless` is a comment
+fn parse_marker(line: &[u8]) -> Option<Marker<'_>> {
+    let line = line.trim_ascii();
+    let rest_after = |marker: &[u8]| {
+        let rest = line.strip_prefix(marker)?;
+        (rest.is_empty() || rest[0].is_ascii_whitespace()).then(|| rest.trim_ascii())
+    };
+    rest_after(GENERATED_MARKER)
+        .map(Marker::Generated)
+        .or_else(|| rest_after(END_MARKER).map(Marker::End))
+}
+
+/// The anchor-name, also accepting the whole marker, e.g. `--@generated levels`
+fn anchor_name(anchor: &str) -> &str {
+    let anchor = anchor.trim();
+    anchor
+        .strip_prefix("--@generated")
+        .map_or(anchor, str::trim_start)
+}
+
+/// Replaces the lines between the markers of the anchor with the block
+///
+/// Splicing the same block again returns the same code
+pub fn splice(code: &[u8], anchor: &str, block: &[u8]) -> Result<Vec<u8>, SpliceError> {
+    let anchor = anchor_name(anchor);
+    let lines: Vec<&[u8]> = code.split_inclusive(|byte| *byte == b'\n').collect();
+    // The regions of the anchor, as the indices of their marker-lines
+    let mut regions: Vec<(usize, usize)> = Vec::new();
+    let mut open: Option<(usize, &[u8])> = None;
+    for (index, line) in lines.iter().enumerate() {
+        match (parse_marker(line), open) {
+            (Some(Marker::Generated(name)), None) => open = Some((index, name)),
+            (Some(Marker::Generated(_)), Some((start, name))) => {
+                return Err(SpliceError::UnterminatedRegion {
+                    anchor: String::from_utf8_lossy(name).into_owned(),
+                    line_number: start + 1,
+                });
+            }
+            (Some(Marker::End(end_name)), Some((start, name))) => {
+                if !end_name.is_empty() && end_name != name {
+                    return Err(SpliceError::MismatchedEnd {
+                        anchor: String::from_utf8_lossy(name).into_owned(),
+                        end_anchor: String::from_utf8_lossy(end_name).into_owned(),
+                        line_number: index + 1,
+                    });
+                }
+                if name == anchor.as_bytes() {
+                    regions.push((start, index));
+                }
+                open = None;
+            }
+            // A stray end-marker is left as the comment it is
+            (Some(Marker::End(_)), None) | (None, _) => {}
+        }
+    }
+    if let Some((start, name)) = open {
+        return Err(SpliceError::UnterminatedRegion {
+            anchor: String::from_utf8_lossy(name).into_owned(),
+            line_number: start + 1,
+        });
+    }
+    let (start, end) = match regions.as_slice() {
+        [] => {
+            return Err(SpliceError::MissingAnchor {
+                anchor: anchor.to_string(),
+            });
+        }
+        [region] => *region,
+        _ => {
+            return Err(SpliceError::DuplicateAnchor {
+                anchor: anchor.to_string(),
+                line_numbers: regions.iter().map(|(start, _)| start + 1).collect(),
+            });
+        }
+    };
+    let mut spliced = lines[..=start].concat();
+    if !spliced.ends_with(b"\n") {
+        spliced.push(b'\n');
+    }
+    spliced.extend_from_slice(block);
+    if !block.is_empty() && !block.ends_with(b"\n") {
+        spliced.push(b'\n');
+    }
+    spliced.extend(lines[end..].concat());
+    Ok(spliced)
+}
+
+impl CartData<'_> {
+    /// Replaces the generated region of the anchor in the tab with the block,
+    /// see the [module-documentation](crate::splice)
+    #[tracing::instrument(level = "debug", skip(self, block))]
+    pub fn splice_code(
+        &mut self,
+        tab_index: usize,
+        anchor: &str,
+        block: &[u8],
+    ) -> Result<(), SpliceError> {
+        let tab = self
+            .code_tabs
+            .get_mut(tab_index)
+            .and_then(Option::as_mut)
+            .ok_or(SpliceError::MissingTab { tab_index })?;
+        tab.code_data = Cow::Owned(splice(&tab.code_data, anchor, block)?);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splices_between_markers_idempotently() {
+        const CODE: &[u8] = b"function _init()
+  --@generated levels
+  levels={}
+  --@end levels
+end
+--@generated enemies
+--@end
+";
+        let block = b"  levels={1,2,3}";
+        let spliced = splice(CODE, "--@generated levels", block).unwrap();
+        assert_eq!(
+            spliced,
+            b"function _init()
+  --@generated levels
+  levels={1,2,3}
+  --@end levels
+end
+--@generated enemies
+--@end
+"
+        );
+        assert_eq!(splice(&spliced, "levels", block).unwrap(), spliced);
+        assert_eq!(
+            splice(CODE, "bosses", block),
+            Err(SpliceError::MissingAnchor {
+                anchor: "bosses".to_string()
+            })
+        );
+        assert_eq!(
+            splice(b"--@generated levels\nlevels={}\n", "levels", block),
+            Err(SpliceError::UnterminatedRegion {
+                anchor: "levels".to_string(),
+                line_number: 1
+            })
+        );
+    }
+}
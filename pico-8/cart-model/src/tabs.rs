@@ -0,0 +1,213 @@
+//! The code-tabs of a cart, one slot per tab of the pico-8 code-editor
+//!
+//! A cart has code in any of the [`P8_MAX_CODE_EDITOR_TAB_COUNT`] tabs, not necessarily
+//! the first ones, so [`CodeTabs`] keeps a slot per tab and iterates the tabs present by
+//! their index, see [`CodeTabs::iter_present`]
+
+use core::fmt;
+use core::ops;
+use core::slice;
+
+use crate::{P8_MAX_CODE_EDITOR_TAB_COUNT, Tab};
+
+/// The code-tabs of a cart, see the module-documentation
+///
+/// Indexed by the tab, e.g. `code_tabs[0]` is the `Option` of the first tab
+#[derive(Clone, Default)]
+pub struct CodeTabs<'a>([Option<Tab<'a>>; P8_MAX_CODE_EDITOR_TAB_COUNT]);
+
+impl<'a> CodeTabs<'a> {
+    /// Puts the tab into the slot of the index, returning the tab it replaces
+    ///
+    /// # Panics
+    ///
+    /// If the index is past the last tab
+    pub fn insert(&mut self, tab_index: usize, tab: Tab<'a>) -> Option<Tab<'a>> {
+        self.0[tab_index].replace(tab)
+    }
+    /// Takes the tab out of the slot of the index, if there is one
+    pub fn remove(&mut self, tab_index: usize) -> Option<Tab<'a>> {
+        self.0.get_mut(tab_index)?.take()
+    }
+    /// The slot of the index, `None` if the index is past the last tab
+    pub fn get(&self, tab_index: usize) -> Option<&Option<Tab<'a>>> {
+        self.0.get(tab_index)
+    }
+    pub fn get_mut(&mut self, tab_index: usize) -> Option<&mut Option<Tab<'a>>> {
+        self.0.get_mut(tab_index)
+    }
+    /// The slots of all tabs, including the empty ones
+    pub fn iter(&self) -> slice::Iter<'_, Option<Tab<'a>>> {
+        self.0.iter()
+    }
+    pub fn iter_mut(&mut self) -> slice::IterMut<'_, Option<Tab<'a>>> {
+        self.0.iter_mut()
+    }
+    /// The tabs present, with their index
+    pub fn iter_present(&self) -> impl DoubleEndedIterator<Item = (usize, &Tab<'a>)> {
+        self.0
+            .iter()
+            .enumerate()
+            .filter_map(|(tab_index, tab)| Some((tab_index, tab.as_ref()?)))
+    }
+    /// The count of the tabs present, not of the slots, see [`CodeTabs::capacity`]
+    pub fn len(&self) -> usize {
+        self.0.iter().flatten().count()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.0.iter().all(Option::is_none)
+    }
+    /// The count of the slots, one per tab of the code-editor
+    pub const fn capacity(&self) -> usize {
+        P8_MAX_CODE_EDITOR_TAB_COUNT
+    }
+    /// The lines of code of all tabs present
+    pub fn total_lines(&self) -> usize {
+        self.0
+            .iter()
+            .flatten()
+            .map(|tab| bytes::NewlineIter::from(tab.code_data.as_ref()).count())
+            .sum()
+    }
+    /// Maps each tab present, keeping the empty slots empty
+    pub fn map<'b>(self, mut f: impl FnMut(Tab<'a>) -> Tab<'b>) -> CodeTabs<'b> {
+        CodeTabs(self.0.map(|tab| tab.map(&mut f)))
+    }
+    pub fn into_owned(self) -> CodeTabs<'static> {
+        self.map(Tab::into_owned)
+    }
+    /// The slots of all tabs, as an array
+    pub fn as_array(&self) -> &[Option<Tab<'a>>; P8_MAX_CODE_EDITOR_TAB_COUNT] {
+        &self.0
+    }
+    pub fn into_array(self) -> [Option<Tab<'a>>; P8_MAX_CODE_EDITOR_TAB_COUNT] {
+        self.0
+    }
+    /// Collects the tabs into the slots from the first, failing if there are more tabs
+    /// than slots
+    pub fn try_from_iter<I: IntoIterator<Item = Tab<'a>>>(
+        tabs: I,
+    ) -> Result<CodeTabs<'a>, TabOverflowError> {
+        let mut code_tabs = CodeTabs::default();
+        let mut tab_count = 0;
+        for tab in tabs {
+            if let Some(slot) = code_tabs.0.get_mut(tab_count) {
+                *slot = Some(tab);
+            }
+            tab_count += 1;
+        }
+        match tab_count > P8_MAX_CODE_EDITOR_TAB_COUNT {
+            true => Err(TabOverflowError { tab_count }),
+            false => Ok(code_tabs),
+        }
+    }
+}
+
+impl fmt::Debug for CodeTabs<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map().entries(self.iter_present()).finish()
+    }
+}
+
+impl<'a> From<[Option<Tab<'a>>; P8_MAX_CODE_EDITOR_TAB_COUNT]> for CodeTabs<'a> {
+    fn from(value: [Option<Tab<'a>>; P8_MAX_CODE_EDITOR_TAB_COUNT]) -> Self {
+        CodeTabs(value)
+    }
+}
+
+impl<'a> From<CodeTabs<'a>> for [Option<Tab<'a>>; P8_MAX_CODE_EDITOR_TAB_COUNT] {
+    fn from(value: CodeTabs<'a>) -> Self {
+        value.0
+    }
+}
+
+impl<'a, I: slice::SliceIndex<[Option<Tab<'a>>]>> ops::Index<I> for CodeTabs<'a> {
+    type Output = I::Output;
+    fn index(&self, index: I) -> &Self::Output {
+        &self.0[index]
+    }
+}
+
+impl<'a, I: slice::SliceIndex<[Option<Tab<'a>>]>> ops::IndexMut<I> for CodeTabs<'a> {
+    fn index_mut(&mut self, index: I) -> &mut Self::Output {
+        &mut self.0[index]
+    }
+}
+
+impl<'a> IntoIterator for CodeTabs<'a> {
+    type Item = Option<Tab<'a>>;
+    type IntoIter = core::array::IntoIter<Option<Tab<'a>>, P8_MAX_CODE_EDITOR_TAB_COUNT>;
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.into_iter()
+    }
+}
+
+impl<'a, 'b> IntoIterator for &'b CodeTabs<'a> {
+    type Item = &'b Option<Tab<'a>>;
+    type IntoIter = slice::Iter<'b, Option<Tab<'a>>>;
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.iter()
+    }
+}
+
+/// More tabs than the code-editor has
+#[derive(Debug, PartialEq, Eq)]
+pub struct TabOverflowError {
+    pub tab_count: usize,
+}
+
+impl fmt::Display for TabOverflowError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let description = "Too many tabs";
+        let reason = format!(
+            "{} tabs do not fit into the {P8_MAX_CODE_EDITOR_TAB_COUNT} tabs of the code-editor",
+            self.tab_count
+        );
+        f.write_fmt(format_args!("{description}: {reason}"))
+    }
+}
+
+impl core::error::Error for TabOverflowError {}
+
+impl TabOverflowError {
+    /// The stable diagnostic-code, see `pico-build-rs explain`
+    pub const fn code(&self) -> &'static str {
+        "E024"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use alloc::borrow::Cow;
+
+    fn tab(code: &'static str) -> Tab<'static> {
+        Tab {
+            line_number: 0,
+            code_data: Cow::Borrowed(code.as_bytes()),
+        }
+    }
+
+    #[test]
+    fn counts_the_tabs_present() {
+        let mut code_tabs = CodeTabs::default();
+        assert!(code_tabs.is_empty());
+        code_tabs.insert(0, tab("a=1\n"));
+        code_tabs.insert(3, tab("b=2\nc=3\n"));
+        assert_eq!(code_tabs.len(), 2);
+        assert_eq!(code_tabs.capacity(), P8_MAX_CODE_EDITOR_TAB_COUNT);
+        assert_eq!(code_tabs.total_lines(), 3);
+        let present: Vec<usize> = code_tabs.iter_present().map(|(index, _)| index).collect();
+        assert_eq!(present, [0, 3]);
+        assert!(code_tabs.remove(3).is_some());
+        assert_eq!(code_tabs.len(), 1);
+
+        let tabs = |count| (0..count).map(|_| tab("x=1\n"));
+        assert_eq!(CodeTabs::try_from_iter(tabs(16)).unwrap().len(), 16);
+        assert_eq!(
+            CodeTabs::try_from_iter(tabs(17)).unwrap_err(),
+            TabOverflowError { tab_count: 17 }
+        );
+    }
+}
@@ -0,0 +1,168 @@
+//! How the text of the code-tabs is checked when parsing a cart
+//!
+//! pico-8 saves its glyphs as utf-8 into a `.p8` file, but carts written by other tools,
+//! or converted from a rom, may hold raw p8scii-bytes instead. The [`TextPolicy`] a cart is
+//! parsed with is kept on the [`CartData`](crate::CartData), so each consumer knows whether
+//! the code is known to be utf-8
+
+use core::fmt;
+
+use alloc::borrow::Cow;
+
+use crate::{CartParseError, CodeTabs, Tab};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TextPolicy {
+    /// Keeps the code as is, which may not be utf-8
+    #[default]
+    Raw,
+    /// Replaces the invalid utf-8 of the code with `U+FFFD`
+    ValidateAndReplace,
+    /// Fails the parse on invalid utf-8 in the code
+    ValidateAndError,
+}
+
+impl TextPolicy {
+    /// Whether the code of a cart parsed with the policy is known to be utf-8
+    pub const fn guarantees_utf8(&self) -> bool {
+        match self {
+            TextPolicy::Raw => false,
+            TextPolicy::ValidateAndReplace | TextPolicy::ValidateAndError => true,
+        }
+    }
+    /// Checks each tab of the code, borrowing the tabs which are valid
+    #[tracing::instrument(level = "debug", skip(code_tabs))]
+    pub fn apply<'a>(&self, code_tabs: CodeTabs<'a>) -> Result<CodeTabs<'a>, CartParseError> {
+        match self {
+            TextPolicy::Raw => Ok(code_tabs),
+            TextPolicy::ValidateAndReplace => Ok(replace_invalid(code_tabs)),
+            TextPolicy::ValidateAndError => match first_invalid_line(&code_tabs) {
+                Some(line_number) => Err(CartParseError::InvalidUtf8 { line_number }),
+                None => Ok(code_tabs),
+            },
+        }
+    }
+}
+
+/// Code set into a cart of [`TextPolicy::ValidateAndError`] which is not utf-8, see
+/// [`CartData::try_set_code_data`](crate::CartData::try_set_code_data)
+#[derive(Debug, PartialEq, Eq)]
+pub struct TextError {
+    pub line_number: usize,
+}
+
+impl fmt::Display for TextError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let description = "Text error";
+        let reason = format!("the code at line {} is not utf-8", self.line_number);
+        f.write_fmt(format_args!("{description}: {reason}"))
+    }
+}
+
+impl core::error::Error for TextError {}
+
+impl TextError {
+    /// The stable diagnostic-code, see `pico-build-rs explain`
+    pub const fn code(&self) -> &'static str {
+        "E015"
+    }
+}
+
+/// The line of the first invalid utf-8 in the code, `None` if all of it is valid
+pub fn first_invalid_line(code_tabs: &CodeTabs<'_>) -> Option<usize> {
+    code_tabs.iter_present().find_map(|(_, tab)| {
+        let e = core::str::from_utf8(&tab.code_data).err()?;
+        // The line of the tab is counted from zero
+        Some(tab.line_number + bytes::NewlineIter::new(&tab.code_data[..e.valid_up_to()]).count())
+    })
+}
+
+/// Replaces the invalid utf-8 of the code with `U+FFFD`, borrowing the tabs which are valid
+pub fn replace_invalid(code_tabs: CodeTabs<'_>) -> CodeTabs<'_> {
+    code_tabs.map(|tab| match String::from_utf8_lossy(&tab.code_data) {
+        Cow::Borrowed(_) => tab,
+        Cow::Owned(code_data) => {
+            tracing::warn!(
+                "Replacing the invalid utf-8 of the tab from line {}",
+                tab.line_number
+            );
+            Tab {
+                line_number: tab.line_number,
+                code_data: Cow::Owned(code_data.into_bytes()),
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn checks_code_per_policy() {
+        const HEADER: &str = "pico-8 cartridge // http://www.pico-8.com\nversion 43\n";
+        let cart_source = [
+            HEADER.as_bytes(),
+            b"__lua__\nx=1\n-->8\ny=2\ns=\"\x83\"\n__gfx__\n",
+        ]
+        .concat();
+        let parse = |text_policy| crate::CartData::from_bytes_with(&cart_source, text_policy);
+
+        let raw = parse(TextPolicy::Raw).unwrap();
+        assert_eq!(raw.text_policy(), TextPolicy::Raw);
+        assert!(
+            raw.code_tabs()[1]
+                .as_ref()
+                .unwrap()
+                .code_data
+                .contains(&0x83)
+        );
+
+        let replaced = parse(TextPolicy::ValidateAndReplace).unwrap();
+        assert_eq!(
+            replaced.code_tabs()[1].as_ref().unwrap().code_data.as_ref(),
+            "y=2\ns=\"\u{fffd}\"\n".as_bytes()
+        );
+        assert!(matches!(
+            replaced.code_tabs()[0].as_ref().unwrap().code_data,
+            Cow::Borrowed(_)
+        ));
+
+        assert!(matches!(
+            parse(TextPolicy::ValidateAndError),
+            Err(crate::CartDataError::Parse(CartParseError::InvalidUtf8 {
+                line_number: 7
+            }))
+        ));
+    }
+
+    #[test]
+    fn sets_code_per_policy() {
+        let mut cart = crate::CartData::from_bytes_with(
+            b"pico-8 cartridge // http://www.pico-8.com\nversion 43\n__lua__\nx=1\n__gfx__\n",
+            TextPolicy::ValidateAndError,
+        )
+        .unwrap();
+        let invalid = || {
+            let mut code_tabs = CodeTabs::default();
+            code_tabs.insert(
+                0,
+                Tab {
+                    line_number: 3,
+                    code_data: Cow::Borrowed(b"y=1\ns=\"\x83\"\n"),
+                },
+            );
+            code_tabs
+        };
+
+        assert_eq!(
+            cart.try_set_code_data(invalid()),
+            Err(TextError { line_number: 5 })
+        );
+        assert_eq!(cart.code_text(0).as_deref(), Some("x=1\n"));
+        assert_eq!(cart.text_policy(), TextPolicy::ValidateAndError);
+
+        cart.set_code_data(invalid());
+        assert_eq!(cart.text_policy(), TextPolicy::Raw);
+    }
+}
@@ -0,0 +1,226 @@
+//! Token-counting, following the rules of the counter in the pico-8 code-editor
+//!
+//! Every name, literal, keyword and operator is a token, except:
+//! - comments, `,` `.` `:` `;` `::`, and the keywords `local` and `end`
+//! - closing brackets, so each pair of brackets counts once
+//! - a `-` directly before a number-literal, so `-1` counts once
+
+use core::fmt;
+
+/// The tokens a cart of the latest stable release can hold, see [`crate::format::Spec`]
+pub const TOKEN_LIMIT: usize = crate::format::LATEST_STABLE.token_limit;
+
+/// Operators and punctuation, longest first so the longest match is taken
+const SYMBOLS: &[&[u8]] = &[
+    b"...", b"..=", b">>>", b"<<>", b">><", b"^^=", b"//=", b"\\=", b"..", b"==", b"~=", b"!=",
+    b"<=", b">=", b"+=", b"-=", b"*=", b"/=", b"%=", b"^=", b"|=", b"&=", b"<<", b">>", b"^^",
+    b"//", b"::", b"+", b"-", b"*", b"/", b"\\", b"%", b"^", b"#", b"&", b"|", b"~", b"<", b">",
+    b"=", b"(", b")", b"{", b"}", b"[", b"]", b";", b":", b",", b".", b"@", b"$", b"?",
+];
+
+/// The symbols which do not count as a token
+const FREE_SYMBOLS: &[&[u8]] = &[b",", b".", b":", b";", b"::", b")", b"]", b"}"];
+
+/// The keywords which do not count as a token
+const FREE_KEYWORDS: &[&[u8]] = &[b"end", b"local"];
+
+/// The keywords after which `-` is a unary operator
+const OPERATOR_KEYWORDS: &[&[u8]] = &[
+    b"and", b"do", b"else", b"elseif", b"if", b"in", b"not", b"or", b"return", b"then", b"until",
+    b"while",
+];
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum TokenKind {
+    /// A name, literal or closing bracket, after which `-` is a binary operator
+    Value,
+    Other,
+}
+
+/// Returns the length of the long bracket opening at the start of the source,
+/// e.g. 4 for `[==[`, and its level
+fn long_bracket_open(src: &[u8]) -> Option<(usize, usize)> {
+    let level = src
+        .strip_prefix(b"[")?
+        .iter()
+        .take_while(|byte| **byte == b'=')
+        .count();
+    (src.get(level + 1) == Some(&b'[')).then_some((level + 2, level))
+}
+
+/// Returns the length of the long string or comment at the start of the source,
+/// including its brackets
+fn long_bracket_length(src: &[u8]) -> Option<usize> {
+    let (open_length, level) = long_bracket_open(src)?;
+    let close = [b"]".as_slice(), &b"=".repeat(level), b"]"].concat();
+    let body_length = src[open_length..]
+        .windows(close.len())
+        .position(|window| window == close)
+        .map_or(src.len() - open_length, |position| position + close.len());
+    Some(open_length + body_length)
+}
+
+/// Returns the length of the quoted string at the start of the source, including its quotes
+fn quoted_string_length(src: &[u8]) -> usize {
+    let quote = src[0];
+    let mut index = 1;
+    while let Some(byte) = src.get(index) {
+        match *byte {
+            b'\\' => index += 2,
+            b'\n' => return index,
+            byte if byte == quote => return index + 1,
+            _ => index += 1,
+        }
+    }
+    src.len()
+}
+
+/// Returns the length of the number-literal at the start of the source,
+/// including hex-, binary- and fractional-parts
+fn number_length(src: &[u8]) -> usize {
+    let radix_prefix = src.get(..2).map(|prefix| prefix.to_ascii_lowercase());
+    let (start, is_digit): (usize, fn(&u8) -> bool) = match radix_prefix.as_deref() {
+        Some(b"0x") => (2, u8::is_ascii_hexdigit),
+        Some(b"0b") => (2, |byte| matches!(byte, b'0' | b'1')),
+        _ => (0, u8::is_ascii_digit),
+    };
+    let mut index = start;
+    let mut seen_point = false;
+    while let Some(byte) = src.get(index) {
+        match byte {
+            b'.' if !seen_point && src.get(index + 1) != Some(&b'.') => seen_point = true,
+            byte if is_digit(byte) => {}
+            _ => break,
+        }
+        index += 1;
+    }
+    index
+}
+
+fn is_name_byte(byte: &u8) -> bool {
+    byte.is_ascii_alphanumeric() || *byte == b'_' || !byte.is_ascii()
+}
+
+/// Counts the tokens of the lua-source as pico-8 does
+pub fn count_tokens(lua_src: &[u8]) -> usize {
+    let mut count = 0;
+    let mut previous = TokenKind::Other;
+    let mut remainder = lua_src;
+    while let Some(byte) = remainder.first() {
+        let (length, counted, kind) = match byte {
+            byte if byte.is_ascii_whitespace() => {
+                remainder = &remainder[1..];
+                continue;
+            }
+            b'-' if remainder.starts_with(b"--") => {
+                let length = long_bracket_length(&remainder[2..]).map_or_else(
+                    || {
+                        remainder
+                            .iter()
+                            .position(|byte| *byte == b'\n')
+                            .unwrap_or(remainder.len())
+                    },
+                    |length| length + 2,
+                );
+                remainder = &remainder[length..];
+                continue;
+            }
+            b'"' | b'\'' => (quoted_string_length(remainder), true, TokenKind::Value),
+            b'[' if long_bracket_open(remainder).is_some() => (
+                long_bracket_length(remainder).unwrap_or(remainder.len()),
+                true,
+                TokenKind::Value,
+            ),
+            byte if byte.is_ascii_digit() => (number_length(remainder), true, TokenKind::Value),
+            b'.' if remainder.get(1).is_some_and(u8::is_ascii_digit) => {
+                (number_length(remainder), true, TokenKind::Value)
+            }
+            b'-' if previous == TokenKind::Other
+                && remainder.get(1).is_some_and(u8::is_ascii_digit) =>
+            {
+                (1, false, TokenKind::Other)
+            }
+            byte if is_name_byte(byte) => {
+                let length = remainder
+                    .iter()
+                    .position(|byte| !is_name_byte(byte))
+                    .unwrap_or(remainder.len());
+                let name = &remainder[..length];
+                let kind = if OPERATOR_KEYWORDS.contains(&name) {
+                    TokenKind::Other
+                } else {
+                    TokenKind::Value
+                };
+                (length, !FREE_KEYWORDS.contains(&name), kind)
+            }
+            _ => match SYMBOLS.iter().find(|symbol| remainder.starts_with(symbol)) {
+                Some(symbol) => {
+                    let kind = match *symbol {
+                        b")" | b"]" | b"}" => TokenKind::Value,
+                        _ => TokenKind::Other,
+                    };
+                    (symbol.len(), !FREE_SYMBOLS.contains(symbol), kind)
+                }
+                None => (1, false, TokenKind::Other),
+            },
+        };
+        if counted {
+            count += 1;
+        }
+        previous = kind;
+        remainder = &remainder[length..];
+    }
+    count
+}
+
+/// The code of a cart has more tokens than [`TOKEN_LIMIT`]
+#[derive(Debug, PartialEq, Eq)]
+pub struct TokenLimitError {
+    pub token_count: usize,
+    /// The limit of the format of the cart
+    pub token_limit: usize,
+}
+
+impl fmt::Display for TokenLimitError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let description = "Token limit exceeded";
+        let reason = format!(
+            "{} tokens, the limit is {}",
+            self.token_count, self.token_limit
+        );
+        f.write_fmt(format_args!("{description}: {reason}"))
+    }
+}
+
+impl core::error::Error for TokenLimitError {}
+
+impl TokenLimitError {
+    /// The stable diagnostic-code, see `pico-build-rs explain`
+    pub const fn code(&self) -> &'static str {
+        "E007"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counts_like_pico_8() {
+        let cases: &[(&str, usize)] = &[
+            ("x=1", 3),
+            ("local x = -1", 3),
+            ("x = a - 1", 5),
+            ("print(\"hello, world\")", 3),
+            ("t={1,2,3}", 6),
+            ("a.b:c(d)", 5),
+            ("if x then y+=1 end -- comment", 6),
+            ("s=[[long\nstring]] --[[ long\ncomment ]]", 3),
+            ("function f(...) return #t..0x1f end", 9),
+            ("return -1", 2),
+        ];
+        for (src, expected) in cases {
+            assert_eq!(count_tokens(src.as_bytes()), *expected, "{src:?}");
+        }
+    }
+}
@@ -0,0 +1,82 @@
+//! Allocation-budget of the parser, measured with a counting allocator
+//!
+//! Lives in its own test-binary, as the allocator is global to it.
+//! Lower the budget when a refactor reduces the allocations
+
+use core::alloc::{GlobalAlloc, Layout};
+use core::cell::Cell;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use std::alloc::System;
+
+use pico_8_cart_model::CartData;
+
+const REFERENCE_CART: &[u8] = include_bytes!("carts/reference.p8");
+
+/// The allocations allowed for parsing and serializing the reference cart,
+/// 31 were measured when the budget was added
+const ALLOCATION_BUDGET: usize = 36;
+
+struct CountingAllocator;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+thread_local! {
+    /// Only the measuring thread is counted, so the harness does not skew the count
+    static COUNTING: Cell<bool> = const { Cell::new(false) };
+}
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        if COUNTING.get() {
+            ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        }
+        unsafe { System.alloc(layout) }
+    }
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        unsafe { System.dealloc(ptr, layout) }
+    }
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        if COUNTING.get() {
+            ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        }
+        unsafe { System.realloc(ptr, layout, new_size) }
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+/// Returns the result of `f`, and the allocations made while running it
+fn count_allocations<T>(f: impl FnOnce() -> T) -> (T, usize) {
+    let before = ALLOCATIONS.load(Ordering::Relaxed);
+    COUNTING.set(true);
+    let result = f();
+    COUNTING.set(false);
+    (result, ALLOCATIONS.load(Ordering::Relaxed) - before)
+}
+
+#[test]
+fn parse_and_serialize() {
+    let (cart_source, allocations) = count_allocations(|| {
+        CartData::from_cart_source(REFERENCE_CART)
+            .unwrap()
+            .into_cart_source::<Vec<u8>>()
+    });
+    assert_eq!(cart_source, REFERENCE_CART);
+    println!("{allocations} allocations for parse + serialize");
+    assert!(
+        allocations <= ALLOCATION_BUDGET,
+        "{allocations} allocations exceed the budget of {ALLOCATION_BUDGET}"
+    );
+}
+
+#[test]
+fn stream_without_allocating() {
+    let cart = CartData::from_cart_source(REFERENCE_CART).unwrap();
+    let mut cart_source = Vec::with_capacity(REFERENCE_CART.len());
+    let (result, allocations) = count_allocations(|| cart.write_to(&mut cart_source));
+    result.unwrap();
+    assert_eq!(cart_source, REFERENCE_CART);
+    assert_eq!(allocations, 0, "streaming the cart allocated");
+}
@@ -8,3 +8,58 @@ fn get_pico_8_cart<P: AsRef<path::Path> + ?Sized>(path: &P) -> io::Result<fs::Fi
 /// Attempts to parse an empty cartridge
 #[test]
 fn empty() {}
+
+/// Merges the tabs past the last one of the code-editor into it, as pico-8 does
+#[test]
+fn merges_tabs_past_the_limit() {
+    let cart_source: &[u8] = include_bytes!("carts/seventeen-tabs.p8");
+    let cart = pico_8_cart_model::CartData::from_cart_source(cart_source).unwrap();
+    let last_tab = cart.code_tabs()[pico_8_cart_model::P8_MAX_CODE_EDITOR_TAB_COUNT - 1]
+        .as_ref()
+        .unwrap();
+    assert_eq!(last_tab.line_number, 48);
+    assert_eq!(
+        last_tab.code_data.as_ref(),
+        b"-- tab 15\nt15=15\n-->8\n-- tab 16\nt16=16\n"
+    );
+
+    let mut written = Vec::new();
+    cart.write_to(&mut written).unwrap();
+    let mut rewritten = Vec::new();
+    pico_8_cart_model::CartData::from_cart_source(written.as_slice())
+        .unwrap()
+        .write_to(&mut rewritten)
+        .unwrap();
+    assert_eq!(rewritten, written);
+}
+
+/// Loads the same cart from bytes, text, a reader and a path
+#[test]
+fn loads_uniformly() {
+    let cart_path = path::Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/carts/reference.p8");
+    let cart_bytes = fs::read(&cart_path).unwrap();
+    let cart_text = fs::read_to_string(&cart_path).unwrap();
+    let write = |cart: pico_8_cart_model::CartData<'_>| {
+        let mut written = Vec::new();
+        cart.write_to(&mut written).unwrap();
+        written
+    };
+
+    let expected = write(pico_8_cart_model::CartData::from_bytes(&cart_bytes).unwrap());
+    assert_eq!(
+        write(pico_8_cart_model::CartData::from_str(&cart_text).unwrap()),
+        expected
+    );
+    assert_eq!(
+        write(pico_8_cart_model::CartData::from_reader(io::Cursor::new(&cart_bytes)).unwrap()),
+        expected
+    );
+    assert_eq!(
+        write(pico_8_cart_model::CartData::from_path(&cart_path).unwrap()),
+        expected
+    );
+    assert!(matches!(
+        pico_8_cart_model::CartData::from_path(&cart_path.with_extension("missing")),
+        Err(pico_8_cart_model::CartDataError::Io(_))
+    ));
+}
@@ -0,0 +1,137 @@
+//! Decoding `.p8.png` carts which were not written by this crate
+//!
+//! A cart saved by pico-8 as `<name>.p8.png` is checked against the `<name>.p8` it was
+//! exported from, when both are in `tests/carts`
+#![cfg(feature = "png")]
+
+use std::fs;
+use std::path;
+
+use pico_8_cart_model::CartData;
+use pico_8_cart_model::png;
+
+fn code_of(cart: &CartData<'_>) -> Vec<(usize, Vec<u8>)> {
+    cart.code_tabs()
+        .iter_present()
+        .map(|(tab_index, tab)| {
+            let code = tab.code_data.as_ref();
+            (tab_index, code.strip_suffix(b"\n").unwrap_or(code).to_vec())
+        })
+        .collect()
+}
+
+/// Decodes each `.p8.png` saved by pico-8 into the code, gfx, sfx and music of its `.p8`
+#[test]
+fn decodes_carts_saved_by_pico_8() {
+    let carts_dir = path::Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/carts");
+    for entry in fs::read_dir(&carts_dir).unwrap() {
+        let png_path = entry.unwrap().path();
+        let Some(name) = png_path
+            .file_name()
+            .and_then(|file_name| file_name.to_str()?.strip_suffix(".p8.png"))
+        else {
+            continue;
+        };
+        let p8_path = carts_dir.join(format!("{name}.p8"));
+        let exported = CartData::from_path(&p8_path).unwrap();
+        let saved = png::read_cart_png(fs::File::open(&png_path).unwrap()).unwrap();
+
+        assert_eq!(code_of(&saved), code_of(&exported), "code of {name}");
+        assert!(
+            saved.gfx_sheet().unwrap() == exported.gfx_sheet().unwrap(),
+            "gfx of {name}"
+        );
+        assert!(
+            saved
+                .sfx_data()
+                .unwrap()
+                .iter()
+                .eq(exported.sfx_data().unwrap().iter()),
+            "sfx of {name}"
+        );
+        assert!(
+            saved
+                .music()
+                .unwrap()
+                .iter()
+                .eq(exported.music().unwrap().iter()),
+            "music of {name}"
+        );
+    }
+}
+
+/// Decodes a cart-image assembled byte by byte from the memory-layout, with the code
+/// compressed by hand
+#[test]
+fn decodes_a_hand_assembled_cart() {
+    let mut rom = vec![0u8; 0x8000];
+    // The first two pixels of the sprite-sheet, the left one in the low nibble
+    rom[0x0000] = 0x21;
+    // The first pattern plays sfx 3 on channel 0 with the others silenced by bit 6, and
+    // loops back by the loop-end flag in bit 7 of the second channel
+    rom[0x3100..0x3104].copy_from_slice(&[0x03, 0xc2, 0x43, 0x44]);
+    // The first note of sfx 3: pitch 24, waveform 1, volume 5, effect 2
+    let note: u16 = 24 | 1 << 6 | 5 << 9 | 2 << 12;
+    rom[0x3200 + 3 * 68..0x3200 + 3 * 68 + 2].copy_from_slice(&note.to_le_bytes());
+    // `a=1` as three literals: `a` at move-to-front index 97, then `=` and `1` at 61 and
+    // 49 unmoved, each as the literal-bit, the unary `110` and the index minus 48
+    let mut fields = Vec::new();
+    for index in [97u16, 61 + 1, 49 + 2] {
+        fields.extend([(1, 1), (3, 0b011), (6, index - 48)]);
+    }
+    // Then `a=1` again, as a back-reference with the 5-bit prefix `1,1`, offset 3 and length 3
+    fields.extend([(1, 0), (2, 0b11), (5, 3 - 1), (3, 0)]);
+    let mut bits = Vec::new();
+    for (count, value) in fields {
+        bits.extend((0..count).map(|bit| value >> bit & 1));
+    }
+    let code_region = &mut rom[0x4300..];
+    code_region[..8].copy_from_slice(b"\0pxa\0\x06\0\x0e");
+    for (byte, chunk) in code_region[8..].iter_mut().zip(bits.chunks(8)) {
+        *byte = chunk
+            .iter()
+            .enumerate()
+            .fold(0, |byte, (bit, value)| byte | (*value as u8) << bit);
+    }
+    rom.push(42);
+
+    let mut rgba = Vec::with_capacity(png::WIDTH * png::HEIGHT * 4);
+    for byte in rom.iter().copied().chain(core::iter::repeat(0)) {
+        if rgba.len() == rgba.capacity() {
+            break;
+        }
+        rgba.extend([byte >> 4 & 3, byte >> 2 & 3, byte & 3, 0xfc | byte >> 6]);
+    }
+    let mut png_data = Vec::new();
+    let mut encoder = ::png::Encoder::new(&mut png_data, png::WIDTH as u32, png::HEIGHT as u32);
+    encoder.set_color(::png::ColorType::Rgba);
+    encoder.set_depth(::png::BitDepth::Eight);
+    encoder
+        .write_header()
+        .unwrap()
+        .write_image_data(&rgba)
+        .unwrap();
+
+    let cart = png::read_cart_png(png_data.as_slice()).unwrap();
+    assert_eq!(cart.version(), Some(42));
+    assert_eq!(code_of(&cart), [(0, b"a=1a=1".to_vec())]);
+    let gfx_sheet = cart.gfx_sheet().unwrap();
+    assert_eq!(
+        (gfx_sheet.get_pixel(0, 0), gfx_sheet.get_pixel(1, 0)),
+        (Some(1), Some(2))
+    );
+    let pattern = *cart.music().unwrap().get(0).unwrap();
+    assert_eq!(
+        (
+            pattern.channel_sfx(0),
+            pattern.channel_sfx(1),
+            pattern.loop_end()
+        ),
+        (Some(3), None, true)
+    );
+    let note = cart.sfx_data().unwrap().get(3).unwrap().notes[0];
+    assert_eq!(
+        (note.pitch, note.waveform, note.volume, note.effect),
+        (24, 1, 5, 2)
+    );
+}
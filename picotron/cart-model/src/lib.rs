@@ -0,0 +1,236 @@
+//! The text-format of picotron `.p64` carts
+//!
+//! A `.p64` cart is a header followed by the files of the cart, each introduced by
+//! a `:: <path>` line and ended by the next one, up to the `:: [eoc]` line.
+//! The files are kept as written, including the `--[[pod...]]` metadata-line picotron
+//! prefixes them with
+
+extern crate alloc;
+
+use core::fmt;
+
+use alloc::borrow::Cow;
+
+/// The first line of every `.p64` cart
+pub const HEADER_PREFIX: &[u8] = b"picotron cartridge // www.picotron.net";
+
+/// Introduces a file, followed by its path
+const FILE_MARKER: &[u8] = b":: ";
+/// The path of the marker ending the cart
+const END_OF_CART: &str = "[eoc]";
+/// The metadata-line of lua-files added by [`P64Cart::set_lua_file`]
+const RAW_POD_METADATA: &[u8] = b"--[[pod_format=\"raw\"]]";
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum P64Error {
+    MissingHeader,
+    /// The path of a file was not valid utf-8
+    InvalidPath {
+        line_number: usize,
+    },
+}
+
+impl fmt::Display for P64Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let description = "Picotron cart error";
+        let reason = match self {
+            P64Error::MissingHeader => "missing picotron cartridge-header".to_string(),
+            P64Error::InvalidPath { line_number } => {
+                format!("file-path at line {line_number} is not valid utf-8")
+            }
+        };
+        f.write_fmt(format_args!("{description}: {reason}"))
+    }
+}
+
+impl core::error::Error for P64Error {}
+
+impl P64Error {
+    /// The stable diagnostic-code, see `pico-build-rs explain`
+    pub const fn code(&self) -> &'static str {
+        "E008"
+    }
+}
+
+/// A file of the cart, a directory if the path ends with `/`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CartFile<'a> {
+    pub path: Cow<'a, str>,
+    /// The content as written, including the metadata-line and trailing newline
+    pub content: Cow<'a, [u8]>,
+}
+
+impl CartFile<'_> {
+    pub fn is_directory(&self) -> bool {
+        self.path.ends_with('/')
+    }
+    pub fn is_lua_file(&self) -> bool {
+        self.path.ends_with(".lua")
+    }
+    /// The metadata-line picotron prefixes files with, if the file has one
+    pub fn pod_metadata(&self) -> Option<&[u8]> {
+        let first_line = self.content.split(|byte| *byte == b'\n').next()?;
+        (first_line.starts_with(b"--[[pod") && first_line.ends_with(b"]]")).then_some(first_line)
+    }
+    pub fn into_owned(self) -> CartFile<'static> {
+        CartFile {
+            path: Cow::Owned(self.path.into_owned()),
+            content: Cow::Owned(self.content.into_owned()),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct P64Cart<'a> {
+    /// The header-lines, up to the first file
+    header: Cow<'a, [u8]>,
+    files: Vec<CartFile<'a>>,
+}
+
+impl<'a> P64Cart<'a> {
+    #[tracing::instrument(level = "debug", skip(cart_src))]
+    pub fn from_cart_source(cart_src: &'a [u8]) -> Result<P64Cart<'a>, P64Error> {
+        if !cart_src.starts_with(HEADER_PREFIX) {
+            return Err(P64Error::MissingHeader);
+        }
+        let mut header_length = None;
+        let mut files: Vec<CartFile<'a>> = Vec::new();
+        let mut content_start = 0;
+        let mut line_start = 0;
+        for (line_index, line) in cart_src.split_inclusive(|byte| *byte == b'\n').enumerate() {
+            let line_end = line_start + line.len();
+            if let Some(path) = line.strip_prefix(FILE_MARKER) {
+                match files.last_mut() {
+                    Some(file) => {
+                        file.content = Cow::Borrowed(&cart_src[content_start..line_start])
+                    }
+                    None => header_length = Some(line_start),
+                }
+                let path = core::str::from_utf8(path.trim_ascii_end()).map_err(|_| {
+                    P64Error::InvalidPath {
+                        line_number: line_index + 1,
+                    }
+                })?;
+                if path == END_OF_CART {
+                    content_start = line_end;
+                    break;
+                }
+                files.push(CartFile {
+                    path: Cow::Borrowed(path),
+                    content: Cow::Borrowed(&[]),
+                });
+                content_start = line_end;
+            }
+            line_start = line_end;
+        }
+        // A cart missing its end-marker keeps the content up to the end
+        if let Some(file) = files.last_mut()
+            && file.content.is_empty()
+            && content_start < line_start
+        {
+            file.content = Cow::Borrowed(&cart_src[content_start..line_start]);
+        }
+        let header_length = header_length.unwrap_or(cart_src.len());
+        tracing::debug!("Parsed {} picotron cart-files", files.len());
+        Ok(P64Cart {
+            header: Cow::Borrowed(&cart_src[..header_length]),
+            files,
+        })
+    }
+    pub fn files(&self) -> &[CartFile<'a>] {
+        &self.files
+    }
+    pub fn file(&self, path: &str) -> Option<&CartFile<'a>> {
+        self.files.iter().find(|file| file.path == path)
+    }
+    pub fn lua_files(&self) -> impl Iterator<Item = &CartFile<'a>> {
+        self.files.iter().filter(|file| file.is_lua_file())
+    }
+    /// Replaces the content of the file, or adds it to the end of the cart
+    #[tracing::instrument(level = "debug", skip(self, content))]
+    pub fn set_file(&mut self, path: &str, content: Cow<'a, [u8]>) {
+        match self.files.iter_mut().find(|file| file.path == path) {
+            Some(file) => file.content = content,
+            None => self.files.push(CartFile {
+                path: Cow::Owned(path.to_string()),
+                content,
+            }),
+        }
+    }
+    /// Injects lua-code into the file, keeping its metadata-line if it has one
+    pub fn set_lua_file(&mut self, path: &str, code: &[u8]) {
+        let metadata = self
+            .file(path)
+            .and_then(CartFile::pod_metadata)
+            .unwrap_or(RAW_POD_METADATA);
+        let mut content = [metadata, b"\n", code].concat();
+        if !content.ends_with(b"\n") {
+            content.push(b'\n');
+        }
+        self.set_file(path, Cow::Owned(content));
+    }
+    pub fn into_owned(self) -> P64Cart<'static> {
+        let P64Cart { header, files } = self;
+        P64Cart {
+            header: Cow::Owned(header.into_owned()),
+            files: files.into_iter().map(CartFile::into_owned).collect(),
+        }
+    }
+    pub fn into_cart_source<T: FromIterator<u8>>(self) -> T {
+        let P64Cart { header, files } = self;
+        let mut cart_src = header.into_owned();
+        for CartFile { path, content } in files {
+            cart_src.extend_from_slice(FILE_MARKER);
+            cart_src.extend_from_slice(path.as_bytes());
+            cart_src.push(b'\n');
+            cart_src.extend_from_slice(&content);
+        }
+        cart_src.extend_from_slice(FILE_MARKER);
+        cart_src.extend_from_slice(END_OF_CART.as_bytes());
+        cart_src.push(b'\n');
+        cart_src.into_iter().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CART: &[u8] = b"picotron cartridge // www.picotron.net
+version 2
+
+:: gfx/
+:: main.lua
+--[[pod_format=\"raw\",created=\"2024-03-01\",revision=3]]
+include \"player.lua\"
+:: player.lua
+--[[pod_format=\"raw\"]]
+player={x=0}
+:: [eoc]
+";
+
+    #[test]
+    fn parses_and_injects() {
+        let mut cart = P64Cart::from_cart_source(CART).unwrap();
+        assert_eq!(cart.files().len(), 3);
+        assert!(cart.file("gfx/").unwrap().is_directory());
+        assert_eq!(
+            cart.lua_files().map(|file| &*file.path).collect::<Vec<_>>(),
+            ["main.lua", "player.lua"]
+        );
+        assert_eq!(cart.clone().into_cart_source::<Vec<u8>>(), CART);
+
+        cart.set_lua_file("main.lua", b"print(\"injected\")");
+        cart.set_lua_file("enemy.lua", b"enemies={}\n");
+        let cart_src: Vec<u8> = cart.into_cart_source();
+        let cart = P64Cart::from_cart_source(&cart_src).unwrap();
+        assert_eq!(
+            cart.file("main.lua").unwrap().content.as_ref(),
+            b"--[[pod_format=\"raw\",created=\"2024-03-01\",revision=3]]\nprint(\"injected\")\n"
+        );
+        assert_eq!(
+            cart.file("enemy.lua").unwrap().content.as_ref(),
+            b"--[[pod_format=\"raw\"]]\nenemies={}\n"
+        );
+    }
+}